@@ -0,0 +1,144 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Feeds arbitrary command sequences through an `OrderBook`.
+//!
+//! The input is read as one configuration byte followed by 8 byte command
+//! records. Records that modify, cancel or bust pick their target by index
+//! into what is live, so most inputs reach the interesting branches instead
+//! of naming ids that don't exist. Prices and quantities are 16 bits wide:
+//! enough to cover every branch while keeping sums well inside their types.
+//! After every command the book's structure is checked; any panic, including
+//! arithmetic overflow in debug builds, is a finding.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use order_book::circuit_breaker::{CircuitBreakerConfig, HaltPolicy, TradingStatus};
+use order_book::risk::RiskLimits;
+use order_book::{OrderBook, OrderCommand, OrderType, Side};
+use std::collections::HashSet;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&config, records)) = data.split_first() else {
+        return;
+    };
+    let mut order_book = OrderBook::with_symbol("FUZZ");
+    if config & 1 != 0 {
+        order_book.set_circuit_breaker(Some(CircuitBreakerConfig::default()));
+    }
+    if config & 2 != 0 {
+        order_book.set_halt_policy(HaltPolicy::Reject);
+    }
+    if config & 4 != 0 {
+        order_book.risk_mut().set_default(RiskLimits {
+            max_order_qty: Some(1_000),
+            max_open_orders: Some(8),
+            max_gross_exposure: Some(1_000_000),
+            ..RiskLimits::default()
+        });
+    }
+    for record in records.chunks_exact(8) {
+        if let Some(command) = command(&order_book, record) {
+            order_book.process_command(command);
+        }
+        check(&order_book);
+    }
+});
+
+fn command(order_book: &OrderBook, r: &[u8]) -> Option<OrderCommand> {
+    let u16_at = |i: usize| u16::from_le_bytes([r[i], r[i + 1]]);
+    let live: Vec<_> = order_book
+        .bids
+        .iter()
+        .chain(&order_book.asks)
+        .flat_map(|level| &level.orders)
+        .collect();
+    let pick = |i: usize| live.get(usize::from(u16_at(i)) % live.len().max(1)).copied();
+    let side = if r[1] & 1 == 0 { Side::Buy } else { Side::Sell };
+    let order_type = if r[1] & 2 == 0 {
+        OrderType::GoodTilCancel
+    } else {
+        OrderType::FillAndKill
+    };
+    Some(match r[0] % 8 {
+        0..=2 => OrderCommand::New {
+            order_type,
+            side,
+            price: i32::from(u16_at(2) as i16),
+            qty: u32::from(u16_at(4)),
+            participant: u32::from(r[6] % 4),
+        },
+        3 => {
+            let order = pick(2)?;
+            OrderCommand::Cancel {
+                id: order.id,
+                side: order.side,
+                price: order.price,
+            }
+        }
+        4 => {
+            let order = pick(2)?;
+            OrderCommand::Modify {
+                id: order.id,
+                price: order.price,
+                side: order.side,
+                qty: u32::from(u16_at(4)),
+                order_type,
+            }
+        }
+        5 => OrderCommand::KillSwitch {
+            participant: u32::from(r[6] % 4),
+            engage: r[1] & 4 != 0,
+        },
+        6 => {
+            if r[1] & 4 != 0 {
+                OrderCommand::Halt
+            } else {
+                OrderCommand::Resume {
+                    auction: r[1] & 8 != 0,
+                }
+            }
+        }
+        _ => {
+            let trades = order_book.trades();
+            let trade = trades.get(usize::from(u16_at(2)) % trades.len().max(1))?;
+            OrderCommand::BustTrade {
+                trade_id: trade.id,
+                restore: r[1] & 4 != 0,
+            }
+        }
+    })
+}
+
+fn check(order_book: &OrderBook) {
+    if order_book.status() == TradingStatus::Open {
+        if let (Some(bid), Some(ask)) = (order_book.best_bid(), order_book.best_ask()) {
+            assert!(bid < ask, "open book crossed: bid {} ask {}", bid, ask);
+        }
+    }
+    let mut ids = HashSet::new();
+    for (side, levels) in [(Side::Buy, &order_book.bids), (Side::Sell, &order_book.asks)] {
+        for pair in levels.windows(2) {
+            match side {
+                Side::Buy => assert!(pair[0].price > pair[1].price),
+                Side::Sell => assert!(pair[0].price < pair[1].price),
+            }
+        }
+        for level in levels {
+            assert!(!level.orders.is_empty(), "empty level at {}", level.price);
+            let total: u64 = level
+                .orders
+                .iter()
+                .map(|order| u64::from(order.remaining_qty))
+                .sum();
+            assert_eq!(total, level.total_qty());
+            for order in &level.orders {
+                assert!(ids.insert(order.id), "order {} rests twice", order.id);
+                assert_eq!((order.side, order.price), (side, level.price));
+                assert!(order.remaining_qty > 0 && order.remaining_qty <= order.initial_qty);
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+/*
+ * Copyright 2024 Mason Hall. All rights reserved.
+ * Use of this source code is governed by a BSD-style
+ * license that can be found in the LICENSE file.
+ *
+ * C interface to matcher-rs, built with `cargo build --release --features ffi`
+ * as target/release/liborder_book.so. See src/ffi.rs for the details; this
+ * header mirrors it and a test checks the two agree.
+ */
+
+#ifndef MATCHER_H
+#define MATCHER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define MATCHER_OK 0
+#define MATCHER_INVALID (-1)
+
+/* Sides, order types and trading statuses, as in the SBE schema. */
+#define MATCHER_BUY 0
+#define MATCHER_SELL 1
+#define MATCHER_FILL_AND_KILL 0
+#define MATCHER_GOOD_TIL_CANCEL 1
+#define MATCHER_DAY 2
+#define MATCHER_OPEN 0
+#define MATCHER_HALTED 1
+
+/* Command kinds. */
+#define MATCHER_NEW 0
+#define MATCHER_MODIFY 1
+#define MATCHER_CANCEL 2
+#define MATCHER_KILL_SWITCH 3
+#define MATCHER_HALT 4
+#define MATCHER_RESUME 5
+#define MATCHER_BUST_TRADE 6
+
+/* Event kinds. */
+#define MATCHER_PLACED 0
+#define MATCHER_MODIFIED 1
+#define MATCHER_CANCELED 2
+#define MATCHER_PARTIALLY_FILLED 3
+#define MATCHER_FILLED 4
+#define MATCHER_TRADE 5
+#define MATCHER_REJECTED 6
+#define MATCHER_KILL_SWITCH_CHANGED 7
+#define MATCHER_STATUS_CHANGED 8
+#define MATCHER_TRADE_BUST 9
+/* One side of a mass quote, which has no command kind here. */
+#define MATCHER_QUOTED 10
+#define MATCHER_PROTECTION_TRIGGERED 11
+#define MATCHER_SESSION_ENDED 12
+#define MATCHER_RESTORED 13
+#define MATCHER_SETTLED 14
+
+typedef struct MatcherBook MatcherBook;
+
+/* Fields a kind doesn't use are ignored. */
+typedef struct MatcherCommand {
+    uint8_t kind;
+    uint8_t side;
+    uint8_t order_type;
+    /* engage for kill switches, auction for resumes, restore for busts. */
+    uint8_t flag;
+    uint32_t participant;
+    int32_t price;
+    uint32_t qty;
+    /* The order for modifies and cancels, the trade for busts. */
+    uint64_t id;
+} MatcherCommand;
+
+/* Fields a kind doesn't use are zero. */
+typedef struct MatcherEvent {
+    uint8_t kind;
+    uint8_t side;
+    uint8_t order_type;
+    /* Kill switch engaged, trade bust restored, the new trading status, or
+       the protection trigger as in the SBE schema. For trades, 1 for a
+       cross and 2 for a dark trade. */
+    uint8_t flag;
+    /* Reject reason code, as in the SBE schema. */
+    uint8_t reason;
+    uint32_t participant;
+    int32_t price;
+    uint32_t qty;
+    /* The order, or the trade for trades and busts. */
+    uint64_t id;
+    uint64_t maker_order_id;
+    uint64_t taker_order_id;
+    uint32_t maker_participant;
+    uint32_t taker_participant;
+    /* Nanoseconds since the Unix epoch. */
+    uint64_t timestamp;
+    /* Trade fees in units of 1/10000 of a price unit. */
+    int64_t maker_fee;
+    int64_t taker_fee;
+    /* The order's arrival sequence, for placements and quotes. */
+    uint64_t arrival;
+} MatcherEvent;
+
+/* symbol may be NULL. Books are not thread safe. */
+MatcherBook *matcher_book_new(const char *symbol);
+void matcher_book_free(MatcherBook *book);
+
+/* Returns MATCHER_OK, or MATCHER_INVALID for an unknown kind or code. */
+int32_t matcher_submit(MatcherBook *book, const MatcherCommand *command);
+
+/* Copies up to capacity unpolled events, oldest first, and returns how many. */
+size_t matcher_poll_events(MatcherBook *book, MatcherEvent *events, size_t capacity);
+
+/* Return 1 and write the price, or 0 if that side is empty. */
+int32_t matcher_best_bid(const MatcherBook *book, int32_t *price);
+int32_t matcher_best_ask(const MatcherBook *book, int32_t *price);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MATCHER_H */
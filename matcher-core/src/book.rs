@@ -0,0 +1,261 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use crate::{Level, PriceType, Resting, Side};
+use alloc::vec::Vec;
+
+/// Levels are kept best price first: bids descending, asks ascending.
+pub fn level_index<O: Resting>(
+    levels: &[Level<O>],
+    side: Side,
+    price: O::Price,
+) -> Result<usize, usize> {
+    match side {
+        Side::Buy => levels.binary_search_by(|lim| price.cmp(&lim.price)),
+        Side::Sell => levels.binary_search_by(|lim| lim.price.cmp(&price)),
+    }
+}
+
+/// Whether an order on `side` limited to `limit` trades against one resting
+/// at `price`.
+pub fn crosses<P: PriceType>(side: Side, limit: P, price: P) -> bool {
+    match side {
+        Side::Buy => limit >= price,
+        Side::Sell => limit <= price,
+    }
+}
+
+/// The price of the best level in `opposite`, if an order on `side` limited
+/// to `limit` would trade there.
+pub fn best_crossing<O: Resting>(
+    opposite: &[Level<O>],
+    side: Side,
+    limit: O::Price,
+) -> Option<O::Price> {
+    let price = opposite.first()?.price;
+    crosses(side, limit, price).then_some(price)
+}
+
+/// Adds `order` to the back of the queue at its price, opening the level if
+/// there isn't one.
+pub fn rest<O: Resting>(levels: &mut Vec<Level<O>>, order: O) {
+    match level_index(levels, order.side(), order.price()) {
+        Ok(lim_pos) => levels[lim_pos].push_back(order),
+        Err(lim_pos) => {
+            let mut level = Level::new(order.price());
+            level.push_back(order);
+            levels.insert(lim_pos, level);
+        }
+    }
+}
+
+/// One fill of a taker against a resting order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fill<O: Resting> {
+    /// The maker's price.
+    pub price: O::Price,
+    pub qty: u32,
+    /// The maker as it was left by the fill. It is no longer in the book if
+    /// it is filled.
+    pub maker: O,
+}
+
+/// Fills `taker` against the order at the front of the best level in
+/// `opposite` at time `at`, for as much as both have left. A filled maker
+/// comes off the book, and so does a level it leaves empty. Check that the
+/// best level crosses with [`best_crossing`] first.
+///
+/// Panics if `opposite` is empty.
+pub fn fill_best<O: Resting + Clone>(
+    opposite: &mut Vec<Level<O>>,
+    taker: &mut O,
+    at: O::Time,
+) -> Fill<O> {
+    let level = &mut opposite[0];
+    let price = level.price;
+    let qty = taker.remaining_qty().min(level.orders[0].remaining_qty());
+    let maker = level.fill_front(qty, at).clone();
+    taker.fill(qty, at);
+    if maker.is_filled() {
+        level.orders.pop_front();
+        if level.orders.is_empty() {
+            opposite.remove(0);
+        }
+    }
+    Fill { price, qty, maker }
+}
+
+/// A two-sided book with nothing but price-time priority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Book<O: Resting> {
+    pub bids: Vec<Level<O>>,
+    pub asks: Vec<Level<O>>,
+}
+
+impl<O: Resting> Default for Book<O> {
+    fn default() -> Self {
+        Book {
+            bids: Vec::new(),
+            asks: Vec::new(),
+        }
+    }
+}
+
+impl<O: Resting + Clone> Book<O> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches `order` against the other side for as long as it crosses.
+    /// Whatever is left of it is not rested.
+    pub fn take(&mut self, order: &mut O, at: O::Time) -> Vec<Fill<O>> {
+        let side = order.side();
+        let opposite = match side {
+            Side::Buy => &mut self.asks,
+            Side::Sell => &mut self.bids,
+        };
+        let mut fills = Vec::new();
+        while !order.is_filled() && best_crossing(opposite, side, order.price()).is_some() {
+            fills.push(fill_best(opposite, order, at));
+        }
+        fills
+    }
+
+    /// Matches `order` like [`Book::take`] and rests what is left of it.
+    pub fn submit(&mut self, mut order: O, at: O::Time) -> Vec<Fill<O>> {
+        let fills = self.take(&mut order, at);
+        if !order.is_filled() {
+            match order.side() {
+                Side::Buy => rest(&mut self.bids, order),
+                Side::Sell => rest(&mut self.asks, order),
+            }
+        }
+        fills
+    }
+
+    /// Removes the order `id` resting on `side` at `price`. Returns false if
+    /// there is no such order.
+    pub fn cancel(&mut self, side: Side, price: O::Price, id: u64) -> bool {
+        let levels = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        let Ok(lim_pos) = level_index(levels, side, price) else {
+            return false;
+        };
+        let removed = levels[lim_pos].remove_order_by_id(id);
+        if levels[lim_pos].orders.is_empty() {
+            levels.remove(lim_pos);
+        }
+        removed
+    }
+
+    pub fn best_bid(&self) -> Option<O::Price> {
+        self.bids.first().map(|lim| lim.price)
+    }
+
+    pub fn best_ask(&self) -> Option<O::Price> {
+        self.asks.first().map(|lim| lim.price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Book;
+    use crate::{PriceType, Resting, Side};
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Order<P = i32> {
+        id: u64,
+        side: Side,
+        price: P,
+        qty: u32,
+        at: u32,
+    }
+
+    impl<P: PriceType> Resting for Order<P> {
+        type Time = u32;
+        type Price = P;
+
+        fn id(&self) -> u64 {
+            self.id
+        }
+        fn side(&self) -> Side {
+            self.side
+        }
+        fn price(&self) -> P {
+            self.price
+        }
+        fn remaining_qty(&self) -> u32 {
+            self.qty
+        }
+        fn fill(&mut self, qty: u32, at: u32) {
+            self.qty -= qty;
+            self.at = at;
+        }
+        fn restore(&mut self, qty: u32) {
+            self.qty += qty;
+        }
+        fn resize(&mut self, qty: u32) {
+            self.qty = qty;
+        }
+    }
+
+    fn order<P>(id: u64, side: Side, price: P, qty: u32) -> Order<P> {
+        Order {
+            id,
+            side,
+            price,
+            qty,
+            at: 0,
+        }
+    }
+
+    #[test]
+    fn matches_by_price_then_time() {
+        let mut book = Book::new();
+        assert!(book.submit(order(1, Side::Sell, 101, 5), 1).is_empty());
+        book.submit(order(2, Side::Sell, 100, 2), 2);
+        book.submit(order(3, Side::Sell, 100, 4), 3);
+        book.submit(order(4, Side::Buy, 98, 1), 4);
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(98), Some(100)));
+
+        let fills = book.submit(order(5, Side::Buy, 101, 9), 5);
+        let summary: alloc::vec::Vec<_> = fills
+            .iter()
+            .map(|fill| (fill.maker.id, fill.price, fill.qty, fill.maker.qty))
+            .collect();
+        assert_eq!(summary, [(2, 100, 2, 0), (3, 100, 4, 0), (1, 101, 3, 2)]);
+        assert_eq!(fills[2].maker.at, 5);
+        assert_eq!(book.best_ask(), Some(101));
+        assert_eq!(book.asks[0].total_qty(), 2);
+
+        let mut fak = order(6, Side::Sell, 99, 3);
+        assert!(book.take(&mut fak, 6).is_empty());
+        assert_eq!(fak.qty, 3);
+        assert!(book.cancel(Side::Buy, 98, 4));
+        assert!(!book.cancel(Side::Buy, 98, 4));
+        assert_eq!(book.best_bid(), None);
+    }
+
+    /// Four decimal places, as a venue quoting in hundredths of a cent
+    /// might use.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    struct Decimal(i64);
+
+    #[test]
+    fn prices_in_any_ordered_type() {
+        let mut book = Book::new();
+        book.submit(order(1, Side::Sell, Decimal(100_0001), 5), 1);
+        book.submit(order(2, Side::Sell, Decimal(100_0000), 5), 2);
+        let fills = book.submit(order(3, Side::Buy, Decimal(100_0001), 7), 3);
+        let summary: alloc::vec::Vec<_> = fills.iter().map(|fill| (fill.price, fill.qty)).collect();
+        assert_eq!(summary, [(Decimal(100_0000), 5), (Decimal(100_0001), 2)]);
+        assert_eq!(book.best_ask(), Some(Decimal(100_0001)));
+
+        let mut book = Book::new();
+        book.submit(order(1, Side::Buy, i64::MAX - 1, 5), 1);
+        assert_eq!(book.best_bid(), Some(i64::MAX - 1));
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use crate::Resting;
+use alloc::collections::VecDeque;
+
+/// A price level. The level's total remaining quantity is kept up to date as
+/// orders are added, filled and removed, so add orders with
+/// [`Level::push_back`] rather than through `orders` directly. The queue
+/// runs from the highest [priority class](Resting::priority) to the lowest,
+/// each class in time order.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Level<O: Resting> {
+    pub price: O::Price,
+    pub orders: VecDeque<O>,
+    qty: u64,
+}
+
+impl<O: Resting> Level<O> {
+    pub fn new(price: O::Price) -> Self {
+        Level {
+            price,
+            orders: VecDeque::new(),
+            qty: 0,
+        }
+    }
+
+    /// Queues `order` behind every order of its class or a higher one. With
+    /// a single class, that is the back of the queue.
+    pub fn push_back(&mut self, order: O) {
+        self.qty += order.remaining_qty() as u64;
+        let class = order.priority();
+        match self.orders.iter().rposition(|o| o.priority() >= class) {
+            Some(pos) => self.orders.insert(pos + 1, order),
+            None => self.orders.push_front(order),
+        }
+    }
+
+    /// Puts `order` ahead of everything else in its class, as when giving
+    /// an order back the place it was taken from.
+    pub fn push_front(&mut self, order: O) {
+        self.qty += order.remaining_qty() as u64;
+        let class = order.priority();
+        match self.orders.iter().position(|o| o.priority() <= class) {
+            Some(pos) => self.orders.insert(pos, order),
+            None => self.orders.push_back(order),
+        }
+    }
+
+    /// Fills `qty` of the order at the front of the queue at time `at` and
+    /// returns it.
+    ///
+    /// Panics if the level is empty.
+    pub fn fill_front(&mut self, qty: u32, at: O::Time) -> &O {
+        let order = self
+            .orders
+            .front_mut()
+            .expect("limit levels are never empty");
+        order.fill(qty, at);
+        self.qty -= qty as u64;
+        order
+    }
+
+    /// Gives back `qty` to a resting order, which keeps its place in the
+    /// queue. Returns false if the order is not at this level.
+    pub fn restore(&mut self, id: u64, qty: u32) -> bool {
+        match self.orders.iter_mut().find(|order| order.id() == id) {
+            Some(order) => {
+                order.restore(qty);
+                self.qty += qty as u64;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets a resting order's remaining quantity to `qty` without moving it
+    /// in the queue. Returns false if the order is not at this level.
+    pub fn resize(&mut self, id: u64, qty: u32) -> bool {
+        match self.orders.iter_mut().find(|order| order.id() == id) {
+            Some(order) => {
+                self.qty = self.qty - order.remaining_qty() as u64 + qty as u64;
+                order.resize(qty);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn find_by_id(&self, id: u64) -> Option<usize> {
+        self.orders.iter().position(|x| x.id() == id)
+    }
+
+    pub fn total_qty(&self) -> u64 {
+        self.qty
+    }
+
+    pub fn remove_order_by_id(&mut self, id: u64) -> bool {
+        match self.find_by_id(id).and_then(|pos| self.orders.remove(pos)) {
+            Some(order) => {
+                self.qty -= order.remaining_qty() as u64;
+                true
+            }
+            None => false,
+        }
+    }
+}
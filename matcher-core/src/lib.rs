@@ -0,0 +1,84 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Price-time matching without the standard library.
+//!
+//! This crate holds the parts of the matcher that are only about price
+//! levels and queues: finding a level, resting an order at it and filling
+//! against the best opposite one. It needs `alloc` and nothing else, so it
+//! builds for embedded targets and for kernel-bypass stacks that bring their
+//! own runtime. Clocks, logging, risk and market data stay in the
+//! `order_book` crate, which is built on top of this one.
+//!
+//! Orders are anything that implements [`Resting`], and times are whatever
+//! the order type says they are, so callers pass them in rather than this
+//! crate reading a clock. Prices are whatever the order type says they are
+//! too, any [`PriceType`]: ticks in an `i32` or `i64`, or a fixed-point
+//! decimal that orders the way prices do. The choice is made at compile
+//! time, so matching never converts or branches on it. [`Book`] puts the
+//! pieces together for callers that want a plain two-sided book.
+
+#![no_std]
+
+extern crate alloc;
+
+use core::fmt;
+
+mod book;
+mod level;
+
+pub use book::{best_crossing, crosses, fill_best, level_index, rest, Book, Fill};
+pub use level::Level;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+/// A price representation. Higher is a higher price: bids are matched
+/// best first by descending order, asks by ascending order.
+pub trait PriceType: Ord + Copy + fmt::Debug {}
+
+impl<T: Ord + Copy + fmt::Debug> PriceType for T {}
+
+/// An order that can rest in a [`Level`].
+pub trait Resting {
+    /// When an order was last changed.
+    type Time: Copy;
+    type Price: PriceType;
+
+    fn id(&self) -> u64;
+    fn side(&self) -> Side;
+    fn price(&self) -> Self::Price;
+    fn remaining_qty(&self) -> u32;
+    /// Takes `qty` off the remaining quantity at time `at`. Never called
+    /// with more than what is left.
+    fn fill(&mut self, qty: u32, at: Self::Time);
+    /// Gives `qty` back to the remaining quantity.
+    fn restore(&mut self, qty: u32);
+    /// Sets the remaining quantity to `qty`, as when an order is amended in
+    /// place.
+    fn resize(&mut self, qty: u32);
+    /// The order's priority class. Within a level, orders of a higher class
+    /// are queued ahead of lower ones, and time priority applies within
+    /// each class. Every order is in class 0 unless this says otherwise.
+    fn priority(&self) -> u8 {
+        0
+    }
+
+    fn is_filled(&self) -> bool {
+        self.remaining_qty() == 0
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+import { EventEmitter } from "node:events";
+
+export type Side = "Buy" | "Sell";
+export type OrderType = "FillAndKill" | "GoodTilCancel" | "Day";
+
+export type OrderCommand =
+  | { New: { order_type: OrderType; side: Side; price: number; qty: number; participant: number } }
+  | { Modify: { id: number; price: number; side: Side; qty: number; order_type: OrderType } }
+  | { Cancel: { id: number; side: Side; price: number } }
+  | { KillSwitch: { participant: number; engage: boolean } }
+  | "Halt"
+  | { Resume: { auction: boolean } }
+  | { BustTrade: { trade_id: number; restore: boolean } }
+  | { Cross: { buyer: number; seller: number; price: number; qty: number } };
+
+/** An event as the REST API has it, keyed by its kind. */
+export type OrderEvent = string | Record<string, any>;
+
+export interface Level {
+  price: number;
+  qty: number;
+  order_count: number;
+}
+
+export interface Trade {
+  id: number;
+  price: number;
+  qty: number;
+  aggressor_side: Side;
+  maker_order_id: number;
+  taker_order_id: number;
+  maker_participant: number;
+  taker_participant: number;
+  timestamp: number;
+  /** Set on crosses reported off the book. */
+  cross?: boolean;
+  /** Set on trades matched in the dark book. */
+  dark?: boolean;
+  /** ISO 4217 code of the currency `price` is in. */
+  currency: string;
+}
+
+export class Book extends EventEmitter {
+  constructor(symbol?: string);
+  submit(command: OrderCommand): OrderEvent[];
+  depth(levels?: number): { bids: Level[]; asks: Level[] };
+  trades(count?: number): Trade[];
+}
@@ -0,0 +1,57 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Node.js bindings for matcher-rs. Build the addon with
+//
+//   cargo build --release --features napi
+//   cp target/release/liborder_book.so node/matcher.node
+//
+// or point MATCHER_ADDON at it. Commands, events and depth are the same JSON
+// as the REST API. Timestamps are nanoseconds, so they lose precision as
+// JavaScript numbers.
+
+"use strict";
+
+const { EventEmitter } = require("node:events");
+const path = require("node:path");
+
+const native = require(process.env.MATCHER_ADDON ?? path.join(__dirname, "matcher.node"));
+
+// A book that emits every event it produces, once under "event" and once
+// under its kind ("Placed", "Trade", "Filled", ...). Events are emitted on
+// the next turn of the event loop, so listeners never run inside submit.
+class Book extends EventEmitter {
+  constructor(symbol = "") {
+    super();
+    this.handle = native.newBook(symbol);
+  }
+
+  // Processes a command such as
+  // {New: {order_type: "GoodTilCancel", side: "Buy", price: 122, qty: 5, participant: 1}}
+  // and returns the events it produced.
+  submit(command) {
+    const events = JSON.parse(native.submit(this.handle, JSON.stringify(command)));
+    if (events.length > 0) {
+      setImmediate(() => {
+        for (const event of events) {
+          // Unit variants are bare strings.
+          const kind = typeof event === "string" ? event : Object.keys(event)[0];
+          this.emit(kind, typeof event === "string" ? {} : event[kind]);
+          this.emit("event", event);
+        }
+      });
+    }
+    return events;
+  }
+
+  depth(levels = 10) {
+    return JSON.parse(native.depth(this.handle, levels));
+  }
+
+  trades(count = 10) {
+    return JSON.parse(native.trades(this.handle, count));
+  }
+}
+
+module.exports = { Book };
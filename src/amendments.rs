@@ -0,0 +1,136 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Order amendment history.
+//!
+//! A modify takes the order off the book and enters it again under a new
+//! id, so on the event stream an amended order looks like one order ending
+//! and another starting. The book records each modify it carries out as an
+//! [`Amendment`] linking the two, and keeps every order's amendments
+//! together: asking for any id the order has had gives the whole chain,
+//! oldest first, without replaying the journal.
+
+use crate::{OrderId, OrderType, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// One modify carried out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Amendment<P = i32> {
+    /// The id the order had before.
+    pub id: OrderId,
+    /// The id it was entered again under.
+    pub new_id: OrderId,
+    pub old_price: P,
+    /// What was left of the order.
+    pub old_qty: u32,
+    pub new_price: P,
+    pub new_qty: u32,
+    pub order_type: OrderType,
+    /// When the amended order was entered, by the original or the previous
+    /// amendment.
+    pub placed_at: Timestamp,
+    pub amended_at: Timestamp,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AmendmentHistory<P = i32> {
+    /// Each amended order's amendments, oldest first.
+    chains: Vec<Vec<Amendment<P>>>,
+    /// Every id an amended order has had, to its chain.
+    chain_of: HashMap<OrderId, usize>,
+}
+
+impl<P> AmendmentHistory<P> {
+    pub fn new() -> Self {
+        AmendmentHistory {
+            chains: Vec::new(),
+            chain_of: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, amendment: Amendment<P>) {
+        let chain = match self.chain_of.get(&amendment.id) {
+            Some(&chain) => chain,
+            None => {
+                self.chains.push(Vec::new());
+                let chain = self.chains.len() - 1;
+                self.chain_of.insert(amendment.id, chain);
+                chain
+            }
+        };
+        self.chain_of.insert(amendment.new_id, chain);
+        self.chains[chain].push(amendment);
+    }
+
+    /// The amendments of the order that has, or once had, id `id`. Empty
+    /// if it was never amended.
+    pub fn of(&self, id: OrderId) -> &[Amendment<P>] {
+        self.chain_of
+            .get(&id)
+            .map_or(&[], |&chain| self.chains[chain].as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    #[test]
+    fn follows_an_order_through_its_modifies() {
+        let mut order_book = OrderBook::new();
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 100,
+            qty: 10,
+            participant: 1,
+        });
+        let modify = |id, price, qty| OrderCommand::Modify {
+            id,
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price,
+            qty,
+        };
+        order_book.process_command(modify(1, 100, 8));
+        order_book.process_command(modify(2, 100, 12));
+        order_book.process_command(modify(3, 101, 5));
+        // Not resting on the sell side, so this one does nothing.
+        order_book.process_command(OrderCommand::Modify {
+            id: 4,
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Sell,
+            price: 102,
+            qty: 5,
+        });
+
+        let history = order_book.amendments(1);
+        assert_eq!(history, order_book.amendments(4));
+        let steps: Vec<_> = history
+            .iter()
+            .map(|a| {
+                (
+                    a.id,
+                    a.new_id,
+                    a.old_price,
+                    a.old_qty,
+                    a.new_price,
+                    a.new_qty,
+                )
+            })
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                (1, 2, 100, 10, 100, 8),
+                (2, 3, 100, 8, 100, 12),
+                (3, 4, 100, 12, 101, 5)
+            ]
+        );
+        assert!(history[0].placed_at <= history[1].placed_at);
+        assert_eq!(order_book.best_bid(), Some(101));
+        assert!(order_book.amendments(5).is_empty());
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Derived book statistics.
+
+use crate::order_book::LevelInfo;
+use crate::{OrderBook, Price};
+use serde::Serialize;
+
+/// `(bid - ask) / (bid + ask)`: 1 when only bids are resting, -1 when only
+/// asks are, `None` when the book is empty.
+pub fn imbalance(bid_qty: u64, ask_qty: u64) -> Option<f64> {
+    let total = bid_qty + ask_qty;
+    (total > 0).then(|| (bid_qty as f64 - ask_qty as f64) / total as f64)
+}
+
+/// Mid weighted towards the side with less size, on the basis that the
+/// thinner side is the one more likely to be taken out next:
+/// `(bid * ask_qty + ask * bid_qty) / (bid_qty + ask_qty)`.
+pub fn microprice<P: Price>(bid: LevelInfo<P>, ask: LevelInfo<P>) -> f64 {
+    let (bid_price, ask_price) = (bid.price.to_ticks() as f64, ask.price.to_ticks() as f64);
+    let total = (bid.qty + ask.qty) as f64;
+    if total == 0.0 {
+        return (bid_price + ask_price) / 2.0;
+    }
+    (bid_price * ask.qty as f64 + ask_price * bid.qty as f64) / total
+}
+
+/// Quantity-weighted average price of `levels`, `None` if they hold no
+/// quantity.
+pub fn weighted_price<P: Price, I: IntoIterator<Item = LevelInfo<P>>>(levels: I) -> Option<f64> {
+    let (notional, qty) = levels
+        .into_iter()
+        .fold((0.0, 0u64), |(notional, qty), level| {
+            (
+                notional + level.price.to_ticks() as f64 * level.qty as f64,
+                qty + level.qty,
+            )
+        });
+    (qty > 0).then(|| notional / qty as f64)
+}
+
+/// What sweeping one side of the book for a quantity would do; see
+/// [`OrderBook::estimate_execution`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ExecutionEstimate<P = i32> {
+    pub filled_qty: u64,
+    /// Quantity-weighted over the fills, `None` if nothing would fill.
+    pub average_price: Option<f64>,
+    /// The price of the last level reached.
+    pub worst_price: Option<P>,
+    /// Levels traded into, the last perhaps only in part.
+    pub levels: usize,
+    /// What the side is too thin to fill.
+    pub unfilled_qty: u64,
+}
+
+/// Fills `qty` against `levels`, best first, without touching them.
+pub fn estimate_execution<P: Price, I: IntoIterator<Item = LevelInfo<P>>>(
+    levels: I,
+    qty: u64,
+) -> ExecutionEstimate<P> {
+    let mut estimate = ExecutionEstimate {
+        unfilled_qty: qty,
+        ..ExecutionEstimate::default()
+    };
+    let mut notional = 0.0;
+    for level in levels {
+        if estimate.unfilled_qty == 0 {
+            break;
+        }
+        let fill = level.qty.min(estimate.unfilled_qty);
+        notional += level.price.to_ticks() as f64 * fill as f64;
+        estimate.filled_qty += fill;
+        estimate.unfilled_qty -= fill;
+        estimate.worst_price = Some(level.price);
+        estimate.levels += 1;
+    }
+    estimate.average_price =
+        (estimate.filled_qty > 0).then(|| notional / estimate.filled_qty as f64);
+    estimate
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ImbalanceScope {
+    /// Best bid against best ask.
+    Touch,
+    /// The top [`ImbalanceConfig::levels`] levels of each side.
+    Depth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Pressure {
+    Bid,
+    Balanced,
+    Ask,
+}
+
+impl Pressure {
+    fn classify(value: f64, threshold: f64) -> Pressure {
+        if value >= threshold {
+            Pressure::Bid
+        } else if value <= -threshold {
+            Pressure::Ask
+        } else {
+            Pressure::Balanced
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImbalanceConfig {
+    pub levels: usize,
+    /// Magnitude at which the touch imbalance counts as one-sided.
+    pub touch_threshold: f64,
+    pub depth_threshold: f64,
+}
+
+impl Default for ImbalanceConfig {
+    fn default() -> Self {
+        ImbalanceConfig {
+            levels: 5,
+            touch_threshold: 0.6,
+            depth_threshold: 0.4,
+        }
+    }
+}
+
+/// Emitted when an imbalance moves into a different [`Pressure`] band.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ImbalanceAlert {
+    pub scope: ImbalanceScope,
+    pub pressure: Pressure,
+    pub value: f64,
+}
+
+/// Tracks touch and depth imbalance across book changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImbalanceMonitor {
+    config: ImbalanceConfig,
+    touch: Option<f64>,
+    depth: Option<f64>,
+    touch_pressure: Pressure,
+    depth_pressure: Pressure,
+}
+
+impl ImbalanceMonitor {
+    pub fn new(config: ImbalanceConfig) -> Self {
+        ImbalanceMonitor {
+            config,
+            touch: None,
+            depth: None,
+            touch_pressure: Pressure::Balanced,
+            depth_pressure: Pressure::Balanced,
+        }
+    }
+
+    pub fn touch(&self) -> Option<f64> {
+        self.touch
+    }
+
+    pub fn depth(&self) -> Option<f64> {
+        self.depth
+    }
+
+    /// Recomputes both imbalances; call after every command. Returns an
+    /// alert for each scope whose pressure band changed. An empty book
+    /// counts as balanced.
+    pub fn update(&mut self, order_book: &OrderBook) -> Vec<ImbalanceAlert> {
+        self.touch = order_book.imbalance(1);
+        self.depth = order_book.imbalance(self.config.levels);
+        let mut alerts = Vec::new();
+        for (scope, value, threshold, pressure) in [
+            (
+                ImbalanceScope::Touch,
+                self.touch,
+                self.config.touch_threshold,
+                &mut self.touch_pressure,
+            ),
+            (
+                ImbalanceScope::Depth,
+                self.depth,
+                self.config.depth_threshold,
+                &mut self.depth_pressure,
+            ),
+        ] {
+            let value = value.unwrap_or(0.0);
+            let current = Pressure::classify(value, threshold);
+            if current != *pressure {
+                *pressure = current;
+                alerts.push(ImbalanceAlert {
+                    scope,
+                    pressure: current,
+                    value,
+                });
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        imbalance, microprice, weighted_price, ImbalanceConfig, ImbalanceMonitor, ImbalanceScope,
+        Pressure,
+    };
+    use crate::analytics::ExecutionEstimate;
+    use crate::order_book::LevelInfo;
+    use crate::testing::new_order;
+    use crate::{OrderBook, Side};
+
+    #[test]
+    fn imbalance_ratio() {
+        assert_eq!(imbalance(0, 0), None);
+        assert_eq!(imbalance(3, 0), Some(1.0));
+        assert_eq!(imbalance(1, 3), Some(-0.5));
+    }
+
+    #[test]
+    fn alerts_on_band_changes() {
+        let mut order_book = OrderBook::new();
+        let mut monitor = ImbalanceMonitor::new(ImbalanceConfig {
+            levels: 2,
+            touch_threshold: 0.5,
+            depth_threshold: 0.5,
+        });
+        assert!(monitor.update(&order_book).is_empty());
+
+        order_book.process_command(new_order(Side::Buy, 100, 9));
+        order_book.process_command(new_order(Side::Sell, 101, 1));
+        let alerts = monitor.update(&order_book);
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts.iter().all(|alert| alert.pressure == Pressure::Bid));
+        assert_eq!(monitor.touch(), Some(0.8));
+
+        // Deep ask liquidity evens out the depth view but not the touch.
+        order_book.process_command(new_order(Side::Sell, 102, 8));
+        let alerts = monitor.update(&order_book);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].scope, ImbalanceScope::Depth);
+        assert_eq!(alerts[0].pressure, Pressure::Balanced);
+        assert!(monitor.update(&order_book).is_empty());
+    }
+
+    fn level(price: i32, qty: u64) -> LevelInfo {
+        LevelInfo {
+            price,
+            qty,
+            order_count: 1,
+        }
+    }
+
+    #[test]
+    fn microprice_leans_towards_thin_side() {
+        assert_eq!(microprice(level(100, 1), level(102, 1)), 101.0);
+        assert_eq!(microprice(level(100, 3), level(102, 1)), 101.5);
+        assert_eq!(weighted_price([level(100, 1), level(98, 3)]), Some(98.5));
+        assert_eq!(weighted_price::<i32, _>([]), None);
+    }
+
+    #[test]
+    fn book_price_signals() {
+        let mut order_book = OrderBook::new();
+        assert_eq!(order_book.microprice(), None);
+        for (side, price, qty) in [
+            (Side::Buy, 100, 3),
+            (Side::Buy, 99, 1),
+            (Side::Sell, 102, 1),
+            (Side::Sell, 104, 1),
+        ] {
+            order_book.process_command(new_order(side, price, qty));
+        }
+        assert_eq!(order_book.microprice(), Some(101.5));
+        // Bids average 99.75 over two levels, asks 103.
+        assert_eq!(order_book.depth_weighted_mid(2), Some(101.375));
+        assert_eq!(order_book.depth_weighted_mid(1), Some(101.0));
+
+        order_book.process_command(new_order(Side::Sell, 99, 4));
+        assert_eq!(order_book.microprice(), None);
+    }
+
+    #[test]
+    fn estimates_a_sweep_without_trading() {
+        let mut order_book = OrderBook::new();
+        for (price, qty) in [(101, 2), (102, 2), (104, 4)] {
+            order_book.process_command(new_order(Side::Sell, price, qty));
+        }
+        assert_eq!(
+            order_book.estimate_execution(Side::Buy, 8),
+            ExecutionEstimate {
+                filled_qty: 8,
+                average_price: Some(102.75),
+                worst_price: Some(104),
+                levels: 3,
+                unfilled_qty: 0,
+            }
+        );
+        let estimate = order_book.estimate_execution(Side::Buy, 10);
+        assert_eq!((estimate.filled_qty, estimate.unfilled_qty), (8, 2));
+        assert_eq!(order_book.estimate_execution(Side::Sell, 1).unfilled_qty, 1);
+        assert!(order_book.trades().is_empty());
+        assert_eq!(order_book.quantity_at(Side::Sell, 101), 2);
+    }
+}
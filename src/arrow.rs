@@ -0,0 +1,562 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Apache Arrow export.
+//!
+//! [`trades`], [`events`] and [`depth`] lay the trade tape, the event
+//! history and depth samples out as columnar [`RecordBatch`]es, and
+//! [`ipc::StreamWriter`] writes batches in the Arrow IPC streaming format,
+//! which `polars.read_ipc_stream`, `pyarrow.ipc.open_stream` and DataFusion
+//! read as they are. Only the types these tables need are supported: 32 and
+//! 64-bit integers, UTF-8 strings and nanosecond UTC timestamps.
+
+use crate::order_book::Depth;
+use crate::{OrderEvent, OrderId, ParticipantId, Side, Timestamp, Trade};
+use std::io;
+
+pub mod ipc;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    Int32,
+    UInt32,
+    Int64,
+    UInt64,
+    Utf8,
+    /// Nanoseconds since the Unix epoch, UTC.
+    Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub data_type: DataType,
+    pub nullable: bool,
+}
+
+impl Field {
+    pub fn new(name: &str, data_type: DataType, nullable: bool) -> Self {
+        Field {
+            name: name.to_string(),
+            data_type,
+            nullable,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub fields: Vec<Field>,
+}
+
+/// A column's values, `None` for null.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Array {
+    Int32(Vec<Option<i32>>),
+    UInt32(Vec<Option<u32>>),
+    Int64(Vec<Option<i64>>),
+    UInt64(Vec<Option<u64>>),
+    Utf8(Vec<Option<String>>),
+    Timestamp(Vec<Option<Timestamp>>),
+}
+
+impl Array {
+    pub fn data_type(&self) -> DataType {
+        match self {
+            Array::Int32(_) => DataType::Int32,
+            Array::UInt32(_) => DataType::UInt32,
+            Array::Int64(_) => DataType::Int64,
+            Array::UInt64(_) => DataType::UInt64,
+            Array::Utf8(_) => DataType::Utf8,
+            Array::Timestamp(_) => DataType::Timestamp,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.validity().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn null_count(&self) -> usize {
+        self.validity().iter().filter(|valid| !**valid).count()
+    }
+
+    /// Whether each value is set.
+    fn validity(&self) -> Vec<bool> {
+        match self {
+            Array::Int32(values) => values.iter().map(Option::is_some).collect(),
+            Array::UInt32(values) => values.iter().map(Option::is_some).collect(),
+            Array::Int64(values) => values.iter().map(Option::is_some).collect(),
+            Array::UInt64(values) => values.iter().map(Option::is_some).collect(),
+            Array::Utf8(values) => values.iter().map(Option::is_some).collect(),
+            Array::Timestamp(values) => values.iter().map(Option::is_some).collect(),
+        }
+    }
+}
+
+/// Equal-length columns under a schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordBatch {
+    schema: Schema,
+    columns: Vec<Array>,
+}
+
+impl RecordBatch {
+    /// Checks that there is a column of the right type per field, all the
+    /// same length, and no nulls where the schema says there are none.
+    pub fn new(schema: Schema, columns: Vec<Array>) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        if columns.len() != schema.fields.len() {
+            return Err(invalid(format!(
+                "{} columns for {} fields",
+                columns.len(),
+                schema.fields.len()
+            )));
+        }
+        let rows = columns.first().map_or(0, Array::len);
+        for (field, column) in schema.fields.iter().zip(&columns) {
+            if column.data_type() != field.data_type {
+                return Err(invalid(format!(
+                    "column {} is {:?}, not {:?}",
+                    field.name,
+                    column.data_type(),
+                    field.data_type
+                )));
+            }
+            if column.len() != rows {
+                return Err(invalid(format!(
+                    "column {} has {} rows, not {}",
+                    field.name,
+                    column.len(),
+                    rows
+                )));
+            }
+            if !field.nullable && column.null_count() > 0 {
+                return Err(invalid(format!("column {} is not nullable", field.name)));
+            }
+        }
+        Ok(RecordBatch { schema, columns })
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.columns.first().map_or(0, Array::len)
+    }
+
+    pub fn columns(&self) -> &[Array] {
+        &self.columns
+    }
+
+    pub fn column_by_name(&self, name: &str) -> Option<&Array> {
+        let index = self.schema.fields.iter().position(|f| f.name == name)?;
+        Some(&self.columns[index])
+    }
+}
+
+fn side(side: Side) -> String {
+    format!("{:?}", side)
+}
+
+/// A row per trade.
+pub fn trades(trades: &[Trade]) -> RecordBatch {
+    let schema = Schema {
+        fields: vec![
+            Field::new("id", DataType::UInt64, false),
+            Field::new("price", DataType::Int32, false),
+            Field::new("qty", DataType::UInt32, false),
+            Field::new("aggressor_side", DataType::Utf8, false),
+            Field::new("maker_order_id", DataType::UInt64, false),
+            Field::new("taker_order_id", DataType::UInt64, false),
+            Field::new("maker_participant", DataType::UInt32, false),
+            Field::new("taker_participant", DataType::UInt32, false),
+            Field::new("timestamp", DataType::Timestamp, false),
+            Field::new("maker_fee", DataType::Int64, false),
+            Field::new("taker_fee", DataType::Int64, false),
+        ],
+    };
+    let columns = vec![
+        Array::UInt64(trades.iter().map(|t| Some(t.id as u64)).collect()),
+        Array::Int32(trades.iter().map(|t| Some(t.price)).collect()),
+        Array::UInt32(trades.iter().map(|t| Some(t.qty)).collect()),
+        Array::Utf8(
+            trades
+                .iter()
+                .map(|t| Some(side(t.aggressor_side)))
+                .collect(),
+        ),
+        Array::UInt64(trades.iter().map(|t| Some(t.maker_order_id)).collect()),
+        Array::UInt64(trades.iter().map(|t| Some(t.taker_order_id)).collect()),
+        Array::UInt32(trades.iter().map(|t| Some(t.maker_participant)).collect()),
+        Array::UInt32(trades.iter().map(|t| Some(t.taker_participant)).collect()),
+        Array::Timestamp(trades.iter().map(|t| Some(t.timestamp)).collect()),
+        Array::Int64(trades.iter().map(|t| Some(t.maker_fee)).collect()),
+        Array::Int64(trades.iter().map(|t| Some(t.taker_fee)).collect()),
+    ];
+    RecordBatch::new(schema, columns).expect("trade columns match their schema")
+}
+
+/// What an event says about the fields the events table has, where it
+/// has them. Trades count as the taker's.
+#[derive(Default)]
+struct EventRow {
+    order_id: Option<OrderId>,
+    participant: Option<ParticipantId>,
+    side: Option<Side>,
+    price: Option<i32>,
+    qty: Option<u32>,
+    timestamp: Option<Timestamp>,
+}
+
+impl EventRow {
+    fn of(event: &OrderEvent) -> Self {
+        let trade = |trade: &Trade| EventRow {
+            order_id: Some(trade.taker_order_id),
+            participant: Some(trade.taker_participant),
+            side: Some(trade.aggressor_side),
+            price: Some(trade.price),
+            qty: Some(trade.qty),
+            timestamp: Some(trade.timestamp),
+        };
+        match *event {
+            OrderEvent::Placed {
+                id,
+                participant,
+                side,
+                price,
+                timestamp,
+                ..
+            } => EventRow {
+                order_id: Some(id),
+                participant: Some(participant),
+                side: Some(side),
+                price: Some(price),
+                timestamp: Some(timestamp),
+                ..EventRow::default()
+            },
+            OrderEvent::Modified | OrderEvent::StatusChanged { .. } => EventRow::default(),
+            OrderEvent::Canceled { id } => EventRow {
+                order_id: Some(id),
+                ..EventRow::default()
+            },
+            OrderEvent::PartiallyFilled {
+                id,
+                price,
+                qty,
+                timestamp,
+            } => EventRow {
+                order_id: Some(id),
+                price: Some(price),
+                qty: Some(qty),
+                timestamp: Some(timestamp),
+                ..EventRow::default()
+            },
+            OrderEvent::Filled {
+                id,
+                price,
+                timestamp,
+            } => EventRow {
+                order_id: Some(id),
+                price: Some(price),
+                timestamp: Some(timestamp),
+                ..EventRow::default()
+            },
+            OrderEvent::Trade(ref t) | OrderEvent::TradeBust { trade: ref t, .. } => trade(t),
+            OrderEvent::Rejected {
+                participant,
+                side,
+                price,
+                qty,
+                ..
+            } => EventRow {
+                participant: Some(participant),
+                side: Some(side),
+                price: Some(price),
+                qty: Some(qty),
+                ..EventRow::default()
+            },
+            OrderEvent::KillSwitch { participant, .. } => EventRow {
+                participant: Some(participant),
+                ..EventRow::default()
+            },
+            OrderEvent::Quoted {
+                id,
+                participant,
+                side,
+                price,
+                qty,
+                timestamp,
+                ..
+            }
+            | OrderEvent::Restored {
+                id,
+                participant,
+                side,
+                price,
+                qty,
+                timestamp,
+                ..
+            } => EventRow {
+                order_id: Some(id),
+                participant: Some(participant),
+                side: Some(side),
+                price: Some(price),
+                qty: Some(qty),
+                timestamp: Some(timestamp),
+            },
+            OrderEvent::ProtectionTriggered {
+                participant,
+                timestamp,
+                ..
+            } => EventRow {
+                participant: Some(participant),
+                timestamp: Some(timestamp),
+                ..EventRow::default()
+            },
+            OrderEvent::Settled { price, timestamp } => EventRow {
+                price: Some(price),
+                timestamp: Some(timestamp),
+                ..EventRow::default()
+            },
+            OrderEvent::SessionEnded { timestamp } => EventRow {
+                timestamp: Some(timestamp),
+                ..EventRow::default()
+            },
+        }
+    }
+}
+
+/// A row per event, numbered from 1 in the order given. Columns an event
+/// has nothing for are null, and `event` has the whole of it as JSON.
+pub fn events(events: &[OrderEvent]) -> RecordBatch {
+    events_from(1, events)
+}
+
+/// As [`events`], numbered from `first_sequence`.
+pub fn events_from(first_sequence: u64, events: &[OrderEvent]) -> RecordBatch {
+    let schema = Schema {
+        fields: vec![
+            Field::new("sequence", DataType::UInt64, false),
+            Field::new("kind", DataType::Utf8, false),
+            Field::new("order_id", DataType::UInt64, true),
+            Field::new("participant", DataType::UInt32, true),
+            Field::new("side", DataType::Utf8, true),
+            Field::new("price", DataType::Int32, true),
+            Field::new("qty", DataType::UInt32, true),
+            Field::new("timestamp", DataType::Timestamp, true),
+            Field::new("event", DataType::Utf8, false),
+        ],
+    };
+    let rows: Vec<EventRow> = events.iter().map(EventRow::of).collect();
+    let kind = |event: &OrderEvent| format!("{:?}", crate::sink::dispatch::EventKind::of(event));
+    let columns = vec![
+        Array::UInt64(
+            (first_sequence..first_sequence + events.len() as u64)
+                .map(Some)
+                .collect(),
+        ),
+        Array::Utf8(events.iter().map(|e| Some(kind(e))).collect()),
+        Array::UInt64(rows.iter().map(|r| r.order_id).collect()),
+        Array::UInt32(rows.iter().map(|r| r.participant).collect()),
+        Array::Utf8(rows.iter().map(|r| r.side.map(side)).collect()),
+        Array::Int32(rows.iter().map(|r| r.price).collect()),
+        Array::UInt32(rows.iter().map(|r| r.qty).collect()),
+        Array::Timestamp(rows.iter().map(|r| r.timestamp).collect()),
+        Array::Utf8(
+            events
+                .iter()
+                .map(|e| serde_json::to_string(e).ok())
+                .collect(),
+        ),
+    ];
+    RecordBatch::new(schema, columns).expect("event columns match their schema")
+}
+
+/// A row per level per sample, levels numbered from 0 at the touch.
+pub fn depth(samples: &[(Timestamp, Depth)]) -> RecordBatch {
+    let schema = Schema {
+        fields: vec![
+            Field::new("timestamp", DataType::Timestamp, false),
+            Field::new("side", DataType::Utf8, false),
+            Field::new("level", DataType::UInt32, false),
+            Field::new("price", DataType::Int32, false),
+            Field::new("qty", DataType::UInt64, false),
+            Field::new("orders", DataType::UInt32, false),
+        ],
+    };
+    let levels: Vec<_> = samples
+        .iter()
+        .flat_map(|(timestamp, depth)| {
+            let bids = depth.bids.iter().enumerate().map(|l| (Side::Buy, l));
+            let asks = depth.asks.iter().enumerate().map(|l| (Side::Sell, l));
+            bids.chain(asks).map(move |level| (*timestamp, level))
+        })
+        .collect();
+    let columns = vec![
+        Array::Timestamp(levels.iter().map(|(t, _)| Some(*t)).collect()),
+        Array::Utf8(levels.iter().map(|(_, (s, _))| Some(side(*s))).collect()),
+        Array::UInt32(
+            levels
+                .iter()
+                .map(|(_, (_, (i, _)))| Some(*i as u32))
+                .collect(),
+        ),
+        Array::Int32(
+            levels
+                .iter()
+                .map(|(_, (_, (_, l)))| Some(l.price))
+                .collect(),
+        ),
+        Array::UInt64(levels.iter().map(|(_, (_, (_, l)))| Some(l.qty)).collect()),
+        Array::UInt32(
+            levels
+                .iter()
+                .map(|(_, (_, (_, l)))| Some(l.order_count as u32))
+                .collect(),
+        ),
+    ];
+    RecordBatch::new(schema, columns).expect("depth columns match their schema")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ipc, Array, DataType, Field, RecordBatch, Schema};
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::io;
+
+    #[test]
+    fn lays_out_the_tape_and_event_history() {
+        let mut order_book = OrderBook::new();
+        for (side, price) in [(Side::Sell, 101), (Side::Buy, 101), (Side::Buy, 99)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty: 4,
+                participant: 1,
+            });
+        }
+        let trades = super::trades(order_book.trades());
+        assert_eq!(trades.num_rows(), 1);
+        assert_eq!(
+            trades.column_by_name("aggressor_side"),
+            Some(&Array::Utf8(vec![Some("Buy".to_string())]))
+        );
+
+        let events = super::events(order_book.events());
+        assert_eq!(events.num_rows(), order_book.events().len());
+        let Some(Array::Utf8(kinds)) = events.column_by_name("kind") else {
+            panic!("no kind column");
+        };
+        assert_eq!(kinds[0].as_deref(), Some("Placed"));
+        let qty = events.column_by_name("qty").unwrap();
+        assert!(qty.null_count() > 0 && qty.null_count() < qty.len());
+
+        let depth = super::depth(&[(order_book.now(), order_book.depth(5))]);
+        assert_eq!(
+            depth.column_by_name("price"),
+            Some(&Array::Int32(vec![Some(99)]))
+        );
+    }
+
+    #[test]
+    fn rejects_columns_that_do_not_match_the_schema() {
+        let schema = Schema {
+            fields: vec![
+                Field::new("id", DataType::UInt64, false),
+                Field::new("price", DataType::Int32, true),
+            ],
+        };
+        let cases = [
+            (vec![Array::UInt64(vec![Some(1)])], "1 columns for 2 fields"),
+            (
+                vec![Array::UInt64(vec![Some(1)]), Array::Int64(vec![Some(99)])],
+                "column price is Int64, not Int32",
+            ),
+            (
+                vec![Array::UInt64(vec![Some(1)]), Array::Int32(vec![])],
+                "column price has 0 rows, not 1",
+            ),
+            (
+                vec![Array::UInt64(vec![None]), Array::Int32(vec![Some(99)])],
+                "column id is not nullable",
+            ),
+        ];
+        for (columns, msg) in cases {
+            let err = RecordBatch::new(schema.clone(), columns).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(err.to_string(), msg);
+        }
+
+        // Nulls are fine where the schema allows them.
+        let batch = RecordBatch::new(
+            schema,
+            vec![Array::UInt64(vec![Some(1)]), Array::Int32(vec![None])],
+        )
+        .unwrap();
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(batch.column_by_name("qty"), None);
+    }
+
+    #[test]
+    fn empty_books_give_empty_batches_with_full_schemas() {
+        let order_book = OrderBook::new();
+        let trades = super::trades(order_book.trades());
+        let events = super::events(order_book.events());
+        let depth = super::depth(&[(order_book.now(), order_book.depth(5))]);
+        for (batch, fields) in [(&trades, 11), (&events, 9), (&depth, 6)] {
+            assert_eq!(batch.num_rows(), 0);
+            assert_eq!(batch.schema().fields.len(), fields);
+            assert!(batch.columns().iter().all(Array::is_empty));
+        }
+        assert_eq!(super::depth(&[]), depth);
+
+        // An empty batch still makes a stream readers accept.
+        let mut writer = ipc::StreamWriter::new(Vec::new(), events.schema()).unwrap();
+        writer.write(&events).unwrap();
+        let stream = writer.finish().unwrap();
+        assert_eq!(
+            stream[stream.len() - 8..],
+            [0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn events_number_from_the_given_sequence() {
+        let mut order_book = OrderBook::new();
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 99,
+            qty: 4,
+            participant: 1,
+        });
+        order_book.process_command(OrderCommand::Cancel {
+            id: 1,
+            side: Side::Buy,
+            price: 99,
+        });
+        let first = u64::from(u32::MAX) + 1;
+        let events = super::events_from(first, order_book.events());
+        let expected: Vec<_> = (first..first + order_book.events().len() as u64)
+            .map(Some)
+            .collect();
+        assert_eq!(
+            events.column_by_name("sequence"),
+            Some(&Array::UInt64(expected))
+        );
+        // A cancel says nothing about side, price or quantity.
+        let Some(Array::Utf8(sides)) = events.column_by_name("side") else {
+            panic!("no side column");
+        };
+        assert_eq!(sides.last(), Some(&None));
+    }
+}
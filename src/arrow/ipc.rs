@@ -0,0 +1,267 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! The Arrow IPC streaming format.
+//!
+//! A stream is a schema message, a message per record batch and an
+//! end-of-stream marker. Each message is a continuation marker
+//! (`0xFFFFFFFF`), the length of its FlatBuffers metadata padded to 8 bytes,
+//! the metadata (`Message.fbs` and `Schema.fbs` in the Arrow format), and
+//! then the message body: the column buffers, each padded to 8 bytes.
+//! Validity bitmaps are left out of columns with no nulls.
+
+use super::{Array, DataType, RecordBatch, Schema};
+use crate::codec::flatbuffers::{build, Field};
+use std::io::{self, Write};
+
+const CONTINUATION: [u8; 4] = [0xFF; 4];
+/// `MetadataVersion.V5`.
+const METADATA_VERSION: i16 = 4;
+/// `MessageHeader` union tags.
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+/// `Type` union tags.
+const TYPE_INT: u8 = 2;
+const TYPE_UTF8: u8 = 5;
+const TYPE_TIMESTAMP: u8 = 10;
+/// `TimeUnit.NANOSECOND`.
+const NANOSECOND: i16 = 3;
+
+/// Writes record batches sharing a schema as an Arrow IPC stream.
+#[derive(Debug)]
+pub struct StreamWriter<W: Write> {
+    writer: W,
+    schema: Schema,
+}
+
+impl<W: Write> StreamWriter<W> {
+    /// Starts a stream of batches with `schema`.
+    pub fn new(mut writer: W, schema: &Schema) -> io::Result<Self> {
+        write_message(&mut writer, HEADER_SCHEMA, schema_table(schema), &[])?;
+        Ok(StreamWriter {
+            writer,
+            schema: schema.clone(),
+        })
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> io::Result<()> {
+        if *batch.schema() != self.schema {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "record batch schema differs from the stream's",
+            ));
+        }
+        let mut body = Vec::new();
+        let mut nodes = Vec::new();
+        let mut buffers = Vec::new();
+        for column in batch.columns() {
+            nodes.extend_from_slice(&(column.len() as i64).to_le_bytes());
+            nodes.extend_from_slice(&(column.null_count() as i64).to_le_bytes());
+            for buffer in column_buffers(column) {
+                buffers.extend_from_slice(&(body.len() as i64).to_le_bytes());
+                buffers.extend_from_slice(&(buffer.len() as i64).to_le_bytes());
+                body.extend_from_slice(&buffer);
+                body.resize(body.len().next_multiple_of(8), 0);
+            }
+        }
+        let header = vec![
+            Field::Scalar((batch.num_rows() as i64).to_le_bytes().to_vec()),
+            Field::Structs {
+                count: batch.columns().len(),
+                bytes: nodes,
+                align: 8,
+            },
+            Field::Structs {
+                count: buffers.len() / 16,
+                bytes: buffers,
+                align: 8,
+            },
+        ];
+        write_message(&mut self.writer, HEADER_RECORD_BATCH, header, &body)
+    }
+
+    /// Writes the end-of-stream marker and hands back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&CONTINUATION)?;
+        self.writer.write_all(&0u32.to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn write_message<W: Write>(
+    writer: &mut W,
+    header_type: u8,
+    header: Vec<Field>,
+    body: &[u8],
+) -> io::Result<()> {
+    let mut metadata = build(
+        None,
+        &[
+            Field::Scalar(METADATA_VERSION.to_le_bytes().to_vec()),
+            Field::Scalar(vec![header_type]),
+            Field::Table(header),
+            Field::Scalar((body.len() as i64).to_le_bytes().to_vec()),
+        ],
+    );
+    metadata.resize(metadata.len().next_multiple_of(8), 0);
+    writer.write_all(&CONTINUATION)?;
+    writer.write_all(&(metadata.len() as u32).to_le_bytes())?;
+    writer.write_all(&metadata)?;
+    writer.write_all(body)
+}
+
+fn schema_table(schema: &Schema) -> Vec<Field<'_>> {
+    let fields = schema
+        .fields
+        .iter()
+        .map(|field| {
+            let (type_type, type_table) = match field.data_type {
+                DataType::Int32 => (TYPE_INT, int(32, true)),
+                DataType::UInt32 => (TYPE_INT, int(32, false)),
+                DataType::Int64 => (TYPE_INT, int(64, true)),
+                DataType::UInt64 => (TYPE_INT, int(64, false)),
+                DataType::Utf8 => (TYPE_UTF8, Vec::new()),
+                DataType::Timestamp => (
+                    TYPE_TIMESTAMP,
+                    vec![
+                        Field::Scalar(NANOSECOND.to_le_bytes().to_vec()),
+                        Field::Str("UTC"),
+                    ],
+                ),
+            };
+            vec![
+                Field::Str(&field.name),
+                Field::Scalar(vec![field.nullable as u8]),
+                Field::Scalar(vec![type_type]),
+                Field::Table(type_table),
+                // No dictionary encoding.
+                Field::Absent,
+                Field::Tables(Vec::new()),
+            ]
+        })
+        .collect();
+    // Little-endian, then the fields.
+    vec![
+        Field::Scalar(0i16.to_le_bytes().to_vec()),
+        Field::Tables(fields),
+    ]
+}
+
+fn int(bit_width: i32, signed: bool) -> Vec<Field<'static>> {
+    vec![
+        Field::Scalar(bit_width.to_le_bytes().to_vec()),
+        Field::Scalar(vec![signed as u8]),
+    ]
+}
+
+/// A column's buffers in the order Arrow lays them out: the validity
+/// bitmap, then the values, or for strings the offsets and the bytes.
+fn column_buffers(column: &Array) -> Vec<Vec<u8>> {
+    let validity = if column.null_count() == 0 {
+        Vec::new()
+    } else {
+        let mut bitmap = vec![0u8; column.len().div_ceil(8)];
+        for (i, valid) in column.validity().into_iter().enumerate() {
+            if valid {
+                bitmap[i / 8] |= 1 << (i % 8);
+            }
+        }
+        bitmap
+    };
+    fn values<T: Copy + Default, const N: usize>(
+        values: &[Option<T>],
+        to_le_bytes: fn(T) -> [u8; N],
+    ) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| to_le_bytes(value.unwrap_or_default()))
+            .collect()
+    }
+    match column {
+        Array::Int32(v) => vec![validity, values(v, i32::to_le_bytes)],
+        Array::UInt32(v) => vec![validity, values(v, u32::to_le_bytes)],
+        Array::Int64(v) => vec![validity, values(v, i64::to_le_bytes)],
+        Array::UInt64(v) => vec![validity, values(v, u64::to_le_bytes)],
+        Array::Timestamp(v) => {
+            let nanos: Vec<_> = v.iter().map(|t| t.map(|t| t.as_nanos())).collect();
+            vec![validity, values(&nanos, u64::to_le_bytes)]
+        }
+        Array::Utf8(v) => {
+            let mut offsets = 0i32.to_le_bytes().to_vec();
+            let mut data = Vec::new();
+            for value in v {
+                data.extend_from_slice(value.as_deref().unwrap_or_default().as_bytes());
+                offsets.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            }
+            vec![validity, offsets, data]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StreamWriter;
+    use crate::arrow;
+    use crate::codec::flatbuffers::Table;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    /// Splits a stream into its messages' metadata and bodies.
+    fn messages(mut stream: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut messages = Vec::new();
+        loop {
+            assert_eq!(stream[..4], [0xFF; 4]);
+            let len = u32::from_le_bytes(stream[4..8].try_into().unwrap()) as usize;
+            if len == 0 {
+                assert_eq!(stream.len(), 8);
+                return messages;
+            }
+            assert_eq!(len % 8, 0);
+            let metadata = &stream[8..8 + len];
+            let message = Table::root(metadata, None).unwrap();
+            let body_len = message.u64(3).unwrap() as usize;
+            messages.push((metadata, &stream[8 + len..8 + len + body_len]));
+            stream = &stream[8 + len + body_len..];
+        }
+    }
+
+    #[test]
+    fn writes_a_schema_then_batches() {
+        let mut order_book = OrderBook::new();
+        for (side, price) in [(Side::Sell, 101), (Side::Sell, 102), (Side::Buy, 102)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty: 4,
+                participant: 1,
+            });
+        }
+        let batch = arrow::events(order_book.events());
+        let mut writer = StreamWriter::new(Vec::new(), batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.write(&batch).unwrap();
+        assert!(writer.write(&arrow::trades(order_book.trades())).is_err());
+        let stream = writer.finish().unwrap();
+
+        let messages = messages(&stream);
+        assert_eq!(messages.len(), 3);
+        let (schema, body) = messages[0];
+        let schema = Table::root(schema, None).unwrap();
+        assert_eq!(schema.u8(1).unwrap(), super::HEADER_SCHEMA);
+        assert!(body.is_empty());
+
+        let (metadata, body) = messages[1];
+        let message = Table::root(metadata, None).unwrap();
+        assert_eq!(message.u8(1).unwrap(), super::HEADER_RECORD_BATCH);
+        let header = message.table(2).unwrap().unwrap();
+        assert_eq!(header.u64(0).unwrap(), order_book.events().len() as u64);
+        // Buffer 6 is the values of the third column, the order ids.
+        let buffers = header.structs(2, 16).unwrap();
+        let offset = u64::from_le_bytes(buffers[96..104].try_into().unwrap()) as usize;
+        let first_id = u64::from_le_bytes(body[offset..offset + 8].try_into().unwrap());
+        assert_eq!(first_id, 1);
+        assert_eq!(body.len() % 8, 0);
+    }
+}
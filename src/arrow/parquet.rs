@@ -0,0 +1,463 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Parquet files.
+//!
+//! [`ParquetWriter`] writes record batches as a Parquet file, a row group
+//! per batch and a single uncompressed, PLAIN encoded data page per column
+//! chunk. Nullable columns carry RLE definition levels. The footer is the
+//! `FileMetaData` of `parquet.thrift` in the Thrift compact protocol, with
+//! logical types so readers see strings, unsigned integers and UTC
+//! nanosecond timestamps rather than bare physical types.
+
+use super::{Array, DataType, RecordBatch, Schema};
+use std::io::{self, Write};
+
+const MAGIC: &[u8; 4] = b"PAR1";
+
+/// Thrift compact protocol type ids.
+const BOOL_TRUE: u8 = 1;
+const BOOL_FALSE: u8 = 2;
+const BYTE: u8 = 3;
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+/// Parquet physical types.
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const BYTE_ARRAY: i32 = 6;
+
+/// `Encoding.PLAIN` and `Encoding.RLE`.
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+
+/// Writes record batches sharing a schema as a Parquet file.
+#[derive(Debug)]
+pub struct ParquetWriter<W: Write> {
+    writer: W,
+    schema: Schema,
+    /// Bytes written so far.
+    offset: u64,
+    row_groups: Vec<RowGroup>,
+}
+
+#[derive(Debug)]
+struct RowGroup {
+    rows: usize,
+    columns: Vec<ColumnChunk>,
+}
+
+#[derive(Debug)]
+struct ColumnChunk {
+    physical_type: i32,
+    values: usize,
+    offset: u64,
+    size: usize,
+}
+
+impl<W: Write> ParquetWriter<W> {
+    /// Starts a file of batches with `schema`.
+    pub fn new(mut writer: W, schema: &Schema) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(ParquetWriter {
+            writer,
+            schema: schema.clone(),
+            offset: MAGIC.len() as u64,
+            row_groups: Vec::new(),
+        })
+    }
+
+    /// Writes `batch` as a row group.
+    pub fn write(&mut self, batch: &RecordBatch) -> io::Result<()> {
+        if *batch.schema() != self.schema {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "record batch schema differs from the file's",
+            ));
+        }
+        let mut columns = Vec::with_capacity(batch.columns().len());
+        for (field, column) in self.schema.fields.iter().zip(batch.columns()) {
+            let mut page = Vec::new();
+            if field.nullable {
+                let levels = definition_levels(&column.validity());
+                page.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+                page.extend_from_slice(&levels);
+            }
+            let physical_type = plain_values(column, &mut page);
+
+            let mut header = Thrift::new();
+            header.i32(1, 0); // DATA_PAGE
+            header.i32(2, page.len() as i32);
+            header.i32(3, page.len() as i32);
+            header.begin_struct(5);
+            header.i32(1, column.len() as i32);
+            header.i32(2, PLAIN);
+            header.i32(3, RLE);
+            header.i32(4, RLE);
+            header.end_struct();
+            let header = header.finish();
+
+            self.writer.write_all(&header)?;
+            self.writer.write_all(&page)?;
+            columns.push(ColumnChunk {
+                physical_type,
+                values: column.len(),
+                offset: self.offset,
+                size: header.len() + page.len(),
+            });
+            self.offset += (header.len() + page.len()) as u64;
+        }
+        self.row_groups.push(RowGroup {
+            rows: batch.num_rows(),
+            columns,
+        });
+        Ok(())
+    }
+
+    /// Writes the footer and hands back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let footer = self.file_metadata();
+        self.writer.write_all(&footer)?;
+        self.writer
+            .write_all(&(footer.len() as u32).to_le_bytes())?;
+        self.writer.write_all(MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn file_metadata(&self) -> Vec<u8> {
+        let fields = &self.schema.fields;
+        let mut meta = Thrift::new();
+        meta.i32(1, 1);
+        meta.list(2, STRUCT, fields.len() + 1);
+        meta.begin_element();
+        meta.binary(4, b"schema");
+        meta.i32(5, fields.len() as i32);
+        meta.end_struct();
+        for field in fields {
+            meta.begin_element();
+            meta.i32(1, physical_type(field.data_type));
+            meta.i32(3, field.nullable as i32); // REQUIRED or OPTIONAL
+            meta.binary(4, field.name.as_bytes());
+            if let Some(converted) = converted_type(field.data_type) {
+                meta.i32(6, converted);
+            }
+            meta.begin_struct(10);
+            logical_type(&mut meta, field.data_type);
+            meta.end_struct();
+            meta.end_struct();
+        }
+        let rows: usize = self.row_groups.iter().map(|group| group.rows).sum();
+        meta.i64(3, rows as i64);
+        meta.list(4, STRUCT, self.row_groups.len());
+        for group in &self.row_groups {
+            meta.begin_element();
+            meta.list(1, STRUCT, group.columns.len());
+            for (field, chunk) in fields.iter().zip(&group.columns) {
+                meta.begin_element();
+                meta.i64(2, chunk.offset as i64);
+                meta.begin_struct(3);
+                meta.i32(1, chunk.physical_type);
+                meta.list(2, I32, 2);
+                meta.elem_i32(PLAIN);
+                meta.elem_i32(RLE);
+                meta.list(3, BINARY, 1);
+                meta.elem_binary(field.name.as_bytes());
+                meta.i32(4, 0); // UNCOMPRESSED
+                meta.i64(5, chunk.values as i64);
+                meta.i64(6, chunk.size as i64);
+                meta.i64(7, chunk.size as i64);
+                meta.i64(9, chunk.offset as i64);
+                meta.end_struct();
+                meta.end_struct();
+            }
+            let size: usize = group.columns.iter().map(|chunk| chunk.size).sum();
+            meta.i64(2, size as i64);
+            meta.i64(3, group.rows as i64);
+            meta.end_struct();
+        }
+        meta.binary(
+            6,
+            concat!(
+                env!("CARGO_PKG_NAME"),
+                " version ",
+                env!("CARGO_PKG_VERSION")
+            )
+            .as_bytes(),
+        );
+        meta.finish()
+    }
+}
+
+fn physical_type(data_type: DataType) -> i32 {
+    match data_type {
+        DataType::Int32 | DataType::UInt32 => INT32,
+        DataType::Int64 | DataType::UInt64 | DataType::Timestamp => INT64,
+        DataType::Utf8 => BYTE_ARRAY,
+    }
+}
+
+/// The older `ConvertedType`, for readers that predate logical types.
+/// Nanosecond timestamps have none.
+fn converted_type(data_type: DataType) -> Option<i32> {
+    match data_type {
+        DataType::Utf8 => Some(0),
+        DataType::UInt32 => Some(13),
+        DataType::UInt64 => Some(14),
+        DataType::Int32 => Some(17),
+        DataType::Int64 => Some(18),
+        DataType::Timestamp => None,
+    }
+}
+
+/// Writes the fields of the `LogicalType` union.
+fn logical_type(meta: &mut Thrift, data_type: DataType) {
+    let int = |meta: &mut Thrift, bits: i8, signed: bool| {
+        meta.begin_struct(10);
+        meta.byte(1, bits);
+        meta.bool(2, signed);
+        meta.end_struct();
+    };
+    match data_type {
+        DataType::Int32 => int(meta, 32, true),
+        DataType::UInt32 => int(meta, 32, false),
+        DataType::Int64 => int(meta, 64, true),
+        DataType::UInt64 => int(meta, 64, false),
+        DataType::Utf8 => {
+            meta.begin_struct(1);
+            meta.end_struct();
+        }
+        DataType::Timestamp => {
+            meta.begin_struct(8);
+            meta.bool(1, true);
+            meta.begin_struct(2);
+            meta.begin_struct(3); // NANOS
+            meta.end_struct();
+            meta.end_struct();
+            meta.end_struct();
+        }
+    }
+}
+
+/// Appends the set values, PLAIN encoded, and returns their physical type.
+fn plain_values(column: &Array, out: &mut Vec<u8>) -> i32 {
+    match column {
+        Array::Int32(values) => {
+            values
+                .iter()
+                .flatten()
+                .for_each(|v| out.extend(v.to_le_bytes()));
+        }
+        Array::UInt32(values) => {
+            values
+                .iter()
+                .flatten()
+                .for_each(|v| out.extend(v.to_le_bytes()));
+        }
+        Array::Int64(values) => {
+            values
+                .iter()
+                .flatten()
+                .for_each(|v| out.extend(v.to_le_bytes()));
+        }
+        Array::UInt64(values) => {
+            values
+                .iter()
+                .flatten()
+                .for_each(|v| out.extend(v.to_le_bytes()));
+        }
+        Array::Timestamp(values) => {
+            values
+                .iter()
+                .flatten()
+                .for_each(|v| out.extend(v.as_nanos().to_le_bytes()));
+        }
+        Array::Utf8(values) => {
+            for value in values.iter().flatten() {
+                out.extend((value.len() as u32).to_le_bytes());
+                out.extend(value.as_bytes());
+            }
+        }
+    }
+    physical_type(column.data_type())
+}
+
+/// Definition levels as RLE runs of a one-bit width: 1 where a value is
+/// set, 0 where it is null.
+fn definition_levels(validity: &[bool]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut rest = validity;
+    while let Some(&first) = rest.first() {
+        let run = rest.iter().take_while(|&&valid| valid == first).count();
+        varint(&mut out, (run as u64) << 1);
+        out.push(first as u8);
+        rest = &rest[run..];
+    }
+    out
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// A Thrift compact protocol struct writer.
+struct Thrift {
+    buf: Vec<u8>,
+    /// The last field id written in each struct being written.
+    last_field: Vec<i16>,
+}
+
+impl Thrift {
+    fn new() -> Self {
+        Thrift {
+            buf: Vec::new(),
+            last_field: vec![0],
+        }
+    }
+
+    fn header(&mut self, id: i16, type_id: u8) {
+        let last = self.last_field.last_mut().expect("inside a struct");
+        let delta = id - *last;
+        if (1..=15).contains(&delta) {
+            self.buf.push((delta as u8) << 4 | type_id);
+        } else {
+            self.buf.push(type_id);
+            varint(&mut self.buf, zigzag(id.into()));
+        }
+        *last = id;
+    }
+
+    fn bool(&mut self, id: i16, value: bool) {
+        self.header(id, if value { BOOL_TRUE } else { BOOL_FALSE });
+    }
+
+    fn byte(&mut self, id: i16, value: i8) {
+        self.header(id, BYTE);
+        self.buf.push(value as u8);
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.header(id, I32);
+        varint(&mut self.buf, zigzag(value.into()));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.header(id, I64);
+        varint(&mut self.buf, zigzag(value));
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.header(id, BINARY);
+        self.elem_binary(value);
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.header(id, STRUCT);
+        self.begin_element();
+    }
+
+    fn end_struct(&mut self) {
+        self.buf.push(0);
+        self.last_field.pop();
+    }
+
+    /// Starts a list of `len` elements; write them with the `elem_`
+    /// methods, or for structs `begin_element` and `end_struct`.
+    fn list(&mut self, id: i16, elem_type: u8, len: usize) {
+        self.header(id, LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | elem_type);
+        } else {
+            self.buf.push(0xF0 | elem_type);
+            varint(&mut self.buf, len as u64);
+        }
+    }
+
+    fn begin_element(&mut self) {
+        self.last_field.push(0);
+    }
+
+    fn elem_i32(&mut self, value: i32) {
+        varint(&mut self.buf, zigzag(value.into()));
+    }
+
+    fn elem_binary(&mut self, value: &[u8]) {
+        varint(&mut self.buf, value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.end_struct();
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{definition_levels, ParquetWriter, Thrift};
+    use crate::arrow::{Array, DataType, Field, RecordBatch, Schema};
+    use crate::Timestamp;
+
+    #[test]
+    fn encodes_thrift_and_levels() {
+        let mut thrift = Thrift::new();
+        thrift.i32(1, 3);
+        thrift.begin_struct(5);
+        thrift.bool(1, true);
+        thrift.end_struct();
+        thrift.i64(22, -1);
+        assert_eq!(
+            thrift.finish(),
+            [0x15, 0x06, 0x4C, 0x11, 0x00, 0x06, 0x2C, 0x01, 0x00]
+        );
+        // Three set, one null, one set.
+        let levels = definition_levels(&[true, true, true, false, true]);
+        assert_eq!(levels, [0x06, 1, 0x02, 0, 0x02, 1]);
+    }
+
+    #[test]
+    fn writes_row_groups_and_a_footer() {
+        let schema = Schema {
+            fields: vec![
+                Field::new("price", DataType::Int32, false),
+                Field::new("side", DataType::Utf8, true),
+                Field::new("timestamp", DataType::Timestamp, false),
+            ],
+        };
+        let batch = RecordBatch::new(
+            schema.clone(),
+            vec![
+                Array::Int32(vec![Some(101), Some(-7)]),
+                Array::Utf8(vec![Some("Buy".to_string()), None]),
+                Array::Timestamp(vec![Some(Timestamp::from_nanos(5)); 2]),
+            ],
+        )
+        .unwrap();
+        let mut writer = ParquetWriter::new(Vec::new(), &schema).unwrap();
+        writer.write(&batch).unwrap();
+        writer.write(&batch).unwrap();
+        let file = writer.finish().unwrap();
+
+        assert_eq!(&file[..4], b"PAR1");
+        assert_eq!(&file[file.len() - 4..], b"PAR1");
+        let footer_len =
+            u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into().unwrap());
+        assert!((footer_len as usize) < file.len() - 12);
+        // The first page's values follow its header.
+        let prices: Vec<u8> = [101i32, -7].iter().flat_map(|p| p.to_le_bytes()).collect();
+        let at = file.windows(8).position(|w| w == prices).unwrap();
+        assert!(at > 4 && at < 32);
+    }
+}
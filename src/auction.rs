@@ -0,0 +1,104 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Single-price auction uncrossing.
+//!
+//! The uncross price is the one that executes the most quantity. Ties go to
+//! the price leaving the smallest surplus on either side, then to the price
+//! nearest the reference price, then to the lower price.
+
+use crate::order_book::LevelInfo;
+use crate::Price;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Uncross<P = i32> {
+    pub price: P,
+    /// Quantity that trades at `price`.
+    pub volume: u64,
+    /// Buy quantity willing to trade at `price` minus sell quantity; what
+    /// is left over once the auction has run.
+    pub surplus: i64,
+}
+
+/// `bids` best (highest) first and `asks` best (lowest) first, as in
+/// [`crate::order_book::Depth`]. `None` if the book does not cross.
+pub fn uncross<P: Price>(
+    bids: &[LevelInfo<P>],
+    asks: &[LevelInfo<P>],
+    reference: Option<P>,
+) -> Option<Uncross<P>> {
+    let mut best: Option<Uncross<P>> = None;
+    for price in bids.iter().chain(asks).map(|level| level.price) {
+        let buy: u64 = bids
+            .iter()
+            .take_while(|level| level.price >= price)
+            .map(|level| level.qty)
+            .sum();
+        let sell: u64 = asks
+            .iter()
+            .take_while(|level| level.price <= price)
+            .map(|level| level.qty)
+            .sum();
+        let candidate = Uncross {
+            price,
+            volume: buy.min(sell),
+            surplus: buy as i64 - sell as i64,
+        };
+        if candidate.volume == 0 {
+            continue;
+        }
+        let distance = |uncross: &Uncross<P>| {
+            reference.map_or(0, |reference| {
+                (i128::from(uncross.price.to_ticks()) - i128::from(reference.to_ticks()))
+                    .unsigned_abs()
+            })
+        };
+        let key = |uncross: &Uncross<P>| {
+            (
+                std::cmp::Reverse(uncross.volume),
+                uncross.surplus.unsigned_abs(),
+                distance(uncross),
+                uncross.price,
+            )
+        };
+        if best.as_ref().is_none_or(|best| key(&candidate) < key(best)) {
+            best = Some(candidate);
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::uncross;
+    use crate::order_book::LevelInfo;
+
+    fn levels(levels: &[(i32, u64)]) -> Vec<LevelInfo> {
+        levels
+            .iter()
+            .map(|&(price, qty)| LevelInfo {
+                price,
+                qty,
+                order_count: 1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn maximizes_volume_then_minimizes_surplus() {
+        let bids = levels(&[(103, 2), (102, 3), (100, 5)]);
+        let asks = levels(&[(99, 4), (101, 2), (104, 1)]);
+        // At 101 and 102: 5 buy against 6 sell, 5 trades.
+        let result = uncross(&bids, &asks, None).unwrap();
+        assert_eq!((result.price, result.volume, result.surplus), (101, 5, -1));
+        assert_eq!(uncross(&bids, &asks, Some(102)).unwrap().price, 102);
+
+        assert_eq!(
+            uncross(&levels(&[(99, 1)]), &levels(&[(100, 1)]), None),
+            None
+        );
+        assert_eq!(uncross(&[], &asks, None), None);
+    }
+}
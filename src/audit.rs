@@ -0,0 +1,406 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Regulatory audit log.
+//!
+//! Each command leaves three kinds of record, in order: the `command` as
+//! received, the `decision` taken on it, and one `event` record for every
+//! event it produced. Records are JSON lines carrying a sequence number, the
+//! wall-clock time they were written and the participant the command came
+//! from. A modify the book carries out adds an `amendment` record after
+//! its events, with the order's [`Amendment`], so the log alone tells which
+//! order each new id continues. Ending the session with [`end_of_day`]
+//! leaves just the `event` records:
+//!
+//! ```text
+//! {"body":{"accepted":false,"reason":"MaxOrderQty"},"hash":"5b1e…","kind":"decision","participant":7,"prev":"c03a…","seq":2,"symbol":"ABC","time":"2024-09-01T12:00:00.000000123Z"}
+//! ```
+//!
+//! Every record names the hash of the one before it in `prev`, and its own
+//! `hash` is the SHA-256 of the record without that field. Editing, dropping
+//! or reordering a record breaks the chain from there on, which [`verify`]
+//! reports along with the sequence number where it happened.
+
+use crate::amendments::Amendment;
+use crate::latency::Stage;
+use crate::order_book::SessionSummary;
+use crate::sink::jsonl::format_rfc3339;
+use crate::{OrderBook, OrderCommand, OrderEvent, ParticipantId, Timestamp};
+use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+pub mod sha256;
+
+/// `prev` of the first record.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Where a chain ends, and so where the next record continues from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainHead {
+    pub next_sequence: u64,
+    pub last_hash: String,
+}
+
+impl Default for ChainHead {
+    fn default() -> Self {
+        ChainHead {
+            next_sequence: 1,
+            last_hash: GENESIS_HASH.to_string(),
+        }
+    }
+}
+
+pub struct AuditLog<W: Write> {
+    writer: W,
+    head: ChainHead,
+}
+
+impl AuditLog<BufWriter<File>> {
+    /// Opens `path` for appending, creating it if needed. An existing log is
+    /// verified first and the chain continues from its last record.
+    pub fn append<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let head = match File::open(path) {
+            Ok(file) => verify(BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ChainHead::default(),
+            Err(e) => return Err(e),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog::resume(BufWriter::new(file), head))
+    }
+}
+
+impl<W: Write> AuditLog<W> {
+    pub fn new(writer: W) -> Self {
+        AuditLog::resume(writer, ChainHead::default())
+    }
+
+    pub fn resume(writer: W, head: ChainHead) -> Self {
+        AuditLog { writer, head }
+    }
+
+    pub fn head(&self) -> &ChainHead {
+        &self.head
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Flushes the log and carries on in `writer` with a new chain, e.g. a
+    /// new file for the next session. Returns the writer just finished.
+    pub fn roll(&mut self, writer: W) -> io::Result<W> {
+        self.writer.flush()?;
+        self.head = ChainHead::default();
+        Ok(std::mem::replace(&mut self.writer, writer))
+    }
+
+    /// Records `command`, whether the book accepted it, and the `events` it
+    /// produced, then flushes so the command is on record before the caller
+    /// acts on its outcome.
+    pub fn record(
+        &mut self,
+        symbol: &str,
+        participant: Option<ParticipantId>,
+        command: &OrderCommand,
+        events: &[OrderEvent],
+    ) -> io::Result<()> {
+        let body = json!({ "command": command });
+        self.write(symbol, participant, "command", body)?;
+        let rejected = events.iter().find_map(|event| match event {
+            OrderEvent::Rejected { reason, .. } => Some(reason),
+            _ => None,
+        });
+        let body = match rejected {
+            Some(reason) => json!({ "accepted": false, "reason": reason }),
+            None => json!({ "accepted": true }),
+        };
+        self.write(symbol, participant, "decision", body)?;
+        for event in events {
+            self.write(symbol, participant, "event", json!({ "event": event }))?;
+        }
+        self.writer.flush()
+    }
+
+    fn write(
+        &mut self,
+        symbol: &str,
+        participant: Option<ParticipantId>,
+        kind: &str,
+        body: Value,
+    ) -> io::Result<()> {
+        let now = Timestamp::now().as_nanos();
+        let mut record = json!({
+            "seq": self.head.next_sequence,
+            "time": format_rfc3339(now),
+            "participant": participant,
+            "symbol": symbol,
+            "kind": kind,
+            "body": body,
+            "prev": self.head.last_hash,
+        });
+        let hash = chain_hash(&record);
+        record["hash"] = Value::from(hash.clone());
+        serde_json::to_writer(&mut self.writer, &record).map_err(io::Error::other)?;
+        self.writer.write_all(b"\n")?;
+        self.head = ChainHead {
+            next_sequence: self.head.next_sequence + 1,
+            last_hash: hash,
+        };
+        Ok(())
+    }
+}
+
+/// Applies `command` to `order_book` and records it in `log`. The command
+/// is attributed to the participant it names, the buyer for a cross or, for
+/// modifies and cancels, to the owner of the order it targets, and a
+/// modify's amendment is recorded after its events. Writing the log counts as the command's
+/// persistence stage if the book is tracking latency.
+pub fn process<W: Write>(
+    order_book: &mut OrderBook,
+    log: &mut AuditLog<W>,
+    command: OrderCommand,
+) -> io::Result<()> {
+    let participant = match command {
+        OrderCommand::New { participant, .. } | OrderCommand::MassQuote { participant, .. } => {
+            Some(participant)
+        }
+        OrderCommand::Modify { id, .. } | OrderCommand::Cancel { id, .. } => {
+            order_book.find_order(id).map(|order| order.participant)
+        }
+        OrderCommand::Cross { buyer, .. } => Some(buyer),
+        OrderCommand::KillSwitch { .. }
+        | OrderCommand::Halt
+        | OrderCommand::Resume { .. }
+        | OrderCommand::BustTrade { .. } => None,
+    };
+    let amended = match command {
+        OrderCommand::Modify { id, .. } => Some((id, order_book.amendments(id).len())),
+        _ => None,
+    };
+    let seen = order_book.events().len();
+    order_book.process_command(command.clone());
+    let symbol = order_book.symbol().to_string();
+    let start = Instant::now();
+    log.record(&symbol, participant, &command, &order_book.events()[seen..])?;
+    let amendment: Option<&Amendment> =
+        amended.and_then(|(id, before)| order_book.amendments(id).get(before));
+    if let Some(amendment) = amendment {
+        let body = json!({ "amendment": amendment });
+        log.write(&symbol, participant, "amendment", body)?;
+        log.writer.flush()?;
+    }
+    if let Some(latency) = order_book.latency_mut() {
+        latency.record(Stage::Persistence, start.elapsed());
+    }
+    Ok(())
+}
+
+/// Ends `order_book`'s trading session, see [`OrderBook::end_of_day`], and
+/// records the events that produced in `log`.
+pub fn end_of_day<W: Write>(
+    order_book: &mut OrderBook,
+    log: &mut AuditLog<W>,
+) -> io::Result<SessionSummary> {
+    let seen = order_book.events().len();
+    let summary = order_book.end_of_day();
+    let symbol = order_book.symbol().to_string();
+    for event in &order_book.events()[seen..] {
+        log.write(&symbol, None, "event", json!({ "event": event }))?;
+    }
+    log.writer.flush()?;
+    Ok(summary)
+}
+
+/// Checks every record's sequence number and hash links, returning where
+/// the chain ends.
+pub fn verify<R: BufRead>(reader: R) -> io::Result<ChainHead> {
+    let mut head = ChainHead::default();
+    for line in reader.lines() {
+        let line = line?;
+        let broken = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("audit record {}: {}", head.next_sequence, reason),
+            )
+        };
+        let mut record: Value = serde_json::from_str(&line).map_err(|_| broken("not JSON"))?;
+        if record["seq"].as_u64() != Some(head.next_sequence) {
+            return Err(broken("sequence gap"));
+        }
+        if record["prev"].as_str() != Some(head.last_hash.as_str()) {
+            return Err(broken("does not follow the previous record"));
+        }
+        let hash = record
+            .as_object_mut()
+            .and_then(|fields| fields.remove("hash"))
+            .ok_or_else(|| broken("missing hash"))?;
+        let expected = chain_hash(&record);
+        if hash.as_str() != Some(expected.as_str()) {
+            return Err(broken("hash mismatch"));
+        }
+        head = ChainHead {
+            next_sequence: head.next_sequence + 1,
+            last_hash: expected,
+        };
+    }
+    Ok(head)
+}
+
+fn chain_hash(record: &Value) -> String {
+    sha256::to_hex(&sha256::sha256(record.to_string().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{end_of_day, process, verify, AuditLog, ChainHead};
+    use crate::risk::RiskLimits;
+    use crate::testing::new_order_for;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    fn audited_session() -> (OrderBook, AuditLog<Vec<u8>>) {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        order_book.risk_mut().set_default(RiskLimits {
+            max_order_qty: Some(10),
+            ..RiskLimits::default()
+        });
+        let mut log = AuditLog::new(Vec::new());
+        for command in [
+            new_order_for(Side::Sell, 100, 5, 1),
+            new_order_for(Side::Buy, 100, 50, 2),
+            new_order_for(Side::Buy, 100, 3, 2),
+            OrderCommand::Halt,
+        ] {
+            process(&mut order_book, &mut log, command).unwrap();
+        }
+        (order_book, log)
+    }
+
+    #[test]
+    fn records_commands_decisions_and_events() {
+        let (_, log) = audited_session();
+        let output = String::from_utf8(log.into_inner()).unwrap();
+        let records: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let kinds: Vec<&str> = records
+            .iter()
+            .map(|record| record["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "command", "decision", "event", // sell rests
+                "command", "decision", "event", // oversized buy rejected
+                "command", "decision", "event", "event", "event", "event", // buy trades
+                "command", "decision", "event", // halt
+            ]
+        );
+        assert_eq!(records[3]["participant"], 2);
+        assert_eq!(records[4]["body"]["accepted"], false);
+        assert_eq!(records[4]["body"]["reason"], "MaxOrderQty");
+        assert_eq!(records[6]["body"]["command"]["New"]["qty"], 3);
+        assert_eq!(records[8]["body"]["event"]["Placed"]["participant"], 2);
+        assert!(records[12]["participant"].is_null());
+        assert_eq!(records[14]["seq"], 15);
+
+        let head = verify(output.as_bytes()).unwrap();
+        assert_eq!(head.next_sequence, 16);
+        assert_eq!(head.last_hash, records[14]["hash"]);
+    }
+
+    #[test]
+    fn records_amendments() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let mut log = AuditLog::new(Vec::new());
+        let modify = OrderCommand::Modify {
+            id: 1,
+            price: 100,
+            side: Side::Sell,
+            qty: 8,
+            order_type: OrderType::GoodTilCancel,
+        };
+        for command in [new_order_for(Side::Sell, 100, 5, 1), modify.clone(), modify] {
+            process(&mut order_book, &mut log, command).unwrap();
+        }
+        let output = String::from_utf8(log.into_inner()).unwrap();
+        let amendments: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|record| record["kind"] == "amendment")
+            .collect();
+        // The second modify finds order 1 gone and amends nothing.
+        assert_eq!(amendments.len(), 1);
+        assert_eq!(amendments[0]["participant"], 1);
+        assert_eq!(amendments[0]["body"]["amendment"]["new_id"], 2);
+        assert_eq!(amendments[0]["body"]["amendment"]["old_qty"], 5);
+        assert_eq!(amendments[0]["body"]["amendment"]["new_qty"], 8);
+        verify(output.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let (_, log) = audited_session();
+        let output = String::from_utf8(log.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        let edited = output.replacen("\"qty\":50", "\"qty\":5", 1);
+        let error = verify(edited.as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "audit record 4: hash mismatch");
+
+        let dropped = [&lines[..4], &lines[5..]].concat().join("\n");
+        let error = verify(dropped.as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "audit record 5: sequence gap");
+
+        let mut swapped = lines.clone();
+        swapped.swap(1, 2);
+        assert!(verify(swapped.join("\n").as_bytes()).is_err());
+    }
+
+    #[test]
+    fn resumes_an_existing_chain() {
+        let (mut order_book, log) = audited_session();
+        let head = log.head().clone();
+        let mut output = log.into_inner();
+        assert_eq!(verify(&output[..]).unwrap(), head);
+
+        let mut log = AuditLog::resume(Vec::new(), head);
+        process(
+            &mut order_book,
+            &mut log,
+            OrderCommand::Resume { auction: true },
+        )
+        .unwrap();
+        output.extend(log.into_inner());
+        let head = verify(&output[..]).unwrap();
+        assert!(head.next_sequence > 16);
+        assert_ne!(head, ChainHead::default());
+    }
+
+    #[test]
+    fn rolls_at_end_of_day() {
+        let (mut order_book, mut log) = audited_session();
+        let summary = end_of_day(&mut order_book, &mut log).unwrap();
+        assert_eq!(summary.stats.trade_count, 1);
+        let session = log.roll(Vec::new()).unwrap();
+        let head = verify(&session[..]).unwrap();
+        assert_eq!(head.next_sequence, 18);
+        let last = session.split(|&b| b == b'\n').rev().nth(1).unwrap();
+        let last: serde_json::Value = serde_json::from_slice(last).unwrap();
+        assert!(last["body"]["event"]["SessionEnded"].is_object());
+
+        process(
+            &mut order_book,
+            &mut log,
+            new_order_for(Side::Sell, 100, 1, 1),
+        )
+        .unwrap();
+        let next = log.into_inner();
+        assert_eq!(verify(&next[..]).unwrap().next_sequence, 4);
+    }
+}
@@ -0,0 +1,325 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A common interface for order book implementations.
+//!
+//! [`OrderBookBackend`] is the part of a book that engine and server code
+//! needs to drive it: submit commands, cancel and modify by order id, look
+//! up resting orders, and read the event log, depth, trades and a full
+//! snapshot. [`OrderBook`] is one backend; the HTTP and ZeroMQ servers, the
+//! [`Gateway`](crate::gateway::Gateway) and the C ABI are written against
+//! the trait, so they take a differently laid out book, or a test double
+//! that records what it was sent, without changes.
+//!
+//! The trait speaks `i32` prices, as the servers and codecs behind it do,
+//! so only the default `OrderBook<i32>` implements it. A book priced in
+//! another type is driven through its own methods.
+
+use crate::order_book::{Bbo, Depth};
+use crate::positions::Position;
+use crate::snapshot::BookSnapshot;
+use crate::{Order, OrderBook, OrderCommand, OrderEvent, OrderId, ParticipantId, Trade};
+
+pub trait OrderBookBackend {
+    fn symbol(&self) -> &str;
+
+    /// Every event the book has produced, oldest first.
+    fn events(&self) -> &[OrderEvent];
+
+    /// Runs `command`, returning the events it produced.
+    fn submit(&mut self, command: OrderCommand) -> Vec<OrderEvent>;
+
+    /// Cancels the resting order `id`. Nothing happens, and no events are
+    /// returned, if there is no such order.
+    fn cancel(&mut self, id: OrderId) -> Vec<OrderEvent>;
+
+    /// Amends the resting order `id` to `qty`, keeping its price, side and
+    /// type. The amended order may come back under a new id. Nothing
+    /// happens if there is no such order.
+    fn modify(&mut self, id: OrderId, qty: u32) -> Vec<OrderEvent>;
+
+    /// Cancels every resting order of `participant`, returning how many
+    /// were canceled.
+    fn cancel_all(&mut self, participant: ParticipantId) -> usize;
+
+    /// The resting order `id`, if there is one.
+    fn find_order(&self, id: OrderId) -> Option<&Order>;
+
+    /// Whether `id` is one of the participant's resting orders. Backends
+    /// that index orders by participant should answer from the index.
+    fn owns(&self, participant: ParticipantId, id: OrderId) -> bool {
+        self.find_order(id)
+            .is_some_and(|order| order.participant == participant)
+    }
+
+    /// Up to `levels` price levels a side, best first.
+    fn depth(&self, levels: usize) -> Depth;
+
+    /// The best level on each side.
+    fn bbo(&self) -> Bbo {
+        let depth = self.depth(1);
+        Bbo {
+            bid: depth.bids.first().copied(),
+            ask: depth.asks.first().copied(),
+        }
+    }
+
+    /// The session's trades, oldest first.
+    fn trades(&self) -> &[Trade];
+
+    /// The participant's position in the book's symbol.
+    fn position(&self, participant: ParticipantId) -> Position;
+
+    fn snapshot(&self) -> BookSnapshot;
+}
+
+/// The default `i32` book only; see the module docs.
+impl OrderBookBackend for OrderBook {
+    fn symbol(&self) -> &str {
+        OrderBook::symbol(self)
+    }
+
+    fn events(&self) -> &[OrderEvent] {
+        OrderBook::events(self)
+    }
+
+    fn submit(&mut self, command: OrderCommand) -> Vec<OrderEvent> {
+        let seen = self.events().len();
+        self.process_command(command);
+        self.events()[seen..].to_vec()
+    }
+
+    fn cancel(&mut self, id: OrderId) -> Vec<OrderEvent> {
+        match self.find_order(id) {
+            Some(order) => {
+                let (side, price) = (order.side, order.price);
+                self.submit(OrderCommand::Cancel { id, side, price })
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn modify(&mut self, id: OrderId, qty: u32) -> Vec<OrderEvent> {
+        match self.find_order(id) {
+            Some(order) => {
+                let (side, price, order_type) = (order.side, order.price, order.order_type);
+                self.submit(OrderCommand::Modify {
+                    id,
+                    price,
+                    side,
+                    qty,
+                    order_type,
+                })
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn cancel_all(&mut self, participant: ParticipantId) -> usize {
+        OrderBook::cancel_all(self, participant)
+    }
+
+    fn find_order(&self, id: OrderId) -> Option<&Order> {
+        OrderBook::find_order(self, id)
+    }
+
+    fn owns(&self, participant: ParticipantId, id: OrderId) -> bool {
+        OrderBook::owns(self, participant, id)
+    }
+
+    fn depth(&self, levels: usize) -> Depth {
+        OrderBook::depth(self, levels)
+    }
+
+    /// Leaves out odd lots where the book's odd-lot policy says to.
+    fn bbo(&self) -> Bbo {
+        OrderBook::bbo(self)
+    }
+
+    fn trades(&self) -> &[Trade] {
+        OrderBook::trades(self)
+    }
+
+    fn position(&self, participant: ParticipantId) -> Position {
+        OrderBook::position(self, participant)
+    }
+
+    fn snapshot(&self) -> BookSnapshot {
+        BookSnapshot::of(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderBookBackend;
+    use crate::gateway::{Gateway, Outbound, SessionConfig};
+    use crate::order_book::Depth;
+    use crate::positions::Position;
+    use crate::snapshot::BookSnapshot;
+    use crate::{
+        Order, OrderBook, OrderCommand, OrderEvent, OrderId, OrderType, ParticipantId, Side, Trade,
+    };
+    use std::time::Instant;
+
+    /// Places a bid, cuts it and pulls it, returning the events.
+    fn work_bid(book: &mut impl OrderBookBackend) -> Vec<OrderEvent> {
+        let mut events = book.submit(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 99,
+            qty: 10,
+            participant: 1,
+        });
+        let placed = |events: &[OrderEvent]| {
+            events.iter().rev().find_map(|event| match event {
+                OrderEvent::Placed { id, .. } => Some(*id),
+                _ => None,
+            })
+        };
+        let Some(id) = placed(&events) else {
+            return events;
+        };
+        events.extend(book.modify(id, 5));
+        assert_eq!(book.depth(1).bids[0].qty, 5);
+        let id = placed(&events).unwrap();
+        events.extend(book.cancel(id));
+        events
+    }
+
+    /// Remembers what it is sent, and never trades.
+    #[derive(Default)]
+    struct Recorder {
+        commands: Vec<OrderCommand>,
+        cancels: Vec<OrderId>,
+        bid: Option<u32>,
+        events: Vec<OrderEvent>,
+    }
+
+    impl Recorder {
+        fn emit(&mut self, event: OrderEvent) -> Vec<OrderEvent> {
+            self.events.push(event.clone());
+            vec![event]
+        }
+    }
+
+    impl OrderBookBackend for Recorder {
+        fn symbol(&self) -> &str {
+            "TEST"
+        }
+
+        fn events(&self) -> &[OrderEvent] {
+            &self.events
+        }
+
+        fn submit(&mut self, command: OrderCommand) -> Vec<OrderEvent> {
+            self.commands.push(command);
+            self.emit(OrderEvent::Placed {
+                id: 1,
+                participant: 1,
+                side: Side::Buy,
+                order_type: OrderType::GoodTilCancel,
+                price: 99,
+                timestamp: crate::Timestamp::from_nanos(0),
+                arrival: 1,
+            })
+        }
+
+        fn cancel(&mut self, id: OrderId) -> Vec<OrderEvent> {
+            self.cancels.push(id);
+            self.emit(OrderEvent::Canceled { id })
+        }
+
+        fn modify(&mut self, _id: OrderId, qty: u32) -> Vec<OrderEvent> {
+            self.bid = Some(qty);
+            self.emit(OrderEvent::Modified)
+        }
+
+        fn cancel_all(&mut self, _participant: ParticipantId) -> usize {
+            0
+        }
+
+        fn find_order(&self, _id: OrderId) -> Option<&Order> {
+            None
+        }
+
+        fn depth(&self, _levels: usize) -> Depth {
+            let level = |qty| crate::order_book::LevelInfo {
+                price: 99,
+                qty: u64::from(qty),
+                order_count: 1,
+            };
+            Depth {
+                bids: self.bid.map(level).into_iter().collect(),
+                asks: Vec::new(),
+            }
+        }
+
+        fn trades(&self) -> &[Trade] {
+            &[]
+        }
+
+        fn position(&self, _participant: ParticipantId) -> Position {
+            Position::default()
+        }
+
+        fn snapshot(&self) -> BookSnapshot {
+            BookSnapshot::of(&OrderBook::with_symbol("TEST"))
+        }
+    }
+
+    #[test]
+    fn drives_any_backend() {
+        let mut order_book = OrderBook::new();
+        let events = work_bid(&mut order_book);
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[3], OrderEvent::Canceled { id: 2 });
+        assert!(order_book.snapshot().bids.is_empty());
+
+        let mut recorder = Recorder::default();
+        let events = work_bid(&mut recorder);
+        assert_eq!(
+            events[1..],
+            [OrderEvent::Modified, OrderEvent::Canceled { id: 1 }]
+        );
+        assert_eq!(recorder.commands.len(), 1);
+        assert_eq!(recorder.cancels, [1]);
+        assert_eq!(recorder.events(), events);
+        assert_eq!(recorder.bbo().bid.map(|level| level.qty), Some(5));
+    }
+
+    #[test]
+    fn gateway_reports_from_any_backend() {
+        let mut gateway = Gateway::new();
+        let mut recorder = Recorder::default();
+        let now = Instant::now();
+        let session = gateway.logon(1, SessionConfig::default(), now).unwrap();
+        let bid = OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 99,
+            qty: 10,
+            participant: 0,
+        };
+        gateway.submit(&mut recorder, session, bid, now).unwrap();
+        assert_eq!(recorder.commands.len(), 1);
+        let reports: Vec<OrderEvent> = gateway
+            .drain(session)
+            .into_iter()
+            .filter_map(|sequenced| match sequenced.message {
+                Outbound::ExecutionReport { event, .. } => Some(event),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(reports, recorder.events());
+
+        // The recorder holds no orders, so none of them are the session's.
+        let cancel = OrderCommand::Cancel {
+            id: 1,
+            side: Side::Buy,
+            price: 99,
+        };
+        assert!(gateway.submit(&mut recorder, session, cancel, now).is_err());
+        assert!(recorder.cancels.is_empty());
+    }
+}
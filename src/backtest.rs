@@ -0,0 +1,528 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Backtesting strategies against recorded order flow.
+//!
+//! A backtest replays historical records (see [`crate::replay`]) into a
+//! fresh book and gives a [`Strategy`] a turn after each one. Its orders go
+//! through the matcher alongside the recorded flow, so they queue behind
+//! what was already resting at their price, and recorded executions fill
+//! them whenever they are ahead of the order the execution was for. The
+//! book's clock follows the recorded times throughout.
+//!
+//! The strategy trades as one participant, [`STRATEGY_PARTICIPANT`] unless
+//! set otherwise, which should not appear in the recording. Its fills are
+//! passed to [`Strategy::on_fill`] and collected in the [`BacktestReport`]
+//! along with the position they add up to.
+//!
+//! Two delays can be modeled, each drawn from a [`LatencyModel`]: from the
+//! strategy sending an order or cancel to the book applying it, and from
+//! the book producing events to the strategy hearing of them. Orders and
+//! events each stay in the order they were sent, as on one session. Until
+//! an order arrives the strategy only has the [`Ticket`] it was sent with,
+//! and a record that comes in first gets ahead of it in the queue. With no
+//! latency, everything the strategy sends is applied as soon as its
+//! callback returns. The book the strategy can look at is always the live
+//! one; only events are late.
+
+use crate::clock::simulated::SimulatedClock;
+use crate::clock::Clock;
+use crate::positions::Position;
+use crate::replay::{Record, ReplayReport, Replayer};
+use crate::sim::latency::LatencyModel;
+use crate::sim::Rng;
+use crate::{OrderBook, OrderCommand, OrderEvent, OrderId, OrderType, ParticipantId, Side};
+use crate::{Timestamp, Trade};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::time::Instant;
+
+pub const STRATEGY_PARTICIPANT: ParticipantId = ParticipantId::MAX;
+
+/// Names an order the strategy sent, from before the book has given it an
+/// id.
+pub type Ticket = u64;
+
+/// Trading logic under test. Both callbacks can place and cancel orders
+/// through the [`Context`].
+pub trait Strategy {
+    /// Called once the strategy has heard what each record did to the book.
+    fn on_record(&mut self, context: &mut Context<'_>);
+
+    /// Called for every fill of one of the strategy's orders, as it hears
+    /// of it.
+    fn on_fill(&mut self, _context: &mut Context<'_>, _fill: &Fill) {}
+}
+
+/// One fill of a strategy order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fill {
+    pub trade_id: usize,
+    pub ticket: Ticket,
+    pub order_id: OrderId,
+    pub side: Side,
+    pub price: i32,
+    pub qty: u32,
+    /// Whether the strategy's order was the one that crossed.
+    pub aggressive: bool,
+    /// When the trade happened, which with latency is before the strategy
+    /// hears of it.
+    pub timestamp: Timestamp,
+}
+
+impl Fill {
+    /// The strategy's side of `trade`, if it took part in it.
+    fn of(trade: &Trade, participant: ParticipantId, ticket: Ticket) -> Option<Fill> {
+        let (order_id, side, aggressive) = if trade.taker_participant == participant {
+            (trade.taker_order_id, trade.aggressor_side, true)
+        } else if trade.maker_participant == participant {
+            (trade.maker_order_id, trade.aggressor_side.opposite(), false)
+        } else {
+            return None;
+        };
+        Some(Fill {
+            trade_id: trade.id,
+            ticket,
+            order_id,
+            side,
+            price: trade.price,
+            qty: trade.qty,
+            aggressive,
+            timestamp: trade.timestamp,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BacktestConfig {
+    pub participant: ParticipantId,
+    /// From the strategy to the book.
+    pub order_latency: LatencyModel,
+    /// From the book to the strategy.
+    pub feed_latency: LatencyModel,
+    /// Seeds the latency samples.
+    pub seed: u64,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        BacktestConfig {
+            participant: STRATEGY_PARTICIPANT,
+            order_latency: LatencyModel::Zero,
+            feed_latency: LatencyModel::Zero,
+            seed: 0,
+        }
+    }
+}
+
+/// Something in flight.
+enum Pending {
+    /// A request from the strategy reaching the book.
+    Order(Ticket, Request),
+    /// Events reaching the strategy. `record` is set if they came of a
+    /// record, which the strategy then gets its turn for.
+    Events {
+        events: Vec<OrderEvent>,
+        record: bool,
+    },
+}
+
+enum Request {
+    Place(OrderCommand),
+    Cancel,
+}
+
+/// Everything about a backtest but the strategy.
+struct Session {
+    order_book: OrderBook,
+    clock: SimulatedClock,
+    config: BacktestConfig,
+    rng: Rng,
+    /// Keyed by arrival time, then by when they were sent.
+    pending: BTreeMap<(Timestamp, u64), Pending>,
+    sent: u64,
+    /// When the last order and the last events arrive, which later ones
+    /// can't overtake.
+    last_order: Timestamp,
+    last_events: Timestamp,
+    /// Book events already sent on to the strategy.
+    seen: usize,
+    next_ticket: Ticket,
+    order_ids: HashMap<Ticket, OrderId>,
+    tickets: HashMap<OrderId, Ticket>,
+    canceled: Vec<Ticket>,
+    fills: Vec<Fill>,
+}
+
+impl Session {
+    fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    fn send(&mut self, at: Timestamp, pending: Pending) {
+        self.pending.insert((at, self.sent), pending);
+        self.sent += 1;
+    }
+
+    fn send_order(&mut self, ticket: Ticket, request: Request) {
+        let at =
+            (self.now() + self.config.order_latency.sample(&mut self.rng)).max(self.last_order);
+        self.last_order = at;
+        self.send(at, Pending::Order(ticket, request));
+    }
+
+    /// Sends the strategy the events the book has produced since last time.
+    fn publish(&mut self, record: bool) {
+        let events = self.order_book.events()[self.seen..].to_vec();
+        self.seen += events.len();
+        if events.is_empty() && !record {
+            return;
+        }
+        let at =
+            (self.now() + self.config.feed_latency.sample(&mut self.rng)).max(self.last_events);
+        self.last_events = at;
+        self.send(at, Pending::Events { events, record });
+    }
+
+    fn arrive(&mut self, ticket: Ticket, request: Request) {
+        match request {
+            Request::Place(command) => {
+                let seen = self.order_book.events().len();
+                self.order_book.process_command(command);
+                let placed =
+                    self.order_book.events()[seen..]
+                        .iter()
+                        .find_map(|event| match *event {
+                            OrderEvent::Placed { id, .. } => Some(id),
+                            _ => None,
+                        });
+                if let Some(id) = placed {
+                    self.order_ids.insert(ticket, id);
+                    self.tickets.insert(id, ticket);
+                }
+            }
+            Request::Cancel => {
+                let Some(&id) = self.order_ids.get(&ticket) else {
+                    return;
+                };
+                if let Some(order) = self.order_book.find_order(id) {
+                    let (side, price) = (order.side, order.price);
+                    self.order_book
+                        .process_command(OrderCommand::Cancel { id, side, price });
+                }
+            }
+        }
+        self.publish(false);
+    }
+}
+
+/// What a strategy sees of the backtest, and how it trades.
+pub struct Context<'a> {
+    session: &'a mut Session,
+}
+
+impl Context<'_> {
+    pub fn order_book(&self) -> &OrderBook {
+        &self.session.order_book
+    }
+
+    /// When the strategy is acting: the time of the record or fill it was
+    /// just told of, plus the feed latency.
+    pub fn now(&self) -> Timestamp {
+        self.session.now()
+    }
+
+    pub fn position(&self) -> Position {
+        let order_book = &self.session.order_book;
+        order_book
+            .positions()
+            .get(self.session.config.participant, order_book.symbol())
+    }
+
+    pub fn buy(&mut self, order_type: OrderType, price: i32, qty: u32) -> Ticket {
+        self.place(order_type, Side::Buy, price, qty)
+    }
+
+    pub fn sell(&mut self, order_type: OrderType, price: i32, qty: u32) -> Ticket {
+        self.place(order_type, Side::Sell, price, qty)
+    }
+
+    /// Sends an order to the book.
+    pub fn place(&mut self, order_type: OrderType, side: Side, price: i32, qty: u32) -> Ticket {
+        let ticket = self.session.next_ticket;
+        self.session.next_ticket += 1;
+        let command = OrderCommand::New {
+            order_type,
+            side,
+            price,
+            qty,
+            participant: self.session.config.participant,
+        };
+        self.session.send_order(ticket, Request::Place(command));
+        ticket
+    }
+
+    /// Sends a cancel for the order. It does nothing if the order has
+    /// traded away or was rejected by the time it arrives. Returns false if
+    /// the order was already canceled or never sent.
+    pub fn cancel(&mut self, ticket: Ticket) -> bool {
+        if ticket >= self.session.next_ticket || self.session.canceled.contains(&ticket) {
+            return false;
+        }
+        self.session.canceled.push(ticket);
+        self.session.send_order(ticket, Request::Cancel);
+        true
+    }
+
+    /// The id the book gave the order, once it has arrived.
+    pub fn order_id(&self, ticket: Ticket) -> Option<OrderId> {
+        self.session.order_ids.get(&ticket).copied()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestReport {
+    pub replay: ReplayReport,
+    pub fills: Vec<Fill>,
+    /// Where the strategy ended up.
+    pub position: Position,
+    /// The last trade price, which open quantity is marked at.
+    pub mark: Option<i32>,
+}
+
+impl BacktestReport {
+    /// Realized PnL plus the open position marked at the last trade.
+    pub fn pnl(&self) -> f64 {
+        let unrealized = self
+            .mark
+            .map_or(0.0, |mark| self.position.unrealized_pnl(mark));
+        self.position.realized_pnl + unrealized
+    }
+}
+
+/// Replays `records` into a fresh book for `symbol` with `strategy` trading
+/// as [`STRATEGY_PARTICIPANT`] with no latency.
+pub fn run(
+    symbol: &str,
+    records: impl IntoIterator<Item = io::Result<Record>>,
+    strategy: &mut impl Strategy,
+) -> io::Result<BacktestReport> {
+    run_with(symbol, records, strategy, BacktestConfig::default())
+}
+
+/// Like [`run`], with the participant and latencies in `config`.
+pub fn run_with(
+    symbol: &str,
+    records: impl IntoIterator<Item = io::Result<Record>>,
+    strategy: &mut impl Strategy,
+    config: BacktestConfig,
+) -> io::Result<BacktestReport> {
+    let mut session: Option<Session> = None;
+    let mut replayer = Replayer::default();
+    let mut report = ReplayReport::default();
+    let mut first = Timestamp::default();
+    let start = Instant::now();
+    for record in records {
+        let record = record?;
+        let session = session.get_or_insert_with(|| {
+            first = record.at;
+            let clock = SimulatedClock::starting_at(record.at);
+            let mut order_book = OrderBook::with_symbol(symbol);
+            order_book.set_clock(clock.clock());
+            Session {
+                order_book,
+                clock,
+                config,
+                rng: Rng::new(config.seed),
+                pending: BTreeMap::new(),
+                sent: 0,
+                last_order: record.at,
+                last_events: record.at,
+                seen: 0,
+                next_ticket: 1,
+                order_ids: HashMap::new(),
+                tickets: HashMap::new(),
+                canceled: Vec::new(),
+                fills: Vec::new(),
+            }
+        });
+        // Anything in flight that gets there first, then the record, which
+        // wins a tie.
+        settle(session, strategy, |at| at < record.at);
+        session.clock.advance_to(&mut session.order_book, record.at);
+        if !replayer.apply(&mut session.order_book, record.action) {
+            report.unknown_orders += 1;
+        }
+        session.publish(true);
+        settle(session, strategy, |at| at <= record.at);
+        report.records += 1;
+        report.recorded = record.at - first;
+    }
+    let Some(mut session) = session else {
+        return Ok(BacktestReport {
+            replay: report,
+            fills: Vec::new(),
+            position: Position::default(),
+            mark: None,
+        });
+    };
+    settle(&mut session, strategy, |_| true);
+    report.elapsed = start.elapsed();
+    report.trades = session.order_book.trades().len();
+    Ok(BacktestReport {
+        replay: report,
+        fills: session.fills,
+        position: session
+            .order_book
+            .positions()
+            .get(config.participant, symbol),
+        mark: session
+            .order_book
+            .tape()
+            .last(1)
+            .first()
+            .map(|trade| trade.price),
+    })
+}
+
+/// Delivers everything in flight that arrives while `due`, in order of
+/// arrival, including whatever the strategy sends in the meantime.
+fn settle(session: &mut Session, strategy: &mut impl Strategy, due: impl Fn(Timestamp) -> bool) {
+    loop {
+        let Some(&(at, sent)) = session.pending.keys().next() else {
+            return;
+        };
+        if !due(at) {
+            return;
+        }
+        let Some(pending) = session.pending.remove(&(at, sent)) else {
+            return;
+        };
+        session.clock.advance_to(&mut session.order_book, at);
+        match pending {
+            Pending::Order(ticket, request) => session.arrive(ticket, request),
+            Pending::Events { events, record } => {
+                let participant = session.config.participant;
+                for event in &events {
+                    let OrderEvent::Trade(trade) = event else {
+                        continue;
+                    };
+                    let ours = [trade.maker_order_id, trade.taker_order_id]
+                        .into_iter()
+                        .find_map(|id| session.tickets.get(&id).copied());
+                    let Some(fill) = ours.and_then(|ticket| Fill::of(trade, participant, ticket))
+                    else {
+                        continue;
+                    };
+                    session.fills.push(fill);
+                    strategy.on_fill(&mut Context { session }, &fill);
+                }
+                if record {
+                    strategy.on_record(&mut Context { session });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, run_with, BacktestConfig, Context, Fill, Strategy, Ticket};
+    use crate::replay::read_csv;
+    use crate::sim::latency::LatencyModel;
+    use crate::{OrderType, Side};
+
+    /// Joins the bid once, then offers whatever it buys at the ask.
+    #[derive(Default)]
+    struct JoinTheBid {
+        bid: Option<Ticket>,
+        offered: u32,
+    }
+
+    impl Strategy for JoinTheBid {
+        fn on_record(&mut self, context: &mut Context<'_>) {
+            if self.bid.is_none() {
+                if let Some(price) = context.order_book().best_bid() {
+                    self.bid = Some(context.buy(OrderType::GoodTilCancel, price, 4));
+                }
+            }
+        }
+
+        fn on_fill(&mut self, context: &mut Context<'_>, fill: &Fill) {
+            if fill.side == Side::Buy {
+                let price = context.order_book().best_ask().unwrap_or(fill.price + 2);
+                context.sell(OrderType::GoodTilCancel, price, fill.qty);
+                self.offered += fill.qty;
+                // Done buying after the first fill.
+                let bid = self.bid.unwrap();
+                assert_eq!(Some(fill.ticket), self.bid);
+                assert!(context.cancel(bid));
+                assert!(!context.cancel(bid));
+            }
+        }
+    }
+
+    const FLOW: &str = "\
+1000,gtc,1,buy,100,5,1
+1000,gtc,2,sell,102,5,2
+2000,fak,3,sell,100,8,3
+3000,gtc,4,buy,102,7,4
+";
+
+    #[test]
+    fn strategy_trades_against_recorded_flow() {
+        let mut strategy = JoinTheBid::default();
+        let report = run("BT", read_csv(FLOW.as_bytes()), &mut strategy).unwrap();
+        let fills: Vec<_> = report
+            .fills
+            .iter()
+            .map(|fill| (fill.side, fill.price, fill.qty, fill.aggressive))
+            .collect();
+        // Behind participant 1 at the bid, then behind 2 at the ask.
+        assert_eq!(
+            fills,
+            [(Side::Buy, 100, 3, false), (Side::Sell, 102, 2, false)]
+        );
+        assert_eq!(strategy.offered, 3);
+        assert_eq!(report.replay.records, 4);
+        assert_eq!(report.position.net_qty, 1);
+        assert_eq!(report.position.realized_pnl, 4.0);
+        assert_eq!(report.mark, Some(102));
+        assert_eq!(report.pnl(), 6.0);
+    }
+
+    #[test]
+    fn latency_costs_queue_position() {
+        // The strategy hears of the first bid a microsecond after it and
+        // joins it. Another bid is recorded half a microsecond later, which
+        // is ahead of the strategy's if that takes any time to arrive.
+        let flow = "\
+1000,gtc,1,buy,100,5,1
+2500,gtc,5,buy,100,2,5
+1000000,gtc,2,sell,102,5,2
+2000000,fak,3,sell,100,8,3
+3000000,gtc,4,buy,102,7,4
+";
+        let fills = |order_micros: u64| {
+            let config = BacktestConfig {
+                order_latency: LatencyModel::Fixed {
+                    micros: order_micros,
+                },
+                feed_latency: LatencyModel::Fixed { micros: 1 },
+                ..BacktestConfig::default()
+            };
+            let mut strategy = JoinTheBid::default();
+            let report = run_with("BT", read_csv(flow.as_bytes()), &mut strategy, config).unwrap();
+            report.fills
+        };
+        let fast = fills(0);
+        assert_eq!((fast[0].qty, fast[0].price), (3, 100));
+        // Stamped when it traded rather than when it was heard of.
+        assert_eq!(fast[0].timestamp.as_nanos(), 2_000_000);
+        assert_eq!((fast[1].qty, fast[1].price), (2, 102));
+        let slow = fills(1);
+        assert_eq!((slow[0].qty, slow[0].price), (1, 100));
+        assert_eq!((slow[1].qty, slow[1].price), (1, 102));
+    }
+}
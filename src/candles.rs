@@ -0,0 +1,272 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! OHLCV bars built from the trade stream.
+//!
+//! Bars are aligned to wall-clock boundaries (a one minute bar starts on the
+//! minute) and only exist for periods that saw at least one trade. A bar
+//! closes when a trade lands in a later period or when [`CandleAggregator::roll`]
+//! is told the period is over, whichever comes first; closed bars are what
+//! gets published on the market data feed.
+
+use crate::sink::EventSink;
+use crate::{OrderEvent, Trade};
+use serde::Serialize;
+use std::io;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum Interval {
+    OneSecond,
+    OneMinute,
+    FiveMinutes,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 3] = [
+        Interval::OneSecond,
+        Interval::OneMinute,
+        Interval::FiveMinutes,
+    ];
+
+    pub fn seconds(self) -> u32 {
+        match self {
+            Interval::OneSecond => 1,
+            Interval::OneMinute => 60,
+            Interval::FiveMinutes => 300,
+        }
+    }
+
+    pub fn from_seconds(seconds: u32) -> Option<Interval> {
+        Interval::ALL
+            .into_iter()
+            .find(|interval| interval.seconds() == seconds)
+    }
+
+    fn nanos(self) -> u64 {
+        u64::from(self.seconds()) * NANOS_PER_SEC
+    }
+
+    /// Start of the period containing `time`, in nanoseconds since the
+    /// Unix epoch.
+    pub fn period_start(self, time: u64) -> u64 {
+        time - time % self.nanos()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Candle {
+    pub interval: Interval,
+    /// Period start, nanoseconds since the Unix epoch.
+    pub start: u64,
+    pub open: i32,
+    pub high: i32,
+    pub low: i32,
+    pub close: i32,
+    pub volume: u64,
+    pub trade_count: u32,
+}
+
+impl Candle {
+    fn new(interval: Interval, start: u64, price: i32, qty: u32) -> Candle {
+        Candle {
+            interval,
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: u64::from(qty),
+            trade_count: 1,
+        }
+    }
+
+    fn add(&mut self, price: i32, qty: u32) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += u64::from(qty);
+        self.trade_count += 1;
+    }
+
+    /// First nanosecond after the bar's period.
+    pub fn end(&self) -> u64 {
+        self.start + self.interval.nanos()
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Series {
+    closed: Vec<Candle>,
+    open: Option<Candle>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandleAggregator {
+    series: Vec<(Interval, Series)>,
+    /// Bars closed since the last call to [`CandleAggregator::take_closed`].
+    newly_closed: Vec<Candle>,
+}
+
+impl Default for CandleAggregator {
+    fn default() -> Self {
+        CandleAggregator::new(&Interval::ALL)
+    }
+}
+
+impl CandleAggregator {
+    pub fn new(intervals: &[Interval]) -> Self {
+        let mut intervals = intervals.to_vec();
+        intervals.sort_unstable();
+        intervals.dedup();
+        CandleAggregator {
+            series: intervals
+                .into_iter()
+                .map(|interval| (interval, Series::default()))
+                .collect(),
+            newly_closed: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, trade: &Trade) {
+        self.record_at(trade.timestamp.as_nanos(), trade.price, trade.qty);
+    }
+
+    /// Folds a print at `time` (nanoseconds since the Unix epoch) into every
+    /// interval. Prints are expected in time order; a late one is added to
+    /// the open bar rather than reopening a closed one.
+    pub fn record_at(&mut self, time: u64, price: i32, qty: u32) {
+        for (interval, series) in &mut self.series {
+            let start = interval.period_start(time);
+            match &mut series.open {
+                Some(bar) if start <= bar.start => bar.add(price, qty),
+                open => {
+                    if let Some(bar) = open.replace(Candle::new(*interval, start, price, qty)) {
+                        series.closed.push(bar);
+                        self.newly_closed.push(bar);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Closes open bars whose period ended at or before `now`.
+    pub fn roll(&mut self, now: u64) {
+        for (_, series) in &mut self.series {
+            if let Some(bar) = series.open.filter(|bar| bar.end() <= now) {
+                series.open = None;
+                series.closed.push(bar);
+                self.newly_closed.push(bar);
+            }
+        }
+    }
+
+    /// Bars closed since the previous call, in the order they closed.
+    pub fn take_closed(&mut self) -> Vec<Candle> {
+        std::mem::take(&mut self.newly_closed)
+    }
+
+    /// Closed bars for `interval`, oldest first.
+    pub fn candles(&self, interval: Interval) -> &[Candle] {
+        self.series(interval)
+            .map_or(&[][..], |series| series.closed.as_slice())
+    }
+
+    /// The bar still accumulating trades for `interval`, if any.
+    pub fn current(&self, interval: Interval) -> Option<&Candle> {
+        self.series(interval)
+            .and_then(|series| series.open.as_ref())
+    }
+
+    /// Closed bars for `interval` starting at or after `from` and before
+    /// `to`.
+    pub fn range(&self, interval: Interval, from: u64, to: u64) -> &[Candle] {
+        let candles = self.candles(interval);
+        let start = candles.partition_point(|bar| bar.start < from);
+        let end = candles.partition_point(|bar| bar.start < to);
+        &candles[start..end.max(start)]
+    }
+
+    fn series(&self, interval: Interval) -> Option<&Series> {
+        self.series
+            .iter()
+            .find(|(i, _)| *i == interval)
+            .map(|(_, series)| series)
+    }
+}
+
+impl EventSink for CandleAggregator {
+    fn publish(&mut self, _symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        if let OrderEvent::Trade(trade) = event {
+            self.record(trade);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CandleAggregator, Interval, NANOS_PER_SEC};
+
+    const T0: u64 = 1_725_192_000 * NANOS_PER_SEC;
+
+    fn secs(s: f64) -> u64 {
+        T0 + (s * NANOS_PER_SEC as f64) as u64
+    }
+
+    #[test]
+    fn builds_ohlcv_bars() {
+        let mut candles = CandleAggregator::new(&[Interval::OneSecond, Interval::OneMinute]);
+        candles.record_at(secs(0.1), 100, 1);
+        candles.record_at(secs(0.5), 104, 2);
+        candles.record_at(secs(0.9), 98, 3);
+        candles.record_at(secs(1.2), 101, 4);
+        candles.record_at(secs(3.0), 102, 5);
+
+        let bars = candles.candles(Interval::OneSecond);
+        assert_eq!(bars.len(), 2);
+        let first = bars[0];
+        assert_eq!(first.start, T0);
+        assert_eq!(
+            (first.open, first.high, first.low, first.close),
+            (100, 104, 98, 98)
+        );
+        assert_eq!((first.volume, first.trade_count), (6, 3));
+        assert_eq!(bars[1].start, secs(1.0));
+        assert_eq!(
+            candles.current(Interval::OneSecond).unwrap().start,
+            secs(3.0)
+        );
+
+        assert!(candles.candles(Interval::OneMinute).is_empty());
+        let minute = candles.current(Interval::OneMinute).unwrap();
+        assert_eq!((minute.open, minute.close, minute.volume), (100, 102, 15));
+        assert!(candles.candles(Interval::FiveMinutes).is_empty());
+    }
+
+    #[test]
+    fn roll_closes_finished_periods() {
+        let mut candles = CandleAggregator::default();
+        candles.record_at(secs(0.5), 100, 1);
+        assert_eq!(candles.take_closed().len(), 0);
+
+        candles.roll(secs(0.99));
+        assert!(candles.take_closed().is_empty());
+        candles.roll(secs(60.0));
+        let closed = candles.take_closed();
+        let intervals: Vec<Interval> = closed.iter().map(|bar| bar.interval).collect();
+        assert_eq!(intervals, [Interval::OneSecond, Interval::OneMinute]);
+        assert!(candles.current(Interval::FiveMinutes).is_some());
+
+        candles.record_at(secs(61.0), 101, 1);
+        candles.record_at(secs(62.0), 102, 1);
+        assert_eq!(
+            candles
+                .range(Interval::OneSecond, secs(1.0), secs(62.0))
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Volatility halts.
+//!
+//! A [`CircuitBreaker`] remembers the trades of the last `window` and trips
+//! when a new trade would print more than `max_move_bps` away from any of
+//! them. The book checks before each trade, so the offending trade never
+//! happens: the book halts instead and stays halted until it is reopened
+//! with an auction. Books can also be halted and resumed by command.
+
+use crate::{Price, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum TradingStatus {
+    Open,
+    /// Nothing matches until the book reopens.
+    Halted,
+}
+
+/// What a halted book does with orders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum HaltPolicy {
+    /// Accept them and let them rest, crossed if need be, until the book
+    /// reopens.
+    #[default]
+    Queue,
+    /// Reject new orders and modifies. Cancels are still accepted.
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    pub max_move_bps: u32,
+    pub window: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    /// 5% in one minute.
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            max_move_bps: 500,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitBreaker<P = i32> {
+    config: CircuitBreakerConfig,
+    /// Trades inside the window, oldest first.
+    recent: VecDeque<(Timestamp, P)>,
+}
+
+impl<P: Price> CircuitBreaker<P> {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            config,
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub fn config(&self) -> CircuitBreakerConfig {
+        self.config
+    }
+
+    /// Whether a trade at `price` would move the price too far.
+    pub fn would_trip(&mut self, price: P, now: Timestamp) -> bool {
+        self.expire(now);
+        let Some((low, high)) = self.range() else {
+            return false;
+        };
+        let max_move = |reference: P| {
+            u128::from(reference.to_ticks().unsigned_abs()) * u128::from(self.config.max_move_bps)
+        };
+        let moved = |from: P| {
+            (i128::from(price.to_ticks()) - i128::from(from.to_ticks())).unsigned_abs() * 10_000
+        };
+        moved(low) > max_move(low) || moved(high) > max_move(high)
+    }
+
+    pub fn record(&mut self, price: P, now: Timestamp) {
+        self.expire(now);
+        self.recent.push_back((now, price));
+    }
+
+    /// Forgets price history, e.g. once the book has reopened at a new level.
+    pub fn reset(&mut self) {
+        self.recent.clear();
+    }
+
+    fn expire(&mut self, now: Timestamp) {
+        while self
+            .recent
+            .front()
+            .is_some_and(|(at, _)| now.saturating_duration_since(*at) > self.config.window)
+        {
+            self.recent.pop_front();
+        }
+    }
+
+    fn range(&self) -> Option<(P, P)> {
+        let low = self.recent.iter().map(|(_, price)| *price).min()?;
+        let high = self.recent.iter().map(|(_, price)| *price).max()?;
+        Some((low, high))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CircuitBreaker, CircuitBreakerConfig, HaltPolicy, TradingStatus};
+    use crate::risk::RejectReason;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side, Timestamp};
+    use std::time::Duration;
+
+    #[test]
+    fn trips_on_moves_inside_window() {
+        let start = Timestamp::now();
+        let mut breaker = CircuitBreaker::new(CircuitBreakerConfig::default());
+        assert!(!breaker.would_trip(1_000, start));
+        breaker.record(1_000, start);
+        assert!(!breaker.would_trip(1_050, start));
+        breaker.record(1_050, start + Duration::from_secs(30));
+        assert!(!breaker.would_trip(1_000, start + Duration::from_secs(40)));
+        // More than 5% above the 1000 print.
+        assert!(breaker.would_trip(1_051, start + Duration::from_secs(40)));
+        // Once the 1000 print ages out only 1050 counts.
+        assert!(!breaker.would_trip(1_100, start + Duration::from_secs(61)));
+        assert!(breaker.would_trip(997, start + Duration::from_secs(61)));
+    }
+
+    #[test]
+    fn book_halts_and_reopens_with_auction() {
+        let mut order_book = OrderBook::new();
+        order_book.set_circuit_breaker(Some(CircuitBreakerConfig::default()));
+        let mut new_order = |side, price, qty, participant| {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant,
+            })
+        };
+        new_order(Side::Sell, 100, 1, 2);
+        new_order(Side::Sell, 103, 1, 2);
+        new_order(Side::Sell, 110, 2, 2);
+        // Sweeps 100 and 103, then halts rather than print 10% away.
+        new_order(Side::Buy, 112, 4, 1);
+        new_order(Side::Buy, 111, 1, 3);
+        assert_eq!(order_book.status(), TradingStatus::Halted);
+        assert_eq!(order_book.trades().len(), 2);
+        assert!(order_book.events().contains(&OrderEvent::StatusChanged {
+            status: TradingStatus::Halted
+        }));
+        assert_eq!(
+            (order_book.best_bid(), order_book.best_ask()),
+            (Some(112), Some(110))
+        );
+
+        let uncross = order_book.reopen().unwrap();
+        assert_eq!((uncross.price, uncross.volume), (112, 2));
+        assert_eq!(order_book.status(), TradingStatus::Open);
+        assert_eq!(order_book.trades().len(), 3);
+        assert_eq!(
+            (order_book.best_bid(), order_book.best_ask()),
+            (Some(111), None)
+        );
+        assert_eq!(
+            order_book.events().last(),
+            Some(&OrderEvent::StatusChanged {
+                status: TradingStatus::Open
+            })
+        );
+        assert_eq!(order_book.reopen(), None);
+    }
+
+    #[test]
+    fn halt_and_resume_commands() {
+        let mut order_book = OrderBook::new();
+        let new_order = |side, price, participant| OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price,
+            qty: 1,
+            participant,
+        };
+        order_book.process_command(new_order(Side::Sell, 100, 2));
+        order_book.process_command(OrderCommand::Halt);
+        assert_eq!(
+            order_book.events().last(),
+            Some(&OrderEvent::StatusChanged {
+                status: TradingStatus::Halted
+            })
+        );
+
+        order_book.set_halt_policy(HaltPolicy::Reject);
+        order_book.process_command(new_order(Side::Buy, 101, 1));
+        assert!(matches!(
+            order_book.events().last(),
+            Some(OrderEvent::Rejected {
+                reason: RejectReason::Halted,
+                ..
+            })
+        ));
+
+        order_book.set_halt_policy(HaltPolicy::Queue);
+        order_book.process_command(new_order(Side::Buy, 101, 1));
+        assert!(order_book.trades().is_empty());
+        order_book.process_command(OrderCommand::Resume { auction: false });
+        assert_eq!(order_book.status(), TradingStatus::Open);
+        // Without an auction the resting sell is the maker.
+        let trade = order_book.trades()[0];
+        assert_eq!((trade.price, trade.aggressor_side), (100, Side::Buy));
+        assert!(order_book.bids.is_empty() && order_book.asks.is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Post-trade clearing, a stub of what a clearing house does at the end of
+//! a session.
+//!
+//! [`Clearing`] is an [`EventSink`] that nets every trade it is given into
+//! one [`Obligation`] per participant and symbol: the quantity to receive or
+//! deliver, and the cash to pay or collect for it, in the currency the
+//! symbol trades in. Busted trades come back out. The [`SettlementReport`] it produces serializes as JSON or writes as
+//! CSV.
+
+use crate::sink::EventSink;
+use crate::{Currency, OrderEvent, ParticipantId, Side, Trade};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// What one participant owes and is owed in one symbol.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Obligation {
+    pub participant: ParticipantId,
+    pub symbol: String,
+    /// What `cash` and `fees` are in.
+    pub currency: Currency,
+    /// Quantity to receive; negative to deliver.
+    pub qty: i64,
+    /// Cash to collect, in price units; negative to pay.
+    pub cash: i64,
+    /// Fees owed, in fee units (see [`crate::fees`]); negative if rebated.
+    pub fees: i64,
+    pub trades: u64,
+}
+
+impl Obligation {
+    fn apply(&mut self, side: Side, price: i32, qty: u32, fee: i64, sign: i64) {
+        let notional = i64::from(price) * i64::from(qty);
+        let (qty, cash) = match side {
+            Side::Buy => (i64::from(qty), -notional),
+            Side::Sell => (-i64::from(qty), notional),
+        };
+        self.qty += sign * qty;
+        self.cash += sign * cash;
+        self.fees += sign * fee;
+        self.trades = self.trades.saturating_add_signed(sign);
+    }
+}
+
+/// Accumulates obligations from trade events.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Clearing {
+    obligations: BTreeMap<(ParticipantId, String), Obligation>,
+}
+
+impl Clearing {
+    pub fn new() -> Self {
+        Clearing::default()
+    }
+
+    /// Books both sides of `trade`.
+    pub fn record(&mut self, symbol: &str, trade: &Trade) {
+        self.clear(symbol, trade, 1);
+    }
+
+    /// Takes a busted trade back out.
+    pub fn bust(&mut self, symbol: &str, trade: &Trade) {
+        self.clear(symbol, trade, -1);
+    }
+
+    fn clear(&mut self, symbol: &str, trade: &Trade, sign: i64) {
+        for (participant, side, fee) in [
+            (
+                trade.maker_participant,
+                trade.aggressor_side.opposite(),
+                trade.maker_fee,
+            ),
+            (
+                trade.taker_participant,
+                trade.aggressor_side,
+                trade.taker_fee,
+            ),
+        ] {
+            self.obligations
+                .entry((participant, symbol.to_string()))
+                .or_insert_with(|| Obligation {
+                    participant,
+                    symbol: symbol.to_string(),
+                    currency: trade.currency,
+                    ..Obligation::default()
+                })
+                .apply(side, trade.price, trade.qty, fee, sign);
+        }
+    }
+
+    /// The participant's obligation in `symbol`, if they traded it.
+    pub fn get(&self, participant: ParticipantId, symbol: &str) -> Option<&Obligation> {
+        self.obligations.get(&(participant, symbol.to_string()))
+    }
+
+    /// Every obligation, by participant and then symbol. Ones that netted to
+    /// nothing, after busts, are left out.
+    pub fn report(&self) -> SettlementReport {
+        SettlementReport {
+            obligations: self
+                .obligations
+                .values()
+                .filter(|obligation| obligation.trades > 0)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl EventSink for Clearing {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        match event {
+            OrderEvent::Trade(trade) => self.record(symbol, trade),
+            OrderEvent::TradeBust { trade, .. } => self.bust(symbol, trade),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// The session's obligations. Across participants, each symbol's quantities
+/// and cash sum to zero.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SettlementReport {
+    pub obligations: Vec<Obligation>,
+}
+
+impl SettlementReport {
+    /// The participant's cash across its obligations, netted within each
+    /// currency.
+    pub fn cash_by_currency(&self, participant: ParticipantId) -> BTreeMap<Currency, i64> {
+        let mut cash = BTreeMap::new();
+        for o in &self.obligations {
+            if o.participant == participant {
+                *cash.entry(o.currency).or_default() += o.cash;
+            }
+        }
+        cash
+    }
+
+    /// Writes a header row and a row per obligation.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "participant,symbol,currency,qty,cash,fees,trades")?;
+        for o in &self.obligations {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{}",
+                o.participant, o.symbol, o.currency, o.qty, o.cash, o.fees, o.trades
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Clearing;
+    use crate::fees::{FeeRate, FeeSchedule};
+    use crate::sink::publish_events;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    #[test]
+    fn nets_trades_into_obligations() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        order_book.set_fee_schedule(FeeSchedule {
+            maker: FeeRate::Free,
+            taker: FeeRate::PerUnit(10),
+        });
+        for (side, price, qty, participant) in [
+            (Side::Sell, 101, 5, 1),
+            (Side::Buy, 101, 3, 2),
+            (Side::Sell, 100, 1, 2),
+            (Side::Buy, 101, 3, 3),
+        ] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant,
+            });
+        }
+        let mut clearing = Clearing::new();
+        publish_events(&mut clearing, "ABC", order_book.events()).unwrap();
+
+        let report = clearing.report();
+        assert_eq!(report.obligations.len(), 3);
+        assert_eq!(report.obligations.iter().map(|o| o.qty).sum::<i64>(), 0);
+        assert_eq!(report.obligations.iter().map(|o| o.cash).sum::<i64>(), 0);
+        let seller = clearing.get(1, "ABC").unwrap();
+        assert_eq!((seller.qty, seller.cash, seller.trades), (-5, 505, 2));
+        // Bought 3 at 101 as the taker, then sold 1 at 100 as the maker.
+        let two = clearing.get(2, "ABC").unwrap();
+        assert_eq!((two.qty, two.cash, two.fees), (2, -203, 30));
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().nth(1), Some("1,ABC,USD,-5,505,0,2"), "{}", csv);
+
+        for trade in order_book.trades() {
+            clearing.bust("ABC", trade);
+        }
+        assert!(clearing.report().obligations.is_empty());
+    }
+}
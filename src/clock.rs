@@ -0,0 +1,185 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Time sources for the book.
+//!
+//! Everything the book stamps — order creation and update times, trade and
+//! event timestamps, rate limiting and circuit breaker windows — reads the
+//! [`Clock`] installed with [`crate::OrderBook::set_clock`]. Tests install a
+//! [`ManualClock`] so a run's timestamps depend only on how far they advance
+//! it; backtests drive one with a [`simulated::SimulatedClock`]. Latency measurement always uses `Instant`.
+
+use crate::Timestamp;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod simulated;
+
+pub trait Clock {
+    fn now(&self) -> Timestamp;
+}
+
+/// The system's wall clock. The book's default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Timestamp {
+        Timestamp::now()
+    }
+}
+
+/// Stands still until advanced. Clones share the same time, so keep one to
+/// drive a clock installed in a book.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Timestamp,
+    elapsed_nanos: Arc<AtomicU64>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock::starting_at(Timestamp::now())
+    }
+
+    pub fn starting_at(start: Timestamp) -> Self {
+        ManualClock {
+            start,
+            elapsed_nanos: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.elapsed_nanos
+            .fetch_add(by.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    /// How far the clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos.load(Ordering::SeqCst))
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Timestamp {
+        self.start + self.elapsed()
+    }
+}
+
+/// The clock installed in a book. Clocks are opaque, so two books compare
+/// equal whatever they read the time from.
+pub(crate) struct BookClock(Box<dyn Clock + Send>);
+
+impl BookClock {
+    pub(crate) fn new(clock: impl Clock + Send + 'static) -> Self {
+        BookClock(Box::new(clock))
+    }
+
+    pub(crate) fn now(&self) -> Timestamp {
+        self.0.now()
+    }
+}
+
+impl Default for BookClock {
+    fn default() -> Self {
+        BookClock::new(RealClock)
+    }
+}
+
+impl fmt::Debug for BookClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BookClock")
+    }
+}
+
+impl PartialEq for BookClock {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, ManualClock};
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side, Timestamp};
+    use std::time::Duration;
+
+    #[test]
+    fn timestamp_arithmetic_saturates() {
+        let epoch = Timestamp::from_nanos(0);
+        let later = Timestamp::from_nanos(1_000);
+        assert_eq!(
+            later - Duration::from_nanos(400),
+            Timestamp::from_nanos(600)
+        );
+        assert_eq!(epoch - Duration::from_nanos(1), epoch);
+        assert_eq!(later - Duration::MAX, epoch);
+        assert_eq!(later + Duration::MAX, Timestamp::from_nanos(u64::MAX));
+        assert_eq!(epoch - later, Duration::ZERO);
+    }
+
+    #[test]
+    fn book_stamps_with_installed_clock() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mut order_book = OrderBook::new();
+        order_book.set_clock(clock.clone());
+        let new_order = |side| OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price: 100,
+            qty: 5,
+            participant: 1,
+        };
+
+        order_book.process_command(new_order(Side::Sell));
+        clock.advance(Duration::from_millis(250));
+        order_book.process_command(new_order(Side::Buy));
+
+        let timestamps: Vec<Duration> = order_book
+            .events()
+            .iter()
+            .filter_map(|event| match event {
+                OrderEvent::Placed { timestamp, .. } | OrderEvent::Filled { timestamp, .. } => {
+                    Some(*timestamp - start)
+                }
+                OrderEvent::Trade(trade) => Some(trade.timestamp - start),
+                _ => None,
+            })
+            .collect();
+        let later = Duration::from_millis(250);
+        assert_eq!(timestamps, [Duration::ZERO, later, later, later, later]);
+    }
+
+    #[test]
+    fn orders_are_stamped_with_installed_clock() {
+        let start = Timestamp::from_nanos(1_000);
+        let clock = ManualClock::starting_at(start);
+        let mut order_book = OrderBook::new();
+        order_book.set_clock(clock.clone());
+        let new_order = |side, qty| OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price: 100,
+            qty,
+            participant: 1,
+        };
+
+        order_book.process_command(new_order(Side::Sell, 5));
+        clock.advance(Duration::from_millis(250));
+        order_book.process_command(new_order(Side::Buy, 2));
+
+        let order = order_book.find_order(1).unwrap();
+        assert_eq!(order.created_at, start);
+        assert_eq!(order.updated_at, start + Duration::from_millis(250));
+    }
+}
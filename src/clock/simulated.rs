@@ -0,0 +1,162 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Simulated time for backtests.
+//!
+//! A [`SimulatedClock`] only moves when the data being replayed says it
+//! does. Each recorded command is applied at its own timestamp, and anything
+//! scheduled in between — a halt, an auction, the end of a session — runs
+//! first, at the moment it was scheduled for, so the book sees the same
+//! sequence of times it would have live. That includes the book's own
+//! timers, such as order expiries, which fire at their due time rather than
+//! at the next replayed command.
+
+use super::{Clock, ManualClock};
+use crate::{OrderBook, OrderCommand, Timestamp};
+use std::collections::BTreeMap;
+
+type Action = Box<dyn FnOnce(&mut OrderBook)>;
+
+pub struct SimulatedClock {
+    clock: ManualClock,
+    /// Keyed by due time, then by when they were scheduled, so timers due
+    /// together run in the order they were set.
+    timers: BTreeMap<(Timestamp, u64), Action>,
+    scheduled: u64,
+}
+
+impl SimulatedClock {
+    pub fn starting_at(start: Timestamp) -> Self {
+        SimulatedClock {
+            clock: ManualClock::starting_at(start),
+            timers: BTreeMap::new(),
+            scheduled: 0,
+        }
+    }
+
+    /// A handle to install in the book with [`OrderBook::set_clock`].
+    pub fn clock(&self) -> ManualClock {
+        self.clock.clone()
+    }
+
+    /// Runs `action` on the book once the clock reaches `at`.
+    pub fn schedule(&mut self, at: Timestamp, action: impl FnOnce(&mut OrderBook) + 'static) {
+        self.timers.insert((at, self.scheduled), Box::new(action));
+        self.scheduled += 1;
+    }
+
+    /// Processes `command` once the clock reaches `at`.
+    pub fn schedule_command(&mut self, at: Timestamp, command: OrderCommand) {
+        self.schedule(at, move |order_book| order_book.process_command(command));
+    }
+
+    /// Timers not yet fired.
+    pub fn pending(&self) -> usize {
+        self.timers.len()
+    }
+
+    /// Moves the clock forward to `to`, firing every timer due by then at
+    /// its own time, the book's included. Returns how many of this clock's
+    /// timers fired. The clock never goes back, so a `to` in the past only
+    /// fires what is already due.
+    pub fn advance_to(&mut self, order_book: &mut OrderBook, to: Timestamp) -> usize {
+        let mut fired = 0;
+        loop {
+            let next = self.timers.first_key_value().map(|(&(at, _), _)| at);
+            // Only a lower bound, but ticking there moves it on.
+            let book_next = order_book.next_timer().filter(|&at| at <= to);
+            match (next.filter(|&at| at <= to), book_next) {
+                (next, Some(book)) if next.is_none_or(|at| book < at) => {
+                    self.set(book);
+                    order_book.tick();
+                }
+                (Some(_), _) => {
+                    let Some(((at, _), action)) = self.timers.pop_first() else {
+                        break;
+                    };
+                    self.set(at);
+                    action(order_book);
+                    fired += 1;
+                }
+                _ => break,
+            }
+        }
+        self.set(to);
+        fired
+    }
+
+    /// Advances to `at`, then processes `command`, as a replayed message
+    /// stamped `at` would be.
+    pub fn replay(&mut self, order_book: &mut OrderBook, at: Timestamp, command: OrderCommand) {
+        self.advance_to(order_book, at);
+        order_book.process_command(command);
+    }
+
+    fn set(&self, to: Timestamp) {
+        self.clock.advance(to.saturating_duration_since(self.now()));
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SimulatedClock;
+    use crate::circuit_breaker::TradingStatus;
+    use crate::clock::Clock;
+    use crate::{OrderBook, OrderCommand, OrderType, Side, Timestamp};
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    #[test]
+    fn fires_timers_at_their_simulated_time() {
+        let start = Timestamp::from_nanos(1_700_000_000_000_000_000);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut sim = SimulatedClock::starting_at(start);
+        let mut order_book = OrderBook::new();
+        order_book.set_clock(sim.clock());
+
+        sim.schedule_command(at(10), OrderCommand::Halt);
+        sim.schedule_command(at(20), OrderCommand::Resume { auction: true });
+        let session_trades = Rc::new(Cell::new(None));
+        let recorded = session_trades.clone();
+        sim.schedule(at(30), move |order_book| {
+            recorded.set(Some(order_book.reset_stats().trade_count));
+        });
+
+        let new_order = |side| OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price: 100,
+            qty: 5,
+            participant: 1,
+        };
+        sim.replay(&mut order_book, at(5), new_order(Side::Sell));
+        sim.replay(&mut order_book, at(6), new_order(Side::Sell));
+        assert!(order_book.expire_at(2, at(15)));
+        let resting = Rc::new(Cell::new(true));
+        let seen = resting.clone();
+        sim.schedule(at(16), move |order_book| {
+            seen.set(order_book.find_order(2).is_some());
+        });
+        // Arrives during the halt, so it waits for the reopening auction.
+        sim.replay(&mut order_book, at(12), new_order(Side::Buy));
+        assert_eq!(order_book.status(), TradingStatus::Halted);
+        assert!(order_book.trades().is_empty());
+        assert_eq!(sim.pending(), 3);
+
+        assert_eq!(sim.advance_to(&mut order_book, at(40)), 3);
+        assert_eq!(sim.now(), at(40));
+        assert_eq!(order_book.status(), TradingStatus::Open);
+        assert_eq!(order_book.trades()[0].timestamp, at(20));
+        assert_eq!(session_trades.get(), Some(1));
+        // The book's own expiry fired at 15s, not when the auction ran.
+        assert!(!resting.get());
+    }
+}
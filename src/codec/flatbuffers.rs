@@ -0,0 +1,422 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! FlatBuffers encoding for depth snapshots and trades.
+//!
+//! Buffers follow `schema/depth.fbs` and `schema/trade.fbs`, so consumers can
+//! read them in place with `flatc` generated accessors. The builder here is a
+//! minimal front-to-back writer: each vtable sits directly in front of its
+//! table and the strings, vectors and tables a table points at follow it,
+//! which keeps every `uoffset` pointing forward as the format requires. The
+//! Arrow IPC writer builds its nested metadata tables with it too.
+
+use super::invalid;
+use crate::order_book::{Depth, LevelInfo};
+use crate::{Side, Trade};
+use std::io;
+
+pub const DEPTH_IDENTIFIER: &[u8; 4] = b"MDDS";
+pub const TRADE_IDENTIFIER: &[u8; 4] = b"MDTR";
+
+const LEVEL_SIZE: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthSnapshot {
+    pub symbol: String,
+    pub timestamp_ns: u64,
+    pub depth: Depth,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradeEvent {
+    pub symbol: String,
+    pub trade_id: u64,
+    pub price: i32,
+    pub qty: u32,
+    pub aggressor_side: Side,
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
+    pub timestamp_ns: u64,
+}
+
+pub fn encode_depth_snapshot(symbol: &str, depth: &Depth, timestamp_ns: u64) -> Vec<u8> {
+    build(
+        Some(DEPTH_IDENTIFIER),
+        &[
+            Field::Str(symbol),
+            Field::Scalar(timestamp_ns.to_le_bytes().to_vec()),
+            levels(&depth.bids),
+            levels(&depth.asks),
+        ],
+    )
+}
+
+pub fn decode_depth_snapshot(bytes: &[u8]) -> io::Result<DepthSnapshot> {
+    let table = Table::root(bytes, Some(DEPTH_IDENTIFIER))?;
+    Ok(DepthSnapshot {
+        symbol: table.string(0)?,
+        timestamp_ns: table.u64(1)?,
+        depth: Depth {
+            bids: read_levels(&table, 2)?,
+            asks: read_levels(&table, 3)?,
+        },
+    })
+}
+
+pub fn encode_trade(symbol: &str, trade: &Trade) -> Vec<u8> {
+    let side: u8 = match trade.aggressor_side {
+        Side::Buy => 0,
+        Side::Sell => 1,
+    };
+    build(
+        Some(TRADE_IDENTIFIER),
+        &[
+            Field::Str(symbol),
+            Field::Scalar((trade.id as u64).to_le_bytes().to_vec()),
+            Field::Scalar(trade.price.to_le_bytes().to_vec()),
+            Field::Scalar(trade.qty.to_le_bytes().to_vec()),
+            Field::Scalar(vec![side]),
+            Field::Scalar(trade.maker_order_id.to_le_bytes().to_vec()),
+            Field::Scalar(trade.taker_order_id.to_le_bytes().to_vec()),
+            Field::Scalar(trade.timestamp.as_nanos().to_le_bytes().to_vec()),
+        ],
+    )
+}
+
+pub fn decode_trade(bytes: &[u8]) -> io::Result<TradeEvent> {
+    let table = Table::root(bytes, Some(TRADE_IDENTIFIER))?;
+    Ok(TradeEvent {
+        symbol: table.string(0)?,
+        trade_id: table.u64(1)?,
+        price: table.u32(2)? as i32,
+        qty: table.u32(3)?,
+        aggressor_side: match table.u8(4)? {
+            0 => Side::Buy,
+            1 => Side::Sell,
+            side => return Err(invalid(format!("invalid side {}", side))),
+        },
+        maker_order_id: table.u64(5)?,
+        taker_order_id: table.u64(6)?,
+        timestamp_ns: table.u64(7)?,
+    })
+}
+
+fn levels(levels: &[LevelInfo]) -> Field<'static> {
+    let mut bytes = Vec::with_capacity(levels.len() * LEVEL_SIZE);
+    for level in levels {
+        bytes.extend_from_slice(&level.price.to_le_bytes());
+        bytes.extend_from_slice(&(level.order_count as u32).to_le_bytes());
+        bytes.extend_from_slice(&level.qty.to_le_bytes());
+    }
+    Field::Structs {
+        count: levels.len(),
+        bytes,
+        align: 8,
+    }
+}
+
+fn read_levels(table: &Table, slot: usize) -> io::Result<Vec<LevelInfo>> {
+    let bytes = table.structs(slot, LEVEL_SIZE)?;
+    Ok(bytes
+        .chunks_exact(LEVEL_SIZE)
+        .map(|level| LevelInfo {
+            price: i32::from_le_bytes(level[0..4].try_into().unwrap()),
+            order_count: u32::from_le_bytes(level[4..8].try_into().unwrap()) as usize,
+            qty: u64::from_le_bytes(level[8..16].try_into().unwrap()),
+        })
+        .collect())
+}
+
+pub(crate) enum Field<'a> {
+    /// Left out; readers see the schema default.
+    #[cfg_attr(not(feature = "arrow"), allow(dead_code))]
+    Absent,
+    /// Inline little-endian scalar, aligned to its own size.
+    Scalar(Vec<u8>),
+    Str(&'a str),
+    Structs {
+        count: usize,
+        bytes: Vec<u8>,
+        align: usize,
+    },
+    #[cfg_attr(not(feature = "arrow"), allow(dead_code))]
+    Table(Vec<Field<'a>>),
+    #[cfg_attr(not(feature = "arrow"), allow(dead_code))]
+    Tables(Vec<Vec<Field<'a>>>),
+}
+
+impl Field<'_> {
+    fn inline_size(&self) -> usize {
+        match self {
+            Field::Absent => 0,
+            Field::Scalar(bytes) => bytes.len(),
+            Field::Str(_) | Field::Structs { .. } | Field::Table(_) | Field::Tables(_) => 4,
+        }
+    }
+}
+
+fn align_up(pos: usize, align: usize) -> usize {
+    pos.div_ceil(align) * align
+}
+
+/// Lays out `[root offset][identifier][vtable][table][what it points at]`,
+/// with one vtable slot per field in schema order.
+pub(crate) fn build(identifier: Option<&[u8; 4]>, fields: &[Field]) -> Vec<u8> {
+    let mut buf = vec![0u8; 4];
+    if let Some(identifier) = identifier {
+        buf.extend_from_slice(identifier);
+    }
+    let table_pos = write_table(&mut buf, fields);
+    buf[0..4].copy_from_slice(&(table_pos as u32).to_le_bytes());
+    buf
+}
+
+/// Appends a vtable, its table and then everything the table points at,
+/// returning where the table starts.
+fn write_table(buf: &mut Vec<u8>, fields: &[Field]) -> usize {
+    let vtable_pos = align_up(buf.len(), 2);
+    buf.resize(vtable_pos, 0);
+    let mut field_offsets = Vec::with_capacity(fields.len());
+    let mut table_size = 4;
+    for field in fields {
+        let size = field.inline_size();
+        if size == 0 {
+            field_offsets.push(0);
+            continue;
+        }
+        table_size = align_up(table_size, size);
+        field_offsets.push(table_size);
+        table_size += size;
+    }
+    buf.extend_from_slice(&((4 + 2 * fields.len()) as u16).to_le_bytes());
+    buf.extend_from_slice(&(table_size as u16).to_le_bytes());
+    for offset in &field_offsets {
+        buf.extend_from_slice(&(*offset as u16).to_le_bytes());
+    }
+
+    let table_pos = align_up(buf.len(), 8);
+    buf.resize(table_pos + table_size, 0);
+    buf[table_pos..table_pos + 4].copy_from_slice(&((table_pos - vtable_pos) as i32).to_le_bytes());
+
+    for (field, offset) in fields.iter().zip(&field_offsets) {
+        let field_pos = table_pos + offset;
+        let object_pos = match field {
+            Field::Absent => continue,
+            Field::Scalar(bytes) => {
+                buf[field_pos..field_pos + bytes.len()].copy_from_slice(bytes);
+                continue;
+            }
+            Field::Str(s) => {
+                let pos = align_up(buf.len(), 4);
+                buf.resize(pos, 0);
+                buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                buf.extend_from_slice(s.as_bytes());
+                buf.push(0);
+                pos
+            }
+            Field::Structs {
+                count,
+                bytes,
+                align,
+            } => {
+                // The length prefix is 4 bytes and the elements after it
+                // need their own alignment.
+                let mut pos = align_up(buf.len(), 4);
+                while !(pos + 4).is_multiple_of(*align) {
+                    pos += 4;
+                }
+                buf.resize(pos, 0);
+                buf.extend_from_slice(&(*count as u32).to_le_bytes());
+                buf.extend_from_slice(bytes);
+                pos
+            }
+            Field::Table(fields) => write_table(buf, fields),
+            Field::Tables(tables) => {
+                let pos = align_up(buf.len(), 4);
+                buf.resize(pos + 4 + 4 * tables.len(), 0);
+                buf[pos..pos + 4].copy_from_slice(&(tables.len() as u32).to_le_bytes());
+                for (i, fields) in tables.iter().enumerate() {
+                    let elem_pos = pos + 4 + 4 * i;
+                    let table_pos = write_table(buf, fields);
+                    buf[elem_pos..elem_pos + 4]
+                        .copy_from_slice(&((table_pos - elem_pos) as u32).to_le_bytes());
+                }
+                pos
+            }
+        };
+        let uoffset = (object_pos - field_pos) as u32;
+        buf[field_pos..field_pos + 4].copy_from_slice(&uoffset.to_le_bytes());
+    }
+    table_pos
+}
+
+/// Bounds-checked accessor for a table.
+pub(crate) struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    vtable: usize,
+    vtable_len: usize,
+}
+
+impl<'a> Table<'a> {
+    pub(crate) fn root(buf: &'a [u8], identifier: Option<&[u8; 4]>) -> io::Result<Self> {
+        if let Some(identifier) = identifier {
+            if read(buf, 4, 4)? != identifier {
+                return Err(invalid("unexpected file identifier".to_string()));
+            }
+        }
+        Table::at(buf, read_u32(buf, 0)? as usize)
+    }
+
+    fn at(buf: &'a [u8], pos: usize) -> io::Result<Self> {
+        let soffset = read_u32(buf, pos)? as i32 as i64;
+        let vtable = usize::try_from(pos as i64 - soffset)
+            .map_err(|_| invalid("vtable offset out of range".to_string()))?;
+        let vtable_len = u16::from_le_bytes(read(buf, vtable, 2)?.try_into().unwrap()) as usize;
+        Ok(Table {
+            buf,
+            pos,
+            vtable,
+            vtable_len,
+        })
+    }
+
+    /// Absolute position of a field, or `None` if the writer left it out.
+    fn field(&self, slot: usize) -> io::Result<Option<usize>> {
+        let entry = 4 + 2 * slot;
+        if entry + 2 > self.vtable_len {
+            return Ok(None);
+        }
+        let offset =
+            u16::from_le_bytes(read(self.buf, self.vtable + entry, 2)?.try_into().unwrap());
+        Ok((offset != 0).then_some(self.pos + offset as usize))
+    }
+
+    fn scalar<const N: usize>(&self, slot: usize) -> io::Result<[u8; N]> {
+        match self.field(slot)? {
+            Some(pos) => Ok(read(self.buf, pos, N)?.try_into().unwrap()),
+            None => Ok([0; N]),
+        }
+    }
+
+    pub(crate) fn u8(&self, slot: usize) -> io::Result<u8> {
+        Ok(self.scalar::<1>(slot)?[0])
+    }
+
+    pub(crate) fn u32(&self, slot: usize) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.scalar(slot)?))
+    }
+
+    pub(crate) fn u64(&self, slot: usize) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.scalar(slot)?))
+    }
+
+    /// The table a field points at, if the writer set it.
+    #[cfg(all(test, feature = "arrow"))]
+    pub(crate) fn table(&self, slot: usize) -> io::Result<Option<Table<'a>>> {
+        match self.field(slot)? {
+            Some(pos) => Table::at(self.buf, pos + read_u32(self.buf, pos)? as usize).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Follows a `uoffset` field to the length-prefixed object it points at.
+    fn object(&self, slot: usize, elem_size: usize) -> io::Result<&'a [u8]> {
+        let Some(pos) = self.field(slot)? else {
+            return Ok(&[]);
+        };
+        let object = pos + read_u32(self.buf, pos)? as usize;
+        let len = read_u32(self.buf, object)? as usize;
+        let bytes = len
+            .checked_mul(elem_size)
+            .ok_or_else(|| invalid("vector length overflow".to_string()))?;
+        read(self.buf, object + 4, bytes)
+    }
+
+    pub(crate) fn string(&self, slot: usize) -> io::Result<String> {
+        let bytes = self.object(slot, 1)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| invalid(e.to_string()))
+    }
+
+    pub(crate) fn structs(&self, slot: usize, stride: usize) -> io::Result<&'a [u8]> {
+        self.object(slot, stride)
+    }
+}
+
+fn read(buf: &[u8], pos: usize, len: usize) -> io::Result<&[u8]> {
+    buf.get(pos..pos.saturating_add(len))
+        .ok_or_else(|| invalid(format!("offset {} out of bounds", pos)))
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read(buf, pos, 4)?.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_depth_snapshot, decode_trade, encode_depth_snapshot, encode_trade};
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    fn book() -> OrderBook {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        for (side, price, qty) in [
+            (Side::Buy, 120, 3),
+            (Side::Buy, 121, 2),
+            (Side::Sell, 123, 7),
+            (Side::Buy, 122, 4),
+        ] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant: 1,
+            });
+        }
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Sell,
+            price: 122,
+            qty: 1,
+            participant: 1,
+        });
+        order_book
+    }
+
+    #[test]
+    fn depth_snapshot_round_trip() {
+        let order_book = book();
+        let depth = order_book.depth(10);
+        let bytes = encode_depth_snapshot(order_book.symbol(), &depth, 99);
+        let root = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
+        assert_eq!(root % 8, 0);
+
+        let snapshot = decode_depth_snapshot(&bytes).unwrap();
+        assert_eq!(snapshot.symbol, "ABC");
+        assert_eq!(snapshot.timestamp_ns, 99);
+        assert_eq!(snapshot.depth, depth);
+        assert!(decode_trade(&bytes).is_err());
+    }
+
+    #[test]
+    fn trade_round_trip() {
+        let order_book = book();
+        let trade = order_book.trades()[0];
+        let decoded = decode_trade(&encode_trade("ABC", &trade)).unwrap();
+        assert_eq!(decoded.symbol, "ABC");
+        assert_eq!(decoded.trade_id, trade.id as u64);
+        assert_eq!(decoded.price, 122);
+        assert_eq!(decoded.qty, 1);
+        assert_eq!(decoded.aggressor_side, Side::Sell);
+        assert_eq!(decoded.maker_order_id, trade.maker_order_id);
+        assert_eq!(decoded.timestamp_ns, trade.timestamp.as_nanos());
+    }
+
+    #[test]
+    fn rejects_truncated_buffers() {
+        let order_book = book();
+        let bytes = encode_depth_snapshot("ABC", &order_book.depth(10), 1);
+        assert!(decode_depth_snapshot(&bytes[..bytes.len() - 8]).is_err());
+    }
+}
@@ -0,0 +1,1340 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Protocol Buffers messages for commands and events.
+//!
+//! The types here mirror `proto/matcher.proto` the way prost would generate
+//! them: enum fields are stored as raw `i32`s and oneofs become an `Option`
+//! of a Rust enum. Conversions to and from the engine's own types live at the
+//! bottom of the file; keep both sides in sync with the schema.
+
+use super::invalid;
+use crate::sink::EventEncoder;
+use crate::Timestamp;
+use std::io;
+
+pub trait Message: Default {
+    fn encode_raw(&self, buf: &mut Vec<u8>);
+
+    /// Applies one decoded field. Unknown field numbers must be ignored so
+    /// older readers accept messages from newer schema versions.
+    fn merge_field(&mut self, field: u32, value: wire::Value) -> io::Result<()>;
+
+    fn encode_to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_raw(&mut buf);
+        buf
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut message = Self::default();
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let key = wire::read_varint(bytes, &mut pos)?;
+            let field = (key >> 3) as u32;
+            let value = match key & 7 {
+                0 => wire::Value::Varint(wire::read_varint(bytes, &mut pos)?),
+                1 => wire::Value::Fixed(wire::take(bytes, &mut pos, 8)?),
+                2 => {
+                    let len = wire::read_varint(bytes, &mut pos)? as usize;
+                    wire::Value::Bytes(wire::take(bytes, &mut pos, len)?)
+                }
+                5 => wire::Value::Fixed(wire::take(bytes, &mut pos, 4)?),
+                wire_type => return Err(invalid(format!("unsupported wire type {}", wire_type))),
+            };
+            if field == 0 {
+                return Err(invalid("field number 0".to_string()));
+            }
+            message.merge_field(field, value)?;
+        }
+        Ok(message)
+    }
+}
+
+/// Low level proto3 wire format helpers.
+pub mod wire {
+    use super::{invalid, Message};
+    use std::io;
+
+    pub enum Value<'a> {
+        Varint(u64),
+        Fixed(&'a [u8]),
+        Bytes(&'a [u8]),
+    }
+
+    pub(super) fn take<'a>(bytes: &'a [u8], pos: &mut usize, len: usize) -> io::Result<&'a [u8]> {
+        let end = pos.saturating_add(len);
+        if end > bytes.len() {
+            return Err(invalid(format!("message truncated at byte {}", pos)));
+        }
+        let slice = &bytes[*pos..end];
+        *pos = end;
+        Ok(slice)
+    }
+
+    pub(super) fn read_varint(bytes: &[u8], pos: &mut usize) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = take(bytes, pos, 1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("varint longer than 10 bytes".to_string()))
+    }
+
+    fn put_varint(mut value: u64, buf: &mut Vec<u8>) {
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    fn put_key(field: u32, wire_type: u8, buf: &mut Vec<u8>) {
+        put_varint(((field as u64) << 3) | wire_type as u64, buf);
+    }
+
+    fn varint(value: Value) -> io::Result<u64> {
+        match value {
+            Value::Varint(v) => Ok(v),
+            _ => Err(invalid("expected varint field".to_string())),
+        }
+    }
+
+    /// Proto3 omits fields holding their default value.
+    fn put_varint_field(field: u32, value: u64, buf: &mut Vec<u8>) {
+        if value != 0 {
+            put_key(field, 0, buf);
+            put_varint(value, buf);
+        }
+    }
+
+    pub fn message<M: Message>(field: u32, message: &M, buf: &mut Vec<u8>) {
+        let body = message.encode_to_vec();
+        put_key(field, 2, buf);
+        put_varint(body.len() as u64, buf);
+        buf.extend_from_slice(&body);
+    }
+
+    pub fn decode_message<M: Message>(value: Value) -> io::Result<M> {
+        match value {
+            Value::Bytes(bytes) => M::decode(bytes),
+            _ => Err(invalid("expected length-delimited field".to_string())),
+        }
+    }
+
+    pub mod uint32 {
+        use super::Value;
+        use std::io;
+
+        pub fn encode(field: u32, value: u32, buf: &mut Vec<u8>) {
+            super::put_varint_field(field, value as u64, buf);
+        }
+
+        pub fn decode(value: Value) -> io::Result<u32> {
+            Ok(super::varint(value)? as u32)
+        }
+    }
+
+    pub mod bool {
+        use super::Value;
+        use std::io;
+
+        pub fn encode(field: u32, value: bool, buf: &mut Vec<u8>) {
+            super::put_varint_field(field, u64::from(value), buf);
+        }
+
+        pub fn decode(value: Value) -> io::Result<bool> {
+            Ok(super::varint(value)? != 0)
+        }
+    }
+
+    pub mod uint64 {
+        use super::Value;
+        use std::io;
+
+        pub fn encode(field: u32, value: u64, buf: &mut Vec<u8>) {
+            super::put_varint_field(field, value, buf);
+        }
+
+        pub fn decode(value: Value) -> io::Result<u64> {
+            super::varint(value)
+        }
+    }
+
+    /// `int32` and enum fields: negative values are sign extended to 64 bits.
+    pub mod int32 {
+        use super::Value;
+        use std::io;
+
+        pub fn encode(field: u32, value: i32, buf: &mut Vec<u8>) {
+            super::put_varint_field(field, value as i64 as u64, buf);
+        }
+
+        pub fn decode(value: Value) -> io::Result<i32> {
+            Ok(super::varint(value)? as i32)
+        }
+    }
+
+    pub mod sint32 {
+        use super::Value;
+        use std::io;
+
+        pub fn encode(field: u32, value: i32, buf: &mut Vec<u8>) {
+            let zigzag = ((value << 1) ^ (value >> 31)) as u32;
+            super::put_varint_field(field, zigzag as u64, buf);
+        }
+
+        pub fn decode(value: Value) -> io::Result<i32> {
+            let zigzag = super::varint(value)? as u32;
+            Ok(((zigzag >> 1) as i32) ^ -((zigzag & 1) as i32))
+        }
+    }
+
+    pub mod sint64 {
+        use super::Value;
+        use std::io;
+
+        pub fn encode(field: u32, value: i64, buf: &mut Vec<u8>) {
+            let zigzag = ((value << 1) ^ (value >> 63)) as u64;
+            super::put_varint_field(field, zigzag, buf);
+        }
+
+        pub fn decode(value: Value) -> io::Result<i64> {
+            let zigzag = super::varint(value)?;
+            Ok(((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64))
+        }
+    }
+
+    pub mod string {
+        use super::{invalid, Value};
+        use std::io;
+
+        pub fn encode(field: u32, value: &str, buf: &mut Vec<u8>) {
+            if !value.is_empty() {
+                super::put_key(field, 2, buf);
+                super::put_varint(value.len() as u64, buf);
+                buf.extend_from_slice(value.as_bytes());
+            }
+        }
+
+        pub fn decode(value: Value) -> io::Result<String> {
+            match value {
+                Value::Bytes(bytes) => String::from_utf8(bytes.to_vec())
+                    .map_err(|_| invalid("string field is not UTF-8".to_string())),
+                _ => Err(invalid("expected length-delimited field".to_string())),
+            }
+        }
+    }
+}
+
+macro_rules! scalar_message {
+    ($name:ident { $($tag:literal => $field:ident: $ty:ty as $kind:ident),* $(,)? }) => {
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+        pub struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        impl Message for $name {
+            fn encode_raw(&self, buf: &mut Vec<u8>) {
+                $(wire::$kind::encode($tag, self.$field, buf);)*
+            }
+
+            fn merge_field(&mut self, field: u32, value: wire::Value) -> io::Result<()> {
+                match field {
+                    $($tag => self.$field = wire::$kind::decode(value)?,)*
+                    _ => {}
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum Side {
+    Unspecified = 0,
+    Buy = 1,
+    Sell = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum OrderType {
+    Unspecified = 0,
+    FillAndKill = 1,
+    GoodTilCancel = 2,
+    Day = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum TradingStatus {
+    Unspecified = 0,
+    Open = 1,
+    Halted = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ProtectionTrigger {
+    Unspecified = 0,
+    Fills = 1,
+    Quantity = 2,
+    Delta = 3,
+}
+
+scalar_message!(NewOrder {
+    1 => order_type: i32 as int32,
+    2 => side: i32 as int32,
+    3 => price: i32 as sint32,
+    4 => qty: u32 as uint32,
+    5 => participant: u32 as uint32,
+});
+
+scalar_message!(ModifyOrder {
+    1 => id: u64 as uint64,
+    2 => price: i32 as sint32,
+    3 => side: i32 as int32,
+    4 => qty: u32 as uint32,
+    5 => order_type: i32 as int32,
+});
+
+scalar_message!(CancelOrder {
+    1 => id: u64 as uint64,
+    2 => side: i32 as int32,
+    3 => price: i32 as sint32,
+});
+
+scalar_message!(OrderPlaced {
+    1 => id: u64 as uint64,
+    2 => side: i32 as int32,
+    3 => order_type: i32 as int32,
+    4 => price: i32 as sint32,
+    5 => timestamp_ns: u64 as uint64,
+    6 => participant: u32 as uint32,
+    7 => arrival: u64 as uint64,
+});
+
+scalar_message!(OrderCanceled {
+    1 => id: u64 as uint64,
+});
+
+scalar_message!(OrderPartiallyFilled {
+    1 => id: u64 as uint64,
+    2 => price: i32 as sint32,
+    3 => qty: u32 as uint32,
+    4 => timestamp_ns: u64 as uint64,
+});
+
+scalar_message!(OrderFilled {
+    1 => id: u64 as uint64,
+    2 => price: i32 as sint32,
+    3 => timestamp_ns: u64 as uint64,
+});
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trade {
+    pub id: u64,
+    pub price: i32,
+    pub qty: u32,
+    pub aggressor_side: i32,
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
+    pub timestamp_ns: u64,
+    pub maker_participant: u32,
+    pub taker_participant: u32,
+    pub maker_fee: i64,
+    pub taker_fee: i64,
+    pub cross: bool,
+    pub dark: bool,
+    pub currency: String,
+}
+
+impl Message for Trade {
+    fn encode_raw(&self, buf: &mut Vec<u8>) {
+        wire::uint64::encode(1, self.id, buf);
+        wire::sint32::encode(2, self.price, buf);
+        wire::uint32::encode(3, self.qty, buf);
+        wire::int32::encode(4, self.aggressor_side, buf);
+        wire::uint64::encode(5, self.maker_order_id, buf);
+        wire::uint64::encode(6, self.taker_order_id, buf);
+        wire::uint64::encode(7, self.timestamp_ns, buf);
+        wire::uint32::encode(8, self.maker_participant, buf);
+        wire::uint32::encode(9, self.taker_participant, buf);
+        wire::sint64::encode(10, self.maker_fee, buf);
+        wire::sint64::encode(11, self.taker_fee, buf);
+        wire::bool::encode(12, self.cross, buf);
+        wire::bool::encode(13, self.dark, buf);
+        wire::string::encode(14, &self.currency, buf);
+    }
+
+    fn merge_field(&mut self, field: u32, value: wire::Value) -> io::Result<()> {
+        match field {
+            1 => self.id = wire::uint64::decode(value)?,
+            2 => self.price = wire::sint32::decode(value)?,
+            3 => self.qty = wire::uint32::decode(value)?,
+            4 => self.aggressor_side = wire::int32::decode(value)?,
+            5 => self.maker_order_id = wire::uint64::decode(value)?,
+            6 => self.taker_order_id = wire::uint64::decode(value)?,
+            7 => self.timestamp_ns = wire::uint64::decode(value)?,
+            8 => self.maker_participant = wire::uint32::decode(value)?,
+            9 => self.taker_participant = wire::uint32::decode(value)?,
+            10 => self.maker_fee = wire::sint64::decode(value)?,
+            11 => self.taker_fee = wire::sint64::decode(value)?,
+            12 => self.cross = wire::bool::decode(value)?,
+            13 => self.dark = wire::bool::decode(value)?,
+            14 => self.currency = wire::string::decode(value)?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+scalar_message!(OrderRejected {
+    1 => participant: u32 as uint32,
+    2 => side: i32 as int32,
+    3 => price: i32 as sint32,
+    4 => qty: u32 as uint32,
+    5 => reason: i32 as int32,
+});
+
+scalar_message!(KillSwitch {
+    1 => participant: u32 as uint32,
+    2 => engage: bool as bool,
+});
+
+scalar_message!(ResumeTrading {
+    1 => auction: bool as bool,
+});
+
+scalar_message!(BustTrade {
+    1 => trade_id: u64 as uint64,
+    2 => restore: bool as bool,
+});
+
+scalar_message!(CrossTrade {
+    1 => buyer: u32 as uint32,
+    2 => seller: u32 as uint32,
+    3 => price: i32 as sint32,
+    4 => qty: u32 as uint32,
+});
+
+scalar_message!(Quoted {
+    1 => id: u64 as uint64,
+    2 => side: i32 as int32,
+    3 => price: i32 as sint32,
+    4 => qty: u32 as uint32,
+    5 => timestamp_ns: u64 as uint64,
+    6 => participant: u32 as uint32,
+    7 => arrival: u64 as uint64,
+});
+
+scalar_message!(ProtectionTriggered {
+    1 => participant: u32 as uint32,
+    2 => trigger: i32 as int32,
+    3 => timestamp_ns: u64 as uint64,
+});
+
+scalar_message!(SessionEnded {
+    1 => timestamp_ns: u64 as uint64,
+});
+
+scalar_message!(Settled {
+    1 => price: i32 as sint32,
+    2 => timestamp_ns: u64 as uint64,
+});
+
+scalar_message!(Restored {
+    1 => id: u64 as uint64,
+    2 => side: i32 as int32,
+    3 => price: i32 as sint32,
+    4 => qty: u32 as uint32,
+    5 => timestamp_ns: u64 as uint64,
+    6 => participant: u32 as uint32,
+    7 => arrival: u64 as uint64,
+});
+
+scalar_message!(KillSwitchChanged {
+    1 => participant: u32 as uint32,
+    2 => engaged: bool as bool,
+});
+
+scalar_message!(TradingStatusChanged {
+    1 => status: i32 as int32,
+});
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TradeBust {
+    pub trade: Option<Trade>,
+    pub restored: bool,
+}
+
+impl Message for TradeBust {
+    fn encode_raw(&self, buf: &mut Vec<u8>) {
+        if let Some(trade) = &self.trade {
+            wire::message(1, trade, buf);
+        }
+        wire::bool::encode(2, self.restored, buf);
+    }
+
+    fn merge_field(&mut self, field: u32, value: wire::Value) -> io::Result<()> {
+        match field {
+            1 => self.trade = Some(wire::decode_message(value)?),
+            2 => self.restored = wire::bool::decode(value)?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuoteEntry {
+    pub symbol: String,
+    pub bid_price: i32,
+    pub bid_qty: u32,
+    pub ask_price: i32,
+    pub ask_qty: u32,
+}
+
+impl Message for QuoteEntry {
+    fn encode_raw(&self, buf: &mut Vec<u8>) {
+        wire::string::encode(1, &self.symbol, buf);
+        wire::sint32::encode(2, self.bid_price, buf);
+        wire::uint32::encode(3, self.bid_qty, buf);
+        wire::sint32::encode(4, self.ask_price, buf);
+        wire::uint32::encode(5, self.ask_qty, buf);
+    }
+
+    fn merge_field(&mut self, field: u32, value: wire::Value) -> io::Result<()> {
+        match field {
+            1 => self.symbol = wire::string::decode(value)?,
+            2 => self.bid_price = wire::sint32::decode(value)?,
+            3 => self.bid_qty = wire::uint32::decode(value)?,
+            4 => self.ask_price = wire::sint32::decode(value)?,
+            5 => self.ask_qty = wire::uint32::decode(value)?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MassQuote {
+    pub participant: u32,
+    pub quotes: Vec<QuoteEntry>,
+}
+
+impl Message for MassQuote {
+    fn encode_raw(&self, buf: &mut Vec<u8>) {
+        wire::uint32::encode(1, self.participant, buf);
+        for quote in &self.quotes {
+            wire::message(2, quote, buf);
+        }
+    }
+
+    fn merge_field(&mut self, field: u32, value: wire::Value) -> io::Result<()> {
+        match field {
+            1 => self.participant = wire::uint32::decode(value)?,
+            2 => self.quotes.push(wire::decode_message(value)?),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HaltTrading {}
+
+impl Message for HaltTrading {
+    fn encode_raw(&self, _buf: &mut Vec<u8>) {}
+
+    fn merge_field(&mut self, _field: u32, _value: wire::Value) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OrderModified {}
+
+impl Message for OrderModified {
+    fn encode_raw(&self, _buf: &mut Vec<u8>) {}
+
+    fn merge_field(&mut self, _field: u32, _value: wire::Value) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    New(NewOrder),
+    Modify(ModifyOrder),
+    Cancel(CancelOrder),
+    KillSwitch(KillSwitch),
+    Halt(HaltTrading),
+    Resume(ResumeTrading),
+    BustTrade(BustTrade),
+    MassQuote(MassQuote),
+    Cross(CrossTrade),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrderCommand {
+    pub command: Option<Command>,
+}
+
+impl Message for OrderCommand {
+    fn encode_raw(&self, buf: &mut Vec<u8>) {
+        match &self.command {
+            Some(Command::New(m)) => wire::message(1, m, buf),
+            Some(Command::Modify(m)) => wire::message(2, m, buf),
+            Some(Command::Cancel(m)) => wire::message(3, m, buf),
+            Some(Command::KillSwitch(m)) => wire::message(4, m, buf),
+            Some(Command::Halt(m)) => wire::message(5, m, buf),
+            Some(Command::Resume(m)) => wire::message(6, m, buf),
+            Some(Command::BustTrade(m)) => wire::message(7, m, buf),
+            Some(Command::MassQuote(m)) => wire::message(8, m, buf),
+            Some(Command::Cross(m)) => wire::message(9, m, buf),
+            None => {}
+        }
+    }
+
+    fn merge_field(&mut self, field: u32, value: wire::Value) -> io::Result<()> {
+        match field {
+            1 => self.command = Some(Command::New(wire::decode_message(value)?)),
+            2 => self.command = Some(Command::Modify(wire::decode_message(value)?)),
+            3 => self.command = Some(Command::Cancel(wire::decode_message(value)?)),
+            4 => self.command = Some(Command::KillSwitch(wire::decode_message(value)?)),
+            5 => self.command = Some(Command::Halt(wire::decode_message(value)?)),
+            6 => self.command = Some(Command::Resume(wire::decode_message(value)?)),
+            7 => self.command = Some(Command::BustTrade(wire::decode_message(value)?)),
+            8 => self.command = Some(Command::MassQuote(wire::decode_message(value)?)),
+            9 => self.command = Some(Command::Cross(wire::decode_message(value)?)),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Placed(OrderPlaced),
+    Modified(OrderModified),
+    Canceled(OrderCanceled),
+    PartiallyFilled(OrderPartiallyFilled),
+    Filled(OrderFilled),
+    Trade(Trade),
+    Rejected(OrderRejected),
+    KillSwitch(KillSwitchChanged),
+    StatusChanged(TradingStatusChanged),
+    TradeBust(TradeBust),
+    Quoted(Quoted),
+    ProtectionTriggered(ProtectionTriggered),
+    SessionEnded(SessionEnded),
+    Restored(Restored),
+    Settled(Settled),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrderEvent {
+    pub event: Option<Event>,
+}
+
+impl Message for OrderEvent {
+    fn encode_raw(&self, buf: &mut Vec<u8>) {
+        match &self.event {
+            Some(Event::Placed(m)) => wire::message(1, m, buf),
+            Some(Event::Modified(m)) => wire::message(2, m, buf),
+            Some(Event::Canceled(m)) => wire::message(3, m, buf),
+            Some(Event::PartiallyFilled(m)) => wire::message(4, m, buf),
+            Some(Event::Filled(m)) => wire::message(5, m, buf),
+            Some(Event::Trade(m)) => wire::message(6, m, buf),
+            Some(Event::Rejected(m)) => wire::message(7, m, buf),
+            Some(Event::KillSwitch(m)) => wire::message(8, m, buf),
+            Some(Event::StatusChanged(m)) => wire::message(9, m, buf),
+            Some(Event::TradeBust(m)) => wire::message(10, m, buf),
+            Some(Event::Quoted(m)) => wire::message(11, m, buf),
+            Some(Event::ProtectionTriggered(m)) => wire::message(12, m, buf),
+            Some(Event::SessionEnded(m)) => wire::message(13, m, buf),
+            Some(Event::Restored(m)) => wire::message(14, m, buf),
+            Some(Event::Settled(m)) => wire::message(15, m, buf),
+            None => {}
+        }
+    }
+
+    fn merge_field(&mut self, field: u32, value: wire::Value) -> io::Result<()> {
+        match field {
+            1 => self.event = Some(Event::Placed(wire::decode_message(value)?)),
+            2 => self.event = Some(Event::Modified(wire::decode_message(value)?)),
+            3 => self.event = Some(Event::Canceled(wire::decode_message(value)?)),
+            4 => self.event = Some(Event::PartiallyFilled(wire::decode_message(value)?)),
+            5 => self.event = Some(Event::Filled(wire::decode_message(value)?)),
+            6 => self.event = Some(Event::Trade(wire::decode_message(value)?)),
+            7 => self.event = Some(Event::Rejected(wire::decode_message(value)?)),
+            8 => self.event = Some(Event::KillSwitch(wire::decode_message(value)?)),
+            9 => self.event = Some(Event::StatusChanged(wire::decode_message(value)?)),
+            10 => self.event = Some(Event::TradeBust(wire::decode_message(value)?)),
+            11 => self.event = Some(Event::Quoted(wire::decode_message(value)?)),
+            12 => self.event = Some(Event::ProtectionTriggered(wire::decode_message(value)?)),
+            13 => self.event = Some(Event::SessionEnded(wire::decode_message(value)?)),
+            14 => self.event = Some(Event::Restored(wire::decode_message(value)?)),
+            15 => self.event = Some(Event::Settled(wire::decode_message(value)?)),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// [`EventEncoder`] producing serialized `matcher.v1.OrderEvent` messages.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProtoEncoder;
+
+impl EventEncoder for ProtoEncoder {
+    fn encode(&self, _symbol: &str, event: &crate::OrderEvent) -> io::Result<Vec<u8>> {
+        Ok(OrderEvent::from(event).encode_to_vec())
+    }
+}
+
+impl From<crate::Side> for Side {
+    fn from(side: crate::Side) -> Self {
+        match side {
+            crate::Side::Buy => Side::Buy,
+            crate::Side::Sell => Side::Sell,
+        }
+    }
+}
+
+impl From<crate::OrderType> for OrderType {
+    fn from(order_type: crate::OrderType) -> Self {
+        match order_type {
+            crate::OrderType::FillAndKill => OrderType::FillAndKill,
+            crate::OrderType::GoodTilCancel => OrderType::GoodTilCancel,
+            crate::OrderType::Day => OrderType::Day,
+        }
+    }
+}
+
+impl From<crate::circuit_breaker::TradingStatus> for TradingStatus {
+    fn from(status: crate::circuit_breaker::TradingStatus) -> Self {
+        match status {
+            crate::circuit_breaker::TradingStatus::Open => TradingStatus::Open,
+            crate::circuit_breaker::TradingStatus::Halted => TradingStatus::Halted,
+        }
+    }
+}
+
+impl From<crate::risk::protection::ProtectionTrigger> for ProtectionTrigger {
+    fn from(trigger: crate::risk::protection::ProtectionTrigger) -> Self {
+        match trigger {
+            crate::risk::protection::ProtectionTrigger::Fills => ProtectionTrigger::Fills,
+            crate::risk::protection::ProtectionTrigger::Quantity => ProtectionTrigger::Quantity,
+            crate::risk::protection::ProtectionTrigger::Delta => ProtectionTrigger::Delta,
+        }
+    }
+}
+
+fn protection_trigger(value: i32) -> io::Result<crate::risk::protection::ProtectionTrigger> {
+    match value {
+        1 => Ok(crate::risk::protection::ProtectionTrigger::Fills),
+        2 => Ok(crate::risk::protection::ProtectionTrigger::Quantity),
+        3 => Ok(crate::risk::protection::ProtectionTrigger::Delta),
+        _ => Err(invalid(format!("invalid protection trigger {}", value))),
+    }
+}
+
+fn trading_status(value: i32) -> io::Result<crate::circuit_breaker::TradingStatus> {
+    match value {
+        1 => Ok(crate::circuit_breaker::TradingStatus::Open),
+        2 => Ok(crate::circuit_breaker::TradingStatus::Halted),
+        _ => Err(invalid(format!("invalid trading status {}", value))),
+    }
+}
+
+fn side(value: i32) -> io::Result<crate::Side> {
+    match value {
+        1 => Ok(crate::Side::Buy),
+        2 => Ok(crate::Side::Sell),
+        _ => Err(invalid(format!("invalid side {}", value))),
+    }
+}
+
+fn order_type(value: i32) -> io::Result<crate::OrderType> {
+    match value {
+        1 => Ok(crate::OrderType::FillAndKill),
+        2 => Ok(crate::OrderType::GoodTilCancel),
+        3 => Ok(crate::OrderType::Day),
+        _ => Err(invalid(format!("invalid order type {}", value))),
+    }
+}
+
+/// Empty from senders that predate currencies, which priced in USD.
+fn currency(code: &str) -> io::Result<crate::Currency> {
+    if code.is_empty() {
+        return Ok(crate::Currency::USD);
+    }
+    crate::Currency::new(code).map_err(|e| invalid(e.to_string()))
+}
+
+/// Proto enum values match [`crate::risk::RejectReason::code`].
+fn reject_reason(value: i32) -> io::Result<crate::risk::RejectReason> {
+    u8::try_from(value)
+        .ok()
+        .and_then(crate::risk::RejectReason::from_code)
+        .ok_or_else(|| invalid(format!("invalid reject reason {}", value)))
+}
+
+impl From<&crate::OrderCommand> for OrderCommand {
+    fn from(command: &crate::OrderCommand) -> Self {
+        let command = match *command {
+            crate::OrderCommand::New {
+                order_type,
+                side,
+                price,
+                qty,
+                participant,
+            } => Command::New(NewOrder {
+                order_type: OrderType::from(order_type) as i32,
+                side: Side::from(side) as i32,
+                price,
+                qty,
+                participant,
+            }),
+            crate::OrderCommand::Modify {
+                id,
+                price,
+                side,
+                qty,
+                order_type,
+            } => Command::Modify(ModifyOrder {
+                id,
+                price,
+                side: Side::from(side) as i32,
+                qty,
+                order_type: OrderType::from(order_type) as i32,
+            }),
+            crate::OrderCommand::Cancel { id, side, price } => Command::Cancel(CancelOrder {
+                id,
+                side: Side::from(side) as i32,
+                price,
+            }),
+            crate::OrderCommand::KillSwitch {
+                participant,
+                engage,
+            } => Command::KillSwitch(KillSwitch {
+                participant,
+                engage,
+            }),
+            crate::OrderCommand::Halt => Command::Halt(HaltTrading {}),
+            crate::OrderCommand::Resume { auction } => Command::Resume(ResumeTrading { auction }),
+            crate::OrderCommand::BustTrade { trade_id, restore } => Command::BustTrade(BustTrade {
+                trade_id: trade_id as u64,
+                restore,
+            }),
+            crate::OrderCommand::MassQuote {
+                participant,
+                ref quotes,
+            } => Command::MassQuote(MassQuote {
+                participant,
+                quotes: quotes
+                    .iter()
+                    .map(|quote| QuoteEntry {
+                        symbol: quote.symbol.clone(),
+                        bid_price: quote.bid_price,
+                        bid_qty: quote.bid_qty,
+                        ask_price: quote.ask_price,
+                        ask_qty: quote.ask_qty,
+                    })
+                    .collect(),
+            }),
+            crate::OrderCommand::Cross {
+                buyer,
+                seller,
+                price,
+                qty,
+            } => Command::Cross(CrossTrade {
+                buyer,
+                seller,
+                price,
+                qty,
+            }),
+        };
+        OrderCommand {
+            command: Some(command),
+        }
+    }
+}
+
+impl TryFrom<OrderCommand> for crate::OrderCommand {
+    type Error = io::Error;
+
+    fn try_from(message: OrderCommand) -> io::Result<Self> {
+        match message.command {
+            Some(Command::New(m)) => Ok(crate::OrderCommand::New {
+                order_type: order_type(m.order_type)?,
+                side: side(m.side)?,
+                price: m.price,
+                qty: m.qty,
+                participant: m.participant,
+            }),
+            Some(Command::Modify(m)) => Ok(crate::OrderCommand::Modify {
+                id: m.id,
+                price: m.price,
+                side: side(m.side)?,
+                qty: m.qty,
+                order_type: order_type(m.order_type)?,
+            }),
+            Some(Command::Cancel(m)) => Ok(crate::OrderCommand::Cancel {
+                id: m.id,
+                side: side(m.side)?,
+                price: m.price,
+            }),
+            Some(Command::KillSwitch(m)) => Ok(crate::OrderCommand::KillSwitch {
+                participant: m.participant,
+                engage: m.engage,
+            }),
+            Some(Command::Halt(_)) => Ok(crate::OrderCommand::Halt),
+            Some(Command::Resume(m)) => Ok(crate::OrderCommand::Resume { auction: m.auction }),
+            Some(Command::BustTrade(m)) => Ok(crate::OrderCommand::BustTrade {
+                trade_id: m.trade_id as usize,
+                restore: m.restore,
+            }),
+            Some(Command::MassQuote(m)) => Ok(crate::OrderCommand::MassQuote {
+                participant: m.participant,
+                quotes: m
+                    .quotes
+                    .into_iter()
+                    .map(|quote| crate::QuoteEntry {
+                        symbol: quote.symbol,
+                        bid_price: quote.bid_price,
+                        bid_qty: quote.bid_qty,
+                        ask_price: quote.ask_price,
+                        ask_qty: quote.ask_qty,
+                    })
+                    .collect(),
+            }),
+            Some(Command::Cross(m)) => Ok(crate::OrderCommand::Cross {
+                buyer: m.buyer,
+                seller: m.seller,
+                price: m.price,
+                qty: m.qty,
+            }),
+            None => Err(invalid("OrderCommand without a command".to_string())),
+        }
+    }
+}
+
+impl From<&crate::Trade> for Trade {
+    fn from(trade: &crate::Trade) -> Self {
+        Trade {
+            id: trade.id as u64,
+            price: trade.price,
+            qty: trade.qty,
+            aggressor_side: Side::from(trade.aggressor_side) as i32,
+            maker_order_id: trade.maker_order_id,
+            taker_order_id: trade.taker_order_id,
+            timestamp_ns: trade.timestamp.as_nanos(),
+            maker_participant: trade.maker_participant,
+            taker_participant: trade.taker_participant,
+            maker_fee: trade.maker_fee,
+            taker_fee: trade.taker_fee,
+            cross: trade.cross,
+            dark: trade.dark,
+            currency: trade.currency.to_string(),
+        }
+    }
+}
+
+impl TryFrom<Trade> for crate::Trade {
+    type Error = io::Error;
+
+    fn try_from(m: Trade) -> io::Result<Self> {
+        Ok(crate::Trade {
+            id: m.id as usize,
+            price: m.price,
+            qty: m.qty,
+            aggressor_side: side(m.aggressor_side)?,
+            maker_order_id: m.maker_order_id,
+            taker_order_id: m.taker_order_id,
+            maker_participant: m.maker_participant,
+            taker_participant: m.taker_participant,
+            timestamp: Timestamp::from_nanos(m.timestamp_ns),
+            maker_fee: m.maker_fee,
+            taker_fee: m.taker_fee,
+            cross: m.cross,
+            dark: m.dark,
+            currency: currency(&m.currency)?,
+        })
+    }
+}
+
+impl From<&crate::OrderEvent> for OrderEvent {
+    fn from(event: &crate::OrderEvent) -> Self {
+        let event = match *event {
+            crate::OrderEvent::Placed {
+                id,
+                participant,
+                side,
+                order_type,
+                price,
+                timestamp,
+                arrival,
+            } => Event::Placed(OrderPlaced {
+                id,
+                side: Side::from(side) as i32,
+                order_type: OrderType::from(order_type) as i32,
+                price,
+                timestamp_ns: timestamp.as_nanos(),
+                participant,
+                arrival,
+            }),
+            crate::OrderEvent::Modified => Event::Modified(OrderModified {}),
+            crate::OrderEvent::Canceled { id } => Event::Canceled(OrderCanceled { id }),
+            crate::OrderEvent::PartiallyFilled {
+                id,
+                price,
+                qty,
+                timestamp,
+            } => Event::PartiallyFilled(OrderPartiallyFilled {
+                id,
+                price,
+                qty,
+                timestamp_ns: timestamp.as_nanos(),
+            }),
+            crate::OrderEvent::Filled {
+                id,
+                price,
+                timestamp,
+            } => Event::Filled(OrderFilled {
+                id,
+                price,
+                timestamp_ns: timestamp.as_nanos(),
+            }),
+            crate::OrderEvent::Trade(trade) => Event::Trade(Trade::from(&trade)),
+            crate::OrderEvent::Rejected {
+                participant,
+                side,
+                price,
+                qty,
+                reason,
+            } => Event::Rejected(OrderRejected {
+                participant,
+                side: Side::from(side) as i32,
+                price,
+                qty,
+                reason: i32::from(reason.code()),
+            }),
+            crate::OrderEvent::KillSwitch {
+                participant,
+                engaged,
+            } => Event::KillSwitch(KillSwitchChanged {
+                participant,
+                engaged,
+            }),
+            crate::OrderEvent::StatusChanged { status } => {
+                Event::StatusChanged(TradingStatusChanged {
+                    status: TradingStatus::from(status) as i32,
+                })
+            }
+            crate::OrderEvent::TradeBust { trade, restored } => Event::TradeBust(TradeBust {
+                trade: Some(Trade::from(&trade)),
+                restored,
+            }),
+            crate::OrderEvent::Quoted {
+                id,
+                participant,
+                side,
+                price,
+                qty,
+                timestamp,
+                arrival,
+            } => Event::Quoted(Quoted {
+                id,
+                side: Side::from(side) as i32,
+                price,
+                qty,
+                timestamp_ns: timestamp.as_nanos(),
+                participant,
+                arrival,
+            }),
+            crate::OrderEvent::ProtectionTriggered {
+                participant,
+                trigger,
+                timestamp,
+            } => Event::ProtectionTriggered(ProtectionTriggered {
+                participant,
+                trigger: ProtectionTrigger::from(trigger) as i32,
+                timestamp_ns: timestamp.as_nanos(),
+            }),
+            crate::OrderEvent::Settled { price, timestamp } => Event::Settled(Settled {
+                price,
+                timestamp_ns: timestamp.as_nanos(),
+            }),
+            crate::OrderEvent::SessionEnded { timestamp } => Event::SessionEnded(SessionEnded {
+                timestamp_ns: timestamp.as_nanos(),
+            }),
+            crate::OrderEvent::Restored {
+                id,
+                participant,
+                side,
+                price,
+                qty,
+                timestamp,
+                arrival,
+            } => Event::Restored(Restored {
+                id,
+                side: Side::from(side) as i32,
+                price,
+                qty,
+                timestamp_ns: timestamp.as_nanos(),
+                participant,
+                arrival,
+            }),
+        };
+        OrderEvent { event: Some(event) }
+    }
+}
+
+impl TryFrom<OrderEvent> for crate::OrderEvent {
+    type Error = io::Error;
+
+    fn try_from(message: OrderEvent) -> io::Result<Self> {
+        match message.event {
+            Some(Event::Placed(m)) => Ok(crate::OrderEvent::Placed {
+                id: m.id,
+                side: side(m.side)?,
+                order_type: order_type(m.order_type)?,
+                price: m.price,
+                timestamp: Timestamp::from_nanos(m.timestamp_ns),
+                participant: m.participant,
+                arrival: m.arrival,
+            }),
+            Some(Event::Modified(_)) => Ok(crate::OrderEvent::Modified),
+            Some(Event::Canceled(m)) => Ok(crate::OrderEvent::Canceled { id: m.id }),
+            Some(Event::PartiallyFilled(m)) => Ok(crate::OrderEvent::PartiallyFilled {
+                id: m.id,
+                price: m.price,
+                qty: m.qty,
+                timestamp: Timestamp::from_nanos(m.timestamp_ns),
+            }),
+            Some(Event::Filled(m)) => Ok(crate::OrderEvent::Filled {
+                id: m.id,
+                price: m.price,
+                timestamp: Timestamp::from_nanos(m.timestamp_ns),
+            }),
+            Some(Event::Trade(m)) => Ok(crate::OrderEvent::Trade(m.try_into()?)),
+            Some(Event::Rejected(m)) => Ok(crate::OrderEvent::Rejected {
+                participant: m.participant,
+                side: side(m.side)?,
+                price: m.price,
+                qty: m.qty,
+                reason: reject_reason(m.reason)?,
+            }),
+            Some(Event::KillSwitch(m)) => Ok(crate::OrderEvent::KillSwitch {
+                participant: m.participant,
+                engaged: m.engaged,
+            }),
+            Some(Event::StatusChanged(m)) => Ok(crate::OrderEvent::StatusChanged {
+                status: trading_status(m.status)?,
+            }),
+            Some(Event::TradeBust(m)) => Ok(crate::OrderEvent::TradeBust {
+                trade: m
+                    .trade
+                    .ok_or_else(|| invalid("TradeBust without a trade".to_string()))?
+                    .try_into()?,
+                restored: m.restored,
+            }),
+            Some(Event::Quoted(m)) => Ok(crate::OrderEvent::Quoted {
+                id: m.id,
+                participant: m.participant,
+                side: side(m.side)?,
+                price: m.price,
+                qty: m.qty,
+                timestamp: Timestamp::from_nanos(m.timestamp_ns),
+                arrival: m.arrival,
+            }),
+            Some(Event::ProtectionTriggered(m)) => Ok(crate::OrderEvent::ProtectionTriggered {
+                participant: m.participant,
+                trigger: protection_trigger(m.trigger)?,
+                timestamp: Timestamp::from_nanos(m.timestamp_ns),
+            }),
+            Some(Event::Settled(m)) => Ok(crate::OrderEvent::Settled {
+                price: m.price,
+                timestamp: Timestamp::from_nanos(m.timestamp_ns),
+            }),
+            Some(Event::SessionEnded(m)) => Ok(crate::OrderEvent::SessionEnded {
+                timestamp: Timestamp::from_nanos(m.timestamp_ns),
+            }),
+            Some(Event::Restored(m)) => Ok(crate::OrderEvent::Restored {
+                id: m.id,
+                participant: m.participant,
+                side: side(m.side)?,
+                price: m.price,
+                qty: m.qty,
+                timestamp: Timestamp::from_nanos(m.timestamp_ns),
+                arrival: m.arrival,
+            }),
+            None => Err(invalid("OrderEvent without an event".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CancelOrder, Command, Message, OrderCommand, OrderEvent};
+    use crate::{OrderType, Side};
+
+    #[test]
+    fn matches_reference_encoding() {
+        // Bytes as produced by protoc for
+        // OrderCommand { cancel: CancelOrder { id: 150, side: SIDE_SELL, price: -2 } }
+        let message = OrderCommand {
+            command: Some(Command::Cancel(CancelOrder {
+                id: 150,
+                side: 2,
+                price: -2,
+            })),
+        };
+        let expected = [0x1a, 0x07, 0x08, 0x96, 0x01, 0x10, 0x02, 0x18, 0x03];
+        assert_eq!(message.encode_to_vec(), expected);
+        assert_eq!(OrderCommand::decode(&expected).unwrap(), message);
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        let commands = [
+            crate::OrderCommand::Modify {
+                id: 7,
+                price: -122,
+                side: Side::Buy,
+                qty: 3,
+                order_type: OrderType::FillAndKill,
+            },
+            crate::OrderCommand::Halt,
+            crate::OrderCommand::Resume { auction: true },
+            crate::OrderCommand::BustTrade {
+                trade_id: 3,
+                restore: false,
+            },
+            crate::OrderCommand::MassQuote {
+                participant: 2,
+                quotes: vec![
+                    crate::QuoteEntry {
+                        symbol: "XYZ".to_string(),
+                        bid_price: -3,
+                        bid_qty: 10,
+                        ask_price: 4,
+                        ask_qty: 0,
+                    },
+                    crate::QuoteEntry::default(),
+                ],
+            },
+            crate::OrderCommand::Cross {
+                buyer: 2,
+                seller: 3,
+                price: -5,
+                qty: 5_000,
+            },
+        ];
+        for command in commands {
+            let bytes = OrderCommand::from(&command).encode_to_vec();
+            let decoded = crate::OrderCommand::try_from(OrderCommand::decode(&bytes).unwrap());
+            assert_eq!(decoded.unwrap(), command);
+        }
+    }
+
+    #[test]
+    fn events_round_trip_and_skip_unknown_fields() {
+        let event = crate::OrderEvent::Canceled { id: 300 };
+        let mut bytes = OrderEvent::from(&event).encode_to_vec();
+        // Field 16, varint 1: something a newer schema might add.
+        bytes.extend_from_slice(&[0x80, 0x01, 0x01]);
+        let decoded = crate::OrderEvent::try_from(OrderEvent::decode(&bytes).unwrap());
+        assert_eq!(decoded.unwrap(), event);
+    }
+
+    #[test]
+    fn engine_events_round_trip() {
+        for event in [
+            crate::OrderEvent::Rejected {
+                participant: 4,
+                side: Side::Sell,
+                price: 120,
+                qty: 9,
+                reason: crate::risk::RejectReason::MaxOrderQty,
+            },
+            crate::OrderEvent::KillSwitch {
+                participant: 4,
+                engaged: true,
+            },
+            crate::OrderEvent::StatusChanged {
+                status: crate::circuit_breaker::TradingStatus::Halted,
+            },
+            crate::OrderEvent::Trade(crate::Trade {
+                id: 3,
+                price: 120,
+                qty: 9,
+                aggressor_side: Side::Buy,
+                maker_order_id: 1,
+                taker_order_id: 2,
+                maker_participant: 4,
+                taker_participant: 5,
+                timestamp: crate::Timestamp::from_nanos(7),
+                maker_fee: -540,
+                taker_fee: i64::MAX,
+                cross: true,
+                dark: false,
+                currency: crate::Currency::EUR,
+            }),
+            crate::OrderEvent::Quoted {
+                id: 6,
+                participant: 4,
+                side: Side::Sell,
+                price: -1,
+                qty: 30,
+                timestamp: crate::Timestamp::from_nanos(8),
+                arrival: 17,
+            },
+            crate::OrderEvent::ProtectionTriggered {
+                participant: 4,
+                trigger: crate::risk::protection::ProtectionTrigger::Quantity,
+                timestamp: crate::Timestamp::from_nanos(9),
+            },
+            crate::OrderEvent::Settled {
+                price: -3,
+                timestamp: crate::Timestamp::from_nanos(10),
+            },
+            crate::OrderEvent::SessionEnded {
+                timestamp: crate::Timestamp::from_nanos(10),
+            },
+            crate::OrderEvent::Restored {
+                id: 7,
+                participant: 4,
+                side: Side::Buy,
+                price: 99,
+                qty: 12,
+                timestamp: crate::Timestamp::from_nanos(11),
+                arrival: 3,
+            },
+        ] {
+            let bytes = OrderEvent::from(&event).encode_to_vec();
+            let decoded = crate::OrderEvent::try_from(OrderEvent::decode(&bytes).unwrap());
+            assert_eq!(decoded.unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn rejects_unspecified_enums() {
+        let message = OrderCommand {
+            command: Some(Command::Cancel(CancelOrder {
+                id: 1,
+                side: 0,
+                price: 1,
+            })),
+        };
+        assert!(crate::OrderCommand::try_from(message).is_err());
+    }
+}
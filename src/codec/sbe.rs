@@ -0,0 +1,1001 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Simple Binary Encoding codecs for commands and events.
+//!
+//! Hand written against `schema/matcher-sbe.xml`; keep the two in sync. Every
+//! message is the standard 8 byte SBE header followed by a fixed block, and
+//! mass quotes follow theirs with a repeating group of quote entries.
+//! Decoders honour the header's block length, so messages from a newer schema
+//! version with extra trailing fields still decode.
+
+use super::{invalid, Reader};
+use crate::circuit_breaker::TradingStatus;
+use crate::risk::protection::ProtectionTrigger;
+use crate::risk::RejectReason;
+use crate::sink::EventEncoder;
+use crate::{Currency, OrderCommand, OrderEvent, OrderType, QuoteEntry, Side, Timestamp, Trade};
+use std::io;
+
+pub const SCHEMA_ID: u16 = 17;
+pub const SCHEMA_VERSION: u16 = 10;
+pub const HEADER_LEN: usize = 8;
+
+const NEW_ORDER: (u16, u16) = (1, 14);
+const MODIFY_ORDER: (u16, u16) = (2, 18);
+const CANCEL_ORDER: (u16, u16) = (3, 13);
+const KILL_SWITCH: (u16, u16) = (4, 5);
+const HALT_TRADING: (u16, u16) = (5, 0);
+const RESUME_TRADING: (u16, u16) = (6, 1);
+const BUST_TRADE: (u16, u16) = (7, 9);
+const MASS_QUOTE: (u16, u16) = (8, 4);
+/// Fixed part of each entry in a mass quote's group; the symbol follows.
+const QUOTE_ENTRY_LEN: u16 = 16;
+const CROSS_TRADE: (u16, u16) = (9, 16);
+const ORDER_PLACED: (u16, u16) = (10, 34);
+const ORDER_MODIFIED: (u16, u16) = (11, 0);
+const ORDER_CANCELED: (u16, u16) = (12, 8);
+const ORDER_PARTIALLY_FILLED: (u16, u16) = (13, 24);
+const ORDER_FILLED: (u16, u16) = (14, 20);
+const TRADE: (u16, u16) = (15, 70);
+const ORDER_REJECTED: (u16, u16) = (16, 14);
+const KILL_SWITCH_CHANGED: (u16, u16) = (17, 5);
+const TRADING_STATUS_CHANGED: (u16, u16) = (18, 1);
+const TRADE_BUST: (u16, u16) = (19, 71);
+const QUOTED: (u16, u16) = (20, 37);
+const PROTECTION_TRIGGERED: (u16, u16) = (21, 13);
+const SESSION_ENDED: (u16, u16) = (22, 8);
+const RESTORED: (u16, u16) = (23, 37);
+const SETTLED: (u16, u16) = (24, 12);
+/// Version 1 trades, before the fees were appended.
+const TRADE_V1: (u16, u16) = (15, 49);
+const TRADE_BUST_V1: (u16, u16) = (19, 50);
+/// Version 6 trades, before the cross flag was appended.
+const TRADE_V6: (u16, u16) = (15, 65);
+const TRADE_BUST_V6: (u16, u16) = (19, 66);
+/// Version 7 trades, before the dark flag was appended.
+const TRADE_V7: (u16, u16) = (15, 66);
+const TRADE_BUST_V7: (u16, u16) = (19, 67);
+/// Version 8 trades, before the currency was appended.
+const TRADE_V8: (u16, u16) = (15, 67);
+const TRADE_BUST_V8: (u16, u16) = (19, 68);
+/// Version 3 placements and quotes, before the arrival sequence was
+/// appended.
+const ORDER_PLACED_V3: (u16, u16) = (10, 26);
+const QUOTED_V3: (u16, u16) = (20, 29);
+
+pub fn encode_command(command: &OrderCommand, buf: &mut Vec<u8>) {
+    match *command {
+        OrderCommand::New {
+            order_type,
+            side,
+            price,
+            qty,
+            participant,
+        } => {
+            header(buf, NEW_ORDER);
+            buf.push(order_type_code(order_type));
+            buf.push(side_code(side));
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&qty.to_le_bytes());
+            buf.extend_from_slice(&participant.to_le_bytes());
+        }
+        OrderCommand::Modify {
+            id,
+            price,
+            side,
+            qty,
+            order_type,
+        } => {
+            header(buf, MODIFY_ORDER);
+            buf.extend_from_slice(&id.to_le_bytes());
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.push(side_code(side));
+            buf.extend_from_slice(&qty.to_le_bytes());
+            buf.push(order_type_code(order_type));
+        }
+        OrderCommand::Cancel { id, side, price } => {
+            header(buf, CANCEL_ORDER);
+            buf.extend_from_slice(&id.to_le_bytes());
+            buf.push(side_code(side));
+            buf.extend_from_slice(&price.to_le_bytes());
+        }
+        OrderCommand::KillSwitch {
+            participant,
+            engage,
+        } => {
+            header(buf, KILL_SWITCH);
+            buf.extend_from_slice(&participant.to_le_bytes());
+            buf.push(u8::from(engage));
+        }
+        OrderCommand::Halt => header(buf, HALT_TRADING),
+        OrderCommand::Resume { auction } => {
+            header(buf, RESUME_TRADING);
+            buf.push(u8::from(auction));
+        }
+        OrderCommand::BustTrade { trade_id, restore } => {
+            header(buf, BUST_TRADE);
+            buf.extend_from_slice(&(trade_id as u64).to_le_bytes());
+            buf.push(u8::from(restore));
+        }
+        OrderCommand::MassQuote {
+            participant,
+            ref quotes,
+        } => {
+            header(buf, MASS_QUOTE);
+            buf.extend_from_slice(&participant.to_le_bytes());
+            buf.extend_from_slice(&QUOTE_ENTRY_LEN.to_le_bytes());
+            buf.extend_from_slice(&(quotes.len() as u16).to_le_bytes());
+            for quote in quotes {
+                buf.extend_from_slice(&quote.bid_price.to_le_bytes());
+                buf.extend_from_slice(&quote.bid_qty.to_le_bytes());
+                buf.extend_from_slice(&quote.ask_price.to_le_bytes());
+                buf.extend_from_slice(&quote.ask_qty.to_le_bytes());
+                buf.extend_from_slice(&(quote.symbol.len() as u16).to_le_bytes());
+                buf.extend_from_slice(quote.symbol.as_bytes());
+            }
+        }
+        OrderCommand::Cross {
+            buyer,
+            seller,
+            price,
+            qty,
+        } => {
+            header(buf, CROSS_TRADE);
+            buf.extend_from_slice(&buyer.to_le_bytes());
+            buf.extend_from_slice(&seller.to_le_bytes());
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&qty.to_le_bytes());
+        }
+    }
+}
+
+pub fn decode_command(bytes: &[u8]) -> io::Result<OrderCommand> {
+    let (template_id, mut block) = read_header(bytes)?;
+    let command = match template_id {
+        1 => {
+            block.expect(NEW_ORDER)?;
+            OrderCommand::New {
+                order_type: read_order_type(&mut block.reader)?,
+                side: read_side(&mut block.reader)?,
+                price: block.reader.i32()?,
+                qty: block.reader.u32()?,
+                participant: block.reader.u32()?,
+            }
+        }
+        2 => {
+            block.expect(MODIFY_ORDER)?;
+            OrderCommand::Modify {
+                id: block.reader.u64()?,
+                price: block.reader.i32()?,
+                side: read_side(&mut block.reader)?,
+                qty: block.reader.u32()?,
+                order_type: read_order_type(&mut block.reader)?,
+            }
+        }
+        3 => {
+            block.expect(CANCEL_ORDER)?;
+            OrderCommand::Cancel {
+                id: block.reader.u64()?,
+                side: read_side(&mut block.reader)?,
+                price: block.reader.i32()?,
+            }
+        }
+        4 => {
+            block.expect(KILL_SWITCH)?;
+            OrderCommand::KillSwitch {
+                participant: block.reader.u32()?,
+                engage: read_bool(&mut block.reader)?,
+            }
+        }
+        5 => {
+            block.expect(HALT_TRADING)?;
+            OrderCommand::Halt
+        }
+        6 => {
+            block.expect(RESUME_TRADING)?;
+            OrderCommand::Resume {
+                auction: read_bool(&mut block.reader)?,
+            }
+        }
+        7 => {
+            block.expect(BUST_TRADE)?;
+            OrderCommand::BustTrade {
+                trade_id: block.reader.u64()? as usize,
+                restore: read_bool(&mut block.reader)?,
+            }
+        }
+        8 => {
+            block.expect(MASS_QUOTE)?;
+            let participant = block.reader.u32()?;
+            let r = &mut block.rest;
+            let entry_len = r.u16()?;
+            if entry_len < QUOTE_ENTRY_LEN {
+                return Err(invalid(format!(
+                    "quote entry length {} shorter than {}",
+                    entry_len, QUOTE_ENTRY_LEN
+                )));
+            }
+            let count = r.u16()?;
+            let mut quotes = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let mut entry = Reader::new(r.take(entry_len as usize)?);
+                let mut quote = QuoteEntry {
+                    bid_price: entry.i32()?,
+                    bid_qty: entry.u32()?,
+                    ask_price: entry.i32()?,
+                    ask_qty: entry.u32()?,
+                    ..QuoteEntry::default()
+                };
+                let len = r.u16()?;
+                quote.symbol = String::from_utf8(r.take(len as usize)?.to_vec())
+                    .map_err(|_| invalid("quote symbol is not UTF-8".to_string()))?;
+                quotes.push(quote);
+            }
+            OrderCommand::MassQuote {
+                participant,
+                quotes,
+            }
+        }
+        9 => {
+            block.expect(CROSS_TRADE)?;
+            let r = &mut block.reader;
+            OrderCommand::Cross {
+                buyer: r.u32()?,
+                seller: r.u32()?,
+                price: r.i32()?,
+                qty: r.u32()?,
+            }
+        }
+        id => return Err(invalid(format!("unknown command template {}", id))),
+    };
+    Ok(command)
+}
+
+pub fn encode_event(event: &OrderEvent, buf: &mut Vec<u8>) {
+    match *event {
+        OrderEvent::Placed {
+            id,
+            participant,
+            side,
+            order_type,
+            price,
+            timestamp,
+            arrival,
+        } => {
+            header(buf, ORDER_PLACED);
+            buf.extend_from_slice(&id.to_le_bytes());
+            buf.push(side_code(side));
+            buf.push(order_type_code(order_type));
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&timestamp.as_nanos().to_le_bytes());
+            buf.extend_from_slice(&participant.to_le_bytes());
+            buf.extend_from_slice(&arrival.to_le_bytes());
+        }
+        OrderEvent::Modified => header(buf, ORDER_MODIFIED),
+        OrderEvent::Canceled { id } => {
+            header(buf, ORDER_CANCELED);
+            buf.extend_from_slice(&id.to_le_bytes());
+        }
+        OrderEvent::PartiallyFilled {
+            id,
+            price,
+            qty,
+            timestamp,
+        } => {
+            header(buf, ORDER_PARTIALLY_FILLED);
+            buf.extend_from_slice(&id.to_le_bytes());
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&qty.to_le_bytes());
+            buf.extend_from_slice(&timestamp.as_nanos().to_le_bytes());
+        }
+        OrderEvent::Filled {
+            id,
+            price,
+            timestamp,
+        } => {
+            header(buf, ORDER_FILLED);
+            buf.extend_from_slice(&id.to_le_bytes());
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&timestamp.as_nanos().to_le_bytes());
+        }
+        OrderEvent::Trade(trade) => {
+            header(buf, TRADE);
+            put_trade(buf, &trade);
+            put_fees(buf, &trade);
+            put_flags(buf, &trade);
+        }
+        OrderEvent::Rejected {
+            participant,
+            side,
+            price,
+            qty,
+            reason,
+        } => {
+            header(buf, ORDER_REJECTED);
+            buf.extend_from_slice(&participant.to_le_bytes());
+            buf.push(side_code(side));
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&qty.to_le_bytes());
+            buf.push(reason.code());
+        }
+        OrderEvent::KillSwitch {
+            participant,
+            engaged,
+        } => {
+            header(buf, KILL_SWITCH_CHANGED);
+            buf.extend_from_slice(&participant.to_le_bytes());
+            buf.push(u8::from(engaged));
+        }
+        OrderEvent::StatusChanged { status } => {
+            header(buf, TRADING_STATUS_CHANGED);
+            buf.push(status_code(status));
+        }
+        OrderEvent::TradeBust { trade, restored } => {
+            header(buf, TRADE_BUST);
+            put_trade(buf, &trade);
+            buf.push(u8::from(restored));
+            put_fees(buf, &trade);
+            put_flags(buf, &trade);
+        }
+        OrderEvent::Quoted {
+            id,
+            participant,
+            side,
+            price,
+            qty,
+            timestamp,
+            arrival,
+        } => {
+            header(buf, QUOTED);
+            buf.extend_from_slice(&id.to_le_bytes());
+            buf.push(side_code(side));
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&qty.to_le_bytes());
+            buf.extend_from_slice(&timestamp.as_nanos().to_le_bytes());
+            buf.extend_from_slice(&participant.to_le_bytes());
+            buf.extend_from_slice(&arrival.to_le_bytes());
+        }
+        OrderEvent::ProtectionTriggered {
+            participant,
+            trigger,
+            timestamp,
+        } => {
+            header(buf, PROTECTION_TRIGGERED);
+            buf.extend_from_slice(&participant.to_le_bytes());
+            buf.push(trigger_code(trigger));
+            buf.extend_from_slice(&timestamp.as_nanos().to_le_bytes());
+        }
+        OrderEvent::SessionEnded { timestamp } => {
+            header(buf, SESSION_ENDED);
+            buf.extend_from_slice(&timestamp.as_nanos().to_le_bytes());
+        }
+        OrderEvent::Restored {
+            id,
+            participant,
+            side,
+            price,
+            qty,
+            timestamp,
+            arrival,
+        } => {
+            header(buf, RESTORED);
+            buf.extend_from_slice(&id.to_le_bytes());
+            buf.push(side_code(side));
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&qty.to_le_bytes());
+            buf.extend_from_slice(&timestamp.as_nanos().to_le_bytes());
+            buf.extend_from_slice(&participant.to_le_bytes());
+            buf.extend_from_slice(&arrival.to_le_bytes());
+        }
+        OrderEvent::Settled { price, timestamp } => {
+            header(buf, SETTLED);
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&timestamp.as_nanos().to_le_bytes());
+        }
+    }
+}
+
+pub fn decode_event(bytes: &[u8]) -> io::Result<OrderEvent> {
+    let (template_id, mut block) = read_header(bytes)?;
+    let event = match template_id {
+        10 => {
+            block.expect(ORDER_PLACED_V3)?;
+            let r = &mut block.reader;
+            OrderEvent::Placed {
+                id: r.u64()?,
+                side: read_side(r)?,
+                order_type: read_order_type(r)?,
+                price: r.i32()?,
+                timestamp: Timestamp::from_nanos(r.u64()?),
+                participant: r.u32()?,
+                arrival: read_arrival(r, block.block_length >= ORDER_PLACED.1)?,
+            }
+        }
+        11 => OrderEvent::Modified,
+        12 => {
+            block.expect(ORDER_CANCELED)?;
+            OrderEvent::Canceled {
+                id: block.reader.u64()?,
+            }
+        }
+        13 => {
+            block.expect(ORDER_PARTIALLY_FILLED)?;
+            let r = &mut block.reader;
+            OrderEvent::PartiallyFilled {
+                id: r.u64()?,
+                price: r.i32()?,
+                qty: r.u32()?,
+                timestamp: Timestamp::from_nanos(r.u64()?),
+            }
+        }
+        14 => {
+            block.expect(ORDER_FILLED)?;
+            let r = &mut block.reader;
+            OrderEvent::Filled {
+                id: r.u64()?,
+                price: r.i32()?,
+                timestamp: Timestamp::from_nanos(r.u64()?),
+            }
+        }
+        15 => {
+            block.expect(TRADE_V1)?;
+            let r = &mut block.reader;
+            let mut trade = read_trade(r)?;
+            if block.block_length >= TRADE_V6.1 {
+                read_fees(r, &mut trade)?;
+            }
+            if block.block_length >= TRADE_V7.1 {
+                trade.cross = read_bool(r)?;
+            }
+            if block.block_length >= TRADE_V8.1 {
+                trade.dark = read_bool(r)?;
+            }
+            if block.block_length >= TRADE.1 {
+                trade.currency = read_currency(r)?;
+            }
+            OrderEvent::Trade(trade)
+        }
+        16 => {
+            block.expect(ORDER_REJECTED)?;
+            let r = &mut block.reader;
+            OrderEvent::Rejected {
+                participant: r.u32()?,
+                side: read_side(r)?,
+                price: r.i32()?,
+                qty: r.u32()?,
+                reason: read_reject_reason(r)?,
+            }
+        }
+        17 => {
+            block.expect(KILL_SWITCH_CHANGED)?;
+            OrderEvent::KillSwitch {
+                participant: block.reader.u32()?,
+                engaged: read_bool(&mut block.reader)?,
+            }
+        }
+        18 => {
+            block.expect(TRADING_STATUS_CHANGED)?;
+            OrderEvent::StatusChanged {
+                status: read_status(&mut block.reader)?,
+            }
+        }
+        19 => {
+            block.expect(TRADE_BUST_V1)?;
+            let r = &mut block.reader;
+            let mut trade = read_trade(r)?;
+            let restored = read_bool(r)?;
+            if block.block_length >= TRADE_BUST_V6.1 {
+                read_fees(r, &mut trade)?;
+            }
+            if block.block_length >= TRADE_BUST_V7.1 {
+                trade.cross = read_bool(r)?;
+            }
+            if block.block_length >= TRADE_BUST_V8.1 {
+                trade.dark = read_bool(r)?;
+            }
+            if block.block_length >= TRADE_BUST.1 {
+                trade.currency = read_currency(r)?;
+            }
+            OrderEvent::TradeBust { trade, restored }
+        }
+        20 => {
+            block.expect(QUOTED_V3)?;
+            let r = &mut block.reader;
+            OrderEvent::Quoted {
+                id: r.u64()?,
+                side: read_side(r)?,
+                price: r.i32()?,
+                qty: r.u32()?,
+                timestamp: Timestamp::from_nanos(r.u64()?),
+                participant: r.u32()?,
+                arrival: read_arrival(r, block.block_length >= QUOTED.1)?,
+            }
+        }
+        21 => {
+            block.expect(PROTECTION_TRIGGERED)?;
+            let r = &mut block.reader;
+            OrderEvent::ProtectionTriggered {
+                participant: r.u32()?,
+                trigger: read_trigger(r)?,
+                timestamp: Timestamp::from_nanos(r.u64()?),
+            }
+        }
+        22 => {
+            block.expect(SESSION_ENDED)?;
+            OrderEvent::SessionEnded {
+                timestamp: Timestamp::from_nanos(block.reader.u64()?),
+            }
+        }
+        23 => {
+            block.expect(RESTORED)?;
+            let r = &mut block.reader;
+            OrderEvent::Restored {
+                id: r.u64()?,
+                side: read_side(r)?,
+                price: r.i32()?,
+                qty: r.u32()?,
+                timestamp: Timestamp::from_nanos(r.u64()?),
+                participant: r.u32()?,
+                arrival: r.u64()?,
+            }
+        }
+        24 => {
+            block.expect(SETTLED)?;
+            let r = &mut block.reader;
+            OrderEvent::Settled {
+                price: r.i32()?,
+                timestamp: Timestamp::from_nanos(r.u64()?),
+            }
+        }
+        id => return Err(invalid(format!("unknown event template {}", id))),
+    };
+    Ok(event)
+}
+
+/// [`EventEncoder`] producing SBE event messages. The symbol is not part of
+/// the schema; transports carry it out of band (e.g. as the Kafka key).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SbeEncoder;
+
+impl EventEncoder for SbeEncoder {
+    fn encode(&self, _symbol: &str, event: &OrderEvent) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(HEADER_LEN + TRADE.1 as usize);
+        encode_event(event, &mut buf);
+        Ok(buf)
+    }
+}
+
+fn header(buf: &mut Vec<u8>, (template_id, block_length): (u16, u16)) {
+    for field in [block_length, template_id, SCHEMA_ID, SCHEMA_VERSION] {
+        buf.extend_from_slice(&field.to_le_bytes());
+    }
+}
+
+struct Block<'a> {
+    block_length: u16,
+    reader: Reader<'a>,
+    /// What follows the block: repeating groups, if the message has any.
+    rest: Reader<'a>,
+}
+
+impl Block<'_> {
+    /// Newer schema versions may only append fields, so a block is valid as
+    /// long as it is at least as long as the one this decoder knows about.
+    fn expect(&self, (template_id, block_length): (u16, u16)) -> io::Result<()> {
+        if self.block_length < block_length {
+            return Err(invalid(format!(
+                "template {} block length {} shorter than {}",
+                template_id, self.block_length, block_length
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn read_header(bytes: &[u8]) -> io::Result<(u16, Block<'_>)> {
+    let mut reader = Reader::new(bytes);
+    let block_length = reader.u16()?;
+    let template_id = reader.u16()?;
+    let schema_id = reader.u16()?;
+    let _version = reader.u16()?;
+    if schema_id != SCHEMA_ID {
+        return Err(invalid(format!("unexpected schema id {}", schema_id)));
+    }
+    let block = reader.take(block_length as usize)?;
+    Ok((
+        template_id,
+        Block {
+            block_length,
+            reader: Reader::new(block),
+            rest: reader,
+        },
+    ))
+}
+
+fn side_code(side: Side) -> u8 {
+    match side {
+        Side::Buy => 0,
+        Side::Sell => 1,
+    }
+}
+
+fn read_side(reader: &mut Reader) -> io::Result<Side> {
+    match reader.u8()? {
+        0 => Ok(Side::Buy),
+        1 => Ok(Side::Sell),
+        code => Err(invalid(format!("invalid side {}", code))),
+    }
+}
+
+fn order_type_code(order_type: OrderType) -> u8 {
+    match order_type {
+        OrderType::FillAndKill => 0,
+        OrderType::GoodTilCancel => 1,
+        OrderType::Day => 2,
+    }
+}
+
+fn read_order_type(reader: &mut Reader) -> io::Result<OrderType> {
+    match reader.u8()? {
+        0 => Ok(OrderType::FillAndKill),
+        1 => Ok(OrderType::GoodTilCancel),
+        2 => Ok(OrderType::Day),
+        code => Err(invalid(format!("invalid order type {}", code))),
+    }
+}
+
+fn status_code(status: TradingStatus) -> u8 {
+    match status {
+        TradingStatus::Open => 0,
+        TradingStatus::Halted => 1,
+    }
+}
+
+fn read_status(reader: &mut Reader) -> io::Result<TradingStatus> {
+    match reader.u8()? {
+        0 => Ok(TradingStatus::Open),
+        1 => Ok(TradingStatus::Halted),
+        code => Err(invalid(format!("invalid trading status {}", code))),
+    }
+}
+
+fn trigger_code(trigger: ProtectionTrigger) -> u8 {
+    match trigger {
+        ProtectionTrigger::Fills => 0,
+        ProtectionTrigger::Quantity => 1,
+        ProtectionTrigger::Delta => 2,
+    }
+}
+
+fn read_trigger(reader: &mut Reader) -> io::Result<ProtectionTrigger> {
+    match reader.u8()? {
+        0 => Ok(ProtectionTrigger::Fills),
+        1 => Ok(ProtectionTrigger::Quantity),
+        2 => Ok(ProtectionTrigger::Delta),
+        code => Err(invalid(format!("invalid protection trigger {}", code))),
+    }
+}
+
+fn put_trade(buf: &mut Vec<u8>, trade: &Trade) {
+    buf.extend_from_slice(&(trade.id as u64).to_le_bytes());
+    buf.extend_from_slice(&trade.price.to_le_bytes());
+    buf.extend_from_slice(&trade.qty.to_le_bytes());
+    buf.push(side_code(trade.aggressor_side));
+    buf.extend_from_slice(&trade.maker_order_id.to_le_bytes());
+    buf.extend_from_slice(&trade.taker_order_id.to_le_bytes());
+    buf.extend_from_slice(&trade.timestamp.as_nanos().to_le_bytes());
+    buf.extend_from_slice(&trade.maker_participant.to_le_bytes());
+    buf.extend_from_slice(&trade.taker_participant.to_le_bytes());
+}
+
+fn read_trade(r: &mut Reader) -> io::Result<Trade> {
+    Ok(Trade {
+        id: r.u64()? as usize,
+        price: r.i32()?,
+        qty: r.u32()?,
+        aggressor_side: read_side(r)?,
+        maker_order_id: r.u64()?,
+        taker_order_id: r.u64()?,
+        timestamp: Timestamp::from_nanos(r.u64()?),
+        maker_participant: r.u32()?,
+        taker_participant: r.u32()?,
+        maker_fee: 0,
+        taker_fee: 0,
+        cross: false,
+        dark: false,
+        currency: Currency::default(),
+    })
+}
+
+fn put_fees(buf: &mut Vec<u8>, trade: &Trade) {
+    buf.extend_from_slice(&trade.maker_fee.to_le_bytes());
+    buf.extend_from_slice(&trade.taker_fee.to_le_bytes());
+}
+
+fn put_flags(buf: &mut Vec<u8>, trade: &Trade) {
+    buf.push(u8::from(trade.cross));
+    buf.push(u8::from(trade.dark));
+    buf.extend_from_slice(&trade.currency.to_bytes());
+}
+
+/// USD from senders older than version 9, which priced in nothing else.
+fn read_currency(r: &mut Reader) -> io::Result<Currency> {
+    let code = r.take(3)?;
+    Currency::from_bytes([code[0], code[1], code[2]]).map_err(|e| invalid(e.to_string()))
+}
+
+fn read_fees(r: &mut Reader, trade: &mut Trade) -> io::Result<()> {
+    trade.maker_fee = r.i64()?;
+    trade.taker_fee = r.i64()?;
+    Ok(())
+}
+
+/// Zero from senders older than version 4, which didn't send one.
+fn read_arrival(r: &mut Reader, present: bool) -> io::Result<u64> {
+    if present {
+        r.u64()
+    } else {
+        Ok(0)
+    }
+}
+
+fn read_bool(reader: &mut Reader) -> io::Result<bool> {
+    match reader.u8()? {
+        0 => Ok(false),
+        1 => Ok(true),
+        code => Err(invalid(format!("invalid boolean {}", code))),
+    }
+}
+
+fn read_reject_reason(reader: &mut Reader) -> io::Result<RejectReason> {
+    let code = reader.u8()?;
+    RejectReason::from_code(code).ok_or_else(|| invalid(format!("invalid reject reason {}", code)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_command, decode_event, encode_command, encode_event, HEADER_LEN};
+    use crate::risk::protection::ProtectionTrigger;
+    use crate::risk::RejectReason;
+    use crate::{
+        Currency, OrderCommand, OrderEvent, OrderType, QuoteEntry, Side, Timestamp, Trade,
+    };
+
+    #[test]
+    fn commands_round_trip() {
+        let commands = [
+            OrderCommand::New {
+                order_type: OrderType::FillAndKill,
+                side: Side::Sell,
+                price: -5,
+                qty: 10,
+                participant: 1,
+            },
+            OrderCommand::Modify {
+                id: 42,
+                price: 122,
+                side: Side::Buy,
+                qty: 3,
+                order_type: OrderType::Day,
+            },
+            OrderCommand::Cancel {
+                id: 42,
+                side: Side::Buy,
+                price: 122,
+            },
+            OrderCommand::KillSwitch {
+                participant: 9,
+                engage: true,
+            },
+            OrderCommand::Halt,
+            OrderCommand::Resume { auction: true },
+            OrderCommand::BustTrade {
+                trade_id: 12,
+                restore: true,
+            },
+            OrderCommand::MassQuote {
+                participant: 4,
+                quotes: vec![
+                    QuoteEntry {
+                        symbol: "ABC".to_string(),
+                        bid_price: -2,
+                        bid_qty: 5,
+                        ask_price: 1,
+                        ask_qty: 0,
+                    },
+                    QuoteEntry::default(),
+                ],
+            },
+            OrderCommand::Cross {
+                buyer: 2,
+                seller: 3,
+                price: -7,
+                qty: 5_000,
+            },
+        ];
+        for command in commands {
+            let mut buf = Vec::new();
+            encode_command(&command, &mut buf);
+            assert_eq!(decode_command(&buf).unwrap(), command);
+            assert!(decode_command(&buf[..buf.len() - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let mut buf = Vec::new();
+        encode_event(&OrderEvent::Canceled { id: 9 }, &mut buf);
+        assert_eq!(buf.len(), HEADER_LEN + 8);
+        assert_eq!(decode_event(&buf).unwrap(), OrderEvent::Canceled { id: 9 });
+
+        let trade = Trade {
+            id: 1,
+            price: 122,
+            qty: 4,
+            aggressor_side: Side::Sell,
+            maker_order_id: 2,
+            taker_order_id: 3,
+            maker_participant: 7,
+            taker_participant: 8,
+            timestamp: Timestamp::now(),
+            maker_fee: -150,
+            taker_fee: 300,
+            cross: true,
+            dark: false,
+            currency: Currency::EUR,
+        };
+        buf.clear();
+        encode_event(&OrderEvent::Trade(trade), &mut buf);
+        match decode_event(&buf).unwrap() {
+            OrderEvent::Trade(decoded) => assert_eq!(decoded, trade),
+            other => panic!("decoded {:?}", other),
+        }
+
+        buf.clear();
+        encode_event(
+            &OrderEvent::TradeBust {
+                trade,
+                restored: true,
+            },
+            &mut buf,
+        );
+        assert_eq!(buf.len(), HEADER_LEN + 71);
+        match decode_event(&buf).unwrap() {
+            OrderEvent::TradeBust {
+                trade: decoded,
+                restored: true,
+            } => assert_eq!(decoded, trade),
+            other => panic!("decoded {:?}", other),
+        }
+
+        let rejected = OrderEvent::Rejected {
+            participant: 3,
+            side: Side::Buy,
+            price: -4,
+            qty: 1_000,
+            reason: RejectReason::MaxOrderNotional,
+        };
+        buf.clear();
+        encode_event(&rejected, &mut buf);
+        assert_eq!(decode_event(&buf).unwrap(), rejected);
+
+        let quoted = OrderEvent::Quoted {
+            id: 5,
+            participant: 2,
+            side: Side::Sell,
+            price: 101,
+            qty: 20,
+            timestamp: Timestamp::from_nanos(7),
+            arrival: 12,
+        };
+        buf.clear();
+        encode_event(&quoted, &mut buf);
+        assert_eq!(buf.len(), HEADER_LEN + 37);
+        assert_eq!(decode_event(&buf).unwrap(), quoted);
+
+        let triggered = OrderEvent::ProtectionTriggered {
+            participant: 2,
+            trigger: ProtectionTrigger::Delta,
+            timestamp: Timestamp::from_nanos(8),
+        };
+        buf.clear();
+        encode_event(&triggered, &mut buf);
+        assert_eq!(buf.len(), HEADER_LEN + 13);
+        assert_eq!(decode_event(&buf).unwrap(), triggered);
+
+        let ended = OrderEvent::SessionEnded {
+            timestamp: Timestamp::from_nanos(9),
+        };
+        buf.clear();
+        encode_event(&ended, &mut buf);
+        assert_eq!(buf.len(), HEADER_LEN + 8);
+        assert_eq!(decode_event(&buf).unwrap(), ended);
+
+        let restored = OrderEvent::Restored {
+            id: 6,
+            participant: 3,
+            side: Side::Buy,
+            price: 99,
+            qty: 15,
+            timestamp: Timestamp::from_nanos(10),
+            arrival: 4,
+        };
+        buf.clear();
+        encode_event(&restored, &mut buf);
+        assert_eq!(buf.len(), HEADER_LEN + 37);
+        assert_eq!(decode_event(&buf).unwrap(), restored);
+
+        let settled = OrderEvent::Settled {
+            price: -4,
+            timestamp: Timestamp::from_nanos(11),
+        };
+        buf.clear();
+        encode_event(&settled, &mut buf);
+        assert_eq!(buf.len(), HEADER_LEN + 12);
+        assert_eq!(decode_event(&buf).unwrap(), settled);
+    }
+
+    #[test]
+    fn accepts_longer_blocks_from_newer_versions() {
+        let mut buf = Vec::new();
+        encode_event(&OrderEvent::Canceled { id: 9 }, &mut buf);
+        buf[0] += 4;
+        buf.extend_from_slice(&[0; 4]);
+        assert_eq!(decode_event(&buf).unwrap(), OrderEvent::Canceled { id: 9 });
+    }
+
+    #[test]
+    fn reads_version_1_trades_without_fees() {
+        let trade = Trade {
+            id: 1,
+            price: 122,
+            qty: 4,
+            aggressor_side: Side::Buy,
+            maker_order_id: 2,
+            taker_order_id: 3,
+            maker_participant: 7,
+            taker_participant: 8,
+            timestamp: Timestamp::from_nanos(5),
+            maker_fee: 10,
+            taker_fee: 20,
+            cross: false,
+            dark: false,
+            currency: Currency::USD,
+        };
+        let mut buf = Vec::new();
+        encode_event(&OrderEvent::Trade(trade), &mut buf);
+        buf.truncate(HEADER_LEN + 49);
+        buf[..2].copy_from_slice(&49_u16.to_le_bytes());
+        buf[6..8].copy_from_slice(&1_u16.to_le_bytes());
+        assert_eq!(
+            decode_event(&buf).unwrap(),
+            OrderEvent::Trade(Trade {
+                maker_fee: 0,
+                taker_fee: 0,
+                ..trade
+            })
+        );
+    }
+
+    #[test]
+    fn reads_version_3_placements_without_arrivals() {
+        let placed = |arrival| OrderEvent::Placed {
+            id: 4,
+            participant: 2,
+            side: Side::Buy,
+            order_type: OrderType::GoodTilCancel,
+            price: 99,
+            timestamp: Timestamp::from_nanos(6),
+            arrival,
+        };
+        let mut buf = Vec::new();
+        encode_event(&placed(9), &mut buf);
+        assert_eq!(decode_event(&buf).unwrap(), placed(9));
+        buf.truncate(HEADER_LEN + 26);
+        buf[..2].copy_from_slice(&26_u16.to_le_bytes());
+        buf[6..8].copy_from_slice(&3_u16.to_le_bytes());
+        assert_eq!(decode_event(&buf).unwrap(), placed(0));
+    }
+}
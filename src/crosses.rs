@@ -0,0 +1,122 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Pre-negotiated crosses.
+//!
+//! Blocks too large to work through the book are agreed between two
+//! participants directly and then reported with [`OrderCommand::Cross`].
+//! The book checks the report against its [`CrossRules`] and prints it as
+//! a trade flagged [`Trade::cross`], under the next trade id, without
+//! touching a resting order. The trade goes on the tape and into the
+//! session statistics and both participants' positions like any other, and
+//! pays fees on the book's schedule with the buyer as the taker, but it
+//! does not trip the circuit breaker.
+//!
+//! A cross the rules refuse is rejected with the buyer as the participant.
+//! Either party's kill switch, and a halted book, refuse it too.
+//!
+//! [`OrderCommand::Cross`]: crate::OrderCommand::Cross
+//! [`Trade::cross`]: crate::Trade::cross
+
+use crate::risk::PriceCollar;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CrossRules {
+    /// The block size threshold: a smaller cross is rejected with
+    /// `RejectReason::BelowBlockSize`.
+    pub min_qty: u32,
+    /// How far from the book's reference price a cross may print, rejected
+    /// with `RejectReason::PriceCollar` beyond it. Its action is ignored.
+    pub band: PriceCollar,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CrossRules;
+    use crate::fees::{FeeLedger, FeeRate, FeeSchedule};
+    use crate::risk::{PriceCollar, RejectReason};
+    use crate::sink::publish_events;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+
+    #[test]
+    fn prints_crosses_without_touching_the_book() {
+        let mut order_book = OrderBook::new();
+        order_book.set_cross_rules(CrossRules {
+            min_qty: 1_000,
+            band: PriceCollar {
+                max_deviation_ticks: Some(5),
+                ..PriceCollar::default()
+            },
+        });
+        for (side, price) in [(Side::Buy, 99), (Side::Sell, 101)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty: 10,
+                participant: 1,
+            });
+        }
+        let cross = |price, qty| OrderCommand::Cross {
+            buyer: 2,
+            seller: 3,
+            price,
+            qty,
+        };
+        order_book.process_command(cross(102, 5_000));
+        order_book.process_command(cross(100, 999));
+        order_book.process_command(cross(110, 5_000));
+
+        let trades = order_book.trades();
+        assert_eq!(trades.len(), 1);
+        assert!(trades[0].cross);
+        assert_eq!((trades[0].price, trades[0].qty), (102, 5_000));
+        assert_eq!(
+            (trades[0].taker_participant, trades[0].maker_participant),
+            (2, 3)
+        );
+        assert_eq!(order_book.position(2).net_qty, 5_000);
+        // The resting orders are untouched.
+        assert_eq!(order_book.bbo().bid.unwrap().qty, 10);
+        assert_eq!(order_book.bbo().ask.unwrap().qty, 10);
+
+        let reasons: Vec<RejectReason> = order_book
+            .events()
+            .iter()
+            .filter_map(|event| match event {
+                OrderEvent::Rejected { reason, .. } => Some(*reason),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            reasons,
+            [RejectReason::BelowBlockSize, RejectReason::PriceCollar]
+        );
+    }
+
+    #[test]
+    fn crosses_pay_fees_like_matched_trades() {
+        let mut order_book = OrderBook::new();
+        order_book.set_fee_schedule(FeeSchedule {
+            maker: FeeRate::PerUnit(1),
+            taker: FeeRate::PerUnit(2),
+        });
+        order_book.process_command(OrderCommand::Cross {
+            buyer: 2,
+            seller: 3,
+            price: 100,
+            qty: 5_000,
+        });
+        let trade = order_book.trades()[0];
+        assert_eq!((trade.maker_fee, trade.taker_fee), (5_000, 10_000));
+        assert_eq!(order_book.fees().get(2).taker, 10_000);
+        assert_eq!(order_book.fees().get(3).maker, 5_000);
+
+        let mut rebuilt = FeeLedger::new();
+        publish_events(&mut rebuilt, "", order_book.events()).unwrap();
+        assert_eq!(&rebuilt, order_book.fees());
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Currencies.
+//!
+//! Each book's instrument is priced in one currency, set with
+//! [`OrderBook::set_currency`](crate::OrderBook::set_currency) and USD
+//! unless told otherwise. Its trades are stamped with it, so notional,
+//! fees and PnL are always read in the currency they were struck in:
+//! positions and clearing obligations carry it, and a settlement report
+//! nets each currency separately. Nothing is ever converted.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+
+/// An ISO 4217 code, three upper-case letters.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const USD: Currency = Currency(*b"USD");
+    pub const EUR: Currency = Currency(*b"EUR");
+
+    pub fn new(code: &str) -> io::Result<Currency> {
+        match code.as_bytes() {
+            &[a, b, c] if [a, b, c].iter().all(u8::is_ascii_uppercase) => Ok(Currency([a, b, c])),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid currency {:?}", code),
+            )),
+        }
+    }
+
+    /// The code's bytes, as the binary codecs carry them.
+    pub const fn to_bytes(self) -> [u8; 3] {
+        self.0
+    }
+
+    pub fn from_bytes(bytes: [u8; 3]) -> io::Result<Currency> {
+        Currency::new(&String::from_utf8_lossy(&bytes))
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ever built from ASCII letters.
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency::USD
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = io::Error;
+
+    fn try_from(code: String) -> io::Result<Currency> {
+        Currency::new(&code)
+    }
+}
+
+impl From<Currency> for String {
+    fn from(currency: Currency) -> String {
+        currency.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Currency;
+    use crate::clearing::Clearing;
+    use crate::sink::publish_events;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    #[test]
+    fn books_in_different_currencies_clear_apart() {
+        assert_eq!(Currency::new("EUR").unwrap(), Currency::EUR);
+        for bad in ["eur", "EURO", "E1R", ""] {
+            assert!(Currency::new(bad).is_err());
+        }
+        assert_eq!(serde_json::to_string(&Currency::EUR).unwrap(), "\"EUR\"");
+        assert!(serde_json::from_str::<Currency>("\"usd\"").is_err());
+
+        let mut clearing = Clearing::new();
+        for (symbol, currency) in [("SAP", Currency::EUR), ("IBM", Currency::USD)] {
+            let mut order_book = OrderBook::with_symbol(symbol);
+            order_book.set_currency(currency);
+            for (side, participant) in [(Side::Sell, 1), (Side::Buy, 2)] {
+                order_book.process_command(OrderCommand::New {
+                    order_type: OrderType::GoodTilCancel,
+                    side,
+                    price: 100,
+                    qty: 10,
+                    participant,
+                });
+            }
+            assert_eq!(order_book.trades()[0].currency, currency);
+            assert_eq!(order_book.position(2).currency, currency);
+            publish_events(&mut clearing, symbol, order_book.events()).unwrap();
+        }
+        let report = clearing.report();
+        let buyer: Vec<_> = report
+            .obligations
+            .iter()
+            .filter(|o| o.participant == 2)
+            .map(|o| (o.symbol.as_str(), o.currency, o.cash))
+            .collect();
+        assert_eq!(
+            buyer,
+            [
+                ("IBM", Currency::USD, -1_000),
+                ("SAP", Currency::EUR, -1_000)
+            ]
+        );
+        let cash: Vec<_> = report.cash_by_currency(2).into_iter().collect();
+        assert_eq!(cash, [(Currency::EUR, -1_000), (Currency::USD, -1_000)]);
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Midpoint dark book.
+//!
+//! Alongside the lit book each [`OrderBook`](crate::OrderBook) keeps a
+//! book of non-displayed orders, entered with
+//! [`OrderBook::add_dark_order`](crate::OrderBook::add_dark_order). A dark
+//! order names a side and a size but no price: it executes only against
+//! the opposite side of the dark book, at the midpoint of the lit BBO. It
+//! never shows in depth, snapshots or market data, and nothing is
+//! published for it until it trades; then the trade goes on the tape
+//! flagged [`Trade::dark`](crate::Trade::dark).
+//!
+//! The dark book matches whenever an order is added and after each
+//! command to the lit book, which may have moved the midpoint. It matches
+//! only while the lit book is open with both sides quoted. Orders pair off
+//! in time priority, each fill is at least both orders' minimum size, or
+//! what is left of an order if that's less, and a participant's orders
+//! never trade with each other. A lit spread of an odd number of ticks
+//! puts the midpoint between two ticks. The trade then prints on the tick
+//! that favours the resting order, the earlier of the two.
+
+use crate::{OrderId, ParticipantId, Price, Side, Timestamp};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DarkOrder {
+    /// Drawn from the same sequence as lit order ids.
+    pub id: OrderId,
+    pub participant: ParticipantId,
+    pub side: Side,
+    pub remaining_qty: u32,
+    /// The smallest fill the order accepts.
+    pub min_qty: u32,
+    pub created_at: Timestamp,
+}
+
+impl DarkOrder {
+    fn min_fill(&self) -> u32 {
+        self.min_qty.min(self.remaining_qty)
+    }
+}
+
+/// One pairing of a dark buy and sell, each as it was before the fill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DarkFill {
+    pub buy: DarkOrder,
+    pub sell: DarkOrder,
+    pub qty: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DarkBook {
+    /// Oldest first.
+    buys: Vec<DarkOrder>,
+    sells: Vec<DarkOrder>,
+}
+
+impl DarkBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buys.len() + self.sells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buys.is_empty() && self.sells.is_empty()
+    }
+
+    /// One side's orders, oldest first.
+    pub fn orders(&self, side: Side) -> &[DarkOrder] {
+        match side {
+            Side::Buy => &self.buys,
+            Side::Sell => &self.sells,
+        }
+    }
+
+    pub fn insert(&mut self, order: DarkOrder) {
+        match order.side {
+            Side::Buy => self.buys.push(order),
+            Side::Sell => self.sells.push(order),
+        }
+    }
+
+    pub fn remove(&mut self, id: OrderId) -> Option<DarkOrder> {
+        for side in [&mut self.buys, &mut self.sells] {
+            if let Some(pos) = side.iter().position(|order| order.id == id) {
+                return Some(side.remove(pos));
+            }
+        }
+        None
+    }
+
+    /// Removes all of `participant`'s orders, returning how many there
+    /// were.
+    pub fn remove_participant(&mut self, participant: ParticipantId) -> usize {
+        let before = self.len();
+        self.buys.retain(|order| order.participant != participant);
+        self.sells.retain(|order| order.participant != participant);
+        before - self.len()
+    }
+
+    /// Pairs buys with sells as far as they will go, taking filled orders
+    /// out of the book.
+    pub fn match_orders(&mut self) -> Vec<DarkFill> {
+        let mut fills = Vec::new();
+        for buy in &mut self.buys {
+            for sell in &mut self.sells {
+                if buy.remaining_qty == 0 {
+                    break;
+                }
+                if sell.remaining_qty == 0 || sell.participant == buy.participant {
+                    continue;
+                }
+                let qty = buy.remaining_qty.min(sell.remaining_qty);
+                if qty < buy.min_fill() || qty < sell.min_fill() {
+                    continue;
+                }
+                fills.push(DarkFill {
+                    buy: *buy,
+                    sell: *sell,
+                    qty,
+                });
+                buy.remaining_qty -= qty;
+                sell.remaining_qty -= qty;
+            }
+        }
+        self.buys.retain(|order| order.remaining_qty > 0);
+        self.sells.retain(|order| order.remaining_qty > 0);
+        fills
+    }
+}
+
+/// Where a dark trade between a resting order on `resting` side prints,
+/// given the lit best bid and ask.
+pub fn midpoint<P: Price>(bid: P, ask: P, resting: Side) -> P {
+    let sum = i128::from(bid.to_ticks()) + i128::from(ask.to_ticks());
+    let mid = match resting {
+        // A resting seller gets the upper tick, a resting buyer the lower.
+        Side::Sell => sum.div_euclid(2) + sum.rem_euclid(2),
+        Side::Buy => sum.div_euclid(2),
+    };
+    P::from_ticks(mid as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::midpoint;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    fn quote(order_book: &mut OrderBook, side: Side, price: i32) {
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price,
+            qty: 10,
+            participant: 1,
+        });
+    }
+
+    #[test]
+    fn matches_at_the_lit_midpoint() {
+        let mut order_book = OrderBook::new();
+        let sell = order_book.add_dark_order(2, Side::Sell, 500, 100).unwrap();
+        let small = order_book.add_dark_order(3, Side::Buy, 50, 0).unwrap();
+        let buy = order_book.add_dark_order(4, Side::Buy, 300, 200).unwrap();
+        // Nothing trades until the lit book is quoted on both sides.
+        assert!(order_book.trades().is_empty());
+
+        quote(&mut order_book, Side::Buy, 100);
+        quote(&mut order_book, Side::Sell, 103);
+        // The 50 is below the seller's minimum, so the later 300 trades.
+        let trades = order_book.trades();
+        assert_eq!(trades.len(), 1);
+        let trade = trades[0];
+        assert!(trade.dark);
+        assert_eq!((trade.price, trade.qty), (102, 300));
+        assert_eq!((trade.maker_order_id, trade.taker_order_id), (sell, buy));
+        assert_eq!(trade.aggressor_side, Side::Buy);
+        assert_eq!(
+            order_book.dark_book().orders(Side::Sell)[0].remaining_qty,
+            200
+        );
+        assert_eq!(order_book.dark_book().orders(Side::Buy)[0].id, small);
+        // The lit book is untouched, and shows none of it.
+        assert_eq!(order_book.depth(5).bids[0].qty, 10);
+
+        assert!(order_book.cancel_dark_order(small));
+        assert!(!order_book.cancel_dark_order(small));
+    }
+
+    #[test]
+    fn odd_spreads_favour_the_resting_order() {
+        assert_eq!(midpoint(100, 103, Side::Sell), 102);
+        assert_eq!(midpoint(100, 103, Side::Buy), 101);
+        assert_eq!(midpoint(-3, 0, Side::Buy), -2);
+        assert_eq!(midpoint(100, 102, Side::Sell), 101);
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Maker and taker fees.
+//!
+//! A [`FeeSchedule`] prices each side of a trade as it happens and the
+//! amounts ride on the [`Trade`] itself, so every consumer of the event
+//! stream sees the same figures. Amounts are whole fee units of
+//! 1/[`FEE_SCALE`] of a price unit: a fee of 1 basis point on a notional of
+//! 100 is 100 units. A negative amount is a rebate.
+
+use crate::sink::EventSink;
+use crate::{OrderEvent, ParticipantId, Price, Trade};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+
+/// Fee units per price unit.
+pub const FEE_SCALE: i64 = 10_000;
+
+/// What one side of a trade pays. In JSON: `"Free"`, `{"Bps": 0.5}`,
+/// `{"PerUnit": 20}` or `{"PerTrade": 5000}`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum FeeRate {
+    #[default]
+    Free,
+    /// Basis points of the notional, price times quantity.
+    Bps(f64),
+    /// Fee units per unit of quantity traded.
+    PerUnit(i64),
+    /// Fee units per trade, whatever its size.
+    PerTrade(i64),
+}
+
+impl FeeRate {
+    /// The fee, in fee units, for a trade of `qty` at `price`. A basis point
+    /// of a notional is exactly its notional in fee units, so only
+    /// fractional rates round, to the nearest unit.
+    pub fn fee<P: Price>(&self, price: P, qty: u32) -> i64 {
+        match *self {
+            FeeRate::Free => 0,
+            FeeRate::Bps(bps) => {
+                let notional = price.to_ticks() * i64::from(qty);
+                (notional as f64 * bps).round() as i64
+            }
+            FeeRate::PerUnit(units) => units * i64::from(qty),
+            FeeRate::PerTrade(units) => units,
+        }
+    }
+}
+
+/// The rates for the resting and the incoming side of each trade.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeeSchedule {
+    pub maker: FeeRate,
+    pub taker: FeeRate,
+}
+
+impl FeeSchedule {
+    /// Sets `trade`'s maker and taker fees.
+    pub fn apply<P: Price>(&self, trade: &mut Trade<P>) {
+        trade.maker_fee = self.maker.fee(trade.price, trade.qty);
+        trade.taker_fee = self.taker.fee(trade.price, trade.qty);
+    }
+}
+
+/// One participant's fees for the session.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FeeTotals {
+    /// Paid on trades where their order was resting; negative if rebated.
+    pub maker: i64,
+    /// Paid on trades where their order was the aggressor.
+    pub taker: i64,
+    /// Trades they were on either side of.
+    pub trades: u64,
+}
+
+impl FeeTotals {
+    pub fn net(&self) -> i64 {
+        self.maker + self.taker
+    }
+}
+
+/// Fee totals keyed by participant.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FeeLedger {
+    totals: HashMap<ParticipantId, FeeTotals>,
+}
+
+impl FeeLedger {
+    pub fn new() -> Self {
+        FeeLedger::default()
+    }
+
+    /// Charges both sides of `trade` the fees it carries.
+    pub fn record<P>(&mut self, trade: &Trade<P>) {
+        self.charge(trade, 1);
+    }
+
+    /// Refunds the fees of a busted trade.
+    pub fn bust<P>(&mut self, trade: &Trade<P>) {
+        self.charge(trade, -1);
+    }
+
+    fn charge<P>(&mut self, trade: &Trade<P>, sign: i64) {
+        let maker = self.totals.entry(trade.maker_participant).or_default();
+        maker.maker += sign * trade.maker_fee;
+        maker.trades = maker.trades.saturating_add_signed(sign);
+        let taker = self.totals.entry(trade.taker_participant).or_default();
+        taker.taker += sign * trade.taker_fee;
+        taker.trades = taker.trades.saturating_add_signed(sign);
+    }
+
+    /// The participant's totals, zero if they never traded.
+    pub fn get(&self, participant: ParticipantId) -> FeeTotals {
+        self.totals.get(&participant).copied().unwrap_or_default()
+    }
+
+    /// Every participant's totals, in participant order.
+    pub fn all(&self) -> Vec<(ParticipantId, FeeTotals)> {
+        let mut totals: Vec<(ParticipantId, FeeTotals)> =
+            self.totals.iter().map(|(&p, &t)| (p, t)).collect();
+        totals.sort_by_key(|&(participant, _)| participant);
+        totals
+    }
+
+    /// Net fees collected from everyone, after rebates.
+    pub fn collected(&self) -> i64 {
+        self.totals.values().map(FeeTotals::net).sum()
+    }
+}
+
+impl EventSink for FeeLedger {
+    fn publish(&mut self, _symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        match event {
+            OrderEvent::Trade(trade) => self.record(trade),
+            OrderEvent::TradeBust { trade, .. } => self.bust(trade),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FeeLedger, FeeRate, FeeSchedule, FeeTotals};
+    use crate::sink::publish_events;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+
+    #[test]
+    fn rates() {
+        assert_eq!(FeeRate::Free.fee(100, 5), 0);
+        assert_eq!(FeeRate::Bps(2.0).fee(100, 5), 1_000);
+        assert_eq!(FeeRate::Bps(-0.25).fee(101, 3), -76);
+        assert_eq!(FeeRate::PerUnit(30).fee(100, 5), 150);
+        assert_eq!(FeeRate::PerTrade(5_000).fee(100, 5), 5_000);
+
+        let schedule: FeeSchedule =
+            serde_json::from_str(r#"{"maker": {"Bps": -0.5}, "taker": {"PerUnit": 20}}"#).unwrap();
+        assert_eq!(
+            schedule,
+            FeeSchedule {
+                maker: FeeRate::Bps(-0.5),
+                taker: FeeRate::PerUnit(20)
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<FeeSchedule>("{}").unwrap(),
+            FeeSchedule::default()
+        );
+    }
+
+    #[test]
+    fn charges_each_trade_and_refunds_busts() {
+        let mut order_book = OrderBook::new();
+        order_book.set_fee_schedule(FeeSchedule {
+            maker: FeeRate::Bps(-1.0),
+            taker: FeeRate::Bps(3.0),
+        });
+        for (side, qty, participant) in [(Side::Sell, 5, 1), (Side::Buy, 2, 2), (Side::Buy, 3, 3)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price: 100,
+                qty,
+                participant,
+            });
+        }
+        let trade = order_book.trades()[0];
+        assert_eq!((trade.maker_fee, trade.taker_fee), (-200, 600));
+        assert!(order_book
+            .events()
+            .contains(&OrderEvent::Trade(order_book.trades()[1])));
+
+        let fees = order_book.fees();
+        assert_eq!(
+            fees.get(1),
+            FeeTotals {
+                maker: -500,
+                taker: 0,
+                trades: 2
+            }
+        );
+        assert_eq!(fees.get(3).net(), 900);
+        assert_eq!(fees.collected(), 1_000);
+        assert_eq!(fees.all().len(), 3);
+
+        let mut rebuilt = FeeLedger::new();
+        publish_events(&mut rebuilt, "", order_book.events()).unwrap();
+        assert_eq!(&rebuilt, order_book.fees());
+
+        order_book.process_command(OrderCommand::BustTrade {
+            trade_id: trade.id,
+            restore: false,
+        });
+        assert_eq!(order_book.fees().get(2), FeeTotals::default());
+        assert_eq!(order_book.fees().get(1).trades, 1);
+    }
+}
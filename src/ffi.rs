@@ -0,0 +1,571 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! C ABI, declared in `include/matcher.h`.
+//!
+//! A C caller creates a book, submits commands as [`MatcherCommand`] structs
+//! and polls for the events they produced as [`MatcherEvent`]s. Each event
+//! is handed out once, oldest first. Sides, order types, statuses and reject
+//! reasons use the same codes as the SBE schema.
+//!
+//! Books are not thread safe: a caller sharing one between threads must
+//! serialize calls on it.
+//!
+//! [`matcher_book_new`] creates an [`OrderBook`]. Rust code can hand C any
+//! other [`OrderBookBackend`] with [`MatcherBook::into_raw`].
+
+use crate::backend::OrderBookBackend;
+use crate::circuit_breaker::TradingStatus;
+use crate::risk::protection::ProtectionTrigger;
+use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side, Trade};
+use std::ffi::{c_char, CStr};
+
+pub const MATCHER_OK: i32 = 0;
+/// A null pointer, or a command with an unknown kind or code.
+pub const MATCHER_INVALID: i32 = -1;
+
+pub const MATCHER_NEW: u8 = 0;
+pub const MATCHER_MODIFY: u8 = 1;
+pub const MATCHER_CANCEL: u8 = 2;
+pub const MATCHER_KILL_SWITCH: u8 = 3;
+pub const MATCHER_HALT: u8 = 4;
+pub const MATCHER_RESUME: u8 = 5;
+pub const MATCHER_BUST_TRADE: u8 = 6;
+
+pub const MATCHER_PLACED: u8 = 0;
+pub const MATCHER_MODIFIED: u8 = 1;
+pub const MATCHER_CANCELED: u8 = 2;
+pub const MATCHER_PARTIALLY_FILLED: u8 = 3;
+pub const MATCHER_FILLED: u8 = 4;
+pub const MATCHER_TRADE: u8 = 5;
+pub const MATCHER_REJECTED: u8 = 6;
+pub const MATCHER_KILL_SWITCH_CHANGED: u8 = 7;
+pub const MATCHER_STATUS_CHANGED: u8 = 8;
+pub const MATCHER_TRADE_BUST: u8 = 9;
+pub const MATCHER_QUOTED: u8 = 10;
+pub const MATCHER_PROTECTION_TRIGGERED: u8 = 11;
+pub const MATCHER_SESSION_ENDED: u8 = 12;
+pub const MATCHER_RESTORED: u8 = 13;
+pub const MATCHER_SETTLED: u8 = 14;
+
+/// A book and how far its events have been polled.
+pub struct MatcherBook {
+    order_book: Box<dyn OrderBookBackend>,
+    polled: usize,
+}
+
+impl MatcherBook {
+    /// Wraps `order_book` for C callers, to be used and freed like a book
+    /// from [`matcher_book_new`].
+    pub fn into_raw(order_book: impl OrderBookBackend + 'static) -> *mut MatcherBook {
+        Box::into_raw(Box::new(MatcherBook {
+            order_book: Box::new(order_book),
+            polled: 0,
+        }))
+    }
+}
+
+/// One command. Fields a kind doesn't use are ignored.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatcherCommand {
+    pub kind: u8,
+    pub side: u8,
+    pub order_type: u8,
+    /// `engage` for kill switches, `auction` for resumes and `restore` for
+    /// trade busts.
+    pub flag: u8,
+    pub participant: u32,
+    pub price: i32,
+    pub qty: u32,
+    /// The order for modifies and cancels, the trade for busts.
+    pub id: u64,
+}
+
+/// One event. Fields a kind doesn't use are zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatcherEvent {
+    pub kind: u8,
+    pub side: u8,
+    pub order_type: u8,
+    /// Kill switch engaged, trade bust restored, or the new trading status.
+    /// For trades, 1 for a cross and 2 for a dark trade.
+    pub flag: u8,
+    /// Reject reason; zero for other kinds.
+    pub reason: u8,
+    pub participant: u32,
+    pub price: i32,
+    pub qty: u32,
+    /// The order, or the trade for trades and busts.
+    pub id: u64,
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
+    pub maker_participant: u32,
+    pub taker_participant: u32,
+    /// Nanoseconds since the Unix epoch, where the event has a time.
+    pub timestamp: u64,
+    /// Trade fees in units of 1/10000 of a price unit.
+    pub maker_fee: i64,
+    pub taker_fee: i64,
+    /// The order's arrival sequence, for placements and quotes.
+    pub arrival: u64,
+}
+
+/// Creates a book for `symbol`, which may be null. Free it with
+/// [`matcher_book_free`].
+///
+/// # Safety
+///
+/// `symbol` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn matcher_book_new(symbol: *const c_char) -> *mut MatcherBook {
+    let symbol = match symbol.is_null() {
+        true => String::new(),
+        false => CStr::from_ptr(symbol).to_string_lossy().into_owned(),
+    };
+    MatcherBook::into_raw(OrderBook::with_symbol(symbol))
+}
+
+/// # Safety
+///
+/// `book` must be null or come from [`matcher_book_new`] or
+/// [`MatcherBook::into_raw`], and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn matcher_book_free(book: *mut MatcherBook) {
+    if !book.is_null() {
+        drop(Box::from_raw(book));
+    }
+}
+
+/// Processes `command`, returning [`MATCHER_OK`] or [`MATCHER_INVALID`].
+///
+/// # Safety
+///
+/// `book` must come from [`matcher_book_new`] and `command` must be null or
+/// point to a command.
+#[no_mangle]
+pub unsafe extern "C" fn matcher_submit(
+    book: *mut MatcherBook,
+    command: *const MatcherCommand,
+) -> i32 {
+    let (Some(book), Some(command)) = (book.as_mut(), command.as_ref()) else {
+        return MATCHER_INVALID;
+    };
+    match to_command(command) {
+        Some(command) => {
+            book.order_book.submit(command);
+            MATCHER_OK
+        }
+        None => MATCHER_INVALID,
+    }
+}
+
+/// Copies up to `capacity` events not yet polled into `events`, returning
+/// how many. Call again until it returns less than `capacity` to drain them.
+///
+/// # Safety
+///
+/// `book` must come from [`matcher_book_new`] and `events` must have room
+/// for `capacity` events.
+#[no_mangle]
+pub unsafe extern "C" fn matcher_poll_events(
+    book: *mut MatcherBook,
+    events: *mut MatcherEvent,
+    capacity: usize,
+) -> usize {
+    let Some(book) = book.as_mut() else {
+        return 0;
+    };
+    if events.is_null() {
+        return 0;
+    }
+    let pending = &book.order_book.events()[book.polled..];
+    let count = pending.len().min(capacity);
+    for (i, event) in pending[..count].iter().enumerate() {
+        events.add(i).write(to_event(event));
+    }
+    book.polled += count;
+    count
+}
+
+/// Writes the best bid to `price` and returns 1, or returns 0 if there are
+/// no bids.
+///
+/// # Safety
+///
+/// `book` must come from [`matcher_book_new`] and `price` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn matcher_best_bid(book: *const MatcherBook, price: *mut i32) -> i32 {
+    best(book, price, Side::Buy)
+}
+
+/// As [`matcher_best_bid`], for the best ask.
+///
+/// # Safety
+///
+/// As for [`matcher_best_bid`].
+#[no_mangle]
+pub unsafe extern "C" fn matcher_best_ask(book: *const MatcherBook, price: *mut i32) -> i32 {
+    best(book, price, Side::Sell)
+}
+
+unsafe fn best(book: *const MatcherBook, price: *mut i32, side: Side) -> i32 {
+    let best = book.as_ref().and_then(|book| {
+        let depth = book.order_book.depth(1);
+        let levels = match side {
+            Side::Buy => depth.bids,
+            Side::Sell => depth.asks,
+        };
+        levels.first().map(|level| level.price)
+    });
+    match (best, price.is_null()) {
+        (Some(best), false) => {
+            price.write(best);
+            1
+        }
+        _ => 0,
+    }
+}
+
+fn to_command(command: &MatcherCommand) -> Option<OrderCommand> {
+    let side = || match command.side {
+        0 => Some(Side::Buy),
+        1 => Some(Side::Sell),
+        _ => None,
+    };
+    let order_type = || match command.order_type {
+        0 => Some(OrderType::FillAndKill),
+        1 => Some(OrderType::GoodTilCancel),
+        2 => Some(OrderType::Day),
+        _ => None,
+    };
+    Some(match command.kind {
+        MATCHER_NEW => OrderCommand::New {
+            order_type: order_type()?,
+            side: side()?,
+            price: command.price,
+            qty: command.qty,
+            participant: command.participant,
+        },
+        MATCHER_MODIFY => OrderCommand::Modify {
+            id: command.id,
+            price: command.price,
+            side: side()?,
+            qty: command.qty,
+            order_type: order_type()?,
+        },
+        MATCHER_CANCEL => OrderCommand::Cancel {
+            id: command.id,
+            side: side()?,
+            price: command.price,
+        },
+        MATCHER_KILL_SWITCH => OrderCommand::KillSwitch {
+            participant: command.participant,
+            engage: command.flag != 0,
+        },
+        MATCHER_HALT => OrderCommand::Halt,
+        MATCHER_RESUME => OrderCommand::Resume {
+            auction: command.flag != 0,
+        },
+        MATCHER_BUST_TRADE => OrderCommand::BustTrade {
+            trade_id: command.id as usize,
+            restore: command.flag != 0,
+        },
+        _ => return None,
+    })
+}
+
+fn to_event(event: &OrderEvent) -> MatcherEvent {
+    let side = |side: Side| match side {
+        Side::Buy => 0,
+        Side::Sell => 1,
+    };
+    let trade_event = |kind, trade: &Trade, flag| MatcherEvent {
+        kind,
+        side: side(trade.aggressor_side),
+        flag,
+        price: trade.price,
+        qty: trade.qty,
+        id: trade.id as u64,
+        maker_order_id: trade.maker_order_id,
+        taker_order_id: trade.taker_order_id,
+        maker_participant: trade.maker_participant,
+        taker_participant: trade.taker_participant,
+        timestamp: trade.timestamp.as_nanos(),
+        maker_fee: trade.maker_fee,
+        taker_fee: trade.taker_fee,
+        ..MatcherEvent::default()
+    };
+    match *event {
+        OrderEvent::Placed {
+            id,
+            participant,
+            side: s,
+            order_type,
+            price,
+            timestamp,
+            arrival,
+        } => MatcherEvent {
+            kind: MATCHER_PLACED,
+            side: side(s),
+            order_type: match order_type {
+                OrderType::FillAndKill => 0,
+                OrderType::GoodTilCancel => 1,
+                OrderType::Day => 2,
+            },
+            participant,
+            price,
+            id,
+            timestamp: timestamp.as_nanos(),
+            arrival,
+            ..MatcherEvent::default()
+        },
+        OrderEvent::Modified => MatcherEvent {
+            kind: MATCHER_MODIFIED,
+            ..MatcherEvent::default()
+        },
+        OrderEvent::Canceled { id } => MatcherEvent {
+            kind: MATCHER_CANCELED,
+            id,
+            ..MatcherEvent::default()
+        },
+        OrderEvent::PartiallyFilled {
+            id,
+            price,
+            qty,
+            timestamp,
+        } => MatcherEvent {
+            kind: MATCHER_PARTIALLY_FILLED,
+            price,
+            qty,
+            id,
+            timestamp: timestamp.as_nanos(),
+            ..MatcherEvent::default()
+        },
+        OrderEvent::Filled {
+            id,
+            price,
+            timestamp,
+        } => MatcherEvent {
+            kind: MATCHER_FILLED,
+            price,
+            id,
+            timestamp: timestamp.as_nanos(),
+            ..MatcherEvent::default()
+        },
+        OrderEvent::Trade(ref trade) => trade_event(
+            MATCHER_TRADE,
+            trade,
+            u8::from(trade.cross) | u8::from(trade.dark) << 1,
+        ),
+        OrderEvent::Rejected {
+            participant,
+            side: s,
+            price,
+            qty,
+            reason,
+        } => MatcherEvent {
+            kind: MATCHER_REJECTED,
+            side: side(s),
+            reason: reason.code(),
+            participant,
+            price,
+            qty,
+            ..MatcherEvent::default()
+        },
+        OrderEvent::KillSwitch {
+            participant,
+            engaged,
+        } => MatcherEvent {
+            kind: MATCHER_KILL_SWITCH_CHANGED,
+            flag: u8::from(engaged),
+            participant,
+            ..MatcherEvent::default()
+        },
+        OrderEvent::StatusChanged { status } => MatcherEvent {
+            kind: MATCHER_STATUS_CHANGED,
+            flag: match status {
+                TradingStatus::Open => 0,
+                TradingStatus::Halted => 1,
+            },
+            ..MatcherEvent::default()
+        },
+        OrderEvent::TradeBust {
+            ref trade,
+            restored,
+        } => trade_event(MATCHER_TRADE_BUST, trade, u8::from(restored)),
+        OrderEvent::Quoted {
+            id,
+            participant,
+            side: s,
+            price,
+            qty,
+            timestamp,
+            arrival,
+        } => MatcherEvent {
+            kind: MATCHER_QUOTED,
+            side: side(s),
+            participant,
+            price,
+            qty,
+            id,
+            timestamp: timestamp.as_nanos(),
+            arrival,
+            ..MatcherEvent::default()
+        },
+        OrderEvent::ProtectionTriggered {
+            participant,
+            trigger,
+            timestamp,
+        } => MatcherEvent {
+            kind: MATCHER_PROTECTION_TRIGGERED,
+            flag: match trigger {
+                ProtectionTrigger::Fills => 0,
+                ProtectionTrigger::Quantity => 1,
+                ProtectionTrigger::Delta => 2,
+            },
+            participant,
+            timestamp: timestamp.as_nanos(),
+            ..MatcherEvent::default()
+        },
+        OrderEvent::Settled { price, timestamp } => MatcherEvent {
+            kind: MATCHER_SETTLED,
+            price,
+            timestamp: timestamp.as_nanos(),
+            ..MatcherEvent::default()
+        },
+        OrderEvent::SessionEnded { timestamp } => MatcherEvent {
+            kind: MATCHER_SESSION_ENDED,
+            timestamp: timestamp.as_nanos(),
+            ..MatcherEvent::default()
+        },
+        OrderEvent::Restored {
+            id,
+            participant,
+            side: s,
+            price,
+            qty,
+            timestamp,
+            arrival,
+        } => MatcherEvent {
+            kind: MATCHER_RESTORED,
+            side: side(s),
+            participant,
+            price,
+            qty,
+            id,
+            timestamp: timestamp.as_nanos(),
+            arrival,
+            ..MatcherEvent::default()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::risk::RiskLimits;
+    use std::ptr;
+
+    fn new_order(side: u8, price: i32, qty: u32, participant: u32) -> MatcherCommand {
+        MatcherCommand {
+            kind: MATCHER_NEW,
+            side,
+            order_type: 1,
+            participant,
+            price,
+            qty,
+            ..MatcherCommand::default()
+        }
+    }
+
+    #[test]
+    fn submits_and_polls_through_the_c_abi() {
+        unsafe {
+            let named = matcher_book_new(c"ABC".as_ptr());
+            assert_eq!((*named).order_book.symbol(), "ABC");
+            matcher_book_free(named);
+
+            let mut order_book = OrderBook::with_symbol("ABC");
+            order_book.risk_mut().set_default(RiskLimits {
+                max_order_qty: Some(100),
+                ..RiskLimits::default()
+            });
+            let book = MatcherBook::into_raw(order_book);
+            assert_eq!(matcher_submit(book, &new_order(1, 100, 5, 1)), MATCHER_OK);
+            assert_eq!(matcher_submit(book, &new_order(0, 100, 3, 2)), MATCHER_OK);
+            assert_eq!(matcher_submit(book, &new_order(0, 100, 500, 2)), MATCHER_OK);
+            let unknown = MatcherCommand {
+                kind: 42,
+                ..MatcherCommand::default()
+            };
+            assert_eq!(matcher_submit(book, &unknown), MATCHER_INVALID);
+            assert_eq!(matcher_submit(book, ptr::null()), MATCHER_INVALID);
+
+            let mut events = [MatcherEvent::default(); 4];
+            assert_eq!(matcher_poll_events(book, events.as_mut_ptr(), 4), 4);
+            let kinds = events.map(|event| event.kind);
+            assert_eq!(
+                kinds,
+                [
+                    MATCHER_PLACED,
+                    MATCHER_PLACED,
+                    MATCHER_TRADE,
+                    MATCHER_PARTIALLY_FILLED
+                ]
+            );
+            let trade = events[2];
+            assert_eq!((trade.price, trade.qty), (100, 3));
+            assert_eq!((trade.maker_order_id, trade.taker_order_id), (1, 2));
+            assert_eq!((trade.maker_participant, trade.taker_participant), (1, 2));
+            assert!(trade.timestamp > 0);
+
+            assert_eq!(matcher_poll_events(book, events.as_mut_ptr(), 4), 2);
+            assert_eq!(events[0].kind, MATCHER_FILLED);
+            assert_eq!(events[1].kind, MATCHER_REJECTED);
+            assert_eq!(events[1].reason, 1);
+            assert_eq!(matcher_poll_events(book, events.as_mut_ptr(), 4), 0);
+
+            let mut price = 0;
+            assert_eq!(matcher_best_ask(book, &mut price), 1);
+            assert_eq!(price, 100);
+            assert_eq!(matcher_best_bid(book, &mut price), 0);
+            matcher_book_free(book);
+        }
+    }
+
+    #[test]
+    fn header_declares_every_export() {
+        let header = include_str!("../include/matcher.h");
+        let source = include_str!("ffi.rs");
+        let exports = source
+            .lines()
+            .filter_map(|line| line.strip_prefix("pub unsafe extern \"C\" fn "))
+            .map(|rest| &rest[..rest.find('(').unwrap()]);
+        for name in exports {
+            assert!(header.contains(&format!("{}(", name)), "{} missing", name);
+        }
+        for (name, value) in [
+            ("MATCHER_INVALID", MATCHER_INVALID as i64),
+            ("MATCHER_BUST_TRADE", MATCHER_BUST_TRADE.into()),
+            ("MATCHER_TRADE_BUST", MATCHER_TRADE_BUST.into()),
+            ("MATCHER_QUOTED", MATCHER_QUOTED.into()),
+            (
+                "MATCHER_PROTECTION_TRIGGERED",
+                MATCHER_PROTECTION_TRIGGERED.into(),
+            ),
+            ("MATCHER_SESSION_ENDED", MATCHER_SESSION_ENDED.into()),
+            ("MATCHER_RESTORED", MATCHER_RESTORED.into()),
+            ("MATCHER_SETTLED", MATCHER_SETTLED.into()),
+        ] {
+            assert!(
+                header.contains(&format!("#define {} {}", name, value))
+                    || header.contains(&format!("#define {} ({})", name, value)),
+                "{} does not match",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,1013 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Client sessions in front of the book.
+//!
+//! A [`Gateway`] tracks who is connected and on whose behalf. Commands
+//! entering through a session are stamped with the session's participant, and
+//! a session that disconnects or stops heartbeating can take its
+//! participant's resting orders down with it.
+//!
+//! The session layer borrows its shape from FIX. Each participant has at most
+//! one live session. Inbound messages carry a per-session sequence number and
+//! must arrive in order; a gap is answered with a `ResendRequest` and the
+//! out-of-order message is dropped. Outbound messages are numbered per
+//! participant and kept in a [`Journal`], so a client that reconnects can
+//! recover the execution reports it missed with its own `ResendRequest`.
+//!
+//! Orders entered as [`Inbound::ClientOrder`] are named by the client; see
+//! [`ClientOrderIds`].
+
+use crate::backend::OrderBookBackend;
+use crate::risk::RejectReason;
+use crate::sink::drop_copy::{DropCopy, DropCopyRecord};
+use crate::sink::EventSink;
+use crate::{OrderCommand, OrderEvent, ParticipantId};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+pub mod client_orders;
+pub mod journal;
+
+pub use client_orders::ClientOrderIds;
+pub use journal::Journal;
+
+pub type SessionId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub heartbeat_interval_ms: u64,
+    /// Heartbeat intervals that may pass in silence before the session is
+    /// considered lost.
+    pub missed_heartbeats: u32,
+    pub cancel_on_disconnect: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            heartbeat_interval_ms: 1_000,
+            missed_heartbeats: 3,
+            cancel_on_disconnect: true,
+        }
+    }
+}
+
+impl SessionConfig {
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_millis(self.heartbeat_interval_ms)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.heartbeat_interval() * self.missed_heartbeats
+    }
+}
+
+/// Messages a client sends to the gateway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inbound {
+    Heartbeat {
+        test_request_id: Option<String>,
+    },
+    TestRequest {
+        id: String,
+    },
+    /// Asks for outbound messages `begin..=end` again; `end == 0` means up
+    /// to the latest.
+    ResendRequest {
+        begin: u64,
+        end: u64,
+    },
+    Command(OrderCommand),
+    /// A command naming its order by client order id. On a new order
+    /// `cl_ord_id` names the order being entered; on a modify or cancel it
+    /// names the order to change, and the command's own `id` is ignored.
+    ClientOrder {
+        cl_ord_id: String,
+        command: OrderCommand,
+    },
+}
+
+/// Messages the gateway sends to a client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outbound {
+    Heartbeat {
+        test_request_id: Option<String>,
+    },
+    TestRequest {
+        id: String,
+    },
+    ResendRequest {
+        begin: u64,
+        end: u64,
+    },
+    /// Tells the client the next message will carry `new_seq`; sent in place
+    /// of session-level messages during a resend.
+    SequenceReset {
+        new_seq: u64,
+    },
+    ExecutionReport {
+        symbol: String,
+        event: OrderEvent,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequenced<T> {
+    pub seq: u64,
+    /// Set on messages replayed in answer to a resend request.
+    pub possible_duplicate: bool,
+    pub message: T,
+}
+
+impl<T> Sequenced<T> {
+    pub fn new(seq: u64, message: T) -> Self {
+        Sequenced {
+            seq,
+            possible_duplicate: false,
+            message,
+        }
+    }
+
+    pub fn resent(seq: u64, message: T) -> Self {
+        Sequenced {
+            seq,
+            possible_duplicate: true,
+            message,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub id: SessionId,
+    pub participant: ParticipantId,
+    pub config: SessionConfig,
+    pub last_seen: Instant,
+    pub last_sent: Instant,
+    /// Sequence number expected on the next inbound message.
+    pub next_inbound_seq: u64,
+    test_request_pending: bool,
+    outbox: Vec<Sequenced<Outbound>>,
+}
+
+/// Why a session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Logout,
+    HeartbeatTimeout,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disconnect {
+    pub session: Session,
+    pub reason: DisconnectReason,
+    /// Resting orders canceled on the session's behalf.
+    pub canceled: usize,
+}
+
+pub struct Gateway {
+    sessions: HashMap<SessionId, Session>,
+    next_session_id: SessionId,
+    next_test_request: u64,
+    journal: Journal,
+    client_orders: ClientOrderIds,
+    reports: DropCopy<Vec<DropCopyRecord>>,
+    events_routed: usize,
+}
+
+impl Default for Gateway {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gateway {
+    pub fn new() -> Gateway {
+        Gateway {
+            sessions: HashMap::new(),
+            next_session_id: 0,
+            next_test_request: 0,
+            journal: Journal::new(),
+            client_orders: ClientOrderIds::new(),
+            reports: DropCopy::new(Vec::new()),
+            events_routed: 0,
+        }
+    }
+
+    /// Opens a session for `participant`, failing if it already has one.
+    pub fn logon(
+        &mut self,
+        participant: ParticipantId,
+        config: SessionConfig,
+        now: Instant,
+    ) -> io::Result<SessionId> {
+        if self.session_for(participant).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("participant {} already has a session", participant),
+            ));
+        }
+        self.next_session_id += 1;
+        let id = self.next_session_id;
+        self.sessions.insert(
+            id,
+            Session {
+                id,
+                participant,
+                config,
+                last_seen: now,
+                last_sent: now,
+                next_inbound_seq: 1,
+                test_request_pending: false,
+                outbox: Vec::new(),
+            },
+        );
+        tracing::info!("session {} logged on for participant {}", id, participant);
+        Ok(id)
+    }
+
+    pub fn session(&self, id: SessionId) -> Option<&Session> {
+        self.sessions.get(&id)
+    }
+
+    pub fn sessions(&self) -> impl Iterator<Item = &Session> {
+        self.sessions.values()
+    }
+
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Takes the messages waiting to be written to session `id`.
+    pub fn drain(&mut self, id: SessionId) -> Vec<Sequenced<Outbound>> {
+        self.sessions
+            .get_mut(&id)
+            .map(|session| std::mem::take(&mut session.outbox))
+            .unwrap_or_default()
+    }
+
+    /// Records liveness for `id`. Any inbound message counts, so
+    /// [`Self::receive`] and [`Self::submit`] call this too.
+    pub fn heartbeat(&mut self, id: SessionId, now: Instant) -> io::Result<()> {
+        let session = self.sessions.get_mut(&id).ok_or_else(|| unknown(id))?;
+        session.last_seen = now;
+        session.test_request_pending = false;
+        Ok(())
+    }
+
+    /// Handles one sequenced message from session `id`.
+    ///
+    /// A sequence number below the expected one is ignored on a possible
+    /// duplicate and otherwise means the client has lost track of the
+    /// session; the error tells the caller to disconnect.
+    pub fn receive(
+        &mut self,
+        order_book: &mut impl OrderBookBackend,
+        id: SessionId,
+        inbound: Sequenced<Inbound>,
+        now: Instant,
+    ) -> io::Result<()> {
+        self.heartbeat(id, now)?;
+        let session = &self.sessions[&id];
+        let expected = session.next_inbound_seq;
+        if inbound.seq < expected {
+            if inbound.possible_duplicate {
+                return Ok(());
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "session {} sequence {} below expected {}",
+                    id, inbound.seq, expected
+                ),
+            ));
+        }
+        if inbound.seq > expected {
+            let participant = session.participant;
+            let request = Outbound::ResendRequest {
+                begin: expected,
+                end: inbound.seq - 1,
+            };
+            self.send(participant, request, now);
+            return Ok(());
+        }
+        self.sessions.get_mut(&id).unwrap().next_inbound_seq += 1;
+
+        match inbound.message {
+            Inbound::Heartbeat { .. } => {}
+            Inbound::TestRequest {
+                id: test_request_id,
+            } => {
+                let participant = self.sessions[&id].participant;
+                let reply = Outbound::Heartbeat {
+                    test_request_id: Some(test_request_id),
+                };
+                self.send(participant, reply, now);
+            }
+            Inbound::ResendRequest { begin, end } => {
+                let session = self.sessions.get_mut(&id).unwrap();
+                let replay = self.journal.resend(session.participant, begin, end);
+                session.outbox.extend(replay);
+                session.last_sent = now;
+            }
+            Inbound::Command(command) => self.submit(order_book, id, command, now)?,
+            Inbound::ClientOrder { cl_ord_id, command } => {
+                self.submit_client_order(order_book, id, &cl_ord_id, command, now)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Forwards `command` to `order_book` on behalf of session `id`. New
+    /// orders are attributed to the session's participant regardless of what
+    /// the client put in the command, and so are mass quotes. A modify or
+    /// cancel must name a resting order of the session's participant, and a
+    /// cross is only taken from a session that is one of its two parties.
+    /// Administrative commands are refused.
+    pub fn submit(
+        &mut self,
+        order_book: &mut impl OrderBookBackend,
+        id: SessionId,
+        mut command: OrderCommand,
+        now: Instant,
+    ) -> io::Result<()> {
+        self.heartbeat(id, now)?;
+        match &mut command {
+            OrderCommand::New { participant, .. } | OrderCommand::MassQuote { participant, .. } => {
+                *participant = self.sessions[&id].participant;
+            }
+            OrderCommand::KillSwitch { .. }
+            | OrderCommand::Halt
+            | OrderCommand::Resume { .. }
+            | OrderCommand::BustTrade { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "administrative command from client session",
+                ));
+            }
+            OrderCommand::Cross { buyer, seller, .. } => {
+                let participant = self.sessions[&id].participant;
+                if participant != *buyer && participant != *seller {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "cross between other participants",
+                    ));
+                }
+            }
+            OrderCommand::Modify { id: order_id, .. }
+            | OrderCommand::Cancel { id: order_id, .. } => {
+                let participant = self.sessions[&id].participant;
+                if !order_book.owns(participant, *order_id) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "order is not the session's",
+                    ));
+                }
+            }
+        }
+        order_book.submit(command);
+        self.route(order_book, now)
+    }
+
+    /// Like [`Self::submit`], for a command naming its order by client order
+    /// id. A new order reusing the id of a live one, or a modify or cancel
+    /// naming no live order, is rejected without reaching the book.
+    pub fn submit_client_order(
+        &mut self,
+        order_book: &mut impl OrderBookBackend,
+        id: SessionId,
+        cl_ord_id: &str,
+        mut command: OrderCommand,
+        now: Instant,
+    ) -> io::Result<()> {
+        self.heartbeat(id, now)?;
+        let participant = self.sessions[&id].participant;
+        let live = self.client_orders.live(order_book, participant, cl_ord_id);
+        let rejected = match (&mut command, live) {
+            (
+                OrderCommand::New {
+                    side, price, qty, ..
+                },
+                Some(_),
+            ) => Some((*side, *price, *qty, RejectReason::DuplicateClientOrderId)),
+            (
+                OrderCommand::Modify {
+                    side, price, qty, ..
+                },
+                None,
+            ) => Some((*side, *price, *qty, RejectReason::UnknownClientOrderId)),
+            (OrderCommand::Cancel { side, price, .. }, None) => {
+                Some((*side, *price, 0, RejectReason::UnknownClientOrderId))
+            }
+            (OrderCommand::Modify { id, .. }, Some(order_id)) => {
+                *id = order_id;
+                None
+            }
+            (OrderCommand::Cancel { id, side, price }, Some(order_id)) => {
+                // The book finds orders by side and price as well as id.
+                let order = order_book.find_order(order_id).unwrap();
+                (*id, *side, *price) = (order_id, order.side, order.price);
+                None
+            }
+            (OrderCommand::New { .. }, None)
+            | (OrderCommand::MassQuote { .. } | OrderCommand::Cross { .. }, _)
+            | (
+                OrderCommand::KillSwitch { .. }
+                | OrderCommand::Halt
+                | OrderCommand::Resume { .. }
+                | OrderCommand::BustTrade { .. },
+                _,
+            ) => None,
+        };
+        if let Some((side, price, qty, reason)) = rejected {
+            let report = Outbound::ExecutionReport {
+                symbol: order_book.symbol().to_string(),
+                event: OrderEvent::Rejected {
+                    participant,
+                    side,
+                    price,
+                    qty,
+                    reason,
+                },
+            };
+            self.send(participant, report, now);
+            return Ok(());
+        }
+
+        let replacing = match command {
+            OrderCommand::Modify { id, .. } => Some(id),
+            _ => None,
+        };
+        let seen = order_book.events().len();
+        self.submit(order_book, id, command, now)?;
+        // New orders and modifies both end in a freshly placed order.
+        let placed = order_book.events()[seen..]
+            .iter()
+            .find_map(|event| match *event {
+                OrderEvent::Placed {
+                    id, participant: p, ..
+                } if p == participant => Some(id),
+                _ => None,
+            });
+        match (placed, replacing) {
+            (Some(order_id), Some(replaced)) => {
+                self.client_orders
+                    .replace(participant, cl_ord_id, replaced, order_id);
+            }
+            (Some(order_id), None) => self.client_orders.assign(participant, cl_ord_id, order_id),
+            (None, _) => {}
+        }
+        Ok(())
+    }
+
+    /// The client order ids of orders entered through the gateway, and the
+    /// book ids their modifies replaced.
+    pub fn client_orders(&self) -> &ClientOrderIds {
+        &self.client_orders
+    }
+
+    /// Ends session `id` at the client's request, or because its transport
+    /// went away.
+    pub fn disconnect(
+        &mut self,
+        order_book: &mut impl OrderBookBackend,
+        id: SessionId,
+        now: Instant,
+    ) -> io::Result<Disconnect> {
+        let session = self.sessions.remove(&id).ok_or_else(|| unknown(id))?;
+        let disconnect = close(order_book, session, DisconnectReason::Logout);
+        self.route(order_book, now)?;
+        Ok(disconnect)
+    }
+
+    /// Sends heartbeats on idle sessions, probes quiet ones with a test
+    /// request and drops any that stay silent past their timeout.
+    pub fn tick(
+        &mut self,
+        order_book: &mut impl OrderBookBackend,
+        now: Instant,
+    ) -> io::Result<Vec<Disconnect>> {
+        let mut ids: Vec<SessionId> = self.sessions.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let session = &self.sessions[&id];
+            let interval = session.config.heartbeat_interval();
+            let participant = session.participant;
+            if !session.test_request_pending
+                && now.saturating_duration_since(session.last_seen) >= interval
+            {
+                self.next_test_request += 1;
+                let request = Outbound::TestRequest {
+                    id: format!("TEST{}", self.next_test_request),
+                };
+                self.send(participant, request, now);
+                self.sessions.get_mut(&id).unwrap().test_request_pending = true;
+            } else if now.saturating_duration_since(session.last_sent) >= interval {
+                let heartbeat = Outbound::Heartbeat {
+                    test_request_id: None,
+                };
+                self.send(participant, heartbeat, now);
+            }
+        }
+        let expired = self.expire(order_book, now);
+        self.route(order_book, now)?;
+        Ok(expired)
+    }
+
+    /// Drops every session that has been silent for longer than its
+    /// configured timeout.
+    pub fn expire(
+        &mut self,
+        order_book: &mut impl OrderBookBackend,
+        now: Instant,
+    ) -> Vec<Disconnect> {
+        let mut expired: Vec<SessionId> = self
+            .sessions
+            .values()
+            .filter(|session| {
+                now.saturating_duration_since(session.last_seen) > session.config.timeout()
+            })
+            .map(|session| session.id)
+            .collect();
+        expired.sort_unstable();
+        expired
+            .into_iter()
+            .filter_map(|id| self.sessions.remove(&id))
+            .map(|session| close(order_book, session, DisconnectReason::HeartbeatTimeout))
+            .collect()
+    }
+
+    fn session_for(&mut self, participant: ParticipantId) -> Option<&mut Session> {
+        self.sessions
+            .values_mut()
+            .find(|session| session.participant == participant)
+    }
+
+    /// Journals `message` for `participant` and queues it on their live
+    /// session, if any.
+    fn send(&mut self, participant: ParticipantId, message: Outbound, now: Instant) {
+        let seq = self.journal.append(participant, message.clone());
+        if let Some(session) = self.session_for(participant) {
+            session.outbox.push(Sequenced::new(seq, message));
+            session.last_sent = now;
+        }
+    }
+
+    /// Turns book events not yet seen into execution reports for the
+    /// participants they concern.
+    fn route(&mut self, order_book: &impl OrderBookBackend, now: Instant) -> io::Result<()> {
+        let events = &order_book.events()[self.events_routed..];
+        for event in events {
+            self.reports.publish(order_book.symbol(), event)?;
+        }
+        self.events_routed = order_book.events().len();
+        let records: Vec<DropCopyRecord> = std::mem::take(self.reports.get_mut());
+        for record in records {
+            let report = Outbound::ExecutionReport {
+                symbol: record.symbol,
+                event: record.event,
+            };
+            self.send(record.participant, report, now);
+        }
+        Ok(())
+    }
+}
+
+fn close(
+    order_book: &mut impl OrderBookBackend,
+    session: Session,
+    reason: DisconnectReason,
+) -> Disconnect {
+    let canceled = if session.config.cancel_on_disconnect {
+        order_book.cancel_all(session.participant)
+    } else {
+        0
+    };
+    tracing::info!(
+        "session {} closed ({:?}), canceled {} orders for participant {}",
+        session.id,
+        reason,
+        canceled,
+        session.participant
+    );
+    Disconnect {
+        session,
+        reason,
+        canceled,
+    }
+}
+
+fn unknown(id: SessionId) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotConnected,
+        format!("unknown session {}", id),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DisconnectReason, Gateway, Inbound, Outbound, Sequenced, SessionConfig};
+    use crate::risk::RejectReason;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+    use std::time::{Duration, Instant};
+
+    fn buy(price: i32) -> OrderCommand {
+        OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price,
+            qty: 1,
+            participant: 0,
+        }
+    }
+
+    fn config(heartbeat_interval_ms: u64) -> SessionConfig {
+        SessionConfig {
+            heartbeat_interval_ms,
+            missed_heartbeats: 2,
+            cancel_on_disconnect: true,
+        }
+    }
+
+    #[test]
+    fn stamps_participant_and_cancels_on_logout() {
+        let mut order_book = OrderBook::new();
+        let mut gateway = Gateway::new();
+        let now = Instant::now();
+        let session = gateway.logon(7, SessionConfig::default(), now).unwrap();
+        assert!(gateway.logon(7, SessionConfig::default(), now).is_err());
+        gateway
+            .submit(&mut order_book, session, buy(120), now)
+            .unwrap();
+        gateway
+            .submit(&mut order_book, session, buy(121), now)
+            .unwrap();
+        assert_eq!(order_book.bids[0].orders[0].participant, 7);
+
+        let disconnect = gateway.disconnect(&mut order_book, session, now).unwrap();
+        assert_eq!(disconnect.reason, DisconnectReason::Logout);
+        assert_eq!(disconnect.canceled, 2);
+        assert!(order_book.bids.is_empty());
+        assert!(gateway
+            .submit(&mut order_book, session, buy(120), now)
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_commands_on_other_participants_orders() {
+        let mut order_book = OrderBook::new();
+        let mut gateway = Gateway::new();
+        let now = Instant::now();
+        let victim = gateway.logon(7, SessionConfig::default(), now).unwrap();
+        let attacker = gateway.logon(8, SessionConfig::default(), now).unwrap();
+        gateway
+            .submit(&mut order_book, victim, buy(120), now)
+            .unwrap();
+        let id = order_book.bids[0].orders[0].id;
+
+        let cancel = OrderCommand::Cancel {
+            id,
+            side: Side::Buy,
+            price: 120,
+        };
+        let modify = OrderCommand::Modify {
+            id,
+            price: 119,
+            side: Side::Buy,
+            qty: 1,
+            order_type: OrderType::GoodTilCancel,
+        };
+        for command in [cancel.clone(), modify] {
+            let err = gateway
+                .submit(&mut order_book, attacker, command, now)
+                .unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        }
+        assert_eq!(order_book.best_bid(), Some(120));
+
+        gateway
+            .submit(&mut order_book, victim, cancel, now)
+            .unwrap();
+        assert!(order_book.bids.is_empty());
+    }
+
+    #[test]
+    fn expires_silent_sessions() {
+        let mut order_book = OrderBook::new();
+        let mut gateway = Gateway::new();
+        let start = Instant::now();
+        let quiet = gateway.logon(1, config(100), start).unwrap();
+        let chatty = gateway.logon(2, config(100), start).unwrap();
+        gateway
+            .submit(&mut order_book, quiet, buy(120), start)
+            .unwrap();
+        gateway
+            .submit(&mut order_book, chatty, buy(121), start)
+            .unwrap();
+
+        gateway
+            .heartbeat(chatty, start + Duration::from_millis(150))
+            .unwrap();
+        assert!(gateway
+            .expire(&mut order_book, start + Duration::from_millis(200))
+            .is_empty());
+
+        let expired = gateway.expire(&mut order_book, start + Duration::from_millis(250));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].session.id, quiet);
+        assert_eq!(expired[0].reason, DisconnectReason::HeartbeatTimeout);
+        assert_eq!(order_book.best_bid(), Some(121));
+        assert!(gateway.session(chatty).is_some());
+    }
+
+    #[test]
+    fn cancel_on_disconnect_is_per_session() {
+        let mut order_book = OrderBook::new();
+        let mut gateway = Gateway::new();
+        let now = Instant::now();
+        let config = SessionConfig {
+            cancel_on_disconnect: false,
+            ..SessionConfig::default()
+        };
+        let session = gateway.logon(3, config, now).unwrap();
+        gateway
+            .submit(&mut order_book, session, buy(120), now)
+            .unwrap();
+        let disconnect = gateway.disconnect(&mut order_book, session, now).unwrap();
+        assert_eq!(disconnect.canceled, 0);
+        assert_eq!(order_book.best_bid(), Some(120));
+    }
+
+    #[test]
+    fn answers_test_requests_and_detects_gaps() {
+        let mut order_book = OrderBook::new();
+        let mut gateway = Gateway::new();
+        let now = Instant::now();
+        let session = gateway.logon(1, config(100), now).unwrap();
+        let test_request = Inbound::TestRequest {
+            id: "abc".to_string(),
+        };
+        gateway
+            .receive(
+                &mut order_book,
+                session,
+                Sequenced::new(1, test_request),
+                now,
+            )
+            .unwrap();
+        let command = Sequenced::new(3, Inbound::Command(buy(120)));
+        gateway
+            .receive(&mut order_book, session, command.clone(), now)
+            .unwrap();
+        assert!(order_book.bids.is_empty());
+        assert_eq!(
+            gateway.drain(session),
+            [
+                Sequenced::new(
+                    1,
+                    Outbound::Heartbeat {
+                        test_request_id: Some("abc".to_string())
+                    }
+                ),
+                Sequenced::new(2, Outbound::ResendRequest { begin: 2, end: 2 }),
+            ]
+        );
+
+        let heartbeat = Inbound::Heartbeat {
+            test_request_id: None,
+        };
+        gateway
+            .receive(&mut order_book, session, Sequenced::new(2, heartbeat), now)
+            .unwrap();
+        gateway
+            .receive(&mut order_book, session, command.clone(), now)
+            .unwrap();
+        assert_eq!(order_book.best_bid(), Some(120));
+        assert!(gateway
+            .receive(&mut order_book, session, command, now)
+            .is_err());
+    }
+
+    #[test]
+    fn probes_idle_sessions() {
+        let mut order_book = OrderBook::new();
+        let mut gateway = Gateway::new();
+        let start = Instant::now();
+        let session = gateway.logon(1, config(100), start).unwrap();
+
+        gateway
+            .tick(&mut order_book, start + Duration::from_millis(100))
+            .unwrap();
+        let sent = gateway.drain(session);
+        assert!(matches!(
+            sent[..],
+            [Sequenced {
+                message: Outbound::TestRequest { .. },
+                ..
+            }]
+        ));
+
+        gateway
+            .tick(&mut order_book, start + Duration::from_millis(200))
+            .unwrap();
+        let sent = gateway.drain(session);
+        assert!(matches!(
+            sent[..],
+            [Sequenced {
+                message: Outbound::Heartbeat { .. },
+                ..
+            }]
+        ));
+
+        let expired = gateway
+            .tick(&mut order_book, start + Duration::from_millis(201))
+            .unwrap();
+        assert_eq!(expired.len(), 1);
+    }
+
+    #[test]
+    fn reconnecting_client_recovers_missed_reports() {
+        let mut order_book = OrderBook::new();
+        let mut gateway = Gateway::new();
+        let now = Instant::now();
+        let maker = gateway.logon(1, SessionConfig::default(), now).unwrap();
+        let taker = gateway.logon(2, SessionConfig::default(), now).unwrap();
+        let sell = OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Sell,
+            price: 120,
+            qty: 2,
+            participant: 0,
+        };
+        gateway.submit(&mut order_book, maker, sell, now).unwrap();
+        let seen = gateway.drain(maker);
+        assert_eq!(seen.len(), 1);
+
+        gateway.disconnect(&mut order_book, maker, now).unwrap();
+        assert!(order_book.asks.is_empty());
+        gateway
+            .submit(&mut order_book, taker, buy(120), now)
+            .unwrap();
+
+        let maker = gateway.logon(1, SessionConfig::default(), now).unwrap();
+        let resend = Inbound::ResendRequest {
+            begin: seen[0].seq + 1,
+            end: 0,
+        };
+        gateway
+            .receive(&mut order_book, maker, Sequenced::new(1, resend), now)
+            .unwrap();
+        let replay = gateway.drain(maker);
+        assert_eq!(replay.len(), 1);
+        assert!(replay[0].possible_duplicate);
+        assert!(matches!(
+            replay[0].message,
+            Outbound::ExecutionReport {
+                event: OrderEvent::Canceled { .. },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn rejects_duplicate_and_unknown_client_order_ids() {
+        let mut order_book = OrderBook::new();
+        let mut gateway = Gateway::new();
+        let now = Instant::now();
+        let session = gateway.logon(1, SessionConfig::default(), now).unwrap();
+        let other = gateway.logon(2, SessionConfig::default(), now).unwrap();
+        let cancel = OrderCommand::Cancel {
+            id: 0,
+            side: Side::Buy,
+            price: 0,
+        };
+        let submit =
+            |gateway: &mut Gateway, book: &mut OrderBook, session, cl_ord_id: &str, command| {
+                gateway
+                    .submit_client_order(book, session, cl_ord_id, command, now)
+                    .unwrap();
+                gateway.drain(session)
+            };
+        let rejected = |reports: &[Sequenced<Outbound>]| match reports {
+            [Sequenced {
+                message:
+                    Outbound::ExecutionReport {
+                        event: OrderEvent::Rejected { reason, .. },
+                        ..
+                    },
+                ..
+            }] => Some(*reason),
+            _ => None,
+        };
+
+        assert_eq!(
+            rejected(&submit(
+                &mut gateway,
+                &mut order_book,
+                session,
+                "a",
+                buy(120)
+            )),
+            None
+        );
+        assert_eq!(
+            rejected(&submit(
+                &mut gateway,
+                &mut order_book,
+                session,
+                "a",
+                buy(121)
+            )),
+            Some(RejectReason::DuplicateClientOrderId)
+        );
+        // Client order ids are per participant.
+        assert_eq!(
+            rejected(&submit(&mut gateway, &mut order_book, other, "a", buy(119))),
+            None
+        );
+        assert_eq!(
+            rejected(&submit(
+                &mut gateway,
+                &mut order_book,
+                session,
+                "b",
+                cancel.clone()
+            )),
+            Some(RejectReason::UnknownClientOrderId)
+        );
+
+        assert_eq!(
+            rejected(&submit(
+                &mut gateway,
+                &mut order_book,
+                session,
+                "a",
+                cancel.clone()
+            )),
+            None
+        );
+        assert_eq!(order_book.best_bid(), Some(119));
+        assert_eq!(
+            rejected(&submit(&mut gateway, &mut order_book, session, "a", cancel)),
+            Some(RejectReason::UnknownClientOrderId)
+        );
+        // Once the order is gone its id is free again.
+        assert_eq!(
+            rejected(&submit(
+                &mut gateway,
+                &mut order_book,
+                session,
+                "a",
+                buy(122)
+            )),
+            None
+        );
+        assert_eq!(order_book.best_bid(), Some(122));
+    }
+
+    #[test]
+    fn client_order_modifies_record_the_replaced_id() {
+        let mut order_book = OrderBook::new();
+        let mut gateway = Gateway::new();
+        let now = Instant::now();
+        let session = gateway.logon(1, SessionConfig::default(), now).unwrap();
+        let modify = |price| OrderCommand::Modify {
+            id: 0,
+            price,
+            side: Side::Buy,
+            qty: 1,
+            order_type: OrderType::GoodTilCancel,
+        };
+        for command in [buy(120), modify(121), modify(122)] {
+            gateway
+                .submit_client_order(&mut order_book, session, "a", command, now)
+                .unwrap();
+        }
+        assert_eq!(order_book.best_bid(), Some(122));
+        let client_orders = gateway.client_orders();
+        assert_eq!(client_orders.replaced(3), Some(2));
+        assert_eq!(client_orders.replaced(2), Some(1));
+        assert_eq!(client_orders.replaced(1), None);
+        assert_eq!(client_orders.original(3), 1);
+        assert_eq!(client_orders.original(1), 1);
+
+        // Once the order is gone, so is its history.
+        let cancel = OrderCommand::Cancel {
+            id: 0,
+            side: Side::Buy,
+            price: 0,
+        };
+        gateway
+            .submit_client_order(&mut order_book, session, "a", cancel.clone(), now)
+            .unwrap();
+        gateway
+            .submit_client_order(&mut order_book, session, "a", cancel, now)
+            .unwrap();
+        assert_eq!(gateway.client_orders().original(3), 3);
+    }
+}
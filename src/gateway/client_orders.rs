@@ -0,0 +1,85 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Client order ids.
+//!
+//! Clients name their orders with ids of their own choosing (FIX's
+//! `ClOrdID`) and refer to them by that name when modifying or canceling.
+//! The gateway maps each participant's ids onto book order ids. An id stays
+//! taken for as long as its order rests; once the order fills or is canceled
+//! the id may be used again.
+//!
+//! A modify re-enters the order under a new book id and the client order id
+//! moves with it. The replaced id is recorded against the replacement, so
+//! execution reports for either can be traced back to the order the client
+//! entered with [`ClientOrderIds::original`]. The record is dropped along
+//! with the client order id once the order is gone.
+
+use crate::backend::OrderBookBackend;
+use crate::{OrderId, ParticipantId};
+use std::collections::HashMap;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientOrderIds {
+    orders: HashMap<(ParticipantId, String), OrderId>,
+    /// Book id of the order each modify's replacement replaced.
+    replaced: HashMap<OrderId, OrderId>,
+}
+
+impl ClientOrderIds {
+    pub fn new() -> ClientOrderIds {
+        ClientOrderIds::default()
+    }
+
+    /// Book id of the live order `participant` named `cl_ord_id`. Entries
+    /// whose order has left the book are forgotten on the way.
+    pub fn live(
+        &mut self,
+        order_book: &impl OrderBookBackend,
+        participant: ParticipantId,
+        cl_ord_id: &str,
+    ) -> Option<OrderId> {
+        let key = (participant, cl_ord_id.to_string());
+        let id = *self.orders.get(&key)?;
+        if !order_book.owns(participant, id) {
+            self.orders.remove(&key);
+            let mut replacement = id;
+            while let Some(replaced) = self.replaced.remove(&replacement) {
+                replacement = replaced;
+            }
+            return None;
+        }
+        Some(id)
+    }
+
+    pub fn assign(&mut self, participant: ParticipantId, cl_ord_id: &str, id: OrderId) {
+        self.orders.insert((participant, cl_ord_id.to_string()), id);
+    }
+
+    /// Moves `cl_ord_id` from book order `replaced` to its replacement `id`.
+    pub fn replace(
+        &mut self,
+        participant: ParticipantId,
+        cl_ord_id: &str,
+        replaced: OrderId,
+        id: OrderId,
+    ) {
+        self.assign(participant, cl_ord_id, id);
+        self.replaced.insert(id, replaced);
+    }
+
+    /// Book id of the order `id` replaced, if it was placed by a modify.
+    pub fn replaced(&self, id: OrderId) -> Option<OrderId> {
+        self.replaced.get(&id).copied()
+    }
+
+    /// Book id the order was first entered under, following `id` back
+    /// through every modify; `id` itself if it was never modified.
+    pub fn original(&self, mut id: OrderId) -> OrderId {
+        while let Some(replaced) = self.replaced(id) {
+            id = replaced;
+        }
+        id
+    }
+}
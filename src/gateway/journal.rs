@@ -0,0 +1,133 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Outbound message journal.
+//!
+//! Every message sent to a participant is appended here under the next
+//! outbound sequence number, whether or not the participant is connected.
+//! Sequence numbers belong to the participant rather than the session, so a
+//! client that reconnects can ask for whatever it missed.
+
+use super::{Outbound, Sequenced};
+use crate::ParticipantId;
+use std::collections::HashMap;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Journal {
+    messages: HashMap<ParticipantId, Vec<Outbound>>,
+}
+
+impl Journal {
+    pub fn new() -> Journal {
+        Journal::default()
+    }
+
+    /// Records `message` and returns the sequence number it was sent with.
+    pub fn append(&mut self, participant: ParticipantId, message: Outbound) -> u64 {
+        let messages = self.messages.entry(participant).or_default();
+        messages.push(message);
+        messages.len() as u64
+    }
+
+    /// Sequence number the next message to `participant` will carry.
+    pub fn next_seq(&self, participant: ParticipantId) -> u64 {
+        self.messages.get(&participant).map_or(0, Vec::len) as u64 + 1
+    }
+
+    /// Replays messages `begin..=end` to `participant`, with `end == 0`
+    /// meaning everything sent so far. Execution reports are resent flagged as
+    /// possible duplicates; runs of session-level messages, which are
+    /// meaningless after the fact, are collapsed into a single
+    /// `SequenceReset` pointing past them.
+    pub fn resend(
+        &self,
+        participant: ParticipantId,
+        begin: u64,
+        end: u64,
+    ) -> Vec<Sequenced<Outbound>> {
+        let messages = self
+            .messages
+            .get(&participant)
+            .map_or(&[][..], Vec::as_slice);
+        let last = if end == 0 {
+            messages.len() as u64
+        } else {
+            end.min(messages.len() as u64)
+        };
+        let mut replay = Vec::new();
+        let mut gap_start = None;
+        for seq in begin.max(1)..=last {
+            let message = &messages[seq as usize - 1];
+            if let Outbound::ExecutionReport { .. } = message {
+                if let Some(start) = gap_start.take() {
+                    replay.push(Sequenced::resent(
+                        start,
+                        Outbound::SequenceReset { new_seq: seq },
+                    ));
+                }
+                replay.push(Sequenced::resent(seq, message.clone()));
+            } else if gap_start.is_none() {
+                gap_start = Some(seq);
+            }
+        }
+        if let Some(start) = gap_start {
+            replay.push(Sequenced::resent(
+                start,
+                Outbound::SequenceReset { new_seq: last + 1 },
+            ));
+        }
+        replay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Journal;
+    use crate::gateway::{Outbound, Sequenced};
+    use crate::{OrderEvent, OrderId};
+
+    fn report(id: OrderId) -> Outbound {
+        Outbound::ExecutionReport {
+            symbol: "ABC".to_string(),
+            event: OrderEvent::Canceled { id },
+        }
+    }
+
+    fn heartbeat() -> Outbound {
+        Outbound::Heartbeat {
+            test_request_id: None,
+        }
+    }
+
+    #[test]
+    fn sequences_per_participant() {
+        let mut journal = Journal::new();
+        assert_eq!(journal.append(1, report(1)), 1);
+        assert_eq!(journal.append(1, report(2)), 2);
+        assert_eq!(journal.append(2, report(3)), 1);
+        assert_eq!(journal.next_seq(1), 3);
+        assert_eq!(journal.next_seq(9), 1);
+    }
+
+    #[test]
+    fn resend_gap_fills_session_messages() {
+        let mut journal = Journal::new();
+        for message in [report(1), heartbeat(), heartbeat(), report(2), heartbeat()] {
+            journal.append(1, message);
+        }
+        let replay = journal.resend(1, 1, 0);
+        assert_eq!(
+            replay,
+            [
+                Sequenced::resent(1, report(1)),
+                Sequenced::resent(2, Outbound::SequenceReset { new_seq: 4 }),
+                Sequenced::resent(4, report(2)),
+                Sequenced::resent(5, Outbound::SequenceReset { new_seq: 6 }),
+            ]
+        );
+        assert_eq!(journal.resend(1, 4, 4), [Sequenced::resent(4, report(2))]);
+        assert!(journal.resend(1, 7, 0).is_empty());
+        assert!(journal.resend(3, 1, 0).is_empty());
+    }
+}
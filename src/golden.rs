@@ -0,0 +1,201 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Golden-file tests.
+//!
+//! Every `tests/golden/<name>.script` is run against a fresh book and its
+//! event stream compared line by line with `tests/golden/<name>.golden`. The
+//! output echoes each command, prefixed with `>`, followed by the events it
+//! produced, rendered as in [`Simulation::transcript`]. A change to execution
+//! prices, priority or event order shows up as a diff in a checked-in file.
+//!
+//! Set `UPDATE_GOLDEN=1` to rewrite the golden files from the current
+//! behavior, then review the diff before committing it.
+//!
+//! Scripts hold one command per line; blank lines and `#` comments are
+//! skipped. Orders and trades are named `o<n>` and `t<n>` after their
+//! numbers in the output.
+//!
+//! ```text
+//! new <participant> buy|sell <qty>@<price> [gtc|day|fak]
+//! modify o<n> <qty>
+//! cancel o<n>
+//! kill <participant> on|off
+//! halt
+//! resume [auction]
+//! bust t<n> [restore]
+//! ```
+//!
+//! [`Simulation::transcript`]: crate::sim::Simulation::transcript
+
+use crate::sim::Labels;
+use crate::{OrderBook, OrderCommand, OrderType, Side};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// Runs `script` and returns the output a golden file holds.
+fn run(script: &str) -> Result<String, String> {
+    let mut order_book = OrderBook::with_symbol("GOLD");
+    let mut labels = Labels::default();
+    let mut output = String::new();
+    for (n, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let command = parse(&order_book, &labels, line)
+            .map_err(|e| format!("line {}: {}: {}", n + 1, line, e))?;
+        let seen = order_book.events().len();
+        order_book.process_command(command);
+        output.push_str(&format!("> {}\n", line));
+        for event in &order_book.events()[seen..] {
+            output.push_str(&labels.render(event));
+            output.push('\n');
+        }
+    }
+    Ok(output)
+}
+
+pub(crate) fn parse(
+    order_book: &OrderBook,
+    labels: &Labels,
+    line: &str,
+) -> Result<OrderCommand, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let number = |word: Option<&&str>| -> Result<u32, String> {
+        let word = word.ok_or("missing argument")?;
+        word.parse().map_err(|_| format!("bad number {}", word))
+    };
+    let order = |word: Option<&&str>| {
+        let word = word.ok_or("missing order")?;
+        let label = word
+            .strip_prefix('o')
+            .and_then(|label| label.parse().ok())
+            .ok_or_else(|| format!("bad order {}", word))?;
+        labels
+            .order(label)
+            .and_then(|id| order_book.find_order(id))
+            .ok_or_else(|| format!("{} is not resting", word))
+    };
+    Ok(match words.first().copied() {
+        Some("new") => {
+            let side = match words.get(2).copied() {
+                Some("buy") => Side::Buy,
+                Some("sell") => Side::Sell,
+                other => return Err(format!("bad side {:?}", other)),
+            };
+            let (qty, price) = words
+                .get(3)
+                .and_then(|word| word.split_once('@'))
+                .ok_or("expected <qty>@<price>")?;
+            let order_type = match words.get(4).copied() {
+                None | Some("gtc") => OrderType::GoodTilCancel,
+                Some("day") => OrderType::Day,
+                Some("fak") => OrderType::FillAndKill,
+                Some(other) => return Err(format!("bad order type {}", other)),
+            };
+            OrderCommand::New {
+                order_type,
+                side,
+                price: price.parse().map_err(|_| format!("bad price {}", price))?,
+                qty: number(Some(&qty))?,
+                participant: number(words.get(1))?,
+            }
+        }
+        Some("modify") => {
+            let order = order(words.get(1))?;
+            OrderCommand::Modify {
+                id: order.id,
+                price: order.price,
+                side: order.side,
+                qty: number(words.get(2))?,
+                order_type: order.order_type,
+            }
+        }
+        Some("cancel") => {
+            let order = order(words.get(1))?;
+            OrderCommand::Cancel {
+                id: order.id,
+                side: order.side,
+                price: order.price,
+            }
+        }
+        Some("kill") => OrderCommand::KillSwitch {
+            participant: number(words.get(1))?,
+            engage: match words.get(2).copied() {
+                Some("on") => true,
+                Some("off") => false,
+                other => return Err(format!("expected on or off, got {:?}", other)),
+            },
+        },
+        Some("halt") => OrderCommand::Halt,
+        Some("resume") => OrderCommand::Resume {
+            auction: words.get(1) == Some(&"auction"),
+        },
+        Some("bust") => {
+            let word = words.get(1).ok_or("missing trade")?;
+            let trade_id = word
+                .strip_prefix('t')
+                .and_then(|label| label.parse().ok())
+                .and_then(|label| labels.trade(label))
+                .ok_or_else(|| format!("unknown trade {}", word))?;
+            OrderCommand::BustTrade {
+                trade_id,
+                restore: words.get(2) == Some(&"restore"),
+            }
+        }
+        other => return Err(format!("unknown command {:?}", other)),
+    })
+}
+
+#[test]
+fn event_streams_match_golden_files() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut scripts: Vec<PathBuf> = fs::read_dir(golden_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "script"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no golden scripts found");
+
+    let mut failures = Vec::new();
+    for script in &scripts {
+        let name = script.file_stem().unwrap().to_string_lossy();
+        let actual =
+            run(&fs::read_to_string(script).unwrap()).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        let golden = script.with_extension("golden");
+        if update {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&golden).unwrap_or_default();
+        if actual == expected {
+            continue;
+        }
+        let (line, (want, got)) = expected
+            .lines()
+            .chain(std::iter::repeat("<end of file>"))
+            .zip(actual.lines().chain(std::iter::repeat("<end of output>")))
+            .enumerate()
+            .find(|(_, (want, got))| want != got)
+            .unwrap();
+        failures.push(format!(
+            "{}, line {}:\n  expected {}\n  got      {}",
+            name,
+            line + 1,
+            want,
+            got
+        ));
+    }
+    assert!(
+        failures.is_empty(),
+        "event streams differ from golden files (rerun with UPDATE_GOLDEN=1 to accept):\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,311 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Book heatmaps.
+//!
+//! A [`HeatmapSampler`] is shown the book after each command and takes a
+//! sample of the quantity resting at every price on a fixed grid of times,
+//! aligned to multiples of the interval like [`candles`](crate::candles).
+//! A sample that fell due between two commands shows the book as the
+//! earlier one left it. At the end of the session [`HeatmapSampler::heatmap`]
+//! lays the samples out as a matrix of time by price, from the lowest to
+//! the highest price anything rested at, for plotting.
+//!
+//! [`Heatmap::write_csv`] writes a header row of prices and a row per
+//! sample. [`Heatmap::write_binary`] writes the same, little-endian:
+//!
+//! ```text
+//! low_price: i32 | prices: u32 | samples: u32
+//! per sample: time: u64 | quantity: u64 per price
+//! ```
+
+use crate::{OrderBook, Timestamp};
+use std::io::{self, Write};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeatmapSampler {
+    interval: Duration,
+    /// When the next sample is due, once the first command has been seen.
+    next: Option<Timestamp>,
+    /// Price and quantity of every level as the last command left them.
+    levels: Vec<(i32, u64)>,
+    samples: Vec<(Timestamp, Vec<(i32, u64)>)>,
+}
+
+impl HeatmapSampler {
+    /// Samples every `interval`.
+    ///
+    /// # Panics
+    ///
+    /// If `interval` is zero.
+    pub fn new(interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "heatmap interval must not be zero");
+        HeatmapSampler {
+            interval,
+            next: None,
+            levels: Vec::new(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Takes the samples due up to the book's current time. Samples before
+    /// it show the book as it was at the last call.
+    pub fn sample(&mut self, order_book: &OrderBook) {
+        let now = order_book.now();
+        let interval = self.interval.as_nanos() as u64;
+        let levels = levels(order_book);
+        let mut next = match self.next {
+            Some(next) => next,
+            None => {
+                self.levels = levels.clone();
+                Timestamp::from_nanos(now.as_nanos().div_ceil(interval) * interval)
+            }
+        };
+        while next < now {
+            self.samples.push((next, self.levels.clone()));
+            next = next + self.interval;
+        }
+        if next == now {
+            self.samples.push((next, levels.clone()));
+            next = next + self.interval;
+        }
+        self.next = Some(next);
+        self.levels = levels;
+    }
+
+    /// How many samples have been taken.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn heatmap(&self) -> Heatmap {
+        let prices = self.samples.iter().flat_map(|(_, levels)| levels);
+        let low = prices.clone().map(|&(price, _)| price).min().unwrap_or(0);
+        let high = prices.map(|&(price, _)| price).max();
+        let width = high.map_or(0, |high| (i64::from(high) - i64::from(low)) as usize + 1);
+        let mut times = Vec::with_capacity(self.samples.len());
+        let mut quantities = vec![0; width * self.samples.len()];
+        for (row, (time, levels)) in self.samples.iter().enumerate() {
+            times.push(*time);
+            for &(price, qty) in levels {
+                quantities[row * width + (i64::from(price) - i64::from(low)) as usize] = qty;
+            }
+        }
+        Heatmap {
+            low_price: low,
+            prices: width,
+            times,
+            quantities,
+        }
+    }
+}
+
+/// Resting quantity by sample time and price.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heatmap {
+    /// The price of the first column; each column is a tick higher.
+    pub low_price: i32,
+    /// How many columns there are.
+    pub prices: usize,
+    pub times: Vec<Timestamp>,
+    /// Row by row, a row per time.
+    pub quantities: Vec<u64>,
+}
+
+impl Heatmap {
+    /// The quantity resting at `price` in the sample at row `row`.
+    pub fn quantity(&self, row: usize, price: i32) -> u64 {
+        let column = i64::from(price) - i64::from(self.low_price);
+        if column < 0 || column as usize >= self.prices {
+            return 0;
+        }
+        self.quantities[row * self.prices + column as usize]
+    }
+
+    fn rows(&self) -> impl Iterator<Item = (Timestamp, &[u64])> {
+        // A heatmap with no prices still has a row per time.
+        let rows = self.quantities.chunks(self.prices.max(1));
+        self.times
+            .iter()
+            .copied()
+            .zip(rows.chain(std::iter::repeat(&[][..])))
+    }
+
+    /// Writes a header row of prices, then the time in nanoseconds and the
+    /// quantity at each price for every sample.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "time")?;
+        for column in 0..self.prices {
+            write!(writer, ",{}", i64::from(self.low_price) + column as i64)?;
+        }
+        writeln!(writer)?;
+        for (time, row) in self.rows() {
+            write!(writer, "{}", time.as_nanos())?;
+            for qty in row {
+                write!(writer, ",{}", qty)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_binary<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.low_price.to_le_bytes())?;
+        writer.write_all(&(self.prices as u32).to_le_bytes())?;
+        writer.write_all(&(self.times.len() as u32).to_le_bytes())?;
+        for (time, row) in self.rows() {
+            writer.write_all(&time.as_nanos().to_le_bytes())?;
+            for qty in row {
+                writer.write_all(&qty.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every level on both sides, bids then asks.
+fn levels(order_book: &OrderBook) -> Vec<(i32, u64)> {
+    let depth = order_book.depth(usize::MAX);
+    depth
+        .bids
+        .iter()
+        .chain(&depth.asks)
+        .map(|level| (level.price, level.qty))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeatmapSampler;
+    use crate::clock::ManualClock;
+    use crate::{OrderBook, OrderCommand, OrderType, Side, Timestamp};
+    use std::time::Duration;
+
+    #[test]
+    fn samples_the_book_on_a_grid() {
+        let clock = ManualClock::starting_at(Timestamp::from_nanos(0));
+        let mut order_book = OrderBook::new();
+        order_book.set_clock(clock.clone());
+        let mut sampler = HeatmapSampler::new(Duration::from_secs(1));
+        let mut send = |order_book: &mut OrderBook, command| {
+            order_book.process_command(command);
+            sampler.sample(order_book);
+            sampler.clone()
+        };
+        let new_order = |side, price, qty| OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price,
+            qty,
+            participant: 1,
+        };
+        send(&mut order_book, new_order(Side::Buy, 99, 10));
+        clock.advance(Duration::from_millis(2500));
+        send(&mut order_book, new_order(Side::Sell, 102, 5));
+        clock.advance(Duration::from_millis(500));
+        let sampler = send(
+            &mut order_book,
+            OrderCommand::Cancel {
+                id: 1,
+                side: Side::Buy,
+                price: 99,
+            },
+        );
+        assert_eq!(sampler.len(), 4);
+
+        let heatmap = sampler.heatmap();
+        assert_eq!((heatmap.low_price, heatmap.prices), (99, 4));
+        assert_eq!(heatmap.quantity(2, 99), 10);
+        assert_eq!(heatmap.quantity(3, 99), 0);
+        assert_eq!(heatmap.quantity(3, 102), 5);
+
+        let mut csv = Vec::new();
+        heatmap.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time,99,100,101,102\n\
+             0,10,0,0,0\n\
+             1000000000,10,0,0,0\n\
+             2000000000,10,0,0,0\n\
+             3000000000,0,0,0,5\n"
+        );
+        let mut binary = Vec::new();
+        heatmap.write_binary(&mut binary).unwrap();
+        assert_eq!(binary.len(), 12 + 4 * (8 + 4 * 8));
+    }
+
+    #[test]
+    fn empty_books_give_rows_without_prices() {
+        let clock = ManualClock::starting_at(Timestamp::from_nanos(1_500_000_000));
+        let mut order_book = OrderBook::new();
+        order_book.set_clock(clock.clone());
+        let mut sampler = HeatmapSampler::new(Duration::from_secs(1));
+
+        // Nothing is due until the grid point after the first command.
+        sampler.sample(&order_book);
+        assert!(sampler.is_empty());
+        let mut csv = Vec::new();
+        sampler.heatmap().write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "time\n");
+
+        clock.advance(Duration::from_millis(500));
+        sampler.sample(&order_book);
+        let heatmap = sampler.heatmap();
+        assert_eq!((heatmap.low_price, heatmap.prices), (0, 0));
+        assert_eq!(heatmap.times, [Timestamp::from_nanos(2_000_000_000)]);
+        assert_eq!(heatmap.quantity(0, 100), 0);
+
+        let mut csv = Vec::new();
+        heatmap.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "time\n2000000000\n");
+        let mut binary = Vec::new();
+        heatmap.write_binary(&mut binary).unwrap();
+        assert_eq!(binary.len(), 12 + 8);
+        assert_eq!(&binary[4..12], &[0, 0, 0, 0, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn quantity_outside_the_grid_is_zero() {
+        let mut order_book = OrderBook::new();
+        let mut sampler = HeatmapSampler::new(Duration::from_secs(1));
+        for (side, price) in [(Side::Buy, -2), (Side::Sell, 1)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty: 3,
+                participant: 1,
+            });
+        }
+        let clock = ManualClock::starting_at(Timestamp::from_nanos(0));
+        order_book.set_clock(clock);
+        sampler.sample(&order_book);
+
+        let heatmap = sampler.heatmap();
+        assert_eq!((heatmap.low_price, heatmap.prices), (-2, 4));
+        assert_eq!(heatmap.quantity(0, -2), 3);
+        assert_eq!(heatmap.quantity(0, -3), 0);
+        assert_eq!(heatmap.quantity(0, 2), 0);
+        assert_eq!(heatmap.quantity(0, i32::MIN), 0);
+        assert_eq!(heatmap.quantity(0, i32::MAX), 0);
+
+        let mut csv = Vec::new();
+        heatmap.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time,-2,-1,0,1\n0,3,0,0,3\n"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "heatmap interval must not be zero")]
+    fn zero_interval_panics() {
+        HeatmapSampler::new(Duration::ZERO);
+    }
+}
@@ -0,0 +1,541 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Minimal HTTP/1.1 front end for order entry and book inspection.
+//!
+//! Routes:
+//! * `POST /orders` - place an order, body `{"order_type","side","price","qty"}` plus
+//!   an optional `"participant"`
+//! * `DELETE /orders/{id}` - cancel a resting order
+//! * `GET /book/depth?levels=N` - aggregated depth, 10 levels by default
+//! * `GET /trades?limit=N` - most recent trades, oldest first, 100 by default
+//! * `GET /positions/{participant}` - the participant's position in this book
+//!
+//! Connections are handled one at a time on the calling thread so the book
+//! never needs to be shared across threads. A client that hasn't sent its
+//! whole request within [`REQUEST_TIMEOUT`] is dropped so it can't hold up
+//! the others, however it trickles the bytes in, and request heads and
+//! bodies are capped in size.
+//!
+//! There is no authentication: an order is entered for whatever
+//! `"participant"` the body names, participant 0 if none, and any client
+//! may cancel any order or read any position. Run the server only where
+//! every client is trusted, or behind something that authenticates them.
+
+use crate::backend::OrderBookBackend;
+use crate::{OrderCommand, OrderEvent, OrderId, OrderType, ParticipantId, Side, Trade};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_DEPTH_LEVELS: usize = 10;
+const DEFAULT_TRADES_LIMIT: usize = 100;
+/// Longest a client may take to send a whole request.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest a write of the response may block.
+pub const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often an idle server checks whether it has been asked to stop.
+pub const ACCEPT_POLL: Duration = Duration::from_millis(10);
+/// Largest request line and headers accepted, together.
+pub const MAX_HEAD_LEN: u64 = 8 * 1024;
+/// Largest request body accepted; larger ones are answered with 413.
+pub const MAX_BODY_LEN: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct NewOrderRequest {
+    order_type: OrderType,
+    side: Side,
+    price: i32,
+    qty: u32,
+    /// Taken on trust; see the module docs.
+    #[serde(default)]
+    participant: ParticipantId,
+}
+
+#[derive(Debug, Serialize)]
+struct NewOrderResponse {
+    id: OrderId,
+    trades: Vec<TradeView>,
+}
+
+#[derive(Debug, Serialize)]
+struct TradeView {
+    id: usize,
+    price: i32,
+    qty: u32,
+    aggressor_side: Side,
+    maker_order_id: OrderId,
+    taker_order_id: OrderId,
+}
+
+impl From<&Trade> for TradeView {
+    fn from(trade: &Trade) -> Self {
+        TradeView {
+            id: trade.id,
+            price: trade.price,
+            qty: trade.qty,
+            aggressor_side: trade.aggressor_side,
+            maker_order_id: trade.maker_order_id,
+            taker_order_id: trade.taker_order_id,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    fn json<T: Serialize>(status: u16, value: &T) -> Response {
+        Response {
+            status,
+            body: serde_json::to_string(value).unwrap_or_else(|_| "null".to_string()),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, &serde_json::json!({ "error": message }))
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            422 => "Unprocessable Entity",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// Accepts connections on `addr` and serves requests against `order_book`,
+/// any [`OrderBookBackend`], until the listener fails.
+pub fn serve<A: ToSocketAddrs>(addr: A, order_book: &mut impl OrderBookBackend) -> io::Result<()> {
+    serve_until(addr, order_book, &AtomicBool::new(false))
+}
+
+/// As [`serve`], returning once `stop` is set. It is checked between
+/// connections and at least every [`ACCEPT_POLL`] while idle.
+pub fn serve_until<A: ToSocketAddrs>(
+    addr: A,
+    order_book: &mut impl OrderBookBackend,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    tracing::info!("HTTP API listening on {}", listener.local_addr()?);
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let handled = stream
+                    .set_nonblocking(false)
+                    .and_then(|()| handle_connection(stream, order_book));
+                if let Err(e) = handled {
+                    tracing::warn!("HTTP connection error: {}", e);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL),
+            Err(e) => tracing::warn!("HTTP accept error: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    order_book: &mut impl OrderBookBackend,
+) -> io::Result<()> {
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let response = match read_request(&stream, Instant::now() + REQUEST_TIMEOUT)? {
+        Ok((method, target, body)) => route(order_book, &method, &target, &body),
+        Err(response) => response,
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+/// A request's method, target and body, or the error response for one
+/// that can't be served.
+type Request = Result<(String, String, Vec<u8>), Response>;
+
+/// Reads from a connection until `deadline`, then fails every read with
+/// `TimedOut`, so a client can't stretch a request out a byte at a time.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timed_out = || io::Error::new(io::ErrorKind::TimedOut, "request not received in time");
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(timed_out());
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        // Unix reports a read timeout as WouldBlock.
+        (&mut &*self.stream).read(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => timed_out(),
+            _ => e,
+        })
+    }
+}
+
+fn read_request(stream: &TcpStream, deadline: Instant) -> io::Result<Request> {
+    let reader = DeadlineReader { stream, deadline };
+    let mut reader = BufReader::new(reader.take(MAX_HEAD_LEN));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(Err(Response::error(400, "incomplete or oversized head")));
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                match value.trim().parse() {
+                    Ok(len) => content_length = len,
+                    Err(_) => return Ok(Err(Response::error(400, "invalid content-length"))),
+                }
+            }
+        }
+    }
+    if content_length > MAX_BODY_LEN {
+        return Ok(Err(Response::error(413, "request body too large")));
+    }
+    reader.get_mut().set_limit(content_length as u64);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Ok((method, target, body)))
+}
+
+/// Dispatches a single request. Kept separate from the socket handling so
+/// routes can be exercised directly in tests.
+pub fn route(
+    order_book: &mut impl OrderBookBackend,
+    method: &str,
+    target: &str,
+    body: &[u8],
+) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        ("POST", ["orders"]) => new_order(order_book, body),
+        ("DELETE", ["orders", id]) => match id.parse() {
+            Ok(id) => cancel_order(order_book, id),
+            Err(_) => Response::error(400, "invalid order id"),
+        },
+        ("GET", ["book", "depth"]) => {
+            let levels = query_param(query, "levels").unwrap_or(DEFAULT_DEPTH_LEVELS);
+            Response::json(200, &order_book.depth(levels))
+        }
+        ("GET", ["trades"]) => {
+            let trades = order_book.trades();
+            let limit = query_param(query, "limit").unwrap_or(DEFAULT_TRADES_LIMIT);
+            let views: Vec<TradeView> = trades[trades.len().saturating_sub(limit)..]
+                .iter()
+                .map(TradeView::from)
+                .collect();
+            Response::json(200, &views)
+        }
+        ("GET", ["positions", participant]) => match participant.parse() {
+            Ok(participant) => Response::json(200, &order_book.position(participant)),
+            Err(_) => Response::error(400, "invalid participant"),
+        },
+        (_, ["orders"])
+        | (_, ["orders", _])
+        | (_, ["book", "depth"])
+        | (_, ["trades"])
+        | (_, ["positions", _]) => Response::error(405, "method not allowed"),
+        _ => Response::error(404, "not found"),
+    }
+}
+
+fn new_order(order_book: &mut impl OrderBookBackend, body: &[u8]) -> Response {
+    let request: NewOrderRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return Response::error(400, &e.to_string()),
+    };
+    let events = order_book.submit(OrderCommand::New {
+        order_type: request.order_type,
+        side: request.side,
+        price: request.price,
+        qty: request.qty,
+        participant: request.participant,
+    });
+    let mut response = NewOrderResponse {
+        id: 0,
+        trades: Vec::new(),
+    };
+    for event in &events {
+        match event {
+            OrderEvent::Placed { id, .. } => response.id = *id,
+            OrderEvent::Trade(trade) => response.trades.push(TradeView::from(trade)),
+            OrderEvent::Rejected { reason, .. } => {
+                return Response::error(422, &format!("rejected: {:?}", reason))
+            }
+            _ => {}
+        }
+    }
+    Response::json(201, &response)
+}
+
+fn cancel_order(order_book: &mut impl OrderBookBackend, id: OrderId) -> Response {
+    if order_book.find_order(id).is_none() {
+        return Response::error(404, "order not found");
+    }
+    for event in &order_book.cancel(id) {
+        match *event {
+            OrderEvent::Canceled { id: canceled } if canceled == id => {
+                return Response::json(200, &serde_json::json!({ "id": id, "canceled": true }))
+            }
+            OrderEvent::Rejected { reason, .. } => {
+                return Response::error(409, &format!("rejected: {:?}", reason))
+            }
+            _ => {}
+        }
+    }
+    Response::error(409, "order not canceled")
+}
+
+fn query_param(query: &str, name: &str) -> Option<usize> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        handle_connection, read_request, route, DEFAULT_TRADES_LIMIT, MAX_BODY_LEN, MAX_HEAD_LEN,
+    };
+    use crate::risk::throttle::RateLimit;
+    use crate::OrderBook;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Sends `request` over a real connection and returns the status line.
+    fn exchange(order_book: &mut OrderBook, request: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        let (stream, _) = listener.accept().unwrap();
+        handle_connection(stream, order_book).unwrap();
+        let response = client.join().unwrap();
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    fn post(order_book: &mut OrderBook, body: &str) -> serde_json::Value {
+        let response = route(order_book, "POST", "/orders", body.as_bytes());
+        assert_eq!(response.status, 201);
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[test]
+    fn post_and_cancel_order() {
+        let mut order_book = OrderBook::new();
+        let placed = post(
+            &mut order_book,
+            r#"{"order_type":"GoodTilCancel","side":"Buy","price":122,"qty":5}"#,
+        );
+        let id = placed["id"].as_u64().unwrap();
+        assert_eq!(order_book.bids.len(), 1);
+
+        let response = route(&mut order_book, "DELETE", &format!("/orders/{}", id), b"");
+        assert_eq!(response.status, 200);
+        assert!(order_book.bids.is_empty());
+
+        let response = route(&mut order_book, "DELETE", &format!("/orders/{}", id), b"");
+        assert_eq!(response.status, 404);
+    }
+
+    #[test]
+    fn throttled_participant_can_still_cancel() {
+        let mut order_book = OrderBook::new();
+        order_book.risk_mut().throttle_mut().set_limit(
+            0,
+            RateLimit {
+                per_second: 0,
+                burst: 1,
+            },
+        );
+        let placed = post(
+            &mut order_book,
+            r#"{"order_type":"GoodTilCancel","side":"Buy","price":122,"qty":5}"#,
+        );
+        let refused = route(
+            &mut order_book,
+            "POST",
+            "/orders",
+            br#"{"order_type":"GoodTilCancel","side":"Buy","price":121,"qty":5}"#,
+        );
+        assert_ne!(refused.status, 201);
+        let target = format!("/orders/{}", placed["id"]);
+        let response = route(&mut order_book, "DELETE", &target, b"");
+        assert_eq!(response.status, 200);
+        assert!(order_book.bids.is_empty());
+    }
+
+    #[test]
+    fn limits_request_size() {
+        let mut order_book = OrderBook::new();
+        let request = |content_length: &str, body: &str| {
+            format!(
+                "POST /orders HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                content_length, body
+            )
+        };
+        let body = r#"{"order_type":"GoodTilCancel","side":"Buy","price":122,"qty":5}"#;
+        let status = exchange(&mut order_book, request(&body.len().to_string(), body));
+        assert_eq!(status, "HTTP/1.1 201 Created");
+        let status = exchange(&mut order_book, request("99999999999", ""));
+        assert_eq!(status, "HTTP/1.1 413 Payload Too Large");
+        let status = exchange(
+            &mut order_book,
+            request(&(MAX_BODY_LEN + 1).to_string(), ""),
+        );
+        assert_eq!(status, "HTTP/1.1 413 Payload Too Large");
+        let status = exchange(&mut order_book, request("five", ""));
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+        // A head that fills the limit without ending.
+        let mut head = "GET /trades HTTP/1.1\r\nX-Padding: ".to_string();
+        head.extend(std::iter::repeat_n(
+            'x',
+            MAX_HEAD_LEN as usize - head.len() - 2,
+        ));
+        head.push_str("\r\n");
+        let status = exchange(&mut order_book, head);
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+        assert_eq!(order_book.bids.len(), 1);
+    }
+
+    #[test]
+    fn drops_clients_that_trickle_a_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            // Each byte arrives well inside any single read timeout.
+            for byte in "GET /trades HTTP/1.1\r\nX-Padding: "
+                .bytes()
+                .cycle()
+                .take(200)
+            {
+                if stream.write_all(&[byte]).is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let start = Instant::now();
+        let error = read_request(&stream, start + Duration::from_millis(100)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        drop(stream);
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn trades_are_limited_by_default() {
+        let mut order_book = OrderBook::new();
+        for _ in 0..DEFAULT_TRADES_LIMIT + 1 {
+            for side in ["Sell", "Buy"] {
+                let body = format!(
+                    r#"{{"order_type":"GoodTilCancel","side":"{}","price":122,"qty":1}}"#,
+                    side
+                );
+                post(&mut order_book, &body);
+            }
+        }
+        assert_eq!(order_book.trades().len(), DEFAULT_TRADES_LIMIT + 1);
+        let response = route(&mut order_book, "GET", "/trades", b"");
+        let trades: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(trades.as_array().unwrap().len(), DEFAULT_TRADES_LIMIT);
+        assert_eq!(trades[0]["id"], 2);
+        let response = route(&mut order_book, "GET", "/trades?limit=500", b"");
+        let trades: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(trades.as_array().unwrap().len(), DEFAULT_TRADES_LIMIT + 1);
+    }
+
+    #[test]
+    fn depth_and_trades() {
+        let mut order_book = OrderBook::new();
+        post(
+            &mut order_book,
+            r#"{"order_type":"GoodTilCancel","side":"Sell","price":123,"qty":5}"#,
+        );
+        let placed = post(
+            &mut order_book,
+            r#"{"order_type":"GoodTilCancel","side":"Buy","price":124,"qty":2}"#,
+        );
+        assert_eq!(placed["trades"][0]["price"], 123);
+        assert_eq!(placed["trades"][0]["qty"], 2);
+
+        let response = route(&mut order_book, "GET", "/book/depth?levels=1", b"");
+        let depth: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(depth["asks"][0]["qty"], 3);
+        assert!(depth["bids"].as_array().unwrap().is_empty());
+
+        let response = route(&mut order_book, "GET", "/trades", b"");
+        let trades: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(trades.as_array().unwrap().len(), 1);
+
+        let response = route(&mut order_book, "GET", "/positions/0", b"");
+        let position: serde_json::Value = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(position["bought"], 2);
+        assert_eq!(position["sold"], 2);
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        let mut order_book = OrderBook::new();
+        assert_eq!(route(&mut order_book, "POST", "/orders", b"{}").status, 400);
+        order_book.risk_mut().set_default(crate::risk::RiskLimits {
+            max_order_qty: Some(1),
+            ..crate::risk::RiskLimits::default()
+        });
+        let body = r#"{"order_type":"GoodTilCancel","side":"Buy","price":1,"qty":2}"#;
+        assert_eq!(
+            route(&mut order_book, "POST", "/orders", body.as_bytes()).status,
+            422
+        );
+        assert_eq!(route(&mut order_book, "GET", "/orders", b"").status, 405);
+        assert_eq!(route(&mut order_book, "GET", "/nope", b"").status, 404);
+    }
+}
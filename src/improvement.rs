@@ -0,0 +1,148 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Price improvement auctions.
+//!
+//! With a window set by
+//! [`OrderBook::set_price_improvement`](crate::OrderBook::set_price_improvement),
+//! a new order that would trade on arrival is placed but held back from
+//! matching for the length of the window. Its `Placed` event starts the
+//! auction, and [`OrderBook::improvement_auctions`](crate::OrderBook::improvement_auctions)
+//! lists those running. Until the window closes, other participants can
+//! respond with
+//! [`OrderBook::respond_to_improvement`](crate::OrderBook::respond_to_improvement),
+//! offering size at a better price than the best opposite price the order
+//! arrived to.
+//!
+//! When the window closes the order trades against the responses first,
+//! best price first and then in the order they came in, each at its own
+//! price. Whatever is left of it then matches the book as it would have
+//! on arrival, and may rest. Responses that don't trade lapse. A held
+//! order can't be modified or canceled until its auction is over.
+
+use crate::risk::RejectReason;
+use crate::{Order, OrderId, ParticipantId, Price, Side, Timestamp};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ImprovementReject {
+    /// No auction is running for the order, or its window has closed.
+    UnknownAuction,
+    /// Not better than the best opposite price the order arrived to.
+    NotImproving,
+    /// Participants can't respond to their own orders.
+    OwnOrder,
+    /// Refused by the responder's pre-trade checks.
+    Risk(RejectReason),
+}
+
+/// Size offered to an auctioned order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Response<P = i32> {
+    /// Drawn from the same sequence as order ids, and the maker's order id
+    /// on any trade.
+    pub id: OrderId,
+    pub participant: ParticipantId,
+    pub price: P,
+    pub qty: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImprovementAuction<P = i32> {
+    /// The held order.
+    pub order: Order<P>,
+    /// The best opposite price when the order arrived.
+    pub reference: P,
+    pub ends_at: Timestamp,
+    /// In the order they came in.
+    pub responses: Vec<Response<P>>,
+}
+
+impl<P: Price> ImprovementAuction<P> {
+    pub fn improves(&self, price: P) -> bool {
+        match self.order.side {
+            Side::Buy => price < self.reference,
+            Side::Sell => price > self.reference,
+        }
+    }
+
+    /// The responses in allocation order: best price for the held order
+    /// first, then first in.
+    pub fn ranked(&self) -> Vec<Response<P>> {
+        let mut ranked = self.responses.clone();
+        match self.order.side {
+            Side::Buy => ranked.sort_by_key(|response| response.price),
+            Side::Sell => ranked.sort_by_key(|response| std::cmp::Reverse(response.price)),
+        }
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImprovementReject;
+    use crate::clock::ManualClock;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::time::Duration;
+
+    #[test]
+    fn responses_improve_on_the_book_before_it_fills() {
+        let clock = ManualClock::new();
+        let mut order_book = OrderBook::new();
+        order_book.set_clock(clock.clone());
+        order_book.set_price_improvement(Some(Duration::from_millis(100)));
+        let new = |order_book: &mut OrderBook, order_type, side, price, qty, participant| {
+            order_book.process_command(OrderCommand::New {
+                order_type,
+                side,
+                price,
+                qty,
+                participant,
+            })
+        };
+        new(
+            &mut order_book,
+            OrderType::GoodTilCancel,
+            Side::Sell,
+            105,
+            50,
+            1,
+        );
+        new(
+            &mut order_book,
+            OrderType::FillAndKill,
+            Side::Buy,
+            106,
+            40,
+            2,
+        );
+        assert!(order_book.trades().is_empty());
+        let id = order_book.improvement_auctions().next().unwrap().order.id;
+
+        assert_eq!(
+            order_book.respond_to_improvement(id, 3, 105, 10),
+            Err(ImprovementReject::NotImproving)
+        );
+        assert_eq!(
+            order_book.respond_to_improvement(id, 2, 103, 10),
+            Err(ImprovementReject::OwnOrder)
+        );
+        let late = order_book.respond_to_improvement(id, 3, 104, 20).unwrap();
+        let best = order_book.respond_to_improvement(id, 4, 103, 15).unwrap();
+
+        clock.advance(Duration::from_millis(100));
+        order_book.tick();
+        let fills: Vec<_> = order_book
+            .trades()
+            .iter()
+            .map(|trade| (trade.maker_order_id, trade.price, trade.qty))
+            .collect();
+        assert_eq!(fills, [(best, 103, 15), (late, 104, 20), (1, 105, 5)]);
+        assert_eq!(order_book.improvement_auctions().count(), 0);
+        assert_eq!(
+            order_book.respond_to_improvement(id, 3, 104, 20),
+            Err(ImprovementReject::UnknownAuction)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Anti-internalization groups.
+//!
+//! A firm running several participants, a desk per strategy say, may not
+//! want them trading with each other. Participants put in the same group
+//! with [`OrderBook::set_group`](crate::OrderBook::set_group) have their
+//! orders tagged with its key on entry, and an incoming order never trades
+//! with a resting order tagged with the same key. What happens instead is
+//! the book's [`Internalization`] setting. Participants outside any group,
+//! including a participant on its own, trade freely.
+//!
+//! Groups only apply to orders matching on entry. Auctions uncross the book
+//! without looking at them.
+
+use serde::Deserialize;
+
+pub type GroupKey = u32;
+
+/// What an incoming order does on reaching a resting order in its own
+/// group.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Internalization {
+    /// Trade past it with the orders behind it, leaving it where it was.
+    /// What is left of the incoming order is canceled rather than rested
+    /// across it.
+    Skip,
+    /// Cancel the resting order and carry on matching.
+    CancelPassive,
+    /// Stop matching and cancel what is left of the incoming order.
+    #[default]
+    CancelAggressive,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Internalization;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, ParticipantId, Side};
+
+    /// Participants 1 and 2 share a group; 3 is on its own.
+    fn book(internalization: Internalization) -> OrderBook {
+        let mut order_book = OrderBook::new();
+        order_book.set_group(1, Some(7));
+        order_book.set_group(2, Some(7));
+        order_book.set_internalization(internalization);
+        for (participant, price) in [(3, 101), (1, 102), (3, 102), (3, 103)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side: Side::Sell,
+                price,
+                qty: 10,
+                participant,
+            });
+        }
+        order_book
+    }
+
+    fn buy(order_book: &mut OrderBook) {
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 102,
+            qty: 30,
+            participant: 2,
+        });
+    }
+
+    fn makers(order_book: &OrderBook) -> Vec<ParticipantId> {
+        order_book
+            .trades()
+            .iter()
+            .map(|trade| trade.maker_participant)
+            .collect()
+    }
+
+    #[test]
+    fn cancels_either_side() {
+        let mut order_book = book(Internalization::CancelPassive);
+        buy(&mut order_book);
+        assert_eq!(makers(&order_book), [3, 3]);
+        assert!(order_book
+            .events()
+            .contains(&OrderEvent::Canceled { id: 2 }));
+        assert_eq!(order_book.best_bid(), Some(102));
+        assert_eq!(order_book.bids[0].total_qty(), 10);
+
+        let mut order_book = book(Internalization::CancelAggressive);
+        buy(&mut order_book);
+        assert_eq!(makers(&order_book), [3]);
+        assert_eq!(
+            order_book.events().last(),
+            Some(&OrderEvent::Canceled { id: 5 })
+        );
+        assert!(order_book.bids.is_empty());
+        assert_eq!(order_book.asks[0].orders[0].participant, 1);
+    }
+
+    #[test]
+    fn skips_without_losing_priority() {
+        let mut order_book = book(Internalization::Skip);
+        buy(&mut order_book);
+        assert_eq!(makers(&order_book), [3, 3]);
+        // Left at the front of its level, and the buy doesn't rest across
+        // it.
+        assert_eq!(order_book.asks[0].price, 102);
+        assert_eq!(order_book.asks[0].orders.len(), 1);
+        assert_eq!(order_book.asks[0].orders[0].participant, 1);
+        assert_eq!(order_book.asks[0].total_qty(), 10);
+        assert!(order_book.bids.is_empty());
+
+        // Participant 3 trades with it as usual.
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::FillAndKill,
+            side: Side::Buy,
+            price: 102,
+            qty: 5,
+            participant: 3,
+        });
+        assert_eq!(makers(&order_book), [3, 3, 1]);
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Per-stage latency breakdown.
+//!
+//! With tracking enabled the book times each stage of every command and
+//! keeps a histogram per [`Stage`]. A stage's sample is its total time within
+//! one command, so a command that fills against ten orders contributes one
+//! match lookup sample, not ten. Stages a command never enters record
+//! nothing.
+//!
+//! Histograms use 16 linear sub-buckets per power of two, so a reported
+//! percentile is within about 6% of the true value while the memory used
+//! stays fixed however many commands are recorded.
+
+use std::time::Duration;
+
+/// Sub-buckets per power of two.
+const SUB_BUCKETS: usize = 16;
+const SUB_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// Rate limiting and pre-trade risk checks.
+    Validation,
+    /// Finding the best opposite level and checking whether it crosses.
+    MatchLookup,
+    /// Filling orders, recording trades, and resting or removing orders.
+    Execution,
+    /// Building events and queuing them on the book.
+    EventEmission,
+    /// Writing the command's outcome out, e.g. to the audit log. The book
+    /// doesn't persist anything itself, so this is recorded by the writer.
+    Persistence,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::Validation,
+        Stage::MatchLookup,
+        Stage::Execution,
+        Stage::EventEmission,
+        Stage::Persistence,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    sum: u128,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            counts: vec![0; BUCKETS],
+            total: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.counts[bucket(nanos)] += 1;
+        self.total += 1;
+        self.sum += u128::from(nanos);
+        self.max = self.max.max(nanos);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.total {
+            0 => Duration::ZERO,
+            n => Duration::from_nanos((self.sum / u128::from(n)) as u64),
+        }
+    }
+
+    /// The value `percentile` percent of samples are at or below, rounded up
+    /// to the top of its bucket.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.total == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((percentile / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(bucket_top(index).min(self.max));
+            }
+        }
+        self.max()
+    }
+}
+
+fn bucket(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let exponent = 63 - nanos.leading_zeros();
+    let sub = (nanos >> (exponent - SUB_BITS)) as usize & (SUB_BUCKETS - 1);
+    (exponent - SUB_BITS + 1) as usize * SUB_BUCKETS + sub
+}
+
+/// Largest value that lands in bucket `index`.
+fn bucket_top(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let sub = (SUB_BUCKETS + index % SUB_BUCKETS) as u64;
+    ((sub + 1) << shift).wrapping_sub(1)
+}
+
+/// Percentiles for one stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageSummary {
+    pub stage: Stage,
+    pub count: u64,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub p999: Duration,
+    pub max: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    stages: [Histogram; 5],
+    /// Time spent in each stage by the command in progress.
+    pending: [Option<Duration>; 5],
+}
+
+impl LatencyStats {
+    pub fn new() -> LatencyStats {
+        LatencyStats::default()
+    }
+
+    /// Records one sample for `stage` directly.
+    pub fn record(&mut self, stage: Stage, duration: Duration) {
+        self.stages[stage.index()].record(duration);
+    }
+
+    pub fn histogram(&self, stage: Stage) -> &Histogram {
+        &self.stages[stage.index()]
+    }
+
+    pub fn summary(&self, stage: Stage) -> StageSummary {
+        let histogram = self.histogram(stage);
+        StageSummary {
+            stage,
+            count: histogram.count(),
+            mean: histogram.mean(),
+            p50: histogram.percentile(50.0),
+            p90: histogram.percentile(90.0),
+            p99: histogram.percentile(99.0),
+            p999: histogram.percentile(99.9),
+            max: histogram.max(),
+        }
+    }
+
+    /// One summary per stage, in pipeline order.
+    pub fn summaries(&self) -> Vec<StageSummary> {
+        Stage::ALL
+            .iter()
+            .map(|&stage| self.summary(stage))
+            .collect()
+    }
+
+    /// Adds to the current command's time in `stage`.
+    pub(crate) fn add(&mut self, stage: Stage, duration: Duration) {
+        let pending = &mut self.pending[stage.index()];
+        *pending = Some(pending.unwrap_or_default() + duration);
+    }
+
+    /// Records the current command's time in each stage it entered.
+    pub(crate) fn finish_command(&mut self) {
+        for stage in Stage::ALL {
+            if let Some(duration) = self.pending[stage.index()].take() {
+                self.record(stage, duration);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bucket, bucket_top, Histogram, Stage, BUCKETS};
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::time::Duration;
+
+    #[test]
+    fn buckets_cover_every_value() {
+        for nanos in (0..5_000).chain([u64::MAX / 3, u64::MAX]) {
+            let index = bucket(nanos);
+            assert!(index < BUCKETS);
+            assert!(
+                nanos <= bucket_top(index),
+                "{} above bucket {}",
+                nanos,
+                index
+            );
+            if index > 0 {
+                assert!(
+                    nanos > bucket_top(index - 1),
+                    "{} below bucket {}",
+                    nanos,
+                    index
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn percentiles_within_bucket_precision() {
+        let mut histogram = Histogram::default();
+        for micros in 1..=1_000 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        assert_eq!(histogram.count(), 1_000);
+        assert_eq!(histogram.max(), Duration::from_micros(1_000));
+        for (percentile, micros) in [(50.0, 500.0), (99.0, 990.0), (100.0, 1_000.0)] {
+            let reported = histogram.percentile(percentile).as_secs_f64() * 1e6;
+            assert!(
+                reported >= micros && reported <= micros * 1.07,
+                "p{} = {}us",
+                percentile,
+                reported
+            );
+        }
+        assert_eq!(Histogram::default().percentile(50.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn book_records_stages_per_command() {
+        let mut order_book = OrderBook::new();
+        order_book.set_latency_tracking(true);
+        for (side, qty) in [(Side::Sell, 1), (Side::Sell, 1), (Side::Buy, 2)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price: 100,
+                qty,
+                participant: 1,
+            });
+        }
+        let latency = order_book.latency().unwrap();
+        assert_eq!(latency.histogram(Stage::Validation).count(), 3);
+        assert_eq!(latency.histogram(Stage::MatchLookup).count(), 3);
+        assert_eq!(latency.histogram(Stage::Execution).count(), 3);
+        assert_eq!(latency.histogram(Stage::EventEmission).count(), 3);
+        assert_eq!(latency.histogram(Stage::Persistence).count(), 0);
+
+        // A cancel for an unknown order is still checked but never matches.
+        order_book.process_command(OrderCommand::Cancel {
+            id: 99,
+            side: Side::Buy,
+            price: 100,
+        });
+        let latency = order_book.latency().unwrap();
+        assert_eq!(latency.histogram(Stage::Validation).count(), 4);
+        assert_eq!(latency.histogram(Stage::MatchLookup).count(), 3);
+
+        order_book.set_latency_tracking(false);
+        assert!(order_book.latency().is_none());
+    }
+}
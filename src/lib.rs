@@ -0,0 +1,488 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+use circuit_breaker::TradingStatus;
+use risk::protection::ProtectionTrigger;
+use risk::RejectReason;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Logs from the matching path at `$level`. Expands to nothing in release
+/// builds without the `trace` feature, so the arguments are never formatted
+/// and the callsite costs nothing.
+macro_rules! hot_trace {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(any(debug_assertions, feature = "trace"))]
+        tracing::$level!($($arg)+);
+    };
+}
+
+pub mod amendments;
+pub mod analytics;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod auction;
+pub mod audit;
+pub mod backend;
+pub mod backtest;
+pub mod candles;
+pub mod circuit_breaker;
+pub mod clearing;
+pub mod clock;
+pub mod codec;
+pub mod crosses;
+pub mod currency;
+pub mod dark;
+pub mod fees;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod gateway;
+#[cfg(test)]
+mod golden;
+pub mod heatmap;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod improvement;
+pub mod internalization;
+pub mod latency;
+pub mod lifecycle;
+pub mod limit;
+pub mod liquidity;
+pub mod market_data;
+pub mod mass_quote;
+pub mod metrics;
+#[cfg(feature = "napi")]
+pub mod napi;
+pub mod odd_lots;
+pub mod order_book;
+pub mod persistence;
+pub mod positions;
+pub mod price;
+pub mod priority;
+#[cfg(test)]
+mod properties;
+#[cfg(test)]
+mod reference;
+pub mod repl;
+pub mod replay;
+pub mod rfq;
+pub mod risk;
+pub mod sandbox;
+#[cfg(test)]
+mod scenario;
+pub mod settlement;
+pub mod sim;
+pub mod sink;
+pub mod snapshot;
+pub mod spreads;
+pub mod stats;
+pub mod stops;
+pub mod surveillance;
+pub mod tape;
+#[cfg(test)]
+mod testing;
+pub mod timer_wheel;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "zmq")]
+pub mod zmq;
+
+pub use currency::Currency;
+pub use internalization::GroupKey;
+pub use mass_quote::QuoteEntry;
+pub use matcher_core::Side;
+pub use order_book::OrderBook;
+pub use price::Price;
+pub use priority::PriorityClass;
+
+/// Identifies the firm or account an order belongs to.
+pub type ParticipantId = u32;
+
+/// Identifies an order within its book.
+pub type OrderId = u64;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
+pub enum OrderCommand<P = i32> {
+    New {
+        order_type: OrderType,
+        side: Side,
+        price: P,
+        qty: u32,
+        participant: ParticipantId,
+    },
+    /// Replaces the resting order `id` on `side` with one for `qty` at
+    /// `price`, which may differ from the price it rests at.
+    Modify {
+        id: OrderId,
+        price: P,
+        side: Side,
+        qty: u32,
+        order_type: OrderType,
+    },
+    Cancel {
+        id: OrderId,
+        side: Side,
+        price: P,
+    },
+    /// Administrative: with `engage` set, cancels all of the participant's
+    /// resting orders, lit and dark, and rejects any further orders from them until the
+    /// switch is released again.
+    KillSwitch {
+        participant: ParticipantId,
+        engage: bool,
+    },
+    /// Administrative: stops matching until the book is resumed.
+    Halt,
+    /// Administrative: reopens a halted book, with a reopening auction if
+    /// `auction` is set.
+    Resume {
+        auction: bool,
+    },
+    /// Administrative: reverses a reported trade. With `restore` set, the
+    /// busted quantity goes back on either order that is still resting.
+    BustTrade {
+        trade_id: usize,
+        restore: bool,
+    },
+    /// Sets or replaces a market maker's two-sided quotes in one or more
+    /// symbols at once; see [`mass_quote`].
+    MassQuote {
+        participant: ParticipantId,
+        quotes: Vec<QuoteEntry<P>>,
+    },
+    /// Reports a trade `buyer` and `seller` negotiated away from the book,
+    /// for `qty` at `price`. It prints on the tape without touching the
+    /// book; see [`crosses`].
+    Cross {
+        buyer: ParticipantId,
+        seller: ParticipantId,
+        price: P,
+        qty: u32,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
+pub enum OrderEvent<P = i32> {
+    Placed {
+        id: OrderId,
+        participant: ParticipantId,
+        side: Side,
+        order_type: OrderType,
+        price: P,
+        timestamp: Timestamp,
+        /// The order's arrival sequence; see [`Order::arrival`].
+        arrival: u64,
+    },
+    Modified,
+    Canceled {
+        id: OrderId,
+    },
+    PartiallyFilled {
+        id: OrderId,
+        price: P,
+        qty: u32,
+        timestamp: Timestamp,
+    },
+    Filled {
+        id: OrderId,
+        price: P,
+        timestamp: Timestamp,
+    },
+    Trade(Trade<P>),
+    /// A new order turned away by pre-trade checks; it never reached the book.
+    Rejected {
+        participant: ParticipantId,
+        side: Side,
+        price: P,
+        qty: u32,
+        reason: RejectReason,
+    },
+    /// A participant's kill switch was engaged or released.
+    KillSwitch {
+        participant: ParticipantId,
+        engaged: bool,
+    },
+    StatusChanged {
+        status: TradingStatus,
+    },
+    /// `trade` was busted and no longer counts. `restored` is set if its
+    /// quantity was put back on the orders still resting.
+    TradeBust {
+        trade: Trade<P>,
+        restored: bool,
+    },
+    /// A mass quote set one side of a participant's quote: order `id` now
+    /// rests for `qty` at `price`, or would have before matching. An id that
+    /// quoted that side before kept its place in the queue.
+    Quoted {
+        id: OrderId,
+        participant: ParticipantId,
+        side: Side,
+        price: P,
+        qty: u32,
+        timestamp: Timestamp,
+        /// Of the order now quoting; unchanged if it kept its place.
+        arrival: u64,
+    },
+    /// Fills on the participant's quotes reached a protection limit, and
+    /// the quotes they had left are pulled; see [`risk::protection`].
+    ProtectionTriggered {
+        participant: ParticipantId,
+        trigger: ProtectionTrigger,
+        timestamp: Timestamp,
+    },
+    /// The closing session's settlement price; see [`settlement`].
+    Settled {
+        price: P,
+        timestamp: Timestamp,
+    },
+    /// The trading session ended; see [`OrderBook::end_of_day`].
+    SessionEnded {
+        timestamp: Timestamp,
+    },
+    /// A good-til-cancel order saved at shutdown rests again for `qty` at
+    /// `price`, with the arrival it was saved with; see [`persistence`].
+    Restored {
+        id: OrderId,
+        participant: ParticipantId,
+        side: Side,
+        price: P,
+        qty: u32,
+        timestamp: Timestamp,
+        arrival: u64,
+    },
+}
+
+/// A single execution between a resting (maker) order and an incoming
+/// (taker) order. Trades in the lit book print at the maker's price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Trade<P = i32> {
+    pub id: usize,
+    pub price: P,
+    pub qty: u32,
+    pub aggressor_side: Side,
+    pub maker_order_id: OrderId,
+    pub taker_order_id: OrderId,
+    pub maker_participant: ParticipantId,
+    pub taker_participant: ParticipantId,
+    pub timestamp: Timestamp,
+    /// Fee units charged to each side; see [`fees`]. Zero, and left out of
+    /// JSON, unless the book has a fee schedule.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub maker_fee: i64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub taker_fee: i64,
+    /// Reported with [`OrderCommand::Cross`] rather than matched: the
+    /// seller is the maker, neither side has an order id, and the buyer is
+    /// taken as the aggressor. Left out of JSON unless set.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub cross: bool,
+    /// Matched in the dark book at the lit midpoint; see [`dark`]. Left out
+    /// of JSON unless set.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub dark: bool,
+    /// What `price` is quoted in, the book's currency; see [`currency`].
+    #[serde(default)]
+    pub currency: Currency,
+}
+
+fn is_zero(fee: &i64) -> bool {
+    *fee == 0
+}
+
+fn is_false(flag: &bool) -> bool {
+    !*flag
+}
+
+/// Wall-clock time in nanoseconds since the Unix epoch. Unlike `Instant`
+/// it means the same thing in every process, so it is what orders, trades
+/// and events are stamped with. `Instant` is kept for measuring latency.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub const fn from_nanos(nanos: u64) -> Timestamp {
+        Timestamp(nanos)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn now() -> Timestamp {
+        let since_epoch = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        Timestamp(since_epoch.as_nanos() as u64)
+    }
+
+    /// wasm32 has no system clock, so the host supplies one as the import
+    /// `env.matcher_now_millis`; `web/matcher.js` passes `Date.now`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn now() -> Timestamp {
+        extern "C" {
+            fn matcher_now_millis() -> f64;
+        }
+        let millis = unsafe { matcher_now_millis() };
+        Timestamp((millis * 1_000_000.0) as u64)
+    }
+
+    pub const fn as_nanos(self) -> u64 {
+        self.0
+    }
+
+    /// Time from `earlier` to `self`, or zero if `earlier` is later.
+    pub fn saturating_duration_since(self, earlier: Timestamp) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+}
+
+/// Saturates at the latest representable time.
+impl std::ops::Add<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, duration: Duration) -> Timestamp {
+        Timestamp(self.0.saturating_add(nanos(duration)))
+    }
+}
+
+/// Saturates at the epoch.
+impl std::ops::Sub<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn sub(self, duration: Duration) -> Timestamp {
+        Timestamp(self.0.saturating_sub(nanos(duration)))
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// Saturates at zero, like `Instant`'s.
+impl std::ops::Sub for Timestamp {
+    type Output = Duration;
+
+    fn sub(self, earlier: Timestamp) -> Duration {
+        self.saturating_duration_since(earlier)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Order<P = i32> {
+    pub id: OrderId,
+    pub participant: ParticipantId,
+    pub order_type: OrderType,
+    pub side: Side,
+    pub price: P,
+    pub initial_qty: u32,
+    pub remaining_qty: u32,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    /// Stamped by the book when it accepts the order, strictly increasing
+    /// across every order it accepts. Timestamps can collide; this can't,
+    /// so it settles which of two orders came first. Zero until stamped.
+    pub arrival: u64,
+    /// Orders in the same anti-internalization group never trade with each
+    /// other; see [`internalization`].
+    pub group: Option<GroupKey>,
+    /// Orders of a higher class are queued ahead at their price; see
+    /// [`priority`].
+    pub class: PriorityClass,
+}
+
+impl<P> Order<P> {
+    /// An order entered at `at`, which a book takes from its
+    /// [`Clock`](clock::Clock) so orders and events agree on the time.
+    pub fn new(
+        id: OrderId,
+        order_type: OrderType,
+        side: Side,
+        price: P,
+        qty: u32,
+        at: Timestamp,
+    ) -> Order<P> {
+        Order {
+            id,
+            participant: 0,
+            order_type,
+            side,
+            price,
+            initial_qty: qty,
+            remaining_qty: qty,
+            created_at: at,
+            updated_at: at,
+            arrival: 0,
+            group: None,
+            class: 0,
+        }
+    }
+
+    /// Reduces the remaining quantity by `qty` at time `at`. Fails without
+    /// touching the order if `qty` is more than what is left.
+    #[allow(clippy::result_unit_err)]
+    pub fn fill(&mut self, qty: u32, at: Timestamp) -> Result<(), ()> {
+        if qty > self.remaining_qty {
+            return Err(());
+        }
+        self.remaining_qty -= qty;
+        self.updated_at = at;
+        Ok(())
+    }
+
+    pub fn is_filled(&self) -> bool {
+        self.remaining_qty == 0
+    }
+}
+
+impl<P: Price> matcher_core::Resting for Order<P> {
+    type Time = Timestamp;
+    type Price = P;
+
+    fn id(&self) -> OrderId {
+        self.id
+    }
+
+    fn side(&self) -> Side {
+        self.side
+    }
+
+    fn price(&self) -> P {
+        self.price
+    }
+
+    fn remaining_qty(&self) -> u32 {
+        self.remaining_qty
+    }
+
+    fn fill(&mut self, qty: u32, at: Timestamp) {
+        self.remaining_qty -= qty;
+        self.updated_at = at;
+    }
+
+    fn restore(&mut self, qty: u32) {
+        self.remaining_qty += qty;
+    }
+
+    /// What has already filled still counts toward the initial quantity.
+    fn resize(&mut self, qty: u32) {
+        self.initial_qty = self.initial_qty - self.remaining_qty + qty;
+        self.remaining_qty = qty;
+    }
+
+    fn priority(&self) -> u8 {
+        self.class
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Ord, PartialOrd)]
+pub enum OrderType {
+    FillAndKill,
+    GoodTilCancel,
+    /// Rests like `GoodTilCancel` until [`OrderBook::end_of_day`] expires
+    /// it.
+    Day,
+}
@@ -0,0 +1,194 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Order lifecycle.
+//!
+//! Every order the book accepts moves through [`OrderState`]s as it is
+//! matched, rested and taken off again, and the book keeps each order's
+//! current state by id. A transition the state machine doesn't allow is
+//! refused and the order keeps the state it had; the book logs it, since it
+//! means the book and the events it emitted disagree.
+//!
+//! ```text
+//! New ──> Working ──> PartiallyFilled ──> Filled
+//!  │         │              │
+//!  │         └──────────────┴──> Canceled / Expired
+//!  └──> PartiallyFilled / Filled / Canceled / Rejected
+//! ```
+//!
+//! A busted fill given back to a resting order can also take it from
+//! `PartiallyFilled` back to `Working`. Terminal states are kept so late
+//! lookups still find them, until [`OrderBook::end_of_day`] drops them so
+//! the map holds at most one session's finished orders.
+//!
+//! [`OrderBook::end_of_day`]: crate::OrderBook::end_of_day
+
+use crate::OrderId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OrderState {
+    /// Accepted and matching on entry.
+    New,
+    /// Resting untouched.
+    Working,
+    /// Has traded and has quantity left.
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    /// Canceled by its expiry timer.
+    Expired,
+    /// Turned away. The book rejects orders before giving them an id, so
+    /// it never reports this itself; it is here for front ends that track
+    /// orders before they reach the book.
+    Rejected,
+}
+
+impl OrderState {
+    /// Whether the order is done with and can't change state again.
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            OrderState::Filled | OrderState::Canceled | OrderState::Expired | OrderState::Rejected
+        )
+    }
+
+    /// Whether an order in this state can move to `next`.
+    pub fn can_become(self, next: OrderState) -> bool {
+        use OrderState::*;
+        matches!(
+            (self, next),
+            (
+                New,
+                Working | PartiallyFilled | Filled | Canceled | Rejected
+            ) | (Working, PartiallyFilled | Filled | Canceled | Expired)
+                | (
+                    PartiallyFilled,
+                    Working | PartiallyFilled | Filled | Canceled | Expired
+                )
+        )
+    }
+}
+
+/// A transition [`OrderState::can_become`] doesn't allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub id: OrderId,
+    /// `None` for an order that was never entered.
+    pub from: Option<OrderState>,
+    pub to: OrderState,
+}
+
+/// The current state of every order seen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Lifecycle {
+    states: HashMap<OrderId, OrderState>,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self, id: OrderId) -> Option<OrderState> {
+        self.states.get(&id).copied()
+    }
+
+    /// Starts tracking a newly accepted order. Fails if the id is taken.
+    pub fn enter(&mut self, id: OrderId) -> Result<(), InvalidTransition> {
+        if let Some(&from) = self.states.get(&id) {
+            return Err(InvalidTransition {
+                id,
+                from: Some(from),
+                to: OrderState::New,
+            });
+        }
+        self.states.insert(id, OrderState::New);
+        Ok(())
+    }
+
+    /// Moves the order to `next`, leaving it where it was if that isn't
+    /// allowed.
+    pub fn advance(&mut self, id: OrderId, next: OrderState) -> Result<(), InvalidTransition> {
+        let from = self.states.get(&id).copied();
+        match from {
+            Some(state) if state.can_become(next) => {
+                self.states.insert(id, next);
+                Ok(())
+            }
+            _ => Err(InvalidTransition { id, from, to: next }),
+        }
+    }
+
+    /// Forgets every order in a terminal state, returning how many there
+    /// were.
+    pub fn prune_terminal(&mut self) -> usize {
+        let before = self.states.len();
+        self.states.retain(|_, state| !state.is_terminal());
+        before - self.states.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InvalidTransition, Lifecycle, OrderState};
+    use crate::{OrderBook, OrderCommand, OrderType, Side, Timestamp};
+
+    #[test]
+    fn refuses_invalid_transitions() {
+        let mut lifecycle = Lifecycle::new();
+        lifecycle.enter(1).unwrap();
+        lifecycle.advance(1, OrderState::Working).unwrap();
+        lifecycle.advance(1, OrderState::Filled).unwrap();
+        assert_eq!(
+            lifecycle.advance(1, OrderState::Canceled),
+            Err(InvalidTransition {
+                id: 1,
+                from: Some(OrderState::Filled),
+                to: OrderState::Canceled
+            })
+        );
+        assert_eq!(lifecycle.state(1), Some(OrderState::Filled));
+        assert!(lifecycle.enter(1).is_err());
+        assert!(lifecycle.advance(2, OrderState::Working).is_err());
+        assert!(!OrderState::New.can_become(OrderState::Expired));
+
+        lifecycle.enter(2).unwrap();
+        assert_eq!(lifecycle.prune_terminal(), 1);
+        assert_eq!(lifecycle.prune_terminal(), 0);
+        assert_eq!(lifecycle.state(1), None);
+        assert_eq!(lifecycle.state(2), Some(OrderState::New));
+    }
+
+    #[test]
+    fn the_book_tracks_each_order() {
+        let mut order_book = OrderBook::new();
+        let mut new = |order_type, side, qty| {
+            order_book.process_command(OrderCommand::New {
+                order_type,
+                side,
+                price: 100,
+                qty,
+                participant: 1,
+            })
+        };
+        new(OrderType::GoodTilCancel, Side::Sell, 10);
+        new(OrderType::GoodTilCancel, Side::Sell, 10);
+        new(OrderType::FillAndKill, Side::Buy, 15);
+        new(OrderType::FillAndKill, Side::Buy, 20);
+        new(OrderType::GoodTilCancel, Side::Buy, 10);
+        assert_eq!(order_book.order_state(5), Some(OrderState::Working));
+        order_book.expire_at(5, Timestamp::from_nanos(0));
+        order_book.tick();
+
+        let state = |id| order_book.order_state(id);
+        assert_eq!(state(1), Some(OrderState::Filled));
+        assert_eq!(state(2), Some(OrderState::Filled));
+        assert_eq!(state(3), Some(OrderState::Filled));
+        assert_eq!(state(4), Some(OrderState::Canceled));
+        assert_eq!(state(5), Some(OrderState::Expired));
+        assert_eq!(state(6), None);
+    }
+}
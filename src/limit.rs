@@ -3,73 +3,129 @@
 // license that can be found in the LICENSE file.
 
 use crate::Order;
-use std::collections::VecDeque;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
-pub struct Limit {
-    pub price: i32,
-    pub orders: VecDeque<Order>,
-}
-
-impl Limit {
-    pub fn new(price: i32) -> Self {
-        Limit {
-            price,
-            orders: VecDeque::new(),
-        }
-    }
+/// A price level of the book. See [`matcher_core::Level`].
+pub type Limit<P = i32> = matcher_core::Level<Order<P>>;
 
-    pub fn find_by_id(&self, id: usize) -> Option<usize> {
-        if let Some(pos) = self.orders.iter().position(|x| x.id == id) {
-            Some(pos)
-        } else {
-            None
-        }
-    }
-
-    pub fn remove_order_by_id(&mut self, id: usize) -> bool {
-        if let Some(order_pos) = self.find_by_id(id) {
-            self.orders.remove(order_pos).is_some()
-        } else {
-            false
-        }
-    }
-}
 #[cfg(test)]
 mod tests {
-    use crate::Order;
+    use crate::{Order, Timestamp};
 
     use super::Limit;
 
     #[test]
     fn test_remove_by_id() {
         let mut limit = Limit::new(10);
-        let order = Order::new(crate::OrderType::GoodTilCancel, crate::Side::Buy, 10, 1);
-        limit.orders.push_back(order.clone());
-        let order = Order::new(crate::OrderType::GoodTilCancel, crate::Side::Buy, 10, 1);
-        limit.orders.push_back(order.clone());
-        let order = Order::new(crate::OrderType::GoodTilCancel, crate::Side::Buy, 10, 1);
-        limit.orders.push_back(order.clone());
-        let order1 = Order::new(crate::OrderType::GoodTilCancel, crate::Side::Buy, 10, 1);
-        limit.orders.push_back(order1.clone());
+        let order = Order::new(
+            1,
+            crate::OrderType::GoodTilCancel,
+            crate::Side::Buy,
+            10,
+            1,
+            Timestamp::from_nanos(0),
+        );
+        limit.push_back(order.clone());
+        let order = Order::new(
+            2,
+            crate::OrderType::GoodTilCancel,
+            crate::Side::Buy,
+            10,
+            1,
+            Timestamp::from_nanos(0),
+        );
+        limit.push_back(order.clone());
+        let order = Order::new(
+            3,
+            crate::OrderType::GoodTilCancel,
+            crate::Side::Buy,
+            10,
+            1,
+            Timestamp::from_nanos(0),
+        );
+        limit.push_back(order.clone());
+        let order1 = Order::new(
+            4,
+            crate::OrderType::GoodTilCancel,
+            crate::Side::Buy,
+            10,
+            1,
+            Timestamp::from_nanos(0),
+        );
+        limit.push_back(order1.clone());
 
         let removed = limit.remove_order_by_id(order1.id);
-        assert_eq!(removed, true)
+        assert!(removed)
     }
 
     #[test]
     fn test_find_by_id() {
         let mut limit = Limit::new(10);
-        let order = Order::new(crate::OrderType::GoodTilCancel, crate::Side::Buy, 10, 1);
-        limit.orders.push_back(order.clone());
-        let order = Order::new(crate::OrderType::GoodTilCancel, crate::Side::Buy, 10, 1);
-        limit.orders.push_back(order.clone());
-        let order = Order::new(crate::OrderType::GoodTilCancel, crate::Side::Buy, 10, 1);
-        limit.orders.push_back(order.clone());
-        let order = Order::new(crate::OrderType::GoodTilCancel, crate::Side::Buy, 10, 1);
-        limit.orders.push_back(order.clone());
+        let order = Order::new(
+            1,
+            crate::OrderType::GoodTilCancel,
+            crate::Side::Buy,
+            10,
+            1,
+            Timestamp::from_nanos(0),
+        );
+        limit.push_back(order.clone());
+        let order = Order::new(
+            2,
+            crate::OrderType::GoodTilCancel,
+            crate::Side::Buy,
+            10,
+            1,
+            Timestamp::from_nanos(0),
+        );
+        limit.push_back(order.clone());
+        let order = Order::new(
+            3,
+            crate::OrderType::GoodTilCancel,
+            crate::Side::Buy,
+            10,
+            1,
+            Timestamp::from_nanos(0),
+        );
+        limit.push_back(order.clone());
+        let order = Order::new(
+            4,
+            crate::OrderType::GoodTilCancel,
+            crate::Side::Buy,
+            10,
+            1,
+            Timestamp::from_nanos(0),
+        );
+        limit.push_back(order.clone());
 
         let pos = limit.find_by_id(order.id);
         assert_eq!(pos, Some(3usize))
     }
+
+    #[test]
+    fn tracks_total_qty() {
+        let mut limit = Limit::new(10);
+        let first = Order::new(
+            1,
+            crate::OrderType::GoodTilCancel,
+            crate::Side::Buy,
+            10,
+            5,
+            Timestamp::from_nanos(0),
+        );
+        let second = Order::new(
+            2,
+            crate::OrderType::GoodTilCancel,
+            crate::Side::Buy,
+            10,
+            3,
+            Timestamp::from_nanos(0),
+        );
+        limit.push_back(first);
+        limit.push_back(second.clone());
+        assert_eq!(limit.total_qty(), 8);
+        assert_eq!(limit.fill_front(2, Timestamp::now()).remaining_qty, 3);
+        assert_eq!(limit.total_qty(), 6);
+        assert!(limit.remove_order_by_id(second.id));
+        assert_eq!(limit.total_qty(), 3);
+    }
 }
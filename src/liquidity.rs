@@ -0,0 +1,333 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Rolling liquidity measures.
+//!
+//! A [`LiquidityMonitor`] is updated with the book after each command. It
+//! picks up the trades the command printed, prices them against the
+//! midpoint the book showed before it, and follows the book for a while
+//! afterwards to see how far the price moved and how quickly size came
+//! back. Each measure is averaged over the most recent observations:
+//!
+//! - *Effective spread*: `2 * d * (price - mid)` per trade, where `d` is 1
+//!   when the buyer was the aggressor and -1 when the seller was, weighted
+//!   by quantity. What a taker paid over the midpoint, round trip.
+//! - *Realized spread*: the same against the midpoint `horizon` after the
+//!   trade, which is what the maker kept once the price had moved.
+//! - *Resilience*: for each command that traded, the quantity within
+//!   `depth_ticks` of the touch on both sides `horizon` later over what it
+//!   was just before. 1 means the book fully refilled.
+//! - *Kyle's lambda*: the slope of midpoint changes on signed traded
+//!   quantity, command by command, fitted through the origin. Ticks of
+//!   price impact per unit bought.
+//!
+//! All are in ticks, and `None` until there is something to go on.
+
+use crate::{OrderBook, Side, Timestamp};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiquidityConfig {
+    /// How long after a trade the realized spread and resilience are taken.
+    pub horizon: Duration,
+    /// How many observations each measure averages over; 0 is taken as 1.
+    pub window: usize,
+    /// How far from the touch counts as near-touch depth.
+    pub depth_ticks: u32,
+}
+
+impl Default for LiquidityConfig {
+    fn default() -> Self {
+        LiquidityConfig {
+            horizon: Duration::from_secs(5),
+            window: 100,
+            depth_ticks: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct LiquidityMeasures {
+    pub effective_spread: Option<f64>,
+    pub realized_spread: Option<f64>,
+    pub resilience: Option<f64>,
+    pub kyle_lambda: Option<f64>,
+}
+
+/// A command's trades, waiting for their horizon.
+#[derive(Debug, Clone, PartialEq)]
+struct Pending {
+    due: Timestamp,
+    /// Direction, price and quantity of each trade.
+    trades: Vec<(f64, i32, u32)>,
+    depth_before: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiquidityMonitor {
+    config: LiquidityConfig,
+    /// The id of the last trade seen.
+    last_trade: Option<usize>,
+    mid: Option<f64>,
+    depth: u64,
+    pending: VecDeque<Pending>,
+    /// Spread and quantity per trade.
+    effective: VecDeque<(f64, u32)>,
+    realized: VecDeque<(f64, u32)>,
+    resilience: VecDeque<f64>,
+    /// Signed quantity and the midpoint change it went with, per command.
+    impact: VecDeque<(f64, f64)>,
+}
+
+impl LiquidityMonitor {
+    pub fn new(config: LiquidityConfig) -> Self {
+        LiquidityMonitor {
+            config,
+            last_trade: None,
+            mid: None,
+            depth: 0,
+            pending: VecDeque::new(),
+            effective: VecDeque::new(),
+            realized: VecDeque::new(),
+            resilience: VecDeque::new(),
+            impact: VecDeque::new(),
+        }
+    }
+
+    /// Takes in the book after a command.
+    pub fn update(&mut self, order_book: &OrderBook) {
+        let now = order_book.now();
+        let mid = midpoint(order_book);
+        let depth = self.near_touch_depth(order_book);
+
+        let tape = order_book.trades();
+        let start = match self.last_trade {
+            Some(last) => tape.partition_point(|trade| trade.id <= last),
+            None => 0,
+        };
+        let trades: Vec<(f64, i32, u32)> = tape[start..]
+            .iter()
+            .map(|trade| {
+                let direction = match trade.aggressor_side {
+                    Side::Buy => 1.0,
+                    Side::Sell => -1.0,
+                };
+                (direction, trade.price, trade.qty)
+            })
+            .collect();
+        if let Some(trade) = tape.last() {
+            self.last_trade = Some(trade.id);
+        }
+
+        if let (false, Some(before)) = (trades.is_empty(), self.mid) {
+            for &(direction, price, qty) in &trades {
+                let spread = 2.0 * direction * (f64::from(price) - before);
+                push(&mut self.effective, (spread, qty), self.config.window);
+            }
+            if let Some(after) = mid {
+                let signed: f64 = trades.iter().map(|&(d, _, qty)| d * f64::from(qty)).sum();
+                push(
+                    &mut self.impact,
+                    (signed, after - before),
+                    self.config.window,
+                );
+            }
+            self.pending.push_back(Pending {
+                due: now + self.config.horizon,
+                trades,
+                depth_before: self.depth,
+            });
+        }
+
+        while self
+            .pending
+            .front()
+            .is_some_and(|pending| pending.due <= now)
+        {
+            let pending = self.pending.pop_front().unwrap();
+            if let Some(mid) = mid {
+                for (direction, price, qty) in pending.trades {
+                    let spread = 2.0 * direction * (f64::from(price) - mid);
+                    push(&mut self.realized, (spread, qty), self.config.window);
+                }
+            }
+            if pending.depth_before > 0 {
+                let ratio = depth as f64 / pending.depth_before as f64;
+                push(&mut self.resilience, ratio, self.config.window);
+            }
+        }
+
+        self.mid = mid;
+        self.depth = depth;
+    }
+
+    pub fn measures(&self) -> LiquidityMeasures {
+        let weighted = |spreads: &VecDeque<(f64, u32)>| {
+            let qty: f64 = spreads.iter().map(|&(_, qty)| f64::from(qty)).sum();
+            let total: f64 = spreads
+                .iter()
+                .map(|&(spread, qty)| spread * f64::from(qty))
+                .sum();
+            (qty > 0.0).then(|| total / qty)
+        };
+        let resilience = (!self.resilience.is_empty())
+            .then(|| self.resilience.iter().sum::<f64>() / self.resilience.len() as f64);
+        let variance: f64 = self.impact.iter().map(|&(q, _)| q * q).sum();
+        let covariance: f64 = self.impact.iter().map(|&(q, dm)| q * dm).sum();
+        LiquidityMeasures {
+            effective_spread: weighted(&self.effective),
+            realized_spread: weighted(&self.realized),
+            resilience,
+            kyle_lambda: (variance > 0.0).then(|| covariance / variance),
+        }
+    }
+
+    fn near_touch_depth(&self, order_book: &OrderBook) -> u64 {
+        [Side::Buy, Side::Sell]
+            .into_iter()
+            .map(|side| order_book.quantity_within(side, self.config.depth_ticks))
+            .sum()
+    }
+}
+
+fn midpoint(order_book: &OrderBook) -> Option<f64> {
+    let (bid, ask) = (order_book.best_bid()?, order_book.best_ask()?);
+    Some((f64::from(bid) + f64::from(ask)) / 2.0)
+}
+
+fn push<T>(window: &mut VecDeque<T>, value: T, len: usize) {
+    if window.len() >= len.max(1) {
+        window.pop_front();
+    }
+    window.push_back(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LiquidityConfig, LiquidityMeasures, LiquidityMonitor};
+    use crate::clock::ManualClock;
+    use crate::testing::new_order;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::time::Duration;
+
+    #[test]
+    fn measures_what_a_sweep_cost_and_how_the_book_recovered() {
+        let clock = ManualClock::new();
+        let mut order_book = OrderBook::new();
+        order_book.set_clock(clock.clone());
+        let mut monitor = LiquidityMonitor::new(LiquidityConfig {
+            horizon: Duration::from_secs(1),
+            window: 10,
+            depth_ticks: 10,
+        });
+        let mut send = |order_book: &mut OrderBook, side, price, qty| {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant: 1,
+            });
+            monitor.update(order_book);
+            monitor.clone()
+        };
+        send(&mut order_book, Side::Buy, 99, 10);
+        send(&mut order_book, Side::Sell, 101, 10);
+        send(&mut order_book, Side::Sell, 103, 10);
+        // Mid 100. A buy of 10 takes out the 101 offer and moves the mid to
+        // 101.
+        let measures = send(&mut order_book, Side::Buy, 101, 10).measures();
+        assert_eq!(measures.effective_spread, Some(2.0));
+        assert_eq!(measures.kyle_lambda, Some(0.1));
+        assert_eq!(measures.realized_spread, None);
+
+        // A second later the offer has come back at 102, mid 100.5.
+        clock.advance(Duration::from_secs(1));
+        let measures = send(&mut order_book, Side::Sell, 102, 10).measures();
+        assert_eq!(measures.realized_spread, Some(1.0));
+        assert_eq!(measures.resilience, Some(1.0));
+    }
+
+    #[test]
+    fn empty_book_measures_nothing() {
+        let mut order_book = OrderBook::new();
+        let mut monitor = LiquidityMonitor::new(LiquidityConfig::default());
+        monitor.update(&order_book);
+        assert_eq!(monitor.measures(), LiquidityMeasures::default());
+
+        // With only an offer up there is no midpoint to price the trade
+        // against, so it is not counted.
+        order_book.process_command(new_order(Side::Sell, 101, 10));
+        monitor.update(&order_book);
+        order_book.process_command(new_order(Side::Buy, 101, 10));
+        monitor.update(&order_book);
+        monitor.update(&order_book);
+        assert_eq!(monitor.measures(), LiquidityMeasures::default());
+    }
+
+    #[test]
+    fn one_sided_book_after_a_trade_leaves_midpoint_measures_empty() {
+        let clock = ManualClock::new();
+        let mut order_book = OrderBook::new();
+        order_book.set_clock(clock.clone());
+        let mut monitor = LiquidityMonitor::new(LiquidityConfig {
+            horizon: Duration::from_secs(1),
+            window: 10,
+            depth_ticks: 10,
+        });
+        for command in [
+            new_order(Side::Buy, 99, 10),
+            new_order(Side::Sell, 101, 10),
+            new_order(Side::Buy, 101, 10),
+        ] {
+            order_book.process_command(command);
+            monitor.update(&order_book);
+        }
+        // The buy emptied the offer side: the spread paid is known, but
+        // there is no midpoint afterwards to measure impact against.
+        let measures = monitor.measures();
+        assert_eq!(measures.effective_spread, Some(2.0));
+        assert_eq!(measures.kyle_lambda, None);
+
+        // Nor at the horizon, though the bid alone still shows how much
+        // size is left near the touch.
+        clock.advance(Duration::from_secs(1));
+        monitor.update(&order_book);
+        let measures = monitor.measures();
+        assert_eq!(measures.realized_spread, None);
+        assert_eq!(measures.resilience, Some(0.5));
+    }
+
+    #[test]
+    fn window_keeps_only_the_latest_observations() {
+        // A zero window keeps one observation, like a window of 1.
+        for window in [0, 1] {
+            let mut order_book = OrderBook::new();
+            let mut monitor = LiquidityMonitor::new(LiquidityConfig {
+                window,
+                ..LiquidityConfig::default()
+            });
+            for command in [
+                new_order(Side::Buy, 99, 10),
+                new_order(Side::Sell, 101, 10),
+                new_order(Side::Sell, 105, 10),
+                // Two buys at 101 against a mid of 100, then the offer is
+                // gone and the mid is 102.
+                new_order(Side::Buy, 101, 5),
+                new_order(Side::Buy, 101, 5),
+                // A sell at 99 against a mid of 102, which leaves it there.
+                new_order(Side::Sell, 99, 5),
+            ] {
+                order_book.process_command(command);
+                monitor.update(&order_book);
+            }
+            let measures = monitor.measures();
+            assert_eq!(measures.effective_spread, Some(6.0));
+            assert_eq!(measures.kyle_lambda, Some(0.0));
+            assert!(monitor.effective.len() <= 1);
+        }
+    }
+}
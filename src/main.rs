@@ -2,27 +2,332 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
-use crate::order_book::OrderBook;
-use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use order_book::clearing::Clearing;
+use order_book::persistence::{self, SavedOrders};
+use order_book::sandbox::SandboxConfig;
+use order_book::sim::agents::{AgentSim, AgentSimConfig};
+use order_book::sink::jsonl::JsonLinesSink;
+use order_book::sink::publish_events;
+use order_book::snapshot::{self, BookSnapshot};
+use order_book::{replay, OrderBook, OrderCommand, OrderType, Side};
+use std::path::Path;
+#[cfg(any(feature = "http", feature = "zmq"))]
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 
+use tracing_subscriber::filter::LevelFilter;
 use tracing_subscriber::fmt;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-mod limit;
-mod order_book;
+fn main() {
+    tracing_subscriber::registry()
+        .with(fmt::layer())
+        .with(LevelFilter::INFO)
+        .init();
+    tracing::info!("Starting up matcher-rs");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        #[cfg(feature = "http")]
+        Some("serve") => {
+            handle_shutdown_signals();
+            serve(&args, &SHUTDOWN);
+        }
+        #[cfg(feature = "zmq")]
+        Some("zmq") => {
+            handle_shutdown_signals();
+            zmq(&args, &SHUTDOWN);
+        }
+        Some("repl") => {
+            let mut order_book = OrderBook::new();
+            if !sandbox(&args, &mut order_book) || !restore_gtc(&args, &mut order_book) {
+                return;
+            }
+            if let Err(e) = order_book::repl::run(
+                &mut order_book,
+                std::io::stdin().lock(),
+                std::io::stdout().lock(),
+            ) {
+                tracing::error!("REPL failed: {}", e);
+            }
+            save_gtc(&args, &order_book);
+        }
+        Some("replay") => {
+            let Some(path) = args.get(1).map(Path::new) else {
+                return tracing::error!(
+                    "Usage: replay <file> [--paced | --speed <factor>] [--symbol <symbol>] [--snapshot <out.json>] [--settlement <out.csv>]"
+                );
+            };
+            let pace = match flag(&args, "--speed").map(|speed| speed.parse()) {
+                Some(Ok(factor)) => replay::Pace::Accelerated(factor),
+                Some(Err(e)) => return tracing::error!("Bad --speed: {}", e),
+                None if args.iter().any(|arg| arg == "--paced") => replay::Pace::Recorded,
+                None => replay::Pace::FullSpeed,
+            };
+            let symbol = flag(&args, "--symbol");
+            let mut order_book = OrderBook::with_symbol(symbol.map_or("", String::as_str));
+            let result = replay::open(
+                path,
+                replay::Format::from_path(path),
+                symbol.map(String::as_str),
+            )
+            .and_then(|records| replay::run(&mut order_book, records, pace));
+            match result {
+                Ok(report) => {
+                    tracing::info!(
+                        "Replayed {} records ({} for unknown orders) covering {:?} in {:?}: {:.0} records/s, {} trades",
+                        report.records,
+                        report.unknown_orders,
+                        report.recorded,
+                        report.elapsed,
+                        report.records_per_sec(),
+                        report.trades
+                    );
+                    println!("{}", order_book.render(10));
+                    if let Some(out) = flag(&args, "--snapshot") {
+                        let snapshot = BookSnapshot::of(&order_book);
+                        let written = serde_json::to_vec_pretty(&snapshot)
+                            .map_err(std::io::Error::other)
+                            .and_then(|json| std::fs::write(out, json));
+                        if let Err(e) = written {
+                            tracing::error!("Cannot write snapshot {}: {}", out, e);
+                        }
+                    }
+                    if let Some(out) = flag(&args, "--settlement") {
+                        let mut clearing = Clearing::new();
+                        let written =
+                            publish_events(&mut clearing, order_book.symbol(), order_book.events())
+                                .and_then(|()| std::fs::File::create(out))
+                                .and_then(|file| {
+                                    clearing.report().write_csv(std::io::BufWriter::new(file))
+                                });
+                        if let Err(e) = written {
+                            tracing::error!("Cannot write settlement report {}: {}", out, e);
+                        }
+                    }
+                }
+                Err(e) => tracing::error!("Replay of {} failed: {}", path.display(), e),
+            }
+        }
+        Some("diff") => {
+            let (Some(left), Some(right)) = (args.get(1), args.get(2)) else {
+                return tracing::error!("Usage: diff <snapshot or journal> <snapshot or journal>");
+            };
+            let (left, right) = match (load_snapshot(left), load_snapshot(right)) {
+                (Ok(left), Ok(right)) => (left, right),
+                (Err(e), _) | (_, Err(e)) => return tracing::error!("Cannot load book: {}", e),
+            };
+            let differences = snapshot::diff(&left, &right);
+            for difference in &differences {
+                println!("{}", difference);
+            }
+            if !differences.is_empty() {
+                std::process::exit(1);
+            }
+            tracing::info!("Books match");
+        }
+        Some("simulate") => {
+            let mut config = match flag(&args, "--config") {
+                Some(path) => match std::fs::read(path).map(|raw| serde_json::from_slice(&raw)) {
+                    Ok(Ok(config)) => config,
+                    Ok(Err(e)) => return tracing::error!("Invalid agent config {}: {}", path, e),
+                    Err(e) => return tracing::error!("Cannot read {}: {}", path, e),
+                },
+                None => AgentSimConfig::default(),
+            };
+            if let Some(seed) = flag(&args, "--seed").and_then(|seed| seed.parse().ok()) {
+                config.seed = seed;
+            }
+            let ticks = flag(&args, "--ticks")
+                .and_then(|ticks| ticks.parse().ok())
+                .unwrap_or(10_000);
+            let mut sim = AgentSim::new(config);
+            sim.run(ticks);
+            let order_book = sim.order_book();
+            tracing::info!(
+                "Simulated {} ticks: {} events, {} trades",
+                ticks,
+                order_book.events().len(),
+                order_book.trades().len()
+            );
+            println!("{}", order_book.render(10));
+            if let Some(out) = flag(&args, "--events") {
+                let written = JsonLinesSink::append(out).and_then(|mut sink| {
+                    publish_events(&mut sink, order_book.symbol(), order_book.events())
+                });
+                if let Err(e) = written {
+                    tracing::error!("Cannot write events {}: {}", out, e);
+                }
+            }
+        }
+        #[cfg(feature = "tui")]
+        Some("tui") => {
+            let config = order_book::tui::TuiConfig {
+                run_for: args
+                    .get(1)
+                    .and_then(|secs| secs.parse().ok())
+                    .map(std::time::Duration::from_secs),
+                ..Default::default()
+            };
+            let mut order_book = OrderBook::with_symbol("DEMO");
+            if let Err(e) = order_book::tui::run(&mut order_book, config) {
+                tracing::error!("Dashboard failed: {}", e);
+            }
+        }
+        _ => bench(),
+    }
+}
+
+/// Set on SIGINT or SIGTERM. The servers return at their next check, so
+/// the book's good-til-cancel orders can be saved on the way out.
+#[cfg(any(feature = "http", feature = "zmq"))]
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+#[cfg(all(unix, any(feature = "http", feature = "zmq")))]
+fn handle_shutdown_signals() {
+    extern "C" fn shut_down(_signal: i32) {
+        SHUTDOWN.store(true, Ordering::SeqCst);
+    }
+    extern "C" {
+        fn signal(signal: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+    // SAFETY: the handler only stores to an atomic, which is safe to do
+    // from a signal handler.
+    unsafe {
+        signal(SIGINT, shut_down);
+        signal(SIGTERM, shut_down);
+    }
+}
 
-fn get_id() -> usize {
-    static COUNTER: AtomicUsize = AtomicUsize::new(1);
-    COUNTER.fetch_add(1, Ordering::Relaxed)
+#[cfg(all(not(unix), any(feature = "http", feature = "zmq")))]
+fn handle_shutdown_signals() {}
+
+/// Serves the HTTP API until `shutdown` is set, then saves the book.
+#[cfg(feature = "http")]
+fn serve(args: &[String], shutdown: &AtomicBool) {
+    let addr = args
+        .get(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .map_or("127.0.0.1:8080", String::as_str);
+    let mut order_book = OrderBook::new();
+    if !sandbox(args, &mut order_book) || !restore_gtc(args, &mut order_book) {
+        return;
+    }
+    if let Err(e) = order_book::http::serve_until(addr, &mut order_book, shutdown) {
+        tracing::error!("HTTP server failed: {}", e);
+    }
+    save_gtc(args, &order_book);
 }
 
-fn main() {
-    tracing_subscriber::registry().with(fmt::layer()).init();
-    tracing::info!("Starting up matcher-rs");
+/// Runs the ZeroMQ transport until `shutdown` is set, then saves the book.
+#[cfg(feature = "zmq")]
+fn zmq(args: &[String], shutdown: &AtomicBool) {
+    let config = match args.get(1).filter(|arg| !arg.starts_with("--")) {
+        Some(path) => match std::fs::read(path).map(|raw| serde_json::from_slice(&raw)) {
+            Ok(Ok(config)) => config,
+            Ok(Err(e)) => return tracing::error!("Invalid ZeroMQ config {}: {}", path, e),
+            Err(e) => return tracing::error!("Cannot read {}: {}", path, e),
+        },
+        None => order_book::zmq::ZmqConfig::default(),
+    };
+    let mut order_book = OrderBook::new();
+    if !sandbox(args, &mut order_book) || !restore_gtc(args, &mut order_book) {
+        return;
+    }
+    let result = order_book::zmq::ZmqTransport::bind(&config)
+        .and_then(|mut transport| transport.run_until(&mut order_book, shutdown));
+    if let Err(e) = result {
+        tracing::error!("ZeroMQ transport failed: {}", e);
+    }
+    save_gtc(args, &order_book);
+}
+
+/// Puts the book in sandbox mode if `--sandbox` was given, with the config
+/// file that follows it or the default one. Returns false if the config
+/// can't be read.
+fn sandbox(args: &[String], order_book: &mut OrderBook) -> bool {
+    if !args.iter().any(|arg| arg == "--sandbox") {
+        return true;
+    }
+    let config = match flag(args, "--sandbox").filter(|arg| !arg.starts_with("--")) {
+        Some(path) => match std::fs::read(path).map(|raw| serde_json::from_slice(&raw)) {
+            Ok(Ok(config)) => config,
+            Ok(Err(e)) => {
+                tracing::error!("Invalid sandbox config {}: {}", path, e);
+                return false;
+            }
+            Err(e) => {
+                tracing::error!("Cannot read {}: {}", path, e);
+                return false;
+            }
+        },
+        None => SandboxConfig::default(),
+    };
+    order_book.set_sandbox(Some(config));
+    true
+}
+
+/// Restores the good-til-cancel orders saved to `--gtc <file>` by the last
+/// run, if there was one.
+fn restore_gtc(args: &[String], order_book: &mut OrderBook) -> bool {
+    let Some(path) = flag(args, "--gtc") else {
+        return true;
+    };
+    let restored = SavedOrders::load(Path::new(path))
+        .and_then(|saved| saved.map_or(Ok(0), |saved| persistence::restore(order_book, &saved)));
+    match restored {
+        Ok(count) => {
+            tracing::info!("Restored {} good-til-cancel orders from {}", count, path);
+            true
+        }
+        Err(e) => {
+            tracing::error!("Cannot restore orders from {}: {}", path, e);
+            false
+        }
+    }
+}
+
+/// Saves the book's good-til-cancel orders to `--gtc <file>` for the next
+/// run.
+fn save_gtc(args: &[String], order_book: &OrderBook) {
+    if let Some(path) = flag(args, "--gtc") {
+        let saved = SavedOrders::of(order_book);
+        match saved.save(Path::new(path)) {
+            Ok(()) => tracing::info!(
+                "Saved {} good-til-cancel orders to {}",
+                saved.orders.len(),
+                path
+            ),
+            Err(e) => tracing::error!("Cannot save orders to {}: {}", path, e),
+        }
+    }
+}
+
+fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+}
+
+/// Reads a snapshot written by `replay --snapshot`, or rebuilds one by
+/// replaying anything else.
+fn load_snapshot(path: &str) -> std::io::Result<BookSnapshot> {
+    let path = Path::new(path);
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let raw = std::fs::read(path)?;
+        return serde_json::from_slice(&raw).map_err(std::io::Error::other);
+    }
+    let mut order_book = OrderBook::new();
+    let records = replay::open(path, replay::Format::from_path(path), None)?;
+    replay::run(&mut order_book, records, replay::Pace::FullSpeed)?;
+    Ok(BookSnapshot::of(&order_book))
+}
+
+fn bench() {
     let mut order_book = OrderBook::new();
+    order_book.set_latency_tracking(true);
     let i = 100_000;
     let now = Instant::now();
     for _ in 0..i {
@@ -31,119 +336,80 @@ fn main() {
             side: Side::Buy,
             price: 122,
             qty: 1,
+            participant: 1,
         });
         order_book.process_command(OrderCommand::New {
             order_type: OrderType::GoodTilCancel,
             side: Side::Sell,
             price: 122,
             qty: 1,
+            participant: 2,
         });
     }
     tracing::info!("Time to place {:?} orders: {:?}", i * 2, now.elapsed());
     tracing::info!("Avg time per order: {:?}", now.elapsed() / i * 2);
+    let summaries = order_book
+        .latency()
+        .map(|l| l.summaries())
+        .unwrap_or_default();
+    for summary in summaries.iter().filter(|summary| summary.count > 0) {
+        tracing::info!(
+            "{:?}: p50 {:?}, p99 {:?}, p99.9 {:?}, max {:?}",
+            summary.stage,
+            summary.p50,
+            summary.p99,
+            summary.p999,
+            summary.max
+        );
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
-enum OrderCommand {
-    New {
-        order_type: OrderType,
-        side: Side,
-        price: i32,
-        qty: u32,
-    },
-    Modify {
-        id: usize,
-        price: i32,
-        side: Side,
-        qty: u32,
-        order_type: OrderType,
-    },
-    Cancel {
-        id: usize,
-        side: Side,
-        price: i32,
-    },
-}
-
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
-enum OrderEvent {
-    Placed {
-        id: usize,
-        side: Side,
-        order_type: OrderType,
-        price: i32,
-        timestamp: Instant,
-    },
-    Modified,
-    Canceled {
-        id: usize,
-    },
-    PartiallyFilled {
-        id: usize,
-        price: i32,
-        qty: u32,
-        timestamp: Instant,
-    },
-    Filled {
-        id: usize,
-        price: i32,
-        timestamp: Instant,
-    },
-}
+#[cfg(all(test, feature = "http"))]
+mod tests {
+    use order_book::persistence::SavedOrders;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-struct Order {
-    id: usize,
-    order_type: OrderType,
-    side: Side,
-    price: i32,
-    initial_qty: u32,
-    remaining_qty: u32,
-    created_at: Instant,
-    updated_at: Instant,
-}
+    #[test]
+    fn shutdown_saves_gtc_orders() {
+        let gtc = std::env::temp_dir().join(format!("gtc-shutdown-{}.json", std::process::id()));
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        let args: Vec<String> = ["serve", &addr.to_string(), "--gtc", gtc.to_str().unwrap()]
+            .map(String::from)
+            .to_vec();
+        let shutdown = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let server = scope.spawn(|| super::serve(&args, &shutdown));
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let mut stream = loop {
+                match TcpStream::connect(addr) {
+                    Ok(stream) => break stream,
+                    Err(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                    Err(e) => panic!("server never came up: {}", e),
+                }
+            };
+            let body = r#"{"order_type":"GoodTilCancel","side":"Buy","price":99,"qty":5}"#;
+            write!(
+                stream,
+                "POST /orders HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 201"), "{}", response);
 
-impl Order {
-    fn new(order_type: OrderType, side: Side, price: i32, qty: u32) -> Order {
-        let now = Instant::now();
-        Order {
-            id: get_id(),
-            order_type,
-            side,
-            price,
-            initial_qty: qty,
-            remaining_qty: qty,
-            created_at: now,
-            updated_at: now,
-        }
-    }
-
-    pub fn fill(&mut self, qty: u32) -> Result<Order, ()> {
-        if qty > self.remaining_qty {
-            return Err(());
-        }
-        let new_rem_qty = self.remaining_qty - qty;
-        Ok(Order {
-            id: self.id,
-            order_type: self.order_type,
-            side: self.side,
-            price: self.price,
-            initial_qty: self.initial_qty,
-            remaining_qty: new_rem_qty,
-            created_at: self.created_at,
-            updated_at: Instant::now(),
-        })
+            shutdown.store(true, Ordering::SeqCst);
+            server.join().unwrap();
+        });
+        let saved = SavedOrders::load(&gtc).unwrap().unwrap();
+        std::fs::remove_file(&gtc).unwrap();
+        assert_eq!(saved.orders.len(), 1);
     }
 }
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Ord, PartialOrd)]
-enum OrderType {
-    FillAndKill,
-    GoodTilCancel,
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Ord, PartialOrd)]
-enum Side {
-    Buy,
-    Sell,
-}
@@ -0,0 +1,731 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Binary market data messages.
+//!
+//! Messages are little-endian and start with a one byte type tag. Symbols are
+//! fixed 8 byte ASCII fields padded with spaces, as in ITCH.
+
+use crate::candles::{Candle, Interval};
+use crate::codec::{invalid, Reader};
+use crate::order_book::{Bbo, Depth, LevelInfo};
+use crate::stats::SessionStats;
+use crate::{OrderBook, Side, Trade};
+use std::io;
+
+pub mod checksum;
+pub mod conflate;
+pub mod imbalance;
+pub mod l2;
+pub mod multicast;
+pub mod recorder;
+pub mod recovery;
+
+pub const SYMBOL_LEN: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarketDataMessage {
+    Trade {
+        symbol: String,
+        trade_id: u64,
+        price: i32,
+        qty: u32,
+        aggressor_side: Side,
+    },
+    Bbo {
+        symbol: String,
+        bbo: Bbo,
+    },
+    /// Incremental change to one aggregated price level. `seq` numbers the
+    /// level updates of a single symbol so consumers can spot gaps.
+    Level {
+        symbol: String,
+        seq: u64,
+        action: LevelAction,
+        side: Side,
+        level: LevelInfo,
+    },
+    /// Full aggregated book as of level update `seq`. `checksum` is the
+    /// [`checksum::book_checksum`] of `depth`.
+    Snapshot {
+        symbol: String,
+        seq: u64,
+        depth: Depth,
+        checksum: u32,
+    },
+    /// Checksum of the book once level update `seq` has been applied, sent
+    /// after each batch of level messages.
+    Checksum {
+        symbol: String,
+        seq: u64,
+        checksum: u32,
+    },
+    /// A closed OHLCV bar.
+    Candle {
+        symbol: String,
+        candle: Candle,
+    },
+    Stats {
+        symbol: String,
+        stats: SessionStats,
+    },
+    /// Where a book in its auction call would uncross now; see
+    /// [`imbalance`].
+    Imbalance {
+        symbol: String,
+        /// The indicative uncross price.
+        price: i32,
+        /// What would trade at `price`.
+        paired_qty: u64,
+        /// What would be left over, on `imbalance_side`.
+        imbalance_qty: u64,
+        /// `None` when the auction is balanced.
+        imbalance_side: Option<Side>,
+    },
+    /// The book's depth split into round-lot and odd-lot orders; see
+    /// [`odd_lots`](crate::odd_lots).
+    LotDepth {
+        symbol: String,
+        round_lot: u32,
+        round: Depth,
+        odd: Depth,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelAction {
+    Added,
+    Changed,
+    Removed,
+}
+
+impl MarketDataMessage {
+    pub fn trade(symbol: &str, trade: &Trade) -> Self {
+        MarketDataMessage::Trade {
+            symbol: symbol.to_string(),
+            trade_id: trade.id as u64,
+            price: trade.price,
+            qty: trade.qty,
+            aggressor_side: trade.aggressor_side,
+        }
+    }
+
+    /// Up to `levels` levels of each side's round-lot and odd-lot depth, or
+    /// `None` if the book has no round lot set.
+    pub fn lot_depth(order_book: &OrderBook, levels: usize) -> Option<Self> {
+        let config = order_book.odd_lots()?;
+        Some(MarketDataMessage::LotDepth {
+            symbol: order_book.symbol().to_string(),
+            round_lot: config.round_lot,
+            round: order_book.round_lot_depth(levels),
+            odd: order_book.odd_lot_depth(levels),
+        })
+    }
+
+    pub fn symbol(&self) -> &str {
+        match self {
+            MarketDataMessage::Trade { symbol, .. }
+            | MarketDataMessage::Bbo { symbol, .. }
+            | MarketDataMessage::Level { symbol, .. }
+            | MarketDataMessage::Snapshot { symbol, .. }
+            | MarketDataMessage::Checksum { symbol, .. }
+            | MarketDataMessage::Candle { symbol, .. }
+            | MarketDataMessage::Stats { symbol, .. }
+            | MarketDataMessage::Imbalance { symbol, .. }
+            | MarketDataMessage::LotDepth { symbol, .. } => symbol,
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            MarketDataMessage::Trade {
+                symbol,
+                trade_id,
+                price,
+                qty,
+                aggressor_side,
+            } => {
+                buf.push(b'T');
+                put_symbol(buf, symbol);
+                buf.extend_from_slice(&trade_id.to_le_bytes());
+                buf.extend_from_slice(&price.to_le_bytes());
+                buf.extend_from_slice(&qty.to_le_bytes());
+                buf.push(side_code(*aggressor_side));
+            }
+            MarketDataMessage::Bbo { symbol, bbo } => {
+                buf.push(b'B');
+                put_symbol(buf, symbol);
+                for level in [bbo.bid, bbo.ask] {
+                    let level = level.unwrap_or(LevelInfo {
+                        price: 0,
+                        qty: 0,
+                        order_count: 0,
+                    });
+                    put_level(buf, &level);
+                }
+            }
+            MarketDataMessage::Level {
+                symbol,
+                seq,
+                action,
+                side,
+                level,
+            } => {
+                buf.push(b'L');
+                put_symbol(buf, symbol);
+                buf.extend_from_slice(&seq.to_le_bytes());
+                buf.push(action_code(*action));
+                buf.push(side_code(*side));
+                put_level(buf, level);
+            }
+            MarketDataMessage::Snapshot {
+                symbol,
+                seq,
+                depth,
+                checksum,
+            } => {
+                buf.push(b'S');
+                put_symbol(buf, symbol);
+                buf.extend_from_slice(&seq.to_le_bytes());
+                buf.extend_from_slice(&checksum.to_le_bytes());
+                put_depth(buf, depth);
+            }
+            MarketDataMessage::Checksum {
+                symbol,
+                seq,
+                checksum,
+            } => {
+                buf.push(b'X');
+                put_symbol(buf, symbol);
+                buf.extend_from_slice(&seq.to_le_bytes());
+                buf.extend_from_slice(&checksum.to_le_bytes());
+            }
+            MarketDataMessage::Candle { symbol, candle } => {
+                buf.push(b'K');
+                put_symbol(buf, symbol);
+                buf.extend_from_slice(&candle.interval.seconds().to_le_bytes());
+                buf.extend_from_slice(&candle.start.to_le_bytes());
+                for price in [candle.open, candle.high, candle.low, candle.close] {
+                    buf.extend_from_slice(&price.to_le_bytes());
+                }
+                buf.extend_from_slice(&candle.volume.to_le_bytes());
+                buf.extend_from_slice(&candle.trade_count.to_le_bytes());
+            }
+            MarketDataMessage::Stats { symbol, stats } => {
+                buf.push(b'Z');
+                put_symbol(buf, symbol);
+                // Prices are only meaningful once something has traded.
+                buf.push(u8::from(stats.trade_count > 0));
+                for price in [stats.open, stats.high, stats.low, stats.last] {
+                    buf.extend_from_slice(&price.unwrap_or(0).to_le_bytes());
+                }
+                buf.extend_from_slice(&stats.volume.to_le_bytes());
+                buf.extend_from_slice(&stats.trade_count.to_le_bytes());
+                buf.extend_from_slice(&stats.turnover.to_le_bytes());
+                buf.push(u8::from(stats.settlement.is_some()));
+                buf.extend_from_slice(&stats.settlement.unwrap_or(0).to_le_bytes());
+            }
+            MarketDataMessage::Imbalance {
+                symbol,
+                price,
+                paired_qty,
+                imbalance_qty,
+                imbalance_side,
+            } => {
+                buf.push(b'I');
+                put_symbol(buf, symbol);
+                buf.extend_from_slice(&price.to_le_bytes());
+                buf.extend_from_slice(&paired_qty.to_le_bytes());
+                buf.extend_from_slice(&imbalance_qty.to_le_bytes());
+                buf.push(imbalance_side.map_or(b' ', side_code));
+            }
+            MarketDataMessage::LotDepth {
+                symbol,
+                round_lot,
+                round,
+                odd,
+            } => {
+                buf.push(b'O');
+                put_symbol(buf, symbol);
+                buf.extend_from_slice(&round_lot.to_le_bytes());
+                put_depth(buf, round);
+                put_depth(buf, odd);
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        match reader.u8()? {
+            b'T' => Ok(MarketDataMessage::Trade {
+                symbol: read_symbol(&mut reader)?,
+                trade_id: reader.u64()?,
+                price: reader.i32()?,
+                qty: reader.u32()?,
+                aggressor_side: read_side(&mut reader)?,
+            }),
+            b'B' => {
+                let symbol = read_symbol(&mut reader)?;
+                let mut levels = [None, None];
+                for level in &mut levels {
+                    let info = read_level(&mut reader)?;
+                    if info.qty > 0 {
+                        *level = Some(info);
+                    }
+                }
+                let [bid, ask] = levels;
+                Ok(MarketDataMessage::Bbo {
+                    symbol,
+                    bbo: Bbo { bid, ask },
+                })
+            }
+            b'L' => Ok(MarketDataMessage::Level {
+                symbol: read_symbol(&mut reader)?,
+                seq: reader.u64()?,
+                action: read_action(&mut reader)?,
+                side: read_side(&mut reader)?,
+                level: read_level(&mut reader)?,
+            }),
+            b'S' => {
+                let symbol = read_symbol(&mut reader)?;
+                let seq = reader.u64()?;
+                let checksum = reader.u32()?;
+                Ok(MarketDataMessage::Snapshot {
+                    symbol,
+                    seq,
+                    depth: read_depth(&mut reader)?,
+                    checksum,
+                })
+            }
+            b'X' => Ok(MarketDataMessage::Checksum {
+                symbol: read_symbol(&mut reader)?,
+                seq: reader.u64()?,
+                checksum: reader.u32()?,
+            }),
+            b'K' => {
+                let symbol = read_symbol(&mut reader)?;
+                let seconds = reader.u32()?;
+                let interval = Interval::from_seconds(seconds)
+                    .ok_or_else(|| invalid(format!("unknown candle interval {}s", seconds)))?;
+                Ok(MarketDataMessage::Candle {
+                    symbol,
+                    candle: Candle {
+                        interval,
+                        start: reader.u64()?,
+                        open: reader.i32()?,
+                        high: reader.i32()?,
+                        low: reader.i32()?,
+                        close: reader.i32()?,
+                        volume: reader.u64()?,
+                        trade_count: reader.u32()?,
+                    },
+                })
+            }
+            b'Z' => {
+                let symbol = read_symbol(&mut reader)?;
+                let traded = reader.u8()? != 0;
+                let mut prices = [None; 4];
+                for price in &mut prices {
+                    let value = reader.i32()?;
+                    *price = traded.then_some(value);
+                }
+                let [open, high, low, last] = prices;
+                let volume = reader.u64()?;
+                let trade_count = reader.u64()?;
+                let turnover = reader.u64()? as i64;
+                let settled = reader.u8()? != 0;
+                let settlement = reader.i32()?;
+                Ok(MarketDataMessage::Stats {
+                    symbol,
+                    stats: SessionStats {
+                        open,
+                        high,
+                        low,
+                        last,
+                        volume,
+                        trade_count,
+                        turnover,
+                        settlement: settled.then_some(settlement),
+                    },
+                })
+            }
+            b'I' => Ok(MarketDataMessage::Imbalance {
+                symbol: read_symbol(&mut reader)?,
+                price: reader.i32()?,
+                paired_qty: reader.u64()?,
+                imbalance_qty: reader.u64()?,
+                imbalance_side: match reader.u8()? {
+                    b' ' => None,
+                    b'B' => Some(Side::Buy),
+                    b'S' => Some(Side::Sell),
+                    code => return Err(invalid(format!("unknown side {:#04x}", code))),
+                },
+            }),
+            b'O' => Ok(MarketDataMessage::LotDepth {
+                symbol: read_symbol(&mut reader)?,
+                round_lot: reader.u32()?,
+                round: read_depth(&mut reader)?,
+                odd: read_depth(&mut reader)?,
+            }),
+            tag => Err(invalid(format!("unknown message type {:#04x}", tag))),
+        }
+    }
+}
+
+fn put_symbol(buf: &mut Vec<u8>, symbol: &str) {
+    let mut field = [b' '; SYMBOL_LEN];
+    let len = symbol.len().min(SYMBOL_LEN);
+    field[..len].copy_from_slice(&symbol.as_bytes()[..len]);
+    buf.extend_from_slice(&field);
+}
+
+fn put_level(buf: &mut Vec<u8>, level: &LevelInfo) {
+    buf.extend_from_slice(&level.price.to_le_bytes());
+    buf.extend_from_slice(&level.qty.to_le_bytes());
+    buf.extend_from_slice(&(level.order_count as u32).to_le_bytes());
+}
+
+fn read_level(reader: &mut Reader) -> io::Result<LevelInfo> {
+    Ok(LevelInfo {
+        price: reader.i32()?,
+        qty: reader.u64()?,
+        order_count: reader.u32()? as usize,
+    })
+}
+
+/// Level counts first, then the bids and the asks.
+fn put_depth(buf: &mut Vec<u8>, depth: &Depth) {
+    buf.extend_from_slice(&(depth.bids.len() as u16).to_le_bytes());
+    buf.extend_from_slice(&(depth.asks.len() as u16).to_le_bytes());
+    for level in depth.bids.iter().chain(&depth.asks) {
+        put_level(buf, level);
+    }
+}
+
+fn read_depth(reader: &mut Reader) -> io::Result<Depth> {
+    let bid_count = reader.u16()?;
+    let ask_count = reader.u16()?;
+    let bids = (0..bid_count)
+        .map(|_| read_level(reader))
+        .collect::<io::Result<_>>()?;
+    let asks = (0..ask_count)
+        .map(|_| read_level(reader))
+        .collect::<io::Result<_>>()?;
+    Ok(Depth { bids, asks })
+}
+
+fn action_code(action: LevelAction) -> u8 {
+    match action {
+        LevelAction::Added => b'A',
+        LevelAction::Changed => b'C',
+        LevelAction::Removed => b'D',
+    }
+}
+
+fn read_action(reader: &mut Reader) -> io::Result<LevelAction> {
+    match reader.u8()? {
+        b'A' => Ok(LevelAction::Added),
+        b'C' => Ok(LevelAction::Changed),
+        b'D' => Ok(LevelAction::Removed),
+        code => Err(invalid(format!("unknown level action {:#04x}", code))),
+    }
+}
+
+fn side_code(side: Side) -> u8 {
+    match side {
+        Side::Buy => b'B',
+        Side::Sell => b'S',
+    }
+}
+
+fn read_symbol(reader: &mut Reader) -> io::Result<String> {
+    let raw = reader.take(SYMBOL_LEN)?;
+    Ok(String::from_utf8_lossy(raw).trim_end().to_string())
+}
+
+fn read_side(reader: &mut Reader) -> io::Result<Side> {
+    match reader.u8()? {
+        b'B' => Ok(Side::Buy),
+        b'S' => Ok(Side::Sell),
+        code => Err(invalid(format!("unknown side {:#04x}", code))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LevelAction, MarketDataMessage, SYMBOL_LEN};
+    use crate::candles::{Candle, Interval};
+    use crate::odd_lots::{OddLotConfig, OddLotPolicy};
+    use crate::order_book::{Bbo, Depth, LevelInfo};
+    use crate::stats::SessionStats;
+    use crate::{OrderBook, Side};
+    use std::io;
+
+    #[test]
+    fn round_trip_messages() {
+        let messages = [
+            MarketDataMessage::Trade {
+                symbol: "ABC".to_string(),
+                trade_id: 9,
+                price: -3,
+                qty: 12,
+                aggressor_side: Side::Sell,
+            },
+            MarketDataMessage::Bbo {
+                symbol: "LONGSYMB".to_string(),
+                bbo: Bbo {
+                    bid: Some(LevelInfo {
+                        price: 100,
+                        qty: 5,
+                        order_count: 2,
+                    }),
+                    ask: None,
+                },
+            },
+            MarketDataMessage::Level {
+                symbol: "ABC".to_string(),
+                seq: 4,
+                action: LevelAction::Removed,
+                side: Side::Buy,
+                level: LevelInfo {
+                    price: 99,
+                    qty: 0,
+                    order_count: 0,
+                },
+            },
+            MarketDataMessage::Snapshot {
+                symbol: "ABC".to_string(),
+                seq: 3,
+                depth: Depth {
+                    bids: Vec::new(),
+                    asks: vec![
+                        LevelInfo {
+                            price: 101,
+                            qty: 2,
+                            order_count: 1,
+                        },
+                        LevelInfo {
+                            price: 102,
+                            qty: 7,
+                            order_count: 3,
+                        },
+                    ],
+                },
+                checksum: 0xDEAD_BEEF,
+            },
+            MarketDataMessage::Checksum {
+                symbol: "ABC".to_string(),
+                seq: 5,
+                checksum: 42,
+            },
+            MarketDataMessage::Candle {
+                symbol: "ABC".to_string(),
+                candle: Candle {
+                    interval: Interval::OneMinute,
+                    start: 1_725_192_000_000_000_000,
+                    open: 100,
+                    high: 104,
+                    low: 98,
+                    close: 101,
+                    volume: 30,
+                    trade_count: 4,
+                },
+            },
+            MarketDataMessage::Stats {
+                symbol: "ABC".to_string(),
+                stats: SessionStats::default(),
+            },
+            MarketDataMessage::Stats {
+                symbol: "ABC".to_string(),
+                stats: SessionStats {
+                    open: Some(100),
+                    high: Some(104),
+                    low: Some(-2),
+                    last: Some(101),
+                    volume: 30,
+                    trade_count: 4,
+                    turnover: 3_000,
+                    settlement: Some(102),
+                },
+            },
+            MarketDataMessage::Imbalance {
+                symbol: "ABC".to_string(),
+                price: 101,
+                paired_qty: 500,
+                imbalance_qty: 0,
+                imbalance_side: None,
+            },
+            MarketDataMessage::Imbalance {
+                symbol: "ABC".to_string(),
+                price: 99,
+                paired_qty: 300,
+                imbalance_qty: 200,
+                imbalance_side: Some(Side::Sell),
+            },
+            MarketDataMessage::LotDepth {
+                symbol: "ABC".to_string(),
+                round_lot: 100,
+                round: Depth {
+                    bids: vec![LevelInfo {
+                        price: 99,
+                        qty: 300,
+                        order_count: 2,
+                    }],
+                    asks: Vec::new(),
+                },
+                odd: Depth {
+                    bids: Vec::new(),
+                    asks: vec![LevelInfo {
+                        price: 101,
+                        qty: 40,
+                        order_count: 1,
+                    }],
+                },
+            },
+        ];
+        for message in messages {
+            let mut buf = Vec::new();
+            message.encode(&mut buf);
+            assert_eq!(MarketDataMessage::decode(&buf).unwrap(), message);
+            assert!(MarketDataMessage::decode(&buf[..buf.len() - 1]).is_err());
+        }
+    }
+
+    /// Decodes `bytes`, expecting an `InvalidData` error that mentions
+    /// `error`.
+    fn assert_rejected(bytes: &[u8], error: &str) {
+        let err = MarketDataMessage::decode(bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains(error), "{}", err);
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let mut trade = Vec::new();
+        MarketDataMessage::Trade {
+            symbol: "ABC".to_string(),
+            trade_id: 1,
+            price: 100,
+            qty: 1,
+            aggressor_side: Side::Buy,
+        }
+        .encode(&mut trade);
+        *trade.last_mut().unwrap() = b'X';
+        assert_rejected(&[], "truncated");
+        assert_rejected(b"?", "unknown message type");
+        assert_rejected(&trade, "unknown side");
+    }
+
+    #[test]
+    fn cuts_symbols_to_the_fixed_field() {
+        let mut buf = Vec::new();
+        MarketDataMessage::Bbo {
+            symbol: "OVERLONGSYMBOL".to_string(),
+            bbo: Bbo::default(),
+        }
+        .encode(&mut buf);
+        assert_eq!(
+            MarketDataMessage::decode(&buf).unwrap(),
+            MarketDataMessage::Bbo {
+                symbol: "OVERLONG".to_string(),
+                bbo: Bbo::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_level_messages() {
+        let mut level = Vec::new();
+        MarketDataMessage::Level {
+            symbol: "ABC".to_string(),
+            seq: 1,
+            action: LevelAction::Added,
+            side: Side::Buy,
+            level: LevelInfo {
+                price: 100,
+                qty: 1,
+                order_count: 1,
+            },
+        }
+        .encode(&mut level);
+        level[1 + SYMBOL_LEN + 8] = b'X';
+        assert_rejected(&level, "unknown level action");
+
+        // A snapshot that claims a level it doesn't carry.
+        let mut snapshot = Vec::new();
+        MarketDataMessage::Snapshot {
+            symbol: "ABC".to_string(),
+            seq: 1,
+            depth: Depth {
+                bids: Vec::new(),
+                asks: Vec::new(),
+            },
+            checksum: 0,
+        }
+        .encode(&mut snapshot);
+        let counts = snapshot.len() - 4;
+        snapshot[counts..counts + 2].copy_from_slice(&1u16.to_le_bytes());
+        assert_rejected(&snapshot, "truncated");
+    }
+
+    #[test]
+    fn rejects_unknown_candle_intervals() {
+        let mut candle = Vec::new();
+        MarketDataMessage::Candle {
+            symbol: "ABC".to_string(),
+            candle: Candle {
+                interval: Interval::OneMinute,
+                start: 0,
+                open: 1,
+                high: 1,
+                low: 1,
+                close: 1,
+                volume: 1,
+                trade_count: 1,
+            },
+        }
+        .encode(&mut candle);
+        candle[1 + SYMBOL_LEN..1 + SYMBOL_LEN + 4].copy_from_slice(&7u32.to_le_bytes());
+        assert_rejected(&candle, "unknown candle interval");
+    }
+
+    #[test]
+    fn rejects_unknown_imbalance_sides() {
+        let mut imbalance = Vec::new();
+        MarketDataMessage::Imbalance {
+            symbol: "ABC".to_string(),
+            price: 100,
+            paired_qty: 1,
+            imbalance_qty: 0,
+            imbalance_side: None,
+        }
+        .encode(&mut imbalance);
+        *imbalance.last_mut().unwrap() = b'X';
+        assert_rejected(&imbalance, "unknown side");
+    }
+
+    #[test]
+    fn lot_depth_of_an_empty_book() {
+        let mut order_book = OrderBook::new();
+        assert_eq!(MarketDataMessage::lot_depth(&order_book, 5), None);
+
+        order_book.set_odd_lots(Some(OddLotConfig {
+            round_lot: 100,
+            policy: OddLotPolicy::Unprotected,
+        }));
+        let message = MarketDataMessage::lot_depth(&order_book, 5).unwrap();
+        let empty = Depth {
+            bids: Vec::new(),
+            asks: Vec::new(),
+        };
+        let mut buf = Vec::new();
+        message.encode(&mut buf);
+        match MarketDataMessage::decode(&buf).unwrap() {
+            MarketDataMessage::LotDepth { round, odd, .. } => {
+                assert_eq!((round, odd), (empty.clone(), empty));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
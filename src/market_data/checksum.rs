@@ -0,0 +1,83 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Book checksums for feed validation.
+//!
+//! Follows the scheme Kraken uses for its book feed: take the top
+//! [`CHECKSUM_LEVELS`] asks (best first) then the top bids (best first),
+//! concatenate each level's price and quantity as decimal strings, and run
+//! CRC-32 over the result. A consumer computes the same over its mirror and
+//! compares.
+
+use crate::order_book::Depth;
+
+pub const CHECKSUM_LEVELS: usize = 10;
+
+pub fn book_checksum(depth: &Depth) -> u32 {
+    let mut input = String::new();
+    let asks = depth.asks.iter().take(CHECKSUM_LEVELS);
+    let bids = depth.bids.iter().take(CHECKSUM_LEVELS);
+    for level in asks.chain(bids) {
+        input.push_str(&level.price.to_string());
+        input.push_str(&level.qty.to_string());
+    }
+    crc32(input.as_bytes())
+}
+
+/// CRC-32 as used by zlib and Ethernet (reflected, polynomial 0xEDB88320).
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{book_checksum, crc32};
+    use crate::order_book::{Depth, LevelInfo};
+
+    fn level(price: i32, qty: u64) -> LevelInfo {
+        LevelInfo {
+            price,
+            qty,
+            order_count: 1,
+        }
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn checksum_covers_asks_then_bids() {
+        let depth = Depth {
+            bids: vec![level(99, 2)],
+            asks: vec![level(101, 5)],
+        };
+        assert_eq!(book_checksum(&depth), crc32(b"1015992"));
+        let swapped = Depth {
+            bids: depth.asks.clone(),
+            asks: depth.bids.clone(),
+        };
+        assert_ne!(book_checksum(&swapped), book_checksum(&depth));
+
+        let deep = Depth {
+            bids: (0..20).map(|i| level(99 - i, 1)).collect(),
+            asks: Vec::new(),
+        };
+        let top = Depth {
+            bids: deep.bids[..10].to_vec(),
+            asks: Vec::new(),
+        };
+        assert_eq!(book_checksum(&deep), book_checksum(&top));
+    }
+}
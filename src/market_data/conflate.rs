@@ -0,0 +1,154 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Conflated depth publication.
+//!
+//! For consumers that cannot keep up with per-order updates. The
+//! [`Conflator`] publishes a depth snapshot per symbol at most once per
+//! interval: the first change after a quiet period goes out immediately, and
+//! any further changes inside the interval are folded into a single snapshot
+//! of the latest state, sent by [`Conflator::poll`] once the interval is up.
+//! Snapshot sequence numbers count conflated snapshots per symbol.
+
+use super::checksum::book_checksum;
+use super::MarketDataMessage;
+use crate::order_book::Depth;
+use crate::OrderBook;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SymbolState {
+    seq: u64,
+    last_sent: Option<Instant>,
+    /// Latest depth not yet published.
+    pending: Option<Depth>,
+    last_depth: Option<Depth>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflator {
+    interval: Duration,
+    levels: usize,
+    symbols: HashMap<String, SymbolState>,
+}
+
+impl Conflator {
+    pub fn new(interval: Duration, levels: usize) -> Self {
+        Conflator {
+            interval,
+            levels,
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// Takes the book's current depth. Returns a snapshot straight away if
+    /// nothing has been sent for the symbol within the interval; otherwise
+    /// the state is held for [`Self::poll`]. Unchanged depth is ignored.
+    pub fn update(&mut self, order_book: &OrderBook, now: Instant) -> Option<MarketDataMessage> {
+        let depth = order_book.depth(self.levels);
+        let state = self
+            .symbols
+            .entry(order_book.symbol().to_string())
+            .or_insert(SymbolState {
+                seq: 0,
+                last_sent: None,
+                pending: None,
+                last_depth: None,
+            });
+        if state.pending.is_none() && state.last_depth.as_ref() == Some(&depth) {
+            return None;
+        }
+        state.pending = Some(depth);
+        let due = state
+            .last_sent
+            .is_none_or(|sent| now.saturating_duration_since(sent) >= self.interval);
+        if due {
+            Some(Self::emit(order_book.symbol(), state, now))
+        } else {
+            None
+        }
+    }
+
+    /// Publishes the held state of every symbol whose interval has elapsed.
+    pub fn poll(&mut self, now: Instant) -> Vec<MarketDataMessage> {
+        let mut due: Vec<(&String, &mut SymbolState)> = self
+            .symbols
+            .iter_mut()
+            .filter(|(_, state)| {
+                state.pending.is_some()
+                    && state
+                        .last_sent
+                        .is_none_or(|sent| now.saturating_duration_since(sent) >= self.interval)
+            })
+            .collect();
+        due.sort_by(|a, b| a.0.cmp(b.0));
+        due.into_iter()
+            .map(|(symbol, state)| Self::emit(symbol, state, now))
+            .collect()
+    }
+
+    fn emit(symbol: &str, state: &mut SymbolState, now: Instant) -> MarketDataMessage {
+        let depth = state
+            .pending
+            .take()
+            .expect("emit called with pending depth");
+        state.seq += 1;
+        state.last_sent = Some(now);
+        state.last_depth = Some(depth.clone());
+        MarketDataMessage::Snapshot {
+            symbol: symbol.to_string(),
+            seq: state.seq,
+            checksum: book_checksum(&depth),
+            depth,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Conflator;
+    use crate::market_data::MarketDataMessage;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::time::{Duration, Instant};
+
+    fn buy(order_book: &mut OrderBook, price: i32) {
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price,
+            qty: 1,
+            participant: 1,
+        });
+    }
+
+    #[test]
+    fn coalesces_updates_within_interval() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let mut conflator = Conflator::new(Duration::from_millis(100), 5);
+
+        buy(&mut order_book, 100);
+        assert!(conflator.update(&order_book, ms(0)).is_some());
+        for price in 101..110 {
+            buy(&mut order_book, price);
+            assert!(conflator.update(&order_book, ms(10)).is_none());
+        }
+        assert!(conflator.poll(ms(50)).is_empty());
+
+        let sent = conflator.poll(ms(100));
+        assert_eq!(sent.len(), 1);
+        match &sent[0] {
+            MarketDataMessage::Snapshot { seq, depth, .. } => {
+                assert_eq!(*seq, 2);
+                assert_eq!(depth.bids.len(), 5);
+                assert_eq!(depth.bids[0].price, 109);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(conflator.poll(ms(300)).is_empty());
+        assert!(conflator.update(&order_book, ms(300)).is_none());
+    }
+}
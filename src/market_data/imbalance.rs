@@ -0,0 +1,115 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Auction imbalance publication.
+//!
+//! While a book is halted it is in its call for the reopening auction:
+//! orders collect, crossed if need be, until it reopens. An
+//! [`ImbalanceFeed`] tells participants where the book would uncross if it
+//! reopened now, at most once per interval, so they can add or adjust
+//! orders to fill the imbalance before it does. The first message of each
+//! call goes out straight away.
+
+use super::MarketDataMessage;
+use crate::circuit_breaker::TradingStatus;
+use crate::{OrderBook, Side};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImbalanceFeed {
+    interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl ImbalanceFeed {
+    pub fn new(interval: Duration) -> Self {
+        ImbalanceFeed {
+            interval,
+            last_sent: None,
+        }
+    }
+
+    /// The book's imbalance if it is in its auction call, the interval is
+    /// up and the book crosses; `None` otherwise.
+    pub fn poll(&mut self, order_book: &OrderBook, now: Instant) -> Option<MarketDataMessage> {
+        if order_book.status() != TradingStatus::Halted {
+            self.last_sent = None;
+            return None;
+        }
+        if self
+            .last_sent
+            .is_some_and(|sent| now.saturating_duration_since(sent) < self.interval)
+        {
+            return None;
+        }
+        let uncross = order_book.indicative_uncross()?;
+        self.last_sent = Some(now);
+        Some(MarketDataMessage::Imbalance {
+            symbol: order_book.symbol().to_string(),
+            price: uncross.price,
+            paired_qty: uncross.volume,
+            imbalance_qty: uncross.surplus.unsigned_abs(),
+            imbalance_side: match uncross.surplus {
+                0 => None,
+                surplus if surplus > 0 => Some(Side::Buy),
+                _ => Some(Side::Sell),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImbalanceFeed;
+    use crate::market_data::MarketDataMessage;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn publishes_during_the_call_only() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let mut feed = ImbalanceFeed::new(Duration::from_secs(1));
+        let start = Instant::now();
+        let mut new = |side, price, qty| {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant: 1,
+            })
+        };
+        new(Side::Buy, 100, 5);
+        new(Side::Sell, 101, 5);
+        order_book.process_command(OrderCommand::Halt);
+        assert_eq!(feed.poll(&order_book, start), None);
+
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 101,
+            qty: 8,
+            participant: 2,
+        });
+        let imbalance = MarketDataMessage::Imbalance {
+            symbol: "ABC".to_string(),
+            price: 101,
+            paired_qty: 5,
+            imbalance_qty: 3,
+            imbalance_side: Some(Side::Buy),
+        };
+        assert_eq!(feed.poll(&order_book, start), Some(imbalance.clone()));
+        assert_eq!(
+            feed.poll(&order_book, start + Duration::from_millis(500)),
+            None
+        );
+        assert_eq!(
+            feed.poll(&order_book, start + Duration::from_secs(1)),
+            Some(imbalance)
+        );
+
+        order_book.process_command(OrderCommand::Resume { auction: true });
+        assert_eq!(feed.poll(&order_book, start + Duration::from_secs(2)), None);
+    }
+}
@@ -0,0 +1,389 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Incremental level 2 feed.
+//!
+//! [`L2Feed`] remembers the aggregated levels it last published and, given
+//! the book after a change, emits one `Level` message per level that was
+//! added, changed or removed. Level messages are numbered from 1 per symbol.
+//! A late joiner takes a `Snapshot`, which is stamped with the last level
+//! sequence it includes, and applies level messages from there on; that is
+//! what [`MirrorBook`] does. Each batch of level messages is followed by a
+//! `Checksum` of the resulting book so the mirror can confirm it matches.
+
+use super::checksum::book_checksum;
+use super::{LevelAction, MarketDataMessage};
+use crate::backend::OrderBookBackend;
+use crate::codec::invalid;
+use crate::order_book::{Depth, LevelInfo};
+use crate::Side;
+use std::collections::BTreeMap;
+use std::io;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Levels {
+    bids: BTreeMap<i32, LevelInfo>,
+    asks: BTreeMap<i32, LevelInfo>,
+}
+
+impl Levels {
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<i32, LevelInfo> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+
+    fn depth(&self) -> Depth {
+        Depth {
+            bids: self.bids.values().rev().copied().collect(),
+            asks: self.asks.values().copied().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct L2Feed {
+    symbol: String,
+    seq: u64,
+    levels: Levels,
+}
+
+impl L2Feed {
+    pub fn new(symbol: impl Into<String>) -> Self {
+        L2Feed {
+            symbol: symbol.into(),
+            seq: 0,
+            levels: Levels::default(),
+        }
+    }
+
+    /// Sequence number of the last level message produced.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns the level messages that take the last published state to the
+    /// current state of `order_book`, bids before asks and in price order
+    /// within a side, followed by a checksum if anything changed.
+    pub fn update(&mut self, order_book: &impl OrderBookBackend) -> Vec<MarketDataMessage> {
+        let depth = order_book.depth(usize::MAX);
+        let mut messages = Vec::new();
+        for (side, current) in [(Side::Buy, depth.bids), (Side::Sell, depth.asks)] {
+            let current: BTreeMap<i32, LevelInfo> = current
+                .into_iter()
+                .map(|level| (level.price, level))
+                .collect();
+            let previous = std::mem::replace(self.levels.side_mut(side), current);
+            let current = match side {
+                Side::Buy => &self.levels.bids,
+                Side::Sell => &self.levels.asks,
+            };
+
+            let mut changes: Vec<(LevelAction, LevelInfo)> = previous
+                .values()
+                .filter(|level| !current.contains_key(&level.price))
+                .map(|level| {
+                    let removed = LevelInfo {
+                        price: level.price,
+                        qty: 0,
+                        order_count: 0,
+                    };
+                    (LevelAction::Removed, removed)
+                })
+                .collect();
+            for level in current.values() {
+                match previous.get(&level.price) {
+                    None => changes.push((LevelAction::Added, *level)),
+                    Some(old) if old != level => changes.push((LevelAction::Changed, *level)),
+                    Some(_) => {}
+                }
+            }
+            changes.sort_by_key(|(_, level)| level.price);
+
+            for (action, level) in changes {
+                self.seq += 1;
+                messages.push(MarketDataMessage::Level {
+                    symbol: self.symbol.clone(),
+                    seq: self.seq,
+                    action,
+                    side,
+                    level,
+                });
+            }
+        }
+        if !messages.is_empty() {
+            messages.push(MarketDataMessage::Checksum {
+                symbol: self.symbol.clone(),
+                seq: self.seq,
+                checksum: book_checksum(&self.levels.depth()),
+            });
+        }
+        messages
+    }
+
+    /// Full book as last published.
+    pub fn snapshot(&self) -> MarketDataMessage {
+        let depth = self.levels.depth();
+        MarketDataMessage::Snapshot {
+            symbol: self.symbol.clone(),
+            seq: self.seq,
+            checksum: book_checksum(&depth),
+            depth,
+        }
+    }
+}
+
+/// Consumer side of the feed: a local copy of the aggregated book.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MirrorBook {
+    /// Last level sequence applied; `None` until a snapshot arrives or after
+    /// a gap.
+    seq: Option<u64>,
+    levels: Levels,
+}
+
+impl MirrorBook {
+    pub fn new() -> Self {
+        MirrorBook::default()
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.seq.is_some()
+    }
+
+    pub fn depth(&self) -> Depth {
+        self.levels.depth()
+    }
+
+    pub fn checksum(&self) -> u32 {
+        book_checksum(&self.levels.depth())
+    }
+
+    /// Applies one feed message. Level messages are ignored until a snapshot
+    /// has been applied, and those already covered by the snapshot are
+    /// skipped. A gap in the sequence or a checksum mismatch is an error and
+    /// leaves the mirror unsynced until the next snapshot.
+    pub fn apply(&mut self, message: &MarketDataMessage) -> io::Result<()> {
+        match message {
+            MarketDataMessage::Snapshot {
+                seq,
+                depth,
+                checksum,
+                ..
+            } => {
+                let index = |levels: &[LevelInfo]| {
+                    levels
+                        .iter()
+                        .map(|level| (level.price, *level))
+                        .collect::<BTreeMap<_, _>>()
+                };
+                self.levels = Levels {
+                    bids: index(&depth.bids),
+                    asks: index(&depth.asks),
+                };
+                self.seq = Some(*seq);
+                self.verify(*checksum)?;
+            }
+            MarketDataMessage::Level {
+                seq,
+                action,
+                side,
+                level,
+                ..
+            } => {
+                let Some(last) = self.seq else {
+                    return Ok(());
+                };
+                if *seq <= last {
+                    return Ok(());
+                }
+                if *seq != last + 1 {
+                    self.seq = None;
+                    return Err(invalid(format!(
+                        "level update gap: expected {}, got {}",
+                        last + 1,
+                        seq
+                    )));
+                }
+                let levels = self.levels.side_mut(*side);
+                match action {
+                    LevelAction::Added | LevelAction::Changed => {
+                        levels.insert(level.price, *level);
+                    }
+                    LevelAction::Removed => {
+                        levels.remove(&level.price);
+                    }
+                }
+                self.seq = Some(*seq);
+            }
+            MarketDataMessage::Checksum { seq, checksum, .. } => {
+                let Some(last) = self.seq else {
+                    return Ok(());
+                };
+                if *seq < last {
+                    return Ok(());
+                }
+                if *seq != last {
+                    self.seq = None;
+                    return Err(invalid(format!(
+                        "checksum for level update {} but mirror is at {}",
+                        seq, last
+                    )));
+                }
+                self.verify(*checksum)?;
+            }
+            MarketDataMessage::Trade { .. }
+            | MarketDataMessage::Bbo { .. }
+            | MarketDataMessage::Candle { .. }
+            | MarketDataMessage::Stats { .. }
+            | MarketDataMessage::Imbalance { .. }
+            | MarketDataMessage::LotDepth { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn verify(&mut self, expected: u32) -> io::Result<()> {
+        let actual = self.checksum();
+        if actual != expected {
+            self.seq = None;
+            return Err(invalid(format!(
+                "book checksum mismatch: expected {:#010x}, got {:#010x}",
+                expected, actual
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{L2Feed, MirrorBook};
+    use crate::market_data::{LevelAction, MarketDataMessage};
+    use crate::testing::new_order;
+    use crate::{OrderBook, OrderCommand, Side};
+
+    fn commands() -> Vec<OrderCommand> {
+        vec![
+            new_order(Side::Buy, 120, 3),
+            new_order(Side::Buy, 121, 2),
+            new_order(Side::Sell, 124, 5),
+            new_order(Side::Sell, 123, 1),
+            new_order(Side::Buy, 121, 4),
+            new_order(Side::Buy, 123, 2),
+            new_order(Side::Sell, 120, 10),
+            new_order(Side::Buy, 125, 1),
+        ]
+    }
+
+    #[test]
+    fn mirror_tracks_book() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let mut feed = L2Feed::new("ABC");
+        let mut mirror = MirrorBook::new();
+        mirror.apply(&feed.snapshot()).unwrap();
+        for command in commands() {
+            order_book.process_command(command);
+            for message in feed.update(&order_book) {
+                let mut buf = Vec::new();
+                message.encode(&mut buf);
+                mirror
+                    .apply(&MarketDataMessage::decode(&buf).unwrap())
+                    .unwrap();
+            }
+            assert_eq!(mirror.depth(), order_book.depth(usize::MAX));
+        }
+        assert!(feed.update(&order_book).is_empty());
+    }
+
+    #[test]
+    fn reports_each_kind_of_change() {
+        let mut order_book = OrderBook::new();
+        let mut feed = L2Feed::new("ABC");
+        order_book.process_command(new_order(Side::Sell, 123, 2));
+        order_book.process_command(new_order(Side::Sell, 124, 2));
+        feed.update(&order_book);
+
+        order_book.process_command(new_order(Side::Buy, 123, 3));
+        let actions: Vec<(u64, LevelAction, Side, i32)> = feed
+            .update(&order_book)
+            .into_iter()
+            .filter_map(|message| match message {
+                MarketDataMessage::Level {
+                    seq,
+                    action,
+                    side,
+                    level,
+                    ..
+                } => Some((seq, action, side, level.price)),
+                MarketDataMessage::Checksum { .. } => None,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                (3, LevelAction::Added, Side::Buy, 123),
+                (4, LevelAction::Removed, Side::Sell, 123),
+            ]
+        );
+    }
+
+    #[test]
+    fn late_joiner_syncs_from_snapshot_and_detects_gaps() {
+        let mut order_book = OrderBook::new();
+        let mut feed = L2Feed::new("ABC");
+        let mut backlog = Vec::new();
+        let mut mirror = MirrorBook::new();
+        let commands = commands();
+        for command in &commands[..4] {
+            order_book.process_command(command.clone());
+            backlog.extend(feed.update(&order_book));
+        }
+        let snapshot = feed.snapshot();
+        for message in &backlog {
+            mirror.apply(message).unwrap();
+        }
+        assert!(!mirror.is_synced());
+        mirror.apply(&snapshot).unwrap();
+        for message in &backlog {
+            mirror.apply(message).unwrap();
+        }
+        assert_eq!(mirror.depth(), order_book.depth(usize::MAX));
+
+        order_book.process_command(commands[4].clone());
+        order_book.process_command(commands[5].clone());
+        let mut updates = feed.update(&order_book);
+        updates.remove(0);
+        assert!(mirror.apply(&updates[0]).is_err());
+        assert!(!mirror.is_synced());
+    }
+
+    #[test]
+    fn checksum_mismatch_unsyncs_mirror() {
+        let mut order_book = OrderBook::new();
+        let mut feed = L2Feed::new("ABC");
+        let mut mirror = MirrorBook::new();
+        mirror.apply(&feed.snapshot()).unwrap();
+        order_book.process_command(new_order(Side::Buy, 120, 3));
+        let updates = feed.update(&order_book);
+        assert_eq!(updates.len(), 2);
+        let MarketDataMessage::Checksum { checksum, .. } = updates[1] else {
+            panic!("unexpected {:?}", updates[1]);
+        };
+
+        // A level update lost in a way the sequence numbers don't show, e.g.
+        // a mangled quantity, is caught by the checksum.
+        let mut corrupted = updates[0].clone();
+        if let MarketDataMessage::Level { level, .. } = &mut corrupted {
+            level.qty += 1;
+        }
+        mirror.apply(&corrupted).unwrap();
+        assert!(mirror.apply(&updates[1]).is_err());
+        assert!(!mirror.is_synced());
+
+        mirror.apply(&feed.snapshot()).unwrap();
+        assert_eq!(mirror.checksum(), checksum);
+    }
+}
@@ -0,0 +1,385 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Sequenced UDP multicast publisher.
+//!
+//! Packet framing is modelled on MoldUDP64:
+//!
+//! ```text
+//! session: [u8; 10] | sequence: u64 | count: u16 | count * (len: u16, message)
+//! ```
+//!
+//! `sequence` is the sequence number of the first message in the packet and
+//! every message consumes one number, so a receiver that sees a packet start
+//! past `last + 1` knows exactly which messages it missed. A packet with a
+//! count of zero is a heartbeat carrying the next expected sequence number.
+
+use super::MarketDataMessage;
+use crate::codec::Reader;
+use crate::order_book::Bbo;
+use crate::sink::EventSink;
+use crate::{OrderBook, OrderEvent};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+
+pub const SESSION_LEN: usize = 10;
+const HEADER_LEN: usize = SESSION_LEN + 8 + 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MulticastConfig {
+    /// Multicast group (or any unicast address, for testing).
+    pub group: SocketAddr,
+    pub ttl: u32,
+    pub session: [u8; SESSION_LEN],
+    /// Upper bound on datagram size; keep below the path MTU.
+    pub max_packet_size: usize,
+}
+
+impl MulticastConfig {
+    pub fn new(group: SocketAddr) -> Self {
+        MulticastConfig {
+            group,
+            ttl: 1,
+            session: *b"MATCHER001",
+            max_packet_size: 1400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub session: [u8; SESSION_LEN],
+    pub sequence: u64,
+    pub messages: Vec<MarketDataMessage>,
+}
+
+impl Packet {
+    pub fn is_heartbeat(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Sequence number expected at the start of the following packet.
+    pub fn next_sequence(&self) -> u64 {
+        self.sequence.saturating_add(self.messages.len() as u64)
+    }
+}
+
+pub fn decode_packet(bytes: &[u8]) -> io::Result<Packet> {
+    let mut reader = Reader::new(bytes);
+    let session = reader.take(SESSION_LEN)?.try_into().unwrap();
+    let sequence = reader.u64()?;
+    let count = reader.u16()?;
+    let mut messages = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let len = reader.u16()? as usize;
+        messages.push(MarketDataMessage::decode(reader.take(len)?)?);
+    }
+    Ok(Packet {
+        session,
+        sequence,
+        messages,
+    })
+}
+
+pub struct MulticastPublisher {
+    socket: UdpSocket,
+    config: MulticastConfig,
+    next_sequence: u64,
+    packet: Vec<u8>,
+    pending: u16,
+    last_bbo: HashMap<String, Bbo>,
+}
+
+impl MulticastPublisher {
+    pub fn new(config: MulticastConfig) -> io::Result<Self> {
+        let bind: SocketAddr = match config.group.ip() {
+            IpAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+            IpAddr::V6(_) => "[::]:0".parse().unwrap(),
+        };
+        let socket = UdpSocket::bind(bind)?;
+        if let IpAddr::V4(ip) = config.group.ip() {
+            if ip.is_multicast() {
+                socket.set_multicast_ttl_v4(config.ttl)?;
+            }
+        }
+        let mut publisher = MulticastPublisher {
+            socket,
+            config,
+            next_sequence: 1,
+            packet: Vec::new(),
+            pending: 0,
+            last_bbo: HashMap::new(),
+        };
+        publisher.start_packet();
+        Ok(publisher)
+    }
+
+    /// Sequence number that the next queued message will receive.
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Queues a message, sending the current packet first if the message
+    /// would not fit.
+    pub fn send(&mut self, message: &MarketDataMessage) -> io::Result<()> {
+        let mut encoded = Vec::new();
+        message.encode(&mut encoded);
+        if self.pending > 0 && self.packet.len() + 2 + encoded.len() > self.config.max_packet_size {
+            self.flush_packet()?;
+        }
+        self.packet
+            .extend_from_slice(&(encoded.len() as u16).to_le_bytes());
+        self.packet.extend_from_slice(&encoded);
+        self.pending += 1;
+        self.next_sequence += 1;
+        if self.pending == u16::MAX {
+            self.flush_packet()?;
+        }
+        Ok(())
+    }
+
+    /// Publishes the book's BBO if it changed since the last call for its
+    /// symbol.
+    pub fn publish_book(&mut self, order_book: &OrderBook) -> io::Result<()> {
+        let bbo = order_book.bbo();
+        if self.last_bbo.get(order_book.symbol()) == Some(&bbo) {
+            return Ok(());
+        }
+        self.last_bbo.insert(order_book.symbol().to_string(), bbo);
+        self.send(&MarketDataMessage::Bbo {
+            symbol: order_book.symbol().to_string(),
+            bbo,
+        })
+    }
+
+    /// Sends an empty packet so idle receivers can still detect loss of the
+    /// tail of the stream.
+    pub fn heartbeat(&mut self) -> io::Result<()> {
+        self.flush_packet()?;
+        self.socket.send_to(&self.packet, self.config.group)?;
+        Ok(())
+    }
+
+    pub fn flush_packet(&mut self) -> io::Result<()> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        self.packet[SESSION_LEN + 8..HEADER_LEN].copy_from_slice(&self.pending.to_le_bytes());
+        self.socket.send_to(&self.packet, self.config.group)?;
+        self.start_packet();
+        Ok(())
+    }
+
+    fn start_packet(&mut self) {
+        self.packet.clear();
+        self.packet.extend_from_slice(&self.config.session);
+        self.packet
+            .extend_from_slice(&self.next_sequence.to_le_bytes());
+        self.packet.extend_from_slice(&0u16.to_le_bytes());
+        self.pending = 0;
+    }
+}
+
+impl EventSink for MulticastPublisher {
+    /// Only trades go on the wire; order-level events are private to the
+    /// participants involved.
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        match event {
+            OrderEvent::Trade(trade) => self.send(&MarketDataMessage::trade(symbol, trade)),
+            _ => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_packet()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_packet, MulticastConfig, MulticastPublisher, Packet, HEADER_LEN};
+    use crate::market_data::MarketDataMessage;
+    use crate::order_book::Bbo;
+    use crate::sink::publish_events;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::io;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    fn receiver() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+    }
+
+    #[test]
+    fn packets_carry_contiguous_sequences() {
+        let rx = receiver();
+        let mut config = MulticastConfig::new(rx.local_addr().unwrap());
+        config.max_packet_size = 80;
+        let mut publisher = MulticastPublisher::new(config).unwrap();
+
+        let mut order_book = OrderBook::with_symbol("ABC");
+        for _ in 0..3 {
+            for side in [Side::Sell, Side::Buy] {
+                order_book.process_command(OrderCommand::New {
+                    order_type: OrderType::GoodTilCancel,
+                    side,
+                    price: 122,
+                    qty: 1,
+                    participant: 1,
+                });
+            }
+        }
+        publish_events(&mut publisher, order_book.symbol(), order_book.events()).unwrap();
+        publisher.heartbeat().unwrap();
+
+        let mut buf = [0u8; 1500];
+        let mut expected = 1;
+        let mut trades = 0;
+        loop {
+            let len = rx.recv(&mut buf).unwrap();
+            let packet = decode_packet(&buf[..len]).unwrap();
+            assert_eq!(&packet.session, b"MATCHER001");
+            assert_eq!(packet.sequence, expected);
+            expected = packet.next_sequence();
+            if packet.is_heartbeat() {
+                break;
+            }
+            for message in packet.messages {
+                assert!(matches!(
+                    message,
+                    MarketDataMessage::Trade { price: 122, .. }
+                ));
+                trades += 1;
+            }
+        }
+        assert_eq!(trades, 3);
+        assert_eq!(expected, 4);
+    }
+
+    /// A packet of `messages`, each given as its encoding.
+    fn packet(sequence: u64, count: u16, messages: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"MATCHER001".to_vec();
+        bytes.extend_from_slice(&sequence.to_le_bytes());
+        bytes.extend_from_slice(&count.to_le_bytes());
+        for message in messages {
+            bytes.extend_from_slice(&(message.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(message);
+        }
+        bytes
+    }
+
+    #[test]
+    fn rejects_malformed_packets() {
+        let mut bbo = Vec::new();
+        MarketDataMessage::Bbo {
+            symbol: "ABC".to_string(),
+            bbo: Bbo::default(),
+        }
+        .encode(&mut bbo);
+        let whole = packet(7, 1, &[&bbo]);
+        assert_eq!(decode_packet(&whole).unwrap().messages.len(), 1);
+
+        let mut unknown = bbo.clone();
+        unknown[0] = b'?';
+        let cases = [
+            (
+                whole[..HEADER_LEN - 1].to_vec(),
+                "message truncated at byte 18",
+            ),
+            (packet(7, 2, &[&bbo]), "message truncated at byte"),
+            (
+                whole[..whole.len() - 1].to_vec(),
+                "message truncated at byte 22",
+            ),
+            (packet(7, 1, &[&unknown]), "unknown message type 0x3f"),
+        ];
+        for (bytes, msg) in cases {
+            let err = decode_packet(&bytes).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().starts_with(msg), "{}", err);
+        }
+    }
+
+    #[test]
+    fn next_sequence_saturates() {
+        let packet = decode_packet(&packet(u64::MAX, 0, &[])).unwrap();
+        assert!(packet.is_heartbeat());
+        assert_eq!(packet.next_sequence(), u64::MAX);
+        let packet = Packet {
+            messages: vec![MarketDataMessage::Bbo {
+                symbol: "ABC".to_string(),
+                bbo: Bbo::default(),
+            }],
+            ..packet
+        };
+        assert_eq!(packet.next_sequence(), u64::MAX);
+    }
+
+    #[test]
+    fn sends_oversized_messages_alone() {
+        let rx = receiver();
+        let mut config = MulticastConfig::new(rx.local_addr().unwrap());
+        config.max_packet_size = HEADER_LEN;
+        let mut publisher = MulticastPublisher::new(config).unwrap();
+        let message = MarketDataMessage::Bbo {
+            symbol: "ABC".to_string(),
+            bbo: Bbo::default(),
+        };
+        publisher.send(&message).unwrap();
+        publisher.send(&message).unwrap();
+        publisher.flush_packet().unwrap();
+
+        let mut buf = [0u8; 1500];
+        for sequence in [1, 2] {
+            let len = rx.recv(&mut buf).unwrap();
+            let packet = decode_packet(&buf[..len]).unwrap();
+            assert_eq!(packet.sequence, sequence);
+            assert_eq!(packet.messages.len(), 1);
+            assert_eq!(packet.messages[0], message);
+        }
+    }
+
+    #[test]
+    fn publishes_a_book_only_when_its_bbo_changes() {
+        let rx = receiver();
+        let mut publisher =
+            MulticastPublisher::new(MulticastConfig::new(rx.local_addr().unwrap())).unwrap();
+        let mut order_book = OrderBook::with_symbol("ABC");
+        // An empty book has a BBO too, with neither side.
+        publisher.publish_book(&order_book).unwrap();
+        publisher.publish_book(&order_book).unwrap();
+        assert_eq!(publisher.next_sequence(), 2);
+
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 99,
+            qty: 1,
+            participant: 1,
+        });
+        publisher.publish_book(&order_book).unwrap();
+        publisher
+            .publish_book(&OrderBook::with_symbol("XYZ"))
+            .unwrap();
+        publisher.flush_packet().unwrap();
+
+        let mut buf = [0u8; 1500];
+        let len = rx.recv(&mut buf).unwrap();
+        let packet = decode_packet(&buf[..len]).unwrap();
+        let bbos: Vec<_> = packet
+            .messages
+            .iter()
+            .map(|message| match message {
+                MarketDataMessage::Bbo { symbol, bbo } => (symbol.as_str(), bbo.bid.is_some()),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(bbos, [("ABC", false), ("ABC", true), ("XYZ", false)]);
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Tick capture.
+//!
+//! A [`TickRecorder`] writes a book's market data as it happens: every
+//! trade, every level 2 delta with its checksum, and the BBO whenever it
+//! changes. The first record is a snapshot of the book as the recording
+//! starts, so a [`MirrorBook`](super::l2::MirrorBook) fed the recording
+//! from the top rebuilds the book exactly. Each record is the time in
+//! nanoseconds (u64), the message length (u16) and the message in its
+//! binary encoding, all little-endian. Nothing is held in memory beyond
+//! the last published levels; [`TickReader`] streams records back.
+
+use super::l2::L2Feed;
+use super::MarketDataMessage;
+use crate::order_book::Bbo;
+use crate::{OrderBook, Timestamp};
+use std::io::{self, Read, Write};
+
+const RECORD_HEADER_LEN: usize = 10;
+
+#[derive(Debug)]
+pub struct TickRecorder<W> {
+    writer: W,
+    feed: L2Feed,
+    bbo: Bbo,
+    /// Trades already recorded.
+    trades: usize,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> TickRecorder<W> {
+    /// Starts recording `order_book` with a snapshot of it as it stands.
+    /// Trades it has already made are not recorded.
+    pub fn new(order_book: &OrderBook, writer: W) -> io::Result<Self> {
+        let mut recorder = TickRecorder {
+            writer,
+            feed: L2Feed::new(order_book.symbol()),
+            bbo: order_book.bbo(),
+            trades: order_book.trades().len(),
+            buf: Vec::new(),
+        };
+        recorder.feed.update(order_book);
+        let snapshot = recorder.feed.snapshot();
+        recorder.write(order_book.now(), &snapshot)?;
+        Ok(recorder)
+    }
+
+    /// Records what changed in `order_book` since the last call: new trades
+    /// at their own times, then level deltas and the BBO at the book's
+    /// current time. Returns the number of records written.
+    pub fn record(&mut self, order_book: &OrderBook) -> io::Result<usize> {
+        let mut written = 0;
+        let trades = order_book.trades();
+        for trade in trades.get(self.trades..).unwrap_or_default() {
+            let message = MarketDataMessage::trade(order_book.symbol(), trade);
+            self.write(trade.timestamp, &message)?;
+            written += 1;
+        }
+        self.trades = trades.len();
+        let now = order_book.now();
+        for message in self.feed.update(order_book) {
+            self.write(now, &message)?;
+            written += 1;
+        }
+        let bbo = order_book.bbo();
+        if bbo != self.bbo {
+            self.bbo = bbo;
+            let message = MarketDataMessage::Bbo {
+                symbol: order_book.symbol().to_string(),
+                bbo,
+            };
+            self.write(now, &message)?;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write(&mut self, at: Timestamp, message: &MarketDataMessage) -> io::Result<()> {
+        self.buf.clear();
+        message.encode(&mut self.buf);
+        let len = u16::try_from(self.buf.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message too long"))?;
+        self.writer.write_all(&at.as_nanos().to_le_bytes())?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(&self.buf)
+    }
+}
+
+/// Reads a recording back one record at a time.
+#[derive(Debug)]
+pub struct TickReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> TickReader<R> {
+    pub fn new(reader: R) -> Self {
+        TickReader {
+            reader,
+            buf: Vec::new(),
+        }
+    }
+
+    /// The next record, or `None` at the end of the recording. A recording
+    /// that stops partway through a record is an error.
+    pub fn next_record(&mut self) -> io::Result<Option<(Timestamp, MarketDataMessage)>> {
+        let mut header = [0; RECORD_HEADER_LEN];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.reader.read(&mut header[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => filled += n,
+            }
+        }
+        let at = u64::from_le_bytes(header[..8].try_into().expect("8 bytes"));
+        let len = u16::from_le_bytes([header[8], header[9]]);
+        self.buf.resize(len as usize, 0);
+        self.reader.read_exact(&mut self.buf)?;
+        let message = MarketDataMessage::decode(&self.buf)?;
+        Ok(Some((Timestamp::from_nanos(at), message)))
+    }
+}
+
+impl<R: Read> Iterator for TickReader<R> {
+    type Item = io::Result<(Timestamp, MarketDataMessage)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TickReader, TickRecorder};
+    use crate::market_data::l2::MirrorBook;
+    use crate::market_data::MarketDataMessage;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    #[test]
+    fn replays_into_a_mirror() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let new = |order_book: &mut OrderBook, side, price, qty| {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant: 1,
+            })
+        };
+        new(&mut order_book, Side::Buy, 99, 10);
+        new(&mut order_book, Side::Sell, 101, 10);
+        let mut recorder = TickRecorder::new(&order_book, Vec::new()).unwrap();
+        assert_eq!(recorder.record(&order_book).unwrap(), 0);
+
+        new(&mut order_book, Side::Buy, 101, 4);
+        recorder.record(&order_book).unwrap();
+        new(&mut order_book, Side::Sell, 102, 5);
+        recorder.record(&order_book).unwrap();
+        let bytes = recorder.into_inner();
+
+        let mut mirror = MirrorBook::new();
+        let mut kinds = Vec::new();
+        for record in TickReader::new(bytes.as_slice()) {
+            let (_, message) = record.unwrap();
+            mirror.apply(&message).unwrap();
+            kinds.push(match message {
+                MarketDataMessage::Snapshot { .. } => 'S',
+                MarketDataMessage::Trade { price, .. } => {
+                    assert_eq!(price, 101);
+                    'T'
+                }
+                MarketDataMessage::Level { .. } => 'L',
+                MarketDataMessage::Checksum { .. } => 'X',
+                MarketDataMessage::Bbo { .. } => 'B',
+                other => panic!("recorded {:?}", other),
+            });
+        }
+        // The trade changes the ask and the BBO; the new offer behind it
+        // only adds a level.
+        assert_eq!(kinds.iter().collect::<String>(), "STLXBLX");
+        assert_eq!(mirror.depth(), order_book.depth(usize::MAX));
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(TickReader::new(truncated).last().unwrap().is_err());
+    }
+}
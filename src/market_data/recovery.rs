@@ -0,0 +1,262 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Snapshot-plus-delta recovery for late joiners.
+//!
+//! A consumer joining a level feed part way through, or one that has just
+//! seen a gap, starts a [`Recovery`], feeds it the incremental messages as
+//! they arrive and asks the publisher for a snapshot. The deltas are held
+//! until the snapshot comes back. It is stamped with the last level update
+//! it includes, so the held deltas up to that one are dropped and the rest
+//! are applied on top, after which the book follows the feed live.
+//!
+//! The publisher answers from [`L2Feed::snapshot`], which is the book as of
+//! the last level update it published rather than the live book, so the
+//! snapshot always sits exactly on a sequence number of the delta stream.
+//!
+//! [`L2Feed::snapshot`]: super::l2::L2Feed::snapshot
+
+use super::l2::MirrorBook;
+use super::MarketDataMessage;
+use crate::codec::invalid;
+use std::collections::VecDeque;
+use std::io;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recovery {
+    mirror: MirrorBook,
+    /// Deltas received while waiting for a snapshot, oldest first.
+    buffered: VecDeque<MarketDataMessage>,
+    capacity: usize,
+}
+
+impl Recovery {
+    /// Holds up to `capacity` deltas while waiting for a snapshot, dropping
+    /// the oldest beyond that.
+    pub fn new(capacity: usize) -> Self {
+        Recovery {
+            mirror: MirrorBook::new(),
+            buffered: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.mirror.is_synced()
+    }
+
+    pub fn mirror(&self) -> &MirrorBook {
+        &self.mirror
+    }
+
+    /// How many deltas are held for the next snapshot.
+    pub fn buffered(&self) -> usize {
+        self.buffered.len()
+    }
+
+    /// Takes a message from the incremental feed. Once synced it is applied
+    /// straight away; an error means the book has lost sync, later deltas
+    /// are held again and a new snapshot is needed.
+    pub fn apply_delta(&mut self, message: &MarketDataMessage) -> io::Result<()> {
+        if !matches!(
+            message,
+            MarketDataMessage::Level { .. } | MarketDataMessage::Checksum { .. }
+        ) {
+            return Ok(());
+        }
+        if self.mirror.is_synced() {
+            return self.mirror.apply(message);
+        }
+        self.buffered.push_back(message.clone());
+        if self.buffered.len() > self.capacity {
+            self.buffered.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Applies a snapshot and then the held deltas that came after it. It
+    /// is an error if the held deltas no longer reach back to the snapshot,
+    /// because the oldest were dropped; hold on to the deltas and ask for a
+    /// newer snapshot.
+    pub fn apply_snapshot(&mut self, snapshot: &MarketDataMessage) -> io::Result<()> {
+        let MarketDataMessage::Snapshot { seq, .. } = *snapshot else {
+            return Err(invalid(format!("expected a snapshot, got {:?}", snapshot)));
+        };
+        let first = self.buffered.iter().find_map(|message| match message {
+            MarketDataMessage::Level { seq, .. } => Some(*seq),
+            _ => None,
+        });
+        if let Some(first) = first.filter(|&first| first.saturating_sub(1) > seq) {
+            return Err(invalid(format!(
+                "snapshot at level update {} is older than the held updates from {}",
+                seq, first
+            )));
+        }
+        self.mirror.apply(snapshot)?;
+        let result = self
+            .buffered
+            .iter()
+            .try_for_each(|message| self.mirror.apply(message));
+        self.buffered.clear();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Recovery;
+    use crate::market_data::l2::L2Feed;
+    use crate::market_data::MarketDataMessage;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::io;
+
+    fn new_order(order_book: &mut OrderBook, side: Side, price: i32) {
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price,
+            qty: 5,
+            participant: 1,
+        });
+    }
+
+    #[test]
+    fn late_joiner_applies_held_deltas_after_the_snapshot() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let mut feed = L2Feed::new("ABC");
+        new_order(&mut order_book, Side::Buy, 99);
+        feed.update(&order_book);
+
+        // Joins here, and asks for a snapshot while the feed moves on.
+        let mut recovery = Recovery::new(16);
+        new_order(&mut order_book, Side::Sell, 102);
+        for message in feed.update(&order_book) {
+            recovery.apply_delta(&message).unwrap();
+        }
+        let snapshot = feed.snapshot();
+        for price in [101, 103] {
+            new_order(&mut order_book, Side::Sell, price);
+            for message in feed.update(&order_book) {
+                recovery.apply_delta(&message).unwrap();
+            }
+        }
+        assert!(!recovery.is_synced());
+        assert_eq!(recovery.buffered(), 6);
+
+        recovery.apply_snapshot(&snapshot).unwrap();
+        assert!(recovery.is_synced());
+        assert_eq!(recovery.buffered(), 0);
+        new_order(&mut order_book, Side::Buy, 100);
+        for message in feed.update(&order_book) {
+            recovery.apply_delta(&message).unwrap();
+        }
+        assert_eq!(recovery.mirror().depth(), order_book.depth(usize::MAX));
+
+        // Too small a buffer loses the deltas right after an old snapshot.
+        let mut recovery = Recovery::new(2);
+        let snapshot = feed.snapshot();
+        for price in [104, 105] {
+            new_order(&mut order_book, Side::Sell, price);
+            for message in feed.update(&order_book) {
+                recovery.apply_delta(&message).unwrap();
+            }
+        }
+        assert!(recovery.apply_snapshot(&snapshot).is_err());
+        assert!(!recovery.is_synced());
+        recovery.apply_snapshot(&feed.snapshot()).unwrap();
+        assert_eq!(recovery.mirror().depth(), order_book.depth(usize::MAX));
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_snapshot() {
+        let mut feed = L2Feed::new("ABC");
+        let mut order_book = OrderBook::with_symbol("ABC");
+        new_order(&mut order_book, Side::Buy, 99);
+        let messages = feed.update(&order_book);
+        let mut recovery = Recovery::new(16);
+        for message in &messages {
+            let err = recovery.apply_snapshot(message).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().starts_with("expected a snapshot, got "));
+        }
+        assert!(!recovery.is_synced());
+    }
+
+    #[test]
+    fn syncs_on_an_empty_book() {
+        let feed = L2Feed::new("ABC");
+        let mut recovery = Recovery::new(16);
+        // Only level and checksum messages are held.
+        recovery.apply_delta(&feed.snapshot()).unwrap();
+        assert_eq!(recovery.buffered(), 0);
+
+        recovery.apply_snapshot(&feed.snapshot()).unwrap();
+        assert!(recovery.is_synced());
+        assert_eq!(
+            recovery.mirror().depth(),
+            OrderBook::new().depth(usize::MAX)
+        );
+    }
+
+    #[test]
+    fn gap_after_syncing_holds_deltas_again() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let mut feed = L2Feed::new("ABC");
+        let mut recovery = Recovery::new(16);
+        recovery.apply_snapshot(&feed.snapshot()).unwrap();
+
+        // The first update is lost.
+        new_order(&mut order_book, Side::Buy, 99);
+        feed.update(&order_book);
+        new_order(&mut order_book, Side::Sell, 101);
+        let mut messages = feed.update(&order_book).into_iter();
+        let err = recovery.apply_delta(&messages.next().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "level update gap: expected 1, got 2");
+        assert!(!recovery.is_synced());
+        for message in messages {
+            recovery.apply_delta(&message).unwrap();
+        }
+        assert_eq!(recovery.buffered(), 1);
+
+        recovery.apply_snapshot(&feed.snapshot()).unwrap();
+        assert_eq!(recovery.mirror().depth(), order_book.depth(usize::MAX));
+    }
+
+    #[test]
+    fn snapshot_at_the_last_sequence_number_takes_no_held_deltas() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let mut feed = L2Feed::new("ABC");
+        let mut snapshot = feed.snapshot();
+        let MarketDataMessage::Snapshot { seq, .. } = &mut snapshot else {
+            unreachable!();
+        };
+        *seq = u64::MAX;
+        let mut recovery = Recovery::new(16);
+        new_order(&mut order_book, Side::Buy, 99);
+        for message in feed.update(&order_book) {
+            recovery.apply_delta(&message).unwrap();
+        }
+
+        recovery.apply_snapshot(&snapshot).unwrap();
+        assert!(recovery.is_synced());
+        assert_eq!(recovery.buffered(), 0);
+        assert!(recovery.mirror().depth().bids.is_empty());
+    }
+
+    #[test]
+    fn holds_nothing_with_no_capacity() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let mut feed = L2Feed::new("ABC");
+        let snapshot = feed.snapshot();
+        let mut recovery = Recovery::new(0);
+        new_order(&mut order_book, Side::Buy, 99);
+        for message in feed.update(&order_book) {
+            recovery.apply_delta(&message).unwrap();
+        }
+        assert_eq!(recovery.buffered(), 0);
+        // With nothing held there is nothing to show the snapshot is stale.
+        recovery.apply_snapshot(&snapshot).unwrap();
+        assert!(recovery.is_synced());
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Mass quotes.
+//!
+//! A market maker keeps one bid and one offer per symbol. An
+//! [`OrderCommand::MassQuote`] sets both for any number of symbols in a
+//! single message, replacing whatever was quoted before. A book acts on the
+//! last entry for its own symbol and ignores the rest: it checks both sides
+//! first and rejects the whole entry if either fails, then pulls or shrinks
+//! the old quotes before placing the new ones, so a quote never trades with
+//! the one it replaces. Each side that is set emits
+//! [`OrderEvent::Quoted`](crate::OrderEvent::Quoted); a side pulled emits
+//! `Canceled`.
+//!
+//! Quote orders are otherwise ordinary good-til-cancel orders: they match
+//! on entry, can be canceled by id, and count toward the participant's risk
+//! limits. [`submit`] spreads one mass quote over several books and applies
+//! it only if every book would accept its part.
+
+use crate::risk::RejectReason;
+use crate::{OrderBook, OrderCommand, ParticipantId, Price, Side};
+use serde::{Deserialize, Serialize};
+
+/// One symbol's two-sided quote. A side with a quantity of zero is pulled.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct QuoteEntry<P = i32> {
+    pub symbol: String,
+    pub bid_price: P,
+    pub bid_qty: u32,
+    pub ask_price: P,
+    pub ask_qty: u32,
+}
+
+impl<P: Copy> QuoteEntry<P> {
+    /// The bid then the ask, as side, price and quantity.
+    pub fn sides(&self) -> [(Side, P, u32); 2] {
+        [
+            (Side::Buy, self.bid_price, self.bid_qty),
+            (Side::Sell, self.ask_price, self.ask_qty),
+        ]
+    }
+}
+
+/// What happens to a quote's place in the queue when it is replaced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum QuotePriority {
+    /// A side requoted at the same price for no more than it has left is
+    /// resized in place, keeping its id and its place in the queue.
+    #[default]
+    Preserve,
+    /// Every requote goes to the back of the queue under a new id.
+    Reset,
+}
+
+/// Checks `quotes` against every book and, if all of them accept their
+/// entries, submits it to each book that has one. Otherwise nothing is
+/// submitted and the first reason found is returned. Entries for symbols
+/// with no book here are ignored.
+///
+/// Rate limits are spent book by book as the quote is submitted, so a
+/// throttled book can still reject its part after the others took theirs.
+pub fn submit<'a, P: Price>(
+    books: impl IntoIterator<Item = &'a mut OrderBook<P>>,
+    participant: ParticipantId,
+    quotes: Vec<QuoteEntry<P>>,
+) -> Result<(), RejectReason> {
+    let mut books: Vec<&mut OrderBook<P>> = books
+        .into_iter()
+        .filter(|book| quotes.iter().any(|quote| quote.symbol == book.symbol()))
+        .collect();
+    for book in &books {
+        book.check_mass_quote(participant, &quotes)?;
+    }
+    for book in &mut books {
+        book.process_command(OrderCommand::MassQuote {
+            participant,
+            quotes: quotes.clone(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{submit, QuoteEntry, QuotePriority};
+    use crate::risk::RejectReason;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+
+    fn quote(bid_price: i32, bid_qty: u32, ask_price: i32, ask_qty: u32) -> QuoteEntry {
+        QuoteEntry {
+            symbol: String::new(),
+            bid_price,
+            bid_qty,
+            ask_price,
+            ask_qty,
+        }
+    }
+
+    fn mass_quote(order_book: &mut OrderBook, entry: QuoteEntry) {
+        order_book.process_command(OrderCommand::MassQuote {
+            participant: 1,
+            quotes: vec![entry],
+        });
+    }
+
+    #[test]
+    fn replaces_quotes_in_place() {
+        let mut order_book = OrderBook::new();
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 99,
+            qty: 10,
+            participant: 2,
+        });
+        mass_quote(&mut order_book, quote(99, 10, 101, 10));
+        let bid = order_book.quoted(1, Side::Buy).unwrap().id;
+        let ask = order_book.quoted(1, Side::Sell).unwrap().id;
+
+        // Smaller at the same price keeps the id and stays behind
+        // participant 2; a new price gets a new id.
+        mass_quote(&mut order_book, quote(99, 4, 102, 10));
+        assert_eq!(order_book.quoted(1, Side::Buy).unwrap().id, bid);
+        assert_eq!(order_book.quoted(1, Side::Buy).unwrap().remaining_qty, 4);
+        assert_ne!(order_book.quoted(1, Side::Sell).unwrap().id, ask);
+        assert!(order_book
+            .events()
+            .contains(&OrderEvent::Canceled { id: ask }));
+        assert_eq!(order_book.bids[0].total_qty(), 14);
+        assert_eq!(order_book.bids[0].orders[1].id, bid);
+        assert_eq!(order_book.exposure(1).gross(), 99 * 4 + 102 * 10);
+
+        // Sizing up goes to the back of the queue.
+        mass_quote(&mut order_book, quote(99, 5, 102, 0));
+        let requoted = order_book.quoted(1, Side::Buy).unwrap().id;
+        assert_ne!(requoted, bid);
+        assert!(order_book.quoted(1, Side::Sell).is_none());
+        assert!(order_book.asks.is_empty());
+        assert_eq!(order_book.open_orders(1), 1);
+
+        order_book.set_quote_priority(QuotePriority::Reset);
+        mass_quote(&mut order_book, quote(99, 5, 102, 0));
+        assert_ne!(order_book.quoted(1, Side::Buy).unwrap().id, requoted);
+    }
+
+    #[test]
+    fn rejects_a_crossed_quote_and_does_not_trade_with_itself() {
+        let mut order_book = OrderBook::new();
+        mass_quote(&mut order_book, quote(100, 10, 101, 10));
+        mass_quote(&mut order_book, quote(101, 10, 101, 10));
+        assert!(matches!(
+            order_book.events().last(),
+            Some(OrderEvent::Rejected {
+                reason: RejectReason::CrossedQuote,
+                ..
+            })
+        ));
+        assert_eq!(order_book.quoted(1, Side::Buy).unwrap().price, 100);
+
+        // Moving both sides up past the old ask trades with nothing.
+        mass_quote(&mut order_book, quote(101, 10, 102, 10));
+        assert!(order_book.trades().is_empty());
+        assert_eq!(
+            (order_book.best_bid(), order_book.best_ask()),
+            (Some(101), Some(102))
+        );
+    }
+
+    #[test]
+    fn submits_across_books_only_if_all_accept() {
+        let mut books = [OrderBook::with_symbol("A"), OrderBook::with_symbol("B")];
+        let entry = |symbol: &str, bid_price, ask_price| QuoteEntry {
+            symbol: symbol.to_string(),
+            ..quote(bid_price, 10, ask_price, 10)
+        };
+        assert_eq!(
+            submit(&mut books, 1, vec![entry("A", 10, 11), entry("B", 21, 20)]),
+            Err(RejectReason::CrossedQuote)
+        );
+        assert!(books.iter().all(|book| book.events().is_empty()));
+
+        submit(
+            &mut books,
+            1,
+            vec![entry("A", 10, 11), entry("B", 20, 21), entry("C", 1, 2)],
+        )
+        .unwrap();
+        assert_eq!(books[0].best_bid(), Some(10));
+        assert_eq!(books[1].best_ask(), Some(21));
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Operational metrics.
+//!
+//! After each command the book reports what it did to a [`MetricsSink`]:
+//!
+//! | metric                          | kind      | labels             |
+//! |---------------------------------|-----------|--------------------|
+//! | `matcher_commands_total`        | counter   | `symbol`           |
+//! | `matcher_orders_placed_total`   | counter   | `symbol`           |
+//! | `matcher_orders_rejected_total` | counter   | `symbol`, `reason` |
+//! | `matcher_orders_canceled_total` | counter   | `symbol`           |
+//! | `matcher_trades_total`          | counter   | `symbol`           |
+//! | `matcher_traded_qty_total`      | counter   | `symbol`           |
+//! | `matcher_trade_qty`             | histogram | `symbol`           |
+//! | `matcher_fills_per_command`     | histogram | `symbol`           |
+//!
+//! `matcher_fills_per_command` is only recorded for commands that traded.
+//! The book owns its sink, so sinks that are read back, like
+//! [`InMemoryMetrics`] and [`prometheus::PrometheusMetrics`], are handles
+//! onto shared storage: keep a clone and install the other.
+
+use crate::OrderEvent;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub mod prometheus;
+
+pub type Labels<'a> = &'a [(&'a str, &'a str)];
+
+pub trait MetricsSink {
+    /// Adds `value` to a counter.
+    fn record_counter(&mut self, name: &str, labels: Labels, value: u64);
+
+    /// Adds one observation to a histogram.
+    fn record_histogram(&mut self, name: &str, labels: Labels, value: f64);
+}
+
+/// Discards everything. The book's default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopMetrics;
+
+impl MetricsSink for NoopMetrics {
+    fn record_counter(&mut self, _: &str, _: Labels, _: u64) {}
+
+    fn record_histogram(&mut self, _: &str, _: Labels, _: f64) {}
+}
+
+/// Keeps every counter total and histogram observation, keyed by series as
+/// Prometheus would write it, e.g. `matcher_trades_total{symbol="ABC"}`.
+#[derive(Debug, Default, Clone)]
+pub struct InMemoryMetrics {
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    counters: BTreeMap<String, u64>,
+    histograms: BTreeMap<String, Vec<f64>>,
+}
+
+impl InMemoryMetrics {
+    pub fn new() -> Self {
+        InMemoryMetrics::default()
+    }
+
+    pub fn counter(&self, name: &str, labels: Labels) -> u64 {
+        let key = series(name, labels);
+        self.lock().counters.get(&key).copied().unwrap_or(0)
+    }
+
+    /// Observations in the order they were recorded.
+    pub fn histogram(&self, name: &str, labels: Labels) -> Vec<f64> {
+        let key = series(name, labels);
+        self.lock()
+            .histograms
+            .get(&key)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn counters(&self) -> BTreeMap<String, u64> {
+        self.lock().counters.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Recorded> {
+        self.recorded.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl MetricsSink for InMemoryMetrics {
+    fn record_counter(&mut self, name: &str, labels: Labels, value: u64) {
+        *self
+            .lock()
+            .counters
+            .entry(series(name, labels))
+            .or_default() += value;
+    }
+
+    fn record_histogram(&mut self, name: &str, labels: Labels, value: f64) {
+        let key = series(name, labels);
+        self.lock().histograms.entry(key).or_default().push(value);
+    }
+}
+
+/// `name{k="v",...}`, or just `name` without labels.
+pub(crate) fn series(name: &str, labels: Labels) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+        .collect();
+    format!("{}{{{}}}", name, labels.join(","))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The sink installed in a book. Sinks are opaque, so two books compare
+/// equal whatever they report to.
+pub(crate) struct Metrics(Box<dyn MetricsSink + Send>);
+
+impl Metrics {
+    pub(crate) fn new(sink: impl MetricsSink + Send + 'static) -> Self {
+        Metrics(Box::new(sink))
+    }
+
+    /// Reports one command's outcome from the `events` it produced.
+    pub(crate) fn command<P>(&mut self, symbol: &str, events: &[OrderEvent<P>]) {
+        let labels: Labels = &[("symbol", symbol)];
+        let sink = &mut self.0;
+        sink.record_counter("matcher_commands_total", labels, 1);
+        let mut fills = 0u32;
+        for event in events {
+            match event {
+                OrderEvent::Placed { .. } => {
+                    sink.record_counter("matcher_orders_placed_total", labels, 1)
+                }
+                OrderEvent::Rejected { reason, .. } => {
+                    let reason = format!("{:?}", reason);
+                    let labels: Labels = &[("symbol", symbol), ("reason", &reason)];
+                    sink.record_counter("matcher_orders_rejected_total", labels, 1);
+                }
+                OrderEvent::Canceled { .. } => {
+                    sink.record_counter("matcher_orders_canceled_total", labels, 1)
+                }
+                OrderEvent::Trade(trade) => {
+                    fills += 1;
+                    sink.record_counter("matcher_trades_total", labels, 1);
+                    sink.record_counter("matcher_traded_qty_total", labels, trade.qty.into());
+                    sink.record_histogram("matcher_trade_qty", labels, trade.qty.into());
+                }
+                _ => {}
+            }
+        }
+        if fills > 0 {
+            sink.record_histogram("matcher_fills_per_command", labels, fills.into());
+        }
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new(NoopMetrics)
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Metrics")
+    }
+}
+
+impl PartialEq for Metrics {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{series, InMemoryMetrics};
+    use crate::risk::RiskLimits;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    #[test]
+    fn book_reports_to_installed_sink() {
+        let metrics = InMemoryMetrics::new();
+        let mut order_book = OrderBook::with_symbol("ABC");
+        order_book.set_metrics_sink(metrics.clone());
+        order_book.risk_mut().set_default(RiskLimits {
+            max_order_qty: Some(10),
+            ..RiskLimits::default()
+        });
+        for (side, qty) in [
+            (Side::Sell, 2),
+            (Side::Sell, 3),
+            (Side::Buy, 50),
+            (Side::Buy, 4),
+        ] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::FillAndKill,
+                side,
+                price: 100,
+                qty,
+                participant: 1,
+            });
+        }
+
+        let symbol = &[("symbol", "ABC")];
+        assert_eq!(metrics.counter("matcher_commands_total", symbol), 4);
+        assert_eq!(metrics.counter("matcher_orders_placed_total", symbol), 3);
+        // Nothing rests, so every accepted order is canceled unfilled.
+        assert_eq!(metrics.counter("matcher_orders_canceled_total", symbol), 3);
+        assert_eq!(metrics.counter("matcher_trades_total", symbol), 0);
+        let rejected = &[("symbol", "ABC"), ("reason", "MaxOrderQty")];
+        assert_eq!(
+            metrics.counter("matcher_orders_rejected_total", rejected),
+            1
+        );
+
+        for qty in [2, 3] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side: Side::Sell,
+                price: 100,
+                qty,
+                participant: 1,
+            });
+        }
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 100,
+            qty: 4,
+            participant: 2,
+        });
+        assert_eq!(metrics.counter("matcher_trades_total", symbol), 2);
+        assert_eq!(metrics.counter("matcher_traded_qty_total", symbol), 4);
+        assert_eq!(metrics.histogram("matcher_trade_qty", symbol), [2.0, 2.0]);
+        assert_eq!(
+            metrics.histogram("matcher_fills_per_command", symbol),
+            [2.0]
+        );
+    }
+
+    #[test]
+    fn series_escapes_label_values() {
+        assert_eq!(series("up", &[]), "up");
+        assert_eq!(
+            series("up", &[("a", "x\"y"), ("b", "1")]),
+            "up{a=\"x\\\"y\",b=\"1\"}"
+        );
+    }
+}
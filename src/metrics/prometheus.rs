@@ -0,0 +1,172 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Prometheus text exposition.
+//!
+//! [`PrometheusMetrics::render`] produces the body of a `/metrics` scrape.
+//! Histograms use one set of bucket bounds for every metric.
+
+use super::{series, Labels, MetricsSink};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Upper bounds suited to quantities and counts: 1, 2, 5, 10, ... 100000.
+pub const DEFAULT_BUCKETS: [f64; 16] = [
+    1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1e3, 2e3, 5e3, 1e4, 2e4, 5e4, 1e5,
+];
+
+#[derive(Debug, Clone)]
+pub struct PrometheusMetrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+#[derive(Debug)]
+struct Registry {
+    buckets: Vec<f64>,
+    /// Series by metric name, so each metric's `# TYPE` line is written once.
+    counters: BTreeMap<String, BTreeMap<Vec<(String, String)>, u64>>,
+    histograms: BTreeMap<String, BTreeMap<Vec<(String, String)>, Histogram>>,
+}
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    /// Observations at or below each bound, not yet cumulative.
+    counts: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Default for PrometheusMetrics {
+    fn default() -> Self {
+        PrometheusMetrics::with_buckets(DEFAULT_BUCKETS.to_vec())
+    }
+}
+
+impl PrometheusMetrics {
+    pub fn new() -> Self {
+        PrometheusMetrics::default()
+    }
+
+    /// Histograms will use these upper bounds, plus `+Inf`.
+    pub fn with_buckets(mut buckets: Vec<f64>) -> Self {
+        buckets.sort_by(f64::total_cmp);
+        buckets.dedup();
+        PrometheusMetrics {
+            registry: Arc::new(Mutex::new(Registry {
+                buckets,
+                counters: BTreeMap::new(),
+                histograms: BTreeMap::new(),
+            })),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let registry = self.lock();
+        let mut out = String::new();
+        for (name, all) in &registry.counters {
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (labels, value) in all {
+                let _ = writeln!(out, "{} {}", series(name, &borrowed(labels)), value);
+            }
+        }
+        for (name, all) in &registry.histograms {
+            let _ = writeln!(out, "# TYPE {} histogram", name);
+            for (labels, histogram) in all {
+                let labels = borrowed(labels);
+                let bucket = |le: &str, count: u64| {
+                    let mut labels = labels.clone();
+                    labels.push(("le", le));
+                    format!("{} {}", series(&format!("{}_bucket", name), &labels), count)
+                };
+                let mut cumulative = 0;
+                for (bound, count) in registry.buckets.iter().zip(&histogram.counts) {
+                    cumulative += count;
+                    let _ = writeln!(out, "{}", bucket(&bound.to_string(), cumulative));
+                }
+                let _ = writeln!(out, "{}", bucket("+Inf", histogram.count));
+                let sum = format!("{}_sum", name);
+                let count = format!("{}_count", name);
+                let _ = writeln!(out, "{} {}", series(&sum, &labels), histogram.sum);
+                let _ = writeln!(out, "{} {}", series(&count, &labels), histogram.count);
+            }
+        }
+        out
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl MetricsSink for PrometheusMetrics {
+    fn record_counter(&mut self, name: &str, labels: Labels, value: u64) {
+        let mut registry = self.lock();
+        let all = registry.counters.entry(name.to_string()).or_default();
+        *all.entry(owned(labels)).or_default() += value;
+    }
+
+    fn record_histogram(&mut self, name: &str, labels: Labels, value: f64) {
+        let mut registry = self.lock();
+        let registry = &mut *registry;
+        let bound = registry.buckets.partition_point(|&bound| bound < value);
+        let histogram = registry
+            .histograms
+            .entry(name.to_string())
+            .or_default()
+            .entry(owned(labels))
+            .or_insert_with(|| Histogram {
+                counts: vec![0; registry.buckets.len()],
+                count: 0,
+                sum: 0.0,
+            });
+        if let Some(count) = histogram.counts.get_mut(bound) {
+            *count += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += value;
+    }
+}
+
+fn owned(labels: Labels) -> Vec<(String, String)> {
+    labels
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+fn borrowed(labels: &[(String, String)]) -> Vec<(&str, &str)> {
+    labels
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrometheusMetrics;
+    use crate::metrics::MetricsSink;
+
+    #[test]
+    fn renders_counters_and_cumulative_buckets() {
+        let metrics = PrometheusMetrics::with_buckets(vec![10.0, 1.0]);
+        let mut sink = metrics.clone();
+        sink.record_counter("trades_total", &[("symbol", "ABC")], 2);
+        sink.record_counter("trades_total", &[("symbol", "ABC")], 1);
+        for qty in [1.0, 3.0, 30.0] {
+            sink.record_histogram("trade_qty", &[("symbol", "ABC")], qty);
+        }
+        assert_eq!(
+            metrics.render(),
+            "# TYPE trades_total counter\n\
+             trades_total{symbol=\"ABC\"} 3\n\
+             # TYPE trade_qty histogram\n\
+             trade_qty_bucket{symbol=\"ABC\",le=\"1\"} 1\n\
+             trade_qty_bucket{symbol=\"ABC\",le=\"10\"} 2\n\
+             trade_qty_bucket{symbol=\"ABC\",le=\"+Inf\"} 3\n\
+             trade_qty_sum{symbol=\"ABC\"} 34\n\
+             trade_qty_count{symbol=\"ABC\"} 3\n"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A Node.js addon, used through `node/index.js`.
+//!
+//! Node loads the shared library, renamed `matcher.node`, and calls
+//! [`napi_register_module_v1`] to fill in its exports. The N-API functions
+//! are declared here rather than taken from a bindings crate; the addon
+//! leaves them undefined and Node supplies them when it loads it. Books are
+//! handed to JavaScript as externals and dropped when those are collected.
+//! Commands, events and depth cross as the same JSON as the REST API, which
+//! `node/index.js` parses and turns into events on an `EventEmitter`.
+
+use crate::{OrderBook, OrderCommand};
+use std::ffi::{c_char, c_void};
+use std::ptr;
+
+type Env = *mut c_void;
+type Value = *mut c_void;
+type CallbackInfo = *mut c_void;
+type Callback = unsafe extern "C" fn(Env, CallbackInfo) -> Value;
+type Finalize = unsafe extern "C" fn(Env, *mut c_void, *mut c_void);
+
+const OK: i32 = 0;
+
+extern "C" {
+    fn napi_get_cb_info(
+        env: Env,
+        info: CallbackInfo,
+        argc: *mut usize,
+        argv: *mut Value,
+        this: *mut Value,
+        data: *mut *mut c_void,
+    ) -> i32;
+    fn napi_get_value_string_utf8(
+        env: Env,
+        value: Value,
+        buf: *mut c_char,
+        len: usize,
+        result: *mut usize,
+    ) -> i32;
+    fn napi_get_value_uint32(env: Env, value: Value, result: *mut u32) -> i32;
+    fn napi_get_value_external(env: Env, value: Value, result: *mut *mut c_void) -> i32;
+    fn napi_create_string_utf8(env: Env, s: *const c_char, len: usize, result: *mut Value) -> i32;
+    fn napi_create_external(
+        env: Env,
+        data: *mut c_void,
+        finalize: Finalize,
+        hint: *mut c_void,
+        result: *mut Value,
+    ) -> i32;
+    fn napi_create_function(
+        env: Env,
+        name: *const c_char,
+        len: usize,
+        callback: Callback,
+        data: *mut c_void,
+        result: *mut Value,
+    ) -> i32;
+    fn napi_set_named_property(env: Env, object: Value, name: *const c_char, value: Value) -> i32;
+    fn napi_throw_error(env: Env, code: *const c_char, msg: *const c_char) -> i32;
+}
+
+/// Processes a JSON command and returns the events it produced as a JSON
+/// array, or `None` if the command doesn't parse.
+pub fn submit_json(order_book: &mut OrderBook, command: &str) -> Option<String> {
+    let command: OrderCommand = serde_json::from_str(command).ok()?;
+    let seen = order_book.events().len();
+    order_book.process_command(command);
+    serde_json::to_string(&order_book.events()[seen..]).ok()
+}
+
+/// Adds the addon's functions to `exports`.
+///
+/// # Safety
+///
+/// Only Node calls this, while loading the addon.
+#[no_mangle]
+pub unsafe extern "C" fn napi_register_module_v1(env: Env, exports: Value) -> Value {
+    let functions: [(&[u8], Callback); 4] = [
+        (b"newBook\0", new_book),
+        (b"submit\0", submit),
+        (b"depth\0", depth),
+        (b"trades\0", trades),
+    ];
+    for (name, callback) in functions {
+        let mut function = ptr::null_mut();
+        let name = name.as_ptr().cast();
+        if napi_create_function(
+            env,
+            name,
+            usize::MAX,
+            callback,
+            ptr::null_mut(),
+            &mut function,
+        ) != OK
+            || napi_set_named_property(env, exports, name, function) != OK
+        {
+            return ptr::null_mut();
+        }
+    }
+    exports
+}
+
+/// The call's first `N` arguments, or a thrown error if there are fewer.
+unsafe fn args<const N: usize>(env: Env, info: CallbackInfo) -> Option<[Value; N]> {
+    let mut argv = [ptr::null_mut(); N];
+    let mut argc = N;
+    let status = napi_get_cb_info(
+        env,
+        info,
+        &mut argc,
+        argv.as_mut_ptr(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+    );
+    if status != OK || argc < N {
+        throw(env, "missing arguments");
+        return None;
+    }
+    Some(argv)
+}
+
+unsafe fn throw(env: Env, message: &str) {
+    let message = format!("{}\0", message);
+    napi_throw_error(env, ptr::null(), message.as_ptr().cast());
+}
+
+unsafe fn string(env: Env, value: Value) -> Option<String> {
+    let mut len = 0;
+    if napi_get_value_string_utf8(env, value, ptr::null_mut(), 0, &mut len) != OK {
+        throw(env, "expected a string");
+        return None;
+    }
+    let mut buf = vec![0_u8; len + 1];
+    napi_get_value_string_utf8(env, value, buf.as_mut_ptr().cast(), buf.len(), &mut len);
+    buf.truncate(len);
+    String::from_utf8(buf).ok()
+}
+
+unsafe fn uint32(env: Env, value: Value) -> Option<u32> {
+    let mut n = 0;
+    if napi_get_value_uint32(env, value, &mut n) != OK {
+        throw(env, "expected a number");
+        return None;
+    }
+    Some(n)
+}
+
+unsafe fn book<'a>(env: Env, value: Value) -> Option<&'a mut OrderBook> {
+    let mut data = ptr::null_mut();
+    if napi_get_value_external(env, value, &mut data) != OK || data.is_null() {
+        throw(env, "expected a book");
+        return None;
+    }
+    Some(&mut *data.cast::<OrderBook>())
+}
+
+unsafe fn to_js(env: Env, s: &str) -> Value {
+    let mut value = ptr::null_mut();
+    napi_create_string_utf8(env, s.as_ptr().cast(), s.len(), &mut value);
+    value
+}
+
+unsafe extern "C" fn drop_book(_env: Env, data: *mut c_void, _hint: *mut c_void) {
+    drop(Box::from_raw(data.cast::<OrderBook>()));
+}
+
+/// `newBook(symbol)`
+unsafe extern "C" fn new_book(env: Env, info: CallbackInfo) -> Value {
+    let Some([symbol]) = args(env, info) else {
+        return ptr::null_mut();
+    };
+    let Some(symbol) = string(env, symbol) else {
+        return ptr::null_mut();
+    };
+    let data = Box::into_raw(Box::new(OrderBook::with_symbol(&symbol)));
+    let mut external = ptr::null_mut();
+    if napi_create_external(env, data.cast(), drop_book, ptr::null_mut(), &mut external) != OK {
+        drop(Box::from_raw(data));
+        return ptr::null_mut();
+    }
+    external
+}
+
+/// `submit(book, commandJson)`
+unsafe extern "C" fn submit(env: Env, info: CallbackInfo) -> Value {
+    let Some([order_book, command]) = args(env, info) else {
+        return ptr::null_mut();
+    };
+    let (Some(order_book), Some(command)) = (book(env, order_book), string(env, command)) else {
+        return ptr::null_mut();
+    };
+    match submit_json(order_book, &command) {
+        Some(events) => to_js(env, &events),
+        None => {
+            throw(env, &format!("invalid command {}", command));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// `depth(book, levels)`
+unsafe extern "C" fn depth(env: Env, info: CallbackInfo) -> Value {
+    let Some([order_book, levels]) = args(env, info) else {
+        return ptr::null_mut();
+    };
+    let (Some(order_book), Some(levels)) = (book(env, order_book), uint32(env, levels)) else {
+        return ptr::null_mut();
+    };
+    let depth = order_book.depth(levels as usize);
+    to_js(env, &serde_json::to_string(&depth).unwrap_or_default())
+}
+
+/// `trades(book, count)`
+unsafe extern "C" fn trades(env: Env, info: CallbackInfo) -> Value {
+    let Some([order_book, count]) = args(env, info) else {
+        return ptr::null_mut();
+    };
+    let (Some(order_book), Some(count)) = (book(env, order_book), uint32(env, count)) else {
+        return ptr::null_mut();
+    };
+    let trades = order_book.tape().last(count as usize);
+    to_js(env, &serde_json::to_string(trades).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::submit_json;
+    use crate::OrderBook;
+    use serde_json::Value;
+
+    #[test]
+    fn submits_json_commands() {
+        let mut order_book = OrderBook::new();
+        let new_order = |side: &str, qty: u32| {
+            format!(
+                r#"{{"New": {{"order_type": "GoodTilCancel", "side": "{}", "price": 101, "qty": {}, "participant": 1}}}}"#,
+                side, qty
+            )
+        };
+        submit_json(&mut order_book, &new_order("Sell", 5)).unwrap();
+        let events = submit_json(&mut order_book, &new_order("Buy", 2)).unwrap();
+        let events: Value = serde_json::from_str(&events).unwrap();
+        assert_eq!(events.as_array().unwrap().len(), 4);
+        assert_eq!(events[1]["Trade"]["qty"], 2);
+        assert_eq!(submit_json(&mut order_book, "{\"Nope\": {}}"), None);
+    }
+}
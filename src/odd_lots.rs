@@ -0,0 +1,145 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Odd lots.
+//!
+//! An instrument trading in round lots, 100 shares say, treats an order for
+//! less than one as an odd lot. An [`OddLotConfig`] set on the book with
+//! [`OrderBook::set_odd_lots`](crate::OrderBook::set_odd_lots) gives the
+//! round lot and what the book does with odd lots. They trade like any
+//! other order, but the BBO is built from round-lot orders only, so a
+//! sliver of size can't set the quoted price; see [`OddLotPolicy`] for the
+//! exceptions. An order that trades down below a round lot is an odd lot
+//! from then on.
+//!
+//! [`OrderBook::round_lot_depth`](crate::OrderBook::round_lot_depth) and
+//! [`OrderBook::odd_lot_depth`](crate::OrderBook::odd_lot_depth) split the
+//! book's depth between the two, and
+//! [`MarketDataMessage::lot_depth`](crate::market_data::MarketDataMessage::lot_depth)
+//! publishes both.
+
+use crate::limit::Limit;
+use crate::order_book::LevelInfo;
+use crate::Price;
+use serde::Deserialize;
+
+/// What the book does with odd-lot orders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum OddLotPolicy {
+    /// Refuse new odd-lot orders with `RejectReason::OddLot`.
+    Reject,
+    /// Accept them, but leave them out of the BBO.
+    #[default]
+    Unprotected,
+    /// Accept them, and count the odd lots at a price towards the BBO once
+    /// together they make up a round lot.
+    Aggregate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct OddLotConfig {
+    pub round_lot: u32,
+    #[serde(default)]
+    pub policy: OddLotPolicy,
+}
+
+impl OddLotConfig {
+    pub fn is_odd_lot(&self, qty: u32) -> bool {
+        qty < self.round_lot
+    }
+
+    /// `level`'s round-lot orders and its odd lots, each summed. Either is
+    /// `None` if the level has no orders of that kind.
+    pub fn split<P: Price>(
+        &self,
+        level: &Limit<P>,
+    ) -> (Option<LevelInfo<P>>, Option<LevelInfo<P>>) {
+        let mut round = LevelInfo {
+            price: level.price,
+            qty: 0,
+            order_count: 0,
+        };
+        let mut odd = round;
+        for order in &level.orders {
+            let part = if self.is_odd_lot(order.remaining_qty) {
+                &mut odd
+            } else {
+                &mut round
+            };
+            part.qty += u64::from(order.remaining_qty);
+            part.order_count += 1;
+        }
+        let present = |info: LevelInfo<P>| (info.order_count > 0).then_some(info);
+        (present(round), present(odd))
+    }
+
+    /// What of `level` counts towards the BBO, if anything.
+    pub fn protected<P: Price>(&self, level: &Limit<P>) -> Option<LevelInfo<P>> {
+        let (round, odd) = self.split(level);
+        match odd {
+            Some(odd)
+                if self.policy == OddLotPolicy::Aggregate
+                    && odd.qty >= u64::from(self.round_lot) =>
+            {
+                Some(LevelInfo {
+                    price: level.price,
+                    qty: round.map_or(0, |round| round.qty) + odd.qty,
+                    order_count: round.map_or(0, |round| round.order_count) + odd.order_count,
+                })
+            }
+            _ => round,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OddLotConfig, OddLotPolicy};
+    use crate::risk::RejectReason;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+
+    fn book(policy: OddLotPolicy) -> OrderBook {
+        let mut order_book = OrderBook::new();
+        order_book.set_odd_lots(Some(OddLotConfig {
+            round_lot: 100,
+            policy,
+        }));
+        for (price, qty) in [(99, 100), (100, 60), (100, 50)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side: Side::Buy,
+                price,
+                qty,
+                participant: 1,
+            });
+        }
+        order_book
+    }
+
+    #[test]
+    fn odd_lots_stay_out_of_the_bbo() {
+        let order_book = book(OddLotPolicy::Unprotected);
+        assert_eq!(order_book.best_bid(), Some(100));
+        let bid = order_book.bbo().bid.unwrap();
+        assert_eq!((bid.price, bid.qty, bid.order_count), (99, 100, 1));
+        assert_eq!(order_book.round_lot_depth(5).bids.len(), 1);
+        let odd = order_book.odd_lot_depth(5);
+        assert_eq!((odd.bids[0].price, odd.bids[0].qty), (100, 110));
+
+        // Together the two odd lots at 100 make up a round lot.
+        let order_book = book(OddLotPolicy::Aggregate);
+        let bid = order_book.bbo().bid.unwrap();
+        assert_eq!((bid.price, bid.qty, bid.order_count), (100, 110, 2));
+
+        let order_book = book(OddLotPolicy::Reject);
+        assert!(order_book.events().contains(&OrderEvent::Rejected {
+            participant: 1,
+            side: Side::Buy,
+            price: 100,
+            qty: 60,
+            reason: RejectReason::OddLot,
+        }));
+        assert_eq!(order_book.best_bid(), Some(99));
+    }
+}
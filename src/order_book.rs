@@ -2,28 +2,1065 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
-use crate::{limit::Limit, Order, OrderCommand, OrderEvent, Side};
-use std::{borrow::BorrowMut, cmp::Ordering, time::Instant};
-
-#[derive(Debug, PartialEq, Eq)]
-pub struct OrderBook {
-    pub bids: Vec<Limit>,
-    pub asks: Vec<Limit>,
-    commands: Vec<OrderCommand>,
-    events: Vec<OrderEvent>,
+use crate::amendments::{Amendment, AmendmentHistory};
+use crate::analytics::{self, imbalance, microprice, weighted_price, ExecutionEstimate};
+use crate::auction::{self, Uncross};
+use crate::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, HaltPolicy, TradingStatus};
+use crate::clock::{BookClock, Clock};
+use crate::crosses::CrossRules;
+use crate::dark::{self, DarkBook, DarkOrder};
+use crate::fees::{FeeLedger, FeeSchedule};
+use crate::improvement::{ImprovementAuction, ImprovementReject, Response};
+use crate::internalization::{GroupKey, Internalization};
+use crate::latency::{LatencyStats, Stage};
+use crate::lifecycle::{Lifecycle, OrderState};
+use crate::mass_quote::{QuoteEntry, QuotePriority};
+use crate::metrics::{Metrics, MetricsSink};
+use crate::odd_lots::{OddLotConfig, OddLotPolicy};
+use crate::positions::{Position, Positions};
+use crate::priority::PriorityClass;
+use crate::risk::credit::Exposure;
+use crate::risk::{notional, RejectReason, RiskChecks};
+use crate::sandbox::{Sandbox, SandboxConfig};
+use crate::settlement::SettlementMethod;
+use crate::stats::SessionStats;
+use crate::stops::{StopId, StopIndex};
+use crate::tape::TradeTape;
+use crate::timer_wheel::TimerWheel;
+use crate::{
+    limit::Limit, Currency, Order, OrderCommand, OrderEvent, OrderId, OrderType, ParticipantId,
+    Price, Side, Timestamp, Trade,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub struct OrderBook<P: Price = i32> {
+    symbol: String,
+    pub bids: Vec<Limit<P>>,
+    pub asks: Vec<Limit<P>>,
+    commands: Vec<OrderCommand<P>>,
+    events: Vec<OrderEvent<P>>,
+    tape: TradeTape<P>,
+    stats: SessionStats<P>,
+    positions: Positions,
+    fee_schedule: FeeSchedule,
+    fees: FeeLedger,
+    risk: RiskChecks,
+    status: TradingStatus,
+    halt_policy: HaltPolicy,
+    breaker: Option<CircuitBreaker<P>>,
+    sandbox: Option<Sandbox>,
+    /// Resting orders by participant, keyed by id, with the side and price
+    /// needed to find them in the book.
+    open_orders: HashMap<ParticipantId, BTreeMap<OrderId, (Side, P)>>,
+    /// The same orders by id, with the participant they belong to.
+    resting: HashMap<OrderId, (ParticipantId, Side, P)>,
+    /// The order each participant last quoted each side with.
+    quotes: BTreeMap<(ParticipantId, Side), OrderId>,
+    quote_priority: QuotePriority,
+    groups: HashMap<ParticipantId, GroupKey>,
+    classes: HashMap<ParticipantId, PriorityClass>,
+    internalization: Internalization,
+    odd_lots: Option<OddLotConfig>,
+    cross_rules: CrossRules,
+    currency: Currency,
+    settlement_method: SettlementMethod,
+    /// The price this session's last auction uncrossed at.
+    auction_price: Option<P>,
+    stops: StopIndex<P>,
+    dark: DarkBook,
+    improvement_window: Option<Duration>,
+    improvements: BTreeMap<OrderId, ImprovementAuction<P>>,
+    lifecycle: Lifecycle,
+    amendments: AmendmentHistory<P>,
+    /// The last arrival sequence stamped.
+    arrivals: u64,
+    next_order_id: OrderId,
+    next_trade_id: usize,
+    latency: Option<LatencyStats>,
+    metrics: Metrics,
+    clock: BookClock,
+    timers: TimerWheel<Timer<P>>,
+}
+
+/// How finely timers are kept apart. A timer fires on the first command or
+/// tick at least this far into the tick it falls in.
+const TIMER_RESOLUTION: Duration = Duration::from_millis(1);
+
+/// Work the book has scheduled for itself.
+#[derive(Debug, Clone, PartialEq)]
+enum Timer<P> {
+    /// Cancels the order if it is still resting.
+    Expire {
+        id: OrderId,
+        side: Side,
+        price: P,
+    },
+    Command(OrderCommand<P>),
+    /// Closes the price improvement auction for the order.
+    EndImprovement(OrderId),
+}
+
+/// Aggregated view of a single price level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LevelInfo<P = i32> {
+    pub price: P,
+    pub qty: u64,
+    pub order_count: usize,
+}
+
+/// Aggregated top-of-book levels for both sides, best price first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Depth<P = i32> {
+    pub bids: Vec<LevelInfo<P>>,
+    pub asks: Vec<LevelInfo<P>>,
+}
+
+/// Best bid and offer with the aggregated size resting at each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Bbo<P = i32> {
+    pub bid: Option<LevelInfo<P>>,
+    pub ask: Option<LevelInfo<P>>,
+}
+
+/// What [`OrderBook::end_of_day`] closed out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSummary<P = i32> {
+    /// The day orders expired, in id order.
+    pub expired: Vec<OrderId>,
+    pub stats: SessionStats<P>,
+    /// The session's trades, taken off the book.
+    pub tape: TradeTape<P>,
+}
+
+impl<P: Price> Default for OrderBook<P> {
+    fn default() -> Self {
+        Self::priced("")
+    }
+}
+
+/// Prints the book as a price ladder, highest price at the top, so the asks
+/// run down to the spread and the bids continue below it. A precision limits
+/// each side to that many levels: `format!("{:.5}", order_book)`.
+impl<P: Price> fmt::Display for OrderBook<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const WIDTH: usize = 32;
+        let Depth { bids, asks } = self.depth(f.precision().unwrap_or(usize::MAX));
+        match self.symbol.as_str() {
+            "" => writeln!(f, "{:?}", self.status)?,
+            symbol => writeln!(f, "{} {:?}", symbol, self.status)?,
+        }
+        writeln!(f, "{:>14} {:>10} {:>6}", "price", "qty", "orders")?;
+        let level = |f: &mut fmt::Formatter<'_>, side, level: &LevelInfo<P>| {
+            writeln!(
+                f,
+                "{} {:>10} {:>10} {:>6}",
+                side, level.price, level.qty, level.order_count
+            )
+        };
+        for ask in asks.iter().rev() {
+            level(f, "ask", ask)?;
+        }
+        match (bids.first(), asks.first()) {
+            (Some(bid), Some(ask)) => writeln!(
+                f,
+                "{:-^WIDTH$}",
+                format!(" spread {} ", ask.price.to_ticks() - bid.price.to_ticks())
+            )?,
+            _ => writeln!(f, "{}", "-".repeat(WIDTH))?,
+        }
+        for bid in &bids {
+            level(f, "bid", bid)?;
+        }
+        Ok(())
+    }
 }
 
 impl OrderBook {
     pub fn new() -> OrderBook {
+        OrderBook::with_symbol("")
+    }
+
+    pub fn with_symbol(symbol: impl Into<String>) -> OrderBook {
+        OrderBook::priced(symbol)
+    }
+}
+
+impl<P: Price> OrderBook<P> {
+    /// A book for `symbol` quoted in price type `P`, such as
+    /// `OrderBook::<i64>::priced("ES")`. [`OrderBook::with_symbol`] is the
+    /// same for the default, `i32`.
+    pub fn priced(symbol: impl Into<String>) -> OrderBook<P> {
         OrderBook {
+            symbol: symbol.into(),
             bids: Vec::new(),
             asks: Vec::new(),
             commands: Vec::with_capacity(200_000),
             events: Vec::with_capacity(200_000),
+            tape: TradeTape::new(),
+            stats: SessionStats::default(),
+            positions: Positions::new(),
+            fee_schedule: FeeSchedule::default(),
+            fees: FeeLedger::new(),
+            risk: RiskChecks::default(),
+            status: TradingStatus::Open,
+            halt_policy: HaltPolicy::default(),
+            breaker: None,
+            sandbox: None,
+            open_orders: HashMap::new(),
+            resting: HashMap::new(),
+            quotes: BTreeMap::new(),
+            quote_priority: QuotePriority::default(),
+            groups: HashMap::new(),
+            classes: HashMap::new(),
+            internalization: Internalization::default(),
+            odd_lots: None,
+            cross_rules: CrossRules::default(),
+            currency: Currency::default(),
+            settlement_method: SettlementMethod::default(),
+            auction_price: None,
+            stops: StopIndex::new(),
+            dark: DarkBook::new(),
+            improvement_window: None,
+            improvements: BTreeMap::new(),
+            lifecycle: Lifecycle::new(),
+            amendments: AmendmentHistory::new(),
+            arrivals: 0,
+            next_order_id: 1,
+            next_trade_id: 1,
+            latency: None,
+            metrics: Metrics::default(),
+            clock: BookClock::default(),
+            timers: TimerWheel::new(TIMER_RESOLUTION),
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn events(&self) -> &[OrderEvent<P>] {
+        &self.events
+    }
+
+    pub fn trades(&self) -> &[Trade<P>] {
+        self.tape.as_slice()
+    }
+
+    pub fn tape(&self) -> &TradeTape<P> {
+        &self.tape
+    }
+
+    pub fn stats(&self) -> SessionStats<P> {
+        self.stats
+    }
+
+    pub fn positions(&self) -> &Positions {
+        &self.positions
+    }
+
+    /// The participant's position in this book's symbol.
+    pub fn position(&self, participant: ParticipantId) -> Position {
+        self.positions.get(participant, &self.symbol)
+    }
+
+    pub fn fee_schedule(&self) -> FeeSchedule {
+        self.fee_schedule
+    }
+
+    /// Sets the fees charged on trades from now on.
+    pub fn set_fee_schedule(&mut self, schedule: FeeSchedule) {
+        self.fee_schedule = schedule;
+    }
+
+    /// Fees charged so far, by participant, in the book's currency.
+    pub fn fees(&self) -> &FeeLedger {
+        &self.fees
+    }
+
+    pub fn risk(&self) -> &RiskChecks {
+        &self.risk
+    }
+
+    pub fn risk_mut(&mut self) -> &mut RiskChecks {
+        &mut self.risk
+    }
+
+    /// The participant's credit exposure in this book.
+    pub fn exposure(&self, participant: ParticipantId) -> Exposure {
+        self.risk.exposure(participant, &self.position(participant))
+    }
+
+    /// How many orders the participant has resting.
+    pub fn open_orders(&self, participant: ParticipantId) -> usize {
+        self.open_orders.get(&participant).map_or(0, BTreeMap::len)
+    }
+
+    /// Whether `id` is one of the participant's resting orders.
+    pub fn owns(&self, participant: ParticipantId, id: OrderId) -> bool {
+        self.open_orders
+            .get(&participant)
+            .is_some_and(|orders| orders.contains_key(&id))
+    }
+
+    pub fn status(&self) -> TradingStatus {
+        self.status
+    }
+
+    /// The id the next new order will get.
+    pub fn next_order_id(&self) -> OrderId {
+        self.next_order_id
+    }
+
+    /// Sets where order ids continue from, e.g. to carry on a replayed
+    /// session's numbering. Ids must not be reused while their orders rest.
+    pub fn set_next_order_id(&mut self, id: OrderId) {
+        self.next_order_id = id;
+    }
+
+    pub fn halt_policy(&self) -> HaltPolicy {
+        self.halt_policy
+    }
+
+    /// Sets what happens to orders that arrive while the book is halted.
+    pub fn set_halt_policy(&mut self, policy: HaltPolicy) {
+        self.halt_policy = policy;
+    }
+
+    /// Enables volatility halts, or disables them with `None`.
+    pub fn set_circuit_breaker(&mut self, config: Option<CircuitBreakerConfig>) {
+        self.breaker = config.map(CircuitBreaker::new);
+    }
+
+    /// Puts the book in sandbox mode, quoting synthetic liquidity right
+    /// away, or takes it out with `None`. Quotes already placed stay.
+    pub fn set_sandbox(&mut self, config: Option<SandboxConfig>) {
+        self.sandbox = config.map(Sandbox::new);
+        self.refresh_sandbox();
+    }
+
+    pub fn quote_priority(&self) -> QuotePriority {
+        self.quote_priority
+    }
+
+    pub fn set_quote_priority(&mut self, priority: QuotePriority) {
+        self.quote_priority = priority;
+    }
+
+    /// Puts the participant in an anti-internalization group, or takes it
+    /// out with `None`. Orders already resting keep the group they entered
+    /// with.
+    pub fn set_group(&mut self, participant: ParticipantId, group: Option<GroupKey>) {
+        match group {
+            Some(group) => self.groups.insert(participant, group),
+            None => self.groups.remove(&participant),
+        };
+    }
+
+    pub fn group(&self, participant: ParticipantId) -> Option<GroupKey> {
+        self.groups.get(&participant).copied()
+    }
+
+    /// Puts the participant's orders entered from now on in priority class
+    /// `class`; see [`priority`](crate::priority). Orders already resting
+    /// keep their place.
+    pub fn set_priority_class(&mut self, participant: ParticipantId, class: PriorityClass) {
+        if class == 0 {
+            self.classes.remove(&participant);
+        } else {
+            self.classes.insert(participant, class);
         }
     }
 
-    pub fn process_command(&mut self, command: OrderCommand) {
+    pub fn priority_class(&self, participant: ParticipantId) -> PriorityClass {
+        self.classes.get(&participant).copied().unwrap_or_default()
+    }
+
+    pub fn odd_lots(&self) -> Option<OddLotConfig> {
+        self.odd_lots
+    }
+
+    /// Sets the round lot and how odd lots are handled; see
+    /// [`odd_lots`](crate::odd_lots). With `None`, the default, every order
+    /// counts alike.
+    pub fn set_odd_lots(&mut self, config: Option<OddLotConfig>) {
+        self.odd_lots = config;
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    /// Sets the currency the book's instrument is priced in, which its
+    /// trades are stamped with; see [`crate::currency`].
+    pub fn set_currency(&mut self, currency: Currency) {
+        self.currency = currency;
+    }
+
+    pub fn settlement_method(&self) -> SettlementMethod {
+        self.settlement_method
+    }
+
+    /// Sets how the session's settlement price is worked out at the close;
+    /// see [`crate::settlement`].
+    pub fn set_settlement_method(&mut self, method: SettlementMethod) {
+        self.settlement_method = method;
+    }
+
+    pub fn cross_rules(&self) -> CrossRules {
+        self.cross_rules
+    }
+
+    /// Sets the checks a reported cross must pass; see
+    /// [`crosses`](crate::crosses). The default takes any size at any price.
+    pub fn set_cross_rules(&mut self, rules: CrossRules) {
+        self.cross_rules = rules;
+    }
+
+    pub fn internalization(&self) -> Internalization {
+        self.internalization
+    }
+
+    pub fn set_internalization(&mut self, internalization: Internalization) {
+        self.internalization = internalization;
+    }
+
+    /// Starts or stops timing the stages of each command; see
+    /// [`crate::latency`]. Stopping discards what was recorded. wasm32 has no
+    /// monotonic clock to time with, so there tracking stays off.
+    pub fn set_latency_tracking(&mut self, enabled: bool) {
+        if !enabled || cfg!(target_arch = "wasm32") {
+            self.latency = None;
+        } else if self.latency.is_none() {
+            self.latency = Some(LatencyStats::new());
+        }
+    }
+
+    pub fn latency(&self) -> Option<&LatencyStats> {
+        self.latency.as_ref()
+    }
+
+    /// For callers timing stages the book can't see, such as persistence.
+    pub fn latency_mut(&mut self) -> Option<&mut LatencyStats> {
+        self.latency.as_mut()
+    }
+
+    /// The time on the book's clock.
+    pub fn now(&self) -> Timestamp {
+        self.clock.now()
+    }
+
+    /// Reads the time from `clock` from now on; see [`crate::clock`].
+    pub fn set_clock(&mut self, clock: impl Clock + Send + 'static) {
+        self.clock = BookClock::new(clock);
+    }
+
+    /// Cancels the resting order `id` once the clock reaches `at`, returning
+    /// false if there is no such order. A modify replaces the order, so the
+    /// replacement has no expiry unless one is set for it.
+    pub fn expire_at(&mut self, id: OrderId, at: Timestamp) -> bool {
+        let Some(order) = self.find_order(id) else {
+            return false;
+        };
+        let timer = Timer::Expire {
+            id,
+            side: order.side,
+            price: order.price,
+        };
+        self.timers.insert(at, timer);
+        true
+    }
+
+    /// Applies `command` once the clock reaches `at`, e.g. a `Resume` to end
+    /// an auction call. Scheduled commands are not rate limited.
+    pub fn schedule(&mut self, at: Timestamp, command: OrderCommand<P>) {
+        self.timers.insert(at, Timer::Command(command));
+    }
+
+    /// Applies `command` once a trade prints at or through `trigger`: at or
+    /// above it for `Side::Buy`, at or below it for `Side::Sell`. Like
+    /// scheduled commands, triggered stops are not rate limited. See
+    /// [`crate::stops`].
+    pub fn add_stop(&mut self, side: Side, trigger: P, command: OrderCommand<P>) -> StopId {
+        self.stops.insert(side, trigger, command)
+    }
+
+    /// Returns false if the stop has already triggered or never existed.
+    pub fn cancel_stop(&mut self, id: StopId) -> bool {
+        self.stops.remove(id).is_some()
+    }
+
+    pub fn stops(&self) -> &StopIndex<P> {
+        &self.stops
+    }
+
+    /// Rests a non-displayed order for `qty` that trades only in the dark
+    /// book, at the lit midpoint, in fills of at least `min_qty`; see
+    /// [`crate::dark`]. It takes the next order id, which is returned.
+    /// Refused if the participant's pre-trade limits, checked at the
+    /// reference price, don't allow it.
+    pub fn add_dark_order(
+        &mut self,
+        participant: ParticipantId,
+        side: Side,
+        qty: u32,
+        min_qty: u32,
+    ) -> Result<OrderId, RejectReason> {
+        let reference = self.reference_price().unwrap_or_default();
+        self.risk.check_new(participant, reference, qty)?;
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+        self.dark.insert(DarkOrder {
+            id,
+            participant,
+            side,
+            remaining_qty: qty,
+            min_qty,
+            created_at: self.clock.now(),
+        });
+        let seen = self.events.len();
+        let printed = self.trades().len();
+        self.match_dark();
+        self.trigger_stops(printed);
+        self.enforce_protections();
+        self.finish_command(seen);
+        Ok(id)
+    }
+
+    /// Returns false if the dark order has already filled or never existed.
+    pub fn cancel_dark_order(&mut self, id: OrderId) -> bool {
+        self.dark.remove(id).is_some()
+    }
+
+    pub fn dark_book(&self) -> &DarkBook {
+        &self.dark
+    }
+
+    pub fn price_improvement(&self) -> Option<Duration> {
+        self.improvement_window
+    }
+
+    /// Holds new orders that would trade on arrival for `window`, to take
+    /// price improvement; see [`crate::improvement`]. `None`, the default,
+    /// matches them straight away.
+    pub fn set_price_improvement(&mut self, window: Option<Duration>) {
+        self.improvement_window = window;
+    }
+
+    /// The running price improvement auctions, oldest order first.
+    pub fn improvement_auctions(&self) -> impl Iterator<Item = &ImprovementAuction<P>> {
+        self.improvements.values()
+    }
+
+    /// Offers `qty` at `price` to the order held in a price improvement
+    /// auction, returning the response's id.
+    pub fn respond_to_improvement(
+        &mut self,
+        id: OrderId,
+        participant: ParticipantId,
+        price: P,
+        qty: u32,
+    ) -> Result<OrderId, ImprovementReject> {
+        let Some(auction) = self.improvements.get(&id) else {
+            return Err(ImprovementReject::UnknownAuction);
+        };
+        if auction.order.participant == participant {
+            return Err(ImprovementReject::OwnOrder);
+        }
+        if !auction.improves(price) {
+            return Err(ImprovementReject::NotImproving);
+        }
+        self.risk
+            .check_new(participant, price, qty)
+            .map_err(ImprovementReject::Risk)?;
+        let response = Response {
+            id: self.next_order_id,
+            participant,
+            price,
+            qty,
+        };
+        self.next_order_id += 1;
+        if let Some(auction) = self.improvements.get_mut(&id) {
+            auction.responses.push(response);
+        }
+        Ok(response.id)
+    }
+
+    /// Starts a price improvement auction for `order` if the book runs them
+    /// and the order would trade now, returning it otherwise.
+    fn hold_for_improvement(&mut self, order: Order<P>) -> Option<Order<P>> {
+        let Some(window) = self.improvement_window else {
+            return Some(order);
+        };
+        let best = match order.side {
+            Side::Buy => self.best_ask(),
+            Side::Sell => self.best_bid(),
+        };
+        let reference = match best {
+            Some(best)
+                if self.status == TradingStatus::Open
+                    && order.initial_qty > 0
+                    && matcher_core::crosses(order.side, order.price, best) =>
+            {
+                best
+            }
+            _ => return Some(order),
+        };
+        let ends_at = self.clock.now() + window;
+        self.timers.insert(ends_at, Timer::EndImprovement(order.id));
+        self.enter(order.id);
+        self.improvements.insert(
+            order.id,
+            ImprovementAuction {
+                order,
+                reference,
+                ends_at,
+                responses: Vec::new(),
+            },
+        );
+        None
+    }
+
+    /// Fills the held order from the auction's responses, then works what
+    /// is left of it against the book.
+    fn end_improvement(&mut self, id: OrderId) {
+        let Some(auction) = self.improvements.remove(&id) else {
+            return;
+        };
+        let mut order = auction.order.clone();
+        if self.status == TradingStatus::Open && !self.risk.is_blocked(order.participant) {
+            for response in auction.ranked() {
+                if order.is_filled() {
+                    break;
+                }
+                if self.risk.is_blocked(response.participant) {
+                    continue;
+                }
+                self.fill_response(&mut order, response);
+            }
+        }
+        if order.is_filled() {
+            return;
+        }
+        if self.risk.is_blocked(order.participant) {
+            self.advance(order.id, OrderState::Canceled);
+            self.events.push(OrderEvent::Canceled { id: order.id });
+            return;
+        }
+        self.work_order(order);
+    }
+
+    fn fill_response(&mut self, order: &mut Order<P>, response: Response<P>) {
+        let qty = order.remaining_qty.min(response.qty);
+        order.remaining_qty -= qty;
+        let timestamp = self.clock.now();
+        order.updated_at = timestamp;
+        let mut trade = Trade {
+            id: self.next_trade_id,
+            price: response.price,
+            qty,
+            aggressor_side: order.side,
+            maker_order_id: response.id,
+            taker_order_id: order.id,
+            maker_participant: response.participant,
+            taker_participant: order.participant,
+            timestamp,
+            maker_fee: 0,
+            taker_fee: 0,
+            cross: false,
+            dark: false,
+            currency: self.currency,
+        };
+        self.next_trade_id += 1;
+        self.fee_schedule.apply(&mut trade);
+        self.tape.record(trade);
+        self.stats.record(&trade);
+        self.positions.record(&self.symbol, &trade);
+        self.fees.record(&trade);
+        self.events.push(OrderEvent::Trade(trade));
+        self.advance(order.id, MatchStatus::of(order).state());
+        self.events.push(match MatchStatus::of(order) {
+            MatchStatus::Done => OrderEvent::Filled {
+                id: order.id,
+                price: response.price,
+                timestamp,
+            },
+            MatchStatus::Pending => OrderEvent::PartiallyFilled {
+                id: order.id,
+                price: response.price,
+                qty,
+                timestamp,
+            },
+        });
+    }
+
+    /// Matches the dark book at the lit midpoint, if the lit book is open
+    /// and quoted on both sides.
+    fn match_dark(&mut self) {
+        if self.status != TradingStatus::Open || self.dark.is_empty() {
+            return;
+        }
+        let (Some(bid), Some(ask)) = (self.bbo().bid, self.bbo().ask) else {
+            return;
+        };
+        if bid.price >= ask.price {
+            return;
+        }
+        let timestamp = self.clock.now();
+        for fill in self.dark.match_orders() {
+            // The later of the two orders completed the pair.
+            let (maker, taker) = if fill.buy.id < fill.sell.id {
+                (fill.buy, fill.sell)
+            } else {
+                (fill.sell, fill.buy)
+            };
+            let mut trade = Trade {
+                id: self.next_trade_id,
+                price: dark::midpoint(bid.price, ask.price, maker.side),
+                qty: fill.qty,
+                aggressor_side: taker.side,
+                maker_order_id: maker.id,
+                taker_order_id: taker.id,
+                maker_participant: maker.participant,
+                taker_participant: taker.participant,
+                timestamp,
+                maker_fee: 0,
+                taker_fee: 0,
+                cross: false,
+                dark: true,
+                currency: self.currency,
+            };
+            self.next_trade_id += 1;
+            self.fee_schedule.apply(&mut trade);
+            self.tape.record(trade);
+            self.stats.record(&trade);
+            self.positions.record(&self.symbol, &trade);
+            self.fees.record(&trade);
+            self.events.push(OrderEvent::Trade(trade));
+        }
+    }
+
+    /// Applies the stops triggered by trades from `seen` on, and those
+    /// triggered in turn by the trades they make.
+    fn trigger_stops(&mut self, mut seen: usize) {
+        while !self.stops.is_empty() {
+            let printed = self.trades().get(seen..).unwrap_or_default();
+            let (Some(low), Some(high)) = (
+                printed.iter().map(|trade| trade.price).min(),
+                printed.iter().map(|trade| trade.price).max(),
+            ) else {
+                break;
+            };
+            seen = self.trades().len();
+            for (_, command) in self.stops.take_triggered(low, high) {
+                self.execute(command);
+            }
+        }
+    }
+
+    /// The order's current state, including after it has left the book
+    /// until the end of the day; see [`crate::lifecycle`].
+    pub fn order_state(&self, id: OrderId) -> Option<OrderState> {
+        self.lifecycle.state(id)
+    }
+
+    /// Every modify of the order that has, or once had, id `id`, oldest
+    /// first; see [`amendments`](crate::amendments).
+    pub fn amendments(&self, id: OrderId) -> &[Amendment<P>] {
+        self.amendments.of(id)
+    }
+
+    fn next_arrival(&mut self) -> u64 {
+        self.arrivals += 1;
+        self.arrivals
+    }
+
+    fn enter(&mut self, id: OrderId) {
+        if let Err(invalid) = self.lifecycle.enter(id) {
+            tracing::error!("order {} entered twice, was {:?}", id, invalid.from);
+        }
+    }
+
+    fn advance(&mut self, id: OrderId, state: OrderState) {
+        if let Err(invalid) = self.lifecycle.advance(id, state) {
+            tracing::error!(
+                "order {} can't go from {:?} to {:?}",
+                id,
+                invalid.from,
+                invalid.to
+            );
+        }
+    }
+
+    /// No later than the earliest scheduled timer, if there is one.
+    pub fn next_timer(&self) -> Option<Timestamp> {
+        self.timers.next_deadline()
+    }
+
+    /// Fires every timer that is due. Timers also fire ahead of each
+    /// command, so this is only needed while the book is otherwise idle.
+    pub fn tick(&mut self) {
+        let seen = self.events.len();
+        let printed = self.trades().len();
+        if self.fire_timers() {
+            self.trigger_stops(printed);
+            self.enforce_protections();
+            self.finish_command(seen);
+        }
+    }
+
+    /// Returns whether any timer fired.
+    fn fire_timers(&mut self) -> bool {
+        if self.timers.is_empty() {
+            return false;
+        }
+        let due = self.timers.advance(self.clock.now());
+        let fired = !due.is_empty();
+        for timer in due {
+            match timer {
+                Timer::Expire { id, side, price } => {
+                    self.commands.push(OrderCommand::Cancel { id, side, price });
+                    self.remove_order(id, price, side, OrderState::Expired);
+                }
+                Timer::Command(command) => self.execute(command),
+                Timer::EndImprovement(id) => self.end_improvement(id),
+            }
+        }
+        fired
+    }
+
+    /// Reports each command's outcome to `sink` from now on; see
+    /// [`crate::metrics`].
+    pub fn set_metrics_sink(&mut self, sink: impl MetricsSink + Send + 'static) {
+        self.metrics = Metrics::new(sink);
+    }
+
+    /// Stops matching until [`Self::resume`]. What happens to orders in the
+    /// meantime depends on the [`HaltPolicy`].
+    pub fn halt(&mut self) {
+        self.set_status(TradingStatus::Halted);
+    }
+
+    /// The auction result if the book were to reopen with an auction now.
+    pub fn indicative_uncross(&self) -> Option<Uncross<P>> {
+        let depth = self.depth(usize::MAX);
+        auction::uncross(&depth.bids, &depth.asks, self.reference_price())
+    }
+
+    /// Reopens a halted book with an auction; see [`Self::resume`].
+    pub fn reopen(&mut self) -> Option<Uncross<P>> {
+        self.resume(true)
+    }
+
+    /// Reopens a halted book. With `auction` set, everything that can trade
+    /// at the single uncross price does and the auction result is returned.
+    /// Otherwise orders left crossed by the halt match in time priority,
+    /// each trade printing at the earlier order's price. Continuous matching
+    /// resumes either way. Does nothing if the book is not halted.
+    pub fn resume(&mut self, auction: bool) -> Option<Uncross<P>> {
+        if self.status != TradingStatus::Halted {
+            return None;
+        }
+        let mut result = None;
+        if auction {
+            result = self.indicative_uncross();
+            if let Some(uncross) = result {
+                self.auction_price = Some(uncross.price);
+                self.uncross_book(Some(uncross.price));
+            }
+        } else {
+            self.uncross_book(None);
+        }
+        if let Some(breaker) = &mut self.breaker {
+            breaker.reset();
+        }
+        self.set_status(TradingStatus::Open);
+        result
+    }
+
+    fn set_status(&mut self, status: TradingStatus) {
+        if self.status != status {
+            self.status = status;
+            self.events.push(OrderEvent::StatusChanged { status });
+        }
+    }
+
+    /// Starts a new trading session, returning the statistics of the one
+    /// just ended.
+    pub fn reset_stats(&mut self) -> SessionStats<P> {
+        std::mem::take(&mut self.stats)
+    }
+
+    /// Closes the trading session: expires every resting day order, emits
+    /// `Settled` with the settlement price if there is one and then
+    /// `SessionEnded`, and starts the statistics and the trade tape afresh.
+    /// Good-til-cancel orders carry over, as do positions, fees and the
+    /// event log. Trades from before can no longer be busted, and orders
+    /// that are done with are forgotten by [`order_state`](Self::order_state).
+    pub fn end_of_day(&mut self) -> SessionSummary<P> {
+        let seen = self.events.len();
+        let mut day: Vec<(OrderId, Side, P)> = self
+            .bids
+            .iter()
+            .chain(self.asks.iter())
+            .flat_map(|limit| limit.orders.iter())
+            .filter(|order| order.order_type == OrderType::Day)
+            .map(|order| (order.id, order.side, order.price))
+            .collect();
+        day.sort_unstable_by_key(|&(id, _, _)| id);
+        for &(id, side, price) in &day {
+            self.commands.push(OrderCommand::Cancel { id, side, price });
+            self.remove_order(id, price, side, OrderState::Expired);
+        }
+        let timestamp = self.now();
+        let settlement =
+            self.settlement_method
+                .price(&self.tape, self.auction_price.take(), timestamp);
+        if let Some(price) = settlement {
+            self.stats.settlement = Some(price);
+            self.events.push(OrderEvent::Settled { price, timestamp });
+        }
+        self.events.push(OrderEvent::SessionEnded { timestamp });
+        self.finish_command(seen);
+        self.lifecycle.prune_terminal();
+        SessionSummary {
+            expired: day.into_iter().map(|(id, _, _)| id).collect(),
+            stats: self.reset_stats(),
+            tape: std::mem::take(&mut self.tape),
+        }
+    }
+
+    /// Rests a good-til-cancel order saved by an earlier run as it was,
+    /// without matching it, and emits `Restored`. It keeps its id, and the
+    /// next new order's id comes after it; its arrival is stamped afresh,
+    /// so orders restored in their saved order keep their priority among
+    /// themselves and ahead of anything entered later. Returns false,
+    /// restoring nothing, if the order isn't good-til-cancel, has nothing
+    /// left or its id is taken. An order that crosses an open book halts it
+    /// first: it was saved during an auction call, and the book reopens
+    /// with an auction as it would have.
+    pub fn restore(&mut self, mut order: Order<P>) -> bool {
+        if order.order_type != OrderType::GoodTilCancel
+            || order.is_filled()
+            || self.lifecycle.state(order.id).is_some()
+        {
+            return false;
+        }
+        let seen = self.events.len();
+        let crosses = match order.side {
+            Side::Buy => self.best_ask().is_some_and(|ask| order.price >= ask),
+            Side::Sell => self.best_bid().is_some_and(|bid| order.price <= bid),
+        };
+        if crosses {
+            self.halt();
+        }
+        order.arrival = self.next_arrival();
+        order.group = self.group(order.participant);
+        order.class = self.priority_class(order.participant);
+        self.next_order_id = self.next_order_id.max(order.id + 1);
+        self.enter(order.id);
+        if order.remaining_qty < order.initial_qty {
+            self.advance(order.id, OrderState::PartiallyFilled);
+        }
+        self.events.push(OrderEvent::Restored {
+            id: order.id,
+            participant: order.participant,
+            side: order.side,
+            price: order.price,
+            qty: order.remaining_qty,
+            timestamp: self.now(),
+            arrival: order.arrival,
+        });
+        self.rest_order(order);
+        self.finish_command(seen);
+        true
+    }
+
+    pub fn process_command(&mut self, command: OrderCommand<P>) {
+        let seen = self.events.len();
+        let printed = self.trades().len();
+        self.fire_timers();
+        let start = self.stage_start();
+        let throttled = self.throttle(&command);
+        self.stage_end(Stage::Validation, start);
+        if let Err(reason) = throttled {
+            let (participant, side, price, qty) = match command {
+                OrderCommand::New {
+                    side,
+                    price,
+                    qty,
+                    participant,
+                    ..
+                } => (participant, side, price, qty),
+                OrderCommand::Modify {
+                    id,
+                    side,
+                    price,
+                    qty,
+                    ..
+                } => (self.owner(id).unwrap_or_default(), side, price, qty),
+                OrderCommand::MassQuote {
+                    participant,
+                    quotes,
+                } => {
+                    let (side, price, qty) = self
+                        .quote_sides(&quotes)
+                        .map_or((Side::Buy, P::default(), 0), |[bid, _]| bid);
+                    (participant, side, price, qty)
+                }
+                OrderCommand::Cross {
+                    buyer, price, qty, ..
+                } => (buyer, Side::Buy, price, qty),
+                OrderCommand::Cancel { .. }
+                | OrderCommand::KillSwitch { .. }
+                | OrderCommand::Halt
+                | OrderCommand::Resume { .. }
+                | OrderCommand::BustTrade { .. } => {
+                    unreachable!("cancels and admin commands are not throttled")
+                }
+            };
+            self.reject(participant, side, price, qty, reason);
+        } else {
+            self.execute(command);
+            self.match_dark();
+            self.trigger_stops(printed);
+            #[cfg(any(test, feature = "invariants"))]
+            self.debug_assert_invariants();
+        }
+        if self.sandbox.as_mut().is_some_and(Sandbox::due) {
+            self.refresh_sandbox();
+        }
+        self.enforce_protections();
+        self.finish_command(seen);
+    }
+
+    /// Tops the sandbox's quotes back up. Nothing is quoted while halted.
+    fn refresh_sandbox(&mut self) {
+        let Some(sandbox) = self.sandbox.take() else {
+            return;
+        };
+        if self.status == TradingStatus::Open {
+            for command in sandbox.refresh(self) {
+                self.execute(command);
+            }
+        }
+        self.sandbox = Some(sandbox);
+    }
+
+    /// Closes out a command whose events start at `seen`.
+    fn finish_command(&mut self, seen: usize) {
+        if let Some(latency) = &mut self.latency {
+            latency.finish_command();
+        }
+        self.metrics.command(&self.symbol, &self.events[seen..]);
+    }
+
+    /// Applies a command that has passed rate limiting. Commands issued by
+    /// the book itself come straight here so they don't spend tokens.
+    fn execute(&mut self, command: OrderCommand<P>) {
         self.commands.push(command.clone());
         match command {
             OrderCommand::New {
@@ -31,18 +1068,20 @@ impl OrderBook {
                 side,
                 price,
                 qty,
+                participant,
             } => {
-                let order = Order::new(order_type, side, price, qty);
-                self.events.push(OrderEvent::Placed {
-                    id: order.id,
-                    side: order.side,
-                    order_type: order.order_type,
-                    price,
-                    timestamp: order.created_at,
-                });
-                self.place_order(order);
+                let start = self.stage_start();
+                let validated = self.validate(participant, side, price, qty, None);
+                self.stage_end(Stage::Validation, start);
+                if let Err(reason) = validated {
+                    self.reject(participant, side, price, qty, reason);
+                    return;
+                }
+                self.accept(order_type, side, price, qty, participant);
+            }
+            OrderCommand::Cancel { id, side, price } => {
+                self.remove_order(id, price, side, OrderState::Canceled)
             }
-            OrderCommand::Cancel { id, side, price } => self.remove_order(id, price, side),
             OrderCommand::Modify {
                 id,
                 side,
@@ -50,188 +1089,1154 @@ impl OrderBook {
                 qty,
                 order_type,
             } => {
-                let queue = match side {
-                    Side::Buy => &mut self.bids,
-                    Side::Sell => &mut self.asks,
+                // The order is found by id, wherever it rests on `side`;
+                // `price` is the one it is entered again at.
+                let Some(order) = self.find_order(id).filter(|order| order.side == side) else {
+                    return;
+                };
+                let order = order.clone();
+                // Check once, before canceling, so a rejected modify leaves
+                // the original order in place. The replacement is not
+                // checked again: canceling the original can move the
+                // collar's reference price.
+                let start = self.stage_start();
+                let validated = self.validate(order.participant, side, price, qty, Some(&order));
+                self.stage_end(Stage::Validation, start);
+                if let Err(reason) = validated {
+                    self.reject(order.participant, side, price, qty, reason);
+                    return;
+                }
+                self.execute(OrderCommand::Cancel {
+                    id,
+                    side,
+                    price: order.price,
+                });
+                let new_id = self.accept(order_type, side, price, qty, order.participant);
+                if self.lifecycle.state(new_id).is_some() {
+                    self.amendments.record(Amendment {
+                        id,
+                        new_id,
+                        old_price: order.price,
+                        old_qty: order.remaining_qty,
+                        new_price: price,
+                        new_qty: qty,
+                        order_type,
+                        placed_at: order.created_at,
+                        amended_at: self.now(),
+                    });
+                }
+            }
+            OrderCommand::KillSwitch {
+                participant,
+                engage,
+            } => {
+                if engage {
+                    self.risk.block(participant);
+                } else {
+                    self.risk.unblock(participant);
+                }
+                self.events.push(OrderEvent::KillSwitch {
+                    participant,
+                    engaged: engage,
+                });
+                if engage {
+                    self.cancel_resting(participant);
+                    self.dark.remove_participant(participant);
+                }
+            }
+            OrderCommand::Halt => self.halt(),
+            OrderCommand::Resume { auction } => {
+                self.resume(auction);
+            }
+            OrderCommand::BustTrade { trade_id, restore } => self.bust_trade(trade_id, restore),
+            OrderCommand::MassQuote {
+                participant,
+                quotes,
+            } => {
+                let Some(sides) = self.quote_sides(&quotes) else {
+                    return;
                 };
-                if let Some(lim_pos) = queue.iter().position(|lim| lim.price == price) {
-                    let limit = queue[lim_pos].borrow_mut();
-                    if let Some(order_pos) = limit.find_by_id(id) {
-                        let order = limit.orders[order_pos].clone();
-                        self.process_command(OrderCommand::Cancel { id, side, price });
-                        self.process_command(OrderCommand::New {
-                            order_type,
-                            side: order.side,
-                            price,
-                            qty,
-                        })
+                let start = self.stage_start();
+                let checked = self.check_quote(participant, sides);
+                self.stage_end(Stage::Validation, start);
+                if let Err((side, price, qty, reason)) = checked {
+                    self.reject(participant, side, price, qty, reason);
+                    return;
+                }
+                self.requote(participant, sides);
+            }
+            OrderCommand::Cross {
+                buyer,
+                seller,
+                price,
+                qty,
+            } => self.cross(buyer, seller, price, qty),
+        }
+    }
+
+    /// Enters a new order that has passed validation, returning its id.
+    fn accept(
+        &mut self,
+        order_type: OrderType,
+        side: Side,
+        price: P,
+        qty: u32,
+        participant: ParticipantId,
+    ) -> OrderId {
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+        hot_trace!(
+            trace,
+            "order {} {:?} {}@{} from {}",
+            id,
+            side,
+            qty,
+            price,
+            participant
+        );
+        let order = Order {
+            participant,
+            arrival: self.next_arrival(),
+            group: self.group(participant),
+            class: self.priority_class(participant),
+            ..Order::new(id, order_type, side, price, qty, self.clock.now())
+        };
+        let start = self.stage_start();
+        self.events.push(OrderEvent::Placed {
+            id: order.id,
+            participant,
+            side: order.side,
+            order_type: order.order_type,
+            price,
+            timestamp: order.created_at,
+            arrival: order.arrival,
+        });
+        self.stage_end(Stage::EventEmission, start);
+        if let Some(order) = self.hold_for_improvement(order) {
+            self.place_order(order);
+        }
+        id
+    }
+
+    /// The participant's quote on `side`, if it is still resting.
+    pub fn quoted(&self, participant: ParticipantId, side: Side) -> Option<&Order<P>> {
+        let id = *self.quotes.get(&(participant, side))?;
+        let &(_, price) = self.open_orders.get(&participant)?.get(&id)?;
+        self.level(side, price)?
+            .orders
+            .iter()
+            .find(|order| order.id == id)
+    }
+
+    /// Counts a fill against the market maker protections of whichever side
+    /// of `trade` was a quote.
+    fn count_quote_fill(&mut self, trade: &Trade<P>) {
+        for (participant, side, id) in [
+            (
+                trade.maker_participant,
+                trade.aggressor_side.opposite(),
+                trade.maker_order_id,
+            ),
+            (
+                trade.taker_participant,
+                trade.aggressor_side,
+                trade.taker_order_id,
+            ),
+        ] {
+            if self.quotes.get(&(participant, side)) == Some(&id) {
+                self.risk
+                    .protection_mut()
+                    .record(participant, side, trade.qty, trade.timestamp);
+            }
+        }
+    }
+
+    /// Pulls the quotes of every participant whose protections tripped.
+    fn enforce_protections(&mut self) {
+        for (participant, trigger) in self.risk.protection_mut().take_tripped() {
+            tracing::info!(
+                "{:?} protection tripped for participant {}",
+                trigger,
+                participant
+            );
+            self.events.push(OrderEvent::ProtectionTriggered {
+                participant,
+                trigger,
+                timestamp: self.clock.now(),
+            });
+            for side in [Side::Buy, Side::Sell] {
+                if let Some((id, price)) = self
+                    .quoted(participant, side)
+                    .map(|order| (order.id, order.price))
+                {
+                    self.remove_order(id, price, side, OrderState::Canceled);
+                }
+                self.quotes.remove(&(participant, side));
+            }
+        }
+    }
+
+    /// Runs the checks a new order would go through here, short of rate
+    /// limiting, without placing it.
+    pub fn check_order(
+        &self,
+        participant: ParticipantId,
+        side: Side,
+        price: P,
+        qty: u32,
+    ) -> Result<(), RejectReason> {
+        self.validate(participant, side, price, qty, None)
+    }
+
+    /// Runs the checks a [`OrderCommand::MassQuote`] would go through here,
+    /// short of rate limiting, without applying it.
+    pub fn check_mass_quote(
+        &self,
+        participant: ParticipantId,
+        quotes: &[QuoteEntry<P>],
+    ) -> Result<(), RejectReason> {
+        match self.quote_sides(quotes) {
+            Some(sides) => self
+                .check_quote(participant, sides)
+                .map_err(|(.., reason)| reason),
+            None => Ok(()),
+        }
+    }
+
+    /// The sides of the last entry for this book's symbol.
+    fn quote_sides(&self, quotes: &[QuoteEntry<P>]) -> Option<[(Side, P, u32); 2]> {
+        quotes
+            .iter()
+            .rev()
+            .find(|quote| quote.symbol == self.symbol)
+            .map(QuoteEntry::sides)
+    }
+
+    /// Checks both sides of a quote, each as a modify of the side it
+    /// replaces. Pulling a side always passes.
+    fn check_quote(
+        &self,
+        participant: ParticipantId,
+        sides: [(Side, P, u32); 2],
+    ) -> Result<(), (Side, P, u32, RejectReason)> {
+        let [(_, bid, bid_qty), (_, ask, ask_qty)] = sides;
+        if bid_qty > 0 && ask_qty > 0 && bid >= ask {
+            return Err((Side::Buy, bid, bid_qty, RejectReason::CrossedQuote));
+        }
+        for (side, price, qty) in sides {
+            if qty > 0 {
+                self.validate(
+                    participant,
+                    side,
+                    price,
+                    qty,
+                    self.quoted(participant, side),
+                )
+                .map_err(|reason| (side, price, qty, reason))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the participant's quotes with `sides`. Old quotes are pulled
+    /// or resized before any new one is placed.
+    fn requote(&mut self, participant: ParticipantId, sides: [(Side, P, u32); 2]) {
+        let now = self.clock.now();
+        let mut placing = Vec::new();
+        for (side, price, qty) in sides {
+            let current = self
+                .quoted(participant, side)
+                .map(|order| (order.id, order.price, order.remaining_qty, order.arrival));
+            match current {
+                Some((id, quoted, remaining, arrival))
+                    if self.quote_priority == QuotePriority::Preserve
+                        && quoted == price
+                        && (1..=remaining).contains(&qty) =>
+                {
+                    let queue = self.queue(side);
+                    if let Ok(lim_pos) = matcher_core::level_index(queue, side, price) {
+                        queue[lim_pos].resize(id, qty);
+                    }
+                    let credit = self.risk.credit_mut();
+                    credit.released(participant, side, price, remaining);
+                    credit.rested(participant, side, price, qty);
+                    self.events.push(OrderEvent::Quoted {
+                        id,
+                        participant,
+                        side,
+                        price,
+                        qty,
+                        timestamp: now,
+                        arrival,
+                    });
+                }
+                current => {
+                    if let Some((id, quoted, ..)) = current {
+                        self.remove_order(id, quoted, side, OrderState::Canceled);
+                    }
+                    self.quotes.remove(&(participant, side));
+                    if qty > 0 {
+                        placing.push((side, price, qty));
                     }
                 }
             }
         }
+        for (side, price, qty) in placing {
+            let id = self.next_order_id;
+            self.next_order_id += 1;
+            let arrival = self.next_arrival();
+            self.quotes.insert((participant, side), id);
+            self.events.push(OrderEvent::Quoted {
+                id,
+                participant,
+                side,
+                price,
+                qty,
+                timestamp: now,
+                arrival,
+            });
+            self.place_order(Order {
+                participant,
+                arrival,
+                group: self.group(participant),
+                class: self.priority_class(participant),
+                ..Order::new(id, OrderType::GoodTilCancel, side, price, qty, now)
+            });
+        }
+    }
+
+    /// Takes a trade back out of the tape, the session totals and both
+    /// participants' positions. With `restore` set, the quantity goes back
+    /// on whichever of the two orders still rests, in its original place in
+    /// the queue.
+    fn bust_trade(&mut self, trade_id: usize, restore: bool) {
+        let Some(trade) = self.tape.bust(trade_id) else {
+            tracing::warn!("bust of unknown trade {}", trade_id);
+            return;
+        };
+        self.stats.bust(&trade);
+        self.positions.bust(&self.symbol, &trade);
+        self.fees.bust(&trade);
+        if restore {
+            for id in [trade.maker_order_id, trade.taker_order_id] {
+                self.restore_qty(id, trade.qty);
+            }
+        }
+        self.events.push(OrderEvent::TradeBust {
+            trade,
+            restored: restore,
+        });
+    }
+
+    fn restore_qty(&mut self, id: OrderId, qty: u32) {
+        let Some(order) = self.find_order(id) else {
+            return;
+        };
+        let (participant, side, price) = (order.participant, order.side, order.price);
+        let queue = self.queue(side);
+        if let Ok(lim_pos) = matcher_core::level_index(queue, side, price) {
+            queue[lim_pos].restore(id, qty);
+        }
+        self.risk.credit_mut().rested(participant, side, price, qty);
+        if self
+            .find_order(id)
+            .is_some_and(|order| order.remaining_qty == order.initial_qty)
+        {
+            self.advance(id, OrderState::Working);
+        }
+    }
+
+    fn owner(&self, id: OrderId) -> Option<ParticipantId> {
+        self.resting.get(&id).map(|&(participant, ..)| participant)
+    }
+
+    /// Prints a cross reported by `buyer` and `seller` if it passes the
+    /// [`CrossRules`], leaving the book as it is.
+    fn cross(&mut self, buyer: ParticipantId, seller: ParticipantId, price: P, qty: u32) {
+        let rules = self.cross_rules;
+        let checked = if self.status == TradingStatus::Halted {
+            Err(RejectReason::Halted)
+        } else if self.risk.is_blocked(buyer) || self.risk.is_blocked(seller) {
+            Err(RejectReason::Blocked)
+        } else if qty == 0 || qty < rules.min_qty {
+            Err(RejectReason::BelowBlockSize)
+        } else if self
+            .reference_price()
+            .is_some_and(|reference| rules.band.breached(price, reference))
+        {
+            Err(RejectReason::PriceCollar)
+        } else {
+            Ok(())
+        };
+        if let Err(reason) = checked {
+            self.reject(buyer, Side::Buy, price, qty, reason);
+            return;
+        }
+        let mut trade = Trade {
+            id: self.next_trade_id,
+            price,
+            qty,
+            aggressor_side: Side::Buy,
+            maker_order_id: 0,
+            taker_order_id: 0,
+            maker_participant: seller,
+            taker_participant: buyer,
+            timestamp: self.clock.now(),
+            maker_fee: 0,
+            taker_fee: 0,
+            cross: true,
+            dark: false,
+            currency: self.currency,
+        };
+        self.next_trade_id += 1;
+        hot_trace!(
+            debug,
+            "cross {} {}@{} between {} and {}",
+            trade.id,
+            qty,
+            price,
+            buyer,
+            seller
+        );
+        self.fee_schedule.apply(&mut trade);
+        self.tape.record(trade);
+        self.stats.record(&trade);
+        self.positions.record(&self.symbol, &trade);
+        self.fees.record(&trade);
+        self.events.push(OrderEvent::Trade(trade));
+    }
+
+    /// Spends a rate limit token for the participant behind `command`.
+    /// Commands for unknown orders do nothing, so they are let through, as
+    /// are administrative commands and cancels, so that a participant who
+    /// has run out of tokens can still pull their orders.
+    fn throttle(&mut self, command: &OrderCommand<P>) -> Result<(), RejectReason> {
+        if !self.risk.throttle().is_enabled() {
+            return Ok(());
+        }
+        let participant = match *command {
+            OrderCommand::New { participant, .. } | OrderCommand::MassQuote { participant, .. } => {
+                Some(participant)
+            }
+            OrderCommand::Modify { id, .. } => self.owner(id),
+            OrderCommand::Cross { buyer, .. } => Some(buyer),
+            OrderCommand::Cancel { .. }
+            | OrderCommand::KillSwitch { .. }
+            | OrderCommand::Halt
+            | OrderCommand::Resume { .. }
+            | OrderCommand::BustTrade { .. } => None,
+        };
+        match participant {
+            Some(participant) => self.risk.admit(participant, self.clock.now()),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the pre-trade checks on a new order, or on the order that
+    /// `replaced` is about to be modified into.
+    fn validate(
+        &self,
+        participant: ParticipantId,
+        side: Side,
+        price: P,
+        qty: u32,
+        replaced: Option<&Order<P>>,
+    ) -> Result<(), RejectReason> {
+        if self.status == TradingStatus::Halted && self.halt_policy == HaltPolicy::Reject {
+            return Err(RejectReason::Halted);
+        }
+        if self
+            .odd_lots
+            .is_some_and(|config| config.policy == OddLotPolicy::Reject && config.is_odd_lot(qty))
+        {
+            return Err(RejectReason::OddLot);
+        }
+        self.risk.check_new(participant, price, qty)?;
+        let open = self.open_orders(participant) - usize::from(replaced.is_some());
+        self.risk.check_open_orders(participant, open)?;
+        self.risk.check_price(price, self.reference_price())?;
+        let replaced = replaced.map_or(0, |order| notional(order.price, order.remaining_qty));
+        self.risk.check_credit(
+            participant,
+            &self.position(participant),
+            side,
+            notional(price, qty),
+            replaced,
+        )
+    }
+
+    fn reject(
+        &mut self,
+        participant: ParticipantId,
+        side: Side,
+        price: P,
+        qty: u32,
+        reason: RejectReason,
+    ) {
+        hot_trace!(
+            debug,
+            "rejected {:?} {}@{} from {}: {:?}",
+            side,
+            qty,
+            price,
+            participant,
+            reason
+        );
+        let start = self.stage_start();
+        self.events.push(OrderEvent::Rejected {
+            participant,
+            side,
+            price,
+            qty,
+            reason,
+        });
+        self.stage_end(Stage::EventEmission, start);
+    }
+
+    /// Cancels every resting order belonging to `participant`, returning how
+    /// many were canceled. Each cancel is logged and emits a `Canceled`
+    /// event like any other, but is exempt from rate limiting.
+    pub fn cancel_all(&mut self, participant: ParticipantId) -> usize {
+        let seen = self.events.len();
+        let canceled = self.cancel_resting(participant);
+        self.finish_command(seen);
+        canceled
+    }
+
+    fn cancel_resting(&mut self, participant: ParticipantId) -> usize {
+        let resting: Vec<(OrderId, Side, P)> = self
+            .open_orders
+            .get(&participant)
+            .into_iter()
+            .flatten()
+            .map(|(&id, &(side, price))| (id, side, price))
+            .collect();
+        for &(id, side, price) in &resting {
+            self.execute(OrderCommand::Cancel { id, side, price });
+        }
+        resting.len()
+    }
+
+    /// Looks up a resting order by id on either side of the book.
+    pub fn find_order(&self, id: OrderId) -> Option<&Order<P>> {
+        let &(_, side, price) = self.resting.get(&id)?;
+        self.level(side, price)?
+            .orders
+            .iter()
+            .find(|order| order.id == id)
+    }
+
+    /// Best (highest) bid and best (lowest) ask prices, if any.
+    pub fn best_bid(&self) -> Option<P> {
+        self.bids.first().map(|lim| lim.price)
+    }
+
+    pub fn best_ask(&self) -> Option<P> {
+        self.asks.first().map(|lim| lim.price)
+    }
+
+    /// Price new orders are collared against: the last trade, or failing
+    /// that the mid, or whichever side of the book is populated.
+    pub fn reference_price(&self) -> Option<P> {
+        if let Some(last) = self.stats.last {
+            return Some(last);
+        }
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => Some(P::from_ticks(
+                ((i128::from(bid.to_ticks()) + i128::from(ask.to_ticks())) / 2) as i64,
+            )),
+            (bid, ask) => bid.or(ask),
+        }
+    }
+
+    /// With odd lots configured, each side's best level is the best with
+    /// round-lot size, and only that size is shown; see
+    /// [`odd_lots`](crate::odd_lots).
+    pub fn bbo(&self) -> Bbo<P> {
+        if let Some(config) = self.odd_lots {
+            let best = |queue: &[Limit<P>]| queue.iter().find_map(|lim| config.protected(lim));
+            return Bbo {
+                bid: best(&self.bids),
+                ask: best(&self.asks),
+            };
+        }
+        let Depth { bids, asks } = self.depth(1);
+        Bbo {
+            bid: bids.first().copied(),
+            ask: asks.first().copied(),
+        }
+    }
+
+    /// Up to `levels` price levels per side with round-lot orders, counting
+    /// only those. Without odd lots configured, the same as
+    /// [`depth`](Self::depth).
+    pub fn round_lot_depth(&self, levels: usize) -> Depth<P> {
+        match self.odd_lots {
+            Some(config) => self.lot_depth(levels, |lim| config.split(lim).0),
+            None => self.depth(levels),
+        }
+    }
+
+    /// Up to `levels` price levels per side with odd-lot orders, counting
+    /// only those. Empty without odd lots configured.
+    pub fn odd_lot_depth(&self, levels: usize) -> Depth<P> {
+        match self.odd_lots {
+            Some(config) => self.lot_depth(levels, |lim| config.split(lim).1),
+            None => Depth {
+                bids: Vec::new(),
+                asks: Vec::new(),
+            },
+        }
+    }
+
+    fn lot_depth(
+        &self,
+        levels: usize,
+        part: impl Fn(&Limit<P>) -> Option<LevelInfo<P>>,
+    ) -> Depth<P> {
+        let summarize = |queue: &[Limit<P>]| queue.iter().filter_map(&part).take(levels).collect();
+        Depth {
+            bids: summarize(&self.bids),
+            asks: summarize(&self.asks),
+        }
+    }
+
+    /// Returns up to `levels` aggregated price levels per side.
+    pub fn depth(&self, levels: usize) -> Depth<P> {
+        let summarize = |queue: &[Limit<P>]| {
+            queue
+                .iter()
+                .take(levels)
+                .map(|lim| LevelInfo {
+                    price: lim.price,
+                    qty: lim.total_qty(),
+                    order_count: lim.orders.len(),
+                })
+                .collect()
+        };
+        Depth {
+            bids: summarize(&self.bids),
+            asks: summarize(&self.asks),
+        }
+    }
+
+    /// The top `levels` of each side as a ladder; see the [`fmt::Display`]
+    /// impl.
+    pub fn render(&self, levels: usize) -> String {
+        format!("{:.*}", levels, self)
+    }
+
+    /// Imbalance of the resting quantity in the top `levels` levels of each
+    /// side; see [`crate::analytics::imbalance`].
+    pub fn imbalance(&self, levels: usize) -> Option<f64> {
+        let side_qty =
+            |queue: &[Limit<P>]| -> u64 { queue.iter().take(levels).map(Limit::total_qty).sum() };
+        imbalance(side_qty(&self.bids), side_qty(&self.asks))
+    }
+
+    /// Size-weighted mid of the best bid and offer; `None` unless both sides
+    /// have liquidity.
+    pub fn microprice(&self) -> Option<f64> {
+        let Bbo { bid, ask } = self.bbo();
+        Some(microprice(bid?, ask?))
+    }
+
+    /// Midpoint of the quantity-weighted prices of the top `levels` levels
+    /// on each side.
+    pub fn depth_weighted_mid(&self, levels: usize) -> Option<f64> {
+        let Depth { bids, asks } = self.depth(levels);
+        Some((weighted_price(bids)? + weighted_price(asks)?) / 2.0)
+    }
+
+    /// Total quantity resting at `price` on `side`, 0 if nothing rests
+    /// there. Levels keep their totals as orders come and go, so this is a
+    /// binary search rather than a sum.
+    pub fn quantity_at(&self, side: Side, price: P) -> u64 {
+        self.level(side, price).map_or(0, Limit::total_qty)
+    }
+
+    /// How many orders rest at `price` on `side`.
+    pub fn orders_at(&self, side: Side, price: P) -> usize {
+        self.level(side, price).map_or(0, |lim| lim.orders.len())
+    }
+
+    /// Total quantity on `side` priced within `ticks` of its best price,
+    /// touch included; 0 if the side is empty. Only the levels in range are
+    /// visited, each contributing its running total.
+    pub fn quantity_within(&self, side: Side, ticks: u32) -> u64 {
+        self.quantity_within_ticks(side, u128::from(ticks))
+    }
+
+    /// As [`quantity_within`](Self::quantity_within), with the range given
+    /// as a price distance from the touch instead of a tick count. A
+    /// negative distance covers nothing.
+    pub fn quantity_within_distance(&self, side: Side, distance: P) -> u64 {
+        match u128::try_from(distance.to_ticks()) {
+            Ok(ticks) => self.quantity_within_ticks(side, ticks),
+            Err(_) => 0,
+        }
+    }
+
+    fn quantity_within_ticks(&self, side: Side, ticks: u128) -> u64 {
+        let queue = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        let ticks_of = |lim: &Limit<P>| i128::from(lim.price.to_ticks());
+        let Some(touch) = queue.first().map(ticks_of) else {
+            return 0;
+        };
+        queue
+            .iter()
+            .take_while(|lim| (ticks_of(lim) - touch).unsigned_abs() <= ticks)
+            .map(Limit::total_qty)
+            .sum()
     }
 
-    fn remove_order(&mut self, id: usize, price: i32, side: Side) {
+    /// What an order on `side` for `qty` with no limit would fill against
+    /// the book as it stands, without trading. Every resting order counts,
+    /// as though none were the order's own.
+    pub fn estimate_execution(&self, side: Side, qty: u32) -> ExecutionEstimate<P> {
+        let opposite = match side {
+            Side::Buy => &self.asks,
+            Side::Sell => &self.bids,
+        };
+        let levels = opposite.iter().map(|lim| LevelInfo {
+            price: lim.price,
+            qty: lim.total_qty(),
+            order_count: lim.orders.len(),
+        });
+        analytics::estimate_execution(levels, u64::from(qty))
+    }
+
+    fn level(&self, side: Side, price: P) -> Option<&Limit<P>> {
         let queue = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        let lim_pos = matcher_core::level_index(queue, side, price).ok()?;
+        Some(&queue[lim_pos])
+    }
+
+    fn queue(&mut self, side: Side) -> &mut Vec<Limit<P>> {
+        match side {
             Side::Buy => &mut self.bids,
             Side::Sell => &mut self.asks,
-        };
-        if let Some(lim_pos) = queue.iter().position(|lim| lim.price == price) {
-            let lim = queue[lim_pos].borrow_mut();
-            if lim.remove_order_by_id(id) == true {
-                self.events.push(OrderEvent::Canceled { id })
+        }
+    }
+
+    /// Bids are kept in descending price order and asks in ascending order so
+    /// the best level is always at index 0. The order ends up `Canceled`,
+    /// or `Expired` by its timer.
+    fn remove_order(&mut self, id: OrderId, price: P, side: Side, end: OrderState) {
+        let start = self.stage_start();
+        let queue = self.queue(side);
+        if let Ok(lim_pos) = matcher_core::level_index(queue, side, price) {
+            let Some(pos) = queue[lim_pos].find_by_id(id) else {
+                return;
+            };
+            let order = &queue[lim_pos].orders[pos];
+            let (participant, remaining) = (order.participant, order.remaining_qty);
+            queue[lim_pos].remove_order_by_id(id);
+            if queue[lim_pos].orders.is_empty() {
+                queue.remove(lim_pos);
             }
+            self.untrack(participant, id);
+            self.risk
+                .credit_mut()
+                .released(participant, side, price, remaining);
+            self.stage_end(Stage::Execution, start);
+            self.advance(id, end);
+            hot_trace!(trace, "order {} canceled with {} left", id, remaining);
+            let start = self.stage_start();
+            self.events.push(OrderEvent::Canceled { id });
+            self.stage_end(Stage::EventEmission, start);
         }
     }
 
-    pub fn place_order(&mut self, mut order: Order) {
+    /// Matches `order` and rests what is left of it if it is good-til-cancel
+    /// or a day order.
+    /// An order without an arrival sequence is given the next one.
+    pub fn place_order(&mut self, mut order: Order<P>) {
         if order.initial_qty == 0 {
             return;
         }
-        let order_to_match = self.find_order_to_match(&order);
+        if order.arrival == 0 {
+            order.arrival = self.next_arrival();
+        }
+        self.enter(order.id);
+        self.work_order(order);
+    }
 
-        if let Some(order_try_match) = order_to_match {
-            let can_match = match order.side {
-                Side::Buy => order.price >= order_try_match.price,
-                Side::Sell => order.price <= order_try_match.price,
-            };
-            if can_match {
-                match self.try_match_order(&mut order, &order_try_match) {
-                    MatchStatus::Done => {}
-                    MatchStatus::Pending => {
-                        let ord = Order {
-                            remaining_qty: order.remaining_qty - order_try_match.remaining_qty,
-                            updated_at: Instant::now(),
-                            ..order
-                        };
-                        self.place_order(ord);
-                    }
-                }
+    /// Matches an order that has entered the lifecycle, resting or
+    /// canceling what is left of it.
+    fn work_order(&mut self, mut order: Order<P>) {
+        let mut may_rest = true;
+        if self.status == TradingStatus::Open {
+            may_rest = self.match_order(&mut order);
+        }
+        if order.is_filled() {
+            return;
+        }
+        let start = self.stage_start();
+        match order.order_type {
+            OrderType::GoodTilCancel | OrderType::Day if may_rest => {
+                self.rest_order(order);
+                self.stage_end(Stage::Execution, start);
             }
-        } else {
-            let queue = match order.side {
-                Side::Buy => &mut self.bids,
-                Side::Sell => &mut self.asks,
-            };
-            if let Some(lim_pos) = queue.iter().position(|lim| lim.price == order.price) {
-                let lim = queue[lim_pos].borrow_mut();
-                lim.orders.push_back(order);
-            } else {
-                let mut new_lim = Limit::new(order.price);
-                new_lim.orders.push_back(order);
-                queue.push(new_lim);
+            _ => {
+                self.advance(order.id, OrderState::Canceled);
+                self.events.push(OrderEvent::Canceled { id: order.id });
+                self.stage_end(Stage::EventEmission, start);
             }
         }
     }
 
-    fn find_order_to_match(&mut self, order: &Order) -> Option<Order> {
-        let order_to_match: Option<Order> = {
-            let queue_to_match = match order.side {
-                Side::Buy => {
-                    self.asks.sort();
-                    &self.asks
+    fn rest_order(&mut self, order: Order<P>) {
+        let side = order.side;
+        if self.lifecycle.state(order.id) == Some(OrderState::New) {
+            self.advance(order.id, OrderState::Working);
+        }
+        self.open_orders
+            .entry(order.participant)
+            .or_default()
+            .insert(order.id, (side, order.price));
+        self.resting
+            .insert(order.id, (order.participant, side, order.price));
+        self.risk
+            .credit_mut()
+            .rested(order.participant, side, order.price, order.remaining_qty);
+        matcher_core::rest(self.queue(side), order);
+    }
+
+    /// Matches `order` against the opposite side until it is filled, the
+    /// best opposite level no longer crosses its limit price, or the next
+    /// trade would trip the circuit breaker, which halts the book. Resting
+    /// orders in the order's anti-internalization group are handled as the
+    /// book's [`Internalization`] says. Returns false if what is left of
+    /// `order` must not rest.
+    fn match_order(&mut self, order: &mut Order<P>) -> bool {
+        let mut tripped = false;
+        let mut may_rest = true;
+        let mut skipped = Vec::new();
+        let timing = self.latency.is_some();
+        let mut lookup = Duration::ZERO;
+        let (mut execution, mut emission) = (None, None);
+        while !order.is_filled() {
+            let start = timing.then(Instant::now);
+            let opposite = match order.side {
+                Side::Buy => &mut self.asks,
+                Side::Sell => &mut self.bids,
+            };
+            let crossing = matcher_core::best_crossing(opposite, order.side, order.price);
+            if let Some(start) = start {
+                lookup += start.elapsed();
+            }
+            let Some(price) = crossing else {
+                break;
+            };
+            let maker = &opposite[0].orders[0];
+            if order.group.is_some() && maker.group == order.group {
+                let id = maker.id;
+                match self.internalization {
+                    Internalization::Skip => {
+                        skipped.push(maker.clone());
+                        opposite[0].remove_order_by_id(id);
+                        if opposite[0].orders.is_empty() {
+                            opposite.remove(0);
+                        }
+                        may_rest = false;
+                    }
+                    Internalization::CancelPassive => {
+                        self.remove_order(id, price, order.side.opposite(), OrderState::Canceled)
+                    }
+                    Internalization::CancelAggressive => {
+                        may_rest = false;
+                        break;
+                    }
                 }
-                Side::Sell => {
-                    self.bids
-                        .sort_by(|a, b| b.price.partial_cmp(&a.price).unwrap());
-                    &self.bids
+                continue;
+            }
+            let timestamp = self.clock.now();
+            let start = timing.then(Instant::now);
+            if let Some(breaker) = &mut self.breaker {
+                if breaker.would_trip(price, timestamp) {
+                    tripped = true;
+                    break;
                 }
-            };
-            if !queue_to_match.is_empty() {
-                queue_to_match.first().unwrap().orders.front().cloned()
-            } else {
-                None
+                breaker.record(price, timestamp);
             }
-        };
-        order_to_match
-    }
+            let fill = matcher_core::fill_best(opposite, order, timestamp);
+            let (qty, opp_ord) = (fill.qty, &fill.maker);
 
-    fn try_match_order(&mut self, order: &mut Order, match_order: &Order) -> MatchStatus {
-        let timestamp = Instant::now();
-        match order.remaining_qty.cmp(&match_order.remaining_qty) {
-            Ordering::Greater => {
-                let lim_vec = match order.side {
-                    Side::Buy => &mut self.asks,
-                    Side::Sell => &mut self.bids,
-                };
-                if let Some(lim_pos) = lim_vec
-                    .iter()
-                    .position(|lim| lim.price == match_order.price)
-                {
-                    let lim = lim_vec[lim_pos].borrow_mut();
-                    let opp_ord = lim.orders.pop_front().unwrap();
-                    if lim.orders.is_empty() {
-                        lim_vec.remove(lim_pos);
-                    }
-                    self.events.push(OrderEvent::PartiallyFilled {
-                        id: order.id,
-                        price: order.price,
-                        qty: opp_ord.remaining_qty,
+            let trade_id = self.next_trade_id;
+            self.next_trade_id += 1;
+            let mut trade = Trade {
+                id: trade_id,
+                price,
+                qty,
+                aggressor_side: order.side,
+                maker_order_id: opp_ord.id,
+                taker_order_id: order.id,
+                maker_participant: opp_ord.participant,
+                taker_participant: order.participant,
+                timestamp,
+                maker_fee: 0,
+                taker_fee: 0,
+                cross: false,
+                dark: false,
+                currency: self.currency,
+            };
+            self.fee_schedule.apply(&mut trade);
+            hot_trace!(
+                trace,
+                "trade {} {}@{} order {} against {}",
+                trade_id,
+                qty,
+                price,
+                order.id,
+                opp_ord.id
+            );
+            self.tape.record(trade);
+            self.stats.record(&trade);
+            self.positions.record(&self.symbol, &trade);
+            self.fees.record(&trade);
+            self.count_quote_fill(&trade);
+            self.risk
+                .credit_mut()
+                .released(opp_ord.participant, order.side.opposite(), price, qty);
+            let emitting = timing.then(Instant::now);
+            if let (Some(start), Some(emitting)) = (start, emitting) {
+                *execution.get_or_insert(Duration::ZERO) += emitting - start;
+            }
+            self.events.push(OrderEvent::Trade(trade));
+            for filled in [opp_ord, &*order] {
+                self.advance(filled.id, MatchStatus::of(filled).state());
+                self.events.push(match MatchStatus::of(filled) {
+                    MatchStatus::Done => OrderEvent::Filled {
+                        id: filled.id,
+                        price,
                         timestamp,
-                    });
-                    self.events.push(OrderEvent::Filled {
-                        id: opp_ord.id,
-                        price: order.price,
+                    },
+                    MatchStatus::Pending => OrderEvent::PartiallyFilled {
+                        id: filled.id,
+                        price,
+                        qty,
                         timestamp,
-                    });
-                    return MatchStatus::Pending;
+                    },
+                });
+            }
+            let start = timing.then(Instant::now);
+            if let (Some(start), Some(emitting)) = (start, emitting) {
+                *emission.get_or_insert(Duration::ZERO) += start - emitting;
+            }
+
+            if opp_ord.is_filled() {
+                self.untrack(opp_ord.participant, opp_ord.id);
+            }
+            if let Some(start) = start {
+                *execution.get_or_insert(Duration::ZERO) += start.elapsed();
+            }
+        }
+        self.stage_add(Stage::MatchLookup, Some(lookup));
+        self.stage_add(Stage::Execution, execution);
+        self.stage_add(Stage::EventEmission, emission);
+        let opposite = self.queue(order.side.opposite());
+        for maker in skipped.into_iter().rev() {
+            match matcher_core::level_index(opposite, maker.side, maker.price) {
+                Ok(lim_pos) => opposite[lim_pos].push_front(maker),
+                Err(lim_pos) => {
+                    let mut level = Limit::new(maker.price);
+                    level.push_front(maker);
+                    opposite.insert(lim_pos, level);
                 }
-                return MatchStatus::Pending;
             }
-            Ordering::Less => {
-                let lim_vec = match order.side {
-                    Side::Buy => &mut self.asks,
-                    Side::Sell => &mut self.bids,
-                };
-                if let Some(lim_pos) = lim_vec
-                    .iter()
-                    .position(|lim| lim.price == match_order.price)
-                {
-                    let lim = lim_vec[lim_pos].borrow_mut();
-                    let mut opp_ord = lim.orders.front().unwrap().to_owned();
-                    let _ = opp_ord.fill(order.remaining_qty);
-                    self.events.push(OrderEvent::PartiallyFilled {
-                        id: opp_ord.id,
-                        price: order.price,
-                        qty: order.remaining_qty,
+        }
+        if tripped {
+            self.halt();
+        }
+        may_rest
+    }
+
+    /// Matches the fronts of both sides against each other for as long as
+    /// they cross: at `price` if given, for as long as both are willing to
+    /// trade there, otherwise at the maker's price. The earlier order of
+    /// each pair counts as the maker.
+    fn uncross_book(&mut self, price: Option<P>) {
+        let timestamp = self.clock.now();
+        while let (Some(bid), Some(ask)) = (self.bids.first_mut(), self.asks.first_mut()) {
+            let crossed = match price {
+                Some(price) => bid.price >= price && ask.price <= price,
+                None => bid.price >= ask.price,
+            };
+            if !crossed {
+                break;
+            }
+            let qty = bid.orders[0].remaining_qty.min(ask.orders[0].remaining_qty);
+            let buy = bid.fill_front(qty, timestamp).clone();
+            let sell = ask.fill_front(qty, timestamp).clone();
+            let (maker, taker) = if buy.arrival < sell.arrival {
+                (&buy, &sell)
+            } else {
+                (&sell, &buy)
+            };
+            let price = price.unwrap_or(maker.price);
+            let trade_id = self.next_trade_id;
+            self.next_trade_id += 1;
+            let mut trade = Trade {
+                id: trade_id,
+                price,
+                qty,
+                aggressor_side: taker.side,
+                maker_order_id: maker.id,
+                taker_order_id: taker.id,
+                maker_participant: maker.participant,
+                taker_participant: taker.participant,
+                timestamp,
+                maker_fee: 0,
+                taker_fee: 0,
+                cross: false,
+                dark: false,
+                currency: self.currency,
+            };
+            self.fee_schedule.apply(&mut trade);
+            self.tape.record(trade);
+            self.stats.record(&trade);
+            self.positions.record(&self.symbol, &trade);
+            self.fees.record(&trade);
+            self.count_quote_fill(&trade);
+            for order in [&buy, &sell] {
+                self.risk
+                    .credit_mut()
+                    .released(order.participant, order.side, order.price, qty);
+            }
+            self.events.push(OrderEvent::Trade(trade));
+            for filled in [maker, taker] {
+                self.advance(filled.id, MatchStatus::of(filled).state());
+                self.events.push(match MatchStatus::of(filled) {
+                    MatchStatus::Done => OrderEvent::Filled {
+                        id: filled.id,
+                        price,
                         timestamp,
-                    });
-                    self.events.push(OrderEvent::Filled {
-                        id: order.id,
-                        price: order.price,
+                    },
+                    MatchStatus::Pending => OrderEvent::PartiallyFilled {
+                        id: filled.id,
+                        price,
+                        qty,
                         timestamp,
-                    });
-                    return MatchStatus::Done;
-                };
-                return MatchStatus::Done;
+                    },
+                });
             }
-            _ => {
-                let lim_vec = match order.side {
-                    Side::Buy => &mut self.asks,
-                    Side::Sell => &mut self.bids,
-                };
-                if let Some(lim_pos) = lim_vec
-                    .iter()
-                    .position(|lim| lim.price == match_order.price)
-                {
-                    let lim = lim_vec[lim_pos].borrow_mut();
-                    let opp_ord = lim.orders.pop_front().unwrap();
-                    if lim.orders.is_empty() {
-                        lim_vec.remove(lim_pos);
+            for (queue, filled) in [
+                (&mut self.bids, buy.is_filled()),
+                (&mut self.asks, sell.is_filled()),
+            ] {
+                if filled {
+                    queue[0].orders.pop_front();
+                    if queue[0].orders.is_empty() {
+                        queue.remove(0);
                     }
-                    self.events.push(OrderEvent::Filled {
-                        id: opp_ord.id,
-                        price: order.price,
-                        timestamp,
-                    });
-                    self.events.push(OrderEvent::Filled {
-                        id: order.id,
-                        price: order.price,
-                        timestamp,
-                    });
-                    return MatchStatus::Done;
                 }
-                return MatchStatus::Done;
+            }
+            for order in [&buy, &sell] {
+                if order.is_filled() {
+                    self.untrack(order.participant, order.id);
+                }
+            }
+        }
+    }
+
+    /// Panics in debug builds if the book's structure is corrupt, so a bad
+    /// mutation is caught by the command that made it.
+    #[cfg(any(test, feature = "invariants"))]
+    fn debug_assert_invariants(&self) {
+        if self.status == TradingStatus::Open {
+            if let (Some(bid), Some(ask)) = (self.best_bid(), self.best_ask()) {
+                debug_assert!(bid < ask, "open book crossed: bid {} ask {}", bid, ask);
+            }
+        }
+        let mut indexed = 0;
+        for (side, levels) in [(Side::Buy, &self.bids), (Side::Sell, &self.asks)] {
+            for level in levels {
+                debug_assert!(!level.orders.is_empty(), "empty level at {}", level.price);
+                debug_assert!(
+                    level
+                        .orders
+                        .iter()
+                        .zip(level.orders.iter().skip(1))
+                        .all(|(a, b)| a.class > b.class
+                            || a.class == b.class && a.arrival < b.arrival),
+                    "level {} out of priority order",
+                    level.price
+                );
+                for order in &level.orders {
+                    debug_assert_eq!(
+                        (order.side, order.price),
+                        (side, level.price),
+                        "order {} on the wrong level",
+                        order.id
+                    );
+                    debug_assert!(
+                        matches!(
+                            self.lifecycle.state(order.id),
+                            Some(OrderState::Working | OrderState::PartiallyFilled)
+                        ),
+                        "order {} resting as {:?}",
+                        order.id,
+                        self.lifecycle.state(order.id)
+                    );
+                    debug_assert_eq!(
+                        self.open_orders
+                            .get(&order.participant)
+                            .and_then(|orders| orders.get(&order.id)),
+                        Some(&(order.side, order.price)),
+                        "order {} missing from the open orders index",
+                        order.id
+                    );
+                    debug_assert_eq!(
+                        self.resting.get(&order.id),
+                        Some(&(order.participant, order.side, order.price)),
+                        "order {} missing from the resting orders index",
+                        order.id
+                    );
+                    indexed += 1;
+                }
+            }
+        }
+        debug_assert_eq!(
+            self.open_orders.values().map(BTreeMap::len).sum::<usize>(),
+            indexed,
+            "open orders index holds orders that are not resting"
+        );
+        debug_assert_eq!(
+            self.resting.len(),
+            indexed,
+            "resting orders index holds orders that are not resting"
+        );
+    }
+
+    /// Starts timing a stage, if latency tracking is on.
+    fn stage_start(&self) -> Option<Instant> {
+        self.latency.as_ref().map(|_| Instant::now())
+    }
+
+    fn stage_end(&mut self, stage: Stage, start: Option<Instant>) {
+        self.stage_add(stage, start.map(|start| start.elapsed()));
+    }
+
+    fn stage_add(&mut self, stage: Stage, duration: Option<Duration>) {
+        if let (Some(latency), Some(duration)) = (&mut self.latency, duration) {
+            latency.add(stage, duration);
+        }
+    }
+
+    /// Drops an order that has left the book from the open orders index.
+    fn untrack(&mut self, participant: ParticipantId, id: OrderId) {
+        self.resting.remove(&id);
+        if let Some(orders) = self.open_orders.get_mut(&participant) {
+            orders.remove(&id);
+            if orders.is_empty() {
+                self.open_orders.remove(&participant);
             }
         }
     }
@@ -242,11 +2247,28 @@ enum MatchStatus {
     Done,
 }
 
+impl MatchStatus {
+    fn of<P>(order: &Order<P>) -> MatchStatus {
+        if order.is_filled() {
+            MatchStatus::Done
+        } else {
+            MatchStatus::Pending
+        }
+    }
+
+    fn state(self) -> OrderState {
+        match self {
+            MatchStatus::Pending => OrderState::PartiallyFilled,
+            MatchStatus::Done => OrderState::Filled,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use crate::order_book::OrderBook;
-    use crate::{OrderCommand, OrderType, Side};
+    use crate::{OrderCommand, OrderEvent, OrderId, OrderType, Side};
 
     #[test]
     fn test_match_multiple_orders() {
@@ -257,6 +2279,7 @@ mod tests {
             side: Side::Sell,
             price: order_price,
             qty: 1,
+            participant: 1,
         };
         order_book.process_command(order);
         let order = OrderCommand::New {
@@ -264,6 +2287,7 @@ mod tests {
             side: Side::Sell,
             price: order_price,
             qty: 1,
+            participant: 1,
         };
         order_book.process_command(order);
         let order = OrderCommand::New {
@@ -271,6 +2295,7 @@ mod tests {
             side: Side::Sell,
             price: order_price,
             qty: 1,
+            participant: 1,
         };
         order_book.process_command(order);
         let order = OrderCommand::New {
@@ -278,6 +2303,7 @@ mod tests {
             side: Side::Sell,
             price: order_price,
             qty: 1,
+            participant: 1,
         };
         order_book.process_command(order);
         let order = OrderCommand::New {
@@ -285,6 +2311,7 @@ mod tests {
             side: Side::Sell,
             price: order_price,
             qty: 1,
+            participant: 1,
         };
         order_book.process_command(order);
         let order = OrderCommand::New {
@@ -292,6 +2319,7 @@ mod tests {
             side: Side::Buy,
             price: order_price,
             qty: 5,
+            participant: 1,
         };
         order_book.process_command(order);
 
@@ -307,24 +2335,28 @@ mod tests {
             side: Side::Buy,
             price: 123,
             qty: 1,
+            participant: 1,
         };
         let order1 = OrderCommand::New {
             order_type: OrderType::GoodTilCancel,
             side: Side::Buy,
             price: 124,
             qty: 1,
+            participant: 1,
         };
         let order2 = OrderCommand::New {
             order_type: OrderType::GoodTilCancel,
             side: Side::Sell,
             price: 122,
             qty: 1,
+            participant: 1,
         };
         let order3 = OrderCommand::New {
             order_type: OrderType::GoodTilCancel,
             side: Side::Sell,
             price: 122,
             qty: 1,
+            participant: 1,
         };
         order_book.process_command(order);
         order_book.process_command(order1);
@@ -343,6 +2375,7 @@ mod tests {
             side: Side::Sell,
             price: order_price,
             qty: 1,
+            participant: 1,
         };
         order_book.process_command(order);
         let order = OrderCommand::New {
@@ -350,6 +2383,7 @@ mod tests {
             side: Side::Buy,
             price: order_price,
             qty: 1,
+            participant: 1,
         };
         order_book.process_command(order);
 
@@ -366,6 +2400,7 @@ mod tests {
             side: Side::Buy,
             price: order_price,
             qty: 1,
+            participant: 1,
         };
         order_book.process_command(order);
         assert_eq!(order_book.bids.len(), 1);
@@ -380,8 +2415,472 @@ mod tests {
             side: Side::Sell,
             price: order_price,
             qty: 1,
+            participant: 1,
         };
         order_book.process_command(order);
         assert_eq!(order_book.asks.len(), 1);
     }
+
+    #[test]
+    fn partial_fill_reduces_resting_order() {
+        let mut order_book = OrderBook::new();
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Sell,
+            price: 122,
+            qty: 5,
+            participant: 1,
+        });
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 122,
+            qty: 2,
+            participant: 1,
+        });
+        assert_eq!(order_book.bids.len(), 0);
+        assert_eq!(order_book.asks[0].orders[0].remaining_qty, 3);
+        assert_eq!(order_book.trades().len(), 1);
+    }
+
+    #[test]
+    fn non_crossing_order_rests() {
+        let mut order_book = OrderBook::new();
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Sell,
+            price: 125,
+            qty: 1,
+            participant: 1,
+        });
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 122,
+            qty: 1,
+            participant: 1,
+        });
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 123,
+            qty: 1,
+            participant: 1,
+        });
+        assert_eq!(order_book.best_bid(), Some(123));
+        assert_eq!(order_book.best_ask(), Some(125));
+        assert_eq!(order_book.bids.len(), 2);
+    }
+
+    #[test]
+    fn trades_print_at_resting_price() {
+        let mut order_book = OrderBook::new();
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Sell,
+            price: 122,
+            qty: 1,
+            participant: 1,
+        });
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 130,
+            qty: 1,
+            participant: 1,
+        });
+        assert_eq!(order_book.trades()[0].price, 122);
+    }
+
+    #[test]
+    fn reports_liquidity_at_a_price() {
+        let mut order_book = OrderBook::new();
+        for (side, price, qty) in [
+            (Side::Buy, 99, 5),
+            (Side::Buy, 99, 7),
+            (Side::Sell, 101, 3),
+            (Side::Buy, 101, 1),
+        ] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant: 1,
+            });
+        }
+        assert_eq!(order_book.quantity_at(Side::Buy, 99), 12);
+        assert_eq!(order_book.orders_at(Side::Buy, 99), 2);
+        assert_eq!(order_book.quantity_at(Side::Sell, 101), 2);
+        assert_eq!(order_book.orders_at(Side::Sell, 101), 1);
+        assert_eq!(order_book.quantity_at(Side::Sell, 99), 0);
+        assert_eq!(order_book.orders_at(Side::Buy, 100), 0);
+
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 97,
+            qty: 4,
+            participant: 1,
+        });
+        assert_eq!(order_book.quantity_within(Side::Buy, 0), 12);
+        assert_eq!(order_book.quantity_within(Side::Buy, 1), 12);
+        assert_eq!(order_book.quantity_within(Side::Buy, 2), 16);
+        assert_eq!(order_book.quantity_within(Side::Sell, 10), 2);
+        assert_eq!(order_book.quantity_within_distance(Side::Buy, 1), 12);
+        assert_eq!(order_book.quantity_within_distance(Side::Buy, 2), 16);
+        assert_eq!(order_book.quantity_within_distance(Side::Buy, -1), 0);
+        assert_eq!(order_book.quantity_within_distance(Side::Sell, 0), 2);
+    }
+
+    #[test]
+    fn quantity_within_handles_empty_sides_and_extreme_prices() {
+        let mut order_book = OrderBook::new();
+        assert_eq!(order_book.quantity_within(Side::Buy, u32::MAX), 0);
+        assert_eq!(order_book.quantity_within(Side::Sell, 0), 0);
+        for (price, qty) in [(i32::MAX, 1), (0, 2), (i32::MIN, 4)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side: Side::Buy,
+                price,
+                qty,
+                participant: 1,
+            });
+        }
+        assert_eq!(order_book.quantity_within(Side::Sell, u32::MAX), 0);
+        assert_eq!(order_book.quantity_within(Side::Buy, 0), 1);
+        // i32::MAX to i32::MIN is exactly u32::MAX ticks.
+        assert_eq!(order_book.quantity_within(Side::Buy, u32::MAX - 1), 3);
+        assert_eq!(order_book.quantity_within(Side::Buy, u32::MAX), 7);
+
+        // A distance can span more than u32::MAX ticks.
+        let mut order_book = OrderBook::<i64>::priced("ES");
+        for price in [1 << 40, -1, -(1 << 40)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side: Side::Sell,
+                price,
+                qty: 1,
+                participant: 1,
+            });
+        }
+        assert_eq!(order_book.quantity_within_distance(Side::Sell, 1 << 40), 2);
+        assert_eq!(order_book.quantity_within(Side::Sell, u32::MAX), 1);
+    }
+
+    #[test]
+    fn fill_and_kill_does_not_rest() {
+        let mut order_book = OrderBook::new();
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Sell,
+            price: 122,
+            qty: 1,
+            participant: 1,
+        });
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::FillAndKill,
+            side: Side::Buy,
+            price: 122,
+            qty: 3,
+            participant: 1,
+        });
+        assert_eq!(order_book.bids.len(), 0);
+        assert_eq!(order_book.asks.len(), 0);
+    }
+
+    #[test]
+    fn renders_a_ladder() {
+        let mut order_book = OrderBook::with_symbol("DEMO");
+        for (side, price, qty) in [
+            (Side::Sell, 124, 10),
+            (Side::Sell, 123, 2),
+            (Side::Sell, 123, 3),
+            (Side::Buy, 120, 4),
+            (Side::Buy, 118, 7),
+        ] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant: 1,
+            });
+        }
+        let expected = "\
+DEMO Open
+         price        qty orders
+ask        124         10      1
+ask        123          5      2
+----------- spread 3 -----------
+bid        120          4      1
+bid        118          7      1
+";
+        assert_eq!(order_book.to_string(), expected);
+        assert_eq!(
+            order_book.render(1),
+            "\
+DEMO Open
+         price        qty orders
+ask        123          5      2
+----------- spread 3 -----------
+bid        120          4      1
+"
+        );
+        assert!(OrderBook::new()
+            .to_string()
+            .ends_with(&format!("{}\n", "-".repeat(32))));
+    }
+
+    #[test]
+    fn order_ids_are_per_book() {
+        let new_order = OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 100,
+            qty: 1,
+            participant: 1,
+        };
+        let mut first = OrderBook::new();
+        let mut second = OrderBook::new();
+        second.set_next_order_id(1_000);
+        first.process_command(new_order.clone());
+        first.process_command(new_order.clone());
+        second.process_command(new_order);
+        let ids = |order_book: &OrderBook| -> Vec<OrderId> {
+            order_book.bids[0]
+                .orders
+                .iter()
+                .map(|order| order.id)
+                .collect()
+        };
+        assert_eq!(ids(&first), [1, 2]);
+        assert_eq!(ids(&second), [1_000]);
+        assert_eq!(second.next_order_id(), 1_001);
+    }
+
+    #[test]
+    fn timers_fire_on_the_next_command_or_tick() {
+        use crate::circuit_breaker::TradingStatus;
+        use crate::clock::{Clock, ManualClock};
+        use std::time::Duration;
+
+        let clock = ManualClock::new();
+        let mut order_book = OrderBook::new();
+        order_book.set_clock(clock.clone());
+        for price in [100, 101] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side: Side::Sell,
+                price,
+                qty: 1,
+                participant: 1,
+            });
+        }
+        let in_secs = |secs| clock.now() + Duration::from_secs(secs);
+        assert!(order_book.expire_at(1, in_secs(5)));
+        assert!(!order_book.expire_at(99, in_secs(5)));
+        order_book.schedule(in_secs(10), OrderCommand::Halt);
+        assert!(order_book.next_timer().unwrap() <= in_secs(5));
+
+        clock.advance(Duration::from_secs(4));
+        order_book.tick();
+        assert_eq!(order_book.best_ask(), Some(100));
+
+        // Due before the command arrives, so it fires first.
+        clock.advance(Duration::from_secs(1));
+        order_book.process_command(OrderCommand::Cancel {
+            id: 2,
+            side: Side::Sell,
+            price: 101,
+        });
+        let events = order_book.events();
+        assert_eq!(
+            events[events.len() - 2..],
+            [
+                OrderEvent::Canceled { id: 1 },
+                OrderEvent::Canceled { id: 2 }
+            ]
+        );
+
+        clock.advance(Duration::from_secs(5));
+        order_book.tick();
+        assert_eq!(order_book.status(), TradingStatus::Halted);
+        assert_eq!(order_book.next_timer(), None);
+    }
+
+    #[test]
+    fn cancel_all_only_touches_participant() {
+        let mut order_book = OrderBook::new();
+        for (side, price, participant) in [
+            (Side::Buy, 120, 1),
+            (Side::Buy, 121, 2),
+            (Side::Sell, 125, 1),
+            (Side::Sell, 125, 2),
+        ] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty: 1,
+                participant,
+            });
+        }
+        assert_eq!(order_book.cancel_all(1), 2);
+        assert_eq!(order_book.cancel_all(1), 0);
+        assert_eq!(order_book.best_bid(), Some(121));
+        assert_eq!(order_book.asks[0].orders.len(), 1);
+        let canceled = order_book
+            .events()
+            .iter()
+            .filter(|event| matches!(event, OrderEvent::Canceled { .. }))
+            .count();
+        assert_eq!(canceled, 2);
+    }
+
+    #[test]
+    fn modify_is_validated_once_against_the_book_it_found() {
+        use crate::risk::PriceCollar;
+
+        let mut order_book = OrderBook::new();
+        for (side, price) in [(Side::Buy, 100), (Side::Sell, 110)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty: 1,
+                participant: 1,
+            });
+        }
+        order_book.risk_mut().set_collar(PriceCollar {
+            max_deviation_ticks: Some(5),
+            ..PriceCollar::default()
+        });
+        // The bid is within 5 of the mid, 105, but not of the ask left once
+        // it is canceled.
+        order_book.process_command(OrderCommand::Modify {
+            id: 1,
+            side: Side::Buy,
+            price: 100,
+            qty: 2,
+            order_type: OrderType::GoodTilCancel,
+        });
+        assert_eq!(order_book.bids[0].orders[0].remaining_qty, 2);
+        assert!(matches!(
+            order_book.events()[order_book.events().len() - 2..],
+            [
+                OrderEvent::Canceled { id: 1 },
+                OrderEvent::Placed { id: 3, .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn end_of_day_expires_day_orders() {
+        use crate::lifecycle::OrderState;
+        use crate::stats::SessionStats;
+
+        let mut order_book = OrderBook::new();
+        for (order_type, side, price) in [
+            (OrderType::Day, Side::Buy, 120),
+            (OrderType::GoodTilCancel, Side::Buy, 119),
+            (OrderType::Day, Side::Sell, 125),
+            (OrderType::Day, Side::Buy, 125),
+        ] {
+            order_book.process_command(OrderCommand::New {
+                order_type,
+                side,
+                price,
+                qty: 1,
+                participant: 1,
+            });
+        }
+        let summary = order_book.end_of_day();
+        assert_eq!(summary.expired, [1]);
+        assert_eq!(summary.stats.trade_count, 1);
+        assert_eq!(summary.tape.len(), 1);
+        // Finished orders are forgotten; the carried-over one is not.
+        assert_eq!(order_book.order_state(1), None);
+        assert_eq!(order_book.order_state(3), None);
+        assert_eq!(order_book.order_state(2), Some(OrderState::Working));
+        assert!(matches!(
+            order_book.events().last(),
+            Some(OrderEvent::SessionEnded { .. })
+        ));
+        assert_eq!(order_book.best_bid(), Some(119));
+        assert!(order_book.trades().is_empty());
+        assert_eq!(order_book.stats(), SessionStats::default());
+    }
+
+    #[test]
+    fn bust_trade_reverses_and_restores() {
+        let mut order_book = OrderBook::new();
+        for (side, qty, participant) in [(Side::Sell, 3, 1), (Side::Sell, 1, 3), (Side::Buy, 2, 2)]
+        {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price: 100,
+                qty,
+                participant,
+            });
+        }
+        let trade = order_book.trades()[0];
+        assert_eq!(order_book.asks[0].orders[0].remaining_qty, 1);
+
+        order_book.process_command(OrderCommand::BustTrade {
+            trade_id: trade.id,
+            restore: true,
+        });
+        assert!(order_book.trades().is_empty());
+        assert_eq!(order_book.stats().volume, 0);
+        assert!(order_book.position(1).is_flat() && order_book.position(2).is_flat());
+        // The maker gets its quantity back without losing its place.
+        assert_eq!(order_book.asks[0].orders[0].remaining_qty, 3);
+        assert_eq!(order_book.asks[0].total_qty(), 4);
+        assert_eq!(
+            order_book.events().last(),
+            Some(&OrderEvent::TradeBust {
+                trade,
+                restored: true
+            })
+        );
+
+        let events = order_book.events().len();
+        order_book.process_command(OrderCommand::BustTrade {
+            trade_id: trade.id,
+            restore: true,
+        });
+        assert_eq!(order_book.events().len(), events);
+    }
+
+    #[test]
+    fn books_priced_in_i64_match_beyond_i32() {
+        let base = i64::from(i32::MAX) * 4;
+        let mut order_book = OrderBook::<i64>::priced("ES");
+        for (side, price, participant) in [(Side::Sell, base + 2, 1), (Side::Buy, base, 2)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty: 5,
+                participant,
+            });
+        }
+        assert_eq!(order_book.reference_price(), Some(base + 1));
+        assert_eq!(order_book.depth(1).asks[0].price, base + 2);
+
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::FillAndKill,
+            side: Side::Buy,
+            price: base + 3,
+            qty: 2,
+            participant: 3,
+        });
+        let trade = order_book.trades()[0];
+        assert_eq!((trade.price, trade.qty), (base + 2, 2));
+        assert_eq!(order_book.stats().last, Some(base + 2));
+        assert_eq!(order_book.position(3).avg_price, (base + 2) as f64);
+        assert!(order_book.to_string().contains(&(base + 2).to_string()));
+    }
 }
@@ -0,0 +1,199 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Good-til-cancel persistence.
+//!
+//! Day orders end with the session, but a good-til-cancel order works
+//! until it is canceled, and a restart shouldn't count as one. At shutdown
+//! [`SavedOrders::of`] takes every resting good-til-cancel order, oldest
+//! arrival first, and [`restore`] rests them in a new book at startup in
+//! that order, so each level's queue comes back as it was. Every restored
+//! order is announced with a `Restored` event, which is how the owners and
+//! downstream consumers learn the order is working again.
+//!
+//! Restore into a book before it takes any flow; orders entered first
+//! would be queued ahead of the restored ones.
+
+use crate::{OrderBook, OrderId, OrderType, ParticipantId, Side, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedOrders {
+    pub symbol: String,
+    /// Where the saving book's order ids had got to.
+    pub next_order_id: OrderId,
+    /// Oldest arrival first.
+    pub orders: Vec<SavedOrder>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedOrder {
+    pub id: OrderId,
+    pub participant: ParticipantId,
+    pub side: Side,
+    pub price: i32,
+    pub initial_qty: u32,
+    pub remaining_qty: u32,
+    pub created_at: Timestamp,
+}
+
+impl SavedOrders {
+    /// The good-til-cancel orders resting in `order_book`.
+    pub fn of(order_book: &OrderBook) -> SavedOrders {
+        let mut resting: Vec<_> = order_book
+            .bids
+            .iter()
+            .chain(order_book.asks.iter())
+            .flat_map(|limit| limit.orders.iter())
+            .filter(|order| order.order_type == OrderType::GoodTilCancel)
+            .collect();
+        resting.sort_unstable_by_key(|order| order.arrival);
+        SavedOrders {
+            symbol: order_book.symbol().to_string(),
+            next_order_id: order_book.next_order_id(),
+            orders: resting
+                .into_iter()
+                .map(|order| SavedOrder {
+                    id: order.id,
+                    participant: order.participant,
+                    side: order.side,
+                    price: order.price,
+                    initial_qty: order.initial_qty,
+                    remaining_qty: order.remaining_qty,
+                    created_at: order.created_at,
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the orders to `path` as JSON. The file is written alongside
+    /// and renamed into place, so a crash partway leaves the last one whole.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, json)?;
+        fs::rename(&partial, path)
+    }
+
+    /// Reads orders saved to `path`, or `None` if nothing was saved there.
+    pub fn load(path: &Path) -> io::Result<Option<SavedOrders>> {
+        let raw = match fs::read(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_slice(&raw)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Rests `saved` in `order_book` in the order they were saved, returning
+/// how many were restored. Orders the book won't take back, such as one
+/// whose id it has already seen, are logged and left out. Fails without
+/// restoring anything if they were saved from another symbol's book.
+pub fn restore(order_book: &mut OrderBook, saved: &SavedOrders) -> io::Result<usize> {
+    if saved.symbol != order_book.symbol() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "orders saved for {}, not {}",
+                saved.symbol,
+                order_book.symbol()
+            ),
+        ));
+    }
+    let mut restored = 0;
+    for order in &saved.orders {
+        let resting = crate::Order {
+            participant: order.participant,
+            remaining_qty: order.remaining_qty,
+            ..crate::Order::new(
+                order.id,
+                OrderType::GoodTilCancel,
+                order.side,
+                order.price,
+                order.initial_qty,
+                order.created_at,
+            )
+        };
+        if order_book.restore(resting) {
+            restored += 1;
+        } else {
+            tracing::warn!("order {} not restored", order.id);
+        }
+    }
+    if order_book.next_order_id() < saved.next_order_id {
+        order_book.set_next_order_id(saved.next_order_id);
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{restore, SavedOrders};
+    use crate::lifecycle::OrderState;
+    use crate::snapshot::BookSnapshot;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+
+    #[test]
+    fn restores_good_til_cancel_orders_in_priority() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let mut new = |order_type, side, price, qty, participant| {
+            order_book.process_command(OrderCommand::New {
+                order_type,
+                side,
+                price,
+                qty,
+                participant,
+            })
+        };
+        new(OrderType::GoodTilCancel, Side::Buy, 100, 10, 1);
+        new(OrderType::Day, Side::Buy, 100, 10, 2);
+        new(OrderType::GoodTilCancel, Side::Buy, 100, 10, 3);
+        new(OrderType::GoodTilCancel, Side::Sell, 102, 10, 1);
+        new(OrderType::FillAndKill, Side::Buy, 102, 4, 2);
+        order_book.process_command(OrderCommand::Cancel {
+            id: 2,
+            side: Side::Buy,
+            price: 100,
+        });
+        let saved = SavedOrders::of(&order_book);
+        assert_eq!(
+            saved.orders.iter().map(|o| o.id).collect::<Vec<_>>(),
+            [1, 3, 4]
+        );
+
+        let path = std::env::temp_dir().join(format!("gtc-{}.json", std::process::id()));
+        saved.save(&path).unwrap();
+        let loaded = SavedOrders::load(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, saved);
+        assert_eq!(SavedOrders::load(&path).unwrap(), None);
+
+        let mut restarted = OrderBook::with_symbol("ABC");
+        assert_eq!(restore(&mut restarted, &loaded).unwrap(), 3);
+        let restored = restarted
+            .events()
+            .iter()
+            .filter(|event| matches!(event, OrderEvent::Restored { .. }))
+            .count();
+        assert_eq!(restored, 3);
+        assert_eq!(
+            BookSnapshot::of(&restarted).bids,
+            BookSnapshot::of(&order_book).bids
+        );
+        assert_eq!(restarted.asks[0].orders[0].remaining_qty, 6);
+        assert_eq!(restarted.order_state(4), Some(OrderState::PartiallyFilled));
+        assert_eq!(restarted.next_order_id(), order_book.next_order_id());
+
+        // Nothing is restored twice, or into another symbol's book.
+        assert_eq!(restore(&mut restarted, &loaded).unwrap(), 0);
+        assert!(restore(&mut OrderBook::with_symbol("XYZ"), &loaded).is_err());
+    }
+}
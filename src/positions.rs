@@ -0,0 +1,201 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Per-participant positions built from trades.
+//!
+//! Positions use average cost: adding to a position moves the average entry
+//! price, reducing it realizes PnL against that average at the traded price.
+//! A trade that takes a position through flat closes it and opens the
+//! remainder on the other side at the trade price.
+
+use crate::sink::EventSink;
+use crate::{Currency, OrderEvent, ParticipantId, Price, Side, Trade};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Position {
+    /// Positive when long, negative when short.
+    pub net_qty: i64,
+    /// Average entry price of the open quantity; 0 when flat.
+    pub avg_price: f64,
+    pub realized_pnl: f64,
+    pub bought: u64,
+    pub sold: u64,
+    /// What the prices and PnL are in, from the trades.
+    pub currency: Currency,
+}
+
+impl Position {
+    pub fn is_flat(&self) -> bool {
+        self.net_qty == 0
+    }
+
+    /// PnL of the open quantity if it were closed at `mark`.
+    pub fn unrealized_pnl<P: Price>(&self, mark: P) -> f64 {
+        (mark.to_ticks() as f64 - self.avg_price) * self.net_qty as f64
+    }
+
+    pub fn apply<P: Price>(&mut self, side: Side, price: P, qty: u32) {
+        let price = price.to_ticks() as f64;
+        let signed = match side {
+            Side::Buy => {
+                self.bought += u64::from(qty);
+                i64::from(qty)
+            }
+            Side::Sell => {
+                self.sold += u64::from(qty);
+                -i64::from(qty)
+            }
+        };
+        let open = self.net_qty.abs();
+        if self.net_qty == 0 || self.net_qty.signum() == signed.signum() {
+            let total = open + signed.abs();
+            self.avg_price =
+                (self.avg_price * open as f64 + price * signed.abs() as f64) / total as f64;
+        } else {
+            let closed = open.min(signed.abs());
+            self.realized_pnl +=
+                (price - self.avg_price) * closed as f64 * self.net_qty.signum() as f64;
+            if signed.abs() > open {
+                self.avg_price = price;
+            } else if signed.abs() == open {
+                self.avg_price = 0.0;
+            }
+        }
+        self.net_qty += signed;
+    }
+}
+
+/// Positions keyed by participant and symbol.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Positions {
+    positions: HashMap<(ParticipantId, String), Position>,
+}
+
+impl Positions {
+    pub fn new() -> Self {
+        Positions::default()
+    }
+
+    /// Applies both sides of `trade`. The taker traded on the aggressor side
+    /// and the maker on the other.
+    pub fn record<P: Price>(&mut self, symbol: &str, trade: &Trade<P>) {
+        for (participant, side) in [
+            (trade.maker_participant, trade.aggressor_side.opposite()),
+            (trade.taker_participant, trade.aggressor_side),
+        ] {
+            let position = self
+                .positions
+                .entry((participant, symbol.to_string()))
+                .or_default();
+            position.currency = trade.currency;
+            position.apply(side, trade.price, trade.qty);
+        }
+    }
+
+    /// Unwinds both sides of a busted trade by trading them back at the same
+    /// price. Quantities return to what they were; realized P&L can differ
+    /// if the position has moved since.
+    pub fn bust<P: Price>(&mut self, symbol: &str, trade: &Trade<P>) {
+        self.record(
+            symbol,
+            &Trade {
+                aggressor_side: trade.aggressor_side.opposite(),
+                ..*trade
+            },
+        );
+    }
+
+    /// The participant's position in `symbol`, flat if they never traded it.
+    pub fn get(&self, participant: ParticipantId, symbol: &str) -> Position {
+        self.positions
+            .get(&(participant, symbol.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Every symbol the participant has traded, in symbol order.
+    pub fn for_participant(&self, participant: ParticipantId) -> Vec<(&str, Position)> {
+        let mut positions: Vec<(&str, Position)> = self
+            .positions
+            .iter()
+            .filter(|((owner, _), _)| *owner == participant)
+            .map(|((_, symbol), position)| (symbol.as_str(), *position))
+            .collect();
+        positions.sort_by(|a, b| a.0.cmp(b.0));
+        positions
+    }
+}
+
+impl EventSink for Positions {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        match event {
+            OrderEvent::Trade(trade) => self.record(symbol, trade),
+            OrderEvent::TradeBust { trade, .. } => self.bust(symbol, trade),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Position, Positions};
+    use crate::sink::publish_events;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    #[test]
+    fn average_cost_and_realized_pnl() {
+        let mut position = Position::default();
+        position.apply(Side::Buy, 100, 2);
+        position.apply(Side::Buy, 103, 1);
+        assert_eq!((position.net_qty, position.avg_price), (3, 101.0));
+        assert_eq!(position.unrealized_pnl(105), 12.0);
+
+        position.apply(Side::Sell, 104, 1);
+        assert_eq!((position.net_qty, position.avg_price), (2, 101.0));
+        assert_eq!(position.realized_pnl, 3.0);
+
+        // Through flat: close 2 long, open 3 short at the trade price.
+        position.apply(Side::Sell, 99, 5);
+        assert_eq!((position.net_qty, position.avg_price), (-3, 99.0));
+        assert_eq!(position.realized_pnl, -1.0);
+        position.apply(Side::Buy, 97, 3);
+        assert!(position.is_flat());
+        assert_eq!(position.realized_pnl, 5.0);
+        assert_eq!((position.bought, position.sold), (6, 6));
+    }
+
+    #[test]
+    fn nets_both_sides_of_each_trade() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        for (side, price, qty, participant) in [
+            (Side::Sell, 101, 5, 1),
+            (Side::Buy, 101, 3, 2),
+            (Side::Buy, 101, 2, 1),
+        ] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant,
+            });
+        }
+        assert_eq!(order_book.position(1).net_qty, -3);
+        assert_eq!(order_book.position(2).net_qty, 3);
+        assert_eq!(order_book.position(3), Position::default());
+
+        let mut rebuilt = Positions::new();
+        publish_events(&mut rebuilt, "ABC", order_book.events()).unwrap();
+        assert_eq!(&rebuilt, order_book.positions());
+        assert_eq!(
+            rebuilt.for_participant(2),
+            [("ABC", order_book.position(2))]
+        );
+        assert!(rebuilt.for_participant(3).is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Price representations.
+//!
+//! A book is generic over what its prices are, chosen at compile time:
+//! `OrderBook` is `OrderBook<i32>`, and `OrderBook<i64>` or a book of a
+//! fixed-point decimal type work the same way. Matching only ever compares
+//! prices, so it runs on the type itself. The few places that do arithmetic
+//! on a price, such as notional, midpoints and collar distances, go through
+//! its tick count, which for the integer types is the value itself.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+
+/// A price a book can be quoted in: a [`matcher_core::PriceType`] that also
+/// prints, serializes, and counts in ticks.
+pub trait Price:
+    matcher_core::PriceType
+    + fmt::Display
+    + Default
+    + Send
+    + Sync
+    + Serialize
+    + DeserializeOwned
+    + 'static
+{
+    /// The price as a number of ticks.
+    fn to_ticks(self) -> i64;
+
+    /// The price `ticks` ticks from zero, saturating at the type's bounds.
+    fn from_ticks(ticks: i64) -> Self;
+}
+
+impl Price for i32 {
+    fn to_ticks(self) -> i64 {
+        i64::from(self)
+    }
+
+    fn from_ticks(ticks: i64) -> i32 {
+        ticks.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32
+    }
+}
+
+impl Price for i64 {
+    fn to_ticks(self) -> i64 {
+        self
+    }
+
+    fn from_ticks(ticks: i64) -> i64 {
+        ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Price;
+
+    #[test]
+    fn from_ticks_saturates() {
+        assert_eq!(i32::from_ticks(i64::MAX), i32::MAX);
+        assert_eq!(i32::from_ticks(i64::MIN), i32::MIN);
+        assert_eq!(i32::from_ticks(-7), -7);
+        assert_eq!(i64::from_ticks(i64::MAX), i64::MAX);
+        assert_eq!(Price::to_ticks(i32::MIN), i64::from(i32::MIN));
+    }
+}
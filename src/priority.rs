@@ -0,0 +1,74 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Priority classes.
+//!
+//! Some markets put certain participants' orders first at a price whatever
+//! time they arrived, customers ahead of market makers say. A participant
+//! given a class with
+//! [`OrderBook::set_priority_class`](crate::OrderBook::set_priority_class)
+//! has its orders tagged with it on entry. Within a level, orders of a
+//! higher class are queued ahead of every order of a lower one, and time
+//! priority applies within each class. Everyone is in class 0 until told
+//! otherwise, which leaves plain price-time priority.
+//!
+//! Classes only order the queue at a price; a better price still comes
+//! first.
+
+/// Higher classes are filled first.
+pub type PriorityClass = u8;
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::BookSnapshot;
+    use crate::{OrderBook, OrderCommand, OrderType, ParticipantId, Side};
+
+    const CUSTOMER: u8 = 1;
+
+    fn sell(order_book: &mut OrderBook, price: i32, participant: ParticipantId) {
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Sell,
+            price,
+            qty: 10,
+            participant,
+        });
+    }
+
+    #[test]
+    fn higher_classes_fill_first_at_a_price() {
+        let mut order_book = OrderBook::new();
+        order_book.set_priority_class(3, CUSTOMER);
+        order_book.set_priority_class(4, CUSTOMER);
+        sell(&mut order_book, 101, 1);
+        sell(&mut order_book, 101, 2);
+        sell(&mut order_book, 101, 3);
+        sell(&mut order_book, 101, 4);
+        sell(&mut order_book, 100, 2);
+        let queue: Vec<ParticipantId> = order_book.asks[1]
+            .orders
+            .iter()
+            .map(|order| order.participant)
+            .collect();
+        assert_eq!(queue, [3, 4, 1, 2]);
+        let level = &BookSnapshot::of(&order_book).asks[1];
+        let classes: Vec<u8> = level.orders.iter().map(|order| order.class).collect();
+        assert_eq!(classes, [CUSTOMER, CUSTOMER, 0, 0]);
+
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::FillAndKill,
+            side: Side::Buy,
+            price: 101,
+            qty: 35,
+            participant: 5,
+        });
+        let makers: Vec<ParticipantId> = order_book
+            .trades()
+            .iter()
+            .map(|trade| trade.maker_participant)
+            .collect();
+        // The better price first, then customers in time order.
+        assert_eq!(makers, [2, 3, 4, 1]);
+    }
+}
@@ -0,0 +1,337 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Property tests over random command streams.
+//!
+//! Each case generates a stream of [`Op`]s from a seed, runs it against a
+//! fresh book and checks the book's invariants after every command. A failing
+//! stream is shrunk by dropping ops for as long as it keeps failing, and the
+//! panic names the seed and the minimal stream so the case can be replayed.
+//!
+//! Ops refer to resting orders by position in the list of live orders rather
+//! than by id, so a stream stays meaningful with ops removed.
+
+use crate::sim::Rng;
+use crate::{Order, OrderBook, OrderCommand, OrderEvent, OrderId, OrderType, Side};
+use std::collections::{HashMap, HashSet};
+
+const CASES: u64 = 100;
+const STEPS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Op {
+    New {
+        order_type: OrderType,
+        side: Side,
+        price: i32,
+        qty: u32,
+        participant: u32,
+    },
+    /// Cancels the `pick`th live order, modulo the number live.
+    Cancel {
+        pick: usize,
+    },
+    Modify {
+        pick: usize,
+        qty: u32,
+    },
+}
+
+impl Op {
+    /// Prices cluster around 100 so orders cross often.
+    pub(crate) fn random(rng: &mut Rng) -> Op {
+        match rng.range(0, 9) {
+            0..=5 => Op::New {
+                order_type: if rng.chance(20) {
+                    OrderType::FillAndKill
+                } else {
+                    OrderType::GoodTilCancel
+                },
+                side: if rng.chance(50) {
+                    Side::Buy
+                } else {
+                    Side::Sell
+                },
+                price: rng.range(95, 105) as i32,
+                qty: rng.range(1, 10) as u32,
+                participant: rng.range(1, 4) as u32,
+            },
+            6..=7 => Op::Cancel {
+                pick: rng.next_u64() as usize,
+            },
+            _ => Op::Modify {
+                pick: rng.next_u64() as usize,
+                qty: rng.range(1, 10) as u32,
+            },
+        }
+    }
+
+    /// The command this op stands for, `None` if it refers to a live order
+    /// and there are none.
+    pub(crate) fn command(self, order_book: &OrderBook, live: &[OrderId]) -> Option<OrderCommand> {
+        let pick = |pick: usize| {
+            let id = *live.get(pick % live.len().max(1))?;
+            order_book.find_order(id)
+        };
+        Some(match self {
+            Op::New {
+                order_type,
+                side,
+                price,
+                qty,
+                participant,
+            } => OrderCommand::New {
+                order_type,
+                side,
+                price,
+                qty,
+                participant,
+            },
+            Op::Cancel { pick: index } => {
+                let order = pick(index)?;
+                OrderCommand::Cancel {
+                    id: order.id,
+                    side: order.side,
+                    price: order.price,
+                }
+            }
+            Op::Modify { pick: index, qty } => {
+                let order = pick(index)?;
+                OrderCommand::Modify {
+                    id: order.id,
+                    price: order.price,
+                    side: order.side,
+                    qty,
+                    order_type: order.order_type,
+                }
+            }
+        })
+    }
+}
+
+/// What the book has said about each order so far.
+#[derive(Default)]
+struct Ledger {
+    submitted: HashMap<OrderId, u32>,
+    filled: HashMap<OrderId, u32>,
+    done: HashSet<OrderId>,
+    canceled: HashSet<OrderId>,
+}
+
+/// Runs `ops` against a fresh book, checking invariants after each command.
+/// On failure returns the index of the offending op and what went wrong.
+pub(crate) fn run(ops: &[Op]) -> Result<(), (usize, String)> {
+    let mut order_book = OrderBook::new();
+    let mut ledger = Ledger::default();
+    for (step, op) in ops.iter().enumerate() {
+        let mut live: Vec<OrderId> = resting(&order_book).into_keys().collect();
+        live.sort_unstable();
+        let Some(command) = op.command(&order_book, &live) else {
+            continue;
+        };
+        let seen = order_book.events().len();
+        order_book.process_command(command.clone());
+        let events = &order_book.events()[seen..];
+        let fail = |message: String| (step, format!("{:?}: {}", command, message));
+        record(&mut ledger, &command, events).map_err(fail)?;
+        check_trades(events).map_err(fail)?;
+        check_book(&order_book, &ledger).map_err(fail)?;
+    }
+    Ok(())
+}
+
+fn record(
+    ledger: &mut Ledger,
+    command: &OrderCommand,
+    events: &[OrderEvent],
+) -> Result<(), String> {
+    let qty = match *command {
+        OrderCommand::New { qty, .. } | OrderCommand::Modify { qty, .. } => Some(qty),
+        _ => None,
+    };
+    for event in events {
+        match *event {
+            OrderEvent::Placed { id, .. } => {
+                let qty = qty.ok_or("placed without a new order")?;
+                if ledger.submitted.insert(id, qty).is_some() {
+                    return Err(format!("order {} placed twice", id));
+                }
+            }
+            OrderEvent::Trade(trade) => {
+                for id in [trade.maker_order_id, trade.taker_order_id] {
+                    *ledger.filled.entry(id).or_default() += trade.qty;
+                }
+            }
+            OrderEvent::Filled { id, .. } => {
+                ledger.done.insert(id);
+            }
+            OrderEvent::Canceled { id } => {
+                ledger.canceled.insert(id);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Within one command the aggressor works through the opposite side best
+/// price first, and oldest first within a price.
+fn check_trades(events: &[OrderEvent]) -> Result<(), String> {
+    let trades: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            OrderEvent::Trade(trade) => Some(trade),
+            _ => None,
+        })
+        .collect();
+    for pair in trades.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let worse = match a.aggressor_side {
+            Side::Buy => b.price < a.price,
+            Side::Sell => b.price > a.price,
+        };
+        if worse {
+            return Err(format!("trade at {} after trade at {}", b.price, a.price));
+        }
+        if a.price == b.price && b.maker_order_id < a.maker_order_id {
+            return Err(format!(
+                "maker {} filled after younger maker {} at {}",
+                b.maker_order_id, a.maker_order_id, a.price
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn resting(order_book: &OrderBook) -> HashMap<OrderId, &Order> {
+    order_book
+        .bids
+        .iter()
+        .chain(&order_book.asks)
+        .flat_map(|level| &level.orders)
+        .map(|order| (order.id, order))
+        .collect()
+}
+
+fn check_book(order_book: &OrderBook, ledger: &Ledger) -> Result<(), String> {
+    if let (Some(bid), Some(ask)) = (order_book.best_bid(), order_book.best_ask()) {
+        if bid >= ask {
+            return Err(format!("book crossed: bid {} ask {}", bid, ask));
+        }
+    }
+    for (side, levels) in [
+        (Side::Buy, &order_book.bids),
+        (Side::Sell, &order_book.asks),
+    ] {
+        for pair in levels.windows(2) {
+            let ordered = match side {
+                Side::Buy => pair[0].price > pair[1].price,
+                Side::Sell => pair[0].price < pair[1].price,
+            };
+            if !ordered {
+                return Err(format!("{:?} levels out of order", side));
+            }
+        }
+        for level in levels {
+            if level.orders.is_empty() {
+                return Err(format!("empty level at {}", level.price));
+            }
+            let total: u64 = level
+                .orders
+                .iter()
+                .map(|o| u64::from(o.remaining_qty))
+                .sum();
+            if total != level.total_qty() {
+                return Err(format!(
+                    "level {} total {} != {}",
+                    level.price,
+                    level.total_qty(),
+                    total
+                ));
+            }
+            let mut last_id = 0;
+            for order in &level.orders {
+                if order.id <= last_id {
+                    return Err(format!("order {} queued behind {}", order.id, last_id));
+                }
+                last_id = order.id;
+                if order.side != side || order.price != level.price {
+                    return Err(format!("order {} on the wrong level", order.id));
+                }
+                if order.remaining_qty == 0 || order.remaining_qty > order.initial_qty {
+                    return Err(format!(
+                        "order {} resting with {} of {}",
+                        order.id, order.remaining_qty, order.initial_qty
+                    ));
+                }
+            }
+        }
+    }
+
+    // Submitted quantity is accounted for as filled, resting or canceled.
+    let resting = resting(order_book);
+    for (&id, &submitted) in &ledger.submitted {
+        let filled = ledger.filled.get(&id).copied().unwrap_or(0);
+        if filled > submitted {
+            return Err(format!("order {} filled {} of {}", id, filled, submitted));
+        }
+        match resting.get(&id) {
+            Some(order) => {
+                if order.initial_qty != submitted || order.remaining_qty != submitted - filled {
+                    return Err(format!(
+                        "order {} resting with {} of {}, filled {} of {}",
+                        id, order.remaining_qty, order.initial_qty, filled, submitted
+                    ));
+                }
+            }
+            None if ledger.done.contains(&id) => {
+                if filled != submitted {
+                    return Err(format!(
+                        "order {} done after {} of {}",
+                        id, filled, submitted
+                    ));
+                }
+            }
+            None if ledger.canceled.contains(&id) => {
+                if filled == submitted {
+                    return Err(format!("order {} canceled after filling", id));
+                }
+            }
+            None => return Err(format!("order {} vanished", id)),
+        }
+    }
+    Ok(())
+}
+
+/// Drops ops one at a time, keeping each removal that still fails.
+fn shrink(mut ops: Vec<Op>) -> (Vec<Op>, String) {
+    let (step, mut message) = run(&ops).expect_err("shrinking a passing case");
+    ops.truncate(step + 1);
+    let mut i = 0;
+    while i < ops.len() {
+        let mut candidate = ops.clone();
+        candidate.remove(i);
+        match run(&candidate) {
+            Err((step, reason)) => {
+                candidate.truncate(step + 1);
+                ops = candidate;
+                message = reason;
+            }
+            Ok(()) => i += 1,
+        }
+    }
+    (ops, message)
+}
+
+#[test]
+fn random_streams_keep_invariants() {
+    for seed in 0..CASES {
+        let mut rng = Rng::new(seed);
+        let ops: Vec<Op> = (0..STEPS).map(|_| Op::random(&mut rng)).collect();
+        if run(&ops).is_err() {
+            let (ops, message) = shrink(ops);
+            panic!("seed {}: {}\nminimal ops: {:#?}", seed, message, ops);
+        }
+    }
+}
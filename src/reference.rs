@@ -0,0 +1,246 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! A reference matcher for differential testing.
+//!
+//! [`ReferenceBook`] keeps every resting order in one list in arrival order
+//! and finds each match by scanning all of it, which makes matching
+//! quadratic but leaves little room for mistakes: the best order is the one
+//! with the best price, and among those the one that arrived first. The test
+//! below runs the same random command streams through it and through
+//! [`OrderBook`] and requires the same trades and the same resting orders
+//! after every command.
+//!
+//! Only plain limit order flow is modeled: no risk checks, halts or busts.
+
+use crate::properties::Op;
+use crate::sim::Rng;
+use crate::{OrderBook, OrderCommand, OrderEvent, OrderId, OrderType, Side};
+use std::collections::HashMap;
+
+const CASES: u64 = 50;
+const STEPS: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RestingOrder {
+    id: usize,
+    side: Side,
+    price: i32,
+    qty: u32,
+}
+
+/// A fill as `(price, qty, maker id, taker id)`.
+type Fill = (i32, u32, usize, usize);
+
+#[derive(Debug, Default)]
+struct ReferenceBook {
+    /// Oldest first.
+    orders: Vec<RestingOrder>,
+    next_id: usize,
+}
+
+impl ReferenceBook {
+    /// Enters an order and returns its id and the fills it took.
+    fn new_order(
+        &mut self,
+        order_type: OrderType,
+        side: Side,
+        price: i32,
+        mut qty: u32,
+    ) -> (usize, Vec<Fill>) {
+        self.next_id += 1;
+        let id = self.next_id;
+        let mut fills = Vec::new();
+        while qty > 0 {
+            let Some(best) = self.best_against(side, price) else {
+                break;
+            };
+            let maker = &mut self.orders[best];
+            let filled = qty.min(maker.qty);
+            fills.push((maker.price, filled, maker.id, id));
+            maker.qty -= filled;
+            qty -= filled;
+            if maker.qty == 0 {
+                self.orders.remove(best);
+            }
+        }
+        if qty > 0 && order_type != OrderType::FillAndKill {
+            self.orders.push(RestingOrder {
+                id,
+                side,
+                price,
+                qty,
+            });
+        }
+        (id, fills)
+    }
+
+    fn cancel(&mut self, id: usize) -> Option<RestingOrder> {
+        let index = self.orders.iter().position(|order| order.id == id)?;
+        Some(self.orders.remove(index))
+    }
+
+    /// Replaces the order with a fresh one for `qty` at the same price.
+    fn modify(&mut self, id: usize, qty: u32, order_type: OrderType) -> Option<(usize, Vec<Fill>)> {
+        let order = self.cancel(id)?;
+        Some(self.new_order(order_type, order.side, order.price, qty))
+    }
+
+    /// Index of the order an incoming `side` order at `price` trades with
+    /// first, if any crosses.
+    fn best_against(&self, side: Side, price: i32) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for (i, order) in self.orders.iter().enumerate() {
+            let crosses = match side {
+                Side::Buy => order.side == Side::Sell && order.price <= price,
+                Side::Sell => order.side == Side::Buy && order.price >= price,
+            };
+            if !crosses {
+                continue;
+            }
+            let better = match best.map(|b| self.orders[b].price) {
+                None => true,
+                Some(best_price) => match side {
+                    Side::Buy => order.price < best_price,
+                    Side::Sell => order.price > best_price,
+                },
+            };
+            if better {
+                best = Some(i);
+            }
+        }
+        best
+    }
+
+    /// Resting orders on `side` in priority order.
+    fn resting(&self, side: Side) -> Vec<RestingOrder> {
+        let mut orders: Vec<RestingOrder> = self
+            .orders
+            .iter()
+            .filter(|order| order.side == side)
+            .copied()
+            .collect();
+        // Stable, so arrival order survives within a price.
+        orders.sort_by_key(|order| match side {
+            Side::Buy => -i64::from(order.price),
+            Side::Sell => i64::from(order.price),
+        });
+        orders
+    }
+}
+
+/// Runs one stream through both books. Engine ids are translated into the
+/// reference's numbering, which is assigned in the same order.
+fn differ(ops: &[Op]) -> Result<(), String> {
+    let mut order_book = OrderBook::new();
+    let mut reference = ReferenceBook::default();
+    let mut ids: HashMap<OrderId, usize> = HashMap::new();
+    for (step, op) in ops.iter().enumerate() {
+        let mut live: Vec<OrderId> = ids
+            .keys()
+            .copied()
+            .filter(|&id| order_book.find_order(id).is_some())
+            .collect();
+        live.sort_unstable();
+        let Some(command) = op.command(&order_book, &live) else {
+            continue;
+        };
+        let seen = order_book.events().len();
+        order_book.process_command(command.clone());
+        let events = &order_book.events()[seen..];
+        let fail = |message: String| format!("step {}: {:?}: {}", step, command, message);
+
+        let expected = match command {
+            OrderCommand::New {
+                order_type,
+                side,
+                price,
+                qty,
+                ..
+            } => Some(reference.new_order(order_type, side, price, qty)),
+            OrderCommand::Modify {
+                id,
+                qty,
+                order_type,
+                ..
+            } => reference.modify(ids[&id], qty, order_type),
+            OrderCommand::Cancel { id, .. } => {
+                reference.cancel(ids[&id]);
+                None
+            }
+            _ => None,
+        };
+        let placed = events.iter().find_map(|event| match event {
+            OrderEvent::Placed { id, .. } => Some(*id),
+            _ => None,
+        });
+        let expected_fills = match (expected, placed) {
+            (Some((reference_id, fills)), Some(id)) => {
+                ids.insert(id, reference_id);
+                fills
+            }
+            (None, None) => Vec::new(),
+            (expected, placed) => {
+                return Err(fail(format!(
+                    "reference placed {:?}, engine placed {:?}",
+                    expected.map(|(id, _)| id),
+                    placed
+                )))
+            }
+        };
+        let fills: Vec<Fill> = events
+            .iter()
+            .filter_map(|event| match event {
+                OrderEvent::Trade(trade) => Some((
+                    trade.price,
+                    trade.qty,
+                    ids[&trade.maker_order_id],
+                    ids[&trade.taker_order_id],
+                )),
+                _ => None,
+            })
+            .collect();
+        if fills != expected_fills {
+            return Err(fail(format!(
+                "engine filled {:?}, reference filled {:?}",
+                fills, expected_fills
+            )));
+        }
+
+        for (side, levels) in [
+            (Side::Buy, &order_book.bids),
+            (Side::Sell, &order_book.asks),
+        ] {
+            let resting: Vec<RestingOrder> = levels
+                .iter()
+                .flat_map(|level| &level.orders)
+                .map(|order| RestingOrder {
+                    id: ids[&order.id],
+                    side: order.side,
+                    price: order.price,
+                    qty: order.remaining_qty,
+                })
+                .collect();
+            let expected = reference.resting(side);
+            if resting != expected {
+                return Err(fail(format!(
+                    "{:?} side rests {:?}, reference rests {:?}",
+                    side, resting, expected
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn matches_reference_book() {
+    for seed in 0..CASES {
+        let mut rng = Rng::new(seed);
+        let ops: Vec<Op> = (0..STEPS).map(|_| Op::random(&mut rng)).collect();
+        if let Err(message) = differ(&ops) {
+            panic!("seed {}: {}", seed, message);
+        }
+    }
+}
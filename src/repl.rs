@@ -0,0 +1,402 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Interactive command line for a single book.
+//!
+//! Each line is one command. Orders are submitted as participant 1 unless
+//! another is named with `as`:
+//!
+//! ```text
+//! buy|sell <qty> @ <price> [gtc|day|fak] [as <participant>]
+//! modify <id> <qty>
+//! cancel <id>
+//! quote <bid qty> @ <bid> <ask qty> @ <ask> [as <participant>]
+//! cross <qty> @ <price> <buyer> <seller>
+//! kill <participant> on|off
+//! halt
+//! resume [auction]
+//! bust <trade id> [restore]
+//! eod
+//! depth [levels]
+//! trades [count]
+//! help
+//! quit
+//! ```
+//!
+//! After a command, the events it produced are printed one per line.
+
+use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, ParticipantId, QuoteEntry, Side};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+buy|sell <qty> @ <price> [gtc|day|fak] [as <participant>]
+modify <id> <qty>
+cancel <id>
+quote <bid qty> @ <bid> <ask qty> @ <ask> [as <participant>]
+cross <qty> @ <price> <buyer> <seller>
+kill <participant> on|off
+halt
+resume [auction]
+bust <trade id> [restore]
+eod
+depth [levels]
+trades [count]
+quit
+";
+
+const DEFAULT_PARTICIPANT: ParticipantId = 1;
+
+/// Reads commands from `input` until it ends or `quit`, writing a prompt
+/// before each and the outcome after.
+pub fn run<R: BufRead, W: Write>(
+    order_book: &mut OrderBook,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    let mut lines = input.lines();
+    loop {
+        write!(output, "> ")?;
+        output.flush()?;
+        let Some(line) = lines.next() else {
+            return writeln!(output);
+        };
+        let line = line?;
+        match line.trim() {
+            "quit" | "exit" => return Ok(()),
+            line => output.write_all(execute(order_book, line).as_bytes())?,
+        }
+    }
+}
+
+/// Runs one line against the book and returns what to print.
+pub fn execute(order_book: &mut OrderBook, line: &str) -> String {
+    // Accept `10@122` as well as `10 @ 122`.
+    let line = line.replace('@', " @ ");
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.first().copied() {
+        None => String::new(),
+        Some("help") => HELP.to_string(),
+        Some("depth") => match count(words.get(1), 5) {
+            Ok(levels) => order_book.render(levels),
+            Err(e) => format!("error: {}\n", e),
+        },
+        Some("trades") => match count(words.get(1), 10) {
+            Ok(n) => trades(order_book, n),
+            Err(e) => format!("error: {}\n", e),
+        },
+        Some("eod") => {
+            let seen = order_book.events().len();
+            let summary = order_book.end_of_day();
+            let mut out = described(&order_book.events()[seen..]);
+            out.push_str(&format!(
+                "{} trades, volume {}\n",
+                summary.stats.trade_count, summary.stats.volume
+            ));
+            out
+        }
+        Some(_) => match parse(order_book, &words) {
+            Ok(command) => {
+                let seen = order_book.events().len();
+                order_book.process_command(command);
+                described(&order_book.events()[seen..])
+            }
+            Err(e) => format!("error: {}\n", e),
+        },
+    }
+}
+
+fn described(events: &[OrderEvent]) -> String {
+    let mut out = String::new();
+    for event in events {
+        out.push_str(&describe(event));
+        out.push('\n');
+    }
+    out
+}
+
+fn parse(order_book: &OrderBook, words: &[&str]) -> Result<OrderCommand, String> {
+    let order = |word: Option<&&str>| {
+        let id = number(word, "order id")?;
+        order_book
+            .find_order(id)
+            .ok_or_else(|| format!("order {} is not resting", id))
+    };
+    Ok(match words[0] {
+        "buy" | "sell" => {
+            let side = if words[0] == "buy" {
+                Side::Buy
+            } else {
+                Side::Sell
+            };
+            if words.get(2) != Some(&"@") {
+                return Err("expected <qty> @ <price>".to_string());
+            }
+            let mut order_type = OrderType::GoodTilCancel;
+            let mut participant = DEFAULT_PARTICIPANT;
+            let mut rest = words[4.min(words.len())..].iter();
+            while let Some(&word) = rest.next() {
+                match word {
+                    "gtc" => order_type = OrderType::GoodTilCancel,
+                    "day" => order_type = OrderType::Day,
+                    "fak" => order_type = OrderType::FillAndKill,
+                    "as" => participant = number(rest.next(), "participant")?,
+                    other => return Err(format!("unexpected {}", other)),
+                }
+            }
+            OrderCommand::New {
+                order_type,
+                side,
+                price: number(words.get(3), "price")?,
+                qty: number(words.get(1), "quantity")?,
+                participant,
+            }
+        }
+        "modify" => {
+            let order = order(words.get(1))?;
+            OrderCommand::Modify {
+                id: order.id,
+                side: order.side,
+                price: order.price,
+                qty: number(words.get(2), "quantity")?,
+                order_type: order.order_type,
+            }
+        }
+        "cancel" => {
+            let order = order(words.get(1))?;
+            OrderCommand::Cancel {
+                id: order.id,
+                side: order.side,
+                price: order.price,
+            }
+        }
+        "quote" => {
+            if words.get(2) != Some(&"@") || words.get(5) != Some(&"@") {
+                return Err("expected <bid qty> @ <bid> <ask qty> @ <ask>".to_string());
+            }
+            let participant = match &words[7.min(words.len())..] {
+                [] => DEFAULT_PARTICIPANT,
+                ["as", participant] => number(Some(participant), "participant")?,
+                [other, ..] => return Err(format!("unexpected {}", other)),
+            };
+            OrderCommand::MassQuote {
+                participant,
+                quotes: vec![QuoteEntry {
+                    symbol: order_book.symbol().to_string(),
+                    bid_price: number(words.get(3), "price")?,
+                    bid_qty: number(words.get(1), "quantity")?,
+                    ask_price: number(words.get(6), "price")?,
+                    ask_qty: number(words.get(4), "quantity")?,
+                }],
+            }
+        }
+        "cross" => {
+            if words.get(2) != Some(&"@") {
+                return Err("expected <qty> @ <price>".to_string());
+            }
+            OrderCommand::Cross {
+                buyer: number(words.get(4), "buyer")?,
+                seller: number(words.get(5), "seller")?,
+                price: number(words.get(3), "price")?,
+                qty: number(words.get(1), "quantity")?,
+            }
+        }
+        "kill" => OrderCommand::KillSwitch {
+            participant: number(words.get(1), "participant")?,
+            engage: match words.get(2).copied() {
+                Some("on") => true,
+                Some("off") => false,
+                _ => return Err("expected on or off".to_string()),
+            },
+        },
+        "halt" => OrderCommand::Halt,
+        "resume" => OrderCommand::Resume {
+            auction: words.get(1) == Some(&"auction"),
+        },
+        "bust" => OrderCommand::BustTrade {
+            trade_id: number(words.get(1), "trade id")?,
+            restore: words.get(2) == Some(&"restore"),
+        },
+        other => return Err(format!("unknown command {}; try help", other)),
+    })
+}
+
+fn number<T: std::str::FromStr>(word: Option<&&str>, what: &str) -> Result<T, String> {
+    let word = word.ok_or_else(|| format!("missing {}", what))?;
+    word.parse().map_err(|_| format!("bad {} {}", what, word))
+}
+
+fn count(word: Option<&&str>, default: usize) -> Result<usize, String> {
+    word.map_or(Ok(default), |_| number(word, "count"))
+}
+
+fn trades(order_book: &OrderBook, n: usize) -> String {
+    let mut out = String::new();
+    for trade in order_book.tape().last(n) {
+        out.push_str(&format!(
+            "trade {}: {} @ {}, maker {} taker {}\n",
+            trade.id, trade.qty, trade.price, trade.maker_order_id, trade.taker_order_id
+        ));
+    }
+    if out.is_empty() {
+        out.push_str("no trades\n");
+    }
+    out
+}
+
+fn describe(event: &OrderEvent) -> String {
+    let side = |side: &Side| match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    };
+    match event {
+        OrderEvent::Placed {
+            id,
+            participant,
+            side: s,
+            order_type,
+            price,
+            ..
+        } => format!(
+            "placed {}: {} {:?} @ {} for participant {}",
+            id,
+            side(s),
+            order_type,
+            price,
+            participant
+        ),
+        OrderEvent::Quoted {
+            id,
+            participant,
+            side: s,
+            price,
+            qty,
+            ..
+        } => format!(
+            "quoted {}: {} {} @ {} for participant {}",
+            id,
+            side(s),
+            qty,
+            price,
+            participant
+        ),
+        OrderEvent::Restored {
+            id,
+            participant,
+            side: s,
+            price,
+            qty,
+            ..
+        } => format!(
+            "restored {}: {} {} @ {} for participant {}",
+            id,
+            side(s),
+            qty,
+            price,
+            participant
+        ),
+        OrderEvent::Modified => "modified".to_string(),
+        OrderEvent::Canceled { id } => format!("canceled {}", id),
+        OrderEvent::PartiallyFilled { id, price, qty, .. } => {
+            format!("partially filled {}: {} @ {}", id, qty, price)
+        }
+        OrderEvent::Filled { id, price, .. } => format!("filled {} @ {}", id, price),
+        OrderEvent::Trade(trade) if trade.cross => format!(
+            "trade {}: {} @ {}, crossed by {} and {}",
+            trade.id, trade.qty, trade.price, trade.taker_participant, trade.maker_participant
+        ),
+        OrderEvent::Trade(trade) => format!(
+            "trade {}: {} @ {}, {} {} hit {}",
+            trade.id,
+            trade.qty,
+            trade.price,
+            side(&trade.aggressor_side),
+            trade.taker_order_id,
+            trade.maker_order_id
+        ),
+        OrderEvent::Rejected {
+            participant,
+            side: s,
+            price,
+            qty,
+            reason,
+        } => format!(
+            "rejected {} {} @ {} for participant {}: {:?}",
+            side(s),
+            qty,
+            price,
+            participant,
+            reason
+        ),
+        OrderEvent::KillSwitch {
+            participant,
+            engaged,
+        } => format!(
+            "kill switch {} for participant {}",
+            if *engaged { "engaged" } else { "released" },
+            participant
+        ),
+        OrderEvent::StatusChanged { status } => format!("status {:?}", status),
+        OrderEvent::Settled { price, .. } => format!("settled at {}", price),
+        OrderEvent::SessionEnded { .. } => "session ended".to_string(),
+        OrderEvent::ProtectionTriggered {
+            participant,
+            trigger,
+            ..
+        } => format!(
+            "{:?} protection triggered for participant {}",
+            trigger, participant
+        ),
+        OrderEvent::TradeBust { trade, restored } => format!(
+            "busted trade {}{}",
+            trade.id,
+            if *restored { ", quantity restored" } else { "" }
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::OrderBook;
+
+    #[test]
+    fn prints_events_and_queries() {
+        let input = "\
+sell 5 @ 122
+sell 3@123 as 2
+buy 7 @ 123 fak
+bogus
+depth
+cancel 2
+trades
+quit
+buy 1 @ 1
+";
+        let mut order_book = OrderBook::new();
+        let mut output = Vec::new();
+        run(&mut order_book, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let expected = "\
+> placed 1: sell GoodTilCancel @ 122 for participant 1
+> placed 2: sell GoodTilCancel @ 123 for participant 2
+> placed 3: buy FillAndKill @ 123 for participant 1
+trade 1: 5 @ 122, buy 3 hit 1
+filled 1 @ 122
+partially filled 3: 5 @ 122
+trade 2: 2 @ 123, buy 3 hit 2
+partially filled 2: 2 @ 123
+filled 3 @ 123
+> error: unknown command bogus; try help
+> Open
+         price        qty orders
+ask        123          1      1
+--------------------------------
+> canceled 2
+> trade 1: 5 @ 122, maker 1 taker 3
+trade 2: 2 @ 123, maker 2 taker 3
+> ";
+        assert_eq!(output, expected);
+        assert!(order_book.bids.is_empty());
+    }
+}
@@ -0,0 +1,515 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Replaying recorded order flow through a book.
+//!
+//! Three sources are understood:
+//!
+//! - an audit log, of which only the `command` records are used;
+//! - CSV, one order action per row:
+//!
+//!   ```text
+//!   # time_ns,action,ref,side,price,qty,participant
+//!   1725192000000000000,gtc,1,buy,122,10,7
+//!   1725192000000100000,fak,2,sell,121,4,8
+//!   1725192000000200000,modify,1,,123,6,
+//!   1725192000000300000,cancel,1,,,,
+//!   ```
+//!
+//! - a NASDAQ TotalView-ITCH 5.0 file; see [`itch`].
+//!
+//! CSV and ITCH name orders by their own references, which are mapped to
+//! whatever ids the book assigns as the replay goes. Audit commands already
+//! carry book ids, and replaying the whole log into a fresh book assigns the
+//! same ones again.
+//!
+//! The book's clock follows the recorded times, so its timestamps and timers
+//! match the original session whether the replay runs flat out or paced to
+//! the recording.
+
+use crate::clock::simulated::SimulatedClock;
+use crate::sink::jsonl::parse_rfc3339;
+use crate::{
+    OrderBook, OrderCommand, OrderEvent, OrderId, OrderType, ParticipantId, Side, Timestamp,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub mod itch;
+
+/// Taker for [`Action::Execute`]: an execution in the source that the replay
+/// has to recreate by trading against the order.
+pub const EXECUTING_PARTICIPANT: ParticipantId = 0;
+
+/// One recorded action and when it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub at: Timestamp,
+    pub action: Action,
+}
+
+/// What a source asks of the book. `reference` is the source's own name for
+/// an order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    New {
+        reference: u64,
+        order_type: OrderType,
+        side: Side,
+        price: i32,
+        qty: u32,
+        participant: ParticipantId,
+    },
+    /// Cancels the order and places it again at `price` and `qty`, after
+    /// which it is known as `new_reference`.
+    Replace {
+        reference: u64,
+        new_reference: u64,
+        price: i32,
+        qty: u32,
+    },
+    /// Takes `qty` off the order. Modifies lose time priority in this book,
+    /// so a reduced order goes to the back of its level.
+    Reduce {
+        reference: u64,
+        qty: u32,
+    },
+    Cancel {
+        reference: u64,
+    },
+    /// The order traded `qty`. Replayed as an immediate order from
+    /// [`EXECUTING_PARTICIPANT`] against its price level, which fills the
+    /// order itself as long as it is still at the front.
+    Execute {
+        reference: u64,
+        qty: u32,
+    },
+    /// Applied as is.
+    Command(OrderCommand),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Audit,
+    Csv,
+    Itch,
+}
+
+impl Format {
+    /// Guesses from the extension: `.csv`, `.itch`, and anything else is
+    /// taken to be an audit log.
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => Format::Csv,
+            Some("itch") => Format::Itch,
+            _ => Format::Audit,
+        }
+    }
+}
+
+/// How quickly to replay.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pace {
+    /// As fast as the book will go.
+    #[default]
+    FullSpeed,
+    /// Waits out the gaps between recorded times.
+    Recorded,
+    /// Waits out the recorded gaps shrunk by this factor, so `10` runs ten
+    /// times faster than the recording. A factor of zero doesn't wait.
+    Accelerated(u32),
+}
+
+impl Pace {
+    /// How far into the replay a record `recorded` after the first is due,
+    /// or `None` to apply it straight away.
+    fn due(self, recorded: Duration) -> Option<Duration> {
+        match self {
+            Pace::FullSpeed => None,
+            Pace::Recorded => Some(recorded),
+            Pace::Accelerated(factor) => recorded.checked_div(factor),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayReport {
+    pub records: usize,
+    /// Records naming an order the replay never saw placed.
+    pub unknown_orders: usize,
+    pub trades: usize,
+    pub elapsed: Duration,
+    /// From the first record to the last.
+    pub recorded: Duration,
+}
+
+impl ReplayReport {
+    pub fn records_per_sec(&self) -> f64 {
+        self.records as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+pub type Records = Box<dyn Iterator<Item = io::Result<Record>>>;
+
+/// Opens `path` as a source of `format`. `symbol` picks one instrument out of
+/// an ITCH file, which usually holds many; other formats ignore it.
+pub fn open(path: &Path, format: Format, symbol: Option<&str>) -> io::Result<Records> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(match format {
+        Format::Audit => Box::new(read_audit(reader)),
+        Format::Csv => Box::new(read_csv(reader)),
+        Format::Itch => Box::new(itch::ItchReader::new(reader, symbol)),
+    })
+}
+
+/// The commands in an audit log, at the times they were recorded.
+pub fn read_audit<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<Record>> {
+    reader.lines().enumerate().filter_map(|(line_no, line)| {
+        let record = line.and_then(|line| {
+            let invalid = |reason: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", line_no + 1, reason),
+                )
+            };
+            let record: Value = serde_json::from_str(&line).map_err(|_| invalid("not JSON"))?;
+            if record["kind"] != "command" {
+                return Ok(None);
+            }
+            let at = record["time"]
+                .as_str()
+                .and_then(parse_rfc3339)
+                .ok_or_else(|| invalid("bad time"))?;
+            let command = serde_json::from_value(record["body"]["command"].clone())
+                .map_err(|_| invalid("bad command"))?;
+            Ok(Some(Record {
+                at: Timestamp::from_nanos(at),
+                action: Action::Command(command),
+            }))
+        });
+        record.transpose()
+    })
+}
+
+/// Rows in the layout shown in the module docs. Blank lines and lines
+/// starting with `#` are skipped.
+pub fn read_csv<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<Record>> {
+    reader
+        .lines()
+        .enumerate()
+        .filter_map(|(line_no, line)| match line {
+            Ok(line) if line.trim().is_empty() || line.starts_with('#') => None,
+            Ok(line) => Some(parse_csv_row(&line).map_err(|reason| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", line_no + 1, reason),
+                )
+            })),
+            Err(e) => Some(Err(e)),
+        })
+}
+
+fn parse_csv_row(line: &str) -> Result<Record, String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let field = |i: usize, name: &str| match fields.get(i) {
+        Some(&value) if !value.is_empty() => Ok(value),
+        _ => Err(format!("missing {}", name)),
+    };
+    fn number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, String> {
+        value.parse().map_err(|_| format!("bad {} {}", name, value))
+    }
+    let at = Timestamp::from_nanos(number(field(0, "time")?, "time")?);
+    let reference = number(field(2, "ref")?, "ref")?;
+    let action = match field(1, "action")? {
+        action @ ("gtc" | "day" | "fak") => Action::New {
+            reference,
+            order_type: match action {
+                "gtc" => OrderType::GoodTilCancel,
+                "day" => OrderType::Day,
+                _ => OrderType::FillAndKill,
+            },
+            side: match field(3, "side")? {
+                "buy" => Side::Buy,
+                "sell" => Side::Sell,
+                other => return Err(format!("bad side {}", other)),
+            },
+            price: number(field(4, "price")?, "price")?,
+            qty: number(field(5, "qty")?, "qty")?,
+            participant: number(field(6, "participant")?, "participant")?,
+        },
+        "modify" => Action::Replace {
+            reference,
+            new_reference: reference,
+            price: number(field(4, "price")?, "price")?,
+            qty: number(field(5, "qty")?, "qty")?,
+        },
+        "cancel" => Action::Cancel { reference },
+        other => return Err(format!("unknown action {}", other)),
+    };
+    Ok(Record { at, action })
+}
+
+/// Feeds `records` through `order_book`, which should start out empty and is
+/// given a clock that follows the recorded times. Records must be in time
+/// order; the clock never goes back.
+pub fn run(
+    order_book: &mut OrderBook,
+    records: impl IntoIterator<Item = io::Result<Record>>,
+    pace: Pace,
+) -> io::Result<ReplayReport> {
+    let mut report = ReplayReport::default();
+    let mut replayer = Replayer::default();
+    let mut clock: Option<(SimulatedClock, Timestamp)> = None;
+    let trades_before = order_book.trades().len();
+    let start = Instant::now();
+    for record in records {
+        let record = record?;
+        let (clock, first) = clock.get_or_insert_with(|| {
+            let clock = SimulatedClock::starting_at(record.at);
+            order_book.set_clock(clock.clock());
+            (clock, record.at)
+        });
+        let recorded = record.at - *first;
+        if let Some(due) = pace.due(recorded) {
+            std::thread::sleep(due.saturating_sub(start.elapsed()));
+        }
+        clock.advance_to(order_book, record.at);
+        if !replayer.apply(order_book, record.action) {
+            report.unknown_orders += 1;
+        }
+        report.records += 1;
+        report.recorded = recorded;
+    }
+    report.elapsed = start.elapsed();
+    report.trades = order_book.trades().len() - trades_before;
+    Ok(report)
+}
+
+/// Maps source references to the orders they became.
+#[derive(Default)]
+pub(crate) struct Replayer {
+    orders: HashMap<u64, (OrderId, Side, i32)>,
+}
+
+impl Replayer {
+    /// Returns false if the action names an order that was never placed.
+    pub(crate) fn apply(&mut self, order_book: &mut OrderBook, action: Action) -> bool {
+        let command = match action {
+            Action::New {
+                reference,
+                order_type,
+                side,
+                price,
+                qty,
+                participant,
+            } => {
+                let command = OrderCommand::New {
+                    order_type,
+                    side,
+                    price,
+                    qty,
+                    participant,
+                };
+                self.place(order_book, reference, command);
+                return true;
+            }
+            Action::Command(command) => {
+                order_book.process_command(command);
+                return true;
+            }
+            Action::Replace {
+                reference,
+                new_reference,
+                price,
+                qty,
+            } => {
+                let Some((id, side, old_price)) = self.orders.remove(&reference) else {
+                    return false;
+                };
+                // A modify here can't move the order to another price.
+                let Some(order) = order_book.find_order(id) else {
+                    return true;
+                };
+                let command = OrderCommand::New {
+                    order_type: order.order_type,
+                    side,
+                    price,
+                    qty,
+                    participant: order.participant,
+                };
+                order_book.process_command(OrderCommand::Cancel {
+                    id,
+                    side,
+                    price: old_price,
+                });
+                self.place(order_book, new_reference, command);
+                return true;
+            }
+            Action::Reduce { reference, qty } => {
+                let Some(&(id, side, price)) = self.orders.get(&reference) else {
+                    return false;
+                };
+                let Some(order) = order_book.find_order(id) else {
+                    return true;
+                };
+                match order.remaining_qty.saturating_sub(qty) {
+                    0 => OrderCommand::Cancel { id, side, price },
+                    qty => {
+                        let command = OrderCommand::Modify {
+                            id,
+                            side,
+                            price,
+                            qty,
+                            order_type: order.order_type,
+                        };
+                        self.orders.remove(&reference);
+                        self.place(order_book, reference, command);
+                        return true;
+                    }
+                }
+            }
+            Action::Cancel { reference } => {
+                let Some((id, side, price)) = self.orders.remove(&reference) else {
+                    return false;
+                };
+                OrderCommand::Cancel { id, side, price }
+            }
+            Action::Execute { reference, qty } => {
+                let Some(&(_, side, price)) = self.orders.get(&reference) else {
+                    return false;
+                };
+                OrderCommand::New {
+                    order_type: OrderType::FillAndKill,
+                    side: match side {
+                        Side::Buy => Side::Sell,
+                        Side::Sell => Side::Buy,
+                    },
+                    price,
+                    qty,
+                    participant: EXECUTING_PARTICIPANT,
+                }
+            }
+        };
+        order_book.process_command(command);
+        true
+    }
+
+    /// Processes a command that places an order and files the order under
+    /// `reference`.
+    fn place(&mut self, order_book: &mut OrderBook, reference: u64, command: OrderCommand) {
+        let seen = order_book.events().len();
+        order_book.process_command(command);
+        let placed = order_book.events()[seen..]
+            .iter()
+            .find_map(|event| match *event {
+                OrderEvent::Placed {
+                    id, side, price, ..
+                } => Some((id, side, price)),
+                _ => None,
+            });
+        if let Some(placed) = placed {
+            self.orders.insert(reference, placed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_audit, read_csv, run, Pace};
+    use crate::audit::{process, AuditLog};
+    use crate::{OrderBook, OrderCommand, OrderType, Side, Timestamp};
+    use std::time::Duration;
+
+    #[test]
+    fn replays_csv_by_reference() {
+        let csv = "\
+# time_ns,action,ref,side,price,qty,participant
+1725192000000000000,gtc,10,sell,122,5,1
+1725192000000000000,gtc,11,sell,123,5,1
+
+1725192000500000000,modify,10,,121,4,
+1725192001000000000,fak,12,buy,122,6,2
+1725192002000000000,cancel,11,,,,
+1725192003000000000,cancel,99,,,,
+";
+        let mut order_book = OrderBook::new();
+        let report = run(&mut order_book, read_csv(csv.as_bytes()), Pace::FullSpeed).unwrap();
+        assert_eq!(report.records, 6);
+        assert_eq!(report.unknown_orders, 1);
+        assert_eq!(report.trades, 1);
+        assert_eq!(report.recorded, Duration::from_secs(3));
+        // The modify moved order 10 to 121, where the buy found it.
+        let trade = order_book.trades()[0];
+        assert_eq!((trade.price, trade.qty, trade.maker_order_id), (121, 4, 3));
+        assert_eq!(
+            trade.timestamp,
+            Timestamp::from_nanos(1_725_192_001_000_000_000)
+        );
+        assert!(order_book.bids.is_empty() && order_book.asks.is_empty());
+
+        let bad = "1725192000000000000,gtc,1,up,122,5,1\n";
+        let err = run(
+            &mut OrderBook::new(),
+            read_csv(bad.as_bytes()),
+            Pace::FullSpeed,
+        );
+        assert_eq!(err.unwrap_err().to_string(), "line 1: bad side up");
+    }
+
+    #[test]
+    fn accelerates_the_recorded_gaps() {
+        let csv = "\
+1725192000000000000,gtc,1,sell,122,5,1
+1725192000400000000,fak,2,buy,122,5,2
+";
+        let report = run(
+            &mut OrderBook::new(),
+            read_csv(csv.as_bytes()),
+            Pace::Accelerated(10),
+        )
+        .unwrap();
+        assert_eq!(report.recorded, Duration::from_millis(400));
+        assert!(report.elapsed >= Duration::from_millis(40));
+        assert!(report.elapsed < Duration::from_millis(400));
+        assert_eq!(Pace::Accelerated(0).due(report.recorded), None);
+    }
+
+    #[test]
+    fn replaying_an_audit_log_rebuilds_the_book() {
+        let mut original = OrderBook::with_symbol("ABC");
+        let mut log = AuditLog::new(Vec::new());
+        for (side, price, participant) in [
+            (Side::Sell, 101, 1),
+            (Side::Sell, 100, 1),
+            (Side::Buy, 100, 2),
+            (Side::Buy, 99, 2),
+        ] {
+            let command = OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty: 3,
+                participant,
+            };
+            process(&mut original, &mut log, command).unwrap();
+        }
+        let command = OrderCommand::Cancel {
+            id: 1,
+            side: Side::Sell,
+            price: 101,
+        };
+        process(&mut original, &mut log, command).unwrap();
+
+        let log = log.into_inner();
+        let mut replayed = OrderBook::with_symbol("ABC");
+        let report = run(&mut replayed, read_audit(&log[..]), Pace::FullSpeed).unwrap();
+        assert_eq!((report.records, report.trades), (5, 1));
+        assert_eq!(replayed.depth(10), original.depth(10));
+        assert_eq!(replayed.to_string(), original.to_string());
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! NASDAQ TotalView-ITCH 5.0.
+//!
+//! Files hold one message after another, each preceded by its length as a
+//! big-endian `u16`. Only the messages that change the order book are read:
+//! adds (`A`, `F`), executions (`E`, `C`), partial cancels (`X`), deletes
+//! (`D`) and replaces (`U`). Everything else is skipped.
+//!
+//! ITCH stamps messages with nanoseconds since midnight, and those are the
+//! times records get. Prices keep ITCH's four implied decimal places, so
+//! $122.50 becomes 1225000. Anonymous adds are placed as
+//! [`ANONYMOUS_PARTICIPANT`]; attributed ones as their MPID read as a
+//! big-endian `u32`.
+
+use super::{Action, Record};
+use crate::{OrderType, ParticipantId, Side, Timestamp};
+use std::collections::HashMap;
+use std::io::{self, Read};
+
+pub const ANONYMOUS_PARTICIPANT: ParticipantId = 1;
+
+/// Length of the type, stock locate, tracking number and timestamp that
+/// start every message.
+const HEADER_LEN: usize = 11;
+
+pub struct ItchReader<R: Read> {
+    reader: R,
+    /// Eight characters, space padded, as ITCH has them.
+    symbol: Option<[u8; 8]>,
+    /// Shares left on each order read so far, so messages for other symbols
+    /// and for orders already gone can be told apart.
+    open: HashMap<u64, u32>,
+}
+
+impl<R: Read> ItchReader<R> {
+    /// With a `symbol`, only that stock's orders are read.
+    pub fn new(reader: R, symbol: Option<&str>) -> Self {
+        ItchReader {
+            reader,
+            symbol: symbol.map(|symbol| {
+                let mut padded = [b' '; 8];
+                for (slot, byte) in padded.iter_mut().zip(symbol.bytes()) {
+                    *slot = byte;
+                }
+                padded
+            }),
+            open: HashMap::new(),
+        }
+    }
+
+    /// The next message, or `None` at a clean end of file.
+    fn read_message(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut len = [0; 2];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let mut message = vec![0; usize::from(u16::from_be_bytes(len))];
+        self.reader.read_exact(&mut message)?;
+        Ok(Some(message))
+    }
+
+    fn parse(&mut self, message: &[u8]) -> io::Result<Option<Record>> {
+        let expected = match message.first() {
+            Some(b'A') => 36,
+            Some(b'F') => 40,
+            Some(b'E') => 31,
+            Some(b'C') => 36,
+            Some(b'X') => 23,
+            Some(b'D') => 19,
+            Some(b'U') => 35,
+            _ => return Ok(None),
+        };
+        if message.len() < expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("short ITCH {} message", message[0] as char),
+            ));
+        }
+        let field = |at: usize, len: usize| {
+            message[at..at + len]
+                .iter()
+                .fold(0_u64, |value, &byte| value << 8 | u64::from(byte))
+        };
+        let at = Timestamp::from_nanos(field(5, 6));
+        let reference = field(HEADER_LEN, 8);
+        let action = match message[0] {
+            kind @ (b'A' | b'F') => {
+                if self
+                    .symbol
+                    .is_some_and(|symbol| message[24..32] != symbol[..])
+                {
+                    return Ok(None);
+                }
+                let qty = field(20, 4) as u32;
+                self.open.insert(reference, qty);
+                Action::New {
+                    reference,
+                    order_type: OrderType::GoodTilCancel,
+                    side: if message[19] == b'B' {
+                        Side::Buy
+                    } else {
+                        Side::Sell
+                    },
+                    price: field(32, 4) as i32,
+                    qty,
+                    participant: match kind {
+                        b'F' => field(36, 4) as ParticipantId,
+                        _ => ANONYMOUS_PARTICIPANT,
+                    },
+                }
+            }
+            kind @ (b'E' | b'C' | b'X') => {
+                let qty = field(19, 4) as u32;
+                let Some(left) = self.open.get_mut(&reference) else {
+                    return Ok(None);
+                };
+                *left = left.saturating_sub(qty);
+                if *left == 0 {
+                    self.open.remove(&reference);
+                }
+                match kind {
+                    b'X' => Action::Reduce { reference, qty },
+                    _ => Action::Execute { reference, qty },
+                }
+            }
+            b'D' => {
+                if self.open.remove(&reference).is_none() {
+                    return Ok(None);
+                }
+                Action::Cancel { reference }
+            }
+            _ => {
+                if self.open.remove(&reference).is_none() {
+                    return Ok(None);
+                }
+                let new_reference = field(19, 8);
+                let qty = field(27, 4) as u32;
+                self.open.insert(new_reference, qty);
+                Action::Replace {
+                    reference,
+                    new_reference,
+                    price: field(31, 4) as i32,
+                    qty,
+                }
+            }
+        };
+        Ok(Some(Record { at, action }))
+    }
+}
+
+impl<R: Read> Iterator for ItchReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let message = match self.read_message() {
+                Ok(Some(message)) => message,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+            match self.parse(&message) {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ItchReader;
+    use crate::replay::{run, Action, Pace};
+    use crate::{OrderBook, Side, Timestamp};
+
+    /// Frames a message with the common header and `body`.
+    fn message(out: &mut Vec<u8>, kind: u8, nanos: u64, body: &[&[u8]]) {
+        let mut message = vec![kind, 0, 1, 0, 0];
+        message.extend_from_slice(&nanos.to_be_bytes()[2..]);
+        for field in body {
+            message.extend_from_slice(field);
+        }
+        out.extend_from_slice(&(message.len() as u16).to_be_bytes());
+        out.extend_from_slice(&message);
+    }
+
+    fn add(out: &mut Vec<u8>, nanos: u64, reference: u64, side: u8, qty: u32, stock: &[u8; 8]) {
+        let price = 1_220_000_u32.to_be_bytes();
+        let body: &[&[u8]] = &[
+            &reference.to_be_bytes(),
+            &[side],
+            &qty.to_be_bytes(),
+            stock,
+            &price,
+        ];
+        message(out, b'A', nanos, body);
+    }
+
+    #[test]
+    fn reads_one_symbols_book() {
+        let mut file = Vec::new();
+        add(&mut file, 1_000, 1, b'S', 100, b"AAPL    ");
+        add(&mut file, 2_000, 2, b'S', 100, b"MSFT    ");
+        // System event: skipped.
+        message(&mut file, b'S', 2_500, &[b"O"]);
+        message(
+            &mut file,
+            b'E',
+            3_000,
+            &[&1_u64.to_be_bytes(), &40_u32.to_be_bytes(), &[0; 8]],
+        );
+        message(
+            &mut file,
+            b'X',
+            4_000,
+            &[&1_u64.to_be_bytes(), &10_u32.to_be_bytes()],
+        );
+        message(&mut file, b'D', 5_000, &[&2_u64.to_be_bytes()]);
+        message(
+            &mut file,
+            b'U',
+            6_000,
+            &[
+                &1_u64.to_be_bytes(),
+                &7_u64.to_be_bytes(),
+                &30_u32.to_be_bytes(),
+                &1_230_000_u32.to_be_bytes(),
+            ],
+        );
+
+        let records: Vec<_> = ItchReader::new(&file[..], Some("AAPL"))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let actions: Vec<_> = records.iter().map(|record| &record.action).collect();
+        assert_eq!(records.len(), 4);
+        assert!(matches!(
+            actions[0],
+            Action::New {
+                reference: 1,
+                side: Side::Sell,
+                price: 1_220_000,
+                qty: 100,
+                ..
+            }
+        ));
+        assert_eq!(
+            *actions[1],
+            Action::Execute {
+                reference: 1,
+                qty: 40
+            }
+        );
+        assert_eq!(
+            *actions[2],
+            Action::Reduce {
+                reference: 1,
+                qty: 10
+            }
+        );
+        assert_eq!(
+            *actions[3],
+            Action::Replace {
+                reference: 1,
+                new_reference: 7,
+                price: 1_230_000,
+                qty: 30
+            }
+        );
+        assert_eq!(records[3].at, Timestamp::from_nanos(6_000));
+
+        let mut order_book = OrderBook::with_symbol("AAPL");
+        let report = run(
+            &mut order_book,
+            records.into_iter().map(Ok),
+            Pace::FullSpeed,
+        )
+        .unwrap();
+        assert_eq!((report.trades, report.unknown_orders), (1, 0));
+        assert_eq!(order_book.best_ask(), Some(1_230_000));
+        assert_eq!(order_book.asks[0].total_qty(), 30);
+
+        // A file cut off mid-message is an error, not an early end.
+        let truncated = ItchReader::new(&file[..file.len() - 3], Some("AAPL"));
+        assert!(truncated.last().unwrap().is_err());
+    }
+}
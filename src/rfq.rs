@@ -0,0 +1,435 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Request for quote.
+//!
+//! A participant asks for a price on a size through an [`RfqDesk`]. Market
+//! makers answer with quotes that rest in a book of their own for that
+//! request, and the requester can execute against the best of them until
+//! the request's window closes. Nothing touches the central book; the desk
+//! records every step as an [`RfqEvent`], and executions carry an ordinary
+//! [`Trade`] with the quote as maker and the request as taker.
+
+use crate::{Currency, ParticipantId, Side, Timestamp, Trade};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+pub type RfqId = u64;
+pub type QuoteId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RfqReject {
+    UnknownRfq,
+    /// The request's window has closed.
+    Expired,
+    /// Sizes must be positive.
+    ZeroQty,
+    /// Requesters can't quote their own requests.
+    OwnRequest,
+    /// Only the requester can execute.
+    NotRequester,
+    /// No quote to execute against.
+    NoQuotes,
+    UnknownQuote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RfqEvent {
+    /// Broadcast to market makers.
+    Requested {
+        rfq: RfqId,
+        requester: ParticipantId,
+        side: Side,
+        qty: u32,
+        expires_at: Timestamp,
+        timestamp: Timestamp,
+    },
+    Quoted {
+        rfq: RfqId,
+        quote: QuoteId,
+        maker: ParticipantId,
+        price: i32,
+        qty: u32,
+        timestamp: Timestamp,
+    },
+    Withdrawn {
+        rfq: RfqId,
+        quote: QuoteId,
+        timestamp: Timestamp,
+    },
+    Executed {
+        rfq: RfqId,
+        quote: QuoteId,
+        trade: Trade,
+    },
+    /// The window closed without an execution.
+    Expired { rfq: RfqId, timestamp: Timestamp },
+    Rejected {
+        rfq: RfqId,
+        participant: ParticipantId,
+        reason: RfqReject,
+        timestamp: Timestamp,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RfqQuote {
+    pub id: QuoteId,
+    pub maker: ParticipantId,
+    pub price: i32,
+    pub qty: u32,
+    pub timestamp: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rfq {
+    pub id: RfqId,
+    pub requester: ParticipantId,
+    pub side: Side,
+    pub qty: u32,
+    pub expires_at: Timestamp,
+    /// Live responses, in arrival order.
+    pub quotes: Vec<RfqQuote>,
+}
+
+impl Rfq {
+    /// The lowest offer for a buyer, the highest bid for a seller, the
+    /// earliest among equals.
+    pub fn best(&self) -> Option<&RfqQuote> {
+        self.quotes.iter().reduce(|best, quote| {
+            let better = match self.side {
+                Side::Buy => quote.price < best.price,
+                Side::Sell => quote.price > best.price,
+            };
+            if better {
+                quote
+            } else {
+                best
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RfqDesk {
+    window: Duration,
+    open: BTreeMap<RfqId, Rfq>,
+    events: Vec<RfqEvent>,
+    next_rfq: RfqId,
+    next_quote: QuoteId,
+    next_trade: usize,
+    currency: Currency,
+}
+
+impl RfqDesk {
+    /// Requests stay open for `window` after they are made.
+    pub fn new(window: Duration) -> Self {
+        RfqDesk {
+            window,
+            open: BTreeMap::new(),
+            events: Vec::new(),
+            next_rfq: 1,
+            next_quote: 1,
+            next_trade: 1,
+            currency: Currency::default(),
+        }
+    }
+
+    /// Sets the currency executions are priced in, USD by default.
+    pub fn set_currency(&mut self, currency: Currency) {
+        self.currency = currency;
+    }
+
+    pub fn events(&self) -> &[RfqEvent] {
+        &self.events
+    }
+
+    pub fn get(&self, rfq: RfqId) -> Option<&Rfq> {
+        self.open.get(&rfq)
+    }
+
+    /// Open requests, oldest first.
+    pub fn open(&self) -> impl Iterator<Item = &Rfq> {
+        self.open.values()
+    }
+
+    pub fn request(
+        &mut self,
+        requester: ParticipantId,
+        side: Side,
+        qty: u32,
+        now: Timestamp,
+    ) -> Result<RfqId, RfqReject> {
+        let id = self.next_rfq;
+        if qty == 0 {
+            return Err(self.reject(id, requester, RfqReject::ZeroQty, now));
+        }
+        self.next_rfq += 1;
+        let expires_at = now + self.window;
+        self.open.insert(
+            id,
+            Rfq {
+                id,
+                requester,
+                side,
+                qty,
+                expires_at,
+                quotes: Vec::new(),
+            },
+        );
+        self.events.push(RfqEvent::Requested {
+            rfq: id,
+            requester,
+            side,
+            qty,
+            expires_at,
+            timestamp: now,
+        });
+        Ok(id)
+    }
+
+    /// Answers a request. A maker's new quote replaces their last one.
+    pub fn quote(
+        &mut self,
+        rfq: RfqId,
+        maker: ParticipantId,
+        price: i32,
+        qty: u32,
+        now: Timestamp,
+    ) -> Result<QuoteId, RfqReject> {
+        self.live(rfq, maker, now)?;
+        let reason = if self.open[&rfq].requester == maker {
+            Some(RfqReject::OwnRequest)
+        } else if qty == 0 {
+            Some(RfqReject::ZeroQty)
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            return Err(self.reject(rfq, maker, reason, now));
+        }
+        let request = self.open.get_mut(&rfq).expect("checked live");
+        if let Some(old) = request.quotes.iter().position(|q| q.maker == maker) {
+            let old = request.quotes.remove(old);
+            self.events.push(RfqEvent::Withdrawn {
+                rfq,
+                quote: old.id,
+                timestamp: now,
+            });
+        }
+        let id = self.next_quote;
+        self.next_quote += 1;
+        request.quotes.push(RfqQuote {
+            id,
+            maker,
+            price,
+            qty,
+            timestamp: now,
+        });
+        self.events.push(RfqEvent::Quoted {
+            rfq,
+            quote: id,
+            maker,
+            price,
+            qty,
+            timestamp: now,
+        });
+        Ok(id)
+    }
+
+    pub fn withdraw(
+        &mut self,
+        rfq: RfqId,
+        maker: ParticipantId,
+        now: Timestamp,
+    ) -> Result<(), RfqReject> {
+        self.live(rfq, maker, now)?;
+        let quotes = &mut self.open.get_mut(&rfq).expect("checked live").quotes;
+        let Some(pos) = quotes.iter().position(|q| q.maker == maker) else {
+            return Err(self.reject(rfq, maker, RfqReject::UnknownQuote, now));
+        };
+        let quote = quotes.remove(pos);
+        self.events.push(RfqEvent::Withdrawn {
+            rfq,
+            quote: quote.id,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    /// Trades the requested size, or as much of it as the best quote is
+    /// good for, at that quote's price. That closes the request.
+    pub fn execute(
+        &mut self,
+        rfq: RfqId,
+        requester: ParticipantId,
+        now: Timestamp,
+    ) -> Result<Trade, RfqReject> {
+        self.live(rfq, requester, now)?;
+        let request = &self.open[&rfq];
+        if request.requester != requester {
+            return Err(self.reject(rfq, requester, RfqReject::NotRequester, now));
+        }
+        let Some(&best) = request.best() else {
+            return Err(self.reject(rfq, requester, RfqReject::NoQuotes, now));
+        };
+        let trade = Trade {
+            id: self.next_trade,
+            price: best.price,
+            qty: request.qty.min(best.qty),
+            aggressor_side: request.side,
+            maker_order_id: best.id,
+            taker_order_id: rfq,
+            maker_participant: best.maker,
+            taker_participant: requester,
+            timestamp: now,
+            maker_fee: 0,
+            taker_fee: 0,
+            cross: false,
+            dark: false,
+            currency: self.currency,
+        };
+        self.next_trade += 1;
+        self.open.remove(&rfq);
+        self.events.push(RfqEvent::Executed {
+            rfq,
+            quote: best.id,
+            trade,
+        });
+        Ok(trade)
+    }
+
+    /// Closes every request whose window has passed by `now`.
+    pub fn expire(&mut self, now: Timestamp) {
+        let expired: Vec<RfqId> = self
+            .open
+            .values()
+            .filter(|rfq| rfq.expires_at <= now)
+            .map(|rfq| rfq.id)
+            .collect();
+        for rfq in expired {
+            self.open.remove(&rfq);
+            self.events.push(RfqEvent::Expired {
+                rfq,
+                timestamp: now,
+            });
+        }
+    }
+
+    /// Checks the request is still open at `now`. One found past its window
+    /// is expired on the spot.
+    fn live(
+        &mut self,
+        rfq: RfqId,
+        participant: ParticipantId,
+        now: Timestamp,
+    ) -> Result<(), RfqReject> {
+        match self.open.get(&rfq) {
+            None => Err(self.reject(rfq, participant, RfqReject::UnknownRfq, now)),
+            Some(request) if request.expires_at <= now => {
+                self.open.remove(&rfq);
+                self.events.push(RfqEvent::Expired {
+                    rfq,
+                    timestamp: now,
+                });
+                Err(self.reject(rfq, participant, RfqReject::Expired, now))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    fn reject(
+        &mut self,
+        rfq: RfqId,
+        participant: ParticipantId,
+        reason: RfqReject,
+        now: Timestamp,
+    ) -> RfqReject {
+        self.events.push(RfqEvent::Rejected {
+            rfq,
+            participant,
+            reason,
+            timestamp: now,
+        });
+        reason
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RfqDesk, RfqEvent, RfqReject};
+    use crate::{Side, Timestamp};
+    use std::time::Duration;
+
+    fn at(secs: u64) -> Timestamp {
+        Timestamp::from_nanos(secs * 1_000_000_000)
+    }
+
+    #[test]
+    fn executes_against_the_best_quote() {
+        let mut desk = RfqDesk::new(Duration::from_secs(30));
+        let rfq = desk.request(1, Side::Buy, 500, at(0)).unwrap();
+        desk.quote(rfq, 2, 101, 500, at(1)).unwrap();
+        let best = desk.quote(rfq, 3, 100, 300, at(2)).unwrap();
+        desk.quote(rfq, 4, 100, 500, at(3)).unwrap();
+        assert_eq!(
+            desk.quote(rfq, 1, 99, 500, at(3)),
+            Err(RfqReject::OwnRequest)
+        );
+        assert_eq!(desk.execute(rfq, 2, at(4)), Err(RfqReject::NotRequester));
+        // A maker requoting goes to the back among equal prices.
+        desk.quote(rfq, 3, 100, 500, at(5)).unwrap();
+        assert_eq!(desk.get(rfq).unwrap().best().unwrap().maker, 4);
+        desk.quote(rfq, 3, 99, 300, at(6)).unwrap();
+
+        let trade = desk.execute(rfq, 1, at(7)).unwrap();
+        assert_ne!(trade.maker_order_id, best);
+        assert_eq!(
+            (
+                trade.price,
+                trade.qty,
+                trade.maker_participant,
+                trade.taker_participant
+            ),
+            (99, 300, 3, 1)
+        );
+        assert!(desk.get(rfq).is_none());
+        assert_eq!(desk.execute(rfq, 1, at(8)), Err(RfqReject::UnknownRfq));
+        assert!(matches!(
+            desk.events()[0],
+            RfqEvent::Requested { qty: 500, .. }
+        ));
+        let json = serde_json::to_string(desk.events()).unwrap();
+        let events: Vec<RfqEvent> = serde_json::from_str(&json).unwrap();
+        assert_eq!(events, desk.events());
+    }
+
+    #[test]
+    fn requests_expire_after_the_window() {
+        let mut desk = RfqDesk::new(Duration::from_secs(10));
+        let first = desk.request(1, Side::Sell, 100, at(0)).unwrap();
+        let second = desk.request(2, Side::Sell, 100, at(5)).unwrap();
+        desk.quote(first, 3, 100, 100, at(9)).unwrap();
+        assert_eq!(
+            desk.quote(first, 4, 101, 100, at(10)),
+            Err(RfqReject::Expired)
+        );
+        assert_eq!(desk.execute(first, 1, at(11)), Err(RfqReject::UnknownRfq));
+
+        desk.expire(at(15));
+        assert!(desk.get(second).is_none());
+        assert_eq!(
+            desk.events().last(),
+            Some(&RfqEvent::Expired {
+                rfq: second,
+                timestamp: at(15)
+            })
+        );
+        assert_eq!(
+            desk.request(1, Side::Buy, 0, at(16)),
+            Err(RfqReject::ZeroQty)
+        );
+    }
+}
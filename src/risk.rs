@@ -0,0 +1,739 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Pre-trade risk checks.
+//!
+//! Every new order passes through [`RiskChecks`] before it reaches the book.
+//! An order that fails a check never rests or trades; the book emits a
+//! `Rejected` event carrying the [`RejectReason`] instead. Limits are set per
+//! participant, falling back to a book-wide default. The [`PriceCollar`]
+//! applies to the whole book. Every command but a cancel, not just new
+//! orders, is also subject to the participant's [`throttle::RateLimit`].
+//! Participants whose kill switch is engaged are blocked from entering
+//! orders altogether. Credit limits cap the participant's
+//! [`credit::Exposure`] including the new order. Market makers can also have
+//! their quotes pulled after a burst of fills; see [`protection`].
+
+use crate::positions::Position;
+use crate::{ParticipantId, Price, Side, Timestamp};
+use credit::{CreditLedger, Exposure};
+use protection::Protections;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use throttle::RateLimiter;
+
+pub mod credit;
+pub mod protection;
+pub mod throttle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum RejectReason {
+    /// Quantity above [`RiskLimits::max_order_qty`].
+    MaxOrderQty,
+    /// `|price| * qty` above [`RiskLimits::max_order_notional`].
+    MaxOrderNotional,
+    /// Priced outside the [`PriceCollar`].
+    PriceCollar,
+    /// The participant is sending commands faster than its rate limit.
+    Throttled,
+    /// The participant's kill switch is engaged.
+    Blocked,
+    /// Would take the participant's exposure past a credit limit.
+    CreditLimit,
+    /// A new order reused the client order id of one still live.
+    DuplicateClientOrderId,
+    /// A modify or cancel named a client order id with no live order.
+    UnknownClientOrderId,
+    /// The participant already has [`RiskLimits::max_open_orders`] resting.
+    MaxOpenOrders,
+    /// The book is halted and not accepting orders.
+    Halted,
+    /// A mass quote's bid was at or above its ask.
+    CrossedQuote,
+    /// Less than a round lot, where the book rejects odd lots; see
+    /// [`odd_lots`](crate::odd_lots).
+    OddLot,
+    /// A cross smaller than the block threshold; see
+    /// [`crosses`](crate::crosses).
+    BelowBlockSize,
+}
+
+impl RejectReason {
+    const ALL: [RejectReason; 13] = [
+        RejectReason::MaxOrderQty,
+        RejectReason::MaxOrderNotional,
+        RejectReason::PriceCollar,
+        RejectReason::Throttled,
+        RejectReason::Blocked,
+        RejectReason::CreditLimit,
+        RejectReason::DuplicateClientOrderId,
+        RejectReason::UnknownClientOrderId,
+        RejectReason::MaxOpenOrders,
+        RejectReason::Halted,
+        RejectReason::CrossedQuote,
+        RejectReason::OddLot,
+        RejectReason::BelowBlockSize,
+    ];
+
+    /// Stable numeric code used by the binary codecs.
+    pub fn code(self) -> u8 {
+        match self {
+            RejectReason::MaxOrderQty => 1,
+            RejectReason::MaxOrderNotional => 2,
+            RejectReason::PriceCollar => 3,
+            RejectReason::Throttled => 4,
+            RejectReason::Blocked => 5,
+            RejectReason::CreditLimit => 6,
+            RejectReason::DuplicateClientOrderId => 7,
+            RejectReason::UnknownClientOrderId => 8,
+            RejectReason::MaxOpenOrders => 9,
+            RejectReason::Halted => 10,
+            RejectReason::CrossedQuote => 11,
+            RejectReason::OddLot => 12,
+            RejectReason::BelowBlockSize => 13,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<RejectReason> {
+        RejectReason::ALL
+            .into_iter()
+            .find(|reason| reason.code() == code)
+    }
+}
+
+/// `|price| * qty`.
+pub fn notional<P: Price>(price: P, qty: u32) -> u64 {
+    price
+        .to_ticks()
+        .unsigned_abs()
+        .saturating_mul(u64::from(qty))
+}
+
+/// Per-participant limits. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RiskLimits {
+    pub max_order_qty: Option<u32>,
+    pub max_order_notional: Option<u64>,
+    /// Cap on [`Exposure::gross`].
+    pub max_gross_exposure: Option<u64>,
+    /// Cap on [`Exposure::net`].
+    pub max_net_exposure: Option<u64>,
+    /// Resting orders the participant may have at once.
+    pub max_open_orders: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum CollarAction {
+    #[default]
+    Reject,
+    /// Log the breach and accept the order anyway.
+    Warn,
+}
+
+/// Band around the book's reference price outside which an order is taken
+/// to be a fat-finger error. Either bound may be left unset; an order is
+/// outside the collar if it breaches any bound that is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PriceCollar {
+    /// Distance from the reference in basis points of the reference price.
+    pub max_deviation_bps: Option<u32>,
+    pub max_deviation_ticks: Option<u32>,
+    pub action: CollarAction,
+}
+
+impl PriceCollar {
+    pub fn breached<P: Price>(&self, price: P, reference: P) -> bool {
+        let reference = reference.to_ticks();
+        let distance = (i128::from(price.to_ticks()) - i128::from(reference)).unsigned_abs();
+        let too_many_ticks = self
+            .max_deviation_ticks
+            .is_some_and(|max| distance > u128::from(max));
+        let too_far = self.max_deviation_bps.is_some_and(|bps| {
+            distance * 10_000 > u128::from(bps) * u128::from(reference.unsigned_abs())
+        });
+        too_many_ticks || too_far
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RiskChecks {
+    default: RiskLimits,
+    participants: HashMap<ParticipantId, RiskLimits>,
+    collar: PriceCollar,
+    throttle: RateLimiter,
+    blocked: HashSet<ParticipantId>,
+    credit: CreditLedger,
+    protection: Protections,
+}
+
+impl RiskChecks {
+    pub fn new(default: RiskLimits) -> Self {
+        RiskChecks {
+            default,
+            participants: HashMap::new(),
+            collar: PriceCollar::default(),
+            throttle: RateLimiter::default(),
+            blocked: HashSet::new(),
+            credit: CreditLedger::default(),
+            protection: Protections::default(),
+        }
+    }
+
+    pub fn collar(&self) -> PriceCollar {
+        self.collar
+    }
+
+    pub fn set_collar(&mut self, collar: PriceCollar) {
+        self.collar = collar;
+    }
+
+    pub fn set_default(&mut self, limits: RiskLimits) {
+        self.default = limits;
+    }
+
+    /// Overrides the default limits for one participant.
+    pub fn set_limits(&mut self, participant: ParticipantId, limits: RiskLimits) {
+        self.participants.insert(participant, limits);
+    }
+
+    /// Puts the participant back on the default limits.
+    pub fn clear_limits(&mut self, participant: ParticipantId) {
+        self.participants.remove(&participant);
+    }
+
+    pub fn limits(&self, participant: ParticipantId) -> RiskLimits {
+        self.participants
+            .get(&participant)
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    pub fn throttle(&self) -> &RateLimiter {
+        &self.throttle
+    }
+
+    pub fn throttle_mut(&mut self) -> &mut RateLimiter {
+        &mut self.throttle
+    }
+
+    pub fn protection(&self) -> &Protections {
+        &self.protection
+    }
+
+    pub fn protection_mut(&mut self) -> &mut Protections {
+        &mut self.protection
+    }
+
+    pub fn block(&mut self, participant: ParticipantId) {
+        self.blocked.insert(participant);
+    }
+
+    pub fn unblock(&mut self, participant: ParticipantId) {
+        self.blocked.remove(&participant);
+    }
+
+    pub fn is_blocked(&self, participant: ParticipantId) -> bool {
+        self.blocked.contains(&participant)
+    }
+
+    /// Charges one message against the participant's rate limit.
+    pub fn admit(
+        &mut self,
+        participant: ParticipantId,
+        now: Timestamp,
+    ) -> Result<(), RejectReason> {
+        if self.throttle.try_acquire(participant, now) {
+            Ok(())
+        } else {
+            Err(RejectReason::Throttled)
+        }
+    }
+
+    pub fn check_new<P: Price>(
+        &self,
+        participant: ParticipantId,
+        price: P,
+        qty: u32,
+    ) -> Result<(), RejectReason> {
+        if self.is_blocked(participant) {
+            return Err(RejectReason::Blocked);
+        }
+        let limits = self.limits(participant);
+        if limits.max_order_qty.is_some_and(|max| qty > max) {
+            return Err(RejectReason::MaxOrderQty);
+        }
+        if limits
+            .max_order_notional
+            .is_some_and(|max| notional(price, qty) > max)
+        {
+            return Err(RejectReason::MaxOrderNotional);
+        }
+        Ok(())
+    }
+
+    /// `open` is how many orders the participant has resting, not counting
+    /// any the new order replaces.
+    pub fn check_open_orders(
+        &self,
+        participant: ParticipantId,
+        open: usize,
+    ) -> Result<(), RejectReason> {
+        if self
+            .limits(participant)
+            .max_open_orders
+            .is_some_and(|max| open >= max)
+        {
+            return Err(RejectReason::MaxOpenOrders);
+        }
+        Ok(())
+    }
+
+    pub fn credit_mut(&mut self) -> &mut CreditLedger {
+        &mut self.credit
+    }
+
+    pub fn exposure(&self, participant: ParticipantId, position: &Position) -> Exposure {
+        let (open_buy, open_sell) = self.credit.open(participant);
+        Exposure::new(open_buy, open_sell, position)
+    }
+
+    /// Checks the participant's exposure with the new order resting in
+    /// full. `replaced` is the notional of an order on the same side that
+    /// the new one replaces, which no longer counts.
+    pub fn check_credit(
+        &self,
+        participant: ParticipantId,
+        position: &Position,
+        side: Side,
+        notional: u64,
+        replaced: u64,
+    ) -> Result<(), RejectReason> {
+        let limits = self.limits(participant);
+        let mut exposure = self.exposure(participant, position);
+        match side {
+            Side::Buy => exposure.open_buy = exposure.open_buy.saturating_sub(replaced),
+            Side::Sell => exposure.open_sell = exposure.open_sell.saturating_sub(replaced),
+        }
+        let exposure = exposure.with_order(side, notional);
+        let breached = limits
+            .max_gross_exposure
+            .is_some_and(|max| exposure.gross() > max)
+            || limits
+                .max_net_exposure
+                .is_some_and(|max| exposure.net() > max);
+        if breached {
+            return Err(RejectReason::CreditLimit);
+        }
+        Ok(())
+    }
+
+    /// Checks `price` against the collar. With no reference price (nothing
+    /// has traded and the book is empty) every price is accepted.
+    pub fn check_price<P: Price>(
+        &self,
+        price: P,
+        reference: Option<P>,
+    ) -> Result<(), RejectReason> {
+        let Some(reference) = reference else {
+            return Ok(());
+        };
+        if !self.collar.breached(price, reference) {
+            return Ok(());
+        }
+        match self.collar.action {
+            CollarAction::Reject => Err(RejectReason::PriceCollar),
+            CollarAction::Warn => {
+                hot_trace!(
+                    warn,
+                    "order price {} outside collar around reference {}",
+                    price,
+                    reference
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::throttle::RateLimit;
+    use super::{CollarAction, PriceCollar, RejectReason, RiskChecks, RiskLimits};
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+
+    #[test]
+    fn per_participant_limits_override_default() {
+        let mut checks = RiskChecks::new(RiskLimits {
+            max_order_qty: Some(100),
+            max_order_notional: Some(10_000),
+            ..RiskLimits::default()
+        });
+        checks.set_limits(
+            7,
+            RiskLimits {
+                max_order_notional: Some(50_000),
+                ..RiskLimits::default()
+            },
+        );
+        assert_eq!(checks.check_new(1, 100, 100), Ok(()));
+        assert_eq!(checks.check_new(1, 10, 101), Err(RejectReason::MaxOrderQty));
+        assert_eq!(
+            checks.check_new(1, -101, 100),
+            Err(RejectReason::MaxOrderNotional)
+        );
+        assert_eq!(checks.check_new(7, 100, 500), Ok(()));
+        checks.clear_limits(7);
+        assert_eq!(
+            checks.check_new(7, 100, 500),
+            Err(RejectReason::MaxOrderQty)
+        );
+
+        for reason in RejectReason::ALL {
+            assert_eq!(RejectReason::from_code(reason.code()), Some(reason));
+        }
+    }
+
+    #[test]
+    fn collar_bounds() {
+        let ticks = PriceCollar {
+            max_deviation_ticks: Some(5),
+            ..PriceCollar::default()
+        };
+        assert!(!ticks.breached(105, 100));
+        assert!(ticks.breached(94, 100));
+        let bps = PriceCollar {
+            max_deviation_bps: Some(1_000),
+            ..PriceCollar::default()
+        };
+        assert!(!bps.breached(110, 100));
+        assert!(!bps.breached(-110, -100));
+        assert!(bps.breached(111, 100));
+        assert!(!PriceCollar::default().breached(i32::MAX, i32::MIN));
+
+        let mut checks = RiskChecks::default();
+        checks.set_collar(ticks);
+        assert_eq!(checks.check_price(94, None), Ok(()));
+        assert_eq!(
+            checks.check_price(94, Some(100)),
+            Err(RejectReason::PriceCollar)
+        );
+        checks.set_collar(PriceCollar {
+            action: CollarAction::Warn,
+            ..ticks
+        });
+        assert_eq!(checks.check_price(94, Some(100)), Ok(()));
+    }
+
+    #[test]
+    fn book_collars_against_reference_price() {
+        let mut order_book = OrderBook::new();
+        let new_order = |side, price| OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price,
+            qty: 1,
+            participant: 1,
+        };
+        let last_rejected = |order_book: &OrderBook| {
+            matches!(
+                order_book.events().last(),
+                Some(OrderEvent::Rejected {
+                    reason: RejectReason::PriceCollar,
+                    ..
+                })
+            )
+        };
+        order_book.process_command(new_order(Side::Buy, 100));
+        order_book.process_command(new_order(Side::Sell, 200));
+        order_book.risk_mut().set_collar(PriceCollar {
+            max_deviation_ticks: Some(30),
+            ..PriceCollar::default()
+        });
+
+        // No trades yet, so the reference is the mid.
+        assert_eq!(order_book.reference_price(), Some(150));
+        order_book.process_command(new_order(Side::Sell, 100));
+        assert!(last_rejected(&order_book));
+        order_book.process_command(new_order(Side::Sell, 140));
+        order_book.process_command(new_order(Side::Buy, 140));
+        assert_eq!(order_book.reference_price(), Some(140));
+        order_book.process_command(new_order(Side::Sell, 109));
+        assert!(last_rejected(&order_book));
+        order_book.process_command(new_order(Side::Sell, 111));
+        assert!(!last_rejected(&order_book));
+
+        // Modifies are collared too, and a rejected one leaves the order.
+        let modify = |id, price| OrderCommand::Modify {
+            id,
+            price,
+            side: Side::Sell,
+            qty: 2,
+            order_type: OrderType::GoodTilCancel,
+        };
+        order_book.process_command(modify(2, 200));
+        assert!(last_rejected(&order_book));
+        assert_eq!(order_book.asks.last().unwrap().orders[0].id, 2);
+        let id = order_book.asks[0].orders[0].id;
+        order_book.process_command(modify(id, 111));
+        assert!(!last_rejected(&order_book));
+        assert_eq!(order_book.asks[0].orders[0].remaining_qty, 2);
+    }
+
+    #[test]
+    fn book_rejects_orders_over_limits() {
+        let mut order_book = OrderBook::new();
+        order_book.risk_mut().set_default(RiskLimits {
+            max_order_qty: Some(10),
+            ..RiskLimits::default()
+        });
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 100,
+            qty: 11,
+            participant: 3,
+        });
+        assert!(order_book.bids.is_empty());
+        assert_eq!(
+            order_book.events(),
+            [OrderEvent::Rejected {
+                participant: 3,
+                side: Side::Buy,
+                price: 100,
+                qty: 11,
+                reason: RejectReason::MaxOrderQty,
+            }]
+        );
+
+        // A modify that would breach a limit leaves the original order alone.
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 100,
+            qty: 10,
+            participant: 3,
+        });
+        let id = order_book.bids[0].orders[0].id;
+        order_book.process_command(OrderCommand::Modify {
+            id,
+            price: 100,
+            side: Side::Buy,
+            qty: 20,
+            order_type: OrderType::GoodTilCancel,
+        });
+        assert_eq!(
+            order_book.find_order(id).map(|order| order.remaining_qty),
+            Some(10)
+        );
+        assert!(matches!(
+            order_book.events().last(),
+            Some(OrderEvent::Rejected {
+                reason: RejectReason::MaxOrderQty,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn book_throttles_participant_commands() {
+        let mut order_book = OrderBook::new();
+        order_book.risk_mut().throttle_mut().set_limit(
+            1,
+            RateLimit {
+                per_second: 1,
+                burst: 2,
+            },
+        );
+        for price in [100, 101, 102] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side: Side::Buy,
+                price,
+                qty: 1,
+                participant: 1,
+            });
+        }
+        assert_eq!(order_book.bids.len(), 2);
+        let id = order_book.bids[0].orders[0].id;
+        order_book.process_command(OrderCommand::Modify {
+            id,
+            side: Side::Buy,
+            price: 101,
+            qty: 2,
+            order_type: OrderType::GoodTilCancel,
+        });
+        assert_eq!(order_book.bids[0].total_qty(), 1);
+        assert!(matches!(
+            order_book.events().last(),
+            Some(OrderEvent::Rejected {
+                participant: 1,
+                reason: RejectReason::Throttled,
+                ..
+            })
+        ));
+        assert_eq!(order_book.risk().throttle().throttled(1), 2);
+
+        // Cancels are not charged, so the orders can still come down with
+        // the bucket empty.
+        order_book.process_command(OrderCommand::Cancel {
+            id,
+            side: Side::Buy,
+            price: 101,
+        });
+        assert_eq!(order_book.bids.len(), 1);
+        assert_eq!(order_book.risk().throttle().throttled(1), 2);
+        assert_eq!(order_book.cancel_all(1), 1);
+        assert!(order_book.bids.is_empty());
+    }
+
+    #[test]
+    fn kill_switch_cancels_and_blocks() {
+        let mut order_book = OrderBook::new();
+        let new_order = |participant| OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Sell,
+            price: 100,
+            qty: 1,
+            participant,
+        };
+        for participant in [1, 1, 2] {
+            order_book.process_command(new_order(participant));
+        }
+        let first_event = order_book.events().len();
+        order_book.process_command(OrderCommand::KillSwitch {
+            participant: 1,
+            engage: true,
+        });
+        let events = &order_book.events()[first_event..];
+        assert_eq!(
+            events[0],
+            OrderEvent::KillSwitch {
+                participant: 1,
+                engaged: true,
+            }
+        );
+        assert_eq!(events.len(), 3);
+        assert_eq!(order_book.asks[0].orders.len(), 1);
+
+        order_book.process_command(new_order(1));
+        assert!(matches!(
+            order_book.events().last(),
+            Some(OrderEvent::Rejected {
+                reason: RejectReason::Blocked,
+                ..
+            })
+        ));
+        order_book.process_command(OrderCommand::KillSwitch {
+            participant: 1,
+            engage: false,
+        });
+        order_book.process_command(new_order(1));
+        assert_eq!(order_book.asks[0].orders.len(), 2);
+    }
+
+    #[test]
+    fn credit_limits_track_resting_orders_and_positions() {
+        let mut order_book = OrderBook::new();
+        order_book.risk_mut().set_limits(
+            1,
+            RiskLimits {
+                max_gross_exposure: Some(1_000),
+                max_net_exposure: Some(600),
+                ..RiskLimits::default()
+            },
+        );
+        let new_order = |side, price, qty, participant| OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price,
+            qty,
+            participant,
+        };
+        let last_rejected = |order_book: &OrderBook| {
+            matches!(
+                order_book.events().last(),
+                Some(OrderEvent::Rejected {
+                    reason: RejectReason::CreditLimit,
+                    ..
+                })
+            )
+        };
+        order_book.process_command(new_order(Side::Buy, 100, 5, 1));
+        order_book.process_command(new_order(Side::Buy, 100, 2, 1));
+        assert!(last_rejected(&order_book));
+        // Selling offsets on a net basis but still adds to gross.
+        order_book.process_command(new_order(Side::Sell, 110, 4, 1));
+        assert_eq!(order_book.exposure(1).gross(), 940);
+        order_book.process_command(new_order(Side::Sell, 110, 1, 1));
+        assert!(last_rejected(&order_book));
+
+        // A fill turns resting exposure into position; a cancel releases it.
+        order_book.process_command(new_order(Side::Sell, 100, 5, 2));
+        let exposure = order_book.exposure(1);
+        assert_eq!((exposure.open_buy, exposure.position), (0, 500));
+        let id = order_book.asks[0].orders[0].id;
+        order_book.process_command(OrderCommand::Cancel {
+            id,
+            side: Side::Sell,
+            price: 110,
+        });
+        assert_eq!(order_book.exposure(1).gross(), 500);
+    }
+
+    #[test]
+    fn caps_open_orders() {
+        let mut order_book = OrderBook::new();
+        order_book.risk_mut().set_default(RiskLimits {
+            max_open_orders: Some(2),
+            ..RiskLimits::default()
+        });
+        let new_order = |side, price, participant| OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price,
+            qty: 1,
+            participant,
+        };
+        let last_rejected = |order_book: &OrderBook| {
+            matches!(
+                order_book.events().last(),
+                Some(OrderEvent::Rejected {
+                    reason: RejectReason::MaxOpenOrders,
+                    ..
+                })
+            )
+        };
+        order_book.process_command(new_order(Side::Buy, 100, 1));
+        order_book.process_command(new_order(Side::Sell, 110, 1));
+        order_book.process_command(new_order(Side::Buy, 101, 1));
+        assert!(last_rejected(&order_book));
+        assert_eq!(order_book.open_orders(1), 2);
+
+        // Modifying an order replaces it rather than adding another.
+        let id = order_book.bids[0].orders[0].id;
+        order_book.process_command(OrderCommand::Modify {
+            id,
+            price: 100,
+            side: Side::Buy,
+            qty: 2,
+            order_type: OrderType::GoodTilCancel,
+        });
+        assert!(!last_rejected(&order_book));
+        assert_eq!(order_book.bids[0].orders[0].remaining_qty, 2);
+        let replacement = order_book.bids[0].orders[0].id;
+        assert!(!order_book.owns(1, id));
+        assert!(order_book.owns(1, replacement));
+        assert!(!order_book.owns(2, replacement));
+
+        // Fills free up room.
+        order_book.process_command(new_order(Side::Buy, 110, 2));
+        assert_eq!(order_book.open_orders(1), 1);
+        order_book.process_command(new_order(Side::Buy, 101, 1));
+        assert!(!last_rejected(&order_book));
+        assert_eq!(order_book.cancel_all(1), 2);
+        assert_eq!(order_book.open_orders(1), 0);
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Credit exposure per participant.
+//!
+//! Exposure counts resting orders at their limit price and the position at
+//! its average cost. The [`CreditLedger`] keeps the resting order notional up
+//! to date as orders rest, fill and cancel, so checking a new order does not
+//! need to walk the book.
+
+use super::notional;
+use crate::positions::Position;
+use crate::{ParticipantId, Price, Side};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Exposure {
+    pub open_buy: u64,
+    pub open_sell: u64,
+    /// Position at average cost; positive when long.
+    pub position: i64,
+}
+
+impl Exposure {
+    pub fn new(open_buy: u64, open_sell: u64, position: &Position) -> Exposure {
+        Exposure {
+            open_buy,
+            open_sell,
+            position: (position.net_qty as f64 * position.avg_price).round() as i64,
+        }
+    }
+
+    /// Everything at risk regardless of direction.
+    pub fn gross(&self) -> u64 {
+        self.open_buy + self.open_sell + self.position.unsigned_abs()
+    }
+
+    /// The larger net position reachable if every resting order on one
+    /// side were filled.
+    pub fn net(&self) -> u64 {
+        let position = i128::from(self.position);
+        let long = position + i128::from(self.open_buy);
+        let short = i128::from(self.open_sell) - position;
+        long.max(short).max(0) as u64
+    }
+
+    /// Exposure with one more resting order.
+    pub fn with_order(mut self, side: Side, notional: u64) -> Exposure {
+        match side {
+            Side::Buy => self.open_buy += notional,
+            Side::Sell => self.open_sell += notional,
+        }
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CreditLedger {
+    /// Resting buy and sell notional.
+    open: HashMap<ParticipantId, (u64, u64)>,
+}
+
+impl CreditLedger {
+    pub fn rested<P: Price>(&mut self, participant: ParticipantId, side: Side, price: P, qty: u32) {
+        let (buy, sell) = self.open.entry(participant).or_default();
+        match side {
+            Side::Buy => *buy += notional(price, qty),
+            Side::Sell => *sell += notional(price, qty),
+        }
+    }
+
+    /// Releases resting quantity that filled or was canceled.
+    pub fn released<P: Price>(
+        &mut self,
+        participant: ParticipantId,
+        side: Side,
+        price: P,
+        qty: u32,
+    ) {
+        let Some((buy, sell)) = self.open.get_mut(&participant) else {
+            return;
+        };
+        match side {
+            Side::Buy => *buy = buy.saturating_sub(notional(price, qty)),
+            Side::Sell => *sell = sell.saturating_sub(notional(price, qty)),
+        }
+        if (*buy, *sell) == (0, 0) {
+            self.open.remove(&participant);
+        }
+    }
+
+    pub fn open(&self, participant: ParticipantId) -> (u64, u64) {
+        self.open.get(&participant).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CreditLedger, Exposure};
+    use crate::Side;
+
+    #[test]
+    fn gross_and_net() {
+        let exposure = Exposure {
+            open_buy: 300,
+            open_sell: 500,
+            position: 200,
+        };
+        assert_eq!(exposure.gross(), 1_000);
+        assert_eq!(exposure.net(), 500);
+        assert_eq!(exposure.with_order(Side::Sell, 100).net(), 500);
+        assert_eq!(exposure.with_order(Side::Buy, 100).net(), 600);
+        assert_eq!(Exposure::default().net(), 0);
+
+        let mut ledger = CreditLedger::default();
+        ledger.rested(1, Side::Buy, 100, 3);
+        ledger.rested(1, Side::Sell, -10, 2);
+        assert_eq!(ledger.open(1), (300, 20));
+        ledger.released(1, Side::Buy, 100, 3);
+        ledger.released(1, Side::Sell, -10, 2);
+        assert_eq!(ledger, CreditLedger::default());
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Market maker protections.
+//!
+//! A market maker quoting many books can be swept across all of them faster
+//! than it can react. Protections count the fills on a participant's mass
+//! quotes over a rolling window: how many there were, how much quantity
+//! traded, and the net delta bought less sold. When any count reaches its
+//! limit the book pulls the participant's remaining quotes and emits
+//! `ProtectionTriggered`. The window then starts over.
+
+use crate::{ParticipantId, Side, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Limits for one participant; `None` means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ProtectionLimits {
+    /// How far back fills are counted.
+    pub window: Duration,
+    pub max_fills: Option<u32>,
+    pub max_qty: Option<u64>,
+    /// On the absolute net quantity, bought less sold.
+    pub max_delta: Option<u64>,
+}
+
+impl Default for ProtectionLimits {
+    fn default() -> Self {
+        ProtectionLimits {
+            window: Duration::from_secs(1),
+            max_fills: None,
+            max_qty: None,
+            max_delta: None,
+        }
+    }
+}
+
+/// The count that reached its limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ProtectionTrigger {
+    Fills,
+    Quantity,
+    Delta,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fill {
+    at: Timestamp,
+    side: Side,
+    qty: u32,
+}
+
+/// Rolling fill counts for participants with limits.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Protections {
+    limits: HashMap<ParticipantId, ProtectionLimits>,
+    fills: HashMap<ParticipantId, VecDeque<Fill>>,
+    tripped: Vec<(ParticipantId, ProtectionTrigger)>,
+}
+
+impl Protections {
+    /// Protects the participant's quotes, or stops with `None`.
+    pub fn set_limits(&mut self, participant: ParticipantId, limits: Option<ProtectionLimits>) {
+        match limits {
+            Some(limits) => {
+                self.limits.insert(participant, limits);
+            }
+            None => {
+                self.limits.remove(&participant);
+                self.fills.remove(&participant);
+            }
+        }
+    }
+
+    pub fn limits(&self, participant: ParticipantId) -> Option<ProtectionLimits> {
+        self.limits.get(&participant).copied()
+    }
+
+    /// Counts a fill on one of the participant's quotes, noting the
+    /// participant as tripped if that reaches a limit.
+    pub fn record(&mut self, participant: ParticipantId, side: Side, qty: u32, at: Timestamp) {
+        let Some(limits) = self.limits.get(&participant) else {
+            return;
+        };
+        let fills = self.fills.entry(participant).or_default();
+        fills.push_back(Fill { at, side, qty });
+        while fills
+            .front()
+            .is_some_and(|fill| at - fill.at >= limits.window)
+        {
+            fills.pop_front();
+        }
+        let qty: u64 = fills.iter().map(|fill| u64::from(fill.qty)).sum();
+        let delta: i64 = fills
+            .iter()
+            .map(|fill| match fill.side {
+                Side::Buy => i64::from(fill.qty),
+                Side::Sell => -i64::from(fill.qty),
+            })
+            .sum();
+        let reached = |limit: Option<u64>, count: u64| limit.is_some_and(|limit| count >= limit);
+        let trigger = if reached(limits.max_fills.map(u64::from), fills.len() as u64) {
+            ProtectionTrigger::Fills
+        } else if reached(limits.max_qty, qty) {
+            ProtectionTrigger::Quantity
+        } else if reached(limits.max_delta, delta.unsigned_abs()) {
+            ProtectionTrigger::Delta
+        } else {
+            return;
+        };
+        fills.clear();
+        self.tripped.push((participant, trigger));
+    }
+
+    /// Participants tripped since the last call, in the order they tripped.
+    pub fn take_tripped(&mut self) -> Vec<(ParticipantId, ProtectionTrigger)> {
+        std::mem::take(&mut self.tripped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProtectionLimits, ProtectionTrigger, Protections};
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, QuoteEntry, Side, Timestamp};
+    use std::time::Duration;
+
+    #[test]
+    fn trips_within_the_window() {
+        let at = |ms: u64| Timestamp::from_nanos(ms * 1_000_000);
+        let mut protections = Protections::default();
+        protections.set_limits(
+            1,
+            Some(ProtectionLimits {
+                window: Duration::from_millis(100),
+                max_fills: Some(4),
+                max_delta: Some(15),
+                ..ProtectionLimits::default()
+            }),
+        );
+        protections.record(1, Side::Buy, 10, at(0));
+        protections.record(1, Side::Sell, 10, at(50));
+        // The first fill has aged out, so this is the second.
+        protections.record(1, Side::Buy, 10, at(100));
+        protections.record(2, Side::Buy, 100, at(100));
+        assert!(protections.take_tripped().is_empty());
+
+        protections.record(1, Side::Buy, 20, at(120));
+        assert_eq!(protections.take_tripped(), [(1, ProtectionTrigger::Delta)]);
+        protections.record(1, Side::Buy, 10, at(130));
+        assert!(protections.take_tripped().is_empty());
+    }
+
+    #[test]
+    fn pulls_quotes_when_tripped() {
+        let mut order_book = OrderBook::new();
+        order_book.risk_mut().protection_mut().set_limits(
+            1,
+            Some(ProtectionLimits {
+                max_qty: Some(8),
+                ..ProtectionLimits::default()
+            }),
+        );
+        order_book.process_command(OrderCommand::MassQuote {
+            participant: 1,
+            quotes: vec![QuoteEntry {
+                bid_price: 99,
+                bid_qty: 10,
+                ask_price: 101,
+                ask_qty: 10,
+                ..QuoteEntry::default()
+            }],
+        });
+        let sell = |qty| OrderCommand::New {
+            order_type: OrderType::FillAndKill,
+            side: Side::Sell,
+            price: 99,
+            qty,
+            participant: 2,
+        };
+        order_book.process_command(sell(5));
+        assert!(order_book.quoted(1, Side::Sell).is_some());
+        order_book.process_command(sell(3));
+        assert!(order_book.bids.is_empty() && order_book.asks.is_empty());
+        assert!(order_book.events().iter().any(|event| matches!(
+            event,
+            OrderEvent::ProtectionTriggered {
+                participant: 1,
+                trigger: ProtectionTrigger::Quantity,
+                ..
+            }
+        )));
+        assert_eq!(order_book.position(1).net_qty, 8);
+    }
+}
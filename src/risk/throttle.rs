@@ -0,0 +1,158 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Per-participant message rate limiting.
+//!
+//! Each participant gets a token bucket holding up to `burst` tokens and
+//! refilling at `per_second`. Every command but a cancel costs one token; a
+//! command that finds the bucket empty is throttled. Cancels are free so a
+//! participant who has hit the limit can still take their orders down. Tokens are tracked in billionths so
+//! refill stays exact at nanosecond resolution.
+
+use crate::{ParticipantId, Timestamp};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const UNITS_PER_TOKEN: u128 = 1_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct RateLimit {
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    fn capacity(self) -> u128 {
+        u128::from(self.burst) * UNITS_PER_TOKEN
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TokenBucket {
+    units: u128,
+    refilled_at: Timestamp,
+}
+
+impl TokenBucket {
+    fn full(limit: RateLimit, now: Timestamp) -> TokenBucket {
+        TokenBucket {
+            units: limit.capacity(),
+            refilled_at: now,
+        }
+    }
+
+    fn try_take(&mut self, limit: RateLimit, now: Timestamp) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_nanos();
+        self.units = (self.units + elapsed * u128::from(limit.per_second)).min(limit.capacity());
+        self.refilled_at = self.refilled_at.max(now);
+        if self.units < UNITS_PER_TOKEN {
+            return false;
+        }
+        self.units -= UNITS_PER_TOKEN;
+        true
+    }
+}
+
+/// Participants without a limit of their own use the default; with no
+/// default they are not limited at all.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RateLimiter {
+    default: Option<RateLimit>,
+    limits: HashMap<ParticipantId, RateLimit>,
+    buckets: HashMap<ParticipantId, TokenBucket>,
+    throttled: HashMap<ParticipantId, u64>,
+}
+
+impl RateLimiter {
+    pub fn new(default: Option<RateLimit>) -> Self {
+        RateLimiter {
+            default,
+            ..RateLimiter::default()
+        }
+    }
+
+    pub fn set_default(&mut self, limit: Option<RateLimit>) {
+        self.default = limit;
+        self.buckets.clear();
+    }
+
+    pub fn set_limit(&mut self, participant: ParticipantId, limit: RateLimit) {
+        self.limits.insert(participant, limit);
+        self.buckets.remove(&participant);
+    }
+
+    pub fn limit(&self, participant: ParticipantId) -> Option<RateLimit> {
+        self.limits.get(&participant).copied().or(self.default)
+    }
+
+    /// Whether anyone is limited at all.
+    pub fn is_enabled(&self) -> bool {
+        self.default.is_some() || !self.limits.is_empty()
+    }
+
+    /// Spends one of the participant's tokens, returning false and counting
+    /// a throttle if none are left.
+    pub fn try_acquire(&mut self, participant: ParticipantId, now: Timestamp) -> bool {
+        let Some(limit) = self.limit(participant) else {
+            return true;
+        };
+        let admitted = self
+            .buckets
+            .entry(participant)
+            .or_insert_with(|| TokenBucket::full(limit, now))
+            .try_take(limit, now);
+        if !admitted {
+            *self.throttled.entry(participant).or_default() += 1;
+        }
+        admitted
+    }
+
+    /// Commands throttled for the participant so far.
+    pub fn throttled(&self, participant: ParticipantId) -> u64 {
+        self.throttled.get(&participant).copied().unwrap_or(0)
+    }
+
+    pub fn total_throttled(&self) -> u64 {
+        self.throttled.values().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimit, RateLimiter};
+    use crate::Timestamp;
+    use std::time::Duration;
+
+    #[test]
+    fn allows_burst_then_refills_at_rate() {
+        let start = Timestamp::now();
+        let mut limiter = RateLimiter::new(Some(RateLimit {
+            per_second: 10,
+            burst: 3,
+        }));
+        for _ in 0..3 {
+            assert!(limiter.try_acquire(1, start));
+        }
+        assert!(!limiter.try_acquire(1, start));
+        // Other participants have their own bucket.
+        assert!(limiter.try_acquire(2, start));
+
+        assert!(!limiter.try_acquire(1, start + Duration::from_millis(99)));
+        assert!(limiter.try_acquire(1, start + Duration::from_millis(100)));
+        assert!(!limiter.try_acquire(1, start + Duration::from_millis(100)));
+        assert_eq!(limiter.throttled(1), 3);
+        assert_eq!(limiter.total_throttled(), 3);
+
+        // A long pause refills no more than the burst.
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.try_acquire(1, later));
+        }
+        assert!(!limiter.try_acquire(1, later));
+
+        limiter.set_default(None);
+        assert!(!limiter.is_enabled());
+        assert!(limiter.try_acquire(1, later));
+    }
+}
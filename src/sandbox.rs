@@ -0,0 +1,206 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Synthetic liquidity for testing order entry.
+//!
+//! A book in sandbox mode quotes a ladder of resting orders for a house
+//! participant, `levels` deep on each side of `mid`, and tops the ladder
+//! back up after client commands trade into it. Clients see a book with
+//! something to hit without anyone running a simulator next to it. In
+//! JSON, every field optional:
+//!
+//! ```json
+//! {"participant": 0, "mid": 10000, "spread": 2, "tick": 1, "levels": 5,
+//!  "qty": 100, "refresh": "FollowTrades", "every": 1}
+//! ```
+
+use crate::{OrderBook, OrderCommand, OrderType, ParticipantId, Price, Side};
+use serde::Deserialize;
+
+/// How the ladder moves once it has been traded into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum SandboxRefresh {
+    /// Top levels back up at the prices first quoted.
+    #[default]
+    Replenish,
+    /// Re-center the ladder on the last trade, canceling quotes that fall
+    /// outside it.
+    FollowTrades,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    /// Who the synthetic orders belong to.
+    pub participant: ParticipantId,
+    /// In ticks, as are `spread` and `tick`, whatever the book's price type.
+    pub mid: i32,
+    /// Between the best synthetic bid and ask.
+    pub spread: i32,
+    /// Between levels.
+    pub tick: i32,
+    /// Price levels quoted on each side.
+    pub levels: usize,
+    /// Quantity kept at each level.
+    pub qty: u32,
+    pub refresh: SandboxRefresh,
+    /// Client commands between refreshes.
+    pub every: u32,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig {
+            participant: 0,
+            mid: 10_000,
+            spread: 2,
+            tick: 1,
+            levels: 5,
+            qty: 100,
+            refresh: SandboxRefresh::Replenish,
+            every: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Sandbox {
+    config: SandboxConfig,
+    commands: u32,
+}
+
+impl Sandbox {
+    pub(crate) fn new(config: SandboxConfig) -> Self {
+        Sandbox {
+            config,
+            commands: 0,
+        }
+    }
+
+    /// Counts a client command, returning whether it is time to refresh.
+    pub(crate) fn due(&mut self) -> bool {
+        self.commands += 1;
+        self.commands.is_multiple_of(self.config.every.max(1))
+    }
+
+    /// The commands that bring the house quotes back to the configured
+    /// ladder.
+    pub(crate) fn refresh<P: Price>(&self, order_book: &OrderBook<P>) -> Vec<OrderCommand<P>> {
+        let config = &self.config;
+        let mid = match (config.refresh, order_book.trades().last()) {
+            (SandboxRefresh::FollowTrades, Some(trade)) => trade.price.to_ticks(),
+            _ => i64::from(config.mid),
+        };
+        let (spread, tick) = (i64::from(config.spread), i64::from(config.tick));
+        let best_bid = mid - spread / 2;
+        let ladder = |side: Side| {
+            (0..config.levels as i64).map(move |level| {
+                P::from_ticks(match side {
+                    Side::Buy => best_bid - level * tick,
+                    Side::Sell => best_bid + spread + level * tick,
+                })
+            })
+        };
+        let mut commands = Vec::new();
+        for (side, levels) in [
+            (Side::Buy, &order_book.bids),
+            (Side::Sell, &order_book.asks),
+        ] {
+            let quoted: Vec<P> = ladder(side).collect();
+            for limit in levels {
+                for order in &limit.orders {
+                    if order.participant == config.participant && !quoted.contains(&limit.price) {
+                        commands.push(OrderCommand::Cancel {
+                            id: order.id,
+                            side,
+                            price: limit.price,
+                        });
+                    }
+                }
+            }
+            for price in quoted {
+                let resting: u32 =
+                    levels
+                        .iter()
+                        .find(|limit| limit.price == price)
+                        .map_or(0, |limit| {
+                            limit
+                                .orders
+                                .iter()
+                                .filter(|order| order.participant == config.participant)
+                                .map(|order| order.remaining_qty)
+                                .sum()
+                        });
+                if resting < config.qty {
+                    commands.push(OrderCommand::New {
+                        order_type: OrderType::GoodTilCancel,
+                        side,
+                        price,
+                        qty: config.qty - resting,
+                        participant: config.participant,
+                    });
+                }
+            }
+        }
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SandboxConfig, SandboxRefresh};
+    use crate::order_book::LevelInfo;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    fn buy(order_book: &mut OrderBook, price: i32, qty: u32) {
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::FillAndKill,
+            side: Side::Buy,
+            price,
+            qty,
+            participant: 7,
+        });
+    }
+
+    #[test]
+    fn quotes_and_replenishes_a_ladder() {
+        let mut order_book = OrderBook::new();
+        order_book.set_sandbox(Some(SandboxConfig {
+            levels: 3,
+            ..SandboxConfig::default()
+        }));
+        let depth = order_book.depth(5);
+        assert_eq!(depth.bids.len(), 3);
+        assert_eq!((depth.bids[0].price, depth.asks[0].price), (9_999, 10_001));
+        assert_eq!(depth.asks[2].price, 10_003);
+
+        buy(&mut order_book, 10_002, 150);
+        assert_eq!(order_book.trades().len(), 2);
+        let levels =
+            |levels: &[LevelInfo]| levels.iter().map(|l| (l.price, l.qty)).collect::<Vec<_>>();
+        assert_eq!(levels(&order_book.depth(5).asks), levels(&depth.asks));
+    }
+
+    #[test]
+    fn follows_trades() {
+        let mut order_book = OrderBook::new();
+        order_book.set_sandbox(Some(SandboxConfig {
+            levels: 2,
+            qty: 10,
+            refresh: SandboxRefresh::FollowTrades,
+            ..SandboxConfig::default()
+        }));
+        buy(&mut order_book, 10_002, 20);
+        let depth = order_book.depth(5);
+        assert_eq!(
+            depth.bids.iter().map(|l| l.price).collect::<Vec<_>>(),
+            [10_001, 10_000]
+        );
+        assert_eq!(
+            depth.asks.iter().map(|l| l.price).collect::<Vec<_>>(),
+            [10_003, 10_004]
+        );
+        assert!(depth.asks.iter().all(|l| l.qty == 10));
+    }
+}
@@ -0,0 +1,299 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Scenario tests.
+//!
+//! Every `tests/scenarios/<name>.scenario` is run against a fresh book.
+//! Scenarios use the commands of the golden scripts (see [`crate::golden`]),
+//! with assertions in between:
+//!
+//! ```text
+//! expect <Event> [<field>=<value> ...]
+//! expect none
+//! level bid|ask <price> <qty> [<orders>]
+//! level bid|ask <price> none
+//! best <bid>|- <ask>|-
+//! resting o<n> <qty>|none
+//! trades <count>
+//! status Open|Halted
+//! ```
+//!
+//! `expect` lines go straight after a command and list every event it
+//! produced, in order; a command without any is not checked. An event
+//! matches if it is of that kind and has the given fields, with ids relabeled
+//! as in the golden files and nested fields named by path, such as
+//! `trade.qty=3`. Fields not named are not checked. The other assertions are
+//! about the book as it stands.
+//!
+//! ```text
+//! new 1 sell 5@100
+//! new 2 buy 7@100
+//! expect Placed id=2
+//! expect Trade id=1 qty=5 maker_order_id=1
+//! expect Filled id=1
+//! expect PartiallyFilled id=2 qty=5
+//! level bid 100 2
+//! ```
+
+use crate::circuit_breaker::TradingStatus;
+use crate::golden;
+use crate::sim::Labels;
+use crate::{OrderBook, Side};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn scenario_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios")
+}
+
+struct Runner {
+    order_book: OrderBook,
+    labels: Labels,
+    /// Events of the last command, relabeled.
+    events: Vec<Value>,
+    /// `expect` lines seen since it, with their line numbers.
+    expected: Vec<(usize, String)>,
+}
+
+/// Runs `scenario`, stopping at the first line that fails.
+fn run(scenario: &str) -> Result<(), String> {
+    let mut runner = Runner {
+        order_book: OrderBook::with_symbol("SCEN"),
+        labels: Labels::default(),
+        events: Vec::new(),
+        expected: Vec::new(),
+    };
+    for (n, line) in scenario.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(expected) = line.strip_prefix("expect ") {
+            runner.expected.push((n + 1, expected.trim().to_string()));
+            continue;
+        }
+        runner.check_events()?;
+        runner
+            .step(line)
+            .map_err(|e| format!("line {}: {}: {}", n + 1, line, e))?;
+    }
+    runner.check_events()
+}
+
+impl Runner {
+    fn step(&mut self, line: &str) -> Result<(), String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |word: Option<&&str>| -> Result<i64, String> {
+            let word = word.ok_or("missing argument")?;
+            word.parse().map_err(|_| format!("bad number {}", word))
+        };
+        let side = |word: Option<&&str>| match word.copied() {
+            Some("bid") => Ok(Side::Buy),
+            Some("ask") => Ok(Side::Sell),
+            other => Err(format!("expected bid or ask, got {:?}", other)),
+        };
+        let check = |ok: bool, actual: String| if ok { Ok(()) } else { Err(actual) };
+        match words[0] {
+            "level" => {
+                let side = side(words.get(1))?;
+                let price = number(words.get(2))? as i32;
+                let queue = match side {
+                    Side::Buy => &self.order_book.bids,
+                    Side::Sell => &self.order_book.asks,
+                };
+                let level = queue.iter().find(|lim| lim.price == price);
+                let actual = level.map(|lim| (lim.total_qty(), lim.orders.len()));
+                let describe = |actual: Option<(u64, usize)>| match actual {
+                    Some((qty, orders)) => format!("got {} in {} orders", qty, orders),
+                    None => "got no level".to_string(),
+                };
+                if words.get(3) == Some(&"none") {
+                    return check(actual.is_none(), describe(actual));
+                }
+                let qty = number(words.get(3))? as u64;
+                let orders = words.get(4).map(|_| number(words.get(4))).transpose()?;
+                check(
+                    actual.is_some_and(|(actual_qty, actual_orders)| {
+                        actual_qty == qty
+                            && orders.is_none_or(|orders| orders as usize == actual_orders)
+                    }),
+                    describe(actual),
+                )
+            }
+            "best" => {
+                let price = |word: Option<&&str>| match word.copied() {
+                    Some("-") => Ok(None),
+                    _ => number(word).map(|price| Some(price as i32)),
+                };
+                let expected = (price(words.get(1))?, price(words.get(2))?);
+                let actual = (self.order_book.best_bid(), self.order_book.best_ask());
+                check(expected == actual, format!("got {:?}", actual))
+            }
+            "resting" => {
+                let word = words.get(1).ok_or("missing order")?;
+                let id = word
+                    .strip_prefix('o')
+                    .and_then(|label| label.parse().ok())
+                    .and_then(|label| self.labels.order(label))
+                    .ok_or_else(|| format!("unknown order {}", word))?;
+                let actual = self
+                    .order_book
+                    .find_order(id)
+                    .map(|order| i64::from(order.remaining_qty));
+                let expected = match words.get(2).copied() {
+                    Some("none") => None,
+                    _ => Some(number(words.get(2))?),
+                };
+                check(expected == actual, format!("got {:?}", actual))
+            }
+            "trades" => {
+                let actual = self.order_book.trades().len();
+                check(
+                    number(words.get(1))? as usize == actual,
+                    format!("got {}", actual),
+                )
+            }
+            "status" => {
+                let expected = match words.get(1).copied() {
+                    Some("Open") => TradingStatus::Open,
+                    Some("Halted") => TradingStatus::Halted,
+                    other => return Err(format!("bad status {:?}", other)),
+                };
+                let actual = self.order_book.status();
+                check(expected == actual, format!("got {:?}", actual))
+            }
+            _ => {
+                let command = golden::parse(&self.order_book, &self.labels, line)?;
+                let seen = self.order_book.events().len();
+                self.order_book.process_command(command);
+                self.events = self.order_book.events()[seen..]
+                    .iter()
+                    .map(|event| {
+                        serde_json::from_str(&self.labels.render(event)).unwrap_or(Value::Null)
+                    })
+                    .collect();
+                Ok(())
+            }
+        }
+    }
+
+    fn check_events(&mut self) -> Result<(), String> {
+        let expected = std::mem::take(&mut self.expected);
+        let Some(&(first, _)) = expected.first() else {
+            return Ok(());
+        };
+        let actual = || {
+            let events: Vec<String> = self.events.iter().map(Value::to_string).collect();
+            format!("got [{}]", events.join(", "))
+        };
+        if expected.len() == 1 && expected[0].1 == "none" {
+            return match self.events.is_empty() {
+                true => Ok(()),
+                false => Err(format!("line {}: expected no events, {}", first, actual())),
+            };
+        }
+        if expected.len() != self.events.len() {
+            return Err(format!(
+                "line {}: expected {} events, {}",
+                first,
+                expected.len(),
+                actual()
+            ));
+        }
+        for ((n, expected), event) in expected.iter().zip(&self.events) {
+            matches(expected, event)
+                .map_err(|e| format!("line {}: expect {}: {}; {}", n, expected, e, actual()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `event` is of the kind `expected` names and has its fields.
+fn matches(expected: &str, event: &Value) -> Result<(), String> {
+    let mut words = expected.split_whitespace();
+    let kind = words.next().ok_or("missing event")?;
+    let body = match event {
+        // Unit variants serialize as bare strings.
+        Value::String(name) if name == kind => &Value::Null,
+        Value::Object(map) if map.contains_key(kind) => &map[kind],
+        _ => return Err(format!("not a {} event", kind)),
+    };
+    for field in words {
+        let (path, want) = field
+            .split_once('=')
+            .ok_or_else(|| format!("bad field {}", field))?;
+        let got = path.split('.').fold(body, |value, key| &value[key]);
+        let matched = match got {
+            Value::String(got) => got == want,
+            Value::Null => false,
+            got => want.parse::<Value>().is_ok_and(|want| want == *got),
+        };
+        if !matched {
+            return Err(format!("{} is {}", path, got));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn scenarios_pass() {
+    let mut scenarios: Vec<PathBuf> = fs::read_dir(scenario_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "scenario"))
+        .collect();
+    scenarios.sort();
+    assert!(!scenarios.is_empty(), "no scenarios found");
+
+    let failures: Vec<String> = scenarios
+        .iter()
+        .filter_map(|scenario| {
+            let name = scenario.file_stem().unwrap().to_string_lossy();
+            run(&fs::read_to_string(scenario).unwrap())
+                .err()
+                .map(|e| format!("{}, {}", name, e))
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "scenarios failed:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn reports_the_failing_line() {
+    let scenario = "\
+new 1 sell 5@100
+new 2 buy 2@100
+expect Placed id=2
+expect Trade qty=3
+expect Filled
+";
+    let error = run(scenario).unwrap_err();
+    assert!(
+        error.starts_with("line 3: expected 3 events, got ["),
+        "{}",
+        error
+    );
+
+    let scenario = "\
+new 1 sell 5@100
+new 2 buy 2@100
+expect Placed id=2
+expect Trade qty=3
+expect PartiallyFilled id=1
+expect Filled id=2
+";
+    let error = run(scenario).unwrap_err();
+    assert!(
+        error.starts_with("line 4: expect Trade qty=3: qty is 2"),
+        "{}",
+        error
+    );
+
+    let error = run("new 1 sell 5@100\nlevel ask 100 4\n").unwrap_err();
+    assert_eq!(error, "line 2: level ask 100 4: got 5 in 1 orders");
+}
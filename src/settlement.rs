@@ -0,0 +1,122 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Settlement prices.
+//!
+//! When [`OrderBook::end_of_day`](crate::OrderBook::end_of_day) closes the
+//! session, the book works out the day's settlement price with the
+//! [`SettlementMethod`] set by
+//! [`OrderBook::set_settlement_method`](crate::OrderBook::set_settlement_method).
+//! It is published as `Settled` ahead of `SessionEnded` and kept in the
+//! closing session's [`SessionStats::settlement`](crate::stats::SessionStats::settlement).
+//! A session with nothing to settle on, no trades and no auction, has no
+//! settlement price and publishes nothing.
+
+use crate::tape::TradeTape;
+use crate::{Price, Timestamp};
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum SettlementMethod {
+    /// The price of the session's last trade.
+    #[default]
+    LastTrade,
+    /// The volume-weighted average price of the trades in the last
+    /// `window` of the session, to the nearest tick. The last trade if
+    /// none fall in it.
+    Vwap { window: Duration },
+    /// The price of the session's last auction, on reopening from a halt.
+    /// The last trade if there was none.
+    Auction,
+}
+
+impl SettlementMethod {
+    /// The settlement price of a session closing at `close` with trades
+    /// `tape`, whose last auction uncrossed at `auction`.
+    pub fn price<P: Price>(
+        &self,
+        tape: &TradeTape<P>,
+        auction: Option<P>,
+        close: Timestamp,
+    ) -> Option<P> {
+        let last = tape.last(1).first().map(|trade| trade.price);
+        match *self {
+            SettlementMethod::LastTrade => last,
+            SettlementMethod::Vwap { window } => {
+                let from = close.as_nanos().saturating_sub(window.as_nanos() as u64);
+                let trades = tape.as_slice();
+                let trades =
+                    &trades[trades.partition_point(|trade| trade.timestamp.as_nanos() < from)..];
+                let volume: i128 = trades.iter().map(|trade| i128::from(trade.qty)).sum();
+                let turnover: i128 = trades
+                    .iter()
+                    .map(|trade| i128::from(trade.price.to_ticks()) * i128::from(trade.qty))
+                    .sum();
+                if volume == 0 {
+                    return last;
+                }
+                // Halves round up.
+                Some(P::from_ticks(
+                    (turnover * 2 + volume).div_euclid(volume * 2) as i64,
+                ))
+            }
+            SettlementMethod::Auction => auction.or(last),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SettlementMethod;
+    use crate::clock::ManualClock;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+    use std::time::Duration;
+
+    fn trade(order_book: &mut OrderBook, price: i32, qty: u32) {
+        for (side, participant) in [(Side::Sell, 1), (Side::Buy, 2)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant,
+            });
+        }
+    }
+
+    #[test]
+    fn settles_on_the_closing_trades() {
+        let clock = ManualClock::new();
+        let mut order_book = OrderBook::new();
+        order_book.set_clock(clock.clone());
+        order_book.set_settlement_method(SettlementMethod::Vwap {
+            window: Duration::from_secs(60),
+        });
+        trade(&mut order_book, 90, 100);
+        clock.advance(Duration::from_secs(120));
+        trade(&mut order_book, 100, 10);
+        clock.advance(Duration::from_secs(30));
+        trade(&mut order_book, 103, 20);
+
+        let summary = order_book.end_of_day();
+        // (100 * 10 + 103 * 20) / 30 = 102.
+        assert_eq!(summary.stats.settlement, Some(102));
+        let settled = order_book.events().iter().rev().nth(1);
+        assert!(matches!(
+            settled,
+            Some(OrderEvent::Settled { price: 102, .. })
+        ));
+        assert_eq!(order_book.stats().settlement, None);
+
+        // Nothing traded since, so nothing settles.
+        let seen = order_book.events().len();
+        assert_eq!(order_book.end_of_day().stats.settlement, None);
+        assert_eq!(order_book.events().len(), seen + 1);
+
+        order_book.set_settlement_method(SettlementMethod::LastTrade);
+        trade(&mut order_book, 95, 5);
+        assert_eq!(order_book.end_of_day().stats.settlement, Some(95));
+    }
+}
@@ -0,0 +1,447 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Deterministic simulation.
+//!
+//! A [`Simulation`] plays a scripted list of [`Step`]s through a [`Gateway`]
+//! into an [`OrderBook`] on a virtual clock. Client messages cross a
+//! simulated network that can drop and reorder them, with every decision
+//! drawn from a seeded [`Rng`], so a run is fully determined by its script
+//! and [`SimConfig`]. Simulated clients behave like real ones: they answer
+//! test requests and resend what the gateway asks for, which is what lets a
+//! run check that the session layer recovers from the faults it injects.
+//!
+//! [`Simulation::transcript`] renders the event stream with timestamps
+//! removed and ids renumbered in order of appearance, so it does not depend
+//! on where the book's id counters started. Two runs of the same script and
+//! config produce the same transcript.
+
+use crate::gateway::{Gateway, Inbound, Outbound, Sequenced, SessionConfig, SessionId};
+use crate::{OrderBook, OrderEvent, ParticipantId};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::time::{Duration, Instant};
+
+pub mod agents;
+pub mod latency;
+
+/// Gives up on a settle that has not converged after this many rounds.
+const MAX_SETTLE_ROUNDS: usize = 1_000;
+
+/// SplitMix64; small, fast and good enough to drive simulations and tests.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `low..=high`.
+    pub fn range(&mut self, low: u64, high: u64) -> u64 {
+        low + self.next_u64() % (high - low + 1)
+    }
+
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.range(1, 100) <= percent
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimConfig {
+    pub seed: u64,
+    /// Chance that a client message is lost on the way to the gateway.
+    pub drop_percent: u8,
+    /// Chance that a client message is overtaken by the one behind it.
+    pub reorder_percent: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Logon(ParticipantId),
+    /// Queues a message from the participant's client. Nothing reaches the
+    /// gateway until the network is next flushed, so a run of sends can be
+    /// reordered among themselves.
+    Send(ParticipantId, Inbound),
+    /// Flushes the network, then moves the clock forward and runs the
+    /// gateway's timers.
+    Advance(Duration),
+    Logout(ParticipantId),
+    /// Has every client probe the gateway until it has processed everything
+    /// the client sent, resending whatever was lost.
+    Settle,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Client {
+    session: SessionId,
+    /// Every message sent, index `seq - 1`.
+    sent: Vec<Inbound>,
+    /// Sequence number of the last message that was not a heartbeat.
+    last_command_seq: u64,
+    received: Vec<Sequenced<Outbound>>,
+}
+
+pub struct Simulation {
+    config: SimConfig,
+    rng: Rng,
+    now: Instant,
+    order_book: OrderBook,
+    gateway: Gateway,
+    clients: BTreeMap<ParticipantId, Client>,
+    network: VecDeque<(ParticipantId, Sequenced<Inbound>)>,
+    dropped: u64,
+    reordered: u64,
+}
+
+impl Simulation {
+    pub fn new(config: SimConfig) -> Simulation {
+        Simulation {
+            config,
+            rng: Rng::new(config.seed),
+            now: Instant::now(),
+            order_book: OrderBook::with_symbol("SIM"),
+            gateway: Gateway::new(),
+            clients: BTreeMap::new(),
+            network: VecDeque::new(),
+            dropped: 0,
+            reordered: 0,
+        }
+    }
+
+    pub fn order_book(&self) -> &OrderBook {
+        &self.order_book
+    }
+
+    pub fn order_book_mut(&mut self) -> &mut OrderBook {
+        &mut self.order_book
+    }
+
+    pub fn gateway(&self) -> &Gateway {
+        &self.gateway
+    }
+
+    pub fn now(&self) -> Instant {
+        self.now
+    }
+
+    /// Client messages lost so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Client messages delivered out of order so far.
+    pub fn reordered(&self) -> u64 {
+        self.reordered
+    }
+
+    /// Everything the gateway has sent the participant's client.
+    pub fn received(&self, participant: ParticipantId) -> &[Sequenced<Outbound>] {
+        self.clients
+            .get(&participant)
+            .map_or(&[], |client| &client.received)
+    }
+
+    pub fn run(&mut self, steps: &[Step]) -> io::Result<()> {
+        for step in steps {
+            self.step(step.clone())?;
+        }
+        Ok(())
+    }
+
+    pub fn step(&mut self, step: Step) -> io::Result<()> {
+        match step {
+            Step::Logon(participant) => {
+                let session =
+                    self.gateway
+                        .logon(participant, SessionConfig::default(), self.now)?;
+                self.clients.insert(
+                    participant,
+                    Client {
+                        session,
+                        ..Client::default()
+                    },
+                );
+            }
+            Step::Send(participant, message) => self.send(participant, message)?,
+            Step::Advance(by) => {
+                self.flush()?;
+                self.now += by;
+                self.gateway.tick(&mut self.order_book, self.now)?;
+                self.collect()?;
+            }
+            Step::Logout(participant) => {
+                self.flush()?;
+                let client = self.client(participant)?;
+                let session = client.session;
+                self.gateway
+                    .disconnect(&mut self.order_book, session, self.now)?;
+                self.clients.remove(&participant);
+            }
+            Step::Settle => self.settle()?,
+        }
+        Ok(())
+    }
+
+    /// The book's events so far with order and trade ids renumbered from 1
+    /// in order of appearance and timestamps left out, one JSON object per
+    /// event.
+    pub fn transcript(&self) -> Vec<String> {
+        let mut labels = Labels::default();
+        self.order_book
+            .events()
+            .iter()
+            .map(|event| labels.render(event))
+            .collect()
+    }
+
+    fn client(&mut self, participant: ParticipantId) -> io::Result<&mut Client> {
+        self.clients.get_mut(&participant).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotConnected,
+                format!("participant {} is not logged on", participant),
+            )
+        })
+    }
+
+    fn send(&mut self, participant: ParticipantId, message: Inbound) -> io::Result<()> {
+        let client = self.client(participant)?;
+        client.sent.push(message.clone());
+        let seq = client.sent.len() as u64;
+        if !matches!(message, Inbound::Heartbeat { .. }) {
+            client.last_command_seq = seq;
+        }
+        self.network
+            .push_back((participant, Sequenced::new(seq, message)));
+        Ok(())
+    }
+
+    /// Delivers everything in flight, along with whatever the clients send
+    /// in reply, until the network is quiet.
+    fn flush(&mut self) -> io::Result<()> {
+        while let Some((participant, message)) = self.network.pop_front() {
+            if self.rng.chance(self.config.drop_percent.into()) {
+                self.dropped += 1;
+                continue;
+            }
+            if !self.network.is_empty() && self.rng.chance(self.config.reorder_percent.into()) {
+                self.reordered += 1;
+                self.network.insert(1, (participant, message));
+                continue;
+            }
+            let Some(client) = self.clients.get(&participant) else {
+                continue;
+            };
+            let session = client.session;
+            self.gateway
+                .receive(&mut self.order_book, session, message, self.now)?;
+            self.collect()?;
+        }
+        Ok(())
+    }
+
+    /// Drains what the gateway sent each client and lets the clients react.
+    fn collect(&mut self) -> io::Result<()> {
+        let participants: Vec<ParticipantId> = self.clients.keys().copied().collect();
+        for participant in participants {
+            let client = self.client(participant)?;
+            let session = client.session;
+            let outbound = self.gateway.drain(session);
+            for message in outbound {
+                match &message.message {
+                    Outbound::TestRequest { id } => {
+                        let reply = Inbound::Heartbeat {
+                            test_request_id: Some(id.clone()),
+                        };
+                        self.send(participant, reply)?;
+                    }
+                    Outbound::ResendRequest { begin, end } => {
+                        self.resend(participant, *begin, *end)?;
+                    }
+                    _ => {}
+                }
+                self.client(participant)?.received.push(message);
+            }
+        }
+        Ok(())
+    }
+
+    fn resend(&mut self, participant: ParticipantId, begin: u64, end: u64) -> io::Result<()> {
+        let client = self.client(participant)?;
+        let last = client.sent.len() as u64;
+        let end = if end == 0 { last } else { end.min(last) };
+        let resent: Vec<_> = (begin.max(1)..=end)
+            .map(|seq| Sequenced::resent(seq, client.sent[seq as usize - 1].clone()))
+            .collect();
+        self.network
+            .extend(resent.into_iter().map(|message| (participant, message)));
+        Ok(())
+    }
+
+    fn settle(&mut self) -> io::Result<()> {
+        for _ in 0..MAX_SETTLE_ROUNDS {
+            self.flush()?;
+            let mut behind = Vec::new();
+            for (&participant, client) in &self.clients {
+                let session = self.gateway.session(client.session);
+                if session
+                    .is_some_and(|session| session.next_inbound_seq <= client.last_command_seq)
+                {
+                    behind.push(participant);
+                }
+            }
+            if behind.is_empty() {
+                return Ok(());
+            }
+            for participant in behind {
+                let probe = Inbound::Heartbeat {
+                    test_request_id: None,
+                };
+                self.send(participant, probe)?;
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "simulation did not settle",
+        ))
+    }
+}
+
+/// Renumbers order and trade ids. Inside a trade `id` is the trade's own id;
+/// everywhere else it is an order id.
+#[derive(Debug, Default)]
+pub(crate) struct Labels {
+    orders: HashMap<u64, usize>,
+    trades: HashMap<u64, usize>,
+}
+
+impl Labels {
+    /// `event` as compact JSON, relabeled and without timestamps.
+    pub(crate) fn render(&mut self, event: &OrderEvent) -> String {
+        let mut value = serde_json::to_value(event).unwrap_or(Value::Null);
+        self.relabel(&mut value, false);
+        value.to_string()
+    }
+
+    /// The order id behind `label`, once it has been seen.
+    #[cfg(test)]
+    pub(crate) fn order(&self, label: usize) -> Option<crate::OrderId> {
+        Self::unlabel(&self.orders, label)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn trade(&self, label: usize) -> Option<usize> {
+        Self::unlabel(&self.trades, label).map(|id| id as usize)
+    }
+
+    fn relabel(&mut self, value: &mut Value, in_trade: bool) {
+        let Value::Object(map) = value else {
+            return;
+        };
+        map.remove("timestamp");
+        for (key, value) in map.iter_mut() {
+            match key.as_str() {
+                "id" if in_trade => Self::label(&mut self.trades, value),
+                "id" | "maker_order_id" | "taker_order_id" => Self::label(&mut self.orders, value),
+                "Trade" | "trade" => self.relabel(value, true),
+                _ => self.relabel(value, in_trade),
+            }
+        }
+    }
+
+    fn label(labels: &mut HashMap<u64, usize>, value: &mut Value) {
+        if let Some(raw) = value.as_u64() {
+            let next = labels.len() + 1;
+            *value = Value::from(*labels.entry(raw).or_insert(next));
+        }
+    }
+
+    #[cfg(test)]
+    fn unlabel(labels: &HashMap<u64, usize>, label: usize) -> Option<u64> {
+        labels
+            .iter()
+            .find(|(_, &l)| l == label)
+            .map(|(&raw, _)| raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SimConfig, Simulation, Step};
+    use crate::gateway::Inbound;
+    use crate::{OrderCommand, OrderType, Side};
+    use std::time::Duration;
+
+    /// Two participants trading a burst of crossing orders.
+    fn script() -> Vec<Step> {
+        let mut steps = vec![Step::Logon(1), Step::Logon(2)];
+        for round in 0..20 {
+            for (participant, side, price) in [(1, Side::Sell, 100), (2, Side::Buy, 101)] {
+                let command = OrderCommand::New {
+                    order_type: OrderType::GoodTilCancel,
+                    side,
+                    price: price + round % 3,
+                    qty: 5,
+                    participant: 0,
+                };
+                steps.push(Step::Send(participant, Inbound::Command(command)));
+            }
+            steps.push(Step::Advance(Duration::from_millis(100)));
+        }
+        steps.push(Step::Settle);
+        steps
+    }
+
+    fn run(config: SimConfig) -> Simulation {
+        let mut sim = Simulation::new(config);
+        sim.run(&script()).unwrap();
+        sim
+    }
+
+    #[test]
+    fn same_seed_same_transcript() {
+        let config = SimConfig {
+            seed: 7,
+            drop_percent: 20,
+            reorder_percent: 20,
+        };
+        let (a, b) = (run(config), run(config));
+        assert!(a.dropped() > 0 && a.reordered() > 0);
+        assert_eq!(a.transcript(), b.transcript());
+        assert_ne!(
+            a.transcript(),
+            run(SimConfig { seed: 8, ..config }).transcript()
+        );
+    }
+
+    #[test]
+    fn recovers_every_lost_command() {
+        let clean = run(SimConfig::default());
+        let lossy = run(SimConfig {
+            seed: 3,
+            drop_percent: 30,
+            reorder_percent: 10,
+        });
+        assert!(lossy.dropped() > 0);
+        // Every order arrives exactly once, so the same volume trades.
+        for sim in [&clean, &lossy] {
+            assert_eq!(sim.order_book().stats().volume, 100);
+            assert!(sim.order_book().bids.is_empty() && sim.order_book().asks.is_empty());
+        }
+    }
+}
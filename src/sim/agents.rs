@@ -0,0 +1,510 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Agent-based market simulation.
+//!
+//! An [`AgentSim`] runs a population of [`Agent`]s against a book on a
+//! simulated clock. Every tick, each agent gets a turn, in an order shuffled
+//! from the seed, to look at the book and place or cancel orders through a
+//! [`Market`]. Everything random comes from one [`Rng`], so a seed and a
+//! config always produce the same order flow, and with it the same events
+//! and trades to feed into whatever consumes market data downstream.
+//!
+//! Three kinds of agent come built in and can be set up from JSON:
+//!
+//! ```json
+//! {
+//!   "seed": 7,
+//!   "start_price": 10000,
+//!   "agents": [
+//!     {"kind": "MarketMaker", "participant": 1, "half_spread": 2, "qty": 50},
+//!     {"kind": "RandomTrader", "participant": 2, "activity": 60},
+//!     {"kind": "Momentum", "participant": 3, "lookback": 5, "threshold": 3}
+//!   ]
+//! }
+//! ```
+//!
+//! Fields left out take the defaults on [`RandomTrader`], [`MarketMaker`]
+//! and [`Momentum`]. Other agents implement [`Agent`] and are added with
+//! [`AgentSim::add`].
+
+use super::Rng;
+use crate::clock::simulated::SimulatedClock;
+use crate::clock::Clock;
+use crate::{OrderBook, OrderCommand, OrderEvent, OrderId, OrderType, ParticipantId, Side};
+use crate::{Timestamp, Trade};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Something that trades in the simulation.
+pub trait Agent {
+    fn participant(&self) -> ParticipantId;
+
+    /// Takes the agent's turn for the current tick.
+    fn act(&mut self, market: &mut Market<'_>);
+}
+
+/// What an agent sees on its turn, and how it trades.
+pub struct Market<'a> {
+    order_book: &'a mut OrderBook,
+    rng: &'a mut Rng,
+    participant: ParticipantId,
+    start_price: i32,
+}
+
+impl Market<'_> {
+    pub fn order_book(&self) -> &OrderBook {
+        self.order_book
+    }
+
+    pub fn rng(&mut self) -> &mut Rng {
+        self.rng
+    }
+
+    /// The book's reference price, or the simulation's starting price until
+    /// the book has one.
+    pub fn reference_price(&self) -> i32 {
+        self.order_book
+            .reference_price()
+            .unwrap_or(self.start_price)
+    }
+
+    /// Places an order for the agent, returning its id, or `None` if the book
+    /// rejected it.
+    pub fn place(
+        &mut self,
+        order_type: OrderType,
+        side: Side,
+        price: i32,
+        qty: u32,
+    ) -> Option<OrderId> {
+        let seen = self.order_book.events().len();
+        self.order_book.process_command(OrderCommand::New {
+            order_type,
+            side,
+            price,
+            qty,
+            participant: self.participant,
+        });
+        self.order_book.events()[seen..]
+            .iter()
+            .find_map(|event| match *event {
+                OrderEvent::Placed { id, .. } => Some(id),
+                _ => None,
+            })
+    }
+
+    /// Cancels one of the agent's resting orders. Returns false if it has
+    /// already traded away or been canceled.
+    pub fn cancel(&mut self, id: OrderId) -> bool {
+        let Some(order) = self.order_book.find_order(id) else {
+            return false;
+        };
+        if order.participant != self.participant {
+            return false;
+        }
+        let (side, price) = (order.side, order.price);
+        self.order_book
+            .process_command(OrderCommand::Cancel { id, side, price });
+        true
+    }
+}
+
+/// Trades at random around the reference price: mostly passive orders a few
+/// ticks away from it, sometimes immediate ones that cross.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RandomTrader {
+    pub participant: ParticipantId,
+    /// Chance of trading on any one tick.
+    pub activity: u8,
+    /// Chance that an order crosses rather than rests.
+    pub aggression: u8,
+    pub max_qty: u32,
+    /// Farthest from the reference an order is priced, in ticks.
+    pub range: i32,
+    /// Resting orders kept before the oldest is canceled.
+    pub max_open: usize,
+    #[serde(skip)]
+    open: VecDeque<OrderId>,
+}
+
+impl Default for RandomTrader {
+    fn default() -> Self {
+        RandomTrader {
+            participant: 0,
+            activity: 50,
+            aggression: 20,
+            max_qty: 100,
+            range: 5,
+            max_open: 10,
+            open: VecDeque::new(),
+        }
+    }
+}
+
+impl Agent for RandomTrader {
+    fn participant(&self) -> ParticipantId {
+        self.participant
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        if !market.rng().chance(u64::from(self.activity)) {
+            return;
+        }
+        let reference = market.reference_price();
+        let side = if market.rng().chance(50) {
+            Side::Buy
+        } else {
+            Side::Sell
+        };
+        let qty = market.rng().range(1, u64::from(self.max_qty.max(1))) as u32;
+        let range = u64::from(self.range.max(1).unsigned_abs());
+        if market.rng().chance(u64::from(self.aggression)) {
+            let through = self.range.max(1);
+            let price = match side {
+                Side::Buy => reference + through,
+                Side::Sell => reference - through,
+            };
+            market.place(OrderType::FillAndKill, side, price.max(1), qty);
+            return;
+        }
+        let away = market.rng().range(1, range) as i32;
+        let price = match side {
+            Side::Buy => reference - away,
+            Side::Sell => reference + away,
+        };
+        if let Some(id) = market.place(OrderType::GoodTilCancel, side, price.max(1), qty) {
+            self.open.push_back(id);
+        }
+        while self.open.len() > self.max_open {
+            if let Some(oldest) = self.open.pop_front() {
+                market.cancel(oldest);
+            }
+        }
+    }
+}
+
+/// Quotes both sides around the reference price and requotes every tick,
+/// leaning its quotes against its inventory and dropping the side that
+/// would take it past its position limit.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct MarketMaker {
+    pub participant: ParticipantId,
+    /// Distance of each quote from the reference, in ticks.
+    pub half_spread: i32,
+    pub qty: u32,
+    /// Inventory, either way, at which it stops quoting the side that would
+    /// add to it.
+    pub max_position: i64,
+    /// Inventory per tick of skew: quotes move down a tick for every this
+    /// many long, and up for every this many short.
+    pub skew_per: i64,
+    #[serde(skip)]
+    quotes: Vec<OrderId>,
+}
+
+impl Default for MarketMaker {
+    fn default() -> Self {
+        MarketMaker {
+            participant: 0,
+            half_spread: 2,
+            qty: 50,
+            max_position: 500,
+            skew_per: 100,
+            quotes: Vec::new(),
+        }
+    }
+}
+
+impl Agent for MarketMaker {
+    fn participant(&self) -> ParticipantId {
+        self.participant
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        for id in self.quotes.drain(..) {
+            market.cancel(id);
+        }
+        let position = market.order_book().position(self.participant).net_qty;
+        let skew = (position / self.skew_per.max(1)) as i32;
+        let center = market.reference_price() - skew;
+        let half_spread = self.half_spread.max(1);
+        if position < self.max_position {
+            let bid = (center - half_spread).max(1);
+            self.quotes
+                .extend(market.place(OrderType::GoodTilCancel, Side::Buy, bid, self.qty));
+        }
+        if position > -self.max_position {
+            let ask = (center + half_spread).max(1);
+            self.quotes
+                .extend(market.place(OrderType::GoodTilCancel, Side::Sell, ask, self.qty));
+        }
+    }
+}
+
+/// Follows the trend: buys when the last `lookback` trades have moved up by
+/// at least `threshold` ticks and sells when they have moved down as far,
+/// taking whatever is at the touch.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Momentum {
+    pub participant: ParticipantId,
+    pub lookback: usize,
+    pub threshold: i32,
+    pub qty: u32,
+    /// Chance of acting on a signal on any one tick.
+    pub activity: u8,
+}
+
+impl Default for Momentum {
+    fn default() -> Self {
+        Momentum {
+            participant: 0,
+            lookback: 10,
+            threshold: 3,
+            qty: 20,
+            activity: 30,
+        }
+    }
+}
+
+impl Agent for Momentum {
+    fn participant(&self) -> ParticipantId {
+        self.participant
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        let trades = market.order_book().tape().last(self.lookback);
+        let (Some(first), Some(last)) = (trades.first(), trades.last()) else {
+            return;
+        };
+        if trades.len() < self.lookback {
+            return;
+        }
+        let moved = last.price - first.price;
+        let (side, touch) = if moved >= self.threshold {
+            (Side::Buy, market.order_book().best_ask())
+        } else if moved <= -self.threshold {
+            (Side::Sell, market.order_book().best_bid())
+        } else {
+            return;
+        };
+        let Some(price) = touch else {
+            return;
+        };
+        if market.rng().chance(u64::from(self.activity)) {
+            market.place(OrderType::FillAndKill, side, price, self.qty);
+        }
+    }
+}
+
+/// One of the built-in agents, as configured.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind")]
+pub enum AgentConfig {
+    RandomTrader(RandomTrader),
+    MarketMaker(MarketMaker),
+    Momentum(Momentum),
+}
+
+impl AgentConfig {
+    fn into_agent(self) -> Box<dyn Agent> {
+        match self {
+            AgentConfig::RandomTrader(agent) => Box::new(agent),
+            AgentConfig::MarketMaker(agent) => Box::new(agent),
+            AgentConfig::Momentum(agent) => Box::new(agent),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct AgentSimConfig {
+    pub seed: u64,
+    pub symbol: String,
+    /// Reference price until the book has one of its own.
+    pub start_price: i32,
+    /// Simulated time between turns.
+    pub tick_micros: u64,
+    /// Where the simulated clock starts, in nanoseconds since the epoch.
+    pub start_nanos: u64,
+    pub agents: Vec<AgentConfig>,
+}
+
+impl Default for AgentSimConfig {
+    fn default() -> Self {
+        AgentSimConfig {
+            seed: 0,
+            symbol: "SIM".to_string(),
+            start_price: 10_000,
+            tick_micros: 1_000,
+            // 2024-09-01T12:00:00Z
+            start_nanos: 1_725_192_000_000_000_000,
+            agents: vec![
+                AgentConfig::MarketMaker(MarketMaker {
+                    participant: 1,
+                    ..MarketMaker::default()
+                }),
+                AgentConfig::RandomTrader(RandomTrader {
+                    participant: 2,
+                    ..RandomTrader::default()
+                }),
+                AgentConfig::RandomTrader(RandomTrader {
+                    participant: 3,
+                    ..RandomTrader::default()
+                }),
+                AgentConfig::Momentum(Momentum {
+                    participant: 4,
+                    ..Momentum::default()
+                }),
+            ],
+        }
+    }
+}
+
+pub struct AgentSim {
+    order_book: OrderBook,
+    clock: SimulatedClock,
+    rng: Rng,
+    agents: Vec<Box<dyn Agent>>,
+    start_price: i32,
+    tick: Duration,
+    ticks: u64,
+}
+
+impl AgentSim {
+    pub fn new(config: AgentSimConfig) -> AgentSim {
+        let clock = SimulatedClock::starting_at(Timestamp::from_nanos(config.start_nanos));
+        let mut order_book = OrderBook::with_symbol(config.symbol);
+        order_book.set_clock(clock.clock());
+        AgentSim {
+            order_book,
+            clock,
+            rng: Rng::new(config.seed),
+            agents: config
+                .agents
+                .into_iter()
+                .map(AgentConfig::into_agent)
+                .collect(),
+            start_price: config.start_price,
+            tick: Duration::from_micros(config.tick_micros),
+            ticks: 0,
+        }
+    }
+
+    pub fn add(&mut self, agent: impl Agent + 'static) {
+        self.agents.push(Box::new(agent));
+    }
+
+    pub fn order_book(&self) -> &OrderBook {
+        &self.order_book
+    }
+
+    pub fn trades(&self) -> &[Trade] {
+        self.order_book.trades()
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Moves the clock on a tick and gives every agent a turn.
+    pub fn step(&mut self) {
+        let now = self.clock.now() + self.tick;
+        self.clock.advance_to(&mut self.order_book, now);
+        let mut order: Vec<usize> = (0..self.agents.len()).collect();
+        // Fisher-Yates, so no agent always moves first.
+        for i in (1..order.len()).rev() {
+            order.swap(i, self.rng.range(0, i as u64) as usize);
+        }
+        for i in order {
+            let agent = &mut self.agents[i];
+            let mut market = Market {
+                order_book: &mut self.order_book,
+                rng: &mut self.rng,
+                participant: agent.participant(),
+                start_price: self.start_price,
+            };
+            agent.act(&mut market);
+        }
+        self.ticks += 1;
+    }
+
+    pub fn run(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AgentConfig, AgentSim, AgentSimConfig, MarketMaker};
+
+    fn transcript(seed: u64) -> String {
+        let mut sim = AgentSim::new(AgentSimConfig {
+            seed,
+            ..AgentSimConfig::default()
+        });
+        sim.run(500);
+        serde_json::to_string(sim.order_book().events()).unwrap()
+    }
+
+    #[test]
+    fn seeds_reproduce_the_flow() {
+        let first = transcript(7);
+        assert_eq!(first, transcript(7));
+        assert_ne!(first, transcript(8));
+
+        let mut sim = AgentSim::new(AgentSimConfig {
+            seed: 7,
+            ..AgentSimConfig::default()
+        });
+        sim.run(500);
+        assert_eq!(sim.ticks(), 500);
+        let trades = sim.trades();
+        assert!(trades.len() > 50, "only {} trades", trades.len());
+        // Every agent gets involved.
+        for participant in 1..=4 {
+            assert!(
+                trades.iter().any(|trade| {
+                    trade.maker_participant == participant || trade.taker_participant == participant
+                }),
+                "participant {} never traded",
+                participant
+            );
+        }
+        let span = trades
+            .last()
+            .unwrap()
+            .timestamp
+            .saturating_duration_since(trades[0].timestamp);
+        assert!(span.as_millis() > 100);
+    }
+
+    #[test]
+    fn reads_agents_from_json() {
+        let config: AgentSimConfig = serde_json::from_str(
+            r#"{"seed": 3, "agents": [{"kind": "MarketMaker", "participant": 9, "qty": 5}]}"#,
+        )
+        .unwrap();
+        assert_eq!(config.symbol, "SIM");
+        assert_eq!(
+            config.agents,
+            [AgentConfig::MarketMaker(MarketMaker {
+                participant: 9,
+                qty: 5,
+                ..MarketMaker::default()
+            })]
+        );
+        let mut sim = AgentSim::new(config);
+        sim.run(3);
+        assert_eq!(sim.order_book().best_bid(), Some(9_998));
+        assert_eq!(sim.order_book().best_ask(), Some(10_002));
+        assert_eq!(sim.order_book().open_orders(9), 2);
+    }
+}
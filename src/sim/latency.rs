@@ -0,0 +1,126 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Simulated network latency.
+//!
+//! A [`LatencyModel`] is a distribution of one-way delays, sampled from a
+//! seeded [`Rng`] so runs stay reproducible. In JSON:
+//!
+//! ```json
+//! {"kind": "Fixed", "micros": 50}
+//! {"kind": "Uniform", "min_micros": 20, "max_micros": 80}
+//! {"kind": "Normal", "mean_micros": 50.0, "std_dev_micros": 10.0}
+//! {"kind": "LogNormal", "median_micros": 40.0, "sigma": 0.5}
+//! ```
+
+use super::Rng;
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(tag = "kind")]
+pub enum LatencyModel {
+    /// Everything arrives the moment it is sent.
+    #[default]
+    Zero,
+    Fixed {
+        micros: u64,
+    },
+    Uniform {
+        min_micros: u64,
+        max_micros: u64,
+    },
+    /// Cut off at zero.
+    Normal {
+        mean_micros: f64,
+        std_dev_micros: f64,
+    },
+    /// Mostly close to `median_micros`, with a long tail of slow ones, the
+    /// usual shape of network latency. `sigma` sets how long the tail is.
+    LogNormal {
+        median_micros: f64,
+        sigma: f64,
+    },
+}
+
+impl LatencyModel {
+    pub fn sample(&self, rng: &mut Rng) -> Duration {
+        let micros = match *self {
+            LatencyModel::Zero => return Duration::ZERO,
+            LatencyModel::Fixed { micros } => return Duration::from_micros(micros),
+            LatencyModel::Uniform {
+                min_micros,
+                max_micros,
+            } => return Duration::from_micros(rng.range(min_micros, max_micros.max(min_micros))),
+            LatencyModel::Normal {
+                mean_micros,
+                std_dev_micros,
+            } => mean_micros + std_dev_micros * standard_normal(rng),
+            LatencyModel::LogNormal {
+                median_micros,
+                sigma,
+            } => median_micros * (sigma * standard_normal(rng)).exp(),
+        };
+        Duration::from_nanos((micros.max(0.0) * 1_000.0) as u64)
+    }
+}
+
+/// Box-Muller.
+fn standard_normal(rng: &mut Rng) -> f64 {
+    // 1 - unit() is in (0, 1], which keeps ln finite.
+    let u1 = 1.0 - rng.unit();
+    let u2 = rng.unit();
+    (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LatencyModel;
+    use crate::sim::Rng;
+    use std::time::Duration;
+
+    fn mean_and_max(model: LatencyModel) -> (f64, Duration) {
+        let mut rng = Rng::new(1);
+        let samples: Vec<Duration> = (0..10_000).map(|_| model.sample(&mut rng)).collect();
+        let mean = samples.iter().map(|d| d.as_secs_f64() * 1e6).sum::<f64>() / 10_000.0;
+        (mean, samples.into_iter().max().unwrap())
+    }
+
+    #[test]
+    fn samples_follow_the_model() {
+        assert_eq!(
+            mean_and_max(LatencyModel::Fixed { micros: 7 }),
+            (7.0, Duration::from_micros(7))
+        );
+        let (mean, max) = mean_and_max(LatencyModel::Uniform {
+            min_micros: 20,
+            max_micros: 80,
+        });
+        assert!((mean - 50.0).abs() < 1.0, "{}", mean);
+        assert_eq!(max, Duration::from_micros(80));
+        let (mean, _) = mean_and_max(LatencyModel::Normal {
+            mean_micros: 50.0,
+            std_dev_micros: 10.0,
+        });
+        assert!((mean - 50.0).abs() < 0.5, "{}", mean);
+        // The mean of a log-normal sits above its median: the tail.
+        let (mean, max) = mean_and_max(LatencyModel::LogNormal {
+            median_micros: 40.0,
+            sigma: 0.5,
+        });
+        assert!((mean - 40.0 * (0.125_f64).exp()).abs() < 1.0, "{}", mean);
+        assert!(max > Duration::from_micros(120));
+
+        let model: LatencyModel =
+            serde_json::from_str(r#"{"kind": "Uniform", "min_micros": 1, "max_micros": 2}"#)
+                .unwrap();
+        assert_eq!(
+            model,
+            LatencyModel::Uniform {
+                min_micros: 1,
+                max_micros: 2
+            }
+        );
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Destinations for the book's event stream.
+//!
+//! A sink receives every `OrderEvent` together with the symbol of the book
+//! that produced it. Sinks that write bytes pick an [`EventEncoder`] so the
+//! wire format can be swapped without touching the transport.
+
+use crate::OrderEvent;
+use std::io;
+
+pub mod dispatch;
+pub mod drop_copy;
+pub mod jsonl;
+pub mod kafka;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod redis;
+pub mod retransmit;
+pub mod sqlite;
+
+pub trait EventSink {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects events in memory.
+impl EventSink for Vec<OrderEvent> {
+    fn publish(&mut self, _symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        self.push(event.clone());
+        Ok(())
+    }
+}
+
+/// Turns an event into the payload bytes handed to a transport.
+pub trait EventEncoder {
+    fn encode(&self, symbol: &str, event: &OrderEvent) -> io::Result<Vec<u8>>;
+}
+
+/// Encodes events as a single JSON object, e.g.
+/// `{"symbol":"ABC","event":{"Canceled":{"id":4}}}`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonEncoder;
+
+impl EventEncoder for JsonEncoder {
+    fn encode(&self, symbol: &str, event: &OrderEvent) -> io::Result<Vec<u8>> {
+        let value = serde_json::json!({ "symbol": symbol, "event": event });
+        serde_json::to_vec(&value).map_err(io::Error::other)
+    }
+}
+
+/// Publishes `events` in order, stopping at the first failure.
+pub fn publish_events<S: EventSink + ?Sized>(
+    sink: &mut S,
+    symbol: &str,
+    events: &[OrderEvent],
+) -> io::Result<()> {
+    for event in events {
+        sink.publish(symbol, event)?;
+    }
+    sink.flush()
+}
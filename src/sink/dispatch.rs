@@ -0,0 +1,282 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Filtered event subscriptions.
+//!
+//! A [`Dispatcher`] sits on the main event stream and hands each event only
+//! to the subscribers whose [`EventFilter`] it matches: those following one
+//! participant, one order or one kind of event. Subscriptions are indexed by
+//! what they filter on, so an event costs a few lookups however many
+//! subscribers there are. As in [`drop_copy`](super::drop_copy), events that
+//! don't name a participant are put down to the owner of their order, which
+//! the dispatcher remembers while the order is live.
+
+use super::EventSink;
+use crate::{OrderEvent, OrderId, ParticipantId};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+pub type SubscriptionId = u64;
+
+/// The variants of [`OrderEvent`], without their fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Placed,
+    Modified,
+    Canceled,
+    PartiallyFilled,
+    Filled,
+    Trade,
+    Rejected,
+    KillSwitch,
+    StatusChanged,
+    TradeBust,
+    Quoted,
+    ProtectionTriggered,
+    Settled,
+    SessionEnded,
+    Restored,
+}
+
+impl EventKind {
+    pub fn of(event: &OrderEvent) -> Self {
+        match event {
+            OrderEvent::Placed { .. } => EventKind::Placed,
+            OrderEvent::Modified => EventKind::Modified,
+            OrderEvent::Canceled { .. } => EventKind::Canceled,
+            OrderEvent::PartiallyFilled { .. } => EventKind::PartiallyFilled,
+            OrderEvent::Filled { .. } => EventKind::Filled,
+            OrderEvent::Trade(_) => EventKind::Trade,
+            OrderEvent::Rejected { .. } => EventKind::Rejected,
+            OrderEvent::KillSwitch { .. } => EventKind::KillSwitch,
+            OrderEvent::StatusChanged { .. } => EventKind::StatusChanged,
+            OrderEvent::TradeBust { .. } => EventKind::TradeBust,
+            OrderEvent::Quoted { .. } => EventKind::Quoted,
+            OrderEvent::ProtectionTriggered { .. } => EventKind::ProtectionTriggered,
+            OrderEvent::Settled { .. } => EventKind::Settled,
+            OrderEvent::SessionEnded { .. } => EventKind::SessionEnded,
+            OrderEvent::Restored { .. } => EventKind::Restored,
+        }
+    }
+}
+
+/// What a subscriber wants to see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventFilter {
+    /// Everything involving the participant's orders, on either side of a
+    /// trade, and its rejections, kill switch and protections.
+    Participant(ParticipantId),
+    /// Everything about one order, including trades it is either side of.
+    Order(OrderId),
+    Kind(EventKind),
+}
+
+pub struct Dispatcher<S> {
+    subscribers: BTreeMap<SubscriptionId, (EventFilter, S)>,
+    by_participant: HashMap<ParticipantId, Vec<SubscriptionId>>,
+    by_order: HashMap<OrderId, Vec<SubscriptionId>>,
+    by_kind: HashMap<EventKind, Vec<SubscriptionId>>,
+    owners: HashMap<OrderId, ParticipantId>,
+    next_id: SubscriptionId,
+    /// Subscribers matched by the event being published.
+    matched: Vec<SubscriptionId>,
+}
+
+impl<S> Default for Dispatcher<S> {
+    fn default() -> Self {
+        Dispatcher {
+            subscribers: BTreeMap::new(),
+            by_participant: HashMap::new(),
+            by_order: HashMap::new(),
+            by_kind: HashMap::new(),
+            owners: HashMap::new(),
+            next_id: 0,
+            matched: Vec::new(),
+        }
+    }
+}
+
+impl<S: EventSink> Dispatcher<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends `sink` the events `filter` matches from now on.
+    pub fn subscribe(&mut self, filter: EventFilter, sink: S) -> SubscriptionId {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.index(filter).push(id);
+        self.subscribers.insert(id, (filter, sink));
+        id
+    }
+
+    /// Ends a subscription, handing back its sink.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> Option<S> {
+        let (filter, sink) = self.subscribers.remove(&id)?;
+        let index = self.index(filter);
+        index.retain(|&subscription| subscription != id);
+        if index.is_empty() {
+            match filter {
+                EventFilter::Participant(participant) => {
+                    self.by_participant.remove(&participant);
+                }
+                EventFilter::Order(order) => {
+                    self.by_order.remove(&order);
+                }
+                EventFilter::Kind(kind) => {
+                    self.by_kind.remove(&kind);
+                }
+            }
+        }
+        Some(sink)
+    }
+
+    pub fn get_mut(&mut self, id: SubscriptionId) -> Option<&mut S> {
+        self.subscribers.get_mut(&id).map(|(_, sink)| sink)
+    }
+
+    fn index(&mut self, filter: EventFilter) -> &mut Vec<SubscriptionId> {
+        match filter {
+            EventFilter::Participant(participant) => {
+                self.by_participant.entry(participant).or_default()
+            }
+            EventFilter::Order(order) => self.by_order.entry(order).or_default(),
+            EventFilter::Kind(kind) => self.by_kind.entry(kind).or_default(),
+        }
+    }
+
+    /// The orders and participants `event` involves, keeping track of who
+    /// owns each live order.
+    fn involved(
+        &mut self,
+        event: &OrderEvent,
+    ) -> ([Option<OrderId>; 2], [Option<ParticipantId>; 2]) {
+        match *event {
+            OrderEvent::Placed {
+                id, participant, ..
+            }
+            | OrderEvent::Quoted {
+                id, participant, ..
+            }
+            | OrderEvent::Restored {
+                id, participant, ..
+            } => {
+                self.owners.insert(id, participant);
+                ([Some(id), None], [Some(participant), None])
+            }
+            OrderEvent::Trade(trade) | OrderEvent::TradeBust { trade, .. } => (
+                [Some(trade.maker_order_id), Some(trade.taker_order_id)],
+                [Some(trade.maker_participant), Some(trade.taker_participant)],
+            ),
+            OrderEvent::PartiallyFilled { id, .. } => {
+                ([Some(id), None], [self.owners.get(&id).copied(), None])
+            }
+            OrderEvent::Filled { id, .. } | OrderEvent::Canceled { id } => {
+                ([Some(id), None], [self.owners.remove(&id), None])
+            }
+            OrderEvent::Rejected { participant, .. }
+            | OrderEvent::KillSwitch { participant, .. }
+            | OrderEvent::ProtectionTriggered { participant, .. } => {
+                ([None, None], [Some(participant), None])
+            }
+            OrderEvent::Modified
+            | OrderEvent::StatusChanged { .. }
+            | OrderEvent::Settled { .. }
+            | OrderEvent::SessionEnded { .. } => ([None, None], [None, None]),
+        }
+    }
+}
+
+impl<S: EventSink> EventSink for Dispatcher<S> {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        let (orders, participants) = self.involved(event);
+        let mut matched = std::mem::take(&mut self.matched);
+        matched.clear();
+        matched.extend(
+            self.by_kind
+                .get(&EventKind::of(event))
+                .into_iter()
+                .flatten(),
+        );
+        for order in orders.into_iter().flatten() {
+            matched.extend(self.by_order.get(&order).into_iter().flatten());
+        }
+        for participant in participants.into_iter().flatten() {
+            matched.extend(self.by_participant.get(&participant).into_iter().flatten());
+        }
+        // An event reaches each subscriber once, in subscription order.
+        matched.sort_unstable();
+        matched.dedup();
+        let mut result = Ok(());
+        for id in &matched {
+            if let Some((_, sink)) = self.subscribers.get_mut(id) {
+                result = sink.publish(symbol, event);
+                if result.is_err() {
+                    break;
+                }
+            }
+        }
+        self.matched = matched;
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.subscribers
+            .values_mut()
+            .try_for_each(|(_, sink)| sink.flush())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dispatcher, EventFilter, EventKind};
+    use crate::sink::publish_events;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+
+    #[test]
+    fn hands_each_subscriber_what_it_asked_for() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        for (side, price, qty, participant) in [
+            (Side::Sell, 122, 5, 1),
+            (Side::Sell, 123, 5, 3),
+            (Side::Buy, 122, 3, 2),
+        ] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant,
+            });
+        }
+        order_book.process_command(OrderCommand::Cancel {
+            id: 2,
+            price: 123,
+            side: Side::Sell,
+        });
+
+        let mut dispatcher = Dispatcher::<Vec<OrderEvent>>::new();
+        let seller = dispatcher.subscribe(EventFilter::Participant(1), Vec::new());
+        let order = dispatcher.subscribe(EventFilter::Order(2), Vec::new());
+        let trades = dispatcher.subscribe(EventFilter::Kind(EventKind::Trade), Vec::new());
+        let unwanted = dispatcher.subscribe(EventFilter::Participant(9), Vec::new());
+        publish_events(&mut dispatcher, "ABC", order_book.events()).unwrap();
+
+        let kinds = |events: Vec<OrderEvent>| -> Vec<EventKind> {
+            events.iter().map(EventKind::of).collect()
+        };
+        use EventKind::*;
+        assert_eq!(
+            kinds(dispatcher.unsubscribe(seller).unwrap()),
+            [Placed, Trade, PartiallyFilled]
+        );
+        assert_eq!(
+            kinds(dispatcher.unsubscribe(order).unwrap()),
+            [Placed, Canceled]
+        );
+        assert_eq!(kinds(dispatcher.unsubscribe(trades).unwrap()), [Trade]);
+        assert_eq!(dispatcher.get_mut(unwanted), Some(&mut Vec::new()));
+        assert!(dispatcher.unsubscribe(seller).is_none());
+    }
+}
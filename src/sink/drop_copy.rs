@@ -0,0 +1,274 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Per-participant drop copy.
+//!
+//! [`DropCopy`] sits on the main event stream and re-publishes every order
+//! state change and execution to the participant that owns the order, so risk
+//! and compliance systems can follow a firm's activity without consuming the
+//! whole feed. Only `Placed` and `Trade` carry a participant, so the sink
+//! remembers which participant owns each live order and forgets it once the
+//! order is filled or canceled.
+
+use super::EventSink;
+use crate::{OrderEvent, OrderId, ParticipantId};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::mpsc::Sender;
+
+/// Receives events already attributed to a participant.
+pub trait DropCopySink {
+    fn publish(
+        &mut self,
+        participant: ParticipantId,
+        symbol: &str,
+        event: &OrderEvent,
+    ) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropCopyRecord {
+    pub participant: ParticipantId,
+    pub symbol: String,
+    pub event: OrderEvent,
+}
+
+impl DropCopySink for Vec<DropCopyRecord> {
+    fn publish(
+        &mut self,
+        participant: ParticipantId,
+        symbol: &str,
+        event: &OrderEvent,
+    ) -> io::Result<()> {
+        self.push(DropCopyRecord {
+            participant,
+            symbol: symbol.to_string(),
+            event: event.clone(),
+        });
+        Ok(())
+    }
+}
+
+/// Hands records to another thread, e.g. a compliance writer.
+impl DropCopySink for Sender<DropCopyRecord> {
+    fn publish(
+        &mut self,
+        participant: ParticipantId,
+        symbol: &str,
+        event: &OrderEvent,
+    ) -> io::Result<()> {
+        let record = DropCopyRecord {
+            participant,
+            symbol: symbol.to_string(),
+            event: event.clone(),
+        };
+        self.send(record)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "drop copy receiver gone"))
+    }
+}
+
+/// One ordinary [`EventSink`] per participant; events for participants
+/// without an entry are dropped.
+impl<S: EventSink> DropCopySink for HashMap<ParticipantId, S> {
+    fn publish(
+        &mut self,
+        participant: ParticipantId,
+        symbol: &str,
+        event: &OrderEvent,
+    ) -> io::Result<()> {
+        match self.get_mut(&participant) {
+            Some(sink) => sink.publish(symbol, event),
+            None => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.values_mut().try_for_each(EventSink::flush)
+    }
+}
+
+pub struct DropCopy<S> {
+    sink: S,
+    owners: HashMap<OrderId, ParticipantId>,
+    participants: Option<HashSet<ParticipantId>>,
+}
+
+impl<S: DropCopySink> DropCopy<S> {
+    pub fn new(sink: S) -> Self {
+        DropCopy {
+            sink,
+            owners: HashMap::new(),
+            participants: None,
+        }
+    }
+
+    /// Restricts the copy to `participants`. Without a filter every
+    /// participant's activity is forwarded.
+    pub fn only<I: IntoIterator<Item = ParticipantId>>(mut self, participants: I) -> Self {
+        self.participants = Some(participants.into_iter().collect());
+        self
+    }
+
+    /// Participant owning the live order `id`, if it has been seen.
+    pub fn owner(&self, id: OrderId) -> Option<ParticipantId> {
+        self.owners.get(&id).copied()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    fn forward(
+        &mut self,
+        participant: ParticipantId,
+        symbol: &str,
+        event: &OrderEvent,
+    ) -> io::Result<()> {
+        match &self.participants {
+            Some(wanted) if !wanted.contains(&participant) => Ok(()),
+            _ => self.sink.publish(participant, symbol, event),
+        }
+    }
+}
+
+impl<S: DropCopySink> EventSink for DropCopy<S> {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        match *event {
+            OrderEvent::Placed {
+                id, participant, ..
+            }
+            | OrderEvent::Quoted {
+                id, participant, ..
+            }
+            | OrderEvent::Restored {
+                id, participant, ..
+            } => {
+                self.owners.insert(id, participant);
+                self.forward(participant, symbol, event)
+            }
+            OrderEvent::Trade(trade) | OrderEvent::TradeBust { trade, .. } => {
+                self.forward(trade.maker_participant, symbol, event)?;
+                if trade.taker_participant != trade.maker_participant {
+                    self.forward(trade.taker_participant, symbol, event)?;
+                }
+                Ok(())
+            }
+            OrderEvent::PartiallyFilled { id, .. } => match self.owner(id) {
+                Some(participant) => self.forward(participant, symbol, event),
+                None => Ok(()),
+            },
+            OrderEvent::Filled { id, .. } | OrderEvent::Canceled { id } => {
+                match self.owners.remove(&id) {
+                    Some(participant) => self.forward(participant, symbol, event),
+                    None => Ok(()),
+                }
+            }
+            OrderEvent::Rejected { participant, .. }
+            | OrderEvent::KillSwitch { participant, .. }
+            | OrderEvent::ProtectionTriggered { participant, .. } => {
+                self.forward(participant, symbol, event)
+            }
+            OrderEvent::Modified
+            | OrderEvent::StatusChanged { .. }
+            | OrderEvent::Settled { .. }
+            | OrderEvent::SessionEnded { .. } => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DropCopy, DropCopyRecord};
+    use crate::sink::jsonl::JsonLinesSink;
+    use crate::sink::publish_events;
+    use crate::testing::new_order_for;
+    use crate::{OrderBook, OrderEvent, ParticipantId, Side};
+    use std::collections::HashMap;
+
+    fn trading_session() -> OrderBook {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        order_book.process_command(new_order_for(Side::Sell, 122, 5, 1));
+        order_book.process_command(new_order_for(Side::Sell, 123, 5, 3));
+        order_book.process_command(new_order_for(Side::Buy, 122, 3, 2));
+        order_book
+    }
+
+    fn participants(records: &[DropCopyRecord]) -> Vec<ParticipantId> {
+        records.iter().map(|record| record.participant).collect()
+    }
+
+    #[test]
+    fn routes_events_to_owning_participant() {
+        let order_book = trading_session();
+        let mut drop_copy = DropCopy::new(Vec::new());
+        publish_events(&mut drop_copy, order_book.symbol(), order_book.events()).unwrap();
+
+        let records = drop_copy.into_inner();
+        // Placed x3, then the trade to both sides, the maker's partial fill
+        // and the taker's fill.
+        assert_eq!(participants(&records), [1, 3, 2, 1, 2, 1, 2]);
+        assert!(matches!(records[3].event, OrderEvent::Trade(_)));
+        assert!(matches!(
+            records[5].event,
+            OrderEvent::PartiallyFilled { .. }
+        ));
+        assert!(matches!(records[6].event, OrderEvent::Filled { .. }));
+        assert!(records.iter().all(|record| record.symbol == "ABC"));
+    }
+
+    #[test]
+    fn filters_by_participant() {
+        let order_book = trading_session();
+        let mut drop_copy = DropCopy::new(Vec::new()).only([2]);
+        publish_events(&mut drop_copy, order_book.symbol(), order_book.events()).unwrap();
+
+        let records = drop_copy.into_inner();
+        assert_eq!(participants(&records), [2, 2, 2]);
+    }
+
+    #[test]
+    fn self_trade_is_copied_once_and_owners_are_pruned() {
+        let mut order_book = OrderBook::new();
+        order_book.process_command(new_order_for(Side::Sell, 122, 2, 4));
+        order_book.process_command(new_order_for(Side::Buy, 122, 2, 4));
+        let mut drop_copy = DropCopy::new(Vec::new());
+        publish_events(&mut drop_copy, "ABC", order_book.events()).unwrap();
+
+        let trades = drop_copy
+            .get_ref()
+            .iter()
+            .filter(|record| matches!(record.event, OrderEvent::Trade(_)))
+            .count();
+        assert_eq!(trades, 1);
+        assert_eq!(drop_copy.owner(order_book.trades()[0].maker_order_id), None);
+    }
+
+    #[test]
+    fn fans_out_to_per_participant_sinks() {
+        let order_book = trading_session();
+        let sinks = HashMap::from([(1, JsonLinesSink::new(Vec::new()))]);
+        let mut drop_copy = DropCopy::new(sinks);
+        publish_events(&mut drop_copy, order_book.symbol(), order_book.events()).unwrap();
+
+        let mut sinks = drop_copy.into_inner();
+        let output = String::from_utf8(sinks.remove(&1).unwrap().into_inner()).unwrap();
+        assert_eq!(output.lines().count(), 3);
+    }
+}
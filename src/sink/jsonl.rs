@@ -0,0 +1,211 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Newline delimited JSON event stream.
+//!
+//! Each event becomes one line:
+//!
+//! ```text
+//! {"seq":1,"time":"2024-09-01T12:00:00.000000123Z","symbol":"ABC","event":{"Canceled":{"id":4}}}
+//! ```
+//!
+//! which keeps the output friendly to `tail -f`, `grep` and `jq`.
+
+use super::EventSink;
+use crate::{OrderEvent, Timestamp};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Stdout, Write};
+use std::path::Path;
+
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+    next_sequence: u64,
+}
+
+impl JsonLinesSink<Stdout> {
+    pub fn stdout() -> Self {
+        JsonLinesSink::new(io::stdout())
+    }
+}
+
+impl JsonLinesSink<BufWriter<File>> {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn append<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(JsonLinesSink::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesSink {
+            writer,
+            next_sequence: 1,
+        }
+    }
+
+    /// Continues numbering from `sequence`, e.g. when appending to a file
+    /// that already holds earlier events.
+    pub fn starting_at(mut self, sequence: u64) -> Self {
+        self.next_sequence = sequence;
+        self
+    }
+
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> EventSink for JsonLinesSink<W> {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        let now = Timestamp::now().as_nanos();
+        let line = serde_json::json!({
+            "seq": self.next_sequence,
+            "time": format_rfc3339(now),
+            "symbol": symbol,
+            "event": event,
+        });
+        serde_json::to_writer(&mut self.writer, &line).map_err(io::Error::other)?;
+        self.writer.write_all(b"\n")?;
+        self.next_sequence += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Formats nanoseconds since the Unix epoch as an RFC 3339 UTC timestamp.
+pub fn format_rfc3339(epoch_nanos: u64) -> String {
+    let secs = epoch_nanos / 1_000_000_000;
+    let nanos = epoch_nanos % 1_000_000_000;
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Howard Hinnant's days-to-civil algorithm.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60,
+        nanos
+    )
+}
+
+/// Reads back what [`format_rfc3339`] writes: UTC, with up to nine
+/// fractional digits.
+pub fn parse_rfc3339(time: &str) -> Option<u64> {
+    let (date, time) = time.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1..=12).contains(&month) || fraction.len() > 9 {
+        return None;
+    }
+    let nanos = match fraction {
+        "" => 0,
+        digits => digits.parse::<u64>().ok()? * 10_u64.pow(9 - digits.len() as u32),
+    };
+
+    // Howard Hinnant's days-from-civil algorithm.
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146_097 + doe - 719_468).ok()?;
+
+    Some(((days * 86_400 + hour * 3_600 + minute * 60 + second) * 1_000_000_000) + nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_rfc3339, parse_rfc3339, JsonLinesSink};
+    use crate::sink::publish_events;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00.000000000Z");
+        assert_eq!(
+            format_rfc3339(951_827_696_000_000_123),
+            "2000-02-29T12:34:56.000000123Z"
+        );
+        assert_eq!(
+            format_rfc3339(1_725_192_000_500_000_000),
+            "2024-09-01T12:00:00.500000000Z"
+        );
+        for nanos in [0, 951_827_696_000_000_123, 1_725_192_000_500_000_000] {
+            assert_eq!(parse_rfc3339(&format_rfc3339(nanos)), Some(nanos));
+        }
+        assert_eq!(
+            parse_rfc3339("2024-09-01T12:00:00.5Z"),
+            Some(1_725_192_000_500_000_000)
+        );
+        assert_eq!(parse_rfc3339("2024-13-01T12:00:00Z"), None);
+    }
+
+    #[test]
+    fn writes_one_sequenced_object_per_line() {
+        let mut sink = JsonLinesSink::new(Vec::new()).starting_at(10);
+        let events = [
+            OrderEvent::Canceled { id: 1 },
+            OrderEvent::Canceled { id: 2 },
+        ];
+        publish_events(&mut sink, "ABC", &events).unwrap();
+        assert_eq!(sink.next_sequence(), 12);
+
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["seq"], 10);
+        assert_eq!(lines[1]["seq"], 11);
+        assert_eq!(lines[1]["symbol"], "ABC");
+        assert_eq!(lines[1]["event"]["Canceled"]["id"], 2);
+        assert!(lines[0]["time"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn events_round_trip_exactly() {
+        let mut order_book = OrderBook::new();
+        for side in [Side::Sell, Side::Buy] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price: 100,
+                qty: 5,
+                participant: 1,
+            });
+        }
+        for event in order_book.events() {
+            let json = serde_json::to_string(event).unwrap();
+            let decoded: OrderEvent = serde_json::from_str(&json).unwrap();
+            assert_eq!(&decoded, event);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Kafka publisher for the event stream.
+//!
+//! The engine does not link a Kafka client itself. Embedders wrap the
+//! producer they already use (rdkafka, kafka-rust, ...) in [`KafkaProducer`]
+//! and this sink takes care of topic routing, keying and encoding.
+
+use super::{EventEncoder, EventSink, JsonEncoder};
+use crate::OrderEvent;
+use std::io;
+
+pub trait KafkaProducer {
+    fn send(&mut self, topic: &str, key: &[u8], payload: &[u8]) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KafkaConfig {
+    /// Topic for order lifecycle events.
+    pub events_topic: String,
+    /// Topic for executions. Trades are published here instead of
+    /// `events_topic` so downstream consumers can subscribe to fills only.
+    pub trades_topic: String,
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        KafkaConfig {
+            events_topic: "matcher.events".to_string(),
+            trades_topic: "matcher.trades".to_string(),
+        }
+    }
+}
+
+/// Every message is keyed by symbol so a symbol's events stay ordered within
+/// a single partition.
+pub struct KafkaSink<P, E = JsonEncoder> {
+    producer: P,
+    encoder: E,
+    config: KafkaConfig,
+}
+
+impl<P: KafkaProducer> KafkaSink<P> {
+    pub fn new(producer: P, config: KafkaConfig) -> Self {
+        KafkaSink::with_encoder(producer, JsonEncoder, config)
+    }
+}
+
+impl<P: KafkaProducer, E: EventEncoder> KafkaSink<P, E> {
+    pub fn with_encoder(producer: P, encoder: E, config: KafkaConfig) -> Self {
+        KafkaSink {
+            producer,
+            encoder,
+            config,
+        }
+    }
+
+    pub fn producer(&self) -> &P {
+        &self.producer
+    }
+}
+
+impl<P: KafkaProducer, E: EventEncoder> EventSink for KafkaSink<P, E> {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        let topic = match event {
+            OrderEvent::Trade(_) | OrderEvent::TradeBust { .. } => &self.config.trades_topic,
+            _ => &self.config.events_topic,
+        };
+        let payload = self.encoder.encode(symbol, event)?;
+        self.producer.send(topic, symbol.as_bytes(), &payload)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.producer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KafkaConfig, KafkaProducer, KafkaSink};
+    use crate::sink::publish_events;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::io;
+
+    #[derive(Default)]
+    struct RecordingProducer {
+        sent: Vec<(String, Vec<u8>, Vec<u8>)>,
+    }
+
+    impl KafkaProducer for RecordingProducer {
+        fn send(&mut self, topic: &str, key: &[u8], payload: &[u8]) -> io::Result<()> {
+            self.sent
+                .push((topic.to_string(), key.to_vec(), payload.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn routes_trades_and_keys_by_symbol() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        for side in [Side::Sell, Side::Buy] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price: 122,
+                qty: 1,
+                participant: 1,
+            });
+        }
+        let mut sink = KafkaSink::new(RecordingProducer::default(), KafkaConfig::default());
+        publish_events(&mut sink, order_book.symbol(), order_book.events()).unwrap();
+
+        let sent = &sink.producer().sent;
+        assert_eq!(sent.len(), order_book.events().len());
+        assert!(sent.iter().all(|(_, key, _)| key == b"ABC"));
+        let trades: Vec<_> = sent
+            .iter()
+            .filter(|(topic, _, _)| topic == "matcher.trades")
+            .collect();
+        assert_eq!(trades.len(), 1);
+        let payload: serde_json::Value = serde_json::from_slice(&trades[0].2).unwrap();
+        assert_eq!(payload["symbol"], "ABC");
+        assert_eq!(payload["event"]["Trade"]["price"], 122);
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Parquet event archive.
+//!
+//! A [`ParquetSink`] holds each symbol's events and writes them out as
+//! Parquet files laid out the way query engines expect partitions:
+//!
+//! ```text
+//! <root>/symbol=ABC/session=0/part-00000.parquet
+//! ```
+//!
+//! A file is written when a symbol has held `max_events`, when its oldest
+//! held event is `flush_interval` old, when its session ends and on
+//! [`EventSink::flush`]. The interval is checked as events arrive, so a
+//! quiet symbol's events wait for the next one or a flush. Sessions are
+//! numbered from 0 per symbol, moving on at each `SessionEnded`; events are
+//! numbered from 1 per symbol. The rows are those of
+//! [`arrow::events`](crate::arrow::events). Existing files are never
+//! overwritten, so a restarted archive carries on at the next free part
+//! number.
+
+use super::EventSink;
+use crate::arrow::{self, parquet::ParquetWriter};
+use crate::clock::{Clock, RealClock};
+use crate::{OrderEvent, Timestamp};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParquetConfig {
+    pub root: PathBuf,
+    /// Most events a symbol holds before they are written.
+    pub max_events: usize,
+    /// Longest an event is held before it is written.
+    pub flush_interval: Duration,
+}
+
+impl ParquetConfig {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        ParquetConfig {
+            root: root.as_ref().to_path_buf(),
+            max_events: 100_000,
+            flush_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Partition {
+    session: u64,
+    next_part: u64,
+    next_sequence: u64,
+    events: Vec<OrderEvent>,
+    /// When the oldest held event arrived.
+    since: Option<Timestamp>,
+}
+
+#[derive(Debug)]
+pub struct ParquetSink<C = RealClock> {
+    config: ParquetConfig,
+    clock: C,
+    partitions: HashMap<String, Partition>,
+}
+
+impl ParquetSink {
+    pub fn new(config: ParquetConfig) -> Self {
+        ParquetSink::with_clock(config, RealClock)
+    }
+}
+
+impl<C: Clock> ParquetSink<C> {
+    /// Times `flush_interval` by `clock`.
+    pub fn with_clock(config: ParquetConfig, clock: C) -> Self {
+        ParquetSink {
+            config,
+            clock,
+            partitions: HashMap::new(),
+        }
+    }
+
+    /// Events held for `symbol`, not yet written.
+    pub fn pending(&self, symbol: &str) -> usize {
+        self.partitions
+            .get(symbol)
+            .map_or(0, |partition| partition.events.len())
+    }
+
+    /// Writes `symbol`'s held events to a new file.
+    fn write(&mut self, symbol: &str) -> io::Result<()> {
+        let Some(partition) = self.partitions.get_mut(symbol) else {
+            return Ok(());
+        };
+        if partition.events.is_empty() {
+            return Ok(());
+        }
+        let dir = self
+            .config
+            .root
+            .join(format!("symbol={}", symbol))
+            .join(format!("session={}", partition.session));
+        fs::create_dir_all(&dir)?;
+        let path = loop {
+            let path = dir.join(format!("part-{:05}.parquet", partition.next_part));
+            partition.next_part += 1;
+            if !path.exists() {
+                break path;
+            }
+        };
+        let batch = arrow::events_from(partition.next_sequence, &partition.events);
+        let mut writer = ParquetWriter::new(BufWriter::new(File::create(path)?), batch.schema())?;
+        writer.write(&batch)?;
+        writer.finish()?;
+        partition.next_sequence += partition.events.len() as u64;
+        partition.events.clear();
+        partition.since = None;
+        Ok(())
+    }
+}
+
+impl<C: Clock> EventSink for ParquetSink<C> {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        let now = self.clock.now();
+        let partition = self
+            .partitions
+            .entry(symbol.to_string())
+            .or_insert_with(|| Partition {
+                next_sequence: 1,
+                ..Partition::default()
+            });
+        partition.events.push(event.clone());
+        let since = *partition.since.get_or_insert(now);
+        let full = partition.events.len() >= self.config.max_events
+            || now.saturating_duration_since(since) >= self.config.flush_interval;
+        if let OrderEvent::SessionEnded { .. } = event {
+            self.write(symbol)?;
+            let partition = self.partitions.get_mut(symbol).unwrap();
+            partition.session += 1;
+            partition.next_part = 0;
+        } else if full {
+            self.write(symbol)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let symbols: Vec<String> = self.partitions.keys().cloned().collect();
+        for symbol in symbols {
+            self.write(&symbol)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParquetConfig, ParquetSink};
+    use crate::clock::ManualClock;
+    use crate::sink::{publish_events, EventSink};
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+    use std::time::Duration;
+
+    #[test]
+    fn partitions_by_symbol_and_session() {
+        let root = std::env::temp_dir().join(format!("parquet-{}", std::process::id()));
+        let clock = ManualClock::new();
+        let mut sink = ParquetSink::with_clock(
+            ParquetConfig {
+                max_events: 3,
+                flush_interval: Duration::from_secs(10),
+                ..ParquetConfig::new(&root)
+            },
+            clock.clone(),
+        );
+        let mut order_book = OrderBook::with_symbol("ABC");
+        for price in [99, 98] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side: Side::Buy,
+                price,
+                qty: 1,
+                participant: 1,
+            });
+        }
+        order_book.end_of_day();
+        // Two placements, then the day's end.
+        publish_events(&mut sink, "ABC", order_book.events()).unwrap();
+        let session = root.join("symbol=ABC");
+        assert!(session.join("session=0/part-00000.parquet").exists());
+
+        sink.publish("XYZ", &order_book.events()[0]).unwrap();
+        assert_eq!(sink.pending("XYZ"), 1);
+        clock.advance(Duration::from_secs(10));
+        sink.publish("XYZ", &order_book.events()[1]).unwrap();
+        assert_eq!(sink.pending("XYZ"), 0);
+        sink.publish("ABC", &order_book.events()[0]).unwrap();
+        sink.flush().unwrap();
+        assert!(root
+            .join("symbol=XYZ/session=0/part-00000.parquet")
+            .exists());
+        let file = std::fs::read(session.join("session=1/part-00000.parquet")).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(&file[..4], b"PAR1");
+    }
+
+    fn placed() -> OrderEvent {
+        let mut order_book = OrderBook::new();
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 99,
+            qty: 1,
+            participant: 1,
+        });
+        order_book.events()[0].clone()
+    }
+
+    #[test]
+    fn flushing_nothing_writes_nothing() {
+        let root = std::env::temp_dir().join(format!("parquet-empty-{}", std::process::id()));
+        let mut sink = ParquetSink::new(ParquetConfig::new(&root));
+        sink.flush().unwrap();
+        assert_eq!(sink.pending("ABC"), 0);
+        assert!(!root.exists());
+    }
+
+    #[test]
+    fn carries_on_past_existing_parts() {
+        let root = std::env::temp_dir().join(format!("parquet-resume-{}", std::process::id()));
+        let session = root.join("symbol=ABC/session=0");
+        std::fs::create_dir_all(&session).unwrap();
+        std::fs::write(session.join("part-00000.parquet"), b"kept").unwrap();
+
+        let mut sink = ParquetSink::new(ParquetConfig {
+            max_events: 1,
+            ..ParquetConfig::new(&root)
+        });
+        sink.publish("ABC", &placed()).unwrap();
+        let kept = std::fs::read(session.join("part-00000.parquet")).unwrap();
+        let written = session.join("part-00001.parquet").exists();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(kept, b"kept");
+        assert!(written);
+    }
+
+    #[test]
+    fn keeps_events_it_could_not_write() {
+        // The root is a file, so no partition directory can be made under it.
+        let root = std::env::temp_dir().join(format!("parquet-blocked-{}", std::process::id()));
+        std::fs::write(&root, b"").unwrap();
+        let mut sink = ParquetSink::new(ParquetConfig {
+            max_events: 1,
+            ..ParquetConfig::new(&root)
+        });
+        let published = sink.publish("ABC", &placed());
+        let flushed = sink.flush();
+        std::fs::remove_file(&root).unwrap();
+        assert!(published.is_err());
+        assert!(flushed.is_err());
+        assert_eq!(sink.pending("ABC"), 1);
+
+        // Once the way is clear the held event goes out.
+        sink.flush().unwrap();
+        let written = root
+            .join("symbol=ABC/session=0/part-00000.parquet")
+            .exists();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(written);
+        assert_eq!(sink.pending("ABC"), 0);
+    }
+}
@@ -0,0 +1,216 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Redis pub/sub broadcaster for events and BBO updates.
+//!
+//! Speaks RESP directly over a socket. For a symbol `ABC` and the default
+//! prefix, messages go to:
+//! * `matcher:ABC:events` - every event, JSON encoded
+//! * `matcher:ABC:bbo` - the best bid/offer whenever it changes
+//!
+//! With `snapshot_keys` enabled the latest BBO and depth are also stored under
+//! `matcher:ABC:bbo:latest` and `matcher:ABC:depth:latest` so late joiners can
+//! seed their state before subscribing.
+
+use super::{EventEncoder, EventSink, JsonEncoder};
+use crate::order_book::Bbo;
+use crate::{OrderBook, OrderEvent};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+const SNAPSHOT_LEVELS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedisConfig {
+    pub channel_prefix: String,
+    pub snapshot_keys: bool,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        RedisConfig {
+            channel_prefix: "matcher".to_string(),
+            snapshot_keys: false,
+        }
+    }
+}
+
+pub struct RedisPublisher<S: Read + Write = TcpStream> {
+    conn: BufReader<S>,
+    config: RedisConfig,
+    encoder: JsonEncoder,
+    last_bbo: HashMap<String, Bbo>,
+}
+
+impl RedisPublisher<TcpStream> {
+    pub fn connect<A: ToSocketAddrs>(addr: A, config: RedisConfig) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(RedisPublisher::new(stream, config))
+    }
+}
+
+impl<S: Read + Write> RedisPublisher<S> {
+    pub fn new(stream: S, config: RedisConfig) -> Self {
+        RedisPublisher {
+            conn: BufReader::new(stream),
+            config,
+            encoder: JsonEncoder,
+            last_bbo: HashMap::new(),
+        }
+    }
+
+    pub fn channel(&self, symbol: &str, kind: &str) -> String {
+        format!("{}:{}:{}", self.config.channel_prefix, symbol, kind)
+    }
+
+    /// Publishes the book's BBO if it changed since the last call for this
+    /// symbol, and refreshes the snapshot keys when they are enabled.
+    pub fn publish_book(&mut self, order_book: &OrderBook) -> io::Result<()> {
+        let symbol = order_book.symbol();
+        let bbo = order_book.bbo();
+        if self.last_bbo.get(symbol) != Some(&bbo) {
+            let payload = serde_json::to_vec(&bbo).map_err(io::Error::other)?;
+            let channel = self.channel(symbol, "bbo");
+            self.command(&[b"PUBLISH", channel.as_bytes(), &payload])?;
+            if self.config.snapshot_keys {
+                let key = self.channel(symbol, "bbo:latest");
+                self.command(&[b"SET", key.as_bytes(), &payload])?;
+            }
+            self.last_bbo.insert(symbol.to_string(), bbo);
+        }
+        if self.config.snapshot_keys {
+            let depth = order_book.depth(SNAPSHOT_LEVELS);
+            let payload = serde_json::to_vec(&depth).map_err(io::Error::other)?;
+            let key = self.channel(symbol, "depth:latest");
+            self.command(&[b"SET", key.as_bytes(), &payload])?;
+        }
+        Ok(())
+    }
+
+    /// Sends one command and waits for its reply so Redis errors surface on
+    /// the call that caused them.
+    fn command(&mut self, args: &[&[u8]]) -> io::Result<()> {
+        let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            buf.extend_from_slice(arg);
+            buf.extend_from_slice(b"\r\n");
+        }
+        self.conn.get_mut().write_all(&buf)?;
+
+        let mut reply = String::new();
+        if self.conn.read_line(&mut reply)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "redis closed the connection",
+            ));
+        }
+        match reply.as_bytes().first() {
+            Some(b'-') => Err(io::Error::other(format!(
+                "redis: {}",
+                reply[1..].trim_end()
+            ))),
+            Some(b'+') | Some(b':') => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected redis reply: {:?}", reply),
+            )),
+        }
+    }
+}
+
+impl<S: Read + Write> EventSink for RedisPublisher<S> {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        let payload = self.encoder.encode(symbol, event)?;
+        let channel = self.channel(symbol, "events");
+        self.command(&[b"PUBLISH", channel.as_bytes(), &payload])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.conn.get_mut().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RedisConfig, RedisPublisher};
+    use crate::sink::EventSink;
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+    use std::io::{self, Cursor, Read, Write};
+
+    /// Replays canned replies and records everything written.
+    struct FakeRedis {
+        replies: Cursor<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl Read for FakeRedis {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for FakeRedis {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn fake(replies: &str) -> FakeRedis {
+        FakeRedis {
+            replies: Cursor::new(replies.as_bytes().to_vec()),
+            written: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn publishes_events_on_symbol_channel() {
+        let mut publisher = RedisPublisher::new(fake(":1\r\n"), RedisConfig::default());
+        publisher
+            .publish("ABC", &OrderEvent::Canceled { id: 7 })
+            .unwrap();
+        let written = String::from_utf8(publisher.conn.get_ref().written.clone()).unwrap();
+        assert!(written.starts_with("*3\r\n$7\r\nPUBLISH\r\n$18\r\nmatcher:ABC:events\r\n"));
+        assert!(written.contains(r#""Canceled":{"id":7}"#));
+    }
+
+    #[test]
+    fn bbo_published_only_on_change() {
+        let config = RedisConfig {
+            snapshot_keys: true,
+            ..RedisConfig::default()
+        };
+        let mut publisher = RedisPublisher::new(fake(":0\r\n+OK\r\n+OK\r\n+OK\r\n"), config);
+        let mut order_book = OrderBook::with_symbol("ABC");
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side: Side::Buy,
+            price: 122,
+            qty: 1,
+            participant: 1,
+        });
+        publisher.publish_book(&order_book).unwrap();
+        publisher.publish_book(&order_book).unwrap();
+
+        let written = String::from_utf8(publisher.conn.get_ref().written.clone()).unwrap();
+        assert_eq!(written.matches("PUBLISH").count(), 1);
+        assert_eq!(written.matches("matcher:ABC:depth:latest").count(), 2);
+    }
+
+    #[test]
+    fn surfaces_redis_errors() {
+        let mut publisher = RedisPublisher::new(fake("-ERR wrong\r\n"), RedisConfig::default());
+        let err = publisher
+            .publish("ABC", &OrderEvent::Canceled { id: 7 })
+            .unwrap_err();
+        assert!(err.to_string().contains("ERR wrong"));
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Retransmission of the event stream.
+//!
+//! [`RetransmitRing`] keeps the last `capacity` events published to it in a
+//! file of fixed-size slots, numbered from 1 like [`JsonLinesSink`]. A
+//! consumer that spots a gap in the sequence asks for the range it missed
+//! with [`RetransmitRing::retransmit`] instead of resubscribing and
+//! resyncing the whole book. Once the stream has moved more than
+//! `capacity` events past a range it is gone and the request is refused.
+//!
+//! ```text
+//! header: magic: [u8; 8] | capacity: u64
+//! slot:   sequence: u64 | symbol_len: u8 | symbol | len: u16 | SBE event
+//! ```
+//!
+//! Event `n` is in slot `(n - 1) % capacity`, and a slot whose sequence is
+//! 0 has never been written. Slots are [`SLOT_LEN`] bytes long.
+//!
+//! [`JsonLinesSink`]: super::jsonl::JsonLinesSink
+
+use super::EventSink;
+use crate::codec::{invalid, sbe, Reader};
+use crate::OrderEvent;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+const MAGIC: [u8; 8] = *b"MATCHRNG";
+const HEADER_LEN: u64 = 16;
+pub const SLOT_LEN: usize = 256;
+
+#[derive(Debug)]
+pub struct RetransmitRing<F = File> {
+    storage: F,
+    capacity: u64,
+    next_sequence: u64,
+}
+
+impl RetransmitRing<File> {
+    /// Creates a ring of `capacity` slots at `path`, replacing any file
+    /// there.
+    pub fn create<P: AsRef<Path>>(path: P, capacity: u64) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        RetransmitRing::new(file, capacity)
+    }
+
+    /// Opens a ring written by an earlier run. Numbering carries on after
+    /// the newest event in it.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        RetransmitRing::resume(file)
+    }
+}
+
+impl<F: Read + Write + Seek> RetransmitRing<F> {
+    /// Lays out an empty ring of `capacity` slots in `storage`.
+    pub fn new(mut storage: F, capacity: u64) -> io::Result<Self> {
+        if capacity == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "retransmit ring needs at least one slot",
+            ));
+        }
+        storage.seek(SeekFrom::Start(0))?;
+        storage.write_all(&MAGIC)?;
+        storage.write_all(&capacity.to_le_bytes())?;
+        let empty = [0; SLOT_LEN];
+        for _ in 0..capacity {
+            storage.write_all(&empty)?;
+        }
+        storage.flush()?;
+        Ok(RetransmitRing {
+            storage,
+            capacity,
+            next_sequence: 1,
+        })
+    }
+
+    pub fn resume(mut storage: F) -> io::Result<Self> {
+        let mut header = [0; HEADER_LEN as usize];
+        storage.seek(SeekFrom::Start(0))?;
+        storage.read_exact(&mut header)?;
+        if header[..8] != MAGIC {
+            return Err(invalid("not a retransmit ring".to_string()));
+        }
+        let capacity = u64::from_le_bytes(header[8..].try_into().unwrap());
+        let mut newest = 0;
+        let mut sequence = [0; 8];
+        for slot in 0..capacity {
+            storage.seek(SeekFrom::Start(HEADER_LEN + slot * SLOT_LEN as u64))?;
+            storage.read_exact(&mut sequence)?;
+            newest = newest.max(u64::from_le_bytes(sequence));
+        }
+        Ok(RetransmitRing {
+            storage,
+            capacity,
+            next_sequence: newest + 1,
+        })
+    }
+
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// The sequence numbers still held.
+    pub fn available(&self) -> Range<u64> {
+        self.next_sequence.saturating_sub(self.capacity).max(1)..self.next_sequence
+    }
+
+    /// The events numbered `range`, with their symbols, oldest first. Ends
+    /// past the newest event are cut short; a start that has been
+    /// overwritten is an error.
+    pub fn retransmit(&mut self, range: Range<u64>) -> io::Result<Vec<(u64, String, OrderEvent)>> {
+        let available = self.available();
+        if range.start < available.start {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "event {} is no longer held, the oldest is {}",
+                    range.start, available.start
+                ),
+            ));
+        }
+        let mut events = Vec::new();
+        let mut slot = vec![0; SLOT_LEN];
+        for sequence in range.start..range.end.min(available.end) {
+            self.storage.seek(SeekFrom::Start(self.offset(sequence)))?;
+            self.storage.read_exact(&mut slot)?;
+            let mut reader = Reader::new(&slot);
+            let stored = reader.u64()?;
+            if stored != sequence {
+                return Err(invalid(format!(
+                    "slot for event {} holds event {}",
+                    sequence, stored
+                )));
+            }
+            let symbol_len = reader.u8()?;
+            let symbol = String::from_utf8_lossy(reader.take(symbol_len.into())?).into_owned();
+            let len = reader.u16()?;
+            let event = sbe::decode_event(reader.take(len.into())?)?;
+            events.push((sequence, symbol, event));
+        }
+        Ok(events)
+    }
+
+    pub fn into_inner(self) -> F {
+        self.storage
+    }
+
+    fn offset(&self, sequence: u64) -> u64 {
+        HEADER_LEN + (sequence - 1) % self.capacity * SLOT_LEN as u64
+    }
+}
+
+impl<F: Read + Write + Seek> EventSink for RetransmitRing<F> {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        let mut payload = Vec::new();
+        sbe::encode_event(event, &mut payload);
+        let symbol_len = u8::try_from(symbol.len())
+            .map_err(|_| invalid(format!("symbol {:?} too long to retransmit", symbol)))?;
+        let mut slot = Vec::with_capacity(SLOT_LEN);
+        slot.extend_from_slice(&self.next_sequence.to_le_bytes());
+        slot.push(symbol_len);
+        slot.extend_from_slice(symbol.as_bytes());
+        slot.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        slot.extend_from_slice(&payload);
+        if slot.len() > SLOT_LEN {
+            return Err(invalid(format!(
+                "event {} takes {} bytes, more than a slot holds",
+                self.next_sequence,
+                slot.len()
+            )));
+        }
+        self.storage
+            .seek(SeekFrom::Start(self.offset(self.next_sequence)))?;
+        self.storage.write_all(&slot)?;
+        self.next_sequence += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.storage.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetransmitRing;
+    use crate::sink::publish_events;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::io;
+
+    #[test]
+    fn retransmits_what_it_still_holds() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        for (side, price) in [(Side::Buy, 99), (Side::Sell, 101), (Side::Sell, 99)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty: 10,
+                participant: 1,
+            });
+        }
+        let events = order_book.events();
+        assert!(events.len() > 4);
+
+        let path = std::env::temp_dir().join(format!("ring-{}.bin", std::process::id()));
+        let mut ring = RetransmitRing::create(&path, 4).unwrap();
+        publish_events(&mut ring, "ABC", events).unwrap();
+        let next = events.len() as u64 + 1;
+        assert_eq!(ring.available(), next - 4..next);
+        drop(ring);
+
+        let mut ring = RetransmitRing::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ring.next_sequence(), next);
+        let resent = ring.retransmit(next - 2..next + 5).unwrap();
+        let expected: Vec<_> = (next - 2..next)
+            .map(|seq| (seq, "ABC".to_string(), events[seq as usize - 1].clone()))
+            .collect();
+        assert_eq!(resent, expected);
+        let err = ring.retransmit(1..3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,429 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! SQLite event store.
+//!
+//! As with [`kafka`](super::kafka), the engine does not link SQLite itself.
+//! Embedders wrap the connection they already use (rusqlite, sqlx, ...) in
+//! [`SqliteConnection`] and [`SqliteStore`] takes care of the schema and of
+//! what each event writes. Events are held and written a batch at a time,
+//! each batch in one transaction: a batch that fails is rolled back and
+//! kept, and goes again with the next one.
+//!
+//! The schema, [`SCHEMA`], has three tables:
+//! * `events` - every event as JSON, with its kind, symbol and order
+//! * `trades` - a row per trade, `busted` once it has been
+//! * `orders` - a row per order with its latest status and filled quantity
+
+use super::dispatch::EventKind;
+use super::EventSink;
+use crate::{OrderEvent, OrderId, Timestamp, Trade};
+use std::io;
+
+pub const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY,
+        symbol TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        order_id INTEGER,
+        timestamp INTEGER,
+        event TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS trades (
+        symbol TEXT NOT NULL,
+        id INTEGER NOT NULL,
+        price INTEGER NOT NULL,
+        qty INTEGER NOT NULL,
+        aggressor_side TEXT NOT NULL,
+        maker_order_id INTEGER NOT NULL,
+        taker_order_id INTEGER NOT NULL,
+        maker_participant INTEGER NOT NULL,
+        taker_participant INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        maker_fee INTEGER NOT NULL,
+        taker_fee INTEGER NOT NULL,
+        busted INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (symbol, id)
+    )",
+    "CREATE TABLE IF NOT EXISTS orders (
+        symbol TEXT NOT NULL,
+        id INTEGER NOT NULL,
+        participant INTEGER NOT NULL,
+        side TEXT NOT NULL,
+        price INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        filled_qty INTEGER NOT NULL DEFAULT 0,
+        updated INTEGER,
+        PRIMARY KEY (symbol, id)
+    )",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SqlValue {
+    Null,
+    Integer(i64),
+    Text(String),
+}
+
+impl From<Option<u64>> for SqlValue {
+    fn from(value: Option<u64>) -> Self {
+        value.map_or(SqlValue::Null, |v| SqlValue::Integer(v as i64))
+    }
+}
+
+pub trait SqliteConnection {
+    /// Runs one statement, binding `params` to its `?` placeholders in
+    /// order.
+    fn execute(&mut self, sql: &str, params: &[SqlValue]) -> io::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SqliteConfig {
+    /// How many events are written per transaction.
+    pub batch_size: usize,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        SqliteConfig { batch_size: 1000 }
+    }
+}
+
+pub struct SqliteStore<C> {
+    conn: C,
+    config: SqliteConfig,
+    pending: Vec<(String, OrderEvent)>,
+}
+
+impl<C: SqliteConnection> SqliteStore<C> {
+    /// Creates the tables that don't exist yet.
+    pub fn new(mut conn: C, config: SqliteConfig) -> io::Result<Self> {
+        for statement in SCHEMA {
+            conn.execute(statement, &[])?;
+        }
+        Ok(SqliteStore {
+            conn,
+            config,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn connection(&self) -> &C {
+        &self.conn
+    }
+
+    /// Events held for the next batch.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn write_batch(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        self.conn.execute("BEGIN", &[])?;
+        let written = self
+            .pending
+            .iter()
+            .try_for_each(|(symbol, event)| write_event(&mut self.conn, symbol, event));
+        match written.and_then(|()| self.conn.execute("COMMIT", &[])) {
+            Ok(()) => {
+                self.pending.clear();
+                Ok(())
+            }
+            Err(err) => {
+                // The batch stays held; the error is the one worth reporting.
+                let _ = self.conn.execute("ROLLBACK", &[]);
+                Err(err)
+            }
+        }
+    }
+}
+
+fn text(value: impl ToString) -> SqlValue {
+    SqlValue::Text(value.to_string())
+}
+
+fn integer(value: impl Into<i64>) -> SqlValue {
+    SqlValue::Integer(value.into())
+}
+
+fn time(timestamp: Timestamp) -> SqlValue {
+    SqlValue::Integer(timestamp.as_nanos() as i64)
+}
+
+fn write_event<C: SqliteConnection>(
+    conn: &mut C,
+    symbol: &str,
+    event: &OrderEvent,
+) -> io::Result<()> {
+    let json = serde_json::to_string(event).map_err(io::Error::other)?;
+    let (order_id, timestamp): (Option<OrderId>, Option<Timestamp>) = match *event {
+        OrderEvent::Placed { id, timestamp, .. }
+        | OrderEvent::PartiallyFilled { id, timestamp, .. }
+        | OrderEvent::Filled { id, timestamp, .. }
+        | OrderEvent::Quoted { id, timestamp, .. }
+        | OrderEvent::Restored { id, timestamp, .. } => (Some(id), Some(timestamp)),
+        OrderEvent::Canceled { id } => (Some(id), None),
+        OrderEvent::Trade(ref trade) | OrderEvent::TradeBust { ref trade, .. } => {
+            (None, Some(trade.timestamp))
+        }
+        OrderEvent::ProtectionTriggered { timestamp, .. }
+        | OrderEvent::Settled { timestamp, .. }
+        | OrderEvent::SessionEnded { timestamp } => (None, Some(timestamp)),
+        OrderEvent::Modified
+        | OrderEvent::Rejected { .. }
+        | OrderEvent::KillSwitch { .. }
+        | OrderEvent::StatusChanged { .. } => (None, None),
+    };
+    conn.execute(
+        "INSERT INTO events (symbol, kind, order_id, timestamp, event) VALUES (?, ?, ?, ?, ?)",
+        &[
+            text(symbol),
+            text(format!("{:?}", EventKind::of(event))),
+            order_id.into(),
+            timestamp.map_or(SqlValue::Null, time),
+            SqlValue::Text(json),
+        ],
+    )?;
+
+    let status = |conn: &mut C, id: OrderId, status: &str, updated: SqlValue| {
+        conn.execute(
+            "UPDATE orders SET status = ?, updated = COALESCE(?, updated) WHERE symbol = ? AND id = ?",
+            &[text(status), updated, text(symbol), Some(id).into()],
+        )
+    };
+    match *event {
+        OrderEvent::Placed {
+            id,
+            participant,
+            side,
+            price,
+            timestamp,
+            ..
+        }
+        | OrderEvent::Quoted {
+            id,
+            participant,
+            side,
+            price,
+            timestamp,
+            ..
+        }
+        | OrderEvent::Restored {
+            id,
+            participant,
+            side,
+            price,
+            timestamp,
+            ..
+        } => conn.execute(
+            "INSERT INTO orders (symbol, id, participant, side, price, status, updated) \
+             VALUES (?, ?, ?, ?, ?, 'open', ?) \
+             ON CONFLICT (symbol, id) DO UPDATE SET \
+             price = excluded.price, status = 'open', updated = excluded.updated",
+            &[
+                text(symbol),
+                Some(id).into(),
+                integer(participant),
+                text(format!("{:?}", side)),
+                integer(price),
+                time(timestamp),
+            ],
+        ),
+        OrderEvent::Filled { id, timestamp, .. } => status(conn, id, "filled", time(timestamp)),
+        OrderEvent::Canceled { id } => status(conn, id, "canceled", SqlValue::Null),
+        OrderEvent::Trade(ref trade) => {
+            insert_trade(conn, symbol, trade)?;
+            for id in [trade.maker_order_id, trade.taker_order_id] {
+                conn.execute(
+                    "UPDATE orders SET filled_qty = filled_qty + ?, updated = ? \
+                     WHERE symbol = ? AND id = ?",
+                    &[
+                        integer(trade.qty),
+                        time(trade.timestamp),
+                        text(symbol),
+                        Some(id).into(),
+                    ],
+                )?;
+            }
+            Ok(())
+        }
+        OrderEvent::TradeBust { ref trade, .. } => conn.execute(
+            "UPDATE trades SET busted = 1 WHERE symbol = ? AND id = ?",
+            &[text(symbol), Some(trade.id as u64).into()],
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn insert_trade<C: SqliteConnection>(conn: &mut C, symbol: &str, trade: &Trade) -> io::Result<()> {
+    conn.execute(
+        "INSERT INTO trades (symbol, id, price, qty, aggressor_side, maker_order_id, \
+         taker_order_id, maker_participant, taker_participant, timestamp, maker_fee, taker_fee) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            text(symbol),
+            Some(trade.id as u64).into(),
+            integer(trade.price),
+            integer(trade.qty),
+            text(format!("{:?}", trade.aggressor_side)),
+            Some(trade.maker_order_id).into(),
+            Some(trade.taker_order_id).into(),
+            integer(trade.maker_participant),
+            integer(trade.taker_participant),
+            time(trade.timestamp),
+            integer(trade.maker_fee),
+            integer(trade.taker_fee),
+        ],
+    )
+}
+
+impl<C: SqliteConnection> EventSink for SqliteStore<C> {
+    fn publish(&mut self, symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        self.pending.push((symbol.to_string(), event.clone()));
+        if self.pending.len() >= self.config.batch_size {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_batch()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SqlValue, SqliteConfig, SqliteConnection, SqliteStore, SCHEMA};
+    use crate::sink::{publish_events, EventSink};
+    use crate::{OrderBook, OrderCommand, OrderEvent, OrderType, Side};
+    use std::io;
+
+    /// Records statements, failing the ones that mention `fail_on`.
+    #[derive(Default)]
+    struct Recorder {
+        statements: Vec<(String, Vec<SqlValue>)>,
+        fail_on: Option<&'static str>,
+    }
+
+    impl SqliteConnection for Recorder {
+        fn execute(&mut self, sql: &str, params: &[SqlValue]) -> io::Result<()> {
+            if self.fail_on.is_some_and(|table| sql.contains(table)) {
+                return Err(io::Error::other("disk I/O error"));
+            }
+            assert_eq!(sql.matches('?').count(), params.len(), "{}", sql);
+            self.statements.push((sql.to_string(), params.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_each_batch_in_a_transaction() {
+        let mut order_book = OrderBook::with_symbol("ABC");
+        for side in [Side::Sell, Side::Buy] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price: 101,
+                qty: 5,
+                participant: 1,
+            });
+        }
+        let events = order_book.events();
+
+        let conn = Recorder {
+            fail_on: Some("INTO trades"),
+            ..Recorder::default()
+        };
+        let mut store = SqliteStore::new(conn, SqliteConfig { batch_size: 2 }).unwrap();
+        store.publish("ABC", &events[0]).unwrap();
+        store.publish("ABC", &events[1]).unwrap();
+        // The second batch has the trade, which fails and is rolled back.
+        assert!(publish_events(&mut store, "ABC", &events[2..4]).is_err());
+        assert_eq!(store.pending(), 2);
+
+        store.conn.fail_on = None;
+        publish_events(&mut store, "ABC", &events[4..]).unwrap();
+        store.flush().unwrap();
+        assert_eq!(store.pending(), 0);
+        let sql: Vec<&str> = store.connection().statements[3..]
+            .iter()
+            .map(|(sql, _)| sql.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(
+            sql[..8],
+            ["BEGIN", "INSERT", "INSERT", "INSERT", "INSERT", "COMMIT", "BEGIN", "INSERT"]
+        );
+        // The trade's insert failed after its event's.
+        assert_eq!(sql[8], "ROLLBACK");
+        assert_eq!((sql[9], sql[sql.len() - 1]), ("BEGIN", "COMMIT"));
+    }
+
+    #[test]
+    fn fails_to_open_without_its_schema() {
+        let conn = Recorder {
+            fail_on: Some("CREATE TABLE IF NOT EXISTS trades"),
+            ..Recorder::default()
+        };
+        let err = SqliteStore::new(conn, SqliteConfig::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "disk I/O error");
+    }
+
+    #[test]
+    fn flushing_nothing_opens_no_transaction() {
+        let mut store = SqliteStore::new(Recorder::default(), SqliteConfig::default()).unwrap();
+        store.flush().unwrap();
+        assert_eq!(store.connection().statements.len(), SCHEMA.len());
+    }
+
+    #[test]
+    fn keeps_a_batch_whose_commit_fails() {
+        let conn = Recorder {
+            fail_on: Some("COMMIT"),
+            ..Recorder::default()
+        };
+        let mut store = SqliteStore::new(conn, SqliteConfig { batch_size: 1 }).unwrap();
+        assert!(store.publish("ABC", &OrderEvent::Modified).is_err());
+        assert_eq!(store.pending(), 1);
+        let (sql, _) = store.connection().statements.last().unwrap();
+        assert_eq!(sql, "ROLLBACK");
+    }
+
+    #[test]
+    fn leaves_what_an_event_does_not_say_null() {
+        let mut store = SqliteStore::new(Recorder::default(), SqliteConfig::default()).unwrap();
+        publish_events(
+            &mut store,
+            "ABC",
+            &[OrderEvent::Canceled { id: 7 }, OrderEvent::Modified],
+        )
+        .unwrap();
+        store.flush().unwrap();
+
+        let statements = &store.connection().statements[SCHEMA.len()..];
+        assert_eq!(statements.len(), 5);
+        let (_, cancel) = &statements[1];
+        assert_eq!(
+            cancel[..4],
+            [
+                SqlValue::Text("ABC".to_string()),
+                SqlValue::Text("Canceled".to_string()),
+                SqlValue::Integer(7),
+                SqlValue::Null,
+            ]
+        );
+        // The order keeps the time it was last updated.
+        let (sql, params) = &statements[2];
+        assert!(sql.starts_with("UPDATE orders"));
+        assert_eq!(
+            params[..2],
+            [SqlValue::Text("canceled".to_string()), SqlValue::Null]
+        );
+        let (_, modified) = &statements[3];
+        assert_eq!(modified[2..4], [SqlValue::Null, SqlValue::Null]);
+    }
+}
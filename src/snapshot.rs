@@ -0,0 +1,370 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Full book snapshots and comparing them.
+//!
+//! A [`BookSnapshot`] holds every resting order in queue order, which is
+//! enough to tell whether two books would behave the same from here on:
+//! one recovered from a journal and the one that wrote it, a replica and
+//! its primary, or the same flow run before and after a change to the
+//! matching path. Timestamps are left out, since a rebuilt book rarely gets
+//! the same ones.
+
+use crate::circuit_breaker::TradingStatus;
+use crate::limit::Limit;
+use crate::order_book::LevelInfo;
+use crate::{OrderBook, OrderId, OrderType, ParticipantId, PriorityClass, Side};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookSnapshot {
+    pub symbol: String,
+    pub status: TradingStatus,
+    pub next_order_id: OrderId,
+    /// Best price first.
+    pub bids: Vec<LevelSnapshot>,
+    pub asks: Vec<LevelSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelSnapshot {
+    pub price: i32,
+    /// Front of the queue first: the highest priority class first, and
+    /// each class in time order.
+    pub orders: Vec<RestingOrder>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestingOrder {
+    pub id: OrderId,
+    pub participant: ParticipantId,
+    pub order_type: OrderType,
+    pub qty: u32,
+    /// The order's priority class; see [`priority`](crate::priority). Left
+    /// out of JSON for class 0.
+    #[serde(default, skip_serializing_if = "is_default_class")]
+    pub class: PriorityClass,
+}
+
+fn is_default_class(class: &PriorityClass) -> bool {
+    *class == 0
+}
+
+impl BookSnapshot {
+    pub fn of(order_book: &OrderBook) -> BookSnapshot {
+        let levels = |queue: &[Limit]| {
+            queue
+                .iter()
+                .map(|lim| LevelSnapshot {
+                    price: lim.price,
+                    orders: lim
+                        .orders
+                        .iter()
+                        .map(|order| RestingOrder {
+                            id: order.id,
+                            participant: order.participant,
+                            order_type: order.order_type,
+                            qty: order.remaining_qty,
+                            class: order.class,
+                        })
+                        .collect(),
+                })
+                .collect()
+        };
+        BookSnapshot {
+            symbol: order_book.symbol().to_string(),
+            status: order_book.status(),
+            next_order_id: order_book.next_order_id(),
+            bids: levels(&order_book.bids),
+            asks: levels(&order_book.asks),
+        }
+    }
+}
+
+impl LevelSnapshot {
+    pub fn info(&self) -> LevelInfo {
+        LevelInfo {
+            price: self.price,
+            qty: self.orders.iter().map(|order| u64::from(order.qty)).sum(),
+            order_count: self.orders.len(),
+        }
+    }
+}
+
+/// One way in which two snapshots disagree. `left` and `right` are the two
+/// snapshots in the order they were passed to [`diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    Status {
+        left: TradingStatus,
+        right: TradingStatus,
+    },
+    NextOrderId {
+        left: OrderId,
+        right: OrderId,
+    },
+    /// A price level only one book has.
+    Level {
+        side: Side,
+        price: i32,
+        left: Option<LevelInfo>,
+        right: Option<LevelInfo>,
+    },
+    /// An order at a level both books have that only one has, or that the
+    /// two disagree on.
+    Order {
+        side: Side,
+        price: i32,
+        left: Option<RestingOrder>,
+        right: Option<RestingOrder>,
+    },
+    /// The same orders at a level, queued differently.
+    Priority {
+        side: Side,
+        price: i32,
+        left: Vec<OrderId>,
+        right: Vec<OrderId>,
+    },
+}
+
+/// Everything that differs between `left` and `right`, bids before asks and
+/// best price first on each side. Empty if the books match. Symbols are not
+/// compared, so a book can be checked against one rebuilt under another name.
+pub fn diff(left: &BookSnapshot, right: &BookSnapshot) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    if left.status != right.status {
+        differences.push(Difference::Status {
+            left: left.status,
+            right: right.status,
+        });
+    }
+    if left.next_order_id != right.next_order_id {
+        differences.push(Difference::NextOrderId {
+            left: left.next_order_id,
+            right: right.next_order_id,
+        });
+    }
+    diff_side(Side::Buy, &left.bids, &right.bids, &mut differences);
+    diff_side(Side::Sell, &left.asks, &right.asks, &mut differences);
+    differences
+}
+
+fn diff_side(
+    side: Side,
+    left: &[LevelSnapshot],
+    right: &[LevelSnapshot],
+    differences: &mut Vec<Difference>,
+) {
+    fn by_price(levels: &[LevelSnapshot]) -> BTreeMap<i32, &LevelSnapshot> {
+        levels.iter().map(|level| (level.price, level)).collect()
+    }
+    let (left, right) = (by_price(left), by_price(right));
+    let prices: BTreeSet<i32> = left.keys().chain(right.keys()).copied().collect();
+    let prices: Box<dyn Iterator<Item = &i32>> = match side {
+        Side::Buy => Box::new(prices.iter().rev()),
+        Side::Sell => Box::new(prices.iter()),
+    };
+    for &price in prices {
+        let (left, right) = match (left.get(&price), right.get(&price)) {
+            (Some(left), Some(right)) => (left, right),
+            (left, right) => {
+                differences.push(Difference::Level {
+                    side,
+                    price,
+                    left: left.map(|level| level.info()),
+                    right: right.map(|level| level.info()),
+                });
+                continue;
+            }
+        };
+        let before = differences.len();
+        let find =
+            |level: &LevelSnapshot, id| level.orders.iter().find(|order| order.id == id).copied();
+        let ids: BTreeSet<OrderId> = left
+            .orders
+            .iter()
+            .chain(&right.orders)
+            .map(|order| order.id)
+            .collect();
+        for id in ids {
+            let (left, right) = (find(left, id), find(right, id));
+            if left != right {
+                differences.push(Difference::Order {
+                    side,
+                    price,
+                    left,
+                    right,
+                });
+            }
+        }
+        if differences.len() == before && left.orders != right.orders {
+            let queue = |level: &LevelSnapshot| level.orders.iter().map(|order| order.id).collect();
+            differences.push(Difference::Priority {
+                side,
+                price,
+                left: queue(left),
+                right: queue(right),
+            });
+        }
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = |side: &Side| match side {
+            Side::Buy => "bid",
+            Side::Sell => "ask",
+        };
+        let level = |level: &Option<LevelInfo>| match level {
+            Some(level) => format!("{} in {} orders", level.qty, level.order_count),
+            None => "nothing".to_string(),
+        };
+        let order = |order: &Option<RestingOrder>| match order {
+            Some(order) => format!(
+                "{} {:?} for participant {}",
+                order.qty, order.order_type, order.participant
+            ),
+            None => "nothing".to_string(),
+        };
+        match self {
+            Difference::Status { left, right } => {
+                write!(f, "status: left {:?}, right {:?}", left, right)
+            }
+            Difference::NextOrderId { left, right } => {
+                write!(f, "next order id: left {}, right {}", left, right)
+            }
+            Difference::Level {
+                side: s,
+                price,
+                left,
+                right,
+            } => write!(
+                f,
+                "{} {}: left has {}, right has {}",
+                side(s),
+                price,
+                level(left),
+                level(right)
+            ),
+            Difference::Order {
+                side: s,
+                price,
+                left,
+                right,
+            } => {
+                let id = left.or(*right).map_or(0, |order| order.id);
+                write!(
+                    f,
+                    "{} {} order {}: left has {}, right has {}",
+                    side(s),
+                    price,
+                    id,
+                    order(left),
+                    order(right)
+                )
+            }
+            Difference::Priority {
+                side: s,
+                price,
+                left,
+                right,
+            } => write!(
+                f,
+                "{} {} queue: left {:?}, right {:?}",
+                side(s),
+                price,
+                left,
+                right
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, BookSnapshot, Difference};
+    use crate::order_book::LevelInfo;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    fn book(orders: &[(Side, i32, u32)]) -> OrderBook {
+        let mut order_book = OrderBook::new();
+        for &(side, price, qty) in orders {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price,
+                qty,
+                participant: 1,
+            });
+        }
+        order_book
+    }
+
+    #[test]
+    fn reports_levels_orders_and_priority() {
+        let primary = book(&[
+            (Side::Buy, 100, 5),
+            (Side::Buy, 100, 3),
+            (Side::Buy, 99, 1),
+            (Side::Sell, 102, 4),
+        ]);
+        let snapshot = BookSnapshot::of(&primary);
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(
+            serde_json::from_str::<BookSnapshot>(&json).unwrap(),
+            snapshot
+        );
+        assert!(diff(&snapshot, &BookSnapshot::of(&primary)).is_empty());
+
+        let mut replica = BookSnapshot::of(&book(&[
+            (Side::Buy, 100, 5),
+            (Side::Buy, 100, 2),
+            (Side::Buy, 98, 1),
+            (Side::Sell, 102, 4),
+            (Side::Sell, 102, 6),
+        ]));
+        let differences = diff(&snapshot, &replica);
+        let lines: Vec<String> = differences.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "next order id: left 5, right 6",
+                "bid 100 order 2: left has 3 GoodTilCancel for participant 1, \
+                 right has 2 GoodTilCancel for participant 1",
+                "bid 99: left has 1 in 1 orders, right has nothing",
+                "bid 98: left has nothing, right has 1 in 1 orders",
+                "ask 102 order 5: left has nothing, right has 6 GoodTilCancel for participant 1",
+            ]
+        );
+        assert_eq!(
+            differences[2],
+            Difference::Level {
+                side: Side::Buy,
+                price: 99,
+                left: Some(LevelInfo {
+                    price: 99,
+                    qty: 1,
+                    order_count: 1
+                }),
+                right: None,
+            }
+        );
+
+        let mut swapped = snapshot.clone();
+        swapped.bids[0].orders.swap(0, 1);
+        assert_eq!(
+            diff(&snapshot, &swapped),
+            [Difference::Priority {
+                side: Side::Buy,
+                price: 100,
+                left: vec![1, 2],
+                right: vec![2, 1],
+            }]
+        );
+        replica.next_order_id = 5;
+        assert_eq!(diff(&snapshot, &replica).len(), 4);
+    }
+}
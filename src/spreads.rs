@@ -0,0 +1,853 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Multi-leg spread instruments with implied pricing.
+//!
+//! A [`SpreadMarket`] holds the books of outright instruments and of spreads
+//! defined over them, such as a calendar spread that buys one expiry and
+//! sells another. A spread's price is the sum of its legs' prices times
+//! their ratios, so one lot of a spread bought is `ratio` lots bought of each
+//! leg with a positive ratio and `-ratio` lots sold of each with a negative
+//! one.
+//!
+//! The market quotes implied prices as ordinary orders for its own
+//! participant. Prices imply *out* of the legs into the spread: buying every
+//! leg at its best offer is an offer for the spread. They imply *in* from a
+//! spread and all but one of its legs to the remaining leg, for legs with a
+//! ratio of one either way. Implied quotes are only ever derived from other
+//! participants' orders, never from other implied quotes.
+//!
+//! When an implied quote trades, whether an incoming order hits it or it
+//! crosses a resting order as it is placed, the market trades the orders it
+//! was derived from straight away with fill-and-kill orders, before anything
+//! else reaches the books, and ends flat. So that a hedge can't fall short, a
+//! quote is only placed, and a command only reaches a book, once the quotes
+//! in it have been checked to be hedgeable in full without touching the
+//! books: there must be enough of other participants' orders at each hedge's
+//! price or better, shared out among the quotes in the order they would
+//! fill, and each hedge must pass its book's pre-trade checks. Quotes that
+//! fail are withdrawn. Rate limits are the one check that can't be made
+//! ahead, so the market's own participant should not have one.
+
+use crate::circuit_breaker::TradingStatus;
+use crate::{OrderBook, OrderCommand, OrderEvent, OrderId, OrderType, ParticipantId, Side};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io;
+
+/// Rounds of quoting and hedging after a command before the market gives up
+/// on reaching a state with nothing left to hedge.
+const MAX_ROUNDS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Leg {
+    pub symbol: String,
+    /// Lots of this leg per lot of the spread, negative for legs sold when
+    /// the spread is bought.
+    pub ratio: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SpreadDefinition {
+    pub symbol: String,
+    pub legs: Vec<Leg>,
+}
+
+impl SpreadDefinition {
+    /// Buys `front` and sells `back`, priced as their difference.
+    pub fn calendar(symbol: impl Into<String>, front: &str, back: &str) -> Self {
+        SpreadDefinition {
+            symbol: symbol.into(),
+            legs: vec![
+                Leg {
+                    symbol: front.to_string(),
+                    ratio: 1,
+                },
+                Leg {
+                    symbol: back.to_string(),
+                    ratio: -1,
+                },
+            ],
+        }
+    }
+}
+
+/// An implied price the market is quoting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImpliedQuote {
+    pub symbol: String,
+    pub side: Side,
+    pub price: i32,
+    pub qty: u32,
+    /// The orders the quote was derived from, traded when it fills.
+    hedges: Vec<Hedge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hedge {
+    symbol: String,
+    side: Side,
+    price: i32,
+    /// Per lot of the quote.
+    ratio: u32,
+}
+
+/// Part of an implied quote that traded and still has to be hedged.
+struct QuoteFill {
+    quote: ImpliedQuote,
+    qty: u32,
+    trade_id: usize,
+}
+
+#[derive(Debug)]
+pub struct SpreadMarket {
+    /// The participant implied quotes and their hedges trade as.
+    participant: ParticipantId,
+    books: BTreeMap<String, OrderBook>,
+    spreads: BTreeMap<String, Vec<Leg>>,
+    quotes: BTreeMap<(String, OrderId), ImpliedQuote>,
+    /// How far into each book's events fills have been looked for.
+    seen: BTreeMap<String, usize>,
+}
+
+impl SpreadMarket {
+    pub fn new(participant: ParticipantId) -> Self {
+        SpreadMarket {
+            participant,
+            books: BTreeMap::new(),
+            spreads: BTreeMap::new(),
+            quotes: BTreeMap::new(),
+            seen: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a book for an outright instrument.
+    pub fn add_outright(&mut self, symbol: &str) -> io::Result<()> {
+        if self.books.contains_key(symbol) {
+            return Err(invalid_input(format!("{} is already listed", symbol)));
+        }
+        self.books
+            .insert(symbol.to_string(), OrderBook::with_symbol(symbol));
+        Ok(())
+    }
+
+    /// Adds a book for a spread over outrights already listed.
+    pub fn add_spread(&mut self, spread: SpreadDefinition) -> io::Result<()> {
+        if self.books.contains_key(&spread.symbol) {
+            return Err(invalid_input(format!(
+                "{} is already listed",
+                spread.symbol
+            )));
+        }
+        if spread.legs.len() < 2 {
+            return Err(invalid_input(format!("{} needs two legs", spread.symbol)));
+        }
+        for (i, leg) in spread.legs.iter().enumerate() {
+            if !self.books.contains_key(&leg.symbol) || self.spreads.contains_key(&leg.symbol) {
+                return Err(invalid_input(format!(
+                    "leg {} of {} is not a listed outright",
+                    leg.symbol, spread.symbol
+                )));
+            }
+            if leg.ratio == 0 || spread.legs[..i].iter().any(|l| l.symbol == leg.symbol) {
+                return Err(invalid_input(format!(
+                    "leg {} of {} needs one non-zero ratio",
+                    leg.symbol, spread.symbol
+                )));
+            }
+        }
+        self.books.insert(
+            spread.symbol.clone(),
+            OrderBook::with_symbol(&spread.symbol),
+        );
+        self.spreads.insert(spread.symbol, spread.legs);
+        Ok(())
+    }
+
+    pub fn book(&self, symbol: &str) -> Option<&OrderBook> {
+        self.books.get(symbol)
+    }
+
+    /// For configuring a book, e.g. its risk limits. Implied quotes catch up
+    /// with anything done through it on the next [`SpreadMarket::submit`].
+    pub fn book_mut(&mut self, symbol: &str) -> Option<&mut OrderBook> {
+        self.books.get_mut(symbol)
+    }
+
+    /// The implied quotes resting in the books.
+    pub fn quotes(&self) -> impl Iterator<Item = &ImpliedQuote> {
+        self.quotes.values()
+    }
+
+    /// Processes `command` in `symbol`'s book, then executes whatever it
+    /// made cross against implied prices and requotes them.
+    pub fn submit(&mut self, symbol: &str, command: OrderCommand) -> io::Result<()> {
+        if !self.books.contains_key(symbol) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unknown symbol {}", symbol),
+            ));
+        }
+        self.withdraw_unhedgeable(symbol);
+        self.books
+            .get_mut(symbol)
+            .expect("just found")
+            .process_command(command);
+        self.settle();
+        Ok(())
+    }
+
+    /// Pulls the quotes in `symbol`'s book that could not all be hedged in
+    /// full if they traded now, taking them in the order the book would
+    /// fill them.
+    fn withdraw_unhedgeable(&mut self, symbol: &str) {
+        let book = &self.books[symbol];
+        let mut resting: Vec<(OrderId, ImpliedQuote, u32)> = self
+            .quotes
+            .iter()
+            .filter(|((quoted, _), _)| quoted == symbol)
+            .filter_map(|((_, id), quote)| {
+                let order = book.find_order(*id)?;
+                Some((*id, quote.clone(), order.remaining_qty))
+            })
+            .collect();
+        resting.sort_by_key(|(id, quote, _)| {
+            let price = i64::from(quote.price);
+            let priority = match quote.side {
+                Side::Buy => -price,
+                Side::Sell => price,
+            };
+            (quote.side, priority, *id)
+        });
+        let mut committed = BTreeMap::new();
+        for (id, quote, qty) in resting {
+            if self.hedgeable(&quote, qty, &mut committed) {
+                continue;
+            }
+            self.quotes.remove(&(symbol.to_string(), id));
+            self.books
+                .get_mut(symbol)
+                .expect("listed")
+                .process_command(OrderCommand::Cancel {
+                    id,
+                    side: quote.side,
+                    price: quote.price,
+                });
+        }
+    }
+
+    /// Whether `qty` lots of `quote` could be hedged in full now on top of
+    /// the hedges already `committed`, which its own join if so.
+    fn hedgeable(
+        &self,
+        quote: &ImpliedQuote,
+        qty: u32,
+        committed: &mut BTreeMap<(String, Side), u64>,
+    ) -> bool {
+        let mut needs = Vec::with_capacity(quote.hedges.len());
+        for hedge in &quote.hedges {
+            let book = &self.books[&hedge.symbol];
+            let need = qty.saturating_mul(hedge.ratio);
+            let key = (hedge.symbol.clone(), hedge.side);
+            let used = committed.get(&key).copied().unwrap_or(0);
+            let fits = book.status() == TradingStatus::Open
+                && book
+                    .check_order(self.participant, hedge.side, hedge.price, need)
+                    .is_ok()
+                && used + u64::from(need) <= self.available(&hedge.symbol, hedge.side, hedge.price);
+            if !fits {
+                return false;
+            }
+            needs.push((key, need));
+        }
+        for (key, need) in needs {
+            *committed.entry(key).or_default() += u64::from(need);
+        }
+        true
+    }
+
+    /// What a fill-and-kill order `side` at `price` in `symbol`'s book would
+    /// find once the market's own quotes are out of the way.
+    fn available(&self, symbol: &str, side: Side, price: i32) -> u64 {
+        let book = &self.books[symbol];
+        let reaches = |level: i32| match side {
+            Side::Buy => level <= price,
+            Side::Sell => level >= price,
+        };
+        let depth = book.depth(usize::MAX);
+        let levels = match side {
+            Side::Buy => depth.asks,
+            Side::Sell => depth.bids,
+        };
+        let total: u64 = levels
+            .iter()
+            .take_while(|level| reaches(level.price))
+            .map(|level| level.qty)
+            .sum();
+        let own: u64 = self
+            .quotes
+            .keys()
+            .filter(|(quoted, _)| quoted == symbol)
+            .filter_map(|(_, id)| book.find_order(*id))
+            .filter(|order| order.side != side && reaches(order.price))
+            .map(|order| u64::from(order.remaining_qty))
+            .sum();
+        total.saturating_sub(own)
+    }
+
+    fn settle(&mut self) {
+        let mut fills = self.quote_fills();
+        for _ in 0..MAX_ROUNDS {
+            if !fills.is_empty() {
+                self.cancel_quotes();
+                let mut hedged = true;
+                for fill in fills {
+                    hedged &= self.hedge(fill);
+                }
+                if !hedged {
+                    // Requoting would only cross and fail again.
+                    return;
+                }
+            }
+            self.refresh_quotes();
+            fills = self.quote_fills();
+            if fills.is_empty() {
+                return;
+            }
+        }
+        tracing::warn!("implied matching still trading after {} rounds", MAX_ROUNDS);
+    }
+
+    /// Trades of implied quotes since the last look.
+    fn quote_fills(&mut self) -> Vec<QuoteFill> {
+        let mut fills = Vec::new();
+        for (symbol, book) in &self.books {
+            let seen = self.seen.entry(symbol.clone()).or_default();
+            for event in &book.events()[*seen..] {
+                let OrderEvent::Trade(trade) = event else {
+                    continue;
+                };
+                for id in [trade.maker_order_id, trade.taker_order_id] {
+                    if let Some(quote) = self.quotes.get(&(symbol.clone(), id)) {
+                        fills.push(QuoteFill {
+                            quote: quote.clone(),
+                            qty: trade.qty,
+                            trade_id: trade.id,
+                        });
+                    }
+                }
+            }
+            *seen = book.events().len();
+        }
+        fills
+    }
+
+    fn cancel_quotes(&mut self) {
+        for ((symbol, id), quote) in std::mem::take(&mut self.quotes) {
+            self.books
+                .get_mut(&symbol)
+                .expect("quotes are for listed books")
+                .process_command(OrderCommand::Cancel {
+                    id,
+                    side: quote.side,
+                    price: quote.price,
+                });
+        }
+    }
+
+    /// Trades the orders `fill`'s quote was derived from, which were checked
+    /// to be there before it could trade.
+    fn hedge(&mut self, fill: QuoteFill) -> bool {
+        for hedge in &fill.quote.hedges {
+            let book = self
+                .books
+                .get_mut(&hedge.symbol)
+                .expect("hedges are in listed books");
+            let qty = fill.qty.saturating_mul(hedge.ratio);
+            let (id, seen) = (book.next_order_id(), book.events().len());
+            book.process_command(OrderCommand::New {
+                order_type: OrderType::FillAndKill,
+                side: hedge.side,
+                price: hedge.price,
+                qty,
+                participant: self.participant,
+            });
+            let mut filled = 0;
+            for event in &book.events()[seen..] {
+                if let OrderEvent::Trade(trade) = event {
+                    if trade.taker_order_id == id {
+                        filled += trade.qty;
+                    }
+                }
+            }
+            if filled < qty {
+                tracing::error!(
+                    "implied {:?} in {} trade {} left unhedged: {} of {} filled in {}",
+                    fill.quote.side,
+                    fill.quote.symbol,
+                    fill.trade_id,
+                    filled,
+                    qty,
+                    hedge.symbol
+                );
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Replaces quotes whose prices have moved. Stops at the first new
+    /// quote that trades, since its hedge may need what later quotes were
+    /// derived from.
+    fn refresh_quotes(&mut self) {
+        let mut wanted = self.implied_quotes();
+        wanted.retain(|quote| self.hedgeable(quote, quote.qty, &mut BTreeMap::new()));
+        let stale: Vec<(String, OrderId)> = self
+            .quotes
+            .iter()
+            .filter(|(_, quote)| !wanted.contains(quote))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in stale {
+            let quote = self.quotes.remove(&key).expect("just found");
+            self.books
+                .get_mut(&key.0)
+                .expect("quotes are for listed books")
+                .process_command(OrderCommand::Cancel {
+                    id: key.1,
+                    side: quote.side,
+                    price: quote.price,
+                });
+        }
+        wanted.retain(|quote| !self.quotes.values().any(|q| q == quote));
+        for quote in wanted {
+            let crosses_own = self.quotes.values().any(|q| {
+                q.symbol == quote.symbol
+                    && match quote.side {
+                        Side::Buy => q.side == Side::Sell && q.price <= quote.price,
+                        Side::Sell => q.side == Side::Buy && q.price >= quote.price,
+                    }
+            });
+            if crosses_own {
+                continue;
+            }
+            let book = self.books.get_mut(&quote.symbol).expect("listed");
+            let (id, seen) = (book.next_order_id(), book.events().len());
+            book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side: quote.side,
+                price: quote.price,
+                qty: quote.qty,
+                participant: self.participant,
+            });
+            let events = &book.events()[seen..];
+            let placed = events.iter().any(
+                |event| matches!(event, OrderEvent::Placed { id: placed, .. } if *placed == id),
+            );
+            let traded = events.iter().any(
+                |event| matches!(event, OrderEvent::Trade(trade) if trade.taker_order_id == id),
+            );
+            if placed {
+                self.quotes.insert((quote.symbol.clone(), id), quote);
+            }
+            if traded {
+                return;
+            }
+        }
+    }
+
+    /// Every quote the books imply right now, out into each spread and in to
+    /// each of its legs, while all the books involved are open.
+    fn implied_quotes(&self) -> Vec<ImpliedQuote> {
+        let mut quotes = Vec::new();
+        for (symbol, legs) in &self.spreads {
+            let open = std::iter::once(symbol)
+                .chain(legs.iter().map(|leg| &leg.symbol))
+                .all(|symbol| self.books[symbol].status() == TradingStatus::Open);
+            if !open {
+                continue;
+            }
+            // `side` is the way the market trades the spread through its legs.
+            for side in [Side::Buy, Side::Sell] {
+                if let Some((price, qty, hedges)) = self.through_legs(legs, side, None) {
+                    quotes.push(ImpliedQuote {
+                        symbol: symbol.clone(),
+                        side: side.opposite(),
+                        price,
+                        qty,
+                        hedges,
+                    });
+                }
+                // Offset by trading the spread the other way against its
+                // resting orders.
+                let Some((spread_price, spread_qty)) = self.top(symbol, side) else {
+                    continue;
+                };
+                for (j, leg) in legs.iter().enumerate() {
+                    if leg.ratio.abs() != 1 {
+                        continue;
+                    }
+                    let Some((value, qty, mut hedges)) = self.through_legs(legs, side, Some(j))
+                    else {
+                        continue;
+                    };
+                    let Some(price) = spread_price.checked_sub(value) else {
+                        continue;
+                    };
+                    hedges.push(Hedge {
+                        symbol: symbol.clone(),
+                        side: side.opposite(),
+                        price: spread_price,
+                        ratio: 1,
+                    });
+                    quotes.push(ImpliedQuote {
+                        symbol: leg.symbol.clone(),
+                        side: leg_side(leg, side),
+                        price: price * leg.ratio,
+                        qty: qty.min(spread_qty),
+                        hedges,
+                    });
+                }
+            }
+        }
+        quotes
+    }
+
+    /// The price of trading a spread `side` by trading its legs, bar leg
+    /// `skip`, against other participants' best orders, with the lots that
+    /// leaves room for and the trades it takes.
+    fn through_legs(
+        &self,
+        legs: &[Leg],
+        side: Side,
+        skip: Option<usize>,
+    ) -> Option<(i32, u32, Vec<Hedge>)> {
+        let (mut value, mut lots, mut hedges) = (0_i32, u32::MAX, Vec::new());
+        for (i, leg) in legs.iter().enumerate() {
+            if skip == Some(i) {
+                continue;
+            }
+            let side = leg_side(leg, side);
+            let (price, qty) = self.top(&leg.symbol, side.opposite())?;
+            let ratio = leg.ratio.unsigned_abs();
+            value = value.checked_add(leg.ratio.checked_mul(price)?)?;
+            lots = lots.min(qty / ratio);
+            hedges.push(Hedge {
+                symbol: leg.symbol.clone(),
+                side,
+                price,
+                ratio,
+            });
+        }
+        (lots > 0).then_some((value, lots, hedges))
+    }
+
+    /// The best price on `side` of `symbol`'s book among other participants'
+    /// orders, with their quantity there.
+    fn top(&self, symbol: &str, side: Side) -> Option<(i32, u32)> {
+        let book = &self.books[symbol];
+        let levels = match side {
+            Side::Buy => &book.bids,
+            Side::Sell => &book.asks,
+        };
+        levels.iter().find_map(|limit| {
+            let qty: u64 = limit
+                .orders
+                .iter()
+                .filter(|order| order.participant != self.participant)
+                .map(|order| u64::from(order.remaining_qty))
+                .sum();
+            (qty > 0).then(|| (limit.price, qty.min(u64::from(u32::MAX)) as u32))
+        })
+    }
+}
+
+/// The way a leg trades when its spread trades `side`.
+fn leg_side(leg: &Leg, side: Side) -> Side {
+    if leg.ratio > 0 {
+        side
+    } else {
+        side.opposite()
+    }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Leg, SpreadDefinition, SpreadMarket};
+    use crate::risk::RiskLimits;
+    use crate::{OrderCommand, OrderEvent, OrderType, ParticipantId, Side};
+
+    const HOUSE: ParticipantId = 99;
+
+    fn market() -> SpreadMarket {
+        let mut market = SpreadMarket::new(HOUSE);
+        market.add_outright("JUN").unwrap();
+        market.add_outright("SEP").unwrap();
+        market
+            .add_spread(SpreadDefinition::calendar("JUN-SEP", "JUN", "SEP"))
+            .unwrap();
+        market
+    }
+
+    fn order(
+        market: &mut SpreadMarket,
+        symbol: &str,
+        order_type: OrderType,
+        side: Side,
+        price: i32,
+        qty: u32,
+        participant: ParticipantId,
+    ) {
+        market
+            .submit(
+                symbol,
+                OrderCommand::New {
+                    order_type,
+                    side,
+                    price,
+                    qty,
+                    participant,
+                },
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn validates_definitions() {
+        let mut market = market();
+        assert!(market.add_outright("JUN").is_err());
+        for legs in [
+            vec![("JUN", 1)],
+            vec![("JUN", 1), ("DEC", -1)],
+            vec![("JUN", 1), ("JUN", -1)],
+            vec![("JUN", 1), ("SEP", 0)],
+            vec![("JUN", 1), ("JUN-SEP", -1)],
+        ] {
+            let legs = legs
+                .into_iter()
+                .map(|(symbol, ratio)| Leg {
+                    symbol: symbol.to_string(),
+                    ratio,
+                })
+                .collect();
+            let spread = SpreadDefinition {
+                symbol: "X".to_string(),
+                legs,
+            };
+            assert!(market.add_spread(spread).is_err());
+        }
+        assert!(market.submit("DEC", OrderCommand::Halt).is_err());
+    }
+
+    #[test]
+    fn implies_out_of_the_legs() {
+        let mut market = market();
+        order(
+            &mut market,
+            "JUN",
+            OrderType::GoodTilCancel,
+            Side::Sell,
+            100,
+            5,
+            1,
+        );
+        order(
+            &mut market,
+            "SEP",
+            OrderType::GoodTilCancel,
+            Side::Buy,
+            97,
+            8,
+            2,
+        );
+        let spread = market.book("JUN-SEP").unwrap();
+        assert_eq!(
+            (spread.best_ask(), spread.bbo().ask.unwrap().qty),
+            (Some(3), 5)
+        );
+
+        order(
+            &mut market,
+            "JUN-SEP",
+            OrderType::FillAndKill,
+            Side::Buy,
+            4,
+            3,
+            3,
+        );
+        let trade = market.book("JUN-SEP").unwrap().trades()[0];
+        assert_eq!((trade.price, trade.qty, trade.taker_participant), (3, 3, 3));
+        let jun = market.book("JUN").unwrap();
+        assert_eq!(
+            (jun.trades()[0].price, jun.position(HOUSE).net_qty),
+            (100, 3)
+        );
+        let sep = market.book("SEP").unwrap();
+        assert_eq!(
+            (sep.trades()[0].price, sep.position(HOUSE).net_qty),
+            (97, -3)
+        );
+        // Requoted for what is left.
+        let spread = market.book("JUN-SEP").unwrap();
+        assert_eq!(spread.bbo().ask.unwrap().qty, 2);
+        assert_eq!(spread.position(HOUSE).net_qty, -3);
+    }
+
+    #[test]
+    fn implies_into_a_leg_and_matches_resting_orders() {
+        let mut market = market();
+        order(
+            &mut market,
+            "JUN-SEP",
+            OrderType::GoodTilCancel,
+            Side::Buy,
+            2,
+            4,
+            1,
+        );
+        order(
+            &mut market,
+            "SEP",
+            OrderType::GoodTilCancel,
+            Side::Buy,
+            98,
+            10,
+            2,
+        );
+        // Buying the spread at 2 and selling SEP at 98 is buying JUN at 100.
+        let implied: Vec<_> = market.quotes().filter(|q| q.symbol == "JUN").collect();
+        assert_eq!(implied.len(), 1);
+        assert_eq!(
+            (implied[0].side, implied[0].price, implied[0].qty),
+            (Side::Buy, 100, 4)
+        );
+
+        // A JUN offer that rests crosses it.
+        order(
+            &mut market,
+            "JUN",
+            OrderType::GoodTilCancel,
+            Side::Sell,
+            100,
+            6,
+            3,
+        );
+        let spread = market.book("JUN-SEP").unwrap();
+        assert_eq!(spread.trades()[0].maker_participant, 1);
+        assert_eq!(spread.position(1).net_qty, 4);
+        assert_eq!(market.book("JUN").unwrap().position(3).net_qty, -4);
+        assert_eq!(market.book("SEP").unwrap().position(2).net_qty, 4);
+        for symbol in ["JUN-SEP", "JUN", "SEP"] {
+            let house = market.book(symbol).unwrap().position(HOUSE).net_qty;
+            assert_eq!(house.abs(), 4, "{}", symbol);
+        }
+    }
+
+    #[test]
+    fn withholds_quotes_a_hedge_would_be_refused_for() {
+        let mut market = market();
+        market.book_mut("SEP").unwrap().risk_mut().set_limits(
+            HOUSE,
+            RiskLimits {
+                max_order_qty: Some(1),
+                ..RiskLimits::default()
+            },
+        );
+        order(
+            &mut market,
+            "JUN",
+            OrderType::GoodTilCancel,
+            Side::Sell,
+            100,
+            5,
+            1,
+        );
+        order(
+            &mut market,
+            "SEP",
+            OrderType::GoodTilCancel,
+            Side::Buy,
+            97,
+            5,
+            2,
+        );
+        order(
+            &mut market,
+            "JUN-SEP",
+            OrderType::FillAndKill,
+            Side::Buy,
+            3,
+            2,
+            3,
+        );
+
+        for symbol in ["JUN-SEP", "JUN", "SEP"] {
+            assert!(
+                market.book(symbol).unwrap().trades().is_empty(),
+                "{}",
+                symbol
+            );
+        }
+        assert_eq!(market.quotes().count(), 0);
+        let jun = market.book("JUN").unwrap();
+        assert_eq!(jun.bbo().ask.unwrap().qty, 5);
+        assert!(market.book("JUN-SEP").unwrap().position(3).is_flat());
+    }
+
+    #[test]
+    fn withdraws_quotes_before_their_legs_can_fall_short() {
+        let mut market = market();
+        order(
+            &mut market,
+            "JUN",
+            OrderType::GoodTilCancel,
+            Side::Sell,
+            100,
+            5,
+            1,
+        );
+        order(
+            &mut market,
+            "SEP",
+            OrderType::GoodTilCancel,
+            Side::Buy,
+            97,
+            5,
+            2,
+        );
+        assert_eq!(market.book("JUN-SEP").unwrap().best_ask(), Some(3));
+        // Taken out behind the market's back, so the quote is still there.
+        let sep = market.book_mut("SEP").unwrap();
+        let id = sep.bids[0].orders[0].id;
+        sep.process_command(OrderCommand::Cancel {
+            id,
+            side: Side::Buy,
+            price: 97,
+        });
+        order(
+            &mut market,
+            "JUN-SEP",
+            OrderType::FillAndKill,
+            Side::Buy,
+            3,
+            2,
+            3,
+        );
+
+        for symbol in ["JUN-SEP", "JUN", "SEP"] {
+            let book = market.book(symbol).unwrap();
+            assert!(book.trades().is_empty(), "{}", symbol);
+            assert!(!book
+                .events()
+                .iter()
+                .any(|event| matches!(event, OrderEvent::TradeBust { .. })));
+        }
+        assert_eq!(market.quotes().count(), 0);
+        assert_eq!(market.book("JUN").unwrap().bbo().ask.unwrap().qty, 5);
+    }
+}
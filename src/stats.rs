@@ -0,0 +1,144 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Trading session statistics.
+
+use crate::market_data::MarketDataMessage;
+use crate::sink::EventSink;
+use crate::{OrderBook, OrderEvent, Price, Trade};
+use serde::Serialize;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Running totals for one trading session. Prices are `None` until the first
+/// trade.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SessionStats<P = i32> {
+    pub open: Option<P>,
+    pub high: Option<P>,
+    pub low: Option<P>,
+    pub last: Option<P>,
+    pub volume: u64,
+    pub trade_count: u64,
+    /// Sum of price * qty over all trades.
+    pub turnover: i64,
+    /// Set when the session closes; see [`crate::settlement`].
+    pub settlement: Option<P>,
+}
+
+impl<P: Price> SessionStats<P> {
+    pub fn record(&mut self, trade: &Trade<P>) {
+        let price = trade.price;
+        self.open.get_or_insert(price);
+        self.high = Some(self.high.map_or(price, |high| high.max(price)));
+        self.low = Some(self.low.map_or(price, |low| low.min(price)));
+        self.last = Some(price);
+        self.volume += u64::from(trade.qty);
+        self.trade_count += 1;
+        self.turnover += price.to_ticks() * i64::from(trade.qty);
+    }
+
+    /// Takes a busted trade out of the totals. Open, high, low and last
+    /// still reflect it.
+    pub fn bust(&mut self, trade: &Trade<P>) {
+        self.volume -= u64::from(trade.qty);
+        self.trade_count -= 1;
+        self.turnover -= trade.price.to_ticks() * i64::from(trade.qty);
+    }
+
+    /// Volume-weighted average price over the session.
+    pub fn vwap(&self) -> Option<f64> {
+        (self.volume > 0).then(|| self.turnover as f64 / self.volume as f64)
+    }
+}
+
+impl EventSink for SessionStats {
+    fn publish(&mut self, _symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        match event {
+            OrderEvent::Trade(trade) => self.record(trade),
+            OrderEvent::TradeBust { trade, .. } => self.bust(trade),
+            OrderEvent::Settled { price, .. } => self.settlement = Some(*price),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Produces a `Stats` market data message for a book every `interval`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsTicker {
+    interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl StatsTicker {
+    pub fn new(interval: Duration) -> Self {
+        StatsTicker {
+            interval,
+            last_sent: None,
+        }
+    }
+
+    pub fn poll(&mut self, order_book: &OrderBook, now: Instant) -> Option<MarketDataMessage> {
+        if self
+            .last_sent
+            .is_some_and(|sent| now.saturating_duration_since(sent) < self.interval)
+        {
+            return None;
+        }
+        self.last_sent = Some(now);
+        Some(MarketDataMessage::Stats {
+            symbol: order_book.symbol().to_string(),
+            stats: order_book.stats(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SessionStats, StatsTicker};
+    use crate::testing::new_order;
+    use crate::{OrderBook, Side};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn tracks_session_totals() {
+        let mut order_book = OrderBook::new();
+        assert_eq!(order_book.stats(), SessionStats::default());
+        for (side, price, qty) in [
+            (Side::Sell, 101, 2),
+            (Side::Sell, 103, 2),
+            (Side::Buy, 103, 4),
+            (Side::Buy, 99, 1),
+            (Side::Sell, 99, 1),
+        ] {
+            order_book.process_command(new_order(side, price, qty));
+        }
+        let stats = order_book.stats();
+        assert_eq!(stats.open, Some(101));
+        assert_eq!(stats.high, Some(103));
+        assert_eq!(stats.low, Some(99));
+        assert_eq!(stats.last, Some(99));
+        assert_eq!((stats.volume, stats.trade_count), (5, 3));
+        assert_eq!(stats.turnover, 202 + 206 + 99);
+        assert_eq!(stats.vwap(), Some(101.4));
+
+        assert_eq!(order_book.reset_stats(), stats);
+        assert_eq!(order_book.stats(), SessionStats::default());
+    }
+
+    #[test]
+    fn ticker_emits_once_per_interval() {
+        let order_book = OrderBook::with_symbol("ABC");
+        let start = Instant::now();
+        let mut ticker = StatsTicker::new(Duration::from_secs(1));
+        assert!(ticker.poll(&order_book, start).is_some());
+        assert!(ticker
+            .poll(&order_book, start + Duration::from_millis(999))
+            .is_none());
+        assert!(ticker
+            .poll(&order_book, start + Duration::from_secs(1))
+            .is_some());
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Stop orders.
+//!
+//! A stop holds a command back until the market trades at or through its
+//! trigger price: up through it for a buy stop, down through it for a sell
+//! stop. Pending stops are kept per side in trigger order, so a trade finds
+//! the stops it triggers by taking them off the front of each side instead
+//! of looking at every pending stop.
+
+use crate::{OrderCommand, Price, Side};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+
+pub type StopId = u64;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StopIndex<P = i32> {
+    /// Lowest trigger first, since prices rise through them in that order.
+    buys: BTreeMap<(P, StopId), OrderCommand<P>>,
+    /// Highest trigger first.
+    sells: BTreeMap<(Reverse<P>, StopId), OrderCommand<P>>,
+    triggers: HashMap<StopId, (Side, P)>,
+    next_id: StopId,
+}
+
+impl<P: Price> StopIndex<P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.triggers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    /// Holds `command` until a trade prints at or through `trigger` in the
+    /// direction `side` stops trigger.
+    pub fn insert(&mut self, side: Side, trigger: P, command: OrderCommand<P>) -> StopId {
+        self.next_id += 1;
+        let id = self.next_id;
+        match side {
+            Side::Buy => self.buys.insert((trigger, id), command),
+            Side::Sell => self.sells.insert((Reverse(trigger), id), command),
+        };
+        self.triggers.insert(id, (side, trigger));
+        id
+    }
+
+    /// Removes a pending stop, returning its command.
+    pub fn remove(&mut self, id: StopId) -> Option<OrderCommand<P>> {
+        match self.triggers.remove(&id)? {
+            (Side::Buy, trigger) => self.buys.remove(&(trigger, id)),
+            (Side::Sell, trigger) => self.sells.remove(&(Reverse(trigger), id)),
+        }
+    }
+
+    /// Takes out every stop triggered by trades between `low` and `high`:
+    /// buy stops from the lowest trigger up, then sell stops from the
+    /// highest down, earliest first among equal triggers.
+    pub fn take_triggered(&mut self, low: P, high: P) -> Vec<(StopId, OrderCommand<P>)> {
+        let mut triggered = take_through(&mut self.buys, high);
+        triggered.extend(take_through(&mut self.sells, Reverse(low)));
+        for (id, _) in &triggered {
+            self.triggers.remove(id);
+        }
+        triggered
+    }
+}
+
+/// Removes the stops at the front of `stops` up to and including those at
+/// `last`, in order.
+fn take_through<K: Ord + Copy, P>(
+    stops: &mut BTreeMap<(K, StopId), OrderCommand<P>>,
+    last: K,
+) -> Vec<(StopId, OrderCommand<P>)> {
+    let keys: Vec<(K, StopId)> = stops
+        .range(..=(last, StopId::MAX))
+        .map(|(key, _)| *key)
+        .collect();
+    keys.into_iter()
+        .filter_map(|key| Some((key.1, stops.remove(&key)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StopIndex;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+
+    fn new(side: Side, price: i32, qty: u32) -> OrderCommand {
+        OrderCommand::New {
+            order_type: OrderType::FillAndKill,
+            side,
+            price,
+            qty,
+            participant: 1,
+        }
+    }
+
+    #[test]
+    fn takes_only_what_a_trade_reaches() {
+        let mut stops = StopIndex::new();
+        let buy_105 = stops.insert(Side::Buy, 105, new(Side::Buy, 110, 1));
+        let buy_101 = stops.insert(Side::Buy, 101, new(Side::Buy, 110, 2));
+        let sell_95 = stops.insert(Side::Sell, 95, new(Side::Sell, 90, 3));
+        let sell_99 = stops.insert(Side::Sell, 99, new(Side::Sell, 90, 4));
+        let canceled = stops.insert(Side::Buy, 100, new(Side::Buy, 110, 5));
+        assert!(stops.remove(canceled).is_some());
+        assert!(stops.remove(canceled).is_none());
+
+        assert!(stops.take_triggered(100, 100).is_empty());
+        let ids = |fired: Vec<_>| fired.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(ids(stops.take_triggered(99, 101)), [buy_101, sell_99]);
+        assert_eq!(
+            ids(stops.take_triggered(i32::MIN, i32::MAX)),
+            [buy_105, sell_95]
+        );
+        assert!(stops.is_empty());
+    }
+
+    #[test]
+    fn a_trade_sets_off_stops() {
+        let mut order_book = OrderBook::new();
+        for (price, qty) in [(101, 5), (102, 5)] {
+            order_book.process_command(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side: Side::Sell,
+                price,
+                qty,
+                participant: 2,
+            });
+        }
+        // The first stop's trade at 102 sets off the second.
+        order_book.add_stop(Side::Buy, 101, new(Side::Buy, 102, 5));
+        order_book.add_stop(Side::Buy, 102, new(Side::Buy, 102, 5));
+        let untouched = order_book.add_stop(Side::Sell, 100, new(Side::Sell, 90, 5));
+        order_book.process_command(new(Side::Buy, 101, 1));
+        let prices: Vec<i32> = order_book.trades().iter().map(|t| t.price).collect();
+        assert_eq!(prices, [101, 101, 102, 102]);
+        assert!(order_book.asks.is_empty());
+        assert_eq!(order_book.stops().len(), 1);
+        assert!(order_book.cancel_stop(untouched));
+    }
+}
@@ -0,0 +1,436 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Trade surveillance.
+//!
+//! [`SpoofingMonitor`] looks for the usual shape of spoofing and layering:
+//! a participant rests large orders behind the touch on one side, where
+//! they make the book look heavy but are unlikely to trade, gets smaller
+//! orders filled on the other side, and cancels the large ones. The
+//! heuristic is deliberately simple and its alerts are leads for a person
+//! to look at, not findings; each carries the orders and trades that set it
+//! off.
+//!
+//! [`WashTradeMonitor`] flags trades that change nobody's position: a
+//! participant, or accounts linked to the same owner, on both sides of a
+//! trade, and pairs of owners trading the same quantity back and forth at
+//! the same price. Unlike [`internalization`](crate::internalization),
+//! which stops trades from happening, it only reports trades that did.
+
+use crate::{OrderBook, OrderEvent, OrderId, ParticipantId, Side, Timestamp, Trade};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpoofingConfig {
+    /// Orders this size and up count as large.
+    pub min_qty: u32,
+    /// How far behind the best price on its side, in ticks, a large order
+    /// must rest to be watched.
+    pub min_ticks_away: i32,
+    /// Large orders a participant must cancel on one side within `window`.
+    pub min_cancels: usize,
+    /// How far back cancels and executions are looked at together.
+    pub window: Duration,
+}
+
+impl Default for SpoofingConfig {
+    fn default() -> Self {
+        SpoofingConfig {
+            min_qty: 1_000,
+            min_ticks_away: 1,
+            min_cancels: 2,
+            window: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpoofingAlert {
+    pub participant: ParticipantId,
+    /// The side the large orders rested on.
+    pub side: Side,
+    /// The large orders canceled, oldest first.
+    pub canceled: Vec<OrderId>,
+    pub canceled_qty: u64,
+    /// The participant's trades on the other side, oldest first.
+    pub trades: Vec<u64>,
+    pub traded_qty: u64,
+    pub timestamp: Timestamp,
+}
+
+/// A large order resting away from the touch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Watched {
+    participant: ParticipantId,
+    side: Side,
+    qty: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Activity {
+    at: Timestamp,
+    side: Side,
+    /// Order id for a cancel, trade id for an execution.
+    id: u64,
+    qty: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct History {
+    cancels: VecDeque<Activity>,
+    executions: VecDeque<Activity>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpoofingMonitor {
+    config: SpoofingConfig,
+    /// Events of the book already looked at.
+    seen: usize,
+    watched: HashMap<OrderId, Watched>,
+    history: HashMap<ParticipantId, History>,
+}
+
+impl SpoofingMonitor {
+    pub fn new(config: SpoofingConfig) -> Self {
+        SpoofingMonitor {
+            config,
+            seen: 0,
+            watched: HashMap::new(),
+            history: HashMap::new(),
+        }
+    }
+
+    /// Looks at the events `order_book` has produced since the last call;
+    /// call after every command, since an order is only watched if it is
+    /// still resting when its placement is looked at.
+    pub fn update(&mut self, order_book: &OrderBook) -> Vec<SpoofingAlert> {
+        let now = order_book.now();
+        let events = order_book.events();
+        let mut placed = Vec::new();
+        let mut alerts = Vec::new();
+        for event in events.get(self.seen..).unwrap_or_default() {
+            match *event {
+                OrderEvent::Placed { id, .. } => placed.push(id),
+                OrderEvent::Trade(trade) => {
+                    // A large order that trades was there to be traded with.
+                    self.watched.remove(&trade.maker_order_id);
+                    let maker_side = trade.aggressor_side.opposite();
+                    for (participant, side) in [
+                        (trade.maker_participant, maker_side),
+                        (trade.taker_participant, trade.aggressor_side),
+                    ] {
+                        let history = self.history.entry(participant).or_default();
+                        history.executions.push_back(Activity {
+                            at: trade.timestamp,
+                            side,
+                            id: trade.id as u64,
+                            qty: trade.qty,
+                        });
+                    }
+                }
+                OrderEvent::Canceled { id } => {
+                    if let Some(watched) = self.watched.remove(&id) {
+                        alerts.extend(self.canceled(id, watched, now));
+                    }
+                }
+                OrderEvent::Filled { id, .. } => {
+                    self.watched.remove(&id);
+                }
+                _ => {}
+            }
+        }
+        self.seen = events.len();
+
+        for id in placed {
+            let Some(order) = order_book.find_order(id) else {
+                continue;
+            };
+            let ticks_away = match order.side {
+                Side::Buy => order_book.best_bid().map(|best| best - order.price),
+                Side::Sell => order_book.best_ask().map(|best| order.price - best),
+            };
+            if order.initial_qty >= self.config.min_qty
+                && ticks_away.is_some_and(|ticks| ticks >= self.config.min_ticks_away)
+            {
+                let watched = Watched {
+                    participant: order.participant,
+                    side: order.side,
+                    qty: order.initial_qty,
+                };
+                self.watched.insert(id, watched);
+            }
+        }
+        alerts
+    }
+
+    /// Records the cancel of a watched order and checks the participant's
+    /// recent activity for the pattern.
+    fn canceled(&mut self, id: OrderId, watched: Watched, now: Timestamp) -> Option<SpoofingAlert> {
+        let window = self.config.window;
+        let history = self.history.entry(watched.participant).or_default();
+        history.cancels.push_back(Activity {
+            at: now,
+            side: watched.side,
+            id,
+            qty: watched.qty,
+        });
+        for recent in [&mut history.cancels, &mut history.executions] {
+            while recent
+                .front()
+                .is_some_and(|activity| now.saturating_duration_since(activity.at) > window)
+            {
+                recent.pop_front();
+            }
+        }
+
+        let cancels: Vec<Activity> = history
+            .cancels
+            .iter()
+            .filter(|cancel| cancel.side == watched.side)
+            .copied()
+            .collect();
+        let executions: Vec<Activity> = history
+            .executions
+            .iter()
+            .filter(|execution| execution.side == watched.side.opposite())
+            .copied()
+            .collect();
+        let canceled_qty = cancels.iter().map(|cancel| cancel.qty as u64).sum();
+        let traded_qty = executions.iter().map(|trade| trade.qty as u64).sum();
+        if cancels.len() < self.config.min_cancels
+            || executions.is_empty()
+            || traded_qty >= canceled_qty
+        {
+            return None;
+        }
+        // Each cancel and execution backs one alert at most.
+        history.cancels.retain(|cancel| cancel.side != watched.side);
+        history
+            .executions
+            .retain(|execution| execution.side == watched.side);
+        Some(SpoofingAlert {
+            participant: watched.participant,
+            side: watched.side,
+            canceled: cancels.iter().map(|cancel| cancel.id).collect(),
+            canceled_qty,
+            trades: executions.iter().map(|trade| trade.id).collect(),
+            traded_qty,
+            timestamp: now,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum WashTradeKind {
+    /// The same owner bought and sold.
+    SameOwner,
+    /// A trade undone by an earlier one between the same owners.
+    Offsetting,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WashTradeAlert {
+    pub kind: WashTradeKind,
+    /// One trade for `SameOwner`, the earlier trade and the one offsetting
+    /// it for `Offsetting`.
+    pub trades: Vec<u64>,
+    pub buyer: ParticipantId,
+    pub seller: ParticipantId,
+    pub price: i32,
+    pub qty: u32,
+    pub timestamp: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WashTradeMonitor {
+    /// How far apart offsetting trades may be.
+    window: Duration,
+    owners: HashMap<ParticipantId, ParticipantId>,
+    /// Trades of the book already looked at.
+    seen: usize,
+    /// Recent trades between different owners that nothing has offset yet.
+    recent: VecDeque<Trade>,
+}
+
+impl WashTradeMonitor {
+    pub fn new(window: Duration) -> Self {
+        WashTradeMonitor {
+            window,
+            owners: HashMap::new(),
+            seen: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Treats `participant` as trading for `owner`, e.g. one of a firm's
+    /// accounts for the firm. Participants not linked own themselves.
+    pub fn link(&mut self, participant: ParticipantId, owner: ParticipantId) {
+        self.owners.insert(participant, owner);
+    }
+
+    pub fn owner(&self, participant: ParticipantId) -> ParticipantId {
+        self.owners
+            .get(&participant)
+            .copied()
+            .unwrap_or(participant)
+    }
+
+    /// Looks at the trades `order_book` has made since the last call.
+    pub fn update(&mut self, order_book: &OrderBook) -> Vec<WashTradeAlert> {
+        let trades = order_book.trades();
+        let mut alerts = Vec::new();
+        for trade in trades.get(self.seen..).unwrap_or_default() {
+            alerts.extend(self.check(trade));
+        }
+        self.seen = trades.len();
+        alerts
+    }
+
+    fn check(&mut self, trade: &Trade) -> Option<WashTradeAlert> {
+        let (buyer, seller) = match trade.aggressor_side {
+            Side::Buy => (trade.taker_participant, trade.maker_participant),
+            Side::Sell => (trade.maker_participant, trade.taker_participant),
+        };
+        let alert = |kind, trades| WashTradeAlert {
+            kind,
+            trades,
+            buyer,
+            seller,
+            price: trade.price,
+            qty: trade.qty,
+            timestamp: trade.timestamp,
+        };
+        if self.owner(buyer) == self.owner(seller) {
+            return Some(alert(WashTradeKind::SameOwner, vec![trade.id as u64]));
+        }
+
+        let window = self.window;
+        while self.recent.front().is_some_and(|earlier| {
+            trade.timestamp.saturating_duration_since(earlier.timestamp) > window
+        }) {
+            self.recent.pop_front();
+        }
+        let offset = self.recent.iter().position(|earlier| {
+            let (earlier_buyer, earlier_seller) = match earlier.aggressor_side {
+                Side::Buy => (earlier.taker_participant, earlier.maker_participant),
+                Side::Sell => (earlier.maker_participant, earlier.taker_participant),
+            };
+            earlier.price == trade.price
+                && earlier.qty == trade.qty
+                && self.owner(earlier_buyer) == self.owner(seller)
+                && self.owner(earlier_seller) == self.owner(buyer)
+        });
+        match offset.and_then(|index| self.recent.remove(index)) {
+            Some(earlier) => Some(alert(
+                WashTradeKind::Offsetting,
+                vec![earlier.id as u64, trade.id as u64],
+            )),
+            None => {
+                self.recent.push_back(*trade);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpoofingConfig, SpoofingMonitor, WashTradeKind, WashTradeMonitor};
+    use crate::{OrderBook, OrderCommand, OrderId, OrderType, ParticipantId, Side};
+    use std::time::Duration;
+
+    fn new(
+        order_book: &mut OrderBook,
+        side: Side,
+        price: i32,
+        qty: u32,
+        participant: ParticipantId,
+    ) {
+        order_book.process_command(OrderCommand::New {
+            order_type: OrderType::GoodTilCancel,
+            side,
+            price,
+            qty,
+            participant,
+        });
+    }
+
+    fn cancel(order_book: &mut OrderBook, id: OrderId, side: Side, price: i32) {
+        order_book.process_command(OrderCommand::Cancel { id, side, price });
+    }
+
+    #[test]
+    fn flags_layered_bids_pulled_after_a_sale() {
+        let mut order_book = OrderBook::new();
+        let mut monitor = SpoofingMonitor::new(SpoofingConfig::default());
+        let mut alerts = Vec::new();
+        // An ordinary market between participants 2 and 3.
+        new(&mut order_book, Side::Buy, 100, 10, 2);
+        new(&mut order_book, Side::Sell, 102, 10, 3);
+        alerts.extend(monitor.update(&order_book));
+
+        // Participant 1 layers large bids behind the touch, sells into the
+        // bid, then pulls the bids.
+        new(&mut order_book, Side::Buy, 99, 5_000, 1);
+        alerts.extend(monitor.update(&order_book));
+        new(&mut order_book, Side::Buy, 98, 5_000, 1);
+        alerts.extend(monitor.update(&order_book));
+        new(&mut order_book, Side::Sell, 100, 10, 1);
+        alerts.extend(monitor.update(&order_book));
+        cancel(&mut order_book, 3, Side::Buy, 99);
+        alerts.extend(monitor.update(&order_book));
+        assert!(alerts.is_empty());
+        cancel(&mut order_book, 4, Side::Buy, 98);
+        alerts.extend(monitor.update(&order_book));
+
+        assert_eq!(alerts.len(), 1);
+        let alert = &alerts[0];
+        assert_eq!((alert.participant, alert.side), (1, Side::Buy));
+        assert_eq!(alert.canceled, [3, 4]);
+        assert_eq!(alert.canceled_qty, 10_000);
+        assert_eq!(alert.trades, [1]);
+        assert_eq!(alert.traded_qty, 10);
+
+        // A large bid at the touch is not watched, so pulling it is fine.
+        new(&mut order_book, Side::Buy, 101, 5_000, 3);
+        new(&mut order_book, Side::Sell, 103, 1, 3);
+        monitor.update(&order_book);
+        cancel(&mut order_book, 6, Side::Buy, 101);
+        assert!(monitor.update(&order_book).is_empty());
+    }
+
+    #[test]
+    fn flags_wash_trades() {
+        let mut order_book = OrderBook::new();
+        let mut monitor = WashTradeMonitor::new(Duration::from_secs(60));
+        monitor.link(4, 1);
+        // Linked accounts on both sides.
+        new(&mut order_book, Side::Sell, 100, 5, 1);
+        new(&mut order_book, Side::Buy, 100, 5, 4);
+        // 2 buys from 3, then sells the same back at the same price.
+        new(&mut order_book, Side::Sell, 101, 7, 3);
+        new(&mut order_book, Side::Buy, 101, 7, 2);
+        new(&mut order_book, Side::Buy, 101, 7, 3);
+        new(&mut order_book, Side::Sell, 101, 7, 2);
+        // An ordinary trade.
+        new(&mut order_book, Side::Sell, 102, 1, 5);
+        new(&mut order_book, Side::Buy, 102, 1, 2);
+
+        let alerts = monitor.update(&order_book);
+        let found: Vec<_> = alerts
+            .iter()
+            .map(|alert| (alert.kind, alert.trades.clone(), alert.buyer, alert.seller))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (WashTradeKind::SameOwner, vec![1], 4, 1),
+                (WashTradeKind::Offsetting, vec![2, 3], 3, 2),
+            ]
+        );
+        assert!(monitor.update(&order_book).is_empty());
+    }
+}
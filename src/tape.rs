@@ -0,0 +1,140 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Time and sales.
+//!
+//! Every book keeps a [`TradeTape`] of its executions. The tape can also be
+//! fed from an event stream, which is how a downstream process rebuilds one.
+
+use crate::sink::EventSink;
+use crate::{OrderEvent, Price, Timestamp, Trade};
+use std::io;
+
+/// Executions in the order they happened. Trade ids and timestamps only ever
+/// grow along the tape, so lookups by either are binary searches.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TradeTape<P = i32> {
+    trades: Vec<Trade<P>>,
+}
+
+impl<P: Price> TradeTape<P> {
+    pub fn new() -> Self {
+        TradeTape::default()
+    }
+
+    pub fn record(&mut self, trade: Trade<P>) {
+        self.trades.push(trade);
+    }
+
+    pub fn len(&self) -> usize {
+        self.trades.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trades.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[Trade<P>] {
+        &self.trades
+    }
+
+    /// The most recent `n` trades, oldest first.
+    pub fn last(&self, n: usize) -> &[Trade<P>] {
+        &self.trades[self.trades.len().saturating_sub(n)..]
+    }
+
+    /// Trades executed at or after `from` and before `to`.
+    pub fn between(&self, from: Timestamp, to: Timestamp) -> &[Trade<P>] {
+        let start = self.trades.partition_point(|trade| trade.timestamp < from);
+        let end = self.trades.partition_point(|trade| trade.timestamp < to);
+        &self.trades[start..end.max(start)]
+    }
+
+    /// Takes a busted trade off the tape.
+    pub fn bust(&mut self, id: usize) -> Option<Trade<P>> {
+        self.trades
+            .binary_search_by_key(&id, |trade| trade.id)
+            .ok()
+            .map(|index| self.trades.remove(index))
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Trade<P>> {
+        self.trades
+            .binary_search_by_key(&id, |trade| trade.id)
+            .ok()
+            .map(|index| &self.trades[index])
+    }
+}
+
+impl EventSink for TradeTape {
+    fn publish(&mut self, _symbol: &str, event: &OrderEvent) -> io::Result<()> {
+        match event {
+            OrderEvent::Trade(trade) => self.record(*trade),
+            OrderEvent::TradeBust { trade, .. } => {
+                self.bust(trade.id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TradeTape;
+    use crate::{Side, Timestamp, Trade};
+    use std::time::Duration;
+
+    fn tape(start: Timestamp) -> TradeTape {
+        let mut tape = TradeTape::new();
+        for i in 0..5 {
+            tape.record(Trade {
+                id: 10 + i,
+                price: 120 + i as i32,
+                qty: 1,
+                aggressor_side: Side::Buy,
+                maker_order_id: 1,
+                taker_order_id: 2,
+                maker_participant: 1,
+                taker_participant: 2,
+                timestamp: start + Duration::from_secs(i as u64),
+                maker_fee: 0,
+                taker_fee: 0,
+                cross: false,
+                dark: false,
+                currency: crate::Currency::USD,
+            });
+        }
+        tape
+    }
+
+    #[test]
+    fn last_n_trades() {
+        let tape = tape(Timestamp::now());
+        let prices: Vec<i32> = tape.last(2).iter().map(|trade| trade.price).collect();
+        assert_eq!(prices, [123, 124]);
+        assert_eq!(tape.last(10).len(), 5);
+        assert!(tape.last(0).is_empty());
+    }
+
+    #[test]
+    fn trades_in_time_range() {
+        let start = Timestamp::now();
+        let tape = tape(start);
+        let range = tape.between(
+            start + Duration::from_secs(1),
+            start + Duration::from_secs(3),
+        );
+        let ids: Vec<usize> = range.iter().map(|trade| trade.id).collect();
+        assert_eq!(ids, [11, 12]);
+        assert!(tape
+            .between(
+                start + Duration::from_secs(3),
+                start + Duration::from_secs(1)
+            )
+            .is_empty());
+        assert_eq!(tape.get(13).unwrap().price, 123);
+        assert!(tape.get(99).is_none());
+    }
+}
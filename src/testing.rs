@@ -0,0 +1,28 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Helpers shared by the unit tests.
+
+use crate::{OrderCommand, OrderType, ParticipantId, Side};
+
+/// A good-til-cancel order for participant 1.
+pub(crate) fn new_order(side: Side, price: i32, qty: u32) -> OrderCommand {
+    new_order_for(side, price, qty, 1)
+}
+
+/// A good-til-cancel order for `participant`.
+pub(crate) fn new_order_for(
+    side: Side,
+    price: i32,
+    qty: u32,
+    participant: ParticipantId,
+) -> OrderCommand {
+    OrderCommand::New {
+        order_type: OrderType::GoodTilCancel,
+        side,
+        price,
+        qty,
+        participant,
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Hierarchical timer wheel.
+//!
+//! Timers are filed by tick, a fixed resolution, but fire at their exact
+//! time. Each level has 64 slots and each slot at level `n` spans 64^n
+//! ticks, so eleven levels cover every `u64` tick. A timer goes in the
+//! lowest level whose slot span still tells it apart from the current tick;
+//! as time reaches a slot its timers either fire or drop to a lower level.
+//! Scheduling is O(1), and advancing costs O(levels) per occupied slot
+//! passed rather than per tick, so long jumps in simulated time are cheap.
+
+use crate::Timestamp;
+use std::time::Duration;
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 64_usize.div_ceil(SLOT_BITS as usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimerWheel<T> {
+    resolution: u64,
+    /// The tick everything before has been fired.
+    current: u64,
+    /// Timers with their deadline in nanoseconds.
+    levels: Vec<Vec<Vec<(u64, T)>>>,
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    /// A coarser `resolution` means fewer cascades between levels but more
+    /// timers sharing a slot.
+    pub fn new(resolution: Duration) -> Self {
+        TimerWheel {
+            resolution: (resolution.as_nanos() as u64).max(1),
+            current: 0,
+            levels: (0..LEVELS)
+                .map(|_| (0..SLOTS).map(|_| Vec::new()).collect())
+                .collect(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Schedules `value` for `at`. Anything at or before the last advance
+    /// fires on the next one.
+    pub fn insert(&mut self, at: Timestamp, value: T) {
+        self.place(at.as_nanos(), value);
+        self.len += 1;
+    }
+
+    /// No later than the first time an advance would fire anything, or
+    /// `None` if there are no timers.
+    pub fn next_deadline(&self) -> Option<Timestamp> {
+        let (level, slot) = self.next_occupied()?;
+        let nanos = match level {
+            0 => self.levels[0][slot]
+                .iter()
+                .map(|&(deadline, _)| deadline)
+                .min()?,
+            _ => self.slot_start(level, slot).saturating_mul(self.resolution),
+        };
+        Some(Timestamp::from_nanos(nanos))
+    }
+
+    /// Removes and returns everything due by `now`, earliest first.
+    pub fn advance(&mut self, now: Timestamp) -> Vec<T> {
+        let now_nanos = now.as_nanos();
+        let now = now_nanos / self.resolution;
+        let mut due = Vec::new();
+        while let Some((level, slot)) = self.next_occupied() {
+            let start = self.slot_start(level, slot);
+            if start > now {
+                break;
+            }
+            self.current = self.current.max(start);
+            let mut waiting = Vec::new();
+            for (deadline, value) in std::mem::take(&mut self.levels[level][slot]) {
+                if deadline <= now_nanos {
+                    due.push((deadline, value));
+                    self.len -= 1;
+                } else if level == 0 {
+                    waiting.push((deadline, value));
+                } else {
+                    self.place(deadline, value);
+                }
+            }
+            // Only the slot for the current tick can hold timers that aren't
+            // due yet, and every other slot is later still.
+            if !waiting.is_empty() {
+                self.levels[0][slot] = waiting;
+                break;
+            }
+        }
+        self.current = self.current.max(now);
+        // Slots are visited in time order, but a slot cascaded early can
+        // hold deadlines past one visited after it.
+        due.sort_by_key(|&(deadline, _)| deadline);
+        due.into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// Timers already past go in the current tick's slot.
+    fn place(&mut self, deadline: u64, value: T) {
+        let tick = (deadline / self.resolution).max(self.current);
+        let level = match tick ^ self.current {
+            0 => 0,
+            differ => ((63 - differ.leading_zeros()) / SLOT_BITS) as usize,
+        };
+        let slot = (tick >> (level as u32 * SLOT_BITS)) as usize % SLOTS;
+        self.levels[level][slot].push((deadline, value));
+    }
+
+    /// The lowest level holds the earliest timers: anything higher differs
+    /// from the current tick in a more significant digit.
+    fn next_occupied(&self) -> Option<(usize, usize)> {
+        if self.len == 0 {
+            return None;
+        }
+        self.levels.iter().enumerate().find_map(|(level, slots)| {
+            let from = self.digit(level);
+            (from..SLOTS)
+                .find(|&slot| !slots[slot].is_empty())
+                .map(|slot| (level, slot))
+        })
+    }
+
+    fn digit(&self, level: usize) -> usize {
+        (self.current >> (level as u32 * SLOT_BITS)) as usize % SLOTS
+    }
+
+    /// First tick covered by `slot` within the current tick's window at
+    /// `level`.
+    fn slot_start(&self, level: usize, slot: usize) -> u64 {
+        let shift = level as u32 * SLOT_BITS;
+        let window = shift + SLOT_BITS;
+        let base = if window >= 64 {
+            0
+        } else {
+            self.current >> window << window
+        };
+        base | (slot as u64) << shift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimerWheel;
+    use crate::Timestamp;
+    use std::time::Duration;
+
+    fn ms(ms: u64) -> Timestamp {
+        Timestamp::from_nanos(1_700_000_000_000_000_000) + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn fires_in_deadline_order_across_levels() {
+        let mut wheel = TimerWheel::new(Duration::from_millis(1));
+        for (at, value) in [(5_000_000, "far"), (70, "b"), (3, "a"), (4_100, "c")] {
+            wheel.insert(ms(at), value);
+        }
+        wheel.insert(ms(70), "b2");
+        assert_eq!(wheel.len(), 5);
+        assert!(wheel.next_deadline().unwrap() <= ms(3));
+
+        assert!(wheel.advance(ms(2)).is_empty());
+        assert_eq!(wheel.advance(ms(100)), ["a", "b", "b2"]);
+        assert!(wheel.next_deadline().unwrap() <= ms(4_100));
+        assert!(wheel.advance(ms(4_099)).is_empty());
+        assert_eq!(wheel.advance(ms(4_100)), ["c"]);
+        assert_eq!(wheel.advance(ms(10_000_000)), ["far"]);
+        assert!(wheel.is_empty());
+        assert_eq!(wheel.next_deadline(), None);
+
+        // Already past: due on the next advance.
+        wheel.insert(ms(1), "late");
+        assert_eq!(wheel.advance(ms(10_000_000)), ["late"]);
+    }
+
+    #[test]
+    fn matches_a_sorted_list() {
+        let mut rng = crate::sim::Rng::new(11);
+        let mut wheel = TimerWheel::new(Duration::from_micros(1));
+        let mut expected = Vec::new();
+        // Spread over many orders of magnitude to reach every level.
+        let span = |rng: &mut crate::sim::Rng, bits| {
+            let bits = rng.range(0, bits);
+            rng.range(0, 1 << bits)
+        };
+        let mut now = 0;
+        for step in 0..2_000 {
+            let at = now + span(&mut rng, 40);
+            wheel.insert(Timestamp::from_nanos(at), (at, step));
+            expected.push((at, step));
+            if rng.chance(20) {
+                now += span(&mut rng, 36);
+                let mut due: Vec<(u64, u64)> = expected
+                    .iter()
+                    .copied()
+                    .filter(|&(at, _)| at <= now)
+                    .collect();
+                due.sort();
+                expected.retain(|&(at, _)| at > now);
+                let mut fired = wheel.advance(Timestamp::from_nanos(now));
+                fired.sort();
+                assert_eq!(fired, due);
+                assert_eq!(wheel.len(), expected.len());
+                if let (Some(bound), Some(&(first, _))) =
+                    (wheel.next_deadline(), expected.iter().min())
+                {
+                    assert!(bound.as_nanos() <= first);
+                }
+            }
+        }
+    }
+}
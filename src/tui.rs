@@ -0,0 +1,290 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Live terminal dashboard.
+//!
+//! Runs a book embedded with a synthetic order flow and redraws depth, the
+//! latest trades, throughput and per-stage latency a few times a second.
+//! Frames are plain text positioned with ANSI escapes, so any terminal will
+//! do and nothing beyond the standard library is needed.
+//!
+//! The flow is random but seeded: limit orders around a drifting mid price,
+//! some marketable, with a share of cancels to keep the book from growing
+//! without bound.
+
+use crate::latency::Stage;
+use crate::sim::Rng;
+use crate::{OrderBook, OrderCommand, OrderType, Side, Trade};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Clears the screen and homes the cursor.
+const CLEAR: &str = "\x1b[2J\x1b[H";
+const BOLD: &str = "\x1b[1m";
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+const BAR_WIDTH: u64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TuiConfig {
+    pub refresh: Duration,
+    /// Commands submitted between frames.
+    pub commands_per_frame: usize,
+    pub depth_levels: usize,
+    pub trades_shown: usize,
+    pub seed: u64,
+    /// Stop after this long; run until interrupted if `None`.
+    pub run_for: Option<Duration>,
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        TuiConfig {
+            refresh: Duration::from_millis(250),
+            commands_per_frame: 20_000,
+            depth_levels: 10,
+            trades_shown: 10,
+            seed: 1,
+            run_for: None,
+        }
+    }
+}
+
+/// Commands and trades over the last frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Throughput {
+    pub commands_per_sec: f64,
+    pub trades_per_sec: f64,
+    pub uptime: Duration,
+}
+
+/// Drives `order_book` with synthetic flow and draws to stdout until
+/// `config.run_for` has passed.
+pub fn run(order_book: &mut OrderBook, config: TuiConfig) -> io::Result<()> {
+    order_book.set_latency_tracking(true);
+    let mut flow = Flow::new(config.seed);
+    let started = Instant::now();
+    let mut stdout = io::stdout();
+    loop {
+        let frame_started = Instant::now();
+        let trades_before = order_book.trades().len();
+        for _ in 0..config.commands_per_frame {
+            let command = flow.next(order_book);
+            order_book.process_command(command);
+        }
+        // Sleep off whatever is left of the refresh interval.
+        let busy = frame_started.elapsed();
+        std::thread::sleep(config.refresh.saturating_sub(busy));
+        let elapsed = frame_started.elapsed().as_secs_f64();
+        let throughput = Throughput {
+            commands_per_sec: config.commands_per_frame as f64 / elapsed,
+            trades_per_sec: (order_book.trades().len() - trades_before) as f64 / elapsed,
+            uptime: started.elapsed(),
+        };
+        stdout.write_all(CLEAR.as_bytes())?;
+        stdout.write_all(render(order_book, &throughput, &config).as_bytes())?;
+        stdout.flush()?;
+        if config
+            .run_for
+            .is_some_and(|limit| started.elapsed() >= limit)
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// One frame of the dashboard.
+pub fn render(order_book: &OrderBook, throughput: &Throughput, config: &TuiConfig) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{BOLD}matcher-rs{RESET}  {}  {:?}  up {}s",
+        order_book.symbol(),
+        order_book.status(),
+        throughput.uptime.as_secs()
+    );
+    let _ = writeln!(
+        out,
+        "{:>12.0} cmds/s {:>12.0} trades/s {:>12} trades total",
+        throughput.commands_per_sec,
+        throughput.trades_per_sec,
+        order_book.trades().len()
+    );
+    out.push('\n');
+
+    let depth = order_book.depth(config.depth_levels);
+    let largest = depth
+        .bids
+        .iter()
+        .chain(&depth.asks)
+        .map(|level| level.qty)
+        .max()
+        .unwrap_or(1);
+    let bar = |qty: u64| "#".repeat((qty * BAR_WIDTH).div_ceil(largest) as usize);
+    let _ = writeln!(
+        out,
+        "{BOLD}{:>20} {:>10} {:>8} | {:<8} {:<10} {:<20}{RESET}",
+        "", "bid qty", "bid", "ask", "ask qty", ""
+    );
+    for i in 0..config.depth_levels {
+        let bid = depth.bids.get(i);
+        let ask = depth.asks.get(i);
+        if bid.is_none() && ask.is_none() {
+            break;
+        }
+        let _ = writeln!(
+            out,
+            "{GREEN}{:>20} {:>10} {:>8}{RESET} | {RED}{:<8} {:<10} {:<20}{RESET}",
+            bid.map(|level| bar(level.qty)).unwrap_or_default(),
+            bid.map(|level| level.qty.to_string()).unwrap_or_default(),
+            bid.map(|level| level.price.to_string()).unwrap_or_default(),
+            ask.map(|level| level.price.to_string()).unwrap_or_default(),
+            ask.map(|level| level.qty.to_string()).unwrap_or_default(),
+            ask.map(|level| bar(level.qty)).unwrap_or_default(),
+        );
+    }
+    out.push('\n');
+
+    let _ = writeln!(out, "{BOLD}last trades{RESET}");
+    for trade in order_book.tape().last(config.trades_shown).iter().rev() {
+        let _ = writeln!(out, "{}", trade_line(trade));
+    }
+    out.push('\n');
+
+    if let Some(latency) = order_book.latency() {
+        let _ = writeln!(
+            out,
+            "{BOLD}{:<14} {:>10} {:>10} {:>10} {:>10}{RESET}",
+            "stage", "p50", "p99", "p99.9", "max"
+        );
+        for summary in Stage::ALL.map(|stage| latency.summary(stage)) {
+            if summary.count == 0 {
+                continue;
+            }
+            let _ = writeln!(
+                out,
+                "{:<14} {:>10} {:>10} {:>10} {:>10}",
+                format!("{:?}", summary.stage),
+                format!("{:?}", summary.p50),
+                format!("{:?}", summary.p99),
+                format!("{:?}", summary.p999),
+                format!("{:?}", summary.max),
+            );
+        }
+    }
+    out
+}
+
+fn trade_line(trade: &Trade) -> String {
+    let (color, side) = match trade.aggressor_side {
+        Side::Buy => (GREEN, "buy "),
+        Side::Sell => (RED, "sell"),
+    };
+    format!(
+        "{color}{side}{RESET} {:>6} @ {:<8} #{}",
+        trade.qty, trade.price, trade.id
+    )
+}
+
+/// Synthetic order flow around a drifting mid.
+struct Flow {
+    rng: Rng,
+    mid: i32,
+}
+
+impl Flow {
+    fn new(seed: u64) -> Flow {
+        Flow {
+            rng: Rng::new(seed),
+            mid: 10_000,
+        }
+    }
+
+    fn next(&mut self, order_book: &OrderBook) -> OrderCommand {
+        if self.rng.chance(1) {
+            self.mid += self.rng.range(0, 2) as i32 - 1;
+        }
+        if self.rng.chance(30) {
+            if let Some(command) = self.cancel(order_book) {
+                return command;
+            }
+        }
+        let side = if self.rng.chance(50) {
+            Side::Buy
+        } else {
+            Side::Sell
+        };
+        // Mostly passive, sometimes a tick or two through the mid.
+        let offset = self.rng.range(0, 12) as i32 - 2;
+        let price = match side {
+            Side::Buy => self.mid - offset,
+            Side::Sell => self.mid + offset,
+        };
+        OrderCommand::New {
+            order_type: if self.rng.chance(10) {
+                OrderType::FillAndKill
+            } else {
+                OrderType::GoodTilCancel
+            },
+            side,
+            price,
+            qty: self.rng.range(1, 100) as u32,
+            participant: self.rng.range(1, 8) as u32,
+        }
+    }
+
+    /// Cancels the oldest order at a random level.
+    fn cancel(&mut self, order_book: &OrderBook) -> Option<OrderCommand> {
+        let levels = if self.rng.chance(50) {
+            &order_book.bids
+        } else {
+            &order_book.asks
+        };
+        let level = levels.get(self.rng.range(0, levels.len() as u64) as usize)?;
+        let order = level.orders.front()?;
+        Some(OrderCommand::Cancel {
+            id: order.id,
+            side: order.side,
+            price: order.price,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, Flow, Throughput, TuiConfig};
+    use crate::OrderBook;
+    use std::time::Duration;
+
+    #[test]
+    fn renders_depth_trades_and_latency() {
+        let mut order_book = OrderBook::with_symbol("DEMO");
+        order_book.set_latency_tracking(true);
+        let mut flow = Flow::new(7);
+        for _ in 0..2_000 {
+            let command = flow.next(&order_book);
+            order_book.process_command(command);
+        }
+        let throughput = Throughput {
+            commands_per_sec: 1_000.0,
+            trades_per_sec: 10.0,
+            uptime: Duration::from_secs(3),
+        };
+        let config = TuiConfig::default();
+        let frame = render(&order_book, &throughput, &config);
+
+        assert!(frame.contains("DEMO"));
+        assert!(frame.contains("up 3s"));
+        let best_bid = order_book.best_bid().unwrap().to_string();
+        let best_ask = order_book.best_ask().unwrap().to_string();
+        assert!(frame.contains(&best_bid) && frame.contains(&best_ask));
+        let last = order_book.trades().last().unwrap();
+        assert!(frame.contains(&format!("#{}", last.id)));
+        assert!(frame.contains("Validation") && frame.contains("MatchLookup"));
+        assert!(!frame.contains("Persistence"));
+    }
+}
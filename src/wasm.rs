@@ -0,0 +1,185 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! Exports for JavaScript, used through `web/matcher.js`.
+//!
+//! WebAssembly functions only pass numbers, so strings go through the
+//! module's memory: the caller copies a UTF-8 string into a buffer from
+//! [`wasm_alloc`] and passes its address and length. Results are written as
+//! JSON to a per-book output buffer; the functions that produce one return
+//! its length, and [`wasm_book_output`] its address. Commands, events and
+//! depth use the same JSON as the REST API.
+
+use crate::{OrderBook, OrderCommand};
+use serde::Serialize;
+
+/// A book and the last result read from it.
+pub struct WasmBook {
+    order_book: OrderBook,
+    output: Vec<u8>,
+}
+
+impl WasmBook {
+    fn write(&mut self, value: &impl Serialize) -> i32 {
+        self.output.clear();
+        match serde_json::to_writer(&mut self.output, value) {
+            Ok(()) => self.output.len() as i32,
+            Err(_) => -1,
+        }
+    }
+}
+
+/// A buffer of `len` bytes for passing a string in.
+#[no_mangle]
+pub extern "C" fn wasm_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// # Safety
+///
+/// `ptr` and `len` must be from one call to [`wasm_alloc`].
+#[no_mangle]
+pub unsafe extern "C" fn wasm_free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// # Safety
+///
+/// `symbol` must point to `len` bytes of UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_book_new(symbol: *const u8, len: usize) -> *mut WasmBook {
+    let symbol = match symbol.is_null() {
+        true => "",
+        false => std::str::from_utf8(std::slice::from_raw_parts(symbol, len)).unwrap_or(""),
+    };
+    Box::into_raw(Box::new(WasmBook {
+        order_book: OrderBook::with_symbol(symbol),
+        output: Vec::new(),
+    }))
+}
+
+/// # Safety
+///
+/// `book` must come from [`wasm_book_new`] and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_book_free(book: *mut WasmBook) {
+    if !book.is_null() {
+        drop(Box::from_raw(book));
+    }
+}
+
+/// Processes the JSON command at `command` and outputs the events it
+/// produced as a JSON array. Returns -1 if the command doesn't parse.
+///
+/// # Safety
+///
+/// `book` must come from [`wasm_book_new`] and `command` must point to
+/// `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_book_command(
+    book: *mut WasmBook,
+    command: *const u8,
+    len: usize,
+) -> i32 {
+    let book = &mut *book;
+    let command: OrderCommand =
+        match serde_json::from_slice(std::slice::from_raw_parts(command, len)) {
+            Ok(command) => command,
+            Err(_) => return -1,
+        };
+    let seen = book.order_book.events().len();
+    book.order_book.process_command(command);
+    let events = book.order_book.events()[seen..].to_vec();
+    book.write(&events)
+}
+
+/// Outputs the top `levels` of each side.
+///
+/// # Safety
+///
+/// `book` must come from [`wasm_book_new`].
+#[no_mangle]
+pub unsafe extern "C" fn wasm_book_depth(book: *mut WasmBook, levels: usize) -> i32 {
+    let book = &mut *book;
+    let depth = book.order_book.depth(levels);
+    book.write(&depth)
+}
+
+/// Outputs up to the last `count` trades, oldest first.
+///
+/// # Safety
+///
+/// `book` must come from [`wasm_book_new`].
+#[no_mangle]
+pub unsafe extern "C" fn wasm_book_trades(book: *mut WasmBook, count: usize) -> i32 {
+    let book = &mut *book;
+    let trades = book.order_book.tape().last(count).to_vec();
+    book.write(&trades)
+}
+
+/// Where the last output starts.
+///
+/// # Safety
+///
+/// `book` must come from [`wasm_book_new`].
+#[no_mangle]
+pub unsafe extern "C" fn wasm_book_output(book: *const WasmBook) -> *const u8 {
+    (*book).output.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// Passes `command` in as JavaScript would.
+    unsafe fn command(book: *mut WasmBook, command: &str) -> i32 {
+        let buf = wasm_alloc(command.len());
+        buf.copy_from_nonoverlapping(command.as_ptr(), command.len());
+        let len = wasm_book_command(book, buf, command.len());
+        wasm_free(buf, command.len());
+        len
+    }
+
+    unsafe fn output(book: *const WasmBook, len: i32) -> Value {
+        let bytes = std::slice::from_raw_parts(wasm_book_output(book), len as usize);
+        serde_json::from_slice(bytes).unwrap()
+    }
+
+    #[test]
+    fn drives_a_book_with_json() {
+        unsafe {
+            let book = wasm_book_new("ABC".as_ptr(), 3);
+            assert_eq!((*book).order_book.symbol(), "ABC");
+            let new_order = |side, qty, participant| {
+                json!({"New": {
+                    "order_type": "GoodTilCancel",
+                    "side": side,
+                    "price": 101,
+                    "qty": qty,
+                    "participant": participant,
+                }})
+                .to_string()
+            };
+            command(book, &new_order("Sell", 5, 1));
+            let len = command(book, &new_order("Buy", 2, 2));
+            let events = output(book, len);
+            assert_eq!(events.as_array().unwrap().len(), 4);
+            assert_eq!(events[1]["Trade"]["qty"], 2);
+            assert_eq!(command(book, "{\"Nope\":{}}"), -1);
+
+            let len = wasm_book_depth(book, 5);
+            assert_eq!(
+                output(book, len),
+                json!({"bids": [], "asks": [{"price": 101, "qty": 3, "order_count": 1}]})
+            );
+            let len = wasm_book_trades(book, 10);
+            assert_eq!(output(book, len)[0]["maker_order_id"], 1);
+            wasm_book_free(book);
+        }
+    }
+}
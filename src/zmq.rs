@@ -0,0 +1,706 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! ZeroMQ transport.
+//!
+//! Binds three sockets, speaking ZMTP 3.0 so stock libzmq clients can
+//! connect:
+//! * a PULL socket for inbound commands; the last frame of each message is an
+//!   SBE encoded `OrderCommand` (see [`crate::codec::sbe`])
+//! * a PUB socket for events, sent as `[symbol, JSON event]`
+//! * a PUB socket for market data, sent as `[topic, MarketDataMessage]`
+//!
+//! Subscribers filter on the first frame with ordinary ZeroMQ prefix
+//! subscriptions, and nothing is sent to a peer that hasn't asked for it.
+//! Market data topics are `symbol.channel`, one per [`Channel`], so a client
+//! picks both the symbols and the kinds of data it wants: `ABC.bbo` for the
+//! top of book only, `ABC.` for everything on `ABC`. Level updates are only
+//! worked out while someone subscribes to depth, and each new depth
+//! subscriber is sent a snapshot to start from.
+
+use crate::backend::OrderBookBackend;
+use crate::codec::sbe;
+use crate::market_data::l2::L2Feed;
+use crate::market_data::MarketDataMessage;
+use crate::order_book::Bbo;
+use crate::sink::{EventEncoder, JsonEncoder};
+use crate::OrderEvent;
+use serde::Deserialize;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+pub mod zmtp;
+
+use zmtp::{Frame, SocketType};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Bytes queued for a slow subscriber before further messages to it are
+/// dropped, mirroring a PUB socket's high-water mark.
+const PUB_HIGH_WATER_MARK: usize = 4 * 1024 * 1024;
+const IDLE_SLEEP: Duration = Duration::from_micros(100);
+/// Bytes read ahead from a peer: enough for the longest frame, so a peer
+/// that sends faster than it is served waits in the socket buffer instead.
+const INBOUND_LIMIT: usize = zmtp::MAX_HEADER_LEN + zmtp::MAX_FRAME_LEN;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ZmqConfig {
+    pub command_endpoint: String,
+    pub event_endpoint: String,
+    pub market_data_endpoint: String,
+}
+
+impl Default for ZmqConfig {
+    fn default() -> Self {
+        ZmqConfig {
+            command_endpoint: "tcp://127.0.0.1:5555".to_string(),
+            event_endpoint: "tcp://127.0.0.1:5556".to_string(),
+            market_data_endpoint: "tcp://127.0.0.1:5557".to_string(),
+        }
+    }
+}
+
+/// Accepts ZeroMQ style `tcp://host:port` endpoints, with `*` meaning all
+/// interfaces.
+fn bind_endpoint(endpoint: &str) -> io::Result<TcpListener> {
+    let addr = endpoint.strip_prefix("tcp://").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported endpoint {}", endpoint),
+        )
+    })?;
+    let addr = match addr.strip_prefix("*:") {
+        Some(port) => format!("0.0.0.0:{}", port),
+        None => addr.to_string(),
+    };
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+struct Peer {
+    stream: TcpStream,
+    inbound: Vec<u8>,
+    outbound: Vec<u8>,
+    parts: Vec<Vec<u8>>,
+    /// Bytes in `parts`, each part counting one more for itself.
+    parts_len: usize,
+    subscriptions: Vec<Vec<u8>>,
+}
+
+impl Peer {
+    /// Reads whatever has arrived without blocking, up to
+    /// [`INBOUND_LIMIT`] held. Returns false once the peer has disconnected.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            let room = INBOUND_LIMIT.saturating_sub(self.inbound.len());
+            if room == 0 {
+                return Ok(true);
+            }
+            match self.stream.read(&mut chunk[..room.min(4096)]) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.inbound.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        match zmtp::parse_frame(&self.inbound)? {
+            Some((frame, used)) => {
+                self.inbound.drain(..used);
+                Ok(Some(frame))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the next complete message, skipping commands, or `None` if
+    /// the rest has not arrived. A message longer in all than a frame may
+    /// be is an error.
+    fn next_message(&mut self) -> io::Result<Option<Vec<Vec<u8>>>> {
+        while let Some(frame) = self.next_frame()? {
+            if frame.command {
+                continue;
+            }
+            self.parts_len += frame.body.len() + 1;
+            if self.parts_len > zmtp::MAX_FRAME_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("message longer than {} bytes", zmtp::MAX_FRAME_LEN),
+                ));
+            }
+            let more = frame.more;
+            self.parts.push(frame.body);
+            if !more {
+                self.parts_len = 0;
+                return Ok(Some(std::mem::take(&mut self.parts)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes as much queued output as the socket will take.
+    fn drain_outbound(&mut self) -> io::Result<()> {
+        while !self.outbound.is_empty() {
+            match self.stream.write(&self.outbound) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.outbound.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Listener {
+    listener: TcpListener,
+    socket_type: SocketType,
+    peers: Vec<Peer>,
+}
+
+impl Listener {
+    fn bind(endpoint: &str, socket_type: SocketType) -> io::Result<Self> {
+        Ok(Listener {
+            listener: bind_endpoint(endpoint)?,
+            socket_type,
+            peers: Vec::new(),
+        })
+    }
+
+    /// Accepts and handshakes any pending connections. A peer that fails
+    /// the handshake is dropped without affecting the others.
+    fn accept(&mut self) -> io::Result<()> {
+        loop {
+            let (mut stream, addr) = match self.listener.accept() {
+                Ok(conn) => conn,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            stream.set_nodelay(true)?;
+            match zmtp::handshake(&mut stream, self.socket_type, true) {
+                Ok(()) => {
+                    stream.set_read_timeout(None)?;
+                    stream.set_nonblocking(true)?;
+                    self.peers.push(Peer {
+                        stream,
+                        inbound: Vec::new(),
+                        outbound: Vec::new(),
+                        parts: Vec::new(),
+                        parts_len: 0,
+                        subscriptions: Vec::new(),
+                    });
+                }
+                Err(e) => tracing::warn!("ZMTP handshake with {} failed: {}", addr, e),
+            }
+        }
+    }
+}
+
+/// A kind of market data, published under its own topic per symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Bbo,
+    /// Level updates and checksums, after a snapshot.
+    Depth,
+    Trades,
+}
+
+impl Channel {
+    pub fn name(self) -> &'static str {
+        match self {
+            Channel::Bbo => "bbo",
+            Channel::Depth => "depth",
+            Channel::Trades => "trades",
+        }
+    }
+
+    /// The topic this channel is published under for `symbol`.
+    pub fn topic(self, symbol: &str) -> String {
+        format!("{}.{}", symbol, self.name())
+    }
+}
+
+pub struct PubSocket {
+    inner: Listener,
+}
+
+impl PubSocket {
+    pub fn bind(endpoint: &str) -> io::Result<Self> {
+        Ok(PubSocket {
+            inner: Listener::bind(endpoint, SocketType::Pub)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.listener.local_addr()
+    }
+
+    /// Sends `parts` to every subscriber whose subscription prefixes the
+    /// first frame. Subscribers that have fallen too far behind miss the
+    /// message rather than stalling the engine.
+    pub fn send(&mut self, parts: &[&[u8]]) -> io::Result<()> {
+        self.poll()?;
+        let topic = parts.first().copied().unwrap_or_default();
+        let mut message = Vec::new();
+        zmtp::encode_message(parts, &mut message);
+        self.inner.peers.retain_mut(|peer| {
+            let subscribed = peer
+                .subscriptions
+                .iter()
+                .any(|prefix| topic.starts_with(prefix));
+            if subscribed && peer.outbound.len() < PUB_HIGH_WATER_MARK {
+                peer.outbound.extend_from_slice(&message);
+            }
+            peer.drain_outbound().is_ok()
+        });
+        Ok(())
+    }
+
+    /// The number of peers that would be sent a message on `topic`.
+    pub fn subscribers(&self, topic: &[u8]) -> usize {
+        self.inner
+            .peers
+            .iter()
+            .filter(|peer| {
+                peer.subscriptions
+                    .iter()
+                    .any(|prefix| topic.starts_with(prefix))
+            })
+            .count()
+    }
+
+    /// Accepts new subscribers and applies subscription changes.
+    pub fn poll(&mut self) -> io::Result<()> {
+        self.inner.accept()?;
+        self.inner
+            .peers
+            .retain_mut(|peer| matches!(Self::read_subscriptions(peer), Ok(true)));
+        Ok(())
+    }
+
+    fn read_subscriptions(peer: &mut Peer) -> io::Result<bool> {
+        let alive = peer.fill()?;
+        while let Some(frame) = peer.next_frame()? {
+            // ZMTP 3.0 peers subscribe with a message whose first byte is
+            // 1 (subscribe) or 0 (cancel); 3.1 peers use commands.
+            let (subscribe, topic) = if frame.command {
+                match zmtp::parse_command(&frame)? {
+                    (b"SUBSCRIBE", topic) => (true, topic.to_vec()),
+                    (b"CANCEL", topic) => (false, topic.to_vec()),
+                    _ => continue,
+                }
+            } else {
+                match frame.body.split_first() {
+                    Some((1, topic)) => (true, topic.to_vec()),
+                    Some((0, topic)) => (false, topic.to_vec()),
+                    _ => continue,
+                }
+            };
+            if subscribe {
+                peer.subscriptions.push(topic);
+            } else if let Some(pos) = peer.subscriptions.iter().position(|t| *t == topic) {
+                peer.subscriptions.remove(pos);
+            }
+        }
+        Ok(alive)
+    }
+}
+
+pub struct PullSocket {
+    inner: Listener,
+    next_peer: usize,
+}
+
+impl PullSocket {
+    pub fn bind(endpoint: &str) -> io::Result<Self> {
+        Ok(PullSocket {
+            inner: Listener::bind(endpoint, SocketType::Pull)?,
+            next_peer: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.listener.local_addr()
+    }
+
+    /// Returns the next complete message, fair-queuing across peers, or
+    /// `None` if nothing is ready. A peer that sends something malformed is
+    /// dropped without affecting the others.
+    pub fn try_recv(&mut self) -> io::Result<Option<Vec<Vec<u8>>>> {
+        self.inner.accept()?;
+        let peers = &mut self.inner.peers;
+        for _ in 0..peers.len() {
+            self.next_peer = (self.next_peer + 1) % peers.len();
+            let peer = &mut peers[self.next_peer];
+            let alive = peer.fill().unwrap_or(false);
+            match peer.next_message() {
+                Ok(Some(message)) => return Ok(Some(message)),
+                Ok(None) if alive => {}
+                Ok(None) => {
+                    peers.remove(self.next_peer);
+                    return Ok(None);
+                }
+                Err(e) => {
+                    tracing::warn!("dropping ZMTP peer: {}", e);
+                    peers.remove(self.next_peer);
+                    return Ok(None);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Runs a book behind the three sockets described in the module docs.
+pub struct ZmqTransport<E = JsonEncoder> {
+    commands: PullSocket,
+    events: PubSocket,
+    market_data: PubSocket,
+    encoder: E,
+    last_bbo: Option<Bbo>,
+    /// Created for the book's symbol on first use.
+    depth: Option<L2Feed>,
+    depth_subscribers: usize,
+}
+
+impl ZmqTransport {
+    pub fn bind(config: &ZmqConfig) -> io::Result<Self> {
+        ZmqTransport::with_encoder(config, JsonEncoder)
+    }
+}
+
+impl<E: EventEncoder> ZmqTransport<E> {
+    pub fn with_encoder(config: &ZmqConfig, encoder: E) -> io::Result<Self> {
+        Ok(ZmqTransport {
+            commands: PullSocket::bind(&config.command_endpoint)?,
+            events: PubSocket::bind(&config.event_endpoint)?,
+            market_data: PubSocket::bind(&config.market_data_endpoint)?,
+            encoder,
+            last_bbo: None,
+            depth: None,
+            depth_subscribers: 0,
+        })
+    }
+
+    pub fn command_addr(&self) -> io::Result<SocketAddr> {
+        self.commands.local_addr()
+    }
+
+    pub fn event_addr(&self) -> io::Result<SocketAddr> {
+        self.events.local_addr()
+    }
+
+    pub fn market_data_addr(&self) -> io::Result<SocketAddr> {
+        self.market_data.local_addr()
+    }
+
+    /// Processes every command that is ready and publishes what it caused.
+    /// Returns the number of commands processed.
+    pub fn poll(&mut self, order_book: &mut impl OrderBookBackend) -> io::Result<usize> {
+        self.events.poll()?;
+        self.market_data.poll()?;
+        self.sync_depth(order_book)?;
+        let mut processed = 0;
+        while let Some(parts) = self.commands.try_recv()? {
+            let Some(payload) = parts.last() else {
+                continue;
+            };
+            let command = match sbe::decode_command(payload) {
+                Ok(command) => command,
+                Err(e) => {
+                    tracing::warn!("dropping undecodable command: {}", e);
+                    continue;
+                }
+            };
+            let events = order_book.submit(command);
+            processed += 1;
+            self.publish(order_book, &events)?;
+        }
+        Ok(processed)
+    }
+
+    /// Polls forever, sleeping briefly whenever there is nothing to do.
+    pub fn run(&mut self, order_book: &mut impl OrderBookBackend) -> io::Result<()> {
+        self.run_until(order_book, &AtomicBool::new(false))
+    }
+
+    /// As [`run`](Self::run), returning once `stop` is set. It is checked
+    /// after every poll.
+    pub fn run_until(
+        &mut self,
+        order_book: &mut impl OrderBookBackend,
+        stop: &AtomicBool,
+    ) -> io::Result<()> {
+        while !stop.load(Ordering::SeqCst) {
+            if self.poll(order_book)? == 0 {
+                thread::sleep(IDLE_SLEEP);
+            }
+        }
+        Ok(())
+    }
+
+    fn publish(
+        &mut self,
+        order_book: &impl OrderBookBackend,
+        events: &[OrderEvent],
+    ) -> io::Result<()> {
+        let symbol = order_book.symbol();
+        for event in events {
+            let payload = self.encoder.encode(symbol, event)?;
+            self.events.send(&[symbol.as_bytes(), &payload])?;
+            if let OrderEvent::Trade(trade) = event {
+                let message = MarketDataMessage::trade(symbol, trade);
+                self.send_market_data(Channel::Trades, &message)?;
+            }
+        }
+        if self.depth_subscribers > 0 {
+            self.publish_depth(order_book)?;
+        }
+        let bbo = order_book.bbo();
+        if self.last_bbo != Some(bbo) {
+            self.last_bbo = Some(bbo);
+            let message = MarketDataMessage::Bbo {
+                symbol: symbol.to_string(),
+                bbo,
+            };
+            self.send_market_data(Channel::Bbo, &message)?;
+        }
+        Ok(())
+    }
+
+    /// Brings depth subscribers up to date, and sends a snapshot if any
+    /// have joined since the last poll.
+    fn sync_depth(&mut self, order_book: &impl OrderBookBackend) -> io::Result<()> {
+        let topic = Channel::Depth.topic(order_book.symbol());
+        let subscribers = self.market_data.subscribers(topic.as_bytes());
+        let joined = subscribers > self.depth_subscribers;
+        self.depth_subscribers = subscribers;
+        if joined {
+            self.publish_depth(order_book)?;
+            let snapshot = self.depth_feed(order_book).snapshot();
+            self.send_market_data(Channel::Depth, &snapshot)?;
+        }
+        Ok(())
+    }
+
+    fn publish_depth(&mut self, order_book: &impl OrderBookBackend) -> io::Result<()> {
+        for message in self.depth_feed(order_book).update(order_book) {
+            self.send_market_data(Channel::Depth, &message)?;
+        }
+        Ok(())
+    }
+
+    fn depth_feed(&mut self, order_book: &impl OrderBookBackend) -> &mut L2Feed {
+        self.depth
+            .get_or_insert_with(|| L2Feed::new(order_book.symbol()))
+    }
+
+    /// Sends `message` on `channel`, unless no one is subscribed to it.
+    fn send_market_data(
+        &mut self,
+        channel: Channel,
+        message: &MarketDataMessage,
+    ) -> io::Result<()> {
+        let topic = channel.topic(message.symbol());
+        if self.market_data.subscribers(topic.as_bytes()) == 0 {
+            return Ok(());
+        }
+        let mut payload = Vec::new();
+        message.encode(&mut payload);
+        self.market_data.send(&[topic.as_bytes(), &payload])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::zmtp::{self, SocketType};
+    use super::{Channel, ZmqConfig, ZmqTransport};
+    use crate::backend::OrderBookBackend;
+    use crate::codec::sbe;
+    use crate::market_data::l2::MirrorBook;
+    use crate::market_data::MarketDataMessage;
+    use crate::{OrderBook, OrderCommand, OrderType, Side};
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn local_config() -> ZmqConfig {
+        ZmqConfig {
+            command_endpoint: "tcp://127.0.0.1:0".to_string(),
+            event_endpoint: "tcp://127.0.0.1:0".to_string(),
+            market_data_endpoint: "tcp://127.0.0.1:0".to_string(),
+        }
+    }
+
+    #[test]
+    fn commands_in_events_out() {
+        let mut transport = ZmqTransport::bind(&local_config()).unwrap();
+        let command_addr = transport.command_addr().unwrap();
+        let event_addr = transport.event_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut sub = TcpStream::connect(event_addr).unwrap();
+            zmtp::handshake(&mut sub, SocketType::Sub, false).unwrap();
+            let mut subscribe = Vec::new();
+            zmtp::encode_message(&[b"\x01ABC"], &mut subscribe);
+            sub.write_all(&subscribe).unwrap();
+
+            let mut push = TcpStream::connect(command_addr).unwrap();
+            zmtp::handshake(&mut push, SocketType::Push, false).unwrap();
+            for side in [Side::Sell, Side::Buy] {
+                let mut payload = Vec::new();
+                sbe::encode_command(
+                    &OrderCommand::New {
+                        order_type: OrderType::GoodTilCancel,
+                        side,
+                        price: 122,
+                        qty: 1,
+                        participant: 1,
+                    },
+                    &mut payload,
+                );
+                let mut message = Vec::new();
+                zmtp::encode_message(&[&payload], &mut message);
+                push.write_all(&message).unwrap();
+            }
+
+            sub.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut received = Vec::new();
+            while received.len() < 5 {
+                received.push(zmtp::read_message(&mut sub).unwrap());
+            }
+            received
+        });
+
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut processed = 0;
+        while processed < 2 && Instant::now() < deadline {
+            processed += transport.poll(&mut order_book).unwrap();
+        }
+        assert_eq!(processed, 2);
+
+        let received = client.join().unwrap();
+        assert!(received.iter().all(|parts| parts[0] == b"ABC"));
+        let trade: serde_json::Value = serde_json::from_slice(&received[2][1]).unwrap();
+        assert_eq!(trade["event"]["Trade"]["price"], 122);
+    }
+
+    #[test]
+    fn drops_peers_that_send_oversized_frames() {
+        let mut transport = ZmqTransport::bind(&local_config()).unwrap();
+        let command_addr = transport.command_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut bad = TcpStream::connect(command_addr).unwrap();
+            zmtp::handshake(&mut bad, SocketType::Push, false).unwrap();
+            let mut header = vec![0x02];
+            header.extend_from_slice(&u64::MAX.to_be_bytes());
+            bad.write_all(&header).unwrap();
+
+            let mut good = TcpStream::connect(command_addr).unwrap();
+            zmtp::handshake(&mut good, SocketType::Push, false).unwrap();
+            let mut payload = Vec::new();
+            sbe::encode_command(
+                &OrderCommand::New {
+                    order_type: OrderType::GoodTilCancel,
+                    side: Side::Buy,
+                    price: 99,
+                    qty: 1,
+                    participant: 1,
+                },
+                &mut payload,
+            );
+            let mut message = Vec::new();
+            zmtp::encode_message(&[&payload], &mut message);
+            good.write_all(&message).unwrap();
+            (bad, good)
+        });
+
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut processed = 0;
+        while processed == 0 && Instant::now() < deadline {
+            processed += transport.poll(&mut order_book).unwrap();
+        }
+        let _peers = client.join().unwrap();
+        assert_eq!(processed, 1);
+        assert_eq!(order_book.best_bid(), Some(99));
+        while transport.commands.inner.peers.len() > 1 && Instant::now() < deadline {
+            transport.poll(&mut order_book).unwrap();
+        }
+        assert_eq!(transport.commands.inner.peers.len(), 1);
+    }
+
+    #[test]
+    fn market_data_goes_only_where_subscribed() {
+        let mut transport = ZmqTransport::bind(&local_config()).unwrap();
+        let market_data_addr = transport.market_data_addr().unwrap();
+        let clients = thread::spawn(move || {
+            ["ABC.bbo", "ABC.depth"].map(|topic| {
+                let mut sub = TcpStream::connect(market_data_addr).unwrap();
+                zmtp::handshake(&mut sub, SocketType::Sub, false).unwrap();
+                let mut subscribe = Vec::new();
+                zmtp::encode_message(&[format!("\x01{}", topic).as_bytes()], &mut subscribe);
+                sub.write_all(&subscribe).unwrap();
+                sub.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+                sub
+            })
+        });
+
+        let mut order_book = OrderBook::with_symbol("ABC");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while transport.depth_subscribers == 0 && Instant::now() < deadline {
+            transport.poll(&mut order_book).unwrap();
+        }
+        let [mut bbo, mut depth] = clients.join().unwrap();
+        let topic = Channel::Bbo.topic("ABC");
+        while transport.market_data.subscribers(topic.as_bytes()) == 0 {
+            transport.market_data.poll().unwrap();
+        }
+        for side in [Side::Sell, Side::Buy] {
+            let events = order_book.submit(OrderCommand::New {
+                order_type: OrderType::GoodTilCancel,
+                side,
+                price: 122,
+                qty: 1,
+                participant: 1,
+            });
+            transport.publish(&order_book, &events).unwrap();
+        }
+
+        let mut mirror = MirrorBook::new();
+        for _ in 0..5 {
+            let parts = zmtp::read_message(&mut depth).unwrap();
+            assert_eq!(parts[0], b"ABC.depth");
+            mirror
+                .apply(&MarketDataMessage::decode(&parts[1]).unwrap())
+                .unwrap();
+        }
+        assert!(mirror.is_synced());
+        assert_eq!(mirror.depth(), order_book.depth(usize::MAX));
+        for _ in 0..2 {
+            let parts = zmtp::read_message(&mut bbo).unwrap();
+            let message = MarketDataMessage::decode(&parts[1]).unwrap();
+            assert!(matches!(message, MarketDataMessage::Bbo { .. }));
+        }
+        // Neither trade went out, since no one asked for trades.
+        bbo.set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        assert!(zmtp::read_message(&mut bbo).is_err());
+    }
+}
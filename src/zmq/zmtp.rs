@@ -0,0 +1,318 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+//! ZMTP 3.0 wire protocol (RFC 23) with the NULL security mechanism.
+//!
+//! Only what the PUB and PULL sockets need is implemented: the greeting, the
+//! READY handshake, and message framing. The protocol is symmetric, so the
+//! same handshake serves both ends of a connection.
+
+use std::io::{self, Read, Write};
+
+const FLAG_MORE: u8 = 0x01;
+const FLAG_LONG: u8 = 0x02;
+const FLAG_COMMAND: u8 = 0x04;
+
+/// Longest frame body accepted from a peer. Commands and subscriptions are
+/// tiny; anything longer is taken as a broken or hostile peer rather than
+/// allocated.
+pub const MAX_FRAME_LEN: usize = 1024 * 1024;
+/// Longest frame header: flags and a 64-bit length.
+pub const MAX_HEADER_LEN: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketType {
+    Pub,
+    Sub,
+    Push,
+    Pull,
+}
+
+impl SocketType {
+    pub fn name(self) -> &'static str {
+        match self {
+            SocketType::Pub => "PUB",
+            SocketType::Sub => "SUB",
+            SocketType::Push => "PUSH",
+            SocketType::Pull => "PULL",
+        }
+    }
+
+    fn accepts(self, peer: &[u8]) -> bool {
+        let allowed: &[&[u8]] = match self {
+            SocketType::Pub => &[b"SUB", b"XSUB"],
+            SocketType::Sub => &[b"PUB", b"XPUB"],
+            SocketType::Push => &[b"PULL"],
+            SocketType::Pull => &[b"PUSH"],
+        };
+        allowed.contains(&peer)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub more: bool,
+    pub command: bool,
+    pub body: Vec<u8>,
+}
+
+fn protocol_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("zmtp: {}", msg))
+}
+
+fn check_len(len: u64) -> io::Result<usize> {
+    match usize::try_from(len) {
+        Ok(len) if len <= MAX_FRAME_LEN => Ok(len),
+        _ => Err(protocol_error(&format!(
+            "frame of {} bytes is longer than {}",
+            len, MAX_FRAME_LEN
+        ))),
+    }
+}
+
+/// Exchanges greetings and READY commands on a freshly connected stream.
+pub fn handshake<S: Read + Write>(
+    stream: &mut S,
+    socket_type: SocketType,
+    as_server: bool,
+) -> io::Result<()> {
+    let mut greeting = [0u8; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    greeting[32] = as_server as u8;
+    stream.write_all(&greeting)?;
+
+    let mut peer = [0u8; 64];
+    stream.read_exact(&mut peer)?;
+    if peer[0] != 0xff || peer[9] & 0x01 == 0 {
+        return Err(protocol_error("peer did not send a ZMTP signature"));
+    }
+    if peer[10] < 3 {
+        return Err(protocol_error("peer speaks a ZMTP version before 3.0"));
+    }
+    if peer[12..32] != greeting[12..32] {
+        return Err(protocol_error("only the NULL mechanism is supported"));
+    }
+
+    let mut ready = Vec::new();
+    ready.push(5);
+    ready.extend_from_slice(b"READY");
+    let name = b"Socket-Type";
+    ready.push(name.len() as u8);
+    ready.extend_from_slice(name);
+    ready.extend_from_slice(&(socket_type.name().len() as u32).to_be_bytes());
+    ready.extend_from_slice(socket_type.name().as_bytes());
+    let mut buf = Vec::new();
+    encode_frame(FLAG_COMMAND, &ready, &mut buf);
+    stream.write_all(&buf)?;
+    stream.flush()?;
+
+    let frame = read_frame(stream)?;
+    let (name, body) = parse_command(&frame)?;
+    if name != b"READY" {
+        return Err(protocol_error("expected READY command"));
+    }
+    let peer_type = ready_property(body, b"Socket-Type")
+        .ok_or_else(|| protocol_error("READY without Socket-Type"))?;
+    if !socket_type.accepts(peer_type) {
+        return Err(protocol_error(&format!(
+            "{} cannot talk to {}",
+            socket_type.name(),
+            String::from_utf8_lossy(peer_type)
+        )));
+    }
+    Ok(())
+}
+
+/// Splits a command frame into its name and body.
+pub fn parse_command(frame: &Frame) -> io::Result<(&[u8], &[u8])> {
+    if !frame.command {
+        return Err(protocol_error("expected a command frame"));
+    }
+    let len = *frame
+        .body
+        .first()
+        .ok_or_else(|| protocol_error("empty command"))? as usize;
+    if frame.body.len() < 1 + len {
+        return Err(protocol_error("truncated command name"));
+    }
+    Ok((&frame.body[1..1 + len], &frame.body[1 + len..]))
+}
+
+fn ready_property<'a>(mut props: &'a [u8], wanted: &[u8]) -> Option<&'a [u8]> {
+    while let Some((&name_len, rest)) = props.split_first() {
+        let name = rest.get(..name_len as usize)?;
+        let rest = &rest[name_len as usize..];
+        let value_len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let value = rest.get(4..4 + value_len)?;
+        if name.eq_ignore_ascii_case(wanted) {
+            return Some(value);
+        }
+        props = &rest[4 + value_len..];
+    }
+    None
+}
+
+pub fn encode_frame(flags: u8, body: &[u8], buf: &mut Vec<u8>) {
+    if body.len() > u8::MAX as usize {
+        buf.push(flags | FLAG_LONG);
+        buf.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        buf.push(flags);
+        buf.push(body.len() as u8);
+    }
+    buf.extend_from_slice(body);
+}
+
+/// Encodes a multipart message, setting MORE on every frame but the last.
+pub fn encode_message(parts: &[&[u8]], buf: &mut Vec<u8>) {
+    for (i, part) in parts.iter().enumerate() {
+        let flags = if i + 1 < parts.len() { FLAG_MORE } else { 0 };
+        encode_frame(flags, part, buf);
+    }
+}
+
+/// Parses one frame from the front of `buf`, returning it with the number of
+/// bytes consumed, or `None` if the frame is not complete yet. A frame
+/// longer than [`MAX_FRAME_LEN`] is an error.
+pub fn parse_frame(buf: &[u8]) -> io::Result<Option<(Frame, usize)>> {
+    let Some(&flags) = buf.first() else {
+        return Ok(None);
+    };
+    if flags & !(FLAG_MORE | FLAG_LONG | FLAG_COMMAND) != 0 {
+        return Err(protocol_error("reserved frame flags set"));
+    }
+    let (len, header): (usize, usize) = if flags & FLAG_LONG != 0 {
+        match buf.get(1..9) {
+            Some(size) => (check_len(u64::from_be_bytes(size.try_into().unwrap()))?, 9),
+            None => return Ok(None),
+        }
+    } else {
+        match buf.get(1) {
+            Some(&size) => (size as usize, 2),
+            None => return Ok(None),
+        }
+    };
+    let Some(body) = buf.get(header..header.saturating_add(len)) else {
+        return Ok(None);
+    };
+    let frame = Frame {
+        more: flags & FLAG_MORE != 0,
+        command: flags & FLAG_COMMAND != 0,
+        body: body.to_vec(),
+    };
+    Ok(Some((frame, header + len)))
+}
+
+/// Reads one frame, rejecting one longer than [`MAX_FRAME_LEN`] before
+/// reading its body.
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<Frame> {
+    let mut flags = [0u8; 1];
+    reader.read_exact(&mut flags)?;
+    let len = if flags[0] & FLAG_LONG != 0 {
+        let mut size = [0u8; 8];
+        reader.read_exact(&mut size)?;
+        check_len(u64::from_be_bytes(size))?
+    } else {
+        let mut size = [0u8; 1];
+        reader.read_exact(&mut size)?;
+        size[0] as usize
+    };
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body)?;
+    Ok(Frame {
+        more: flags[0] & FLAG_MORE != 0,
+        command: flags[0] & FLAG_COMMAND != 0,
+        body,
+    })
+}
+
+/// Reads frames until one without the MORE flag, skipping commands.
+pub fn read_message<R: Read>(reader: &mut R) -> io::Result<Vec<Vec<u8>>> {
+    let mut parts = Vec::new();
+    loop {
+        let frame = read_frame(reader)?;
+        if frame.command {
+            continue;
+        }
+        let more = frame.more;
+        parts.push(frame.body);
+        if !more {
+            return Ok(parts);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_message, handshake, parse_frame, read_frame, read_message, SocketType, MAX_FRAME_LEN,
+    };
+    use std::io;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    #[test]
+    fn frames_round_trip() {
+        let long = vec![7u8; 300];
+        let mut buf = Vec::new();
+        encode_message(&[b"topic", &long], &mut buf);
+        let (first, used) = parse_frame(&buf).unwrap().unwrap();
+        assert!(first.more);
+        assert_eq!(first.body, b"topic");
+        let (second, rest) = parse_frame(&buf[used..]).unwrap().unwrap();
+        assert!(!second.more);
+        assert_eq!(second.body, long);
+        assert_eq!(used + rest, buf.len());
+        assert!(parse_frame(&buf[used..used + 5]).unwrap().is_none());
+        assert_eq!(
+            read_message(&mut &buf[..]).unwrap(),
+            vec![b"topic".to_vec(), long]
+        );
+    }
+
+    #[test]
+    fn rejects_frames_longer_than_the_limit() {
+        let mut header = vec![0x02];
+        header.extend_from_slice(&u64::MAX.to_be_bytes());
+        for err in [
+            parse_frame(&header).unwrap_err(),
+            read_frame(&mut &header[..]).unwrap_err(),
+        ] {
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().contains("is longer than"), "{}", err);
+        }
+
+        let mut buf = Vec::new();
+        encode_message(&[&vec![0; MAX_FRAME_LEN]], &mut buf);
+        assert_eq!(parse_frame(&buf).unwrap().unwrap().1, buf.len());
+        buf.clear();
+        encode_message(&[&vec![0; MAX_FRAME_LEN + 1]], &mut buf);
+        assert!(parse_frame(&buf[..9]).is_err());
+        assert!(read_message(&mut &buf[..]).is_err());
+    }
+
+    #[test]
+    fn handshake_checks_socket_types() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            handshake(&mut stream, SocketType::Push, false)
+        });
+        let (mut stream, _) = listener.accept().unwrap();
+        handshake(&mut stream, SocketType::Pull, true).unwrap();
+        client.join().unwrap().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            handshake(&mut stream, SocketType::Sub, false)
+        });
+        let (mut stream, _) = listener.accept().unwrap();
+        assert!(handshake(&mut stream, SocketType::Pull, true).is_err());
+        let _ = client.join();
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2024 Mason Hall. All rights reserved.
+// Use of this source code is governed by a BSD-style
+// license that can be found in the LICENSE file.
+
+// Browser bindings for matcher-rs built with
+//
+//   cargo build --release --target wasm32-unknown-unknown --features wasm
+//
+// Commands, events and depth are the same JSON as the REST API. Timestamps
+// are nanoseconds, so they lose precision as JavaScript numbers.
+
+const encoder = new TextEncoder();
+const decoder = new TextDecoder();
+
+export async function loadMatcher(url) {
+  const { instance } = await WebAssembly.instantiateStreaming(fetch(url), {
+    env: { matcher_now_millis: () => Date.now() },
+  });
+  return instance.exports;
+}
+
+export class Book {
+  constructor(wasm, symbol = "") {
+    this.wasm = wasm;
+    this.ptr = this.withString(symbol, (ptr, len) => wasm.wasm_book_new(ptr, len));
+  }
+
+  // Processes a command such as
+  // {New: {order_type: "GoodTilCancel", side: "Buy", price: 122, qty: 5, participant: 1}}
+  // and returns the events it produced.
+  submit(command) {
+    const len = this.withString(JSON.stringify(command), (ptr, len) =>
+      this.wasm.wasm_book_command(this.ptr, ptr, len),
+    );
+    if (len < 0) {
+      throw new Error(`invalid command ${JSON.stringify(command)}`);
+    }
+    return this.output(len);
+  }
+
+  depth(levels) {
+    return this.output(this.wasm.wasm_book_depth(this.ptr, levels));
+  }
+
+  trades(count) {
+    return this.output(this.wasm.wasm_book_trades(this.ptr, count));
+  }
+
+  free() {
+    this.wasm.wasm_book_free(this.ptr);
+    this.ptr = 0;
+  }
+
+  withString(text, f) {
+    const bytes = encoder.encode(text);
+    const ptr = this.wasm.wasm_alloc(bytes.length);
+    new Uint8Array(this.wasm.memory.buffer, ptr, bytes.length).set(bytes);
+    try {
+      return f(ptr, bytes.length);
+    } finally {
+      this.wasm.wasm_free(ptr, bytes.length);
+    }
+  }
+
+  output(len) {
+    const ptr = this.wasm.wasm_book_output(this.ptr);
+    return JSON.parse(decoder.decode(new Uint8Array(this.wasm.memory.buffer, ptr, len)));
+  }
+}